@@ -1,19 +1,23 @@
 use crate::cache::schema_cache;
-use crate::db::Db;
+use crate::db::{CategoryRow, Db, FolderRow, HistoryRow, MigrationBackupRow};
+use crate::db_recovery::{self, DbRecoveryStatus};
+use crate::error::AppError;
 use crate::excel;
-use crate::models::ExcelSchema;
+use crate::models::{ExcelSchema, SchemaDiff};
 use crate::ocr;
-use crate::services::excel_scanner;
-use crate::types::{InvoiceData, RowCell, FailedScan, BatchScanResult, InvoiceFieldValue};
+use crate::ods;
+use crate::services::{app_state_archive, app_warnings, archive_store, bundle_keys, closing_checklist, document_format, document_sets, document_types, duplicate_keys, email_intake, export_delivery, export_locale, export_manifest, export_order, export_targets, excel_lock, excel_scanner, export_diff, export_naming, field_schemas, google_sheets, history_report, ledger_snapshots, llm_extraction, mapping_suggestions, model_mappings, ocr_provider, onboarding, path_scope, pending_appends, perf_metrics, preset_bundles, preview, profile_audit, redaction, scan_sessions, schema_drift, settings, sheet_routes, spending_report, storage, strict_export, temp_store, validation, vat_report, vendor_corrections, vendors, webhooks, work_pool, workbook_format, workbook_standby};
+use crate::types::{CellWrite, InvoiceData, RowCell, FailedScan, BatchScanResult, InvoiceFieldValue};
 use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex, RwLock};
 use std::time::UNIX_EPOCH;
-use tauri::{AppHandle, Manager, State};
+use tauri::{AppHandle, Emitter, Manager, State};
+use tauri_plugin_dialog::DialogExt;
 
 #[derive(Serialize)]
 pub struct ValidationResult {
@@ -39,11 +43,148 @@ pub struct AnalyzedExcelSchema {
     pub headers: Vec<String>,
     pub column_samples: Vec<Vec<String>>,
     pub last_data_row: u32,
+    /// Data exists below a gap longer than the configured empty-row limit and was not counted.
+    pub last_row_truncated: bool,
     pub schema_hash: String,
+    /// Data row count, per-column fill rates and types, likely amount/date columns.
+    pub stats: excel::SheetStats,
 }
 
+/// Shared state for all commands. The database handle sits behind a short-lived RwLock that is only
+/// held to clone the `Arc`; `Db` serializes access to its connection internally, so a long Excel scan
+/// or OCR poll never holds a lock that unrelated commands need.
 pub struct AppState {
-    pub db: Mutex<Option<Db>>,
+    db: RwLock<Option<Arc<Db>>>,
+    pub db_path: PathBuf,
+    /// Set when the DB could not be opened at startup; cleared after a successful recovery.
+    pub db_recovery: Mutex<Option<DbRecoveryStatus>>,
+    /// Viewer workstation (READ_ONLY_MODE=1 in .env): history, previews, OCR and new-file exports work,
+    /// but nothing that modifies ledgers, profiles or history is allowed.
+    pub read_only: bool,
+}
+
+impl AppState {
+    pub fn new(db: Option<Db>, db_path: PathBuf, db_recovery: Option<DbRecoveryStatus>, read_only: bool) -> Self {
+        let state = Self {
+            db: RwLock::new(None),
+            db_path,
+            db_recovery: Mutex::new(db_recovery),
+            read_only,
+        };
+        let _ = state.set_db(db);
+        state
+    }
+
+    /// Folder holding the database, `.env`, templates and archives.
+    pub fn app_data_dir(&self) -> PathBuf {
+        self.db_path.parent().map(Path::to_path_buf).unwrap_or_default()
+    }
+
+    /// Handle to the open database; fails while the app is waiting for database recovery.
+    pub fn db(&self) -> Result<Arc<Db>, AppError> {
+        let guard = self.db.read().map_err(AppError::internal)?;
+        guard.clone().ok_or_else(|| AppError::Db("Database not initialized".to_string()))
+    }
+
+    /// Replace the open database (None closes it once in-flight commands drop their handles).
+    /// Settings from the new database are loaded so OCR and exports pick them up immediately.
+    pub fn set_db(&self, db: Option<Db>) -> Result<(), AppError> {
+        let db = db.map(Arc::new);
+        if let Some(db) = &db {
+            if let Err(e) = settings::load(db, &self.app_data_dir()) {
+                app_warnings::report("settings", format!("could not load settings: {}", e));
+            }
+            if let Err(e) = model_mappings::load(db) {
+                app_warnings::report("model_mappings", format!("could not load field mappings: {}", e));
+            }
+            if let Err(e) = document_types::load(db) {
+                app_warnings::report("document_types", format!("could not load document types: {}", e));
+            }
+            if let Err(e) = ocr_provider::load(db) {
+                app_warnings::report("ocr_provider", format!("could not load OCR provider: {}", e));
+            }
+        }
+        let mut guard = self.db.write().map_err(AppError::internal)?;
+        *guard = db;
+        Ok(())
+    }
+
+    pub fn has_db(&self) -> bool {
+        self.db.read().map(|g| g.is_some()).unwrap_or(false)
+    }
+
+    /// Reject mutating commands in read-only mode.
+    pub fn ensure_writable(&self) -> Result<(), AppError> {
+        if self.read_only {
+            return Err(AppError::Validation("Read-only mode: this workstation cannot modify Excel ledgers, profiles or history.".to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// READ_ONLY_MODE from the environment / app-data .env ("1", "true" or "yes").
+pub fn read_only_mode_from_env() -> bool {
+    std::env::var("READ_ONLY_MODE")
+        .map(|v| matches!(v.trim().to_lowercase().as_str(), "1" | "true" | "yes"))
+        .unwrap_or(false)
+}
+
+/// Run a DB call from an async command on the blocking pool so a busy connection never stalls the executor.
+async fn with_db_blocking<T, E, F>(state: &AppState, f: F) -> Result<T, AppError>
+where
+    T: Send + 'static,
+    E: Into<AppError> + Send + 'static,
+    F: FnOnce(&Db) -> Result<T, E> + Send + 'static,
+{
+    let db = state.db()?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let _timer = perf_metrics::timer(perf_metrics::Metric::DbQuery);
+        f(&db)
+    })
+    .await
+        .map_err(AppError::internal)?
+        .map_err(Into::into)
+}
+
+/// Add cell writes to the write log. A logging failure is shown as an app warning but does not fail the write itself.
+async fn record_cell_writes(state: &AppState, file_path: String, history_id: Option<i64>, writes: Vec<CellWrite>) {
+    let path_for_log = file_path.clone();
+    if let Err(e) = with_db_blocking(state, move |db| db.log_cell_writes(&file_path, history_id, &writes)).await {
+        app_warnings::report("write_log", format!("could not record writes to {}: {}", path_for_log, e));
+    }
+}
+
+/// Snapshot the profile's workbook when its snapshot policy is due. A failure is shown as an app warning but does not fail the append.
+async fn snapshot_after_append(state: &AppState, profile_id: i64) {
+    let app_data_dir = state.app_data_dir();
+    if let Err(e) =
+        with_db_blocking(state, move |db| ledger_snapshots::record_append(db, &app_data_dir, profile_id)).await
+    {
+        app_warnings::report("snapshots", format!("could not snapshot profile {}: {}", profile_id, e));
+    }
+}
+
+/// When `wait_for_unlock_secs` is set and the workbook is open in Excel, wait for it to be closed
+/// (emitting `excel-lock-wait` events) instead of failing right away. `wait_id` is for `retry_excel_write_now`.
+async fn wait_for_excel_unlock(
+    app: &AppHandle,
+    path: &str,
+    wait_for_unlock_secs: Option<u64>,
+    wait_id: Option<String>,
+) -> Result<(), AppError> {
+    let Some(timeout_secs) = wait_for_unlock_secs.filter(|s| *s > 0) else {
+        return Ok(());
+    };
+    let wait_id = wait_id
+        .filter(|id| !id.trim().is_empty())
+        .unwrap_or_else(|| format!("lock-{}", chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default()));
+    let progress_app = app.clone();
+    let on_progress = move |p: excel_lock::LockWaitProgress| {
+        let _ = progress_app.emit("excel-lock-wait", p);
+    };
+    excel_lock::wait_for_unlock(Path::new(path), &wait_id, timeout_secs, &on_progress)
+        .await
+        .map_err(AppError::Excel)
 }
 
 #[derive(Deserialize)]
@@ -51,6 +192,11 @@ pub struct AppendRowPayload {
     pub path: String,
     pub sheet: String,
     pub row: Vec<RowCell>,
+    /// History record the row belongs to, for the write log.
+    pub history_id: Option<i64>,
+    /// Seconds to wait for the file to be closed in Excel (None = fail immediately).
+    pub wait_for_unlock_secs: Option<u64>,
+    pub wait_id: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -71,12 +217,24 @@ pub struct AddHistoryPayload {
     pub excel_profile_id: Option<i64>,
     pub error_message: Option<String>,
     pub folder_id: Option<i64>,
+    /// Full path of the scanned file when `file_path_or_name` is only its name; used to archive the
+    /// original and to scan it again from history.
+    pub source_path: Option<String>,
 }
 
 #[derive(Deserialize)]
 pub struct GetHistoryPayload {
     pub search: Option<String>,
     pub folder_id: Option<i64>, // None = all, -1 = uncategorized
+    /// Also match records in subfolders of `folder_id`.
+    pub include_subfolders: Option<bool>,
+    /// Hide records grouped under a batch export (the History screen shows them via get_history_children).
+    pub top_level_only: Option<bool>,
+    /// Only records scanned in this session.
+    pub session_id: Option<i64>,
+    /// Page size; None returns every matching record.
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
 }
 
 #[derive(Deserialize)]
@@ -122,11 +280,11 @@ pub struct GetColumnSamplesPayload {
 }
 
 #[tauri::command]
-pub fn get_app_data_path(app: AppHandle) -> Result<String, String> {
-    let path = app.path().app_data_dir().map_err(|e| e.to_string())?;
+pub fn get_app_data_path(app: AppHandle) -> Result<String, AppError> {
+    let path = app.path().app_data_dir().map_err(AppError::internal)?;
     path.to_str()
         .map(String::from)
-        .ok_or_else(|| "Invalid path".to_string())
+        .ok_or_else(|| AppError::Validation("Invalid path".to_string()))
 }
 
 #[tauri::command]
@@ -134,8 +292,23 @@ pub fn get_app_version(app: AppHandle) -> String {
     app.package_info().version.to_string()
 }
 
+/// Side-work failures not shown yet (see `app_warnings`); they are cleared.
+#[tauri::command]
+pub fn take_app_warnings() -> Vec<app_warnings::AppWarning> {
+    app_warnings::take()
+}
+
+/// Whether this workstation runs in read-only (viewer) mode.
+#[tauri::command]
+pub fn get_read_only_mode(state: State<AppState>) -> bool {
+    state.read_only
+}
+
 #[tauri::command]
 pub fn get_azure_status() -> String {
+    if settings::azure_credentials().is_some() {
+        return "configured".to_string();
+    }
     let _ = dotenvy::dotenv();
     match (
         std::env::var("AZURE_OCR_KEY"),
@@ -146,939 +319,4218 @@ pub fn get_azure_status() -> String {
     }
 }
 
+/// Stored app settings; the Azure key is only reported as configured or not.
 #[tauri::command]
-pub fn open_app_data_folder(app: AppHandle) -> Result<(), String> {
-    let path = app.path().app_data_dir().map_err(|e| e.to_string())?;
-    opener::open(&path).map_err(|e| e.to_string())
-}
-
-#[tauri::command]
-pub fn run_ocr(file_path: String) -> Result<crate::types::OcrResult, String> {
-    ocr::run_ocr(&file_path)
+pub async fn get_settings(state: State<'_, AppState>) -> Result<settings::AppSettings, AppError> {
+    with_db_blocking(&state, settings::get_settings).await
 }
 
+/// Update settings (None leaves a value unchanged, "" clears it). Returns the new settings.
 #[tauri::command]
-pub async fn run_ocr_invoice(
-    file_path: String,
-    document_type: Option<String>,
-) -> Result<crate::types::OcrInvoiceResult, String> {
-    let path = file_path.clone();
-    let doc_type = document_type.clone();
-    tauri::async_runtime::spawn_blocking(move || ocr::run_ocr_invoice(&path, doc_type.as_deref()))
-        .await
-        .map_err(|e| e.to_string())?
+pub async fn set_settings(
+    state: State<'_, AppState>,
+    update: settings::SettingsUpdate,
+) -> Result<settings::AppSettings, AppError> {
+    state.ensure_writable()?;
+    if let Some(folder) = update.default_export_folder.as_deref().filter(|p| !p.trim().is_empty()) {
+        ensure_path_allowed(&state, folder)?;
+    }
+    let app_data_dir = state.app_data_dir();
+    with_db_blocking(&state, move |db| settings::save_settings(db, &app_data_dir, update)).await
 }
 
-/// Run OCR on multiple PDFs in parallel; returns both successful and failed results.
+/// Save the Azure endpoint and key; the key goes to the OS credential store (Windows Credential
+/// Manager / macOS Keychain) instead of `.env`.
 #[tauri::command]
-pub async fn batch_scan_invoices(
-    pdf_paths: Vec<String>,
-    document_type: Option<String>,
-) -> Result<BatchScanResult, String> {
-    const CONCURRENCY: usize = 8;
-    let mut successes = Vec::new();
-    let mut failures = Vec::new();
-    let doc_type = document_type.clone();
-    
-    for chunk in pdf_paths.chunks(CONCURRENCY) {
-        let chunk_paths: Vec<(String, String)> = chunk
-            .iter()
-            .map(|path| {
-                let path = path.clone();
-                let filename = Path::new(&path)
-                    .file_name()
-                    .and_then(|o| o.to_str())
-                    .unwrap_or("")
-                    .to_string();
-                (path, filename)
-            })
-            .collect();
-        
-        let handles: Vec<_> = chunk_paths
-            .iter()
-            .map(|(path, _)| {
-                let path = path.clone();
-                let doc_type = doc_type.clone();
-                tauri::async_runtime::spawn_blocking(move || {
-                    ocr::run_ocr_invoice(&path, doc_type.as_deref())
-                })
-            })
-            .collect();
-        
-        for ((path, filename), h) in chunk_paths.into_iter().zip(handles) {
-            match h.await {
-                Ok(Ok(res)) => {
-                    let mut inv = res.invoice_data;
-                    // Ensure document_type is populated for batch flows when the user selected
-                    // a specific document type on the Home screen (Фактури, Даночен биланс, ДДВ, Плати).
-                    if let Some(ref dt) = doc_type {
-                        let friendly = match dt.as_str() {
-                            "smetka" => Some("Даночен биланс"),
-                            "generic" => Some("ДДВ"),
-                            "plata" => Some("Плата"),
-                            "faktura" => Some("Фактура"),
-                            _ => None,
-                        };
-                        if let Some(label) = friendly {
-                            let needs_set = inv
-                                .fields
-                                .get("document_type")
-                                .map(|v| v.value.trim().is_empty())
-                                .unwrap_or(true);
-                            if needs_set {
-                                inv.fields.insert(
-                                    "document_type".to_string(),
-                                    InvoiceFieldValue {
-                                        value: label.to_string(),
-                                        confidence: Some(1.0),
-                                    },
-                                );
-                            }
-                        }
-                    }
-                    inv.source_file = Some(filename.clone());
-                    inv.source_file_path = Some(path.clone());
-                    successes.push(inv);
-                }
-                Ok(Err(e)) => {
-                    failures.push(FailedScan {
-                        file_path: path,
-                        file_name: filename,
-                        error: e,
-                    });
-                }
-                Err(e) => {
-                    failures.push(FailedScan {
-                        file_path: path,
-                        file_name: filename,
-                        error: format!("Task join error: {}", e),
-                    });
-                }
-            }
-        }
-    }
-    
-    Ok(BatchScanResult { successes, failures })
+pub async fn set_azure_credentials(
+    state: State<'_, AppState>,
+    endpoint: String,
+    key: String,
+) -> Result<settings::AppSettings, AppError> {
+    state.ensure_writable()?;
+    let app_data_dir = state.app_data_dir();
+    let update = settings::SettingsUpdate {
+        azure_endpoint: Some(endpoint),
+        azure_key: Some(key),
+        ..Default::default()
+    };
+    with_db_blocking(&state, move |db| settings::save_settings(db, &app_data_dir, update)).await
 }
 
+/// Check Azure credentials before saving them; without arguments checks the ones OCR currently uses.
 #[tauri::command]
-pub async fn export_invoices_to_excel(
-    invoices: Vec<InvoiceData>,
-    path: Option<String>,
-) -> Result<String, String> {
+pub async fn test_azure_credentials(endpoint: Option<String>, key: Option<String>) -> Result<(), AppError> {
     tauri::async_runtime::spawn_blocking(move || {
-        excel::export_invoices_to_excel(&invoices, path.as_deref())
+        let (endpoint, key) = match (endpoint, key) {
+            (Some(endpoint), Some(key)) => (endpoint, key),
+            _ => ocr::current_azure_credentials()?,
+        };
+        ocr::check_azure_credentials(&endpoint, &key)
     })
     .await
-    .map_err(|e| e.to_string())?
+    .map_err(AppError::internal)?
 }
 
+/// Page count, page sizes and estimated Azure cost of a document, checked before it is scanned.
 #[tauri::command]
-pub async fn export_invoices_to_new_excel(
-    invoices: Vec<InvoiceData>,
-    path: Option<String>,
-    worksheet_name: Option<String>,
-) -> Result<String, String> {
-    tauri::async_runtime::spawn_blocking(move || {
-        excel::export_invoices_to_new_excel(&invoices, path.as_deref(), worksheet_name.as_deref())
-    })
-    .await
-    .map_err(|e| e.to_string())?
+pub async fn inspect_document(
+    state: State<'_, AppState>,
+    file_path: String,
+    document_type: Option<String>,
+) -> Result<ocr::DocumentInspection, AppError> {
+    ensure_path_allowed(&state, &file_path)?;
+    let _permit = work_pool::acquire(work_pool::WorkClass::Preview).await;
+    tauri::async_runtime::spawn_blocking(move || ocr::inspect_document(&file_path, document_type.as_deref()))
+        .await
+        .map_err(AppError::internal)?
 }
 
+/// PNG (base64) of one page of a PDF or image for previews in History and review; cached in the
+/// thumbnails store.
 #[tauri::command]
-pub async fn export_to_new_excel_with_columns(
+pub async fn render_document_preview(
+    state: State<'_, AppState>,
     path: String,
-    worksheet_name: String,
-    headers: Vec<String>,
-    column_field_keys: Vec<String>,
-    invoices: Vec<InvoiceData>,
-) -> Result<String, String> {
-    tauri::async_runtime::spawn_blocking(move || {
-        excel::export_to_new_excel_with_columns(
-            &path,
-            &worksheet_name,
-            &headers,
-            &column_field_keys,
-            &invoices,
-        )
-    })
-    .await
-    .map_err(|e| e.to_string())?
+    page: Option<u32>,
+    dpi: Option<u32>,
+) -> Result<preview::RenderedPreview, AppError> {
+    ensure_path_allowed(&state, &path)?;
+    let app_data_dir = state.app_data_dir();
+    let _permit = work_pool::acquire(work_pool::WorkClass::Preview).await;
+    tauri::async_runtime::spawn_blocking(move || preview::render(&app_data_dir, &path, page, dpi))
+        .await
+        .map_err(AppError::internal)?
+        .map_err(AppError::from)
 }
 
-/// Copy the profile's template file to dest_path and append each invoice as a row.
-/// Preserves the template's exact layout (merged headers, styling). Use this for Даночен биланс etc.
-/// For Plata (sheet "МПИН"): copy template then write each invoice into its month column (no row append).
+/// Replay the stored poll responses in `dir` through extraction and report field-level diffs against the
+/// expected files (`update` rewrites them). Only in builds with the `regression` feature.
 #[tauri::command]
-pub async fn copy_template_and_append_rows(
+pub async fn run_extraction_regression(
     state: State<'_, AppState>,
-    profile_id: i64,
-    dest_path: String,
-    invoices: Vec<InvoiceData>,
-) -> Result<String, String> {
-    if invoices.is_empty() {
-        return Err("No invoices to export".to_string());
-    }
-    let (excel_path, sheet_name, column_mapping_json): (String, String, String) = {
-        let db = state.db.lock().map_err(|e: std::sync::PoisonError<_>| e.to_string())?;
-        let db = db.as_ref().ok_or("Database not initialized")?;
-        db.get_profile_by_id(profile_id)?
-    };
-
-    // Plata: copy template then write each invoice into its month column in Пресметка на плата grid.
-    if sheet_name == "МПИН" {
-        let template_path = excel_path.clone();
-        let dest = dest_path.clone();
-        let sheet = sheet_name.clone();
-        let inv = invoices;
-        tauri::async_runtime::spawn_blocking(move || {
-            fs::copy(Path::new(&template_path), Path::new(&dest)).map_err(|e| e.to_string())?;
-            for invoice in &inv {
-                let declaration_period = invoice
-                    .fields
-                    .get("declarationPeriod")
-                    .or_else(|| invoice.fields.get("taxPeriod"))
-                    .map(|v| v.value.clone())
-                    .unwrap_or_else(String::new);
-                excel::write_plata_to_template(&dest, &sheet, &declaration_period, &invoice.fields)?;
-            }
-            Ok::<(), String>(())
+    dir: String,
+    update: Option<bool>,
+) -> Result<Value, AppError> {
+    ensure_path_allowed(&state, &dir)?;
+    #[cfg(feature = "regression")]
+    {
+        let report = tauri::async_runtime::spawn_blocking(move || {
+            crate::services::regression::run(Path::new(&dir), update.unwrap_or(false))
         })
         .await
-        .map_err(|e| e.to_string())??;
-        return Ok(dest_path);
+        .map_err(AppError::internal)??;
+        serde_json::to_value(report).map_err(AppError::internal)
+    }
+    #[cfg(not(feature = "regression"))]
+    {
+        let _ = (dir, update);
+        Err(AppError::Validation(
+            "This build does not include the regression harness (cargo feature \"regression\").".to_string(),
+        ))
     }
-
-    let schema = {
-        if let Some(cached) = schema_cache::get_cached_schema(profile_id) {
-            let db = state.db.lock().map_err(|e: std::sync::PoisonError<_>| e.to_string())?;
-            let db = db.as_ref().ok_or("Database not initialized")?;
-            if is_cache_valid(db, profile_id, &cached)? {
-                cached
-            } else {
-                let db = state.db.lock().map_err(|e: std::sync::PoisonError<_>| e.to_string())?;
-                let db = db.as_ref().ok_or("Database not initialized")?;
-                db.load_excel_schema(profile_id)?
-            }
-        } else {
-            let db = state.db.lock().map_err(|e: std::sync::PoisonError<_>| e.to_string())?;
-            let db = db.as_ref().ok_or("Database not initialized")?;
-            db.load_excel_schema(profile_id)?
-        }
-    };
-    let column_mapping: std::collections::HashMap<String, String> =
-        serde_json::from_str(&column_mapping_json).map_err(|e| format!("Invalid column_mapping: {}", e))?;
-
-    let template_path = excel_path.clone();
-    let dest = dest_path.clone();
-    let sheet = sheet_name.clone();
-    let inv = invoices;
-    tauri::async_runtime::spawn_blocking(move || {
-        fs::copy(Path::new(&template_path), Path::new(&dest)).map_err(|e| e.to_string())?;
-        let mut row = schema.next_free_row;
-        for invoice in &inv {
-            let mut column_values = Vec::new();
-            for h in &schema.headers {
-                let field_key = column_mapping
-                    .get(&h.column_letter)
-                    .or_else(|| column_mapping.get(&h.column_letter.to_uppercase()))
-                    .map(String::from)
-                    .unwrap_or_else(|| format!("col_{}", h.column_letter));
-                let mut value = invoice
-                    .fields
-                    .get(&field_key)
-                    .map(|v| v.value.clone())
-                    .unwrap_or_default();
-                // DDV template: write month name (e.g. "Февруари") in Период column instead of full date range
-                if field_key == "taxPeriod" {
-                    if let Some(month_name) = excel::period_to_month_name_mk(&value) {
-                        value = month_name;
-                    }
-                }
-                column_values.push((h.column_letter.clone(), value));
-            }
-            excel::append_row_to_excel_at_row(&dest, &sheet, row, column_values)?;
-            row += 1;
-        }
-        Ok::<(), String>(())
-    })
-    .await
-    .map_err(|e| e.to_string())??;
-    Ok(dest_path)
 }
 
-/// Row (1-based) in РД-Данок на добивка template for each AOP field — value is written to column D.
-/// FullTaxBalanceAnalyzer: aop_1=row 10, aop_2=11, …, aop_59=68.
-fn tax_balance_row_map() -> Vec<(String, u32)> {
-    (1..=59).map(|i| (format!("aop_{}", i), 9 + i as u32)).collect()
+/// Diagnose the Azure setup before scanning: latency, analyzer availability per document type (and
+/// `analyzer_id` when given), and a categorized error.
+#[tauri::command]
+pub async fn test_azure_connection(analyzer_id: Option<String>) -> Result<ocr::AzureConnectionTest, AppError> {
+    tauri::async_runtime::spawn_blocking(move || ocr::test_azure_connection(analyzer_id.as_deref()))
+        .await
+        .map_err(AppError::internal)
 }
 
-/// Prefer the repo example template so the exported file matches the exact layout (first picture).
-fn tax_balance_example_template_path() -> Option<PathBuf> {
-    let rel = PathBuf::from("example")
-        .join("Примери за автоматизирање на процеси")
-        .join("Даночен биланс")
-        .join("РД-Данок на добивка-2024-Example.xlsx");
-    let mut dir = std::env::current_dir().ok()?;
-    for _ in 0..10 {
-        let candidate = dir.join(&rel);
-        if candidate.exists() {
-            return Some(candidate);
-        }
-        dir = dir.parent()?.to_path_buf();
-    }
-    None
+/// Registered document types, built-in first.
+#[tauri::command]
+pub async fn get_document_types(state: State<'_, AppState>) -> Result<Vec<document_types::DocumentType>, AppError> {
+    with_db_blocking(&state, document_types::list).await
 }
 
-/// Prefer the repo example template for Плати (РД-Трошоци за вработени) so export matches the desired layout.
-fn plata_example_template_path() -> Option<PathBuf> {
-    let rel = PathBuf::from("example")
-        .join("Примери за автоматизирање на процеси")
-        .join("Плати")
-        .join("РД-Трошоци за вработени-Example.xlsx");
-    let mut dir = std::env::current_dir().ok()?;
-    for _ in 0..10 {
-        let candidate = dir.join(&rel);
-        if candidate.exists() {
-            return Some(candidate);
-        }
-        dir = dir.parent()?.to_path_buf();
+/// Add or change a document type (model, extractor, default folder, export layout). Returns all types.
+#[tauri::command]
+pub async fn save_document_type(
+    state: State<'_, AppState>,
+    document_type: document_types::DocumentType,
+) -> Result<Vec<document_types::DocumentType>, AppError> {
+    state.ensure_writable()?;
+    if let Some(folder) = document_type.default_folder.as_deref().filter(|p| !p.trim().is_empty()) {
+        ensure_path_allowed(&state, folder)?;
     }
-    None
+    with_db_blocking(&state, move |db| document_types::save(db, document_type)).await
 }
 
-/// Response for get_plata_template_path: path to template and sheet name to use.
-#[derive(Serialize)]
-#[serde(rename_all = "camelCase")]
-pub struct PlataTemplatePath {
-    pub path: String,
-    pub sheet_name: String,
+/// Delete a user-added document type; built-in types stay.
+#[tauri::command]
+pub async fn delete_document_type(state: State<'_, AppState>, key: String) -> Result<bool, AppError> {
+    state.ensure_writable()?;
+    with_db_blocking(&state, move |db| document_types::remove(db, &key)).await
 }
 
-/// Returns the preferred Plata template path and sheet name. Uses the repo example file when present,
-/// otherwise the generated Plati-Template.xlsx in app data. No Settings change required.
+/// Write a preset bundle of a document type (and optionally a profile's sheet and mapping) to `path`.
 #[tauri::command]
-pub fn get_plata_template_path(app: AppHandle) -> Result<PlataTemplatePath, String> {
-    if let Some(p) = plata_example_template_path() {
-        if let Some(s) = p.to_str() {
-            return Ok(PlataTemplatePath {
-                path: s.to_string(),
-                sheet_name: "Пресметка на плата".to_string(),
-            });
+pub async fn export_preset_bundle(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    path: String,
+    name: String,
+    description: Option<String>,
+    document_type: String,
+    profile_id: Option<i64>,
+) -> Result<String, AppError> {
+    ensure_path_allowed(&state, &path)?;
+    let app_version = app.package_info().version.to_string();
+    with_db_blocking(&state, move |db| {
+        let bundle = preset_bundles::build(db, &document_type, profile_id, &name, description, &app_version)?;
+        let mut target = PathBuf::from(path.trim());
+        if target.extension().is_none() {
+            target.set_extension(preset_bundles::EXTENSION);
         }
-    }
-    let app_data = app.path().app_data_dir().map_err(|e| e.to_string())?;
-    let fallback = app_data.join("templates").join("Plati-Template.xlsx");
-    let path = fallback
-        .to_str()
-        .ok_or("Invalid path for Plati template")?
-        .to_string();
-    if !fallback.exists() {
-        return Err("Шаблонот за Плати не е пронајден. Потребен е примерот РД-Трошоци за вработени-Example.xlsx или генериран шаблон во Поставки.".to_string());
-    }
-    Ok(PlataTemplatePath {
-        path,
-        sheet_name: "МПИН".to_string(),
+        preset_bundles::write(&bundle, &target)?;
+        Ok::<_, String>(target.to_string_lossy().to_string())
     })
+    .await
 }
 
-/// Copy the profile's template to dest_path and fill column D at the fixed rows (Даночен биланс form layout).
-/// Prefers the repo example template when present so the layout matches the first picture exactly.
-/// Legacy `profile_id` argument is ignored for new fixed-template flow; DB is only used as a fallback
-/// when the bundled example template is not found.
+/// Contents of a preset bundle, for a confirmation before importing it.
 #[tauri::command]
-pub async fn copy_template_and_fill_tax_balance(
+pub fn read_preset_bundle(state: State<AppState>, path: String) -> Result<preset_bundles::PresetBundle, AppError> {
+    ensure_path_allowed(&state, &path)?;
+    preset_bundles::read(Path::new(&path)).map_err(AppError::Validation)
+}
+
+/// Import a preset bundle; its profile is created on `excel_path` when given.
+#[tauri::command]
+pub async fn import_preset_bundle(
     state: State<'_, AppState>,
-    profile_id: i64,
-    dest_path: String,
-    invoice: InvoiceData,
-) -> Result<String, String> {
-    // 1) Try to use the bundled Даночен биланс example template from the repo.
-    // 2) If not found, fall back to any legacy profile template (for older DBs),
-    //    but do NOT fail with "Profile not found" when profiles are no longer used.
-    let template_path = if let Some(p) = tax_balance_example_template_path() {
-        p.to_str().map(String::from)
-    } else if profile_id > 0 {
-        // Legacy fallback: try to read template path from DB, but swallow errors and let caller know
-        // if nothing could be found.
-        let db_guard = state
-            .db
-            .lock()
-            .map_err(|e: std::sync::PoisonError<_>| e.to_string())?;
-        let db = db_guard.as_ref().ok_or("Database not initialized")?;
-        match db.get_profile_by_id(profile_id) {
-            Ok((excel_path, _sheet_name, _)) => Some(excel_path),
-            Err(_) => None,
-        }
-    } else {
-        None
+    path: String,
+    excel_path: Option<String>,
+) -> Result<preset_bundles::PresetImportResult, AppError> {
+    state.ensure_writable()?;
+    ensure_path_allowed(&state, &path)?;
+    if let Some(excel_path) = &excel_path {
+        ensure_path_allowed(&state, excel_path)?;
     }
-    .ok_or_else(|| "Не можам да ја најдам Excel шаблон датотеката за Даночен биланс.".to_string())?;
+    let bundle = preset_bundles::read(Path::new(&path)).map_err(AppError::Validation)?;
+    with_db_blocking(&state, move |db| preset_bundles::import(db, bundle, excel_path.as_deref())).await
+}
 
-    let dest = dest_path.clone();
-    tauri::async_runtime::spawn_blocking(move || {
-        fs::copy(Path::new(&template_path), Path::new(&dest)).map_err(|e| e.to_string())?;
-        let updates: Vec<(u32, &'static str, String)> = tax_balance_row_map()
-            .into_iter()
-            .map(|(field_key, row)| {
-                let value = invoice.fields.get(&field_key).map(|v| v.value.clone()).unwrap_or_default();
-                (row, "D", value)
-            })
-            .collect();
-        let refs: Vec<(u32, &str, &str)> = updates
-            .iter()
-            .map(|(r, c, v)| (*r, *c, v.as_str()))
-            .collect();
-        excel::fill_tax_balance_cells_via_zip(Path::new(&dest), &refs)?;
-        Ok::<(), String>(())
+/// Custom field mappings of Azure analyzers, grouped by model; one model when `model_id` is given.
+#[tauri::command]
+pub async fn get_model_field_mappings(
+    state: State<'_, AppState>,
+    model_id: Option<String>,
+) -> Result<Vec<model_mappings::ModelFieldMappings>, AppError> {
+    with_db_blocking(&state, move |db| model_mappings::get(db, model_id.as_deref())).await
+}
+
+/// Replace an analyzer's field mappings; used from the next scan on. An empty list removes them.
+#[tauri::command]
+pub async fn save_model_field_mappings(
+    state: State<'_, AppState>,
+    model_id: String,
+    mappings: Vec<model_mappings::FieldMapping>,
+) -> Result<model_mappings::ModelFieldMappings, AppError> {
+    state.ensure_writable()?;
+    with_db_blocking(&state, move |db| model_mappings::save(db, &model_id, mappings)).await
+}
+
+/// Field schemas of the built-in document types and of every type with a saved schema.
+#[tauri::command]
+pub async fn get_field_schemas(state: State<'_, AppState>) -> Result<Vec<field_schemas::FieldSchema>, AppError> {
+    with_db_blocking(&state, field_schemas::list).await
+}
+
+/// Effective schema of one document type (saved, else built-in); drives the review form.
+#[tauri::command]
+pub async fn get_field_schema(state: State<'_, AppState>, document_type: String) -> Result<field_schemas::FieldSchema, AppError> {
+    with_db_blocking(&state, move |db| field_schemas::get(db, &document_type)).await
+}
+
+#[tauri::command]
+pub async fn save_field_schema(
+    state: State<'_, AppState>,
+    document_type: String,
+    fields: Vec<field_schemas::SchemaField>,
+) -> Result<field_schemas::FieldSchema, AppError> {
+    state.ensure_writable()?;
+    with_db_blocking(&state, move |db| {
+        field_schemas::save(db, &document_type, fields).map_err(AppError::Validation)
     })
     .await
-    .map_err(|e| e.to_string())??;
-    Ok(dest_path)
 }
 
+/// Drop the saved schema of a document type and return the built-in one.
 #[tauri::command]
-pub async fn append_invoices_to_existing_excel(
-    excel_path: String,
+pub async fn reset_field_schema(state: State<'_, AppState>, document_type: String) -> Result<field_schemas::FieldSchema, AppError> {
+    state.ensure_writable()?;
+    with_db_blocking(&state, move |db| field_schemas::reset(db, &document_type)).await
+}
+
+/// Re-check edited fields against the document type's schema (review form, before saving).
+#[tauri::command]
+pub async fn validate_with_field_schema(
+    state: State<'_, AppState>,
+    document_type: String,
+    invoice: InvoiceData,
+) -> Result<Vec<crate::types::ValidationWarning>, AppError> {
+    let schema = with_db_blocking(&state, move |db| field_schemas::get(db, &document_type)).await?;
+    Ok(field_schemas::validate(&schema, &invoice))
+}
+
+/// New workbook with the columns of the document type's schema (fields with an export header, in order).
+#[tauri::command]
+pub async fn export_with_field_schema(
+    state: State<'_, AppState>,
+    path: String,
     worksheet_name: String,
-    header_row: u32,
+    document_type: String,
     invoices: Vec<InvoiceData>,
-) -> Result<(), String> {
+    profile_id: Option<i64>,
+) -> Result<String, AppError> {
+    ensure_path_allowed(&state, &path)?;
+    ensure_strict_batch(&state, profile_id, &invoices, Some(document_type.clone()), None).await?;
+    let (schema, locale, sort) = with_db_blocking(&state, move |db| {
+        Ok::<_, AppError>((
+            field_schemas::get(db, &document_type)?,
+            export_locale::get(db, profile_id)?,
+            export_order::get(db, profile_id)?,
+        ))
+    })
+    .await?;
+    let invoices = export_order::sorted(invoices, &sort);
+    let (headers, keys) = field_schemas::export_columns(&schema);
+    if headers.is_empty() {
+        return Err(AppError::Validation(format!(
+            "The schema of '{}' has no exported fields.",
+            schema.document_type
+        )));
+    }
     tauri::async_runtime::spawn_blocking(move || {
-        excel::append_invoices_to_existing_excel(&excel_path, &worksheet_name, header_row, &invoices)
+        excel::export_to_new_excel_with_columns(&path, &worksheet_name, &headers, &keys, &invoices, &locale)
     })
     .await
-    .map_err(|e| e.to_string())?
+    .map_err(AppError::internal)?
 }
 
+/// OCR service used for scans (Azure or AWS Textract) and the Textract credentials.
 #[tauri::command]
-pub fn validate_document_file(path: String) -> Result<ValidationResult, String> {
-    let path = Path::new(&path);
-    if !path.exists() {
-        return Ok(ValidationResult {
-            valid: false,
-            error: Some("File not found.".to_string()),
-        });
-    }
-    let metadata = fs::metadata(path).map_err(|e| e.to_string())?;
-    if metadata.len() > 50 * 1024 * 1024 {
-        return Ok(ValidationResult {
-            valid: false,
-            error: Some("File too large (max 50MB).".to_string()),
-        });
-    }
-    let mut f = fs::File::open(path).map_err(|e| format!("Could not open: {}", e))?;
-    let mut header = [0u8; 8];
-    use std::io::Read;
-    if f.read(&mut header).unwrap_or(0) < 5 {
-        return Ok(ValidationResult {
-            valid: false,
-            error: Some("Not a valid PDF (could not read header).".to_string()),
-        });
-    }
-    if !header.starts_with(b"%PDF-") {
-        return Ok(ValidationResult {
-            valid: false,
-            error: Some("Not a valid PDF file.".to_string()),
-        });
-    }
-    Ok(ValidationResult {
-        valid: true,
-        error: None,
+pub async fn get_ocr_provider_settings(state: State<'_, AppState>) -> Result<ocr_provider::OcrProviderSettings, AppError> {
+    with_db_blocking(&state, ocr_provider::get_settings).await
+}
+
+#[tauri::command]
+pub async fn set_ocr_provider_settings(
+    state: State<'_, AppState>,
+    update: ocr_provider::OcrProviderSettingsUpdate,
+) -> Result<ocr_provider::OcrProviderSettings, AppError> {
+    state.ensure_writable()?;
+    with_db_blocking(&state, move |db| {
+        ocr_provider::save_settings(db, update).map_err(AppError::Validation)
     })
+    .await
 }
 
+/// LLM endpoint used to turn plain-text scans (plata, generic) into fields; no endpoint = disabled.
 #[tauri::command]
-pub fn validate_excel_file(path: String) -> Result<ValidationResult, String> {
-    let path = Path::new(&path);
-    if !path.exists() {
-        return Ok(ValidationResult {
-            valid: false,
-            error: Some("File not found.".to_string()),
-        });
-    }
-    let metadata = fs::metadata(path).map_err(|e| e.to_string())?;
-    if metadata.len() > 100 * 1024 * 1024 {
-        return Ok(ValidationResult {
-            valid: false,
-            error: Some("File too large (max 100MB).".to_string()),
-        });
-    }
-    let mut f = fs::File::open(path).map_err(|e| format!("Could not open: {}", e))?;
-    let mut header = [0u8; 4];
-    use std::io::Read;
-    if f.read(&mut header).unwrap_or(0) < 4 {
-        return Ok(ValidationResult {
-            valid: false,
-            error: Some("Not a valid Excel file (could not read header).".to_string()),
-        });
-    }
-    if header != [0x50, 0x4B, 0x03, 0x04] {
-        return Ok(ValidationResult {
-            valid: false,
-            error: Some("Not a valid Excel file (.xlsx).".to_string()),
-        });
-    }
-    match fs::OpenOptions::new().write(true).open(path) {
-        Ok(_) => Ok(ValidationResult {
-            valid: true,
-            error: None,
-        }),
-        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => Ok(ValidationResult {
-            valid: false,
-            error: Some("Excel file is open. Please close it and try again.".to_string()),
-        }),
-        Err(e) => Err(e.to_string()),
+pub async fn get_llm_settings(state: State<'_, AppState>) -> Result<llm_extraction::LlmSettings, AppError> {
+    with_db_blocking(&state, llm_extraction::get_settings).await
+}
+
+#[tauri::command]
+pub async fn set_llm_settings(
+    state: State<'_, AppState>,
+    update: llm_extraction::LlmSettingsUpdate,
+) -> Result<llm_extraction::LlmSettings, AppError> {
+    state.ensure_writable()?;
+    with_db_blocking(&state, move |db| {
+        llm_extraction::save_settings(db, update).map_err(AppError::Validation)
+    })
+    .await
+}
+
+/// Where archived originals go: local app data, a network share or S3-compatible storage.
+#[tauri::command]
+pub async fn get_archive_settings(state: State<'_, AppState>) -> Result<archive_store::ArchiveSettings, AppError> {
+    with_db_blocking(&state, archive_store::get_archive_settings).await
+}
+
+#[tauri::command]
+pub async fn set_archive_settings(
+    state: State<'_, AppState>,
+    update: archive_store::ArchiveSettingsUpdate,
+) -> Result<archive_store::ArchiveSettings, AppError> {
+    state.ensure_writable()?;
+    if let Some(share_path) = update.share_path.as_deref().filter(|p| !p.trim().is_empty()) {
+        ensure_path_allowed(&state, share_path)?;
     }
+    with_db_blocking(&state, move |db| archive_store::save_archive_settings(db, update)).await
 }
 
+/// Copy a scanned original to the configured archive backend and link it to the history record.
 #[tauri::command]
-pub fn read_file_base64(path: String) -> Result<String, String> {
-    let bytes = fs::read(Path::new(&path)).map_err(|e| {
-        if e.kind() == io::ErrorKind::NotFound {
-            "File not found.".to_string()
-        } else {
-            format!("Could not read file: {}", e)
-        }
-    })?;
-    Ok(BASE64.encode(&bytes))
+pub async fn archive_document(
+    state: State<'_, AppState>,
+    history_id: i64,
+    file_path: String,
+) -> Result<archive_store::ArchivedDocument, AppError> {
+    state.ensure_writable()?;
+    ensure_path_allowed(&state, &file_path)?;
+    let app_data_dir = state.app_data_dir();
+    with_db_blocking(&state, move |db| archive_store::archive_document(db, &app_data_dir, history_id, &file_path)).await
 }
 
+/// Archive record of a history entry (None when the original was not archived).
 #[tauri::command]
-pub fn write_file_base64(path: String, base64_content: String) -> Result<(), String> {
-    let bytes = BASE64.decode(&base64_content).map_err(|e| format!("Invalid base64: {}", e))?;
-    fs::write(Path::new(&path), &bytes).map_err(|e| format!("Could not write file: {}", e))?;
-    Ok(())
+pub async fn get_archived_document(
+    state: State<'_, AppState>,
+    history_id: i64,
+) -> Result<Option<archive_store::ArchivedDocument>, AppError> {
+    with_db_blocking(&state, move |db| archive_store::get_archived_document(db, history_id)).await
 }
 
+/// Local path of the archived original (downloaded first when it lives in object storage).
 #[tauri::command]
-pub fn copy_file(src: String, dest: String) -> Result<(), String> {
-    fs::copy(Path::new(&src), Path::new(&dest)).map_err(|e| format!("Could not copy file: {}", e))?;
-    Ok(())
+pub async fn get_archived_document_path(state: State<'_, AppState>, history_id: i64) -> Result<String, AppError> {
+    let app_data_dir = state.app_data_dir();
+    with_db_blocking(&state, move |db| archive_store::archived_document_path(db, &app_data_dir, history_id))
+        .await
+        .map(|p| p.to_string_lossy().to_string())
 }
 
+/// Open the archived original in the default viewer (downloaded first when it lives in object storage).
 #[tauri::command]
-pub fn delete_file(path: String) -> Result<(), String> {
-    fs::remove_file(Path::new(&path)).map_err(|e| format!("Could not delete file: {}", e))?;
-    Ok(())
+pub async fn open_archived_document(state: State<'_, AppState>, history_id: i64) -> Result<(), AppError> {
+    let app_data_dir = state.app_data_dir();
+    let path = with_db_blocking(&state, move |db| archive_store::archived_document_path(db, &app_data_dir, history_id))
+        .await?;
+    opener::open(&path).map_err(AppError::io)
 }
 
 #[tauri::command]
-pub fn get_excel_schema(state: State<AppState>, path: String) -> Result<ExcelSchemaResponse, String> {
-    let metadata = fs::metadata(Path::new(&path)).map_err(|e| format!("File not found: {}", e))?;
-    let mtime = metadata
-        .modified()
-        .map_err(|e| format!("Cannot get mtime: {}", e))?;
-    let mtime_ms = mtime
-        .duration_since(UNIX_EPOCH)
-        .map(|d| d.as_millis())
-        .unwrap_or(0);
-    let cache_key = format!("{}:{}", path, mtime_ms);
+pub async fn get_delivery_settings(state: State<'_, AppState>) -> Result<export_delivery::DeliverySettings, AppError> {
+    with_db_blocking(&state, export_delivery::get_delivery_settings).await
+}
 
-    let db = state.db.lock().map_err(|e| e.to_string())?;
-    let db = db.as_ref().ok_or("Database not initialized")?;
-    if let Some(schema_json) = db.get_cached_schema(&cache_key)? {
-        return Ok(ExcelSchemaResponse {
-            cached: true,
-            schema_json: Some(schema_json),
-            file_bytes: None,
-        });
+#[tauri::command]
+pub async fn set_delivery_settings(
+    state: State<'_, AppState>,
+    update: export_delivery::DeliverySettingsUpdate,
+) -> Result<export_delivery::DeliverySettings, AppError> {
+    state.ensure_writable()?;
+    if let Some(share_path) = update.share_path.as_deref().filter(|p| !p.trim().is_empty()) {
+        ensure_path_allowed(&state, share_path)?;
     }
-
-    // Do not send file_bytes: frontend will use analyze_excel_schema instead to avoid OOM.
-    Ok(ExcelSchemaResponse {
-        cached: false,
-        schema_json: None,
-        file_bytes: None,
-    })
+    with_db_blocking(&state, move |db| export_delivery::save_delivery_settings(db, update)).await
 }
 
-/// Scan Excel file and return full schema (headers, formats, next_free_row). Uses edit-xlsx for format reading.
+/// Copy a finished export to `destination`, the profile's destination or the default share, verified by checksum.
 #[tauri::command]
-pub async fn scan_excel_schema(
-    excel_path: String,
-    worksheet_name: String,
-) -> Result<ExcelSchema, String> {
-    let path = excel_path.clone();
-    let sheet = worksheet_name.clone();
-    tauri::async_runtime::spawn_blocking(move || {
-        let path = std::path::Path::new(&path);
-        let (header_row, headers, last_data_row, next_free_row, total_rows, columns, row_template, file_size, file_mtime) =
-            excel_scanner::scan_excel_file(path, &sheet)?;
-        let total_columns = headers.len() as u16;
-        Ok(ExcelSchema {
-            header_row,
-            first_data_row: header_row + 1,
-            last_data_row,
-            next_free_row,
-            total_rows,
-            total_columns,
-            headers,
-            columns,
-            row_template,
-            file_size,
-            file_mtime,
-        })
+pub async fn deliver_export(
+    state: State<'_, AppState>,
+    path: String,
+    profile_id: Option<i64>,
+    destination: Option<String>,
+) -> Result<export_delivery::ExportDelivery, AppError> {
+    state.ensure_writable()?;
+    ensure_path_allowed(&state, &path)?;
+    if let Some(destination) = &destination {
+        ensure_path_allowed(&state, destination)?;
+    }
+    with_db_blocking(&state, move |db| {
+        export_delivery::deliver_export(db, profile_id, &path, destination.as_deref())
     })
     .await
-    .map_err(|e| e.to_string())?
 }
 
-/// Save scanned schema to database for the given profile (call after scan when creating/editing profile).
 #[tauri::command]
-pub fn save_excel_schema(
-    state: State<AppState>,
+pub async fn list_export_deliveries(
+    state: State<'_, AppState>,
+    profile_id: Option<i64>,
+    limit: Option<u32>,
+) -> Result<Vec<export_delivery::ExportDelivery>, AppError> {
+    with_db_blocking(&state, move |db| export_delivery::list_export_deliveries(db, profile_id, limit.unwrap_or(50))).await
+}
+
+#[tauri::command]
+pub async fn get_profile_delivery(
+    state: State<'_, AppState>,
     profile_id: i64,
-    schema: ExcelSchema,
-) -> Result<(), String> {
-    let db = state.db.lock().map_err(|e| e.to_string())?;
-    let db = db.as_ref().ok_or("Database not initialized")?;
-    db.save_excel_schema(profile_id, &schema)?;
-    schema_cache::set_cached_schema(profile_id, schema);
-    Ok(())
+) -> Result<Option<export_delivery::ProfileDelivery>, AppError> {
+    with_db_blocking(&state, move |db| export_delivery::get_profile_delivery(db, profile_id)).await
 }
 
-/// Get excel schema for a profile from cache or database. Validates cache with file mtime.
+/// Set a profile's delivery destination (network share or SFTP with key-based login).
 #[tauri::command]
-pub fn get_excel_schema_for_profile(
+pub async fn set_profile_delivery(
     state: State<'_, AppState>,
     profile_id: i64,
-    force_refresh: bool,
-) -> Result<ExcelSchema, String> {
-    if !force_refresh {
-        if let Some(cached) = schema_cache::get_cached_schema(profile_id) {
-            let db = state.db.lock().map_err(|e| e.to_string())?;
-            let db = db.as_ref().ok_or("Database not initialized")?;
-            if is_cache_valid(db, profile_id, &cached)? {
-                return Ok(cached);
-            }
-            schema_cache::invalidate_cache(profile_id);
-        }
+    update: export_delivery::ProfileDeliveryUpdate,
+) -> Result<export_delivery::ProfileDelivery, AppError> {
+    state.ensure_writable()?;
+    if let Some(share_path) = update.share_path.as_deref().filter(|p| !p.trim().is_empty()) {
+        ensure_path_allowed(&state, share_path)?;
     }
-
-    let db = state.db.lock().map_err(|e| e.to_string())?;
-    let db = db.as_ref().ok_or("Database not initialized")?;
-    let schema = db.load_excel_schema(profile_id)?;
-    schema_cache::set_cached_schema(profile_id, schema.clone());
-    Ok(schema)
+    with_db_blocking(&state, move |db| export_delivery::save_profile_delivery(db, profile_id, update)).await
 }
 
-fn is_cache_valid(db: &Db, profile_id: i64, cached: &ExcelSchema) -> Result<bool, String> {
-    let (excel_path, _, _) = db.get_profile_by_id(profile_id)?;
-    if !Path::new(&excel_path).exists() {
-        return Ok(false);
-    }
-    let metadata = fs::metadata(&excel_path).map_err(|e| e.to_string())?;
-    let current_mtime = metadata
-        .modified()
-        .map_err(|e| e.to_string())?
-        .duration_since(UNIX_EPOCH)
-        .map(|d| d.as_secs())
-        .unwrap_or(0);
-    Ok(current_mtime == cached.file_mtime)
+#[tauri::command]
+pub async fn remove_profile_delivery(state: State<'_, AppState>, profile_id: i64) -> Result<(), AppError> {
+    state.ensure_writable()?;
+    with_db_blocking(&state, move |db| export_delivery::remove_profile_delivery(db, profile_id)).await
 }
 
-/// Fast append: use cached schema (next_free_row), write row, update cache and DB.
-/// For Plata (sheet "МПИН"): write into Пресметка на плата grid by month column instead of appending a row.
+/// Check that a profile's destination is reachable; pins the SFTP host key on first success.
 #[tauri::command]
-pub async fn append_to_excel_fast(
+pub async fn test_profile_delivery(
     state: State<'_, AppState>,
     profile_id: i64,
-    invoice_data: InvoiceData,
-) -> Result<i64, String> {
-    let (excel_path, sheet_name, _column_mapping_json): (String, String, String) = {
-        let db = state.db.lock().map_err(|e: std::sync::PoisonError<_>| e.to_string())?;
-        let db = db.as_ref().ok_or("Database not initialized")?;
-        db.get_profile_by_id(profile_id)?
-    };
+) -> Result<export_delivery::ProfileDelivery, AppError> {
+    with_db_blocking(&state, move |db| export_delivery::test_profile_delivery(db, profile_id)).await
+}
 
-    // Plata: write into month column of Пресметка на плата template (no row append).
-    if sheet_name == "МПИН" {
-        let declaration_period = invoice_data
-            .fields
-            .get("declarationPeriod")
-            .or_else(|| invoice_data.fields.get("taxPeriod"))
-            .map(|v| v.value.clone())
-            .unwrap_or_else(String::new);
-        let path = excel_path.clone();
-        let sheet = sheet_name.clone();
-        let fields = invoice_data.fields.clone();
-        tauri::async_runtime::spawn_blocking(move || {
-            excel::write_plata_to_template(&path, &sheet, &declaration_period, &fields)
-        })
-        .await
-        .map_err(|e| e.to_string())??;
-        return Ok(0);
-    }
+#[tauri::command]
+pub async fn get_google_sheets_settings(
+    state: State<'_, AppState>,
+) -> Result<google_sheets::GoogleSheetsSettings, AppError> {
+    let app_data_dir = state.app_data_dir();
+    with_db_blocking(&state, move |db| google_sheets::get_settings(db, &app_data_dir)).await
+}
 
-    let schema = {
-        if let Some(cached) = schema_cache::get_cached_schema(profile_id) {
-            let db = state.db.lock().map_err(|e: std::sync::PoisonError<_>| e.to_string())?;
-            let db = db.as_ref().ok_or("Database not initialized")?;
-            if is_cache_valid(db, profile_id, &cached)? {
-                cached
-            } else {
-                schema_cache::invalidate_cache(profile_id);
-                let db = state.db.lock().map_err(|e: std::sync::PoisonError<_>| e.to_string())?;
-                let db = db.as_ref().ok_or("Database not initialized")?;
-                let s = db.load_excel_schema(profile_id)?;
-                schema_cache::set_cached_schema(profile_id, s.clone());
-                s
-            }
-        } else {
-            let db = state.db.lock().map_err(|e: std::sync::PoisonError<_>| e.to_string())?;
-            let db = db.as_ref().ok_or("Database not initialized")?;
-            let s = db.load_excel_schema(profile_id)?;
-            schema_cache::set_cached_schema(profile_id, s.clone());
-            s
-        }
-    };
+/// Save the Google OAuth client (id and secret of a "Desktop app" client).
+#[tauri::command]
+pub async fn set_google_sheets_settings(
+    state: State<'_, AppState>,
+    update: google_sheets::GoogleSheetsSettingsUpdate,
+) -> Result<google_sheets::GoogleSheetsSettings, AppError> {
+    state.ensure_writable()?;
+    let app_data_dir = state.app_data_dir();
+    with_db_blocking(&state, move |db| google_sheets::save_settings(db, &app_data_dir, update)).await
+}
 
-    let (excel_path, sheet_name, column_mapping_json): (String, String, String) = {
-        let db = state.db.lock().map_err(|e: std::sync::PoisonError<_>| e.to_string())?;
-        let db = db.as_ref().ok_or("Database not initialized")?;
-        db.get_profile_by_id(profile_id)?
+/// Sign in to Google in the browser and keep the refresh token. Resolves when the browser comes back.
+#[tauri::command]
+pub async fn connect_google_sheets(state: State<'_, AppState>) -> Result<google_sheets::GoogleSheetsSettings, AppError> {
+    state.ensure_writable()?;
+    let app_data_dir = state.app_data_dir();
+    let dir = app_data_dir.clone();
+    let client = with_db_blocking(&state, move |db| google_sheets::client(db, &dir)).await?;
+    let refresh_token = tauri::async_runtime::spawn_blocking(move || google_sheets::sign_in(&client))
+        .await
+        .map_err(AppError::internal)??;
+    with_db_blocking(&state, move |db| {
+        google_sheets::save_refresh_token(db, &app_data_dir, Some(&refresh_token))?;
+        google_sheets::get_settings(db, &app_data_dir)
+    })
+    .await
+}
+
+/// Sign out of Google (the token is revoked when Google can be reached).
+#[tauri::command]
+pub async fn disconnect_google_sheets(
+    state: State<'_, AppState>,
+) -> Result<google_sheets::GoogleSheetsSettings, AppError> {
+    state.ensure_writable()?;
+    let app_data_dir = state.app_data_dir();
+    let dir = app_data_dir.clone();
+    let connection = with_db_blocking(&state, move |db| google_sheets::connection(db, &dir)).await.ok();
+    if let Some(connection) = connection {
+        tauri::async_runtime::spawn_blocking(move || google_sheets::revoke(&connection))
+            .await
+            .map_err(AppError::internal)?;
+    }
+    with_db_blocking(&state, move |db| {
+        google_sheets::save_refresh_token(db, &app_data_dir, None)?;
+        google_sheets::get_settings(db, &app_data_dir)
+    })
+    .await
+}
+
+/// Spreadsheet id the profile appends to instead of its local workbook, if any.
+#[tauri::command]
+pub async fn get_profile_google_sheet(state: State<'_, AppState>, profile_id: i64) -> Result<Option<String>, AppError> {
+    with_db_blocking(&state, move |db| google_sheets::spreadsheet_id(db, profile_id)).await
+}
+
+/// Point the profile's appends at a Google spreadsheet (id or link), or back at its workbook (None).
+#[tauri::command]
+pub async fn set_profile_google_sheet(
+    state: State<'_, AppState>,
+    profile_id: i64,
+    spreadsheet: Option<String>,
+) -> Result<Option<String>, AppError> {
+    state.ensure_writable()?;
+    with_db_blocking(&state, move |db| google_sheets::set_spreadsheet(db, profile_id, spreadsheet.as_deref())).await
+}
+
+/// Headers of the profile's sheet in its Google spreadsheet; also checks the sign-in and access.
+#[tauri::command]
+pub async fn get_google_sheet_headers(
+    state: State<'_, AppState>,
+    profile_id: i64,
+) -> Result<Vec<crate::models::HeaderInfo>, AppError> {
+    let (target, connection) = google_sheet_destination(&state, profile_id)
+        .await?
+        .ok_or_else(|| AppError::Validation("The profile does not append to Google Sheets.".to_string()))?;
+    tauri::async_runtime::spawn_blocking(move || google_sheets::read_headers(&connection, &target))
+        .await
+    .map_err(AppError::internal)?
+    .map_err(AppError::Excel)
+}
+
+#[tauri::command]
+pub async fn get_profile_export_target(
+    state: State<'_, AppState>,
+    profile_id: i64,
+) -> Result<Option<export_targets::ProfileExportTarget>, AppError> {
+    let app_data_dir = state.app_data_dir();
+    with_db_blocking(&state, move |db| export_targets::get(db, &app_data_dir, profile_id)).await
+}
+
+/// Set the profile's database target (PostgreSQL or MySQL table and its column mapping).
+#[tauri::command]
+pub async fn set_profile_export_target(
+    state: State<'_, AppState>,
+    profile_id: i64,
+    update: export_targets::ProfileExportTargetUpdate,
+) -> Result<export_targets::ProfileExportTarget, AppError> {
+    state.ensure_writable()?;
+    let app_data_dir = state.app_data_dir();
+    with_db_blocking(&state, move |db| export_targets::set(db, &app_data_dir, profile_id, update)).await
+}
+
+#[tauri::command]
+pub async fn remove_profile_export_target(state: State<'_, AppState>, profile_id: i64) -> Result<(), AppError> {
+    state.ensure_writable()?;
+    with_db_blocking(&state, move |db| export_targets::remove(db, profile_id)).await
+}
+
+/// Connect to the profile's database target; returns the table's columns after checking the mapped ones.
+#[tauri::command]
+pub async fn test_profile_export_target(state: State<'_, AppState>, profile_id: i64) -> Result<Vec<String>, AppError> {
+    let app_data_dir = state.app_data_dir();
+    let (target, password) = with_db_blocking(&state, move |db| export_targets::load(db, &app_data_dir, profile_id))
+        .await?
+        .ok_or_else(|| AppError::Validation("The profile has no database target.".to_string()))?;
+    tauri::async_runtime::spawn_blocking(move || export_targets::test(&target, password.as_deref()))
+        .await
+        .map_err(AppError::internal)?
+        .map_err(AppError::from)
+}
+
+#[tauri::command]
+pub async fn get_webhook_settings(state: State<'_, AppState>) -> Result<webhooks::WebhookSettings, AppError> {
+    let app_data_dir = state.app_data_dir();
+    with_db_blocking(&state, move |db| webhooks::get_settings(db, &app_data_dir)).await
+}
+
+/// Save the webhook URL, the events sent to it and the signing secret.
+#[tauri::command]
+pub async fn set_webhook_settings(
+    state: State<'_, AppState>,
+    update: webhooks::WebhookSettingsUpdate,
+) -> Result<webhooks::WebhookSettings, AppError> {
+    state.ensure_writable()?;
+    let app_data_dir = state.app_data_dir();
+    with_db_blocking(&state, move |db| webhooks::save_settings(db, &app_data_dir, update)).await
+}
+
+/// Most recent webhook deliveries first (default 100).
+#[tauri::command]
+pub async fn list_webhook_deliveries(
+    state: State<'_, AppState>,
+    limit: Option<u32>,
+) -> Result<Vec<webhooks::WebhookDelivery>, AppError> {
+    with_db_blocking(&state, move |db| webhooks::list_deliveries(db, limit.unwrap_or(100))).await
+}
+
+/// Send a recorded delivery again (same body, current URL and secret). Resolves when it is delivered or
+/// its retries are used up.
+#[tauri::command]
+pub async fn retry_webhook_delivery(state: State<'_, AppState>, id: i64) -> Result<webhooks::WebhookDelivery, AppError> {
+    state.ensure_writable()?;
+    let db = state.db()?;
+    let app_data_dir = state.app_data_dir();
+    tauri::async_runtime::spawn_blocking(move || webhooks::deliver(&db, &app_data_dir, id))
+        .await
+        .map_err(AppError::internal)?
+        .map_err(AppError::from)
+}
+
+#[tauri::command]
+pub async fn get_email_intake_settings(
+    state: State<'_, AppState>,
+) -> Result<Option<email_intake::EmailIntakeSettings>, AppError> {
+    let app_data_dir = state.app_data_dir();
+    with_db_blocking(&state, move |db| email_intake::get_settings(db, &app_data_dir)).await
+}
+
+#[tauri::command]
+pub async fn set_email_intake_settings(
+    state: State<'_, AppState>,
+    update: email_intake::EmailIntakeSettingsUpdate,
+) -> Result<email_intake::EmailIntakeSettings, AppError> {
+    state.ensure_writable()?;
+    let app_data_dir = state.app_data_dir();
+    with_db_blocking(&state, move |db| email_intake::save_settings(db, &app_data_dir, update)).await
+}
+
+#[tauri::command]
+pub async fn remove_email_intake_settings(state: State<'_, AppState>) -> Result<(), AppError> {
+    state.ensure_writable()?;
+    with_db_blocking(&state, email_intake::remove_settings).await
+}
+
+/// Log in to the intake mailbox; returns how many messages it holds.
+#[tauri::command]
+pub async fn test_email_intake(state: State<'_, AppState>) -> Result<u32, AppError> {
+    let app_data_dir = state.app_data_dir();
+    with_db_blocking(&state, move |db| email_intake::test(db, &app_data_dir)).await
+}
+
+/// Poll the intake mailbox now; returns the attachments queued. Also emits `email-intake-received`.
+#[tauri::command]
+pub async fn poll_email_intake(app: AppHandle, state: State<'_, AppState>) -> Result<Vec<email_intake::IntakeItem>, AppError> {
+    state.ensure_writable()?;
+    let app_data_dir = state.app_data_dir();
+    let added = with_db_blocking(&state, move |db| email_intake::poll(db, &app_data_dir)).await?;
+    if !added.is_empty() {
+        let _ = app.emit("email-intake-received", &added);
+    }
+    Ok(added)
+}
+
+/// Background polling at the configured interval. Waits a minute between checks while intake is off;
+/// failures only go to the log.
+pub async fn poll_email_intake_in_background(app: AppHandle) {
+    const IDLE: std::time::Duration = std::time::Duration::from_secs(60);
+    loop {
+        let state = app.state::<AppState>();
+        let app_data_dir = state.app_data_dir();
+        let interval = if state.read_only {
+            None
+        } else {
+            with_db_blocking(&state, move |db| email_intake::poll_interval(db, &app_data_dir))
+                .await
+                .unwrap_or(None)
+        };
+        let Some(interval) = interval else {
+            tokio::time::sleep(IDLE).await;
+            continue;
+        };
+        let app_data_dir = state.app_data_dir();
+        match with_db_blocking(&state, move |db| email_intake::poll(db, &app_data_dir)).await {
+            Ok(added) if !added.is_empty() => {
+                let _ = app.emit("email-intake-received", &added);
+            }
+            Ok(_) => {}
+            Err(e) => app_warnings::report("email_intake", format!("poll failed: {}", e)),
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
+
+/// Intake items, oldest first; `status` "queued" gives the ones waiting for a batch scan.
+#[tauri::command]
+pub async fn list_email_intake(
+    state: State<'_, AppState>,
+    status: Option<String>,
+) -> Result<Vec<email_intake::IntakeItem>, AppError> {
+    with_db_blocking(&state, move |db| email_intake::list(db, status.as_deref())).await
+}
+
+/// Mark intake items "scanned" (after `batch_scan_invoices`) or "dismissed" (deletes the saved PDF).
+#[tauri::command]
+pub async fn set_email_intake_status(state: State<'_, AppState>, ids: Vec<i64>, status: String) -> Result<(), AppError> {
+    state.ensure_writable()?;
+    with_db_blocking(&state, move |db| email_intake::set_status(db, &ids, &status)).await
+}
+
+/// Disk usage per app-data store (archive, OCR cache, thumbnails, backups, templates) and quota warnings.
+#[tauri::command]
+pub async fn get_storage_usage(state: State<'_, AppState>) -> Result<storage::StorageUsage, AppError> {
+    let app_data_dir = state.app_data_dir();
+    let db_path = state.db_path.clone();
+    with_db_blocking(&state, move |db| storage::get_storage_usage(db, &app_data_dir, &db_path)).await
+}
+
+/// Set a store's quota in MB (0 = unlimited, None = default) and trim re-derivable stores right away.
+#[tauri::command]
+pub async fn set_storage_quota(
+    state: State<'_, AppState>,
+    store: String,
+    quota_mb: Option<u64>,
+) -> Result<Vec<storage::StoreCleanup>, AppError> {
+    state.ensure_writable()?;
+    let app_data_dir = state.app_data_dir();
+    with_db_blocking(&state, move |db| {
+        storage::set_storage_quota(db, &store, quota_mb)?;
+        storage::enforce_storage_quotas(db, &app_data_dir)
+    })
+    .await
+}
+
+/// Delete least-recently-used thumbnails and cached OCR JSON from stores over their quota.
+#[tauri::command]
+pub async fn cleanup_storage(state: State<'_, AppState>) -> Result<Vec<storage::StoreCleanup>, AppError> {
+    state.ensure_writable()?;
+    let app_data_dir = state.app_data_dir();
+    with_db_blocking(&state, move |db| storage::enforce_storage_quotas(db, &app_data_dir)).await
+}
+
+#[tauri::command]
+pub fn open_app_data_folder(app: AppHandle) -> Result<(), AppError> {
+    let path = app.path().app_data_dir().map_err(AppError::internal)?;
+    opener::open(&path).map_err(AppError::io)
+}
+
+#[tauri::command]
+pub fn run_ocr(state: State<AppState>, file_path: String) -> Result<crate::types::OcrResult, AppError> {
+    ensure_path_allowed(&state, &file_path)?;
+    ocr::run_ocr(&file_path)
+}
+
+/// Payload of the `ocr-status` event: emitted when a job starts and when it finishes.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OcrStatusEvent {
+    pub job_id: String,
+    pub file_path: String,
+    /// "started", "succeeded", "failed" or "cancelled"
+    pub status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Send `event` webhooks for `invoices` in the background (see `webhooks`); failures are recorded in
+/// `webhook_deliveries` and shown as app warnings here, so they never fail the scan or append itself.
+fn notify_webhooks(state: &AppState, event: webhooks::WebhookEvent, profile_id: Option<i64>, invoices: Vec<InvoiceData>) {
+    let db = match state.db() {
+        Ok(db) => db,
+        Err(e) => return app_warnings::report("webhooks", format!("skipped: {}", e)),
     };
+    let app_data_dir = state.app_data_dir();
+    tauri::async_runtime::spawn_blocking(move || {
+        let ids = match webhooks::queue(&db, &app_data_dir, event, profile_id, &invoices) {
+            Ok(ids) => ids,
+            Err(e) => return app_warnings::report("webhooks", format!("could not queue {}: {}", event.as_str(), e)),
+        };
+        for id in ids {
+            match webhooks::deliver(&db, &app_data_dir, id) {
+                Ok(d) if d.status != "delivered" => {
+                    app_warnings::report("webhooks", format!("delivery {} failed: {}", id, d.error.unwrap_or_default()))
+                }
+                Ok(_) => {}
+                Err(e) => app_warnings::report("webhooks", format!("delivery {} failed: {}", id, e)),
+            }
+        }
+    });
+}
 
-    let column_mapping: std::collections::HashMap<String, String> =
-        serde_json::from_str(&column_mapping_json).unwrap_or_default();
+/// OCR one document without blocking a thread. Emits `ocr-progress` per Azure polling cycle and
+/// `ocr-status` on start/finish; pass `job_id` to be able to call `cancel_ocr`.
+#[tauri::command]
+pub async fn run_ocr_invoice(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    file_path: String,
+    document_type: Option<String>,
+    job_id: Option<String>,
+) -> Result<crate::types::OcrInvoiceResult, AppError> {
+    ensure_path_allowed(&state, &file_path)?;
+    let job_id = job_id
+        .filter(|id| !id.trim().is_empty())
+        .unwrap_or_else(|| format!("ocr-{}", chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default()));
+    let job = ocr::OcrJob::register(job_id.clone());
+    let _permit = work_pool::acquire(work_pool::WorkClass::Ocr).await;
+    let emit_status = |status: &str, error: Option<String>| {
+        let _ = app.emit(
+            "ocr-status",
+            OcrStatusEvent {
+                job_id: job_id.clone(),
+                file_path: file_path.clone(),
+                status: status.to_string(),
+                error,
+            },
+        );
+    };
+    emit_status("started", None);
+    let progress_app = app.clone();
+    let on_progress = move |p: ocr::OcrProgress| {
+        let _ = progress_app.emit("ocr-progress", p);
+    };
+    let mut result = ocr::run_ocr_invoice_async(&file_path, document_type.as_deref(), &job, &on_progress).await;
+    let extraction_type = document_types::extraction_type(document_type.as_deref());
+    if let Ok(r) = &mut result {
+        if llm_extraction::applies(extraction_type.as_deref(), r) {
+            let dt = document_type.clone().unwrap_or_default();
+            let text = r.raw_text.clone().unwrap_or_default();
+            let extracted = with_db_blocking(&state, move |db| {
+                let Some(cfg) = llm_extraction::config(db)? else {
+                    return Ok::<_, String>(None);
+                };
+                let schema = field_schemas::get(db, &dt)?;
+                llm_extraction::extract(&cfg, &schema.fields, &text).map(Some)
+            })
+            .await;
+            match extracted {
+                Ok(Some(fields)) => llm_extraction::merge(&mut r.invoice_data, fields),
+                Ok(None) => {}
+                Err(e) => app_warnings::report("llm_extraction", format!("post-processing skipped: {}", e)),
+            }
+        }
+        let mut invoice = r.invoice_data.clone();
+        match with_db_blocking(&state, move |db| {
+            let mut applied = vendor_corrections::apply(db, &mut invoice)?;
+            // Canonical vendor name last, so a learned name correction is what gets matched.
+            for change in vendors::observe(db, &mut invoice)? {
+                applied.retain(|a| a.field_key != change.field_key);
+                applied.push(change);
+            }
+            Ok::<_, String>((invoice, applied))
+        })
+        .await
+        {
+            Ok((invoice, applied)) => {
+                r.invoice_data = invoice;
+                r.applied_corrections = applied;
+            }
+            Err(e) => app_warnings::report("vendors", format!("could not apply vendor data: {}", e)),
+        }
+        r.warnings = validation::validate_invoice(extraction_type.as_deref(), &r.invoice_data);
+        if let Some(dt) = document_type.clone() {
+            match with_db_blocking(&state, move |db| field_schemas::get(db, &dt)).await {
+                Ok(schema) => {
+                    for w in field_schemas::validate(&schema, &r.invoice_data) {
+                        if !r.warnings.iter().any(|e| e.field_key == w.field_key) {
+                            r.warnings.push(w);
+                        }
+                    }
+                }
+                Err(e) => app_warnings::report("field_schemas", format!("could not load schema: {}", e)),
+            }
+        }
+    }
+    match &result {
+        Ok(r) => {
+            emit_status("succeeded", None);
+            notify_webhooks(&state, webhooks::WebhookEvent::ScanSucceeded, None, vec![r.invoice_data.clone()]);
+        }
+        Err(e) if e.message() == ocr::OCR_CANCELLED => emit_status("cancelled", None),
+        Err(e) => emit_status("failed", Some(e.to_string())),
+    }
+    result
+}
 
-    let row_number = schema.next_free_row;
-    let mut column_values = Vec::new();
-    for h in schema.headers.iter() {
-        let field_key = column_mapping
-            .get(&h.column_letter)
-            .or_else(|| column_mapping.get(&h.column_letter.to_uppercase()))
-            .map(String::from)
-            .unwrap_or_else(|| format!("col_{}", h.column_letter));
-        let mut value = invoice_data
-            .fields
-            .get(&field_key)
-            .map(|v| v.value.clone())
-            .unwrap_or_else(String::new);
-        // DDV template: write month name (e.g. "Февруари") in Период column instead of full date range
-        if field_key == "taxPeriod" {
-            if let Some(month_name) = excel::period_to_month_name_mk(&value) {
-                value = month_name;
+/// Cancel a running `run_ocr_invoice` job. Returns false if the job already finished.
+#[tauri::command]
+pub fn cancel_ocr(job_id: String) -> bool {
+    ocr::cancel_ocr_job(&job_id)
+}
+
+/// Re-check a workbook lock right away (user just closed Excel). Returns false if nothing is waiting.
+#[tauri::command]
+pub fn retry_excel_write_now(wait_id: String) -> bool {
+    excel_lock::retry_now(&wait_id)
+}
+
+/// Processes / users holding a workbook open (e.g. EXCEL.EXE in Maria's session). Empty when unknown.
+#[tauri::command]
+pub fn get_excel_lock_owners(state: State<AppState>, path: String) -> Result<Vec<excel_lock::LockOwner>, AppError> {
+    ensure_path_allowed(&state, &path)?;
+    Ok(excel_lock::find_lock_owners(Path::new(&path)))
+}
+
+/// Stop waiting for a locked workbook; the pending write fails. Returns false if nothing is waiting.
+#[tauri::command]
+pub fn cancel_excel_lock_wait(wait_id: String) -> bool {
+    excel_lock::cancel_wait(&wait_id)
+}
+
+/// Payload of the `batch-scan-progress` event: emitted when each file starts and when it finishes.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchScanProgress {
+    pub file_name: String,
+    /// 0-based position of the file in the batch.
+    pub index: usize,
+    pub total: usize,
+    /// "started", "finished", "failed" or "skipped" (already scanned)
+    pub state: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+fn emit_batch_progress(app: &AppHandle, file_name: &str, index: usize, total: usize, state: &str, error: Option<String>) {
+    let _ = app.emit(
+        "batch-scan-progress",
+        BatchScanProgress {
+            file_name: file_name.to_string(),
+            index,
+            total,
+            state: state.to_string(),
+            error,
+        },
+    );
+}
+
+/// Run OCR on multiple documents (PDFs or scanned images) in parallel; returns both successful and failed results.
+/// Emits `batch-scan-progress` for every file. Files wait for OCR slots of the worker pool, so a large batch
+/// runs at the OCR budget and leaves room for nothing less important until it is through. Files whose content
+/// is already in history, or repeats an earlier file of the batch, are not sent to OCR again but returned as
+/// `skipped_duplicates`, unless `rescan_duplicates` is set.
+#[tauri::command]
+pub async fn batch_scan_invoices(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    pdf_paths: Vec<String>,
+    document_type: Option<String>,
+    rescan_duplicates: Option<bool>,
+) -> Result<BatchScanResult, AppError> {
+    for path in &pdf_paths {
+        ensure_path_allowed(&state, path)?;
+    }
+    let total = pdf_paths.len();
+    let mut successes = Vec::new();
+    let mut failures = Vec::new();
+    let doc_type = document_type.clone();
+
+    let skipped_duplicates = if rescan_duplicates.unwrap_or(false) {
+        Vec::new()
+    } else {
+        let paths = pdf_paths.clone();
+        with_db_blocking(&state, move |db| archive_store::find_duplicates(db, &paths)).await?
+    };
+
+    // (index in the batch, path, file name) of the files sent to OCR.
+    let files: Vec<(usize, String, String)> = pdf_paths
+        .iter()
+        .enumerate()
+        .filter_map(|(index, path)| {
+            let filename = Path::new(path)
+                .file_name()
+                .and_then(|o| o.to_str())
+                .unwrap_or("")
+                .to_string();
+            if skipped_duplicates.iter().any(|d| d.file_path == *path) {
+                emit_batch_progress(&app, &filename, index, total, "skipped", None);
+                return None;
+            }
+            Some((index, path.clone(), filename))
+        })
+        .collect();
+
+    let handles: Vec<_> = files
+        .iter()
+        .map(|(index, path, filename)| {
+            let index = *index;
+            let path = path.clone();
+            let filename = filename.clone();
+            let doc_type = doc_type.clone();
+            let app = app.clone();
+            tauri::async_runtime::spawn(async move {
+                let _permit = work_pool::acquire(work_pool::WorkClass::Ocr).await;
+                tauri::async_runtime::spawn_blocking(move || {
+                    emit_batch_progress(&app, &filename, index, total, "started", None);
+                    let result = ocr::run_ocr_invoice(&path, doc_type.as_deref());
+                    match &result {
+                        Ok(_) => emit_batch_progress(&app, &filename, index, total, "finished", None),
+                        Err(e) => emit_batch_progress(&app, &filename, index, total, "failed", Some(e.to_string())),
+                    }
+                    result
+                })
+                .await
+            })
+        })
+        .collect();
+
+    for ((index, path, filename), h) in files.into_iter().zip(handles) {
+        match h.await.and_then(|inner| inner) {
+            Ok(Ok(res)) => {
+                let mut inv = res.invoice_data;
+                // Ensure document_type is populated for batch flows when the user selected
+                // a specific document type on the Home screen (Фактури, Даночен биланс, ДДВ, Плати).
+                if let Some(ref dt) = doc_type {
+                    let friendly = match dt.as_str() {
+                        "smetka" => Some("Даночен биланс"),
+                        "generic" => Some("ДДВ"),
+                        "plata" => Some("Плата"),
+                        "faktura" => Some("Фактура"),
+                        _ => None,
+                    };
+                    if let Some(label) = friendly {
+                        let needs_set = inv
+                            .fields
+                            .get("document_type")
+                            .map(|v| v.value.trim().is_empty())
+                            .unwrap_or(true);
+                        if needs_set {
+                            inv.fields.insert(
+                                "document_type".to_string(),
+                                InvoiceFieldValue {
+                                    value: label.to_string(),
+                                    confidence: Some(1.0),
+                                    ..Default::default()
+                                },
+                            );
+                        }
+                    }
+                }
+                inv.source_file = Some(filename.clone());
+                inv.source_file_path = Some(path.clone());
+                successes.push(inv);
+            }
+            Ok(Err(e)) => {
+                failures.push(FailedScan {
+                    file_path: path,
+                    file_name: filename,
+                    error: e.to_string(),
+                });
+            }
+            Err(e) => {
+                let error = format!("Task join error: {}", e);
+                emit_batch_progress(&app, &filename, index, total, "failed", Some(error.clone()));
+                failures.push(FailedScan {
+                    file_path: path,
+                    file_name: filename,
+                    error,
+                });
             }
         }
-        column_values.push((h.column_letter.clone(), value));
     }
 
-    let path = excel_path.clone();
-    let sheet = sheet_name.clone();
-    let row_num = row_number;
-    let values = column_values;
+    if !successes.is_empty() {
+        notify_webhooks(&state, webhooks::WebhookEvent::ScanSucceeded, None, successes.clone());
+    }
+    Ok(BatchScanResult { successes, failures, skipped_duplicates })
+}
+
+#[tauri::command]
+pub async fn export_invoices_to_excel(
+    state: State<'_, AppState>,
+    invoices: Vec<InvoiceData>,
+    path: Option<String>,
+    profile_id: Option<i64>,
+    document_type: Option<String>,
+    redact: Option<bool>,
+) -> Result<String, AppError> {
+    if let Some(path) = &path {
+        ensure_path_allowed(&state, path)?;
+    }
+    ensure_strict_batch(&state, profile_id, &invoices, document_type.clone(), None).await?;
+    let db = state.db()?;
     tauri::async_runtime::spawn_blocking(move || {
-        excel::append_row_to_excel_at_row(&path, &sheet, row_num, values)
+        let invoices = shared_copy(&db, redact.unwrap_or(false), profile_id, invoices)?;
+        let invoices = export_order::sorted(invoices, &export_order::get(&db, profile_id)?);
+        let path = export_target(&db, path, profile_id, document_type.as_deref(), &invoices, "xlsx")?;
+        let locale = export_locale::get(&db, profile_id)?;
+        excel::export_invoices_to_excel(&invoices, path.as_deref(), &locale)
     })
     .await
-    .map_err(|e| e.to_string())??;
+    .map_err(AppError::internal)?
+}
 
-    let new_next = row_number + 1;
-    {
-        let db = state.db.lock().map_err(|e: std::sync::PoisonError<_>| e.to_string())?;
-        let db = db.as_ref().ok_or("Database not initialized")?;
-        db.update_excel_schema_next_free_row(profile_id, new_next, row_number)?;
+#[tauri::command]
+pub async fn export_invoices_to_new_excel(
+    state: State<'_, AppState>,
+    invoices: Vec<InvoiceData>,
+    path: Option<String>,
+    worksheet_name: Option<String>,
+    profile_id: Option<i64>,
+    document_type: Option<String>,
+    redact: Option<bool>,
+) -> Result<String, AppError> {
+    if let Some(path) = &path {
+        ensure_path_allowed(&state, path)?;
     }
+    ensure_strict_batch(&state, profile_id, &invoices, document_type.clone(), None).await?;
+    let db = state.db()?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let invoices = shared_copy(&db, redact.unwrap_or(false), profile_id, invoices)?;
+        let invoices = export_order::sorted(invoices, &export_order::get(&db, profile_id)?);
+        let path = export_target(&db, path, profile_id, document_type.as_deref(), &invoices, "xlsx")?;
+        let locale = export_locale::get(&db, profile_id)?;
+        if let Some(dt) = document_type.as_deref().filter(|dt| document_types::export_layout(dt) == document_types::ExportLayout::Schema) {
+            let schema = field_schemas::get(&db, dt)?;
+            let (headers, keys) = field_schemas::export_columns(&schema);
+            if !headers.is_empty() {
+                let path = excel::new_export_path(path.as_deref(), "xlsx")?;
+                let sheet = excel::export_sheet_name(worksheet_name.as_deref());
+                return excel::export_to_new_excel_with_columns(&path.to_string_lossy(), sheet, &headers, &keys, &invoices, &locale);
+            }
+        }
+        excel::export_invoices_to_new_excel(&invoices, path.as_deref(), worksheet_name.as_deref(), &locale)
+    })
+    .await
+    .map_err(AppError::internal)?
+}
 
-    if let Some(mut cached) = schema_cache::get_cached_schema(profile_id) {
-        cached.next_free_row = new_next;
-        cached.last_data_row = row_number;
-        schema_cache::set_cached_schema(profile_id, cached);
+/// Same export as `export_invoices_to_new_excel`, written as OpenDocument (.ods) for LibreOffice.
+#[tauri::command]
+pub async fn export_invoices_to_ods(
+    state: State<'_, AppState>,
+    invoices: Vec<InvoiceData>,
+    path: Option<String>,
+    worksheet_name: Option<String>,
+    profile_id: Option<i64>,
+    document_type: Option<String>,
+    redact: Option<bool>,
+) -> Result<String, AppError> {
+    if let Some(path) = &path {
+        ensure_path_allowed(&state, path)?;
     }
+    ensure_strict_batch(&state, profile_id, &invoices, document_type.clone(), None).await?;
+    let db = state.db()?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let invoices = shared_copy(&db, redact.unwrap_or(false), profile_id, invoices)?;
+        let invoices = export_order::sorted(invoices, &export_order::get(&db, profile_id)?);
+        let path = export_target(&db, path, profile_id, document_type.as_deref(), &invoices, "ods")?;
+        ods::export_invoices_to_ods(&invoices, path.as_deref(), worksheet_name.as_deref()).map_err(AppError::Excel)
+    })
+    .await
+    .map_err(AppError::internal)?
+}
 
-    Ok(row_number as i64)
+/// With `redact`, the invoices with the profile's redaction rules applied (for exports sent to third parties).
+fn shared_copy(
+    db: &Db,
+    redact: bool,
+    profile_id: Option<i64>,
+    invoices: Vec<InvoiceData>,
+) -> Result<Vec<InvoiceData>, AppError> {
+    if !redact {
+        return Ok(invoices);
+    }
+    let profile_id = profile_id.ok_or(AppError::Validation("Redacted exports need a profile.".to_string()))?;
+    Ok(redaction::redact_for_profile(db, profile_id, invoices)?)
+}
+
+/// Explicit export path, else one named by the profile's file name template (None keeps Invoices_<timestamp>).
+fn export_target(
+    db: &Db,
+    path: Option<String>,
+    profile_id: Option<i64>,
+    document_type: Option<&str>,
+    invoices: &[InvoiceData],
+    extension: &str,
+) -> Result<Option<String>, AppError> {
+    if path.as_deref().is_some_and(|p| !p.trim().is_empty()) {
+        return Ok(path);
+    }
+    let named = export_naming::export_path(db, profile_id, document_type, invoices, extension)?;
+    // Without a naming template, a type with its own folder still exports there.
+    let named = named.or_else(|| {
+        let dir = document_type.and_then(document_types::default_folder)?;
+        let stem = format!("Invoices_{}", chrono::Local::now().format("%Y%m%d_%H%M%S"));
+        Some(excel::unique_export_path_in(&dir, &stem, extension))
+    });
+    Ok(named.map(|p| p.to_string_lossy().to_string()))
+}
+
+#[tauri::command]
+pub async fn get_redaction_rules(
+    state: State<'_, AppState>,
+    profile_id: i64,
+) -> Result<Vec<redaction::RedactionRule>, AppError> {
+    with_db_blocking(&state, move |db| redaction::get_rules(db, profile_id)).await
+}
+
+/// Replace a profile's redaction rules (empty list removes them).
+#[tauri::command]
+pub async fn set_redaction_rules(
+    state: State<'_, AppState>,
+    profile_id: i64,
+    rules: Vec<redaction::RedactionRule>,
+) -> Result<Vec<redaction::RedactionRule>, AppError> {
+    state.ensure_writable()?;
+    with_db_blocking(&state, move |db| {
+        redaction::save_rules(db, profile_id, rules).map_err(AppError::Validation)
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn get_export_locale(
+    state: State<'_, AppState>,
+    profile_id: i64,
+) -> Result<export_locale::ExportLocale, AppError> {
+    with_db_blocking(&state, move |db| export_locale::get(db, Some(profile_id))).await
+}
+
+/// Set the separators and date order of a profile's exports; None goes back to the default.
+#[tauri::command]
+pub async fn set_export_locale(
+    state: State<'_, AppState>,
+    profile_id: i64,
+    locale: Option<export_locale::ExportLocale>,
+) -> Result<export_locale::ExportLocale, AppError> {
+    state.ensure_writable()?;
+    with_db_blocking(&state, move |db| {
+        export_locale::set(db, profile_id, locale).map_err(AppError::Validation)
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn get_export_sort(
+    state: State<'_, AppState>,
+    profile_id: i64,
+) -> Result<export_order::ExportSort, AppError> {
+    with_db_blocking(&state, move |db| export_order::get(db, Some(profile_id))).await
+}
+
+/// Set the row order of a profile's batch exports and appends; None goes back to chronological.
+#[tauri::command]
+pub async fn set_export_sort(
+    state: State<'_, AppState>,
+    profile_id: i64,
+    sort: Option<export_order::ExportSort>,
+) -> Result<export_order::ExportSort, AppError> {
+    state.ensure_writable()?;
+    with_db_blocking(&state, move |db| export_order::set(db, profile_id, sort).map_err(AppError::Validation)).await
+}
+
+#[tauri::command]
+pub async fn get_strict_export(state: State<'_, AppState>, profile_id: i64) -> Result<bool, AppError> {
+    with_db_blocking(&state, move |db| strict_export::get(db, Some(profile_id))).await
+}
+
+/// Document type key -> worksheet for the profile's single-row appends.
+#[tauri::command]
+pub async fn get_sheet_routes(
+    state: State<'_, AppState>,
+    profile_id: i64,
+) -> Result<sheet_routes::SheetRoutes, AppError> {
+    with_db_blocking(&state, move |db| sheet_routes::get(db, profile_id)).await
+}
+
+/// Route document types to sheets of the profile's workbook; types left out go to the profile's sheet.
+#[tauri::command]
+pub async fn set_sheet_routes(
+    state: State<'_, AppState>,
+    profile_id: i64,
+    routes: sheet_routes::SheetRoutes,
+) -> Result<sheet_routes::SheetRoutes, AppError> {
+    state.ensure_writable()?;
+    with_db_blocking(&state, move |db| sheet_routes::set(db, profile_id, routes).map_err(AppError::Validation)).await
+}
+
+/// Turn a profile's strict mode on or off; in strict mode a batch with a failing document is not written.
+#[tauri::command]
+pub async fn set_strict_export(state: State<'_, AppState>, profile_id: i64, strict: bool) -> Result<bool, AppError> {
+    state.ensure_writable()?;
+    with_db_blocking(&state, move |db| strict_export::set(db, profile_id, strict).map_err(AppError::Validation)).await
+}
+
+/// Run the strict-mode checks on a batch without writing anything (whether or not the profile is strict),
+/// e.g. to show the report before exporting. `against_ledger` also looks for the documents in the profile's ledger.
+#[tauri::command]
+pub async fn check_strict_export(
+    state: State<'_, AppState>,
+    profile_id: i64,
+    invoices: Vec<InvoiceData>,
+    document_type: Option<String>,
+    against_ledger: Option<bool>,
+) -> Result<strict_export::StrictReport, AppError> {
+    let ledger = if against_ledger.unwrap_or(false) {
+        Some(profile_ledger(&state, profile_id).await?)
+    } else {
+        None
+    };
+    strict_report(&state, profile_id, invoices, document_type, ledger).await
+}
+
+/// (path, sheet, header row) of the profile's ledger; the header row is 1 before the first scan.
+async fn profile_ledger(state: &AppState, profile_id: i64) -> Result<(String, String, u32), AppError> {
+    with_db_blocking(state, move |db| {
+        let (excel_path, sheet_name, _) = db.get_profile_by_id(profile_id)?;
+        let header_row = cached_or_load_schema(db, profile_id).map_or(1, |schema| schema.header_row);
+        Ok::<_, AppError>((excel_path, sheet_name, header_row))
+    })
+    .await
+}
+
+/// `strict_export::check_batch` with the profile's duplicate key. `ledger` (path, sheet, header row) is read
+/// for documents already in it, unless the profile updates duplicate rows in place.
+async fn strict_report(
+    state: &AppState,
+    profile_id: i64,
+    invoices: Vec<InvoiceData>,
+    document_type: Option<String>,
+    ledger: Option<(String, String, u32)>,
+) -> Result<strict_export::StrictReport, AppError> {
+    let schema_type = document_type.clone();
+    let (field_schema, key, (_, _, column_mapping_json)) = with_db_blocking(state, move |db| {
+        Ok::<_, AppError>((
+            schema_type.as_deref().map(|dt| field_schemas::get(db, dt)).transpose()?,
+            duplicate_keys::get_key(db, profile_id)?,
+            db.get_profile_by_id(profile_id)?,
+        ))
+    })
+    .await?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let column_mapping = parse_column_mapping(&column_mapping_json);
+        let fields = duplicate_keys::effective_fields(key.as_ref(), &column_mapping)
+            .unwrap_or_else(|_| vec![duplicate_keys::DOCUMENT_NUMBER_KEYS[0].to_string()]);
+        let updates_in_place = key.as_ref().is_some_and(|k| k.on_duplicate == duplicate_keys::OnDuplicate::Update);
+        let ledger_keys = match (ledger, duplicate_keys::key_columns(&fields, &column_mapping)) {
+            (Some((path, sheet, header_row)), Ok(columns)) if !updates_in_place => {
+                duplicate_keys::ledger_keys(&path, &sheet, header_row, &fields, &columns).map_err(AppError::Excel)?
+            }
+            _ => Vec::new(),
+        };
+        Ok::<_, AppError>(strict_export::check_batch(
+            &invoices,
+            document_type.as_deref(),
+            field_schema.as_ref(),
+            &fields,
+            &ledger_keys,
+        ))
+    })
+    .await
+    .map_err(AppError::internal)?
+}
+
+/// When the profile is in strict mode, refuse the batch (with `strict_export::describe`) unless every
+/// document passes `strict_report`. Called before anything is written.
+async fn ensure_strict_batch(
+    state: &AppState,
+    profile_id: Option<i64>,
+    invoices: &[InvoiceData],
+    document_type: Option<String>,
+    ledger: Option<(String, String, u32)>,
+) -> Result<(), AppError> {
+    let Some(profile_id) = profile_id else {
+        return Ok(());
+    };
+    if !with_db_blocking(state, move |db| strict_export::get(db, Some(profile_id))).await? {
+        return Ok(());
+    }
+    let report = strict_report(state, profile_id, invoices.to_vec(), document_type, ledger).await?;
+    if report.passed {
+        Ok(())
+    } else {
+        Err(AppError::Validation(strict_export::describe(&report)))
+    }
+}
+
+#[tauri::command]
+pub async fn get_export_name_template(
+    state: State<'_, AppState>,
+    profile_id: Option<i64>,
+) -> Result<export_naming::ExportNameTemplate, AppError> {
+    with_db_blocking(&state, move |db| export_naming::get_template(db, profile_id)).await
+}
+
+/// Set (or clear with None) the export file name template of a profile, or the global default without profile_id.
+#[tauri::command]
+pub async fn set_export_name_template(
+    state: State<'_, AppState>,
+    profile_id: Option<i64>,
+    template: Option<String>,
+) -> Result<export_naming::ExportNameTemplate, AppError> {
+    state.ensure_writable()?;
+    with_db_blocking(&state, move |db| {
+        export_naming::set_template(db, profile_id, template).map_err(AppError::Validation)
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn export_to_new_excel_with_columns(
+    state: State<'_, AppState>,
+    path: String,
+    worksheet_name: String,
+    headers: Vec<String>,
+    column_field_keys: Vec<String>,
+    invoices: Vec<InvoiceData>,
+    profile_id: Option<i64>,
+) -> Result<String, AppError> {
+    ensure_path_allowed(&state, &path)?;
+    ensure_strict_batch(&state, profile_id, &invoices, None, None).await?;
+    let (locale, sort) = with_db_blocking(&state, move |db| {
+        Ok::<_, AppError>((export_locale::get(db, profile_id)?, export_order::get(db, profile_id)?))
+    })
+    .await?;
+    let invoices = export_order::sorted(invoices, &sort);
+    tauri::async_runtime::spawn_blocking(move || {
+        excel::export_to_new_excel_with_columns(
+            &path,
+            &worksheet_name,
+            &headers,
+            &column_field_keys,
+            &invoices,
+            &locale,
+        )
+    })
+    .await
+    .map_err(AppError::internal)?
+}
+
+/// Copy the profile's template file to dest_path and append each invoice as a row.
+/// Preserves the template's exact layout (merged headers, styling). Use this for Даночен биланс etc.
+/// For Plata (sheet "МПИН"): copy template then write each invoice into its month column (no row append).
+#[tauri::command]
+pub async fn copy_template_and_append_rows(
+    state: State<'_, AppState>,
+    profile_id: i64,
+    dest_path: String,
+    invoices: Vec<InvoiceData>,
+) -> Result<String, AppError> {
+    state.ensure_writable()?;
+    ensure_path_allowed(&state, &dest_path)?;
+    if invoices.is_empty() {
+        return Err(AppError::Validation("No invoices to export".to_string()));
+    }
+    ensure_strict_batch(&state, Some(profile_id), &invoices, None, None).await?;
+    let ((excel_path, sheet_name, column_mapping_json), sort) = with_db_blocking(&state, move |db| {
+        Ok::<_, AppError>((db.get_profile_by_id(profile_id)?, export_order::get(db, Some(profile_id))?))
+    })
+    .await?;
+    workbook_format::ensure_appendable(Path::new(&excel_path))?;
+    let invoices = export_order::sorted(invoices, &sort);
+
+    // Plata: copy template then write each invoice into its month column in Пресметка на плата grid.
+    if sheet_name == "МПИН" {
+        let template_path = excel_path.clone();
+        let dest = dest_path.clone();
+        let sheet = sheet_name.clone();
+        let inv = invoices;
+        let writes = tauri::async_runtime::spawn_blocking(move || {
+            fs::copy(Path::new(&template_path), Path::new(&dest)).map_err(AppError::io)?;
+            let mut writes = Vec::new();
+            for invoice in &inv {
+                let declaration_period = invoice
+                    .fields
+                    .get("declarationPeriod")
+                    .or_else(|| invoice.fields.get("taxPeriod"))
+                    .map(|v| v.value.clone())
+                    .unwrap_or_else(String::new);
+                writes.extend(excel::write_plata_to_template(&dest, &sheet, &declaration_period, &invoice.fields)?);
+            }
+            Ok::<_, String>(writes)
+        })
+        .await
+        .map_err(AppError::internal)??;
+        record_cell_writes(&state, dest_path.clone(), None, writes).await;
+        return Ok(dest_path);
+    }
+
+    let (schema, locale) = with_db_blocking(&state, move |db| {
+        Ok::<_, AppError>((cached_or_load_schema(db, profile_id)?, export_locale::get(db, Some(profile_id))?))
+    })
+    .await?;
+    let column_mapping: std::collections::HashMap<String, String> =
+        serde_json::from_str(&column_mapping_json).map_err(|e| AppError::Db(format!("Invalid column_mapping: {}", e)))?;
+
+    let template_path = excel_path.clone();
+    let dest = dest_path.clone();
+    let sheet = sheet_name.clone();
+    let inv = invoices;
+    let writes = tauri::async_runtime::spawn_blocking(move || {
+        fs::copy(Path::new(&template_path), Path::new(&dest)).map_err(AppError::io)?;
+        let mut writes = Vec::new();
+        let mut row = schema.next_free_row;
+        for invoice in &inv {
+            let mut column_values = Vec::new();
+            for h in &schema.headers {
+                let field_key = column_mapping
+                    .get(&h.column_letter)
+                    .or_else(|| column_mapping.get(&h.column_letter.to_uppercase()))
+                    .map(String::from)
+                    .unwrap_or_else(|| format!("col_{}", h.column_letter));
+                let mut value = invoice
+                    .fields
+                    .get(&field_key)
+                    .map(|v| v.value.clone())
+                    .unwrap_or_default();
+                // DDV template: write month name (e.g. "Февруари") in Период column instead of full date range
+                if field_key == "taxPeriod" {
+                    if let Some(month_name) = excel::period_to_month_name_mk(&value) {
+                        value = month_name;
+                    }
+                } else if excel::is_date_field(&field_key) {
+                    value = locale.date(&value);
+                }
+                column_values.push((h.column_letter.clone(), value));
+            }
+            writes.extend(excel::append_row_to_excel_at_row(&dest, &sheet, row, column_values, &schema, &locale)?);
+            row += 1;
+        }
+        Ok::<_, String>(writes)
+    })
+    .await
+    .map_err(AppError::internal)??;
+    record_cell_writes(&state, dest_path.clone(), None, writes).await;
+    Ok(dest_path)
+}
+
+/// Row (1-based) in РД-Данок на добивка template for each AOP field — value is written to column D.
+/// FullTaxBalanceAnalyzer: aop_1=row 10, aop_2=11, …, aop_59=68.
+fn tax_balance_row_map() -> Vec<(String, u32)> {
+    (1..=59).map(|i| (format!("aop_{}", i), 9 + i as u32)).collect()
+}
+
+/// Prefer the repo example template so the exported file matches the exact layout (first picture).
+fn tax_balance_example_template_path() -> Option<PathBuf> {
+    let rel = PathBuf::from("example")
+        .join("Примери за автоматизирање на процеси")
+        .join("Даночен биланс")
+        .join("РД-Данок на добивка-2024-Example.xlsx");
+    let mut dir = std::env::current_dir().ok()?;
+    for _ in 0..10 {
+        let candidate = dir.join(&rel);
+        if candidate.exists() {
+            return Some(candidate);
+        }
+        dir = dir.parent()?.to_path_buf();
+    }
+    None
+}
+
+/// Prefer the repo example template for Плати (РД-Трошоци за вработени) so export matches the desired layout.
+fn plata_example_template_path() -> Option<PathBuf> {
+    let rel = PathBuf::from("example")
+        .join("Примери за автоматизирање на процеси")
+        .join("Плати")
+        .join("РД-Трошоци за вработени-Example.xlsx");
+    let mut dir = std::env::current_dir().ok()?;
+    for _ in 0..10 {
+        let candidate = dir.join(&rel);
+        if candidate.exists() {
+            return Some(candidate);
+        }
+        dir = dir.parent()?.to_path_buf();
+    }
+    None
+}
+
+/// Response for get_plata_template_path: path to template and sheet name to use.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlataTemplatePath {
+    pub path: String,
+    pub sheet_name: String,
+}
+
+/// Returns the preferred Plata template path and sheet name. Uses the repo example file when present,
+/// otherwise the generated Plati-Template.xlsx in app data. No Settings change required.
+#[tauri::command]
+pub fn get_plata_template_path(app: AppHandle) -> Result<PlataTemplatePath, AppError> {
+    if let Some(p) = plata_example_template_path() {
+        if let Some(s) = p.to_str() {
+            return Ok(PlataTemplatePath {
+                path: s.to_string(),
+                sheet_name: "Пресметка на плата".to_string(),
+            });
+        }
+    }
+    let app_data = app.path().app_data_dir().map_err(AppError::internal)?;
+    let fallback = app_data.join("templates").join("Plati-Template.xlsx");
+    let path = fallback
+        .to_str()
+        .ok_or(AppError::Validation("Invalid path for Plati template".to_string()))?
+        .to_string();
+    if !fallback.exists() {
+        return Err(AppError::Excel("Шаблонот за Плати не е пронајден. Потребен е примерот РД-Трошоци за вработени-Example.xlsx или генериран шаблон во Поставки.".to_string()));
+    }
+    Ok(PlataTemplatePath {
+        path,
+        sheet_name: "МПИН".to_string(),
+    })
+}
+
+/// Copy the profile's template to dest_path and fill column D at the fixed rows (Даночен биланс form layout).
+/// Prefers the repo example template when present so the layout matches the first picture exactly.
+/// Legacy `profile_id` argument is ignored for new fixed-template flow; DB is only used as a fallback
+/// when the bundled example template is not found.
+#[tauri::command]
+pub async fn copy_template_and_fill_tax_balance(
+    state: State<'_, AppState>,
+    profile_id: i64,
+    dest_path: String,
+    invoice: InvoiceData,
+) -> Result<String, AppError> {
+    state.ensure_writable()?;
+    ensure_path_allowed(&state, &dest_path)?;
+    // 1) Try to use the bundled Даночен биланс example template from the repo.
+    // 2) If not found, fall back to any legacy profile template (for older DBs),
+    //    but do NOT fail with "Profile not found" when profiles are no longer used.
+    let template_path = if let Some(p) = tax_balance_example_template_path() {
+        p.to_str().map(String::from)
+    } else if profile_id > 0 {
+        // Legacy fallback: try to read template path from DB, but swallow errors and let caller know
+        // if nothing could be found.
+        with_db_blocking(&state, move |db| db.get_profile_by_id(profile_id))
+            .await
+            .ok()
+            .map(|(excel_path, _sheet_name, _)| excel_path)
+    } else {
+        None
+    }
+    .ok_or_else(|| AppError::Excel("Не можам да ја најдам Excel шаблон датотеката за Даночен биланс.".to_string()))?;
+
+    let dest = dest_path.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        fs::copy(Path::new(&template_path), Path::new(&dest)).map_err(AppError::io)?;
+        let updates: Vec<(u32, &'static str, String)> = tax_balance_row_map()
+            .into_iter()
+            .map(|(field_key, row)| {
+                let value = invoice.fields.get(&field_key).map(|v| v.value.clone()).unwrap_or_default();
+                (row, "D", value)
+            })
+            .collect();
+        let refs: Vec<(u32, &str, &str)> = updates
+            .iter()
+            .map(|(r, c, v)| (*r, *c, v.as_str()))
+            .collect();
+        excel::fill_tax_balance_cells_via_zip(Path::new(&dest), &refs)?;
+        Ok::<(), String>(())
+    })
+    .await
+    .map_err(AppError::internal)??;
+    Ok(dest_path)
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchExportSummary {
+    /// History entry for the whole export; per-invoice records are linked to it via parent_id.
+    pub history_id: i64,
+    pub excel_path: String,
+    pub worksheet_name: String,
+    pub first_row: u32,
+    pub last_row: u32,
+    pub invoice_count: usize,
+    pub net_total: f64,
+    pub tax_total: f64,
+    pub total: f64,
+    /// Invoices with a net, tax or total amount that is missing or not a number; left out of the totals.
+    pub amounts_missing: usize,
+}
+
+/// Append a batch of invoices and record the export as one history entry. `history_ids` are the
+/// per-invoice history records of the batch; they are grouped under the new entry. With
+/// `expected_total` (the cover sheet total) nothing is written unless the batch adds up to it.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn append_invoices_to_existing_excel(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    excel_path: String,
+    worksheet_name: String,
+    header_row: u32,
+    invoices: Vec<InvoiceData>,
+    history_ids: Option<Vec<i64>>,
+    wait_for_unlock_secs: Option<u64>,
+    wait_id: Option<String>,
+    expected_total: Option<f64>,
+    profile_id: Option<i64>,
+) -> Result<BatchExportSummary, AppError> {
+    state.ensure_writable()?;
+    ensure_path_allowed(&state, &excel_path)?;
+    if invoices.is_empty() {
+        return Err(AppError::Validation("No invoices to append.".to_string()));
+    }
+    if let Some(expected) = expected_total {
+        let reconciliation = validation::reconcile_batch(&invoices, Some(expected), None).map_err(AppError::Validation)?;
+        if !reconciliation.balanced {
+            return Err(AppError::Validation(validation::describe_imbalance(&reconciliation)));
+        }
+    }
+    workbook_format::ensure_appendable(Path::new(&excel_path))?;
+    let ledger = (excel_path.clone(), worksheet_name.clone(), header_row);
+    ensure_strict_batch(&state, profile_id, &invoices, None, Some(ledger)).await?;
+    wait_for_excel_unlock(&app, &excel_path, wait_for_unlock_secs, wait_id).await?;
+    let (locale, sort) = with_db_blocking(&state, move |db| {
+        Ok::<_, AppError>((export_locale::get(db, profile_id)?, export_order::get(db, profile_id)?))
+    })
+    .await?;
+    let invoices = export_order::sorted(invoices, &sort);
+    let (path, sheet) = (excel_path.clone(), worksheet_name.clone());
+    let _permit = work_pool::acquire(work_pool::WorkClass::Append).await;
+    let (invoices, (first_row, last_row, writes)) = tauri::async_runtime::spawn_blocking(move || {
+        let rows = excel::append_invoices_to_existing_excel(&path, &sheet, header_row, &invoices, &locale)?;
+        Ok::<_, String>((invoices, rows))
+    })
+    .await
+    .map_err(AppError::internal)??;
+
+    let amount = |inv: &InvoiceData, key: &str| inv.fields.get(key).and_then(|f| spending_report::parse_amount(&f.value));
+    let sum = |key: &str| -> f64 {
+        let total: f64 = invoices.iter().filter_map(|inv| amount(inv, key)).sum();
+        (total * 100.0).round() / 100.0
+    };
+    let amounts_missing = invoices
+        .iter()
+        .filter(|inv| ["net_amount", "tax_amount", "total_amount"].iter().any(|key| amount(inv, key).is_none()))
+        .count();
+    let mut summary = BatchExportSummary {
+        history_id: 0,
+        excel_path,
+        worksheet_name,
+        first_row,
+        last_row,
+        invoice_count: invoices.len(),
+        net_total: sum("net_amount"),
+        tax_total: sum("tax_amount"),
+        total: sum("total_amount"),
+        amounts_missing,
+    };
+    let source_files: Vec<String> = invoices.iter().filter_map(|inv| inv.source_file.clone()).collect();
+    let data = serde_json::json!({
+        "excelPath": summary.excel_path,
+        "worksheetName": summary.worksheet_name,
+        "firstRow": first_row,
+        "lastRow": last_row,
+        "invoiceCount": summary.invoice_count,
+        "netTotal": summary.net_total,
+        "taxTotal": summary.tax_total,
+        "total": summary.total,
+        "amountsMissing": summary.amounts_missing,
+        "sourceFiles": source_files,
+    });
+    let excel_path = summary.excel_path.clone();
+    let child_ids = history_ids.unwrap_or_default();
+    summary.history_id = with_db_blocking(&state, move |db| {
+        let id = db.add_history_record("batch_export", &excel_path, &data, "added_to_excel", None, None, None)?;
+        db.set_history_parent(id, &child_ids)?;
+        Ok::<_, AppError>(id)
+    })
+    .await?;
+    record_cell_writes(&state, summary.excel_path.clone(), Some(summary.history_id), writes).await;
+    Ok(summary)
+}
+
+/// Check a batch against its cover sheet: `expected_total` as entered, or the total scanned from the
+/// document at `cover_index`. Reports the difference and the documents that may explain it.
+#[tauri::command]
+pub fn reconcile_batch_totals(
+    invoices: Vec<InvoiceData>,
+    expected_total: Option<f64>,
+    cover_index: Option<usize>,
+) -> Result<validation::BatchReconciliation, AppError> {
+    validation::reconcile_batch(&invoices, expected_total, cover_index).map_err(AppError::Validation)
+}
+
+#[tauri::command]
+pub fn validate_document_file(state: State<AppState>, path: String) -> Result<ValidationResult, AppError> {
+    ensure_path_allowed(&state, &path)?;
+    let path = Path::new(&path);
+    if !path.exists() {
+        return Ok(ValidationResult {
+            valid: false,
+            error: Some("File not found.".to_string()),
+        });
+    }
+    let metadata = fs::metadata(path).map_err(AppError::io)?;
+    if metadata.len() > 50 * 1024 * 1024 {
+        return Ok(ValidationResult {
+            valid: false,
+            error: Some("File too large (max 50MB).".to_string()),
+        });
+    }
+    // PDF or scanned image (JPG/PNG/TIFF/HEIC), detected by magic bytes.
+    if document_format::detect_file_format(path)?.is_none() {
+        return Ok(ValidationResult {
+            valid: false,
+            error: Some(document_format::UNSUPPORTED_FORMAT.to_string()),
+        });
+    }
+    Ok(ValidationResult {
+        valid: true,
+        error: None,
+    })
+}
+
+#[tauri::command]
+pub fn validate_excel_file(state: State<AppState>, path: String) -> Result<ValidationResult, AppError> {
+    ensure_path_allowed(&state, &path)?;
+    let path = Path::new(&path);
+    if !path.exists() {
+        return Ok(ValidationResult {
+            valid: false,
+            error: Some("File not found.".to_string()),
+        });
+    }
+    let metadata = fs::metadata(path).map_err(AppError::io)?;
+    if metadata.len() > 100 * 1024 * 1024 {
+        return Ok(ValidationResult {
+            valid: false,
+            error: Some("File too large (max 100MB).".to_string()),
+        });
+    }
+    let mut f = fs::File::open(path).map_err(|e| AppError::Io(format!("Could not open: {}", e)))?;
+    let mut header = [0u8; 4];
+    use std::io::Read;
+    if f.read(&mut header).unwrap_or(0) < 4 {
+        return Ok(ValidationResult {
+            valid: false,
+            error: Some("Not a valid Excel file (could not read header).".to_string()),
+        });
+    }
+    if header != [0x50, 0x4B, 0x03, 0x04] {
+        return Ok(ValidationResult {
+            valid: false,
+            error: Some("Not a valid Excel file (.xlsx).".to_string()),
+        });
+    }
+    match fs::OpenOptions::new().write(true).open(path) {
+        Ok(_) => Ok(ValidationResult {
+            valid: true,
+            error: None,
+        }),
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => Ok(ValidationResult {
+            valid: false,
+            error: Some(excel_lock::locked_error(path)),
+        }),
+        Err(e) => Err(AppError::io(e)),
+    }
+}
+
+#[tauri::command]
+pub fn read_file_base64(state: State<AppState>, path: String) -> Result<String, AppError> {
+    let db = state.db()?;
+    let path = path_scope::check(&db, &state.app_data_dir(), &path)?;
+    let bytes = fs::read(&path).map_err(|e| {
+        if e.kind() == io::ErrorKind::NotFound {
+            "File not found.".to_string()
+        } else {
+            format!("Could not read file: {}", e)
+        }
+    })?;
+    Ok(BASE64.encode(&bytes))
+}
+
+#[tauri::command]
+pub fn write_file_base64(state: State<AppState>, path: String, base64_content: String) -> Result<(), AppError> {
+    state.ensure_writable()?;
+    let db = state.db()?;
+    let path = path_scope::check(&db, &state.app_data_dir(), &path)?;
+    let bytes = BASE64.decode(&base64_content).map_err(|e| AppError::Validation(format!("Invalid base64: {}", e)))?;
+    fs::write(&path, &bytes).map_err(|e| AppError::Io(format!("Could not write file: {}", e)))?;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn copy_file(state: State<AppState>, src: String, dest: String) -> Result<(), AppError> {
+    state.ensure_writable()?;
+    let db = state.db()?;
+    let src = path_scope::check(&db, &state.app_data_dir(), &src)?;
+    let dest = path_scope::check(&db, &state.app_data_dir(), &dest)?;
+    fs::copy(&src, &dest).map_err(|e| AppError::Io(format!("Could not copy file: {}", e)))?;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn delete_file(state: State<AppState>, path: String) -> Result<(), AppError> {
+    state.ensure_writable()?;
+    let db = state.db()?;
+    let path = path_scope::check(&db, &state.app_data_dir(), &path)?;
+    fs::remove_file(&path).map_err(|e| AppError::Io(format!("Could not delete file: {}", e)))?;
+    Ok(())
+}
+
+/// Store bytes from the webview (e.g. a pasted scan) in the managed temp store and return the path, so
+/// they can be scanned like any file. Identical content returns the same path.
+#[tauri::command]
+pub fn put_temp_file(base64_content: String, extension: String) -> Result<String, AppError> {
+    let bytes = BASE64.decode(&base64_content).map_err(|e| AppError::Validation(format!("Invalid base64: {}", e)))?;
+    let path = temp_store::put(&bytes, &extension).map_err(AppError::Io)?;
+    Ok(path.to_string_lossy().to_string())
+}
+
+/// Folders and files the generic file commands may use.
+#[tauri::command]
+pub fn get_path_scope(state: State<AppState>) -> Result<path_scope::PathScope, AppError> {
+    let db = state.db()?;
+    Ok(path_scope::get_scope(&db, &state.app_data_dir())?)
+}
+
+/// Let the user add a document root in a native folder picker; returns the roots (unchanged when cancelled).
+#[tauri::command]
+pub async fn pick_document_root(app: AppHandle, state: State<'_, AppState>) -> Result<Vec<String>, AppError> {
+    state.ensure_writable()?;
+    let picked = tauri::async_runtime::spawn_blocking(move || app.dialog().file().blocking_pick_folder())
+        .await
+        .map_err(AppError::internal)?;
+    with_db_blocking(&state, move |db| {
+        let roots = match picked {
+            Some(folder) => path_scope::add_document_root(db, &folder.into_path().map_err(AppError::internal)?)?,
+            None => path_scope::document_roots(db)?,
+        };
+        Ok::<_, AppError>(roots)
+    })
+    .await
+}
+
+#[tauri::command]
+pub fn remove_document_root(state: State<AppState>, root: String) -> Result<Vec<String>, AppError> {
+    state.ensure_writable()?;
+    let db = state.db()?;
+    Ok(path_scope::remove_document_root(&db, &root)?)
+}
+
+/// `PATH_NOT_ALLOWED` unless `path` from the webview is inside the path scope (see `path_scope`).
+fn ensure_path_allowed(state: &AppState, path: &str) -> Result<(), AppError> {
+    let db = state.db()?;
+    path_scope::check(&db, &state.app_data_dir(), path).map(|_| ())
+}
+
+/// Native open dialog; the chosen files are allowed for the file commands. Empty when cancelled.
+#[tauri::command]
+pub async fn pick_open_paths(
+    app: AppHandle,
+    title: Option<String>,
+    filters: Option<Vec<SaveDialogFilter>>,
+    multiple: Option<bool>,
+) -> Result<Vec<String>, AppError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let mut dialog = app.dialog().file();
+        for filter in filters.unwrap_or_default() {
+            let extensions: Vec<&str> = filter.extensions.iter().map(String::as_str).collect();
+            dialog = dialog.add_filter(&filter.name, &extensions);
+        }
+        if let Some(title) = title {
+            dialog = dialog.set_title(title);
+        }
+        let picked = if multiple.unwrap_or(false) {
+            dialog.blocking_pick_files().unwrap_or_default()
+        } else {
+            dialog.blocking_pick_file().into_iter().collect()
+        };
+        let mut paths = Vec::with_capacity(picked.len());
+        for file in picked {
+            let path = file.into_path().map_err(AppError::internal)?;
+            path_scope::grant(&path);
+            paths.push(path.to_string_lossy().to_string());
+        }
+        Ok(paths)
+    })
+    .await
+    .map_err(AppError::internal)?
+}
+
+/// Native folder picker for export, archive and delivery folders; the folder is allowed for the rest of
+/// the session. None when cancelled.
+#[tauri::command]
+pub async fn pick_folder(app: AppHandle, title: Option<String>) -> Result<Option<String>, AppError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let mut dialog = app.dialog().file();
+        if let Some(title) = title {
+            dialog = dialog.set_title(title);
+        }
+        let Some(picked) = dialog.blocking_pick_folder() else {
+            return Ok(None);
+        };
+        let path = picked.into_path().map_err(AppError::internal)?;
+        path_scope::grant_folder(&path);
+        Ok(Some(path.to_string_lossy().to_string()))
+    })
+    .await
+    .map_err(AppError::internal)?
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SaveDialogFilter {
+    pub name: String,
+    pub extensions: Vec<String>,
+}
+
+/// Native save dialog; the chosen file (with the first filter's extension when it has none of them) is
+/// allowed for the file commands. None when cancelled.
+#[tauri::command]
+pub async fn pick_save_path(
+    app: AppHandle,
+    default_path: Option<String>,
+    title: Option<String>,
+    filters: Option<Vec<SaveDialogFilter>>,
+) -> Result<Option<String>, AppError> {
+    let filters = filters.unwrap_or_default();
+    tauri::async_runtime::spawn_blocking(move || {
+        let mut dialog = app.dialog().file();
+        for filter in &filters {
+            let extensions: Vec<&str> = filter.extensions.iter().map(String::as_str).collect();
+            dialog = dialog.add_filter(&filter.name, &extensions);
+        }
+        if let Some(default_path) = default_path {
+            dialog = dialog.set_file_name(default_path);
+        }
+        if let Some(title) = title {
+            dialog = dialog.set_title(title);
+        }
+        let Some(picked) = dialog.blocking_save_file() else {
+            return Ok(None);
+        };
+        let mut path = picked.into_path().map_err(AppError::internal)?;
+        let allowed: Vec<&String> = filters.iter().flat_map(|f| &f.extensions).collect();
+        let has_allowed = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .is_some_and(|e| allowed.iter().any(|a| a.eq_ignore_ascii_case(&e)));
+        if let (Some(first), false) = (allowed.first(), has_allowed) {
+            path.set_extension(first);
+        }
+        path_scope::grant(&path);
+        Ok(Some(path.to_string_lossy().to_string()))
+    })
+    .await
+    .map_err(AppError::internal)?
+}
+
+#[tauri::command]
+pub fn get_excel_schema(state: State<AppState>, path: String) -> Result<ExcelSchemaResponse, AppError> {
+    ensure_path_allowed(&state, &path)?;
+    let metadata = fs::metadata(Path::new(&path)).map_err(|e| AppError::Io(format!("File not found: {}", e)))?;
+    let mtime = metadata
+        .modified()
+        .map_err(|e| AppError::Io(format!("Cannot get mtime: {}", e)))?;
+    let mtime_ms = mtime
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0);
+    let cache_key = format!("{}:{}", path, mtime_ms);
+
+    let db = state.db()?;
+    if let Some(schema_json) = db.get_cached_schema(&cache_key)? {
+        return Ok(ExcelSchemaResponse {
+            cached: true,
+            schema_json: Some(schema_json),
+            file_bytes: None,
+        });
+    }
+
+    // Do not send file_bytes: frontend will use analyze_excel_schema instead to avoid OOM.
+    Ok(ExcelSchemaResponse {
+        cached: false,
+        schema_json: None,
+        file_bytes: None,
+    })
+}
+
+/// Blocking full scan of a ledger sheet into an `ExcelSchema`. Workbooks that are only read (.xls, .xlsb,
+/// .ods) are scanned through calamine.
+fn scan_schema(path: &str, sheet: &str) -> Result<ExcelSchema, AppError> {
+    let read_only = workbook_format::detect(Path::new(path)).ok().flatten().is_some_and(|f| !f.appendable());
+    let (header_row, headers, last_data_row, next_free_row, total_rows, columns, row_template, file_size, file_mtime) =
+        if read_only {
+            excel_scanner::scan_read_only(Path::new(path), sheet)?
+        } else {
+            excel_scanner::scan_excel_file(Path::new(path), sheet)?
+        };
+    let total_columns = headers.len() as u16;
+    Ok(ExcelSchema {
+        header_row,
+        first_data_row: header_row + 1,
+        last_data_row: last_data_row.last_row,
+        last_row_truncated: last_data_row.truncated,
+        next_free_row,
+        total_rows,
+        total_columns,
+        headers,
+        columns,
+        row_template,
+        file_size,
+        file_mtime,
+    })
+}
+
+/// Scan Excel file and return full schema (headers, formats, next_free_row). Uses edit-xlsx for format reading.
+#[tauri::command]
+pub async fn scan_excel_schema(
+    state: State<'_, AppState>,
+    excel_path: String,
+    worksheet_name: String,
+) -> Result<ExcelSchema, AppError> {
+    ensure_path_allowed(&state, &excel_path)?;
+    tauri::async_runtime::spawn_blocking(move || scan_schema(&excel_path, &worksheet_name))
+        .await
+        .map_err(AppError::internal)?
+}
+
+/// Outcome of refreshing one profile's ledger schema.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileRefreshResult {
+    pub profile_id: i64,
+    pub profile_name: String,
+    /// "unchanged", "rescanned", "missing" (ledger file not found) or "error"
+    pub status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_free_row: Option<u32>,
+}
+
+/// Payload of the `profile-refresh-progress` event, emitted as each profile finishes.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileRefreshProgress {
+    pub done: usize,
+    pub total: usize,
+    pub result: ProfileRefreshResult,
+}
+
+fn file_mtime_secs(path: &Path) -> Option<u64> {
+    fs::metadata(path)
+        .ok()?
+        .modified()
+        .ok()?
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|d| d.as_secs())
+}
+
+/// Compare the ledger's mtime with the stored schema and re-scan when it changed.
+fn refresh_profile(db: &Db, profile_id: i64, excel_path: &str, sheet_name: &str, persist: bool) -> Result<(String, Option<u32>), AppError> {
+    let Some(mtime) = file_mtime_secs(Path::new(excel_path)) else {
+        schema_cache::invalidate_cache(profile_id);
+        return Ok(("missing".to_string(), None));
+    };
+    if let Ok(stored) = db.load_excel_schema(profile_id) {
+        if stored.file_mtime == mtime {
+            let next_free_row = stored.next_free_row;
+            schema_cache::set_cached_schema(profile_id, stored);
+            return Ok(("unchanged".to_string(), Some(next_free_row)));
+        }
+    }
+    let schema = scan_schema(excel_path, sheet_name)?;
+    let next_free_row = schema.next_free_row;
+    if persist {
+        db.save_excel_schema(profile_id, &schema)?;
+    }
+    schema_cache::set_cached_schema(profile_id, schema);
+    Ok(("rescanned".to_string(), Some(next_free_row)))
+}
+
+/// Check every profile's ledger mtime and re-scan changed ledgers in parallel on the blocking pool.
+/// Emits `profile-refresh-progress` per profile; returns all results in profile order.
+#[tauri::command]
+pub async fn refresh_all_profiles(app: AppHandle, state: State<'_, AppState>) -> Result<Vec<ProfileRefreshResult>, AppError> {
+    let concurrency = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(4).clamp(2, 8);
+    let db = state.db()?;
+    let persist = !state.read_only;
+    let profiles = with_db_blocking(&state, |db| db.get_profiles()).await?;
+    let total = profiles.len();
+    let done = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let mut results = Vec::with_capacity(total);
+
+    for chunk in profiles.chunks(concurrency) {
+        let handles: Vec<_> = chunk
+            .iter()
+            .map(|(profile_id, name, excel_path, sheet_name, _)| {
+                let (db, app, done) = (db.clone(), app.clone(), done.clone());
+                let (profile_id, name, excel_path, sheet_name) =
+                    (*profile_id, name.clone(), excel_path.clone(), sheet_name.clone());
+                tauri::async_runtime::spawn_blocking(move || {
+                    let (status, next_free_row, error) =
+                        match refresh_profile(&db, profile_id, &excel_path, &sheet_name, persist) {
+                            Ok((status, next_free_row)) => (status, next_free_row, None),
+                            Err(e) => ("error".to_string(), None, Some(e.to_string())),
+                        };
+                    let result = ProfileRefreshResult {
+                        profile_id,
+                        profile_name: name,
+                        status,
+                        error,
+                        next_free_row,
+                    };
+                    let done = done.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
+                    let _ = app.emit(
+                        "profile-refresh-progress",
+                        ProfileRefreshProgress { done, total, result: result.clone() },
+                    );
+                    result
+                })
+            })
+            .collect();
+        for ((profile_id, name, ..), h) in chunk.iter().zip(handles) {
+            results.push(h.await.unwrap_or_else(|e| ProfileRefreshResult {
+                profile_id: *profile_id,
+                profile_name: name.clone(),
+                status: "error".to_string(),
+                error: Some(format!("Task join error: {}", e)),
+                next_free_row: None,
+            }));
+        }
+    }
+    Ok(results)
+}
+
+/// Save scanned schema to database for the given profile (call after scan when creating/editing profile).
+#[tauri::command]
+pub fn save_excel_schema(
+    state: State<AppState>,
+    profile_id: i64,
+    schema: ExcelSchema,
+) -> Result<(), AppError> {
+    state.ensure_writable()?;
+    let db = state.db()?;
+    db.save_excel_schema(profile_id, &schema)?;
+    schema_cache::set_cached_schema(profile_id, schema);
+    Ok(())
+}
+
+/// Get excel schema for a profile from cache or database. Validates cache with file mtime.
+#[tauri::command]
+pub fn get_excel_schema_for_profile(
+    state: State<'_, AppState>,
+    profile_id: i64,
+    force_refresh: bool,
+) -> Result<ExcelSchema, AppError> {
+    let db = state.db()?;
+    if !force_refresh {
+        return cached_or_load_schema(&db, profile_id);
+    }
+    let schema = db.load_excel_schema(profile_id)?;
+    schema_cache::set_cached_schema(profile_id, schema.clone());
+    Ok(schema)
+}
+
+/// Cached schema when the workbook's mtime still matches, otherwise reload from the DB and re-cache.
+fn cached_or_load_schema(db: &Db, profile_id: i64) -> Result<ExcelSchema, AppError> {
+    if let Some(cached) = schema_cache::get_cached_schema(profile_id) {
+        if is_cache_valid(db, profile_id, &cached)? {
+            return Ok(cached);
+        }
+        schema_cache::invalidate_cache(profile_id);
+    }
+    let schema = db.load_excel_schema(profile_id)?;
+    schema_cache::set_cached_schema(profile_id, schema.clone());
+    Ok(schema)
+}
+
+fn is_cache_valid(db: &Db, profile_id: i64, cached: &ExcelSchema) -> Result<bool, AppError> {
+    let (excel_path, _, _) = db.get_profile_by_id(profile_id)?;
+    if !Path::new(&excel_path).exists() {
+        return Ok(false);
+    }
+    let metadata = fs::metadata(&excel_path).map_err(AppError::io)?;
+    let current_mtime = metadata
+        .modified()
+        .map_err(AppError::internal)?
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    Ok(current_mtime == cached.file_mtime)
+}
+
+/// Column letter -> field key of a profile's stored mapping. Entries that are not strings (`_headerRow`)
+/// are left out.
+fn parse_column_mapping(column_mapping_json: &str) -> std::collections::HashMap<String, String> {
+    let mapping: serde_json::Map<String, Value> = serde_json::from_str(column_mapping_json).unwrap_or_default();
+    mapping
+        .into_iter()
+        .filter_map(|(letter, field)| field.as_str().map(|f| (letter, f.to_string())))
+        .collect()
+}
+
+/// `schema` checked against the ledger's header row before an append (see `schema_drift`). A mapped column
+/// that moved or disappeared fails with `SchemaChanged`; other changes re-scan the sheet and store the new
+/// schema, which is returned.
+fn current_schema(
+    db: &Db,
+    profile_id: i64,
+    excel_path: &str,
+    sheet_name: &str,
+    schema: ExcelSchema,
+    column_mapping: &std::collections::HashMap<String, String>,
+) -> Result<ExcelSchema, AppError> {
+    let headers = excel::read_header_row(excel_path, sheet_name, schema.header_row)?;
+    if schema_drift::diff(&schema, &headers, column_mapping).is_none() {
+        return Ok(schema);
+    }
+    let scanned = scan_schema(excel_path, sheet_name)?;
+    if let Some(diff) = schema_drift::diff(&schema, &scanned.headers, column_mapping) {
+        if schema_drift::breaks_mapping(&diff) {
+            return Err(schema_drift::changed_error(sheet_name, diff));
+        }
+    }
+    db.save_excel_schema(profile_id, &scanned)?;
+    schema_cache::set_cached_schema(profile_id, scanned.clone());
+    Ok(scanned)
+}
+
+/// Ledger rows with the same duplicate key as `invoice_data` (none when the record lacks a key value).
+async fn ledger_duplicates(
+    excel_path: &str,
+    sheet_name: &str,
+    header_row: u32,
+    column_mapping: &std::collections::HashMap<String, String>,
+    fields: Vec<String>,
+    invoice_data: &InvoiceData,
+) -> Result<Vec<u32>, AppError> {
+    let Some(key) = duplicate_keys::key_of(&fields, |f| invoice_data.fields.get(f).map(|v| v.value.clone())) else {
+        return Ok(Vec::new());
+    };
+    let columns = duplicate_keys::key_columns(&fields, column_mapping).map_err(AppError::Validation)?;
+    let path = excel_path.to_string();
+    let sheet = sheet_name.to_string();
+    tauri::async_runtime::spawn_blocking(move || {
+        duplicate_keys::find_matching_rows(&path, &sheet, header_row, &fields, &columns, &key)
+    })
+    .await
+    .map_err(AppError::internal)?
+    .map_err(AppError::Excel)
+}
+
+/// (column letter, value) per schema header for `invoice_data`. When `updating` an existing row, empty
+/// values are left out so the row keeps what it already has.
+fn ledger_row_values(
+    schema: &ExcelSchema,
+    column_mapping: &std::collections::HashMap<String, String>,
+    invoice_data: &InvoiceData,
+    updating: bool,
+    locale: &export_locale::ExportLocale,
+) -> Vec<(String, String)> {
+    let mut column_values = Vec::new();
+    for h in schema.headers.iter() {
+        let field_key = column_mapping
+            .get(&h.column_letter)
+            .or_else(|| column_mapping.get(&h.column_letter.to_uppercase()))
+            .map(String::from)
+            .unwrap_or_else(|| format!("col_{}", h.column_letter));
+        let value = ledger_value(&field_key, invoice_data, locale);
+        if updating && value.trim().is_empty() {
+            continue;
+        }
+        column_values.push((h.column_letter.clone(), value));
+    }
+    column_values
+}
+
+/// Ledger text of one field of a document.
+fn ledger_value(field_key: &str, invoice_data: &InvoiceData, locale: &export_locale::ExportLocale) -> String {
+    let value = invoice_data
+        .fields
+        .get(field_key)
+        .map(|v| v.value.clone())
+        .unwrap_or_else(String::new);
+    // DDV template: write month name (e.g. "Февруари") in Период column instead of full date range
+    if field_key == "taxPeriod" {
+        excel::period_to_month_name_mk(&value).unwrap_or(value)
+    } else if excel::is_date_field(field_key) {
+        locale.date(&value)
+    } else {
+        value
+    }
+}
+
+/// The profile's Google Sheets destination and sign-in, None when it appends to its workbook.
+async fn google_sheet_destination(
+    state: &AppState,
+    profile_id: i64,
+) -> Result<Option<(google_sheets::SheetTarget, google_sheets::Connection)>, AppError> {
+    let app_data_dir = state.app_data_dir();
+    with_db_blocking(state, move |db| google_sheets::destination(db, &app_data_dir, profile_id)).await
+}
+
+/// Append `invoices` in order to the profile's Google sheet by its column mapping; returns the row of each.
+async fn append_to_google_sheet(
+    state: &AppState,
+    profile_id: i64,
+    target: google_sheets::SheetTarget,
+    connection: google_sheets::Connection,
+    invoices: &[InvoiceData],
+) -> Result<Vec<i64>, AppError> {
+    let ((_, _, column_mapping_json), locale) = with_db_blocking(state, move |db| {
+        Ok::<_, AppError>((db.get_profile_by_id(profile_id)?, export_locale::get(db, Some(profile_id))?))
+    })
+    .await?;
+    let column_mapping = parse_column_mapping(&column_mapping_json);
+    let rows: Vec<Vec<(String, String)>> = invoices
+        .iter()
+        .map(|invoice_data| {
+            column_mapping
+                .iter()
+                .filter(|(letter, field)| !letter.starts_with('_') && !field.is_empty())
+                .map(|(letter, field)| (letter.to_uppercase(), ledger_value(field, invoice_data, &locale)))
+                .collect()
+        })
+        .collect();
+    let _permit = work_pool::acquire(work_pool::WorkClass::Append).await;
+    let written = tauri::async_runtime::spawn_blocking(move || google_sheets::append_rows(&connection, &target, &rows))
+        .await
+        .map_err(AppError::internal)?
+        .map_err(AppError::Excel)?;
+    Ok(written.into_iter().map(i64::from).collect())
+}
+
+/// Insert `invoices` into the profile's database target (see `export_targets`), when it has one. Returns
+/// whether the documents still go to the ledger.
+async fn insert_into_export_target(state: &AppState, profile_id: i64, invoices: &[InvoiceData]) -> Result<bool, AppError> {
+    let app_data_dir = state.app_data_dir();
+    let Some((target, password)) =
+        with_db_blocking(state, move |db| export_targets::load(db, &app_data_dir, profile_id)).await?
+    else {
+        return Ok(true);
+    };
+    let also_ledger = target.also_append_to_ledger;
+    let invoices = invoices.to_vec();
+    tauri::async_runtime::spawn_blocking(move || {
+        export_targets::insert_documents(&target, password.as_deref(), &invoices)
+    })
+    .await
+    .map_err(AppError::internal)??;
+    Ok(also_ledger)
+}
+
+/// Fast append: use cached schema (next_free_row), write row, update cache and DB.
+/// For Plata (sheet "МПИН"): write into Пресметка на плата grid by month column instead of appending a row.
+/// `history_id` links the written cells in the write log to the scanned document. With `queue_if_unavailable`
+/// a workbook that is still locked after the wait, missing or on an offline share does not lose the row: it
+/// is kept in `pending_appends`, an `excel-append-queued` event is emitted and the command fails with a
+/// "queued" message. `document_type` (else the type of the history record) picks the sheet when the profile
+/// routes that type to another sheet of the workbook (`sheet_routes`).
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn append_to_excel_fast(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    profile_id: i64,
+    invoice_data: InvoiceData,
+    history_id: Option<i64>,
+    document_type: Option<String>,
+    wait_for_unlock_secs: Option<u64>,
+    wait_id: Option<String>,
+    queue_if_unavailable: Option<bool>,
+) -> Result<i64, AppError> {
+    state.ensure_writable()?;
+    // Inserted before the ledger write; a queued ledger row is not inserted again when it is flushed.
+    if !insert_into_export_target(&state, profile_id, std::slice::from_ref(&invoice_data)).await? {
+        notify_webhooks(&state, webhooks::WebhookEvent::AppendSucceeded, Some(profile_id), vec![invoice_data]);
+        return Ok(0);
+    }
+    let queued_data = queue_if_unavailable.unwrap_or(false).then(|| invoice_data.clone());
+    let notified_data = invoice_data.clone();
+    let result = append_invoice_fast(
+        &app,
+        &state,
+        profile_id,
+        invoice_data,
+        history_id,
+        document_type,
+        wait_for_unlock_secs,
+        wait_id,
+    )
+    .await;
+    if result.is_ok() {
+        notify_webhooks(&state, webhooks::WebhookEvent::AppendSucceeded, Some(profile_id), vec![notified_data]);
+    }
+    let (Err(error), Some(invoice_data)) = (&result, queued_data) else {
+        return result;
+    };
+    let (excel_path, _, _) = with_db_blocking(&state, move |db| db.get_profile_by_id(profile_id)).await?;
+    let Some(reason) = pending_appends::unavailable_reason(&excel_path, error) else {
+        return result;
+    };
+    let message = error.to_string();
+    let last_error = message.clone();
+    let pending = with_db_blocking(&state, move |db| {
+        pending_appends::queue(db, profile_id, &invoice_data, history_id, reason, &last_error)
+    })
+    .await?;
+    let _ = app.emit("excel-append-queued", &pending);
+    Err(AppError::Excel(format!(
+        "{} The document was queued and will be written when the file is available again.",
+        message
+    )))
+}
+
+/// Parse the profile's ledger into memory in the background, so the first single-row append from review
+/// does not have to (later ones start from the copy `workbook_standby` keeps). Plata templates are skipped.
+#[tauri::command]
+pub async fn warm_profile_workbook(state: State<'_, AppState>, profile_id: i64) -> Result<(), AppError> {
+    let (excel_path, sheet_name, _) = with_db_blocking(&state, move |db| db.get_profile_by_id(profile_id)).await?;
+    if sheet_name != "МПИН" && Path::new(&excel_path).exists() {
+        workbook_standby::prewarm(Path::new(&excel_path), &sheet_name);
+    }
+    Ok(())
+}
+
+/// Emit `excel-append-fallback` ({profileId, path, warning}) when an append needed the fallback writer, so
+/// the user learns which formatting was not applied.
+fn emit_append_fallback(app: &AppHandle, profile_id: i64, path: &str, warning: Option<String>) {
+    if let Some(warning) = warning {
+        let payload = serde_json::json!({ "profileId": profile_id, "path": path, "warning": warning });
+        let _ = app.emit("excel-append-fallback", payload);
+    }
+}
+
+/// A sheet of the profile's workbook other than its own, with its schema and the profile's column mapping
+/// moved onto its headers. A missing sheet is added first with the headers of the profile's sheet.
+fn routed_sheet(
+    excel_path: &str,
+    sheet_name: &str,
+    profile_schema: &ExcelSchema,
+    column_mapping: &std::collections::HashMap<String, String>,
+) -> Result<(String, ExcelSchema, std::collections::HashMap<String, String>), AppError> {
+    let available = excel::get_sheet_names(excel_path)?;
+    let sheet_name = match sheet_routes::existing_sheet(&available, sheet_name) {
+        Some(existing) => existing,
+        None => {
+            let headers: Vec<(String, String)> =
+                profile_schema.headers.iter().map(|h| (h.column_letter.clone(), h.text.clone())).collect();
+            excel::add_sheet_with_headers(Path::new(excel_path), sheet_name, &headers)?;
+            sheet_name.to_string()
+        }
+    };
+    let schema = scan_schema(excel_path, &sheet_name)?;
+    let mapping = sheet_routes::map_columns(&profile_schema.headers, &schema.headers, column_mapping);
+    Ok((sheet_name, schema, mapping))
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn append_invoice_fast(
+    app: &AppHandle,
+    state: &AppState,
+    profile_id: i64,
+    invoice_data: InvoiceData,
+    history_id: Option<i64>,
+    document_type: Option<String>,
+    wait_for_unlock_secs: Option<u64>,
+    wait_id: Option<String>,
+) -> Result<i64, AppError> {
+    state.ensure_writable()?;
+    if let Some((target, connection)) = google_sheet_destination(state, profile_id).await? {
+        let rows = append_to_google_sheet(state, profile_id, target, connection, &[invoice_data]).await?;
+        return Ok(rows.first().copied().unwrap_or(0));
+    }
+    let ((excel_path, profile_sheet, _column_mapping_json), route) = with_db_blocking(state, move |db| {
+        let document_type = match (document_type, history_id) {
+            (Some(document_type), _) => Some(document_type),
+            (None, Some(id)) => db.get_history_by_id(id)?.map(|record| record.1),
+            (None, None) => None,
+        };
+        Ok::<_, AppError>((
+            db.get_profile_by_id(profile_id)?,
+            sheet_routes::sheet_for(db, profile_id, document_type.as_deref())?,
+        ))
+    })
+    .await?;
+    let sheet_name = route.clone().unwrap_or_else(|| profile_sheet.clone());
+    workbook_format::ensure_appendable(Path::new(&excel_path))?;
+    wait_for_excel_unlock(app, &excel_path, wait_for_unlock_secs, wait_id).await?;
+
+    // Plata: write into month column of Пресметка на плата template (no row append).
+    if sheet_name == "МПИН" {
+        let declaration_period = invoice_data
+            .fields
+            .get("declarationPeriod")
+            .or_else(|| invoice_data.fields.get("taxPeriod"))
+            .map(|v| v.value.clone())
+            .unwrap_or_else(String::new);
+        let path = excel_path.clone();
+        let sheet = sheet_name.clone();
+        let fields = invoice_data.fields.clone();
+        let writes = tauri::async_runtime::spawn_blocking(move || {
+            excel::write_plata_to_template(&path, &sheet, &declaration_period, &fields)
+        })
+        .await
+        .map_err(AppError::internal)??;
+        record_cell_writes(state, excel_path, history_id, writes).await;
+        snapshot_after_append(state, profile_id).await;
+        return Ok(0);
+    }
+
+    let (schema, (excel_path, _, column_mapping_json), duplicate_key, locale) =
+        with_db_blocking(state, move |db| {
+            Ok::<_, AppError>((
+                cached_or_load_schema(db, profile_id)?,
+                db.get_profile_by_id(profile_id)?,
+                duplicate_keys::get_key(db, profile_id)?,
+                export_locale::get(db, Some(profile_id))?,
+            ))
+        })
+        .await?;
+
+    let column_mapping = parse_column_mapping(&column_mapping_json);
+
+    // Routed to another sheet: its schema is read from the file, the profile's stays untouched.
+    let routed = route.is_some();
+    let (sheet_name, schema, column_mapping) = if routed {
+        let path = excel_path.clone();
+        tauri::async_runtime::spawn_blocking(move || routed_sheet(&path, &sheet_name, &schema, &column_mapping))
+            .await
+            .map_err(AppError::internal)??
+    } else {
+        let (path, sheet, mapping) = (excel_path.clone(), sheet_name.clone(), column_mapping.clone());
+        let schema =
+            with_db_blocking(state, move |db| current_schema(db, profile_id, &path, &sheet, schema, &mapping)).await?;
+        (sheet_name, schema, column_mapping)
+    };
+
+    // Reject or update mode: look for a row with the same key first.
+    let mut existing_row = None;
+    if let Some(key) = duplicate_key.filter(|k| k.on_duplicate != duplicate_keys::OnDuplicate::Append) {
+        let rows = ledger_duplicates(
+            &excel_path,
+            &sheet_name,
+            schema.header_row,
+            &column_mapping,
+            key.fields,
+            &invoice_data,
+        )
+        .await?;
+        if let Some(&row) = rows.first() {
+            if key.on_duplicate == duplicate_keys::OnDuplicate::Reject {
+                return Err(AppError::Validation(format!(
+                    "The ledger already has this document on row {}.",
+                    row
+                )));
+            }
+            existing_row = Some(row);
+        }
+    }
+
+    let row_number = existing_row.unwrap_or(schema.next_free_row);
+    let column_values = ledger_row_values(&schema, &column_mapping, &invoice_data, existing_row.is_some(), &locale);
+
+    let path = excel_path.clone();
+    let sheet = sheet_name.clone();
+    let row_num = row_number;
+    let values = column_values;
+    let row_schema = schema.clone();
+    let _permit = work_pool::acquire(work_pool::WorkClass::Append).await;
+    let excel::FittedAppend { writes, widened, fallback_warning } = tauri::async_runtime::spawn_blocking(move || {
+        excel::append_row_fitting_columns(&path, &sheet, row_num, values, &row_schema, &locale)
+    })
+    .await
+    .map_err(AppError::internal)??;
+    emit_append_fallback(app, profile_id, &excel_path, fallback_warning);
+    record_cell_writes(state, excel_path, history_id, writes).await;
+    snapshot_after_append(state, profile_id).await;
+    if routed {
+        return Ok(row_number as i64);
+    }
+
+    let updated = existing_row.is_some();
+    let new_next = if updated { schema.next_free_row } else { row_number + 1 };
+    let last_data_row = if updated { schema.last_data_row } else { row_number };
+    let widths = widened.clone();
+    with_db_blocking(state, move |db| {
+        if !updated {
+            db.update_excel_schema_next_free_row(profile_id, new_next, row_number)?;
+        }
+        db.update_column_widths(profile_id, &widths)
+    })
+    .await?;
+
+    if let Some(mut cached) = schema_cache::get_cached_schema(profile_id) {
+        cached.next_free_row = new_next;
+        cached.last_data_row = last_data_row;
+        for (letter, width) in &widened {
+            if let Some(col) = cached.columns.iter_mut().find(|c| c.column_letter.eq_ignore_ascii_case(letter)) {
+                col.column_width = *width;
+            }
+        }
+        schema_cache::set_cached_schema(profile_id, cached);
+    }
+
+    Ok(row_number as i64)
+}
+
+/// Rows waiting for a locked workbook, oldest first; only those of `profile_id` when given.
+#[tauri::command]
+pub async fn get_pending_appends(
+    state: State<'_, AppState>,
+    profile_id: Option<i64>,
+) -> Result<Vec<pending_appends::PendingAppend>, AppError> {
+    with_db_blocking(&state, move |db| pending_appends::list(db, profile_id)).await
+}
+
+/// Rolling OCR, append, schema scan and database timings plus worker pool and pending-append queue depths.
+#[tauri::command]
+pub async fn get_performance_metrics(state: State<'_, AppState>) -> Result<perf_metrics::PerformanceMetrics, AppError> {
+    with_db_blocking(&state, perf_metrics::snapshot).await
+}
+
+/// Retry queued rows (only those of `profile_id` when given), oldest first. A profile whose workbook is
+/// still unavailable is skipped after its first failure so its rows keep their order. Emits
+/// `pending-appends-flushed` with the summary.
+#[tauri::command]
+pub async fn flush_pending_appends(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    profile_id: Option<i64>,
+) -> Result<pending_appends::FlushSummary, AppError> {
+    flush_pending(&app, &state, profile_id).await
+}
+
+async fn flush_pending(
+    app: &AppHandle,
+    state: &AppState,
+    profile_id: Option<i64>,
+) -> Result<pending_appends::FlushSummary, AppError> {
+    state.ensure_writable()?;
+    let queued = with_db_blocking(state, move |db| pending_appends::list(db, profile_id)).await?;
+    let mut summary = pending_appends::FlushSummary::default();
+    let mut blocked: Vec<i64> = Vec::new();
+    for pending in queued {
+        if blocked.contains(&pending.profile_id) {
+            summary.remaining.push(pending);
+            continue;
+        }
+        let id = pending.id;
+        let result = append_invoice_fast(
+            app,
+            state,
+            pending.profile_id,
+            pending.invoice_data.clone(),
+            pending.history_id,
+            None,
+            None,
+            None,
+        )
+        .await;
+        match result {
+            Ok(row) => {
+                with_db_blocking(state, move |db| db.delete_pending_append(id)).await?;
+                summary.written.push((id, row));
+            }
+            Err(error) => {
+                let pid = pending.profile_id;
+                let excel_path = with_db_blocking(state, move |db| db.get_profile_by_id(pid)).await.map(|p| p.0);
+                let reason = match &excel_path {
+                    Ok(path) => pending_appends::unavailable_reason(path, &error),
+                    Err(_) => None,
+                };
+                if reason.is_some() {
+                    blocked.push(pid);
+                }
+                let reason = reason.unwrap_or(pending.reason.as_str()).to_string();
+                let message = error.to_string();
+                let (r, m) = (reason.clone(), message.clone());
+                with_db_blocking(state, move |db| db.record_pending_append_attempt(id, &r, &m)).await?;
+                summary.remaining.push(pending_appends::PendingAppend {
+                    reason,
+                    last_error: Some(message),
+                    attempts: pending.attempts + 1,
+                    last_attempt_at: Some(chrono::Utc::now().to_rfc3339()),
+                    ..pending
+                });
+            }
+        }
+    }
+    let _ = app.emit("pending-appends-flushed", &summary);
+    Ok(summary)
+}
+
+/// Startup retry of the pending queue; failures only go to the log.
+pub async fn flush_pending_appends_on_startup(app: AppHandle) {
+    let state = app.state::<AppState>();
+    if state.read_only || state.db().is_err() {
+        return;
+    }
+    match flush_pending(&app, &state, None).await {
+        Ok(summary) if !summary.written.is_empty() || !summary.remaining.is_empty() => app_warnings::report(
+            "pending_appends",
+            format!(
+                "startup flush: {} written, {} still queued",
+                summary.written.len(),
+                summary.remaining.len()
+            ),
+        ),
+        Ok(_) => {}
+        Err(e) => app_warnings::report("pending_appends", format!("startup flush failed: {}", e)),
+    }
+}
+
+/// Drop a queued row without writing it.
+#[tauri::command]
+pub async fn discard_pending_append(state: State<'_, AppState>, id: i64) -> Result<(), AppError> {
+    state.ensure_writable()?;
+    with_db_blocking(&state, move |db| db.delete_pending_append(id)).await
+}
+
+/// `append_to_excel_fast` for several documents in one open/save of the workbook: rows are taken from
+/// next_free_row in the profile's export order and next_free_row is updated once. `history_ids` pairs
+/// with `invoices`. Returns the row of each document (0 for Plata). With a reject duplicate key,
+/// nothing is written when any document is already in the ledger or repeats another one in the batch.
+/// `column_overrides` (column letter -> field key) change the profile's mapping for this batch only and
+/// are kept in the export manifest with the rows written.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn append_invoices_to_excel_fast(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    profile_id: i64,
+    invoices: Vec<InvoiceData>,
+    history_ids: Option<Vec<Option<i64>>>,
+    column_overrides: Option<export_manifest::ColumnOverrides>,
+    wait_for_unlock_secs: Option<u64>,
+    wait_id: Option<String>,
+) -> Result<Vec<i64>, AppError> {
+    let notified = invoices.clone();
+    let rows = append_invoices_batch(
+        app,
+        &state,
+        profile_id,
+        invoices,
+        history_ids,
+        column_overrides,
+        wait_for_unlock_secs,
+        wait_id,
+    )
+    .await?;
+    if !notified.is_empty() {
+        notify_webhooks(&state, webhooks::WebhookEvent::AppendSucceeded, Some(profile_id), notified);
+    }
+    Ok(rows)
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn append_invoices_batch(
+    app: AppHandle,
+    state: &AppState,
+    profile_id: i64,
+    invoices: Vec<InvoiceData>,
+    history_ids: Option<Vec<Option<i64>>>,
+    column_overrides: Option<export_manifest::ColumnOverrides>,
+    wait_for_unlock_secs: Option<u64>,
+    wait_id: Option<String>,
+) -> Result<Vec<i64>, AppError> {
+    state.ensure_writable()?;
+    if invoices.is_empty() {
+        return Ok(Vec::new());
+    }
+    let column_overrides = column_overrides.unwrap_or_default();
+    if let Some((target, connection)) = google_sheet_destination(state, profile_id).await? {
+        if !column_overrides.is_empty() {
+            return Err(AppError::Validation("Column overrides do not apply to Google Sheets ledgers.".to_string()));
+        }
+        ensure_strict_batch(state, Some(profile_id), &invoices, None, None).await?;
+        if !insert_into_export_target(state, profile_id, &invoices).await? {
+            return Ok(vec![0; invoices.len()]);
+        }
+        let sort = with_db_blocking(state, move |db| export_order::get(db, Some(profile_id))).await?;
+        let positions = export_order::order(&invoices, &sort);
+        let ordered: Vec<InvoiceData> = positions.iter().map(|&i| invoices[i].clone()).collect();
+        let rows = append_to_google_sheet(state, profile_id, target, connection, &ordered).await?;
+        let mut by_position = vec![0i64; rows.len()];
+        for (i, row) in rows.into_iter().enumerate() {
+            by_position[positions[i]] = row;
+        }
+        return Ok(by_position);
+    }
+    let ledger = profile_ledger(state, profile_id).await?;
+    ensure_strict_batch(state, Some(profile_id), &invoices, None, Some(ledger)).await?;
+    if !insert_into_export_target(state, profile_id, &invoices).await? {
+        return Ok(vec![0; invoices.len()]);
+    }
+    let ((excel_path, sheet_name, _column_mapping_json), sort) = with_db_blocking(state, move |db| {
+        Ok::<_, AppError>((db.get_profile_by_id(profile_id)?, export_order::get(db, Some(profile_id))?))
+    })
+    .await?;
+    // Written in export order; `positions[i]` is the caller's index of the i-th document written.
+    let positions = export_order::order(&invoices, &sort);
+    let history_ids = history_ids.unwrap_or_default();
+    let history_id = |i: usize| history_ids.get(positions[i]).copied().flatten();
+    let document_count = invoices.len();
+    let mut slots: Vec<Option<InvoiceData>> = invoices.into_iter().map(Some).collect();
+    let invoices: Vec<InvoiceData> = positions.iter().filter_map(|&i| slots[i].take()).collect();
+    workbook_format::ensure_appendable(Path::new(&excel_path))?;
+    wait_for_excel_unlock(&app, &excel_path, wait_for_unlock_secs, wait_id).await?;
+
+    // Plata fills month columns of one template; each document is its own write.
+    if sheet_name == "МПИН" {
+        if !column_overrides.is_empty() {
+            return Err(AppError::Validation("Column overrides do not apply to payroll templates.".to_string()));
+        }
+        for (i, invoice_data) in invoices.iter().enumerate() {
+            let declaration_period = invoice_data
+                .fields
+                .get("declarationPeriod")
+                .or_else(|| invoice_data.fields.get("taxPeriod"))
+                .map(|v| v.value.clone())
+                .unwrap_or_else(String::new);
+            let path = excel_path.clone();
+            let sheet = sheet_name.clone();
+            let fields = invoice_data.fields.clone();
+            let writes = tauri::async_runtime::spawn_blocking(move || {
+                excel::write_plata_to_template(&path, &sheet, &declaration_period, &fields)
+            })
+            .await
+            .map_err(AppError::internal)??;
+            record_cell_writes(state, excel_path.clone(), history_id(i), writes).await;
+        }
+        snapshot_after_append(state, profile_id).await;
+        return Ok(vec![0; invoices.len()]);
+    }
+
+    let (schema, (excel_path, sheet_name, column_mapping_json), duplicate_key, locale) =
+        with_db_blocking(state, move |db| {
+            Ok::<_, AppError>((
+                cached_or_load_schema(db, profile_id)?,
+                db.get_profile_by_id(profile_id)?,
+                duplicate_keys::get_key(db, profile_id)?,
+                export_locale::get(db, Some(profile_id))?,
+            ))
+        })
+        .await?;
+
+    let column_mapping = parse_column_mapping(&column_mapping_json);
+    let (path, sheet, mapping) = (excel_path.clone(), sheet_name.clone(), column_mapping.clone());
+    let schema =
+        with_db_blocking(state, move |db| current_schema(db, profile_id, &path, &sheet, schema, &mapping)).await?;
+    // Existing rows were written with the saved mapping, so duplicates are still looked up with it.
+    let (batch_mapping, column_overrides) =
+        export_manifest::apply_overrides(&schema.headers, &column_mapping, &column_overrides)
+            .map_err(AppError::Validation)?;
+    let duplicate_key = duplicate_key.filter(|k| k.on_duplicate != duplicate_keys::OnDuplicate::Append);
+
+    // Assign rows first so a rejected duplicate fails the batch before anything is written.
+    let mut next_row = schema.next_free_row;
+    let mut batch_rows: std::collections::HashMap<String, u32> = std::collections::HashMap::new();
+    let mut rows = Vec::with_capacity(invoices.len());
+    let mut updating = Vec::with_capacity(invoices.len());
+    for invoice_data in &invoices {
+        let mut existing_row = None;
+        if let Some(key) = &duplicate_key {
+            let value = duplicate_keys::key_of(&key.fields, |f| invoice_data.fields.get(f).map(|v| v.value.clone()));
+            let in_batch = value.as_ref().and_then(|v| batch_rows.get(v).copied());
+            let found = match in_batch {
+                Some(row) => Some(row),
+                None => ledger_duplicates(
+                    &excel_path,
+                    &sheet_name,
+                    schema.header_row,
+                    &column_mapping,
+                    key.fields.clone(),
+                    invoice_data,
+                )
+                .await?
+                .first()
+                .copied(),
+            };
+            if let Some(row) = found {
+                if key.on_duplicate == duplicate_keys::OnDuplicate::Reject {
+                    return Err(AppError::Validation(format!(
+                        "The ledger already has this document on row {}.",
+                        row
+                    )));
+                }
+                existing_row = Some(row);
+            }
+            let row = existing_row.unwrap_or(next_row);
+            if let Some(v) = value {
+                batch_rows.insert(v, row);
+            }
+        }
+        let row_number = existing_row.unwrap_or_else(|| {
+            next_row += 1;
+            next_row - 1
+        });
+        rows.push(row_number);
+        updating.push(existing_row.is_some());
+    }
+
+    let row_values: Vec<(u32, Vec<(String, String)>)> = invoices
+        .iter()
+        .zip(rows.iter().zip(&updating))
+        .map(|(invoice_data, (&row, &update))| {
+            (row, ledger_row_values(&schema, &batch_mapping, invoice_data, update, &locale))
+        })
+        .collect();
+    let path = excel_path.clone();
+    let sheet = sheet_name.clone();
+    let row_schema = schema.clone();
+    let _permit = work_pool::acquire(work_pool::WorkClass::Append).await;
+    let excel::FittedAppend { writes, widened, fallback_warning } = tauri::async_runtime::spawn_blocking(move || {
+        excel::append_rows_fitting_columns(&path, &sheet, row_values, &row_schema, &locale)
+    })
+    .await
+    .map_err(AppError::internal)??;
+    emit_append_fallback(&app, profile_id, &excel_path, fallback_warning);
+
+    // Log each document's cells under its own history entry.
+    let mut writes_by_history: Vec<(Option<i64>, Vec<CellWrite>)> = Vec::new();
+    for write in writes {
+        let id = rows.iter().position(|&r| r == write.row_number).and_then(history_id);
+        match writes_by_history.iter_mut().find(|(h, _)| *h == id) {
+            Some((_, group)) => group.push(write),
+            None => writes_by_history.push((id, vec![write])),
+        }
+    }
+    for (id, group) in writes_by_history {
+        record_cell_writes(state, excel_path.clone(), id, group).await;
+    }
+    snapshot_after_append(state, profile_id).await;
+
+    let appended = next_row > schema.next_free_row;
+    let new_next = next_row;
+    let last_data_row = if appended { next_row - 1 } else { schema.last_data_row };
+    let widths = widened.clone();
+    with_db_blocking(state, move |db| {
+        if appended {
+            db.update_excel_schema_next_free_row(profile_id, new_next, last_data_row)?;
+        }
+        db.update_column_widths(profile_id, &widths)
+    })
+    .await?;
+
+    if let Some(mut cached) = schema_cache::get_cached_schema(profile_id) {
+        cached.next_free_row = new_next;
+        cached.last_data_row = last_data_row;
+        for (letter, width) in &widened {
+            if let Some(col) = cached.columns.iter_mut().find(|c| c.column_letter.eq_ignore_ascii_case(letter)) {
+                col.column_width = *width;
+            }
+        }
+        schema_cache::set_cached_schema(profile_id, cached);
+    }
+
+    let mut by_position = vec![0i64; rows.len()];
+    for (i, row) in rows.into_iter().enumerate() {
+        by_position[positions[i]] = i64::from(row);
+    }
+    let manifest_rows: Vec<u32> = by_position.iter().map(|&r| r as u32).collect();
+    let manifest_history: Vec<Option<i64>> =
+        (0..document_count).map(|i| history_ids.get(i).copied().flatten()).collect();
+    if let Err(e) = with_db_blocking(state, move |db| {
+        export_manifest::record(
+            db,
+            profile_id,
+            &excel_path,
+            &sheet_name,
+            &manifest_rows,
+            &manifest_history,
+            &column_overrides,
+        )
+    })
+    .await
+    {
+        app_warnings::report("export_manifest", format!("could not record the append for profile {}: {}", profile_id, e));
+    }
+    Ok(by_position)
+}
+
+/// Export manifests (rows written, documents and one-off column overrides of each batch append), newest
+/// first; only those of `profile_id` when given.
+#[tauri::command]
+pub async fn get_export_manifests(
+    state: State<'_, AppState>,
+    profile_id: Option<i64>,
+    limit: Option<u32>,
+) -> Result<Vec<export_manifest::ExportManifest>, AppError> {
+    with_db_blocking(&state, move |db| export_manifest::list(db, profile_id, limit.unwrap_or(200))).await
+}
+
+#[tauri::command]
+pub async fn analyze_excel_schema(
+    state: State<'_, AppState>,
+    path: String,
+    sheet_name: String,
+    header_row: u32,
+) -> Result<AnalyzedExcelSchema, AppError> {
+    ensure_path_allowed(&state, &path)?;
+    let path = path.clone();
+    let sheet_name = sheet_name.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        excel::analyze_excel_schema(&path, &sheet_name, header_row)
+    })
+    .await
+    .map_err(AppError::internal)?
+    .map(|(worksheet_name, headers, column_samples, last_row, schema_hash, stats)| {
+        AnalyzedExcelSchema {
+            worksheet_name,
+            headers,
+            column_samples,
+            last_data_row: last_row.last_row,
+            last_row_truncated: last_row.truncated,
+            schema_hash,
+            stats,
+        }
+    })
+}
+
+#[tauri::command]
+pub fn cache_excel_schema(
+    state: State<AppState>,
+    path: String,
+    schema_json: String,
+    schema_hash: String,
+    worksheet_name: String,
+) -> Result<(), AppError> {
+    state.ensure_writable()?;
+    ensure_path_allowed(&state, &path)?;
+    let metadata = fs::metadata(Path::new(&path)).map_err(|e| AppError::Io(format!("File not found: {}", e)))?;
+    let mtime = metadata
+        .modified()
+        .map_err(|e| AppError::Io(format!("Cannot get mtime: {}", e)))?;
+    let mtime_ms = mtime
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0);
+    let cache_key = format!("{}:{}", path, mtime_ms);
+    let last_modified = mtime_ms.to_string();
+
+    let db = state.db()?;
+    db.upsert_schema_cache(
+        &cache_key,
+        &path,
+        &schema_hash,
+        &worksheet_name,
+        &schema_json,
+        &last_modified,
+    )
+}
+
+/// Read Excel headers on a background thread so the UI stays responsive (avoids "Not Responding" on large or Cyrillic paths).
+#[tauri::command]
+pub async fn read_excel_headers(
+    state: State<'_, AppState>,
+    path: String,
+    sheet: String,
+    header_row: Option<u32>,
+) -> Result<Vec<String>, AppError> {
+    ensure_path_allowed(&state, &path)?;
+    let path = path.clone();
+    let sheet = sheet.clone();
+    tauri::async_runtime::spawn_blocking(move || excel::read_excel_headers(&path, &sheet, header_row))
+        .await
+        .map_err(AppError::internal)?
+}
+
+/// Get Excel headers with column letter and index for visual mapping UI. Reads from local filesystem only.
+#[tauri::command]
+pub async fn get_excel_headers(
+    state: State<'_, AppState>,
+    excel_path: String,
+    worksheet_name: String,
+    header_row: i32,
+) -> Result<Vec<excel::ExcelHeader>, AppError> {
+    ensure_path_allowed(&state, &excel_path)?;
+    let path = excel_path.clone();
+    let sheet = worksheet_name.clone();
+    let row = header_row.max(1) as u32;
+    tauri::async_runtime::spawn_blocking(move || excel::get_excel_headers(&path, &sheet, row))
+        .await
+        .map_err(AppError::internal)?
+}
+
+/// Read sheet names on a background thread so the UI stays responsive.
+#[tauri::command]
+pub async fn get_sheet_names(state: State<'_, AppState>, path: String) -> Result<Vec<String>, AppError> {
+    ensure_path_allowed(&state, &path)?;
+    let path = path.clone();
+    tauri::async_runtime::spawn_blocking(move || excel::get_sheet_names(&path))
+        .await
+        .map_err(AppError::internal)?
+}
+
+/// Diff two exports (CSV or Excel) by a key column: rows added in B, removed from A, and changed cells.
+#[tauri::command]
+pub async fn diff_exports(
+    state: State<'_, AppState>,
+    path_a: String,
+    path_b: String,
+    key_column: String,
+) -> Result<export_diff::ExportDiff, AppError> {
+    ensure_path_allowed(&state, &path_a)?;
+    ensure_path_allowed(&state, &path_b)?;
+    tauri::async_runtime::spawn_blocking(move || export_diff::diff_exports(&path_a, &path_b, &key_column))
+        .await
+        .map_err(AppError::internal)?
+        .map_err(AppError::Excel)
+}
+
+/// Append row on a background thread so the UI stays responsive.
+#[tauri::command]
+pub async fn append_row_to_excel(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    payload: AppendRowPayload,
+) -> Result<(), AppError> {
+    state.ensure_writable()?;
+    ensure_path_allowed(&state, &payload.path)?;
+    workbook_format::ensure_appendable(Path::new(&payload.path))?;
+    wait_for_excel_unlock(&app, &payload.path, payload.wait_for_unlock_secs, payload.wait_id.clone()).await?;
+    let path = payload.path.clone();
+    let sheet = payload.sheet.clone();
+    let row: Vec<(String, String)> = payload
+        .row
+        .into_iter()
+        .map(|c| (c.column, c.value))
+        .collect();
+    let _permit = work_pool::acquire(work_pool::WorkClass::Append).await;
+    let writes = tauri::async_runtime::spawn_blocking(move || excel::append_row_to_excel(&path, &sheet, row))
+        .await
+        .map_err(AppError::internal)??;
+    record_cell_writes(&state, payload.path, payload.history_id, writes).await;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_profiles(state: State<AppState>) -> Result<Vec<(i64, String, String, String, String)>, AppError> {
+    let db = state.db()?;
+    db.get_profiles()
+}
+
+#[tauri::command]
+pub fn save_profile(state: State<AppState>, payload: SaveProfilePayload) -> Result<i64, AppError> {
+    state.ensure_writable()?;
+    ensure_path_allowed(&state, &payload.excel_path)?;
+    let db = state.db()?;
+    db.save_profile(
+        payload.id,
+        &payload.name,
+        &payload.excel_path,
+        &payload.sheet_name,
+        &payload.column_mapping,
+    )
+}
+
+/// Copy a workbook appends cannot write (.xls, .xlsb, .ods: the CONVERSION_REQUIRED error) to an .xlsx
+/// next to it, values only. With `profile_id` the profile is pointed at the copy and its schema re-scanned.
+/// Returns the new path.
+#[tauri::command]
+pub async fn convert_workbook_to_xlsx(
+    state: State<'_, AppState>,
+    path: String,
+    profile_id: Option<i64>,
+) -> Result<String, AppError> {
+    state.ensure_writable()?;
+    let app_data_dir = state.app_data_dir();
+    with_db_blocking(&state, move |db| {
+        let source = path_scope::check(db, &app_data_dir, &path)?;
+        let target = workbook_format::convert_to_xlsx(&source)?;
+        let target_path = target.to_string_lossy().to_string();
+        if let Some(profile_id) = profile_id {
+            let (_, sheet_name, column_mapping_json) = db.get_profile_by_id(profile_id)?;
+            let name = db
+                .get_profiles()?
+                .into_iter()
+                .find(|p| p.0 == profile_id)
+                .map(|p| p.1)
+                .unwrap_or_default();
+            let column_mapping: Value = serde_json::from_str(&column_mapping_json).unwrap_or_default();
+            let schema = scan_schema(&target_path, &sheet_name)?;
+            db.save_profile(Some(profile_id), &name, &target_path, &sheet_name, &column_mapping)?;
+            db.save_excel_schema(profile_id, &schema)?;
+            schema_cache::set_cached_schema(profile_id, schema);
+        }
+        Ok::<_, AppError>(target_path)
+    })
+    .await
+}
+
+/// Result of `remap_profile_columns`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemappedProfile {
+    pub column_mapping: std::collections::HashMap<String, String>,
+    /// Fields that had a column before and have none now.
+    pub unmapped_fields: Vec<String>,
+    /// What changed since the stored schema (None when nothing did or no schema was stored).
+    pub diff: Option<SchemaDiff>,
+    pub schema: ExcelSchema,
+}
+
+/// Re-scan the profile's sheet after columns were inserted, deleted or moved (an append failed with
+/// `SCHEMA_CHANGED`) and move the column mapping onto the columns as they are now: by header text, or
+/// `column_mapping` (column letter -> field key) as the user corrected it. Saves mapping and schema.
+#[tauri::command]
+pub async fn remap_profile_columns(
+    state: State<'_, AppState>,
+    profile_id: i64,
+    column_mapping: Option<std::collections::HashMap<String, String>>,
+) -> Result<RemappedProfile, AppError> {
+    state.ensure_writable()?;
+    with_db_blocking(&state, move |db| {
+        let (excel_path, sheet_name, column_mapping_json) = db.get_profile_by_id(profile_id)?;
+        let old_mapping = parse_column_mapping(&column_mapping_json);
+        let stored = db.load_excel_schema(profile_id).ok();
+        let schema = scan_schema(&excel_path, &sheet_name)?;
+        let diff = stored.as_ref().and_then(|s| schema_drift::diff(s, &schema.headers, &old_mapping));
+        let new_mapping: std::collections::HashMap<String, String> = match (column_mapping, &stored) {
+            (Some(mapping), _) => mapping
+                .into_iter()
+                .filter_map(|(letter, field)| {
+                    let header = schema.headers.iter().find(|h| h.column_letter.eq_ignore_ascii_case(letter.trim()))?;
+                    let field = field.trim().to_string();
+                    (!field.is_empty()).then(|| (header.column_letter.clone(), field))
+                })
+                .collect(),
+            (None, Some(stored)) => schema_drift::remap(&stored.headers, &schema.headers, &old_mapping),
+            (None, None) => {
+                return Err(AppError::Validation(
+                    "The profile has no stored schema to remap from; choose the columns again.".to_string(),
+                ))
+            }
+        };
+
+        // Keep the settings stored next to the columns (`_headerRow` and the like).
+        let mut saved: serde_json::Map<String, Value> = serde_json::from_str(&column_mapping_json).unwrap_or_default();
+        saved.retain(|key, _| key.starts_with('_'));
+        saved.insert("_headerRow".to_string(), Value::from(schema.header_row));
+        for (letter, field) in &new_mapping {
+            saved.insert(letter.clone(), Value::String(field.clone()));
+        }
+        db.update_profile_mapping(profile_id, &Value::Object(saved))?;
+        db.save_excel_schema(profile_id, &schema)?;
+        schema_cache::set_cached_schema(profile_id, schema.clone());
+        Ok(RemappedProfile {
+            unmapped_fields: schema_drift::lost_fields(&old_mapping, &new_mapping),
+            column_mapping: new_mapping,
+            diff,
+            schema,
+        })
+    })
+    .await
+}
+
+/// Ranked field suggestions per column for a header row (`headers` from column A on) and, when given, the
+/// sample values per column as returned by `analyze_excel_schema`.
+#[tauri::command]
+pub async fn suggest_column_mapping(
+    state: State<'_, AppState>,
+    headers: Vec<String>,
+    column_samples: Option<Vec<Vec<String>>>,
+) -> Result<Vec<mapping_suggestions::ColumnSuggestions>, AppError> {
+    with_db_blocking(&state, move |db| {
+        mapping_suggestions::rank(db, &headers, &column_samples.unwrap_or_default())
+    })
+    .await
+}
+
+/// A profile made by `create_profile_from_template`, with the mapping it was saved with.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreatedProfile {
+    pub profile_id: i64,
+    pub excel_path: String,
+    pub sheet_name: String,
+    pub schema: ExcelSchema,
+    pub suggestions: Vec<mapping_suggestions::MappingSuggestion>,
+}
+
+/// Copy a blank template workbook to `target_path` (which must not exist yet), scan its first sheet,
+/// map its columns from learned mappings and existing profiles, and save profile and schema together.
+/// The copy is removed again when anything after it fails.
+#[tauri::command]
+pub async fn create_profile_from_template(
+    state: State<'_, AppState>,
+    template_path: String,
+    target_path: String,
+    name: String,
+) -> Result<CreatedProfile, AppError> {
+    state.ensure_writable()?;
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        return Err(AppError::Validation("Profile name is required.".to_string()));
+    }
+    let app_data_dir = state.app_data_dir();
+    let (template, target) = with_db_blocking(&state, move |db| {
+        Ok::<_, AppError>((
+            path_scope::check(db, &app_data_dir, &template_path)?,
+            path_scope::check(db, &app_data_dir, &target_path)?,
+        ))
+    })
+    .await?;
+    if target.exists() {
+        return Err(AppError::Validation(format!("{} already exists.", target.display())));
+    }
+    let target_str = target.to_string_lossy().to_string();
+
+    let path = target_str.clone();
+    let scanned = tauri::async_runtime::spawn_blocking(move || {
+        fs::copy(&template, &path).map_err(AppError::io)?;
+        let sheet_name = excel::get_sheet_names(&path)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| AppError::Excel("The template has no sheets.".to_string()))?;
+        let schema = scan_schema(&path, &sheet_name)?;
+        let (_, headers, column_samples, ..) = excel::analyze_excel_schema(&path, &sheet_name, schema.header_row)?;
+        Ok::<_, AppError>((sheet_name, schema, headers, column_samples))
+    })
+    .await
+    .map_err(AppError::internal)
+    .and_then(|r| r);
+    let (sheet_name, schema, headers, column_samples) = match scanned {
+        Ok(scanned) => scanned,
+        Err(e) => {
+            let _ = fs::remove_file(&target);
+            return Err(e);
+        }
+    };
+
+    let (path, sheet, profile_schema) = (target_str.clone(), sheet_name.clone(), schema.clone());
+    let saved = with_db_blocking(&state, move |db| {
+        let suggestions = mapping_suggestions::best_mapping(&mapping_suggestions::rank(db, &headers, &column_samples)?);
+        let mut mapping = serde_json::Map::new();
+        for s in &suggestions {
+            mapping.insert(s.column_letter.clone(), Value::String(s.field_key.clone()));
+        }
+        mapping.insert("_headerRow".to_string(), Value::from(profile_schema.header_row));
+        let profile_id = db.save_profile(None, &name, &path, &sheet, &Value::Object(mapping))?;
+        if let Err(e) = db.save_excel_schema(profile_id, &profile_schema) {
+            let _ = db.delete_profile(profile_id);
+            return Err(e);
+        }
+        Ok::<_, AppError>((profile_id, suggestions))
+    })
+    .await;
+    let (profile_id, suggestions) = match saved {
+        Ok(saved) => saved,
+        Err(e) => {
+            let _ = fs::remove_file(&target);
+            return Err(e);
+        }
+    };
+    schema_cache::set_cached_schema(profile_id, schema.clone());
+    Ok(CreatedProfile {
+        profile_id,
+        excel_path: target_str,
+        sheet_name,
+        schema,
+        suggestions,
+    })
+}
+
+/// First-run demo: write a sample ledger and `document_count` demo invoices (default 5) to `<app data>/demo`,
+/// scan them with the mock provider and save a demo profile for the ledger with its schema. Running it
+/// again rebuilds the workspace and keeps the same profile.
+#[tauri::command]
+pub async fn create_demo_workspace(
+    state: State<'_, AppState>,
+    document_count: Option<u32>,
+) -> Result<onboarding::DemoWorkspace, AppError> {
+    state.ensure_writable()?;
+    let folder = onboarding::folder(&state.app_data_dir());
+    let count = document_count.unwrap_or(onboarding::DEFAULT_DOCUMENTS);
+    let dir = folder.clone();
+    let (ledger_path, documents, schema) = tauri::async_runtime::spawn_blocking(move || {
+        let (ledger_path, documents) = onboarding::write_files(&dir, count)?;
+        let schema = scan_schema(&ledger_path.to_string_lossy(), onboarding::LEDGER_SHEET)?;
+        Ok::<_, AppError>((ledger_path, documents, schema))
+    })
+    .await
+    .map_err(AppError::internal)??;
+
+    let (path, profile_schema) = (ledger_path.clone(), schema.clone());
+    let profile_id = with_db_blocking(&state, move |db| {
+        let profile_id = onboarding::save_profile(db, &path)?;
+        db.save_excel_schema(profile_id, &profile_schema)?;
+        Ok::<_, AppError>(profile_id)
+    })
+    .await?;
+    schema_cache::set_cached_schema(profile_id, schema);
+    Ok(onboarding::DemoWorkspace {
+        folder: folder.to_string_lossy().to_string(),
+        ledger_path: ledger_path.to_string_lossy().to_string(),
+        sheet_name: onboarding::LEDGER_SHEET.to_string(),
+        profile_id,
+        documents,
+    })
+}
+
+/// Delete the demo profile and the demo folder. Returns whether there was a demo profile.
+#[tauri::command]
+pub async fn remove_demo_workspace(state: State<'_, AppState>) -> Result<bool, AppError> {
+    state.ensure_writable()?;
+    let removed = with_db_blocking(&state, |db| {
+        let Some(id) = onboarding::profile_id(db)? else {
+            onboarding::forget(db)?;
+            return Ok::<_, AppError>(false);
+        };
+        export_delivery::remove_profile_delivery(db, id)?;
+        redaction::remove_rules(db, id)?;
+        ledger_snapshots::remove_policy(db, id)?;
+        db.delete_profile(id)?;
+        onboarding::forget(db)?;
+        schema_cache::invalidate_cache(id);
+        Ok(true)
+    })
+    .await?;
+    let folder = onboarding::folder(&state.app_data_dir());
+    if folder.exists() {
+        fs::remove_dir_all(&folder).map_err(AppError::io)?;
+    }
+    Ok(removed)
+}
+
+#[tauri::command]
+pub fn delete_profile(state: State<AppState>, id: i64) -> Result<(), AppError> {
+    state.ensure_writable()?;
+    let db = state.db()?;
+    export_delivery::remove_profile_delivery(&db, id)?;
+    export_targets::remove(&db, id)?;
+    redaction::remove_rules(&db, id)?;
+    ledger_snapshots::remove_policy(&db, id)?;
+    db.delete_profile(id)
+}
+
+#[tauri::command]
+pub fn get_history(
+    state: State<AppState>,
+    payload: Option<GetHistoryPayload>,
+) -> Result<Vec<(i64, String, String, String, String, String, Option<i64>, Option<String>)>, AppError>
+{
+    let db = state.db()?;
+    let search = payload.as_ref().and_then(|p| p.search.clone());
+    let folder_id = payload.as_ref().and_then(|p| p.folder_id);
+    let include_subfolders = payload.as_ref().and_then(|p| p.include_subfolders).unwrap_or(false);
+    let top_level_only = payload.as_ref().and_then(|p| p.top_level_only).unwrap_or(false);
+    let session_id = payload.as_ref().and_then(|p| p.session_id);
+    let limit = payload.as_ref().and_then(|p| p.limit);
+    let offset = payload.as_ref().and_then(|p| p.offset);
+    db.get_history(search.as_deref(), folder_id, include_subfolders, top_level_only, session_id, limit, offset)
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistorySearchHit {
+    pub id: i64,
+    pub created_at: String,
+    pub document_type: String,
+    pub file_path_or_name: String,
+    pub status: String,
+    pub excel_profile_id: Option<i64>,
+    /// Matching text with hits wrapped in <mark></mark>.
+    pub snippet: String,
+    /// BM25 score; lower is a better match.
+    pub rank: f64,
+}
+
+/// Ranked full-text search over history (file names and extracted values); default page size 50.
+#[tauri::command]
+pub async fn search_history_fts(
+    state: State<'_, AppState>,
+    query: String,
+    limit: Option<i64>,
+    offset: Option<i64>,
+) -> Result<Vec<HistorySearchHit>, AppError> {
+    let rows = with_db_blocking(&state, move |db| {
+        db.search_history_fts(&query, limit.unwrap_or(50), offset.unwrap_or(0))
+    })
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(
+            |(id, created_at, document_type, file_path_or_name, status, excel_profile_id, snippet, rank)| HistorySearchHit {
+                id,
+                created_at,
+                document_type,
+                file_path_or_name,
+                status,
+                excel_profile_id,
+                snippet,
+                rank,
+            },
+        )
+        .collect())
+}
+
+/// Total for the History filters (limit/offset are ignored), for page counts.
+#[tauri::command]
+pub fn get_history_count(state: State<AppState>, payload: Option<GetHistoryPayload>) -> Result<i64, AppError> {
+    let db = state.db()?;
+    let search = payload.as_ref().and_then(|p| p.search.clone());
+    let folder_id = payload.as_ref().and_then(|p| p.folder_id);
+    let include_subfolders = payload.as_ref().and_then(|p| p.include_subfolders).unwrap_or(false);
+    let top_level_only = payload.as_ref().and_then(|p| p.top_level_only).unwrap_or(false);
+    let session_id = payload.as_ref().and_then(|p| p.session_id);
+    db.count_history(search.as_deref(), folder_id, include_subfolders, top_level_only, session_id)
+}
+
+/// Cell write log for a workbook, newest first; narrow to a sheet and/or row to answer
+/// "who put this number in row 912" (history_id points at the document that was written).
+#[tauri::command]
+pub fn get_write_log(
+    state: State<AppState>,
+    file_path: String,
+    sheet_name: Option<String>,
+    row_number: Option<u32>,
+    limit: Option<u32>,
+) -> Result<Vec<crate::db::WriteLogRow>, AppError> {
+    let db = state.db()?;
+    db.get_write_log(&file_path, sheet_name.as_deref(), row_number, limit.unwrap_or(500))
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WrittenRow {
+    pub row_number: u32,
+    pub written_at: String,
+    pub history_id: Option<i64>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LedgerPage {
+    #[serde(flatten)]
+    pub range: excel::SheetRange,
+    /// Rows in this page that the app wrote (from the write log), for highlighting recent appends.
+    pub written_rows: Vec<WrittenRow>,
+}
+
+/// Read-only page of a ledger sheet's values (1-based `start_row`, up to 500 rows), so appends can be
+/// checked without opening Excel.
+#[tauri::command]
+pub async fn read_ledger_range(
+    state: State<'_, AppState>,
+    path: String,
+    sheet_name: String,
+    start_row: u32,
+    row_count: u32,
+) -> Result<LedgerPage, AppError> {
+    ensure_path_allowed(&state, &path)?;
+    let db = state.db()?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let range = excel::read_sheet_range(&path, &sheet_name, start_row, row_count)?;
+        let written_rows = match range.rows.last() {
+            Some(last) => db
+                .get_written_rows(&path, &range.sheet_name, range.start_row, last.row_number)?
+                .into_iter()
+                .map(|(row_number, written_at, history_id)| WrittenRow {
+                    row_number,
+                    written_at,
+                    history_id,
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+        Ok(LedgerPage { range, written_rows })
+    })
+    .await
+    .map_err(AppError::internal)?
+}
+
+/// Per-invoice records of a batch export entry.
+#[tauri::command]
+pub fn get_history_children(
+    state: State<AppState>,
+    parent_id: i64,
+) -> Result<Vec<HistoryRow>, AppError> {
+    let db = state.db()?;
+    db.get_history_children(parent_id)
+}
+
+/// `parent_id` nests the folder under another one; top level when omitted.
+#[tauri::command]
+pub fn create_folder(state: State<AppState>, name: String, parent_id: Option<i64>) -> Result<i64, AppError> {
+    state.ensure_writable()?;
+    let db = state.db()?;
+    db.create_folder(&name, parent_id)
+}
+
+/// (id, name, created_at, parent_id) of every folder; build the tree from `parent_id`.
+#[tauri::command]
+pub fn get_folders(state: State<AppState>) -> Result<Vec<FolderRow>, AppError> {
+    let db = state.db()?;
+    db.get_folders()
+}
+
+#[tauri::command]
+pub fn rename_folder(state: State<AppState>, id: i64, name: String) -> Result<(), AppError> {
+    state.ensure_writable()?;
+    let db = state.db()?;
+    db.rename_folder(id, &name)
+}
+
+/// Moves the folder with its subfolders and documents under `parent_id` (top level when omitted).
+#[tauri::command]
+pub fn move_folder(state: State<AppState>, id: i64, parent_id: Option<i64>) -> Result<(), AppError> {
+    state.ensure_writable()?;
+    let db = state.db()?;
+    db.move_folder(id, parent_id)
+}
+
+/// Documents and subfolders of the deleted folder move up to its parent.
+#[tauri::command]
+pub fn delete_folder(state: State<AppState>, id: i64) -> Result<(), AppError> {
+    state.ensure_writable()?;
+    let db = state.db()?;
+    db.delete_folder(id)
+}
+
+#[tauri::command]
+pub fn assign_history_to_folder(state: State<AppState>, history_id: i64, folder_id: Option<i64>) -> Result<(), AppError> {
+    state.ensure_writable()?;
+    let db = state.db()?;
+    db.assign_history_to_folder(history_id, folder_id)
+}
+
+#[tauri::command]
+pub fn create_category(state: State<AppState>, name: String, monthly_budget: Option<f64>) -> Result<i64, AppError> {
+    state.ensure_writable()?;
+    let db = state.db()?;
+    db.create_category(&name, monthly_budget)
+}
+
+#[tauri::command]
+pub fn update_category(state: State<AppState>, id: i64, name: String, monthly_budget: Option<f64>) -> Result<(), AppError> {
+    state.ensure_writable()?;
+    let db = state.db()?;
+    db.update_category(id, &name, monthly_budget)
+}
+
+#[tauri::command]
+pub fn get_categories(state: State<AppState>) -> Result<Vec<CategoryRow>, AppError> {
+    let db = state.db()?;
+    db.get_categories()
+}
+
+#[tauri::command]
+pub fn delete_category(state: State<AppState>, id: i64) -> Result<(), AppError> {
+    state.ensure_writable()?;
+    let db = state.db()?;
+    db.delete_category(id)
+}
+
+#[tauri::command]
+pub fn assign_history_to_category(state: State<AppState>, history_id: i64, category_id: Option<i64>) -> Result<(), AppError> {
+    state.ensure_writable()?;
+    let db = state.db()?;
+    db.assign_history_to_category(history_id, category_id)
+}
+
+fn build_spending_report(state: &State<AppState>, period: &str) -> Result<spending_report::SpendingReport, AppError> {
+    let db = state.db()?;
+    let categories: Vec<(i64, String, Option<f64>)> = db
+        .get_categories()?
+        .into_iter()
+        .map(|(id, name, budget, _)| (id, name, budget))
+        .collect();
+    let rows = db.get_spending_rows()?;
+    spending_report::build_spending_report(period, &categories, &rows).map_err(AppError::Validation)
+}
+
+/// Totals per category for a month ("YYYY-MM") with month-over-month deltas and a 6-month trend.
+#[tauri::command]
+pub fn get_spending_by_category(state: State<AppState>, period: String) -> Result<spending_report::SpendingReport, AppError> {
+    build_spending_report(&state, &period)
+}
+
+/// Export the spending report for a month to .xlsx. Returns the saved path.
+#[tauri::command]
+pub fn export_spending_report(state: State<AppState>, period: String, path: String) -> Result<String, AppError> {
+    ensure_path_allowed(&state, &path)?;
+    let report = build_spending_report(&state, &period)?;
+    excel::export_spending_report(&report, &path)
+}
+
+/// Input VAT summary (ДДВ-04 style: per rate, month and vendor) for `period_start`..`period_end`
+/// ("YYYY-MM" or "YYYY-MM-DD", inclusive), written to `path` or a DDV_<start>_<end>.xlsx in the export folder.
+#[tauri::command]
+pub async fn generate_vat_report(
+    state: State<'_, AppState>,
+    period_start: String,
+    period_end: String,
+    path: Option<String>,
+) -> Result<vat_report::VatReport, AppError> {
+    if let Some(path) = &path {
+        ensure_path_allowed(&state, path)?;
+    }
+    let rows = with_db_blocking(&state, |db| db.get_history_report_rows(None, None)).await?;
+    let mut report = vat_report::build_vat_report(&period_start, &period_end, &rows).map_err(AppError::Validation)?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let target = match path.filter(|p| !p.trim().is_empty()) {
+            Some(p) => std::path::PathBuf::from(p.trim()),
+            None => excel::unique_export_path(&format!("DDV_{}_{}", report.period_start, report.period_end), "xlsx")?,
+        };
+        report.saved_path = Some(excel::export_vat_report(&report, &target)?);
+        Ok(report)
+    })
+    .await
+    .map_err(AppError::internal)?
+}
+
+#[tauri::command]
+pub async fn get_snapshot_policy(
+    state: State<'_, AppState>,
+    profile_id: i64,
+) -> Result<Option<ledger_snapshots::SnapshotPolicy>, AppError> {
+    with_db_blocking(&state, move |db| ledger_snapshots::get_policy(db, profile_id)).await
+}
+
+/// Snapshot the profile's workbook after every N appends and/or daily; None turns it off.
+#[tauri::command]
+pub async fn set_snapshot_policy(
+    state: State<'_, AppState>,
+    profile_id: i64,
+    policy: Option<ledger_snapshots::SnapshotPolicy>,
+) -> Result<Option<ledger_snapshots::SnapshotPolicy>, AppError> {
+    state.ensure_writable()?;
+    with_db_blocking(&state, move |db| ledger_snapshots::save_policy(db, profile_id, policy)).await
+}
+
+#[tauri::command]
+pub async fn get_duplicate_key(
+    state: State<'_, AppState>,
+    profile_id: i64,
+) -> Result<Option<duplicate_keys::DuplicateKey>, AppError> {
+    with_db_blocking(&state, move |db| duplicate_keys::get_key(db, profile_id)).await
+}
+
+/// Set the fields that make a ledger row unique and what appends do with duplicates; None goes back to
+/// the document number alone.
+#[tauri::command]
+pub async fn set_duplicate_key(
+    state: State<'_, AppState>,
+    profile_id: i64,
+    key: Option<duplicate_keys::DuplicateKey>,
+) -> Result<Option<duplicate_keys::DuplicateKey>, AppError> {
+    state.ensure_writable()?;
+    with_db_blocking(&state, move |db| duplicate_keys::save_key(db, profile_id, key)).await
+}
+
+/// Ledger rows (1-based) that already hold a document with the same key, for a warning before appending.
+#[tauri::command]
+pub async fn find_duplicate_rows(
+    state: State<'_, AppState>,
+    profile_id: i64,
+    invoice_data: InvoiceData,
+) -> Result<Vec<u32>, AppError> {
+    let (schema, (excel_path, sheet_name, column_mapping_json), duplicate_key) = with_db_blocking(&state, move |db| {
+        Ok::<_, AppError>((
+            cached_or_load_schema(db, profile_id)?,
+            db.get_profile_by_id(profile_id)?,
+            duplicate_keys::get_key(db, profile_id)?,
+        ))
+    })
+    .await?;
+    let column_mapping = parse_column_mapping(&column_mapping_json);
+    let fields =
+        duplicate_keys::effective_fields(duplicate_key.as_ref(), &column_mapping).map_err(AppError::Validation)?;
+    ledger_duplicates(&excel_path, &sheet_name, schema.header_row, &column_mapping, fields, &invoice_data).await
+}
+
+/// Snapshot the profile's workbook now.
+#[tauri::command]
+pub async fn take_ledger_snapshot(
+    state: State<'_, AppState>,
+    profile_id: i64,
+) -> Result<ledger_snapshots::LedgerSnapshot, AppError> {
+    state.ensure_writable()?;
+    let app_data_dir = state.app_data_dir();
+    with_db_blocking(&state, move |db| {
+        ledger_snapshots::take_snapshot(db, &app_data_dir, profile_id, "manual").map_err(AppError::Io)
+    })
+    .await
+}
+
+/// Snapshots of a profile's workbook, newest first.
+#[tauri::command]
+pub async fn list_snapshots(
+    state: State<'_, AppState>,
+    profile_id: i64,
+) -> Result<Vec<ledger_snapshots::LedgerSnapshot>, AppError> {
+    with_db_blocking(&state, move |db| ledger_snapshots::list_snapshots(db, profile_id)).await
+}
+
+/// Replace the workbook with a snapshot (the current file is snapshotted first). Fails while Excel has it open.
+#[tauri::command]
+pub async fn restore_snapshot(
+    state: State<'_, AppState>,
+    snapshot_id: i64,
+) -> Result<ledger_snapshots::LedgerSnapshot, AppError> {
+    state.ensure_writable()?;
+    let app_data_dir = state.app_data_dir();
+    with_db_blocking(&state, move |db| {
+        ledger_snapshots::restore_snapshot(db, &app_data_dir, snapshot_id).map_err(AppError::Excel)
+    })
+    .await
 }
 
+/// Filtered history (date range, folder, status) with totals per seller and per month, saved as .xlsx,
+/// or as CSV (documents only) when `path` ends in .csv. Returns the saved path.
 #[tauri::command]
-pub async fn analyze_excel_schema(
+pub async fn export_history_report(
+    state: State<'_, AppState>,
+    filter: Option<history_report::HistoryReportFilter>,
     path: String,
-    sheet_name: String,
-    header_row: u32,
-) -> Result<AnalyzedExcelSchema, String> {
-    let path = path.clone();
-    let sheet_name = sheet_name.clone();
+) -> Result<String, AppError> {
+    ensure_path_allowed(&state, &path)?;
+    let filter = filter.unwrap_or_default();
+    let rows = {
+        let (folder_id, status) = (filter.folder_id, filter.status.clone());
+        with_db_blocking(&state, move |db| db.get_history_report_rows(folder_id, status.as_deref())).await?
+    };
+    let report = history_report::build_history_report(&filter, &rows).map_err(AppError::Validation)?;
     tauri::async_runtime::spawn_blocking(move || {
-        excel::analyze_excel_schema(&path, &sheet_name, header_row)
+        let target = std::path::PathBuf::from(path.trim());
+        if target.extension().is_some_and(|e| e.eq_ignore_ascii_case("csv")) {
+            history_report::write_history_report_csv(&report, &target).map_err(AppError::Io)
+        } else {
+            excel::export_history_report(&report, &path)
+        }
     })
     .await
-    .map_err(|e| e.to_string())?
-    .map(|(worksheet_name, headers, column_samples, last_data_row, schema_hash)| {
-        AnalyzedExcelSchema {
-            worksheet_name,
-            headers,
-            column_samples,
-            last_data_row,
-            schema_hash,
-        }
+    .map_err(AppError::internal)?
+}
+
+/// Cross-check history records exported to a profile's ledger against the workbook by the profile's
+/// duplicate key (document number by default): reports missing, duplicated and altered rows.
+#[tauri::command]
+pub async fn audit_profile_consistency(
+    state: State<'_, AppState>,
+    profile_id: i64,
+) -> Result<profile_audit::ProfileAuditReport, AppError> {
+    let (schema, (excel_path, sheet_name, column_mapping_json), history, duplicate_key) =
+        with_db_blocking(&state, move |db| {
+            Ok::<_, AppError>((
+                cached_or_load_schema(db, profile_id)?,
+                db.get_profile_by_id(profile_id)?,
+                db.get_exported_history_for_profile(profile_id)?,
+                duplicate_keys::get_key(db, profile_id)?,
+            ))
+        })
+        .await?;
+    let column_mapping: std::collections::HashMap<String, String> =
+        serde_json::from_str(&column_mapping_json).map_err(|e| AppError::Db(format!("Invalid column_mapping: {}", e)))?;
+    let key_fields =
+        duplicate_keys::effective_fields(duplicate_key.as_ref(), &column_mapping).map_err(AppError::Validation)?;
+    tauri::async_runtime::spawn_blocking(move || {
+        profile_audit::audit_profile(
+            profile_id,
+            &excel_path,
+            &sheet_name,
+            schema.header_row,
+            &column_mapping,
+            &key_fields,
+            &history,
+        )
     })
+    .await
+    .map_err(AppError::internal)?
+    .map_err(AppError::Excel)
 }
 
 #[tauri::command]
-pub fn cache_excel_schema(
+pub fn get_history_by_id(
     state: State<AppState>,
-    path: String,
-    schema_json: String,
-    schema_hash: String,
-    worksheet_name: String,
-) -> Result<(), String> {
-    let metadata = fs::metadata(Path::new(&path)).map_err(|e| format!("File not found: {}", e))?;
-    let mtime = metadata
-        .modified()
-        .map_err(|e| format!("Cannot get mtime: {}", e))?;
-    let mtime_ms = mtime
-        .duration_since(UNIX_EPOCH)
-        .map(|d| d.as_millis())
-        .unwrap_or(0);
-    let cache_key = format!("{}:{}", path, mtime_ms);
-    let last_modified = mtime_ms.to_string();
+    id: i64,
+) -> Result<Option<(String, String, String, String, Option<i64>)>, AppError> {
+    let db = state.db()?;
+    db.get_history_by_id(id)
+}
 
-    let db = state.db.lock().map_err(|e| e.to_string())?;
-    let db = db.as_ref().ok_or("Database not initialized")?;
-    db.upsert_schema_cache(
-        &cache_key,
-        &path,
-        &schema_hash,
-        &worksheet_name,
-        &schema_json,
-        &last_modified,
-    )
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InvoiceEditInput {
+    pub field_key: String,
+    /// Value as extracted by OCR (None when the field was empty or missing).
+    pub original_value: Option<String>,
+    pub corrected_value: String,
 }
 
-/// Read Excel headers on a background thread so the UI stays responsive (avoids "Not Responding" on large or Cyrillic paths).
-#[tauri::command]
-pub async fn read_excel_headers(path: String, sheet: String, header_row: Option<u32>) -> Result<Vec<String>, String> {
-    let path = path.clone();
-    let sheet = sheet.clone();
-    tauri::async_runtime::spawn_blocking(move || excel::read_excel_headers(&path, &sheet, header_row))
-        .await
-        .map_err(|e| e.to_string())?
+/// Document type and extracted data of a history record before a change.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryRevision {
+    pub id: i64,
+    pub document_type: String,
+    pub extracted_data: Value,
+    /// Changed keys as [{key, before, after}].
+    pub diff: Value,
+    /// "edit", "rescan" or "restore"
+    pub reason: String,
+    pub created_at: String,
 }
 
-/// Get Excel headers with column letter and index for visual mapping UI. Reads from local filesystem only.
-#[tauri::command]
-pub async fn get_excel_headers(
-    excel_path: String,
-    worksheet_name: String,
-    header_row: i32,
-) -> Result<Vec<excel::ExcelHeader>, String> {
-    let path = excel_path.clone();
-    let sheet = worksheet_name.clone();
-    let row = header_row.max(1) as u32;
-    tauri::async_runtime::spawn_blocking(move || excel::get_excel_headers(&path, &sheet, row))
-        .await
-        .map_err(|e| e.to_string())?
+/// `extracted_data` of a history record for scanned fields: key -> value plus `_confidence`, as the
+/// frontend builds it after a scan.
+fn history_extracted_data(invoice: &InvoiceData) -> Value {
+    let mut data = serde_json::Map::new();
+    let mut confidence = serde_json::Map::new();
+    for (key, field) in &invoice.fields {
+        data.insert(key.clone(), Value::String(field.value.clone()));
+        if let Some(c) = field.confidence {
+            confidence.insert(key.clone(), serde_json::json!(c));
+        }
+    }
+    if !confidence.is_empty() {
+        data.insert("_confidence".to_string(), Value::Object(confidence));
+    }
+    Value::Object(data)
 }
 
-/// Read sheet names on a background thread so the UI stays responsive.
+/// Run OCR again on the original of a history record, e.g. with another document type's model, and
+/// replace its extracted data (and document type); the previous data is kept as a revision. Uses the
+/// source file while it exists, else the archived copy.
 #[tauri::command]
-pub async fn get_sheet_names(path: String) -> Result<Vec<String>, String> {
-    let path = path.clone();
-    tauri::async_runtime::spawn_blocking(move || excel::get_sheet_names(&path))
-        .await
-        .map_err(|e| e.to_string())?
+pub async fn rescan_history_record(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    id: i64,
+    document_type: Option<String>,
+) -> Result<crate::types::OcrInvoiceResult, AppError> {
+    state.ensure_writable()?;
+    let app_data_dir = state.app_data_dir();
+    let (current_type, file_path) = with_db_blocking(&state, move |db| {
+        let (document_type, name, source_path, _) = db
+            .get_history_source(id)?
+            .ok_or_else(|| AppError::Validation(format!("History record {} not found.", id)))?;
+        if document_type == "batch_export" {
+            return Err(AppError::Validation("Batch exports cannot be scanned again.".to_string()));
+        }
+        let local = [source_path, Some(name)].into_iter().flatten().map(PathBuf::from).find(|p| p.is_file());
+        let file_path = match local {
+            Some(path) => path,
+            None => archive_store::archived_document_path(db, &app_data_dir, id).map_err(|_| {
+                AppError::Validation("The original of this document is no longer available.".to_string())
+            })?,
+        };
+        Ok((document_type, file_path.to_string_lossy().to_string()))
+    })
+    .await?;
+    let document_type = document_type.filter(|t| !t.trim().is_empty()).unwrap_or(current_type);
+    let result = run_ocr_invoice(app, state.clone(), file_path, Some(document_type.clone()), None).await?;
+    let data = history_extracted_data(&result.invoice_data);
+    with_db_blocking(&state, move |db| db.revise_history_record(id, &document_type, &data, "rescan")).await?;
+    Ok(result)
 }
 
-/// Append row on a background thread so the UI stays responsive.
+/// Earlier versions of a history record's extracted data, newest first.
 #[tauri::command]
-pub async fn append_row_to_excel(payload: AppendRowPayload) -> Result<(), String> {
-    let path = payload.path.clone();
-    let sheet = payload.sheet.clone();
-    let row: Vec<(String, String)> = payload
-        .row
+pub async fn get_history_revisions(state: State<'_, AppState>, history_id: i64) -> Result<Vec<HistoryRevision>, AppError> {
+    let rows = with_db_blocking(&state, move |db| db.get_history_revisions(history_id)).await?;
+    Ok(rows
         .into_iter()
-        .map(|c| (c.column, c.value))
-        .collect();
-    tauri::async_runtime::spawn_blocking(move || excel::append_row_to_excel(&path, &sheet, row))
-        .await
-        .map_err(|e| e.to_string())?
+        .map(|(id, document_type, extracted_data, diff, reason, created_at)| HistoryRevision {
+            id,
+            document_type,
+            extracted_data: serde_json::from_str(&extracted_data).unwrap_or(Value::Null),
+            diff: serde_json::from_str(&diff).unwrap_or(Value::Array(Vec::new())),
+            reason,
+            created_at,
+        })
+        .collect())
 }
 
+/// Put back the document type and extracted data a record had before `revision_id`. The data replaced is
+/// kept as a "restore" revision, so a restore can be undone too.
 #[tauri::command]
-pub fn get_profiles(state: State<AppState>) -> Result<Vec<(i64, String, String, String, String)>, String> {
-    let db = state.db.lock().map_err(|e| e.to_string())?;
-    let db = db.as_ref().ok_or("Database not initialized")?;
-    db.get_profiles()
+pub async fn restore_history_revision(state: State<'_, AppState>, history_id: i64, revision_id: i64) -> Result<(), AppError> {
+    state.ensure_writable()?;
+    with_db_blocking(&state, move |db| {
+        let (_, document_type, extracted_data, ..) = db
+            .get_history_revision(history_id, revision_id)?
+            .ok_or_else(|| AppError::Validation(format!("Revision {} of record {} not found.", revision_id, history_id)))?;
+        let data: Value = serde_json::from_str(&extracted_data).map_err(AppError::db)?;
+        db.revise_history_record(history_id, &document_type, &data, "restore")?;
+        Ok::<_, AppError>(())
+    })
+    .await
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InvoiceEdit {
+    pub id: i64,
+    pub history_id: i64,
+    pub document_type: String,
+    pub field_key: String,
+    pub original_value: Option<String>,
+    pub corrected_value: String,
+    pub created_at: String,
 }
 
+/// Record the fields the user corrected before appending a history record (OCR value and corrected value).
+/// Unchanged fields are skipped. Returns every edit recorded for the record so far.
 #[tauri::command]
-pub fn save_profile(state: State<AppState>, payload: SaveProfilePayload) -> Result<i64, String> {
-    let db = state.db.lock().map_err(|e| e.to_string())?;
-    let db = db.as_ref().ok_or("Database not initialized")?;
-    db.save_profile(
-        payload.id,
-        &payload.name,
-        &payload.excel_path,
-        &payload.sheet_name,
-        &payload.column_mapping,
-    )
+pub fn save_invoice_edits(
+    state: State<AppState>,
+    history_id: i64,
+    edits: Vec<InvoiceEditInput>,
+) -> Result<Vec<InvoiceEdit>, AppError> {
+    state.ensure_writable()?;
+    let db = state.db()?;
+    let (_, document_type, _, extracted_data, _) = db
+        .get_history_by_id(history_id)?
+        .ok_or_else(|| AppError::Validation(format!("History record {} not found.", history_id)))?;
+    let changed: Vec<(String, Option<String>, String)> = edits
+        .into_iter()
+        .filter(|e| !e.field_key.trim().is_empty())
+        .filter(|e| e.original_value.as_deref().unwrap_or("").trim() != e.corrected_value.trim())
+        .map(|e| (e.field_key.trim().to_string(), e.original_value, e.corrected_value))
+        .collect();
+    db.add_invoice_edits(history_id, &document_type, &changed)?;
+    // Learning is best-effort; the edits themselves are already saved.
+    if let Err(e) = vendor_corrections::record(&db, &extracted_data, &changed) {
+        app_warnings::report("vendor_corrections", format!("could not record corrections: {}", e));
+    }
+    get_invoice_edits(state, history_id)
 }
 
+/// Corrections recorded for a history record, oldest first.
 #[tauri::command]
-pub fn delete_profile(state: State<AppState>, id: i64) -> Result<(), String> {
-    let db = state.db.lock().map_err(|e| e.to_string())?;
-    let db = db.as_ref().ok_or("Database not initialized")?;
-    db.delete_profile(id)
+pub fn get_invoice_edits(state: State<AppState>, history_id: i64) -> Result<Vec<InvoiceEdit>, AppError> {
+    let db = state.db()?;
+    Ok(db
+        .get_invoice_edits(history_id)?
+        .into_iter()
+        .map(
+            |(id, history_id, document_type, field_key, original_value, corrected_value, created_at)| InvoiceEdit {
+                id,
+                history_id,
+                document_type,
+                field_key,
+                original_value,
+                corrected_value,
+                created_at,
+            },
+        )
+        .collect())
 }
 
+/// Corrections learned per vendor, optionally for one seller EDB.
 #[tauri::command]
-pub fn get_history(
+pub fn get_vendor_corrections(
     state: State<AppState>,
-    payload: Option<GetHistoryPayload>,
-) -> Result<Vec<(i64, String, String, String, String, String, Option<i64>, Option<String>)>, String>
-{
-    let db = state.db.lock().map_err(|e| e.to_string())?;
-    let db = db.as_ref().ok_or("Database not initialized")?;
-    let search = payload.as_ref().and_then(|p| p.search.clone());
-    let folder_id = payload.as_ref().and_then(|p| p.folder_id);
-    db.get_history(search.as_deref(), folder_id)
+    seller_edb: Option<String>,
+) -> Result<Vec<vendor_corrections::VendorCorrection>, AppError> {
+    let db = state.db()?;
+    Ok(vendor_corrections::list(&db, seller_edb.as_deref())?)
 }
 
+/// Forget a learned vendor correction so it is no longer applied to new scans.
 #[tauri::command]
-pub fn create_folder(state: State<AppState>, name: String) -> Result<i64, String> {
-    let db = state.db.lock().map_err(|e| e.to_string())?;
-    let db = db.as_ref().ok_or("Database not initialized")?;
-    db.create_folder(&name)
+pub fn delete_vendor_correction(state: State<AppState>, seller_edb: String, field_key: String) -> Result<bool, AppError> {
+    state.ensure_writable()?;
+    let db = state.db()?;
+    Ok(vendor_corrections::remove(&db, &seller_edb, &field_key)?)
 }
 
+/// Vendor master data, optionally filtered by name, alias or tax number.
 #[tauri::command]
-pub fn get_folders(state: State<AppState>) -> Result<Vec<(i64, String, String)>, String> {
-    let db = state.db.lock().map_err(|e| e.to_string())?;
-    let db = db.as_ref().ok_or("Database not initialized")?;
-    db.get_folders()
+pub async fn get_vendors(state: State<'_, AppState>, search: Option<String>) -> Result<Vec<vendors::Vendor>, AppError> {
+    with_db_blocking(&state, move |db| vendors::list(db, search.as_deref())).await
 }
 
+/// Create (no id) or update a vendor.
 #[tauri::command]
-pub fn delete_folder(state: State<AppState>, id: i64) -> Result<(), String> {
-    let db = state.db.lock().map_err(|e| e.to_string())?;
-    let db = db.as_ref().ok_or("Database not initialized")?;
-    db.delete_folder(id)
+pub async fn save_vendor(state: State<'_, AppState>, payload: vendors::VendorInput) -> Result<vendors::Vendor, AppError> {
+    state.ensure_writable()?;
+    with_db_blocking(&state, move |db| vendors::save(db, payload)).await
 }
 
 #[tauri::command]
-pub fn assign_history_to_folder(state: State<AppState>, history_id: i64, folder_id: Option<i64>) -> Result<(), String> {
-    let db = state.db.lock().map_err(|e| e.to_string())?;
-    let db = db.as_ref().ok_or("Database not initialized")?;
-    db.assign_history_to_folder(history_id, folder_id)
+pub async fn delete_vendor(state: State<'_, AppState>, id: i64) -> Result<bool, AppError> {
+    state.ensure_writable()?;
+    with_db_blocking(&state, move |db| vendors::remove(db, id)).await
 }
 
+/// Known vendor for a seller name and/or tax number (exact tax number first, then fuzzy name match).
 #[tauri::command]
-pub fn get_history_by_id(
-    state: State<AppState>,
-    id: i64,
-) -> Result<Option<(String, String, String, String, Option<i64>)>, String> {
-    let db = state.db.lock().map_err(|e| e.to_string())?;
-    let db = db.as_ref().ok_or("Database not initialized")?;
-    db.get_history_by_id(id)
+pub async fn resolve_vendor(
+    state: State<'_, AppState>,
+    name: Option<String>,
+    tax_id: Option<String>,
+) -> Result<Option<vendors::Vendor>, AppError> {
+    with_db_blocking(&state, move |db| vendors::resolve(db, name.as_deref(), tax_id.as_deref())).await
 }
 
 #[tauri::command]
-pub fn add_history_record(state: State<AppState>, payload: AddHistoryPayload) -> Result<i64, String> {
-    let db = state.db.lock().map_err(|e| e.to_string())?;
-    let db = db.as_ref().ok_or("Database not initialized")?;
-    db.add_history_record(
+pub fn add_history_record(state: State<AppState>, payload: AddHistoryPayload) -> Result<i64, AppError> {
+    state.ensure_writable()?;
+    let source_path = payload.source_path.filter(|p| !p.trim().is_empty());
+    let file_path = source_path.clone().unwrap_or_else(|| payload.file_path_or_name.clone());
+    if Path::new(&file_path).is_absolute() {
+        ensure_path_allowed(&state, &file_path)?;
+    }
+    let db = state.db()?;
+    let id = db.add_history_record(
         &payload.document_type,
         &payload.file_path_or_name,
         &payload.extracted_data,
@@ -1086,16 +4538,70 @@ pub fn add_history_record(state: State<AppState>, payload: AddHistoryPayload) ->
         payload.excel_profile_id,
         payload.error_message.as_deref(),
         payload.folder_id,
-    )
+    )?;
+    if let Some(path) = &source_path {
+        db.set_history_source_path(id, path)?;
+    }
+    if let Err(e) = document_sets::record_links(&db, id, &payload.extracted_data) {
+        app_warnings::report("document_sets", format!("could not link documents of history {}: {}", id, e));
+    }
+    // Keep the scanned original even if the user deletes the source file later.
+    let app_data_dir = state.app_data_dir();
+    tauri::async_runtime::spawn_blocking(move || {
+        if let Err(e) = archive_store::archive_scanned_original(&db, &app_data_dir, id, &file_path) {
+            app_warnings::report("archive_store", format!("could not archive the original of history {}: {}", id, e));
+        }
+    });
+    Ok(id)
+}
+
+/// Start a scan session for `operator` (else the operator from settings, else the OS login); documents
+/// added to history until it ends are attributed to it.
+#[tauri::command]
+pub fn start_scan_session(
+    state: State<AppState>,
+    operator: Option<String>,
+    note: Option<String>,
+) -> Result<scan_sessions::ScanSession, AppError> {
+    state.ensure_writable()?;
+    let db = state.db()?;
+    Ok(scan_sessions::start(&db, operator.as_deref(), note.as_deref())?)
+}
+
+#[tauri::command]
+pub fn end_scan_session(state: State<AppState>, id: i64) -> Result<scan_sessions::ScanSession, AppError> {
+    state.ensure_writable()?;
+    let db = state.db()?;
+    Ok(scan_sessions::end(&db, id)?)
+}
+
+/// The open session, if any.
+#[tauri::command]
+pub fn get_active_scan_session(state: State<AppState>) -> Result<Option<scan_sessions::ScanSession>, AppError> {
+    let db = state.db()?;
+    Ok(scan_sessions::active(&db)?)
+}
+
+/// Sessions with operator and counts, newest first, for the supervisor's review.
+#[tauri::command]
+pub fn list_scan_sessions(state: State<AppState>, limit: Option<u32>) -> Result<Vec<scan_sessions::ScanSession>, AppError> {
+    let db = state.db()?;
+    Ok(scan_sessions::list(&db, limit.unwrap_or(100))?)
+}
+
+/// Documents linked to a history record (e.g. the delivery note scanned with an invoice), either way.
+#[tauri::command]
+pub fn get_document_links(state: State<AppState>, history_id: i64) -> Result<Vec<document_sets::DocumentLink>, AppError> {
+    let db = state.db()?;
+    Ok(document_sets::links_for(&db, history_id)?)
 }
 
 #[tauri::command]
 pub fn get_learned_mapping(
     state: State<AppState>,
     payload: GetLearnedMappingPayload,
-) -> Result<Option<(String, f64)>, String> {
-    let db = state.db.lock().map_err(|e| e.to_string())?;
-    let db = db.as_ref().ok_or("Database not initialized")?;
+) -> Result<Option<(String, f64)>, AppError> {
+    let db = state.db()?;
     db.get_learned_mapping(&payload.schema_hash, &payload.field_type)
 }
 
@@ -1103,9 +4609,9 @@ pub fn get_learned_mapping(
 pub fn upsert_learned_mapping(
     state: State<AppState>,
     payload: UpsertLearnedMappingPayload,
-) -> Result<(), String> {
-    let db = state.db.lock().map_err(|e| e.to_string())?;
-    let db = db.as_ref().ok_or("Database not initialized")?;
+) -> Result<(), AppError> {
+    state.ensure_writable()?;
+    let db = state.db()?;
     db.upsert_learned_mapping(
         &payload.schema_hash,
         &payload.field_type,
@@ -1115,8 +4621,25 @@ pub fn upsert_learned_mapping(
     )
 }
 
+/// Confidence updates of learned mappings, newest first; narrow to a schema and/or field to see how one
+/// mapping's confidence moved with each accept, edit or reject.
+#[tauri::command]
+pub fn get_learned_mapping_history(
+    state: State<AppState>,
+    schema_hash: Option<String>,
+    field_type: Option<String>,
+    limit: Option<u32>,
+) -> Result<Vec<crate::db::LearnedMappingEventRow>, AppError> {
+    let db = state.db()?;
+    db.get_learned_mapping_history(schema_hash.as_deref(), field_type.as_deref(), limit.unwrap_or(500))
+}
+
 #[tauri::command]
-pub async fn get_column_samples(payload: GetColumnSamplesPayload) -> Result<Vec<Vec<String>>, String> {
+pub async fn get_column_samples(
+    state: State<'_, AppState>,
+    payload: GetColumnSamplesPayload,
+) -> Result<Vec<Vec<String>>, AppError> {
+    ensure_path_allowed(&state, &payload.path)?;
     let path = payload.path.clone();
     let sheet = payload.sheet.clone();
     let header_row = payload.header_row;
@@ -1125,13 +4648,13 @@ pub async fn get_column_samples(payload: GetColumnSamplesPayload) -> Result<Vec<
         excel::read_excel_column_samples(&path, &sheet, header_row, max_rows)
     })
     .await
-    .map_err(|e| e.to_string())?
+    .map_err(AppError::internal)?
 }
 
 #[tauri::command]
-pub fn update_history_status(state: State<AppState>, payload: UpdateHistoryPayload) -> Result<(), String> {
-    let db = state.db.lock().map_err(|e| e.to_string())?;
-    let db = db.as_ref().ok_or("Database not initialized")?;
+pub fn update_history_status(state: State<AppState>, payload: UpdateHistoryPayload) -> Result<(), AppError> {
+    state.ensure_writable()?;
+    let db = state.db()?;
     db.update_history_status(
         payload.id,
         &payload.status,
@@ -1144,9 +4667,9 @@ pub fn update_history_status(state: State<AppState>, payload: UpdateHistoryPaylo
 pub fn update_history_record(
     state: State<AppState>,
     payload: UpdateHistoryRecordPayload,
-) -> Result<(), String> {
-    let db = state.db.lock().map_err(|e| e.to_string())?;
-    let db = db.as_ref().ok_or("Database not initialized")?;
+) -> Result<(), AppError> {
+    state.ensure_writable()?;
+    let db = state.db()?;
     db.update_history_record(
         payload.id,
         &payload.document_type,
@@ -1158,16 +4681,311 @@ pub fn update_history_record(
     )
 }
 
+/// Database backups taken automatically before schema migrations (newest first).
+#[tauri::command]
+pub fn get_migration_backups(state: State<AppState>) -> Result<Vec<MigrationBackupRow>, AppError> {
+    let db = state.db()?;
+    db.get_migration_backups()
+}
+
+/// Month-end checklist for a "YYYY-MM" period.
+#[tauri::command]
+pub async fn get_closing_status(
+    state: State<'_, AppState>,
+    period: String,
+) -> Result<closing_checklist::ClosingStatus, AppError> {
+    with_db_blocking(&state, move |db| closing_checklist::get_closing_status(db, &period)).await
+}
+
+#[tauri::command]
+pub async fn get_closing_steps(state: State<'_, AppState>) -> Result<Vec<closing_checklist::ClosingStep>, AppError> {
+    with_db_blocking(&state, closing_checklist::get_steps).await
+}
+
+/// Choose the checklist steps; None restores all of them.
+#[tauri::command]
+pub async fn set_closing_steps(
+    state: State<'_, AppState>,
+    steps: Option<Vec<closing_checklist::ClosingStep>>,
+) -> Result<Vec<closing_checklist::ClosingStep>, AppError> {
+    state.ensure_writable()?;
+    with_db_blocking(&state, move |db| closing_checklist::set_steps(db, steps)).await
+}
+
+/// Bundle the database, .env settings, secrets, templates and (optionally) archived documents into one
+/// AES-256 encrypted archive at `path`, protected by `password` or, with `recipients` (public keys),
+/// readable only by those recipients. Returns the saved path.
+#[tauri::command]
+pub async fn export_app_state(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    path: String,
+    password: Option<String>,
+    include_documents: Option<bool>,
+    recipients: Option<Vec<String>>,
+) -> Result<String, AppError> {
+    ensure_path_allowed(&state, &path)?;
+    let (password, wrapped_keys) = match (password.filter(|p| !p.is_empty()), recipients.filter(|r| !r.is_empty())) {
+        (Some(password), None) => (password, Vec::new()),
+        (None, Some(recipients)) => bundle_keys::new_bundle_key(&recipients).map_err(AppError::Validation)?,
+        _ => return Err(AppError::Validation("Protect the archive with either a password or recipient keys.".to_string())),
+    };
+    let app_data_dir = app.path().app_data_dir().map_err(AppError::internal)?;
+    let snapshot = temp_store::scratch_path("invoice_scanner_export", "db")?;
+    let snapshot_dest = snapshot.clone();
+    let secrets_dir = app_data_dir.clone();
+    let secrets = with_db_blocking(&state, move |db| {
+        db.snapshot_to(&snapshot_dest)?;
+        let profile_ids: Vec<i64> = db.get_profiles()?.into_iter().map(|(id, ..)| id).collect();
+        let names = app_state_archive::credential_names(&profile_ids);
+        Ok::<_, AppError>(settings::export_secrets(db, &secrets_dir, &names)?)
+    })
+    .await?;
+    let app_version = app.package_info().version.to_string();
+    let archive_path = PathBuf::from(&path);
+    let snapshot_for_task = snapshot.clone();
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        app_state_archive::write_archive(
+            &archive_path,
+            &password,
+            &wrapped_keys,
+            &app_data_dir,
+            &snapshot_for_task,
+            &app_version,
+            include_documents.unwrap_or(false),
+            &secrets,
+        )
+    })
+    .await
+    .map_err(AppError::internal)?;
+    let _ = fs::remove_file(&snapshot);
+    result.map_err(AppError::Io)?;
+    let exported_at = chrono::Utc::now().to_rfc3339();
+    with_db_blocking(&state, move |db| {
+        db.set_settings(&[(app_state_archive::LAST_EXPORT_KEY, Some(exported_at))])
+    })
+    .await?;
+    Ok(path)
+}
+
+/// Replace the current app state with an archive from `export_app_state`. The current database is
+/// kept in backups/pre_import_<ts>.db; profile paths are rewritten to this machine's app data folder and
+/// secrets protected for this machine. The returned manifest lists the credentials to enter again.
+/// Without `password`, the archive must have been encrypted for this installation's bundle key.
+#[tauri::command]
+pub fn import_app_state(
+    app: AppHandle,
+    state: State<AppState>,
+    path: String,
+    password: Option<String>,
+) -> Result<app_state_archive::AppStateManifest, AppError> {
+    state.ensure_writable()?;
+    ensure_path_allowed(&state, &path)?;
+    let app_data_dir = app.path().app_data_dir().map_err(AppError::internal)?;
+    let archive_path = PathBuf::from(&path);
+    let password = match password.filter(|p| !p.is_empty()) {
+        Some(password) => password,
+        None => {
+            let wrapped_keys = app_state_archive::read_wrapped_keys(&archive_path)?;
+            if wrapped_keys.is_empty() {
+                return Err(AppError::Validation("This archive is protected by a password.".to_string()));
+            }
+            bundle_keys::open_bundle_key(&wrapped_keys).map_err(AppError::Validation)?
+        }
+    };
+    // Validates the password before anything on disk is touched.
+    let mut manifest = app_state_archive::read_manifest(&archive_path, &password)?;
+
+    // Close the current connection so the file can be moved. From here on every failure puts the previous
+    // database back and reopens it, so the app is never left without one.
+    state.set_db(None)?;
+    let previous = match app_state_archive::move_current_db_aside(&state.db_path) {
+        Ok(previous) => previous,
+        Err(e) => {
+            state.set_db(Db::new(state.db_path.clone()).ok())?;
+            return Err(AppError::Io(e));
+        }
+    };
+    let imported = app_state_archive::extract_archive(&archive_path, &password, &manifest, &app_data_dir, &state.db_path)
+        .map_err(AppError::Io)
+        .and_then(|_| {
+            let imported = Db::new(state.db_path.clone())?;
+            let new_dir = app_data_dir.to_string_lossy().to_string();
+            if manifest.source_app_data_dir != new_dir {
+                imported.rebase_profile_paths(&manifest.source_app_data_dir, &new_dir)?;
+            }
+            let secrets = app_state_archive::read_secrets(&archive_path, &password).map_err(AppError::Io)?;
+            let reenter = settings::import_secrets(&imported, &app_data_dir, &secrets)?;
+            Ok((imported, reenter))
+        });
+    let imported = match imported {
+        Ok((imported, reenter)) => {
+            manifest.reenter_credentials.extend(reenter);
+            manifest.reenter_credentials.sort();
+            manifest.reenter_credentials.dedup();
+            imported
+        }
+        Err(e) => {
+            if let Err(restore_error) = app_state_archive::restore_db_moved_aside(&state.db_path, previous.as_deref()) {
+                app_warnings::report("import_app_state", restore_error.to_string());
+            }
+            state.set_db(Db::new(state.db_path.clone()).ok())?;
+            return Err(e);
+        }
+    };
+    state.set_db(Some(imported))?;
+    schema_cache::clear_all_cache();
+    let env_path = app_data_dir.join(".env");
+    if env_path.exists() {
+        let _ = dotenvy::from_path_override(&env_path);
+    }
+    Ok(manifest)
+}
+
+/// This installation's bundle public key and the imported recipient keys.
+#[tauri::command]
+pub async fn get_bundle_keys(state: State<'_, AppState>) -> Result<bundle_keys::BundleKeys, AppError> {
+    with_db_blocking(&state, bundle_keys::get_keys).await
+}
+
+/// Generate this installation's bundle key pair; `replace` must be set to overwrite an existing key.
+#[tauri::command]
+pub async fn generate_bundle_key(
+    state: State<'_, AppState>,
+    replace: Option<bool>,
+) -> Result<bundle_keys::BundleKeys, AppError> {
+    state.ensure_writable()?;
+    with_db_blocking(&state, move |db| bundle_keys::generate(db, replace.unwrap_or(false))).await
+}
+
+#[tauri::command]
+pub async fn import_recipient_key(
+    state: State<'_, AppState>,
+    label: String,
+    public_key: String,
+) -> Result<bundle_keys::BundleKeys, AppError> {
+    state.ensure_writable()?;
+    with_db_blocking(&state, move |db| {
+        bundle_keys::import_recipient(db, &label, &public_key).map_err(AppError::Validation)
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn remove_recipient_key(
+    state: State<'_, AppState>,
+    public_key: String,
+) -> Result<bundle_keys::BundleKeys, AppError> {
+    state.ensure_writable()?;
+    with_db_blocking(&state, move |db| bundle_keys::remove_recipient(db, &public_key)).await
+}
+
+/// Why the database failed to open at startup (None when it opened normally).
+#[tauri::command]
+pub fn get_db_recovery_status(state: State<AppState>) -> Result<Option<DbRecoveryStatus>, AppError> {
+    let status = state.db_recovery.lock().map_err(AppError::internal)?;
+    Ok(status.clone())
+}
+
+/// Recover from a failed startup open: "retry", "restore" (latest or given backup) or "rebuild".
+/// Restore and rebuild are only for a database whose integrity check failed. The damaged file is moved
+/// aside first; returns its new path when one was moved. Read-only mode only retries.
+#[tauri::command]
+pub fn recover_database(
+    state: State<AppState>,
+    action: String,
+    backup_path: Option<String>,
+) -> Result<Option<String>, AppError> {
+    if action != "retry" {
+        state.ensure_writable()?;
+    }
+    if let Some(backup_path) = &backup_path {
+        ensure_path_allowed(&state, backup_path)?;
+    }
+    if state.has_db() {
+        return Err(AppError::Validation("Database is already open; recovery is not needed.".to_string()));
+    }
+    if action != "retry" {
+        let corrupted = state
+            .db_recovery
+            .lock()
+            .map_err(AppError::internal)?
+            .as_ref()
+            .is_some_and(|status| status.kind == "corrupted");
+        if !corrupted {
+            return Err(AppError::Validation(
+                "The database is not damaged; close the program holding it and retry instead.".to_string(),
+            ));
+        }
+    }
+    let (recovered, quarantined) = db_recovery::recover_database(&state.db_path, &action, backup_path.as_deref())?;
+    state.set_db(Some(recovered))?;
+    *state.db_recovery.lock().map_err(AppError::internal)? = None;
+    Ok(quarantined.map(|p| p.to_string_lossy().to_string()))
+}
+
 #[tauri::command]
-pub fn clear_learned_mappings(state: State<AppState>) -> Result<u64, String> {
-    let db = state.db.lock().map_err(|e| e.to_string())?;
-    let db = db.as_ref().ok_or("Database not initialized")?;
+pub fn clear_learned_mappings(state: State<AppState>) -> Result<u64, AppError> {
+    state.ensure_writable()?;
+    let db = state.db()?;
     db.clear_learned_mappings()
 }
 
+/// Moves the record to the trash (see `get_trash`, `restore_history_record`, `purge_trash`).
 #[tauri::command]
-pub fn delete_history_record(state: State<AppState>, id: i64) -> Result<(), String> {
-    let db = state.db.lock().map_err(|e| e.to_string())?;
-    let db = db.as_ref().ok_or("Database not initialized")?;
+pub fn delete_history_record(state: State<AppState>, id: i64) -> Result<(), AppError> {
+    state.ensure_writable()?;
+    let db = state.db()?;
     db.delete_history_record(id)
 }
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrashedHistoryRecord {
+    pub id: i64,
+    pub created_at: String,
+    pub document_type: String,
+    pub file_path_or_name: String,
+    pub status: String,
+    pub excel_profile_id: Option<i64>,
+    pub deleted_at: String,
+}
+
+/// Deleted history records, most recently deleted first.
+#[tauri::command]
+pub async fn get_trash(state: State<'_, AppState>) -> Result<Vec<TrashedHistoryRecord>, AppError> {
+    let rows = with_db_blocking(&state, |db| db.get_trash()).await?;
+    Ok(rows
+        .into_iter()
+        .map(|(id, created_at, document_type, file_path_or_name, status, excel_profile_id, deleted_at)| {
+            TrashedHistoryRecord { id, created_at, document_type, file_path_or_name, status, excel_profile_id, deleted_at }
+        })
+        .collect())
+}
+
+#[tauri::command]
+pub fn restore_history_record(state: State<AppState>, id: i64) -> Result<(), AppError> {
+    state.ensure_writable()?;
+    let db = state.db()?;
+    if !db.restore_history_record(id)? {
+        return Err(AppError::Validation(format!("History record {} is not in the trash.", id)));
+    }
+    Ok(())
+}
+
+/// Permanently delete records that have been in the trash longer than `older_than_days` (all of them when
+/// omitted or 0). Returns how many were removed.
+#[tauri::command]
+pub async fn purge_trash(state: State<'_, AppState>, older_than_days: Option<u32>) -> Result<usize, AppError> {
+    state.ensure_writable()?;
+    let cutoff = older_than_days
+        .filter(|days| *days > 0)
+        .map(|days| (chrono::Utc::now() - chrono::Duration::days(days as i64)).to_rfc3339());
+    let app_data_dir = state.app_data_dir();
+    with_db_blocking(&state, move |db| {
+        let (removed, orphaned) = db.purge_trash(cutoff.as_deref())?;
+        archive_store::delete_archived_objects(db, &app_data_dir, &orphaned);
+        Ok::<_, AppError>(removed)
+    })
+    .await
+}
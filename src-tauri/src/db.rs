@@ -1,6 +1,8 @@
+use crate::error::AppError;
 use crate::models::{ExcelSchema, HeaderInfo};
 use crate::excel;
 use crate::services::excel_scanner;
+use crate::types::CellWrite;
 use rusqlite::{params, Connection};
 use serde_json::Value;
 use std::collections::HashMap;
@@ -9,16 +11,34 @@ use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Mutex;
 
+/// Latest schema version; bump together with each new migration block in `Db::new`.
+const SCHEMA_VERSION: i64 = 33;
+
 pub struct Db {
     conn: Mutex<Connection>,
 }
 
+/// Copy the database file to `backups/pre_migration_v{N}_{timestamp}.db` next to it, where N is the
+/// first migration about to run. Called before any migration touches the schema.
+fn backup_before_migration(db_path: &Path, next_version: i64) -> Result<PathBuf, AppError> {
+    let backups_dir = db_path
+        .parent()
+        .ok_or(AppError::Db("Database path has no parent directory".to_string()))?
+        .join("backups");
+    fs::create_dir_all(&backups_dir).map_err(|e| AppError::Io(format!("Could not create backups folder: {}", e)))?;
+    let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S");
+    let backup_path = backups_dir.join(format!("pre_migration_v{}_{}.db", next_version, timestamp));
+    fs::copy(db_path, &backup_path).map_err(|e| AppError::Io(format!("Could not back up database before migration: {}", e)))?;
+    Ok(backup_path)
+}
+
 impl Db {
-    pub fn new(db_path: PathBuf) -> Result<Self, String> {
+    pub fn new(db_path: PathBuf) -> Result<Self, AppError> {
         if let Some(parent) = db_path.parent() {
-            std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+            std::fs::create_dir_all(parent).map_err(AppError::db)?;
         }
-        let conn = Connection::open(&db_path).map_err(|e| e.to_string())?;
+        let existed = db_path.exists();
+        let conn = Connection::open(&db_path).map_err(AppError::db)?;
         conn.execute_batch(
             "
             CREATE TABLE IF NOT EXISTS schema_version (
@@ -56,7 +76,7 @@ impl Db {
             );
             ",
         )
-        .map_err(|e| e.to_string())?;
+        .map_err(AppError::db)?;
 
         // Normalize schema_version to a single row (fixes DBs that had two rows from old INSERT OR IGNORE)
         let _ = conn.execute(
@@ -64,10 +84,38 @@ impl Db {
             [],
         );
 
-        // Migration 002: profile-centric excel schema cache (run once when version < 2)
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS migration_backups (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                from_version INTEGER NOT NULL,
+                to_version INTEGER NOT NULL,
+                backup_path TEXT NOT NULL,
+                created_at TEXT NOT NULL
+            )",
+            [],
+        )
+        .map_err(AppError::db)?;
+
+        // Back up an existing database once before any pending migration runs, so a failed or
+        // destructive migration (e.g. 002 drops excel_schemas) can be rolled back by hand.
         let current_version: i64 = conn
             .query_row("SELECT version FROM schema_version LIMIT 1", [], |r| r.get(0))
             .unwrap_or(1);
+        if existed && current_version < SCHEMA_VERSION {
+            let backup_path = backup_before_migration(&db_path, current_version + 1)?;
+            conn.execute(
+                "INSERT INTO migration_backups (from_version, to_version, backup_path, created_at) VALUES (?, ?, ?, ?)",
+                params![
+                    current_version,
+                    SCHEMA_VERSION,
+                    backup_path.to_string_lossy(),
+                    chrono::Utc::now().to_rfc3339()
+                ],
+            )
+            .map_err(AppError::db)?;
+        }
+
+        // Migration 002: profile-centric excel schema cache (run once when version < 2)
         if current_version < 2 {
             conn.execute_batch(
                 "
@@ -132,7 +180,7 @@ impl Db {
                 CREATE INDEX IF NOT EXISTS idx_cache_changes_profile ON cache_changes(profile_id);
                 ",
             )
-            .map_err(|e| e.to_string())?;
+            .map_err(AppError::db)?;
             for alter_sql in &[
                 "ALTER TABLE profiles ADD COLUMN file_size INTEGER",
                 "ALTER TABLE profiles ADD COLUMN file_mtime INTEGER",
@@ -140,12 +188,12 @@ impl Db {
             ] {
                 if let Err(e) = conn.execute(alter_sql, []) {
                     if !e.to_string().contains("duplicate column") {
-                        return Err(e.to_string());
+                        return Err(AppError::db(e));
                     }
                 }
             }
             conn.execute("UPDATE schema_version SET version = 2", [])
-                .map_err(|e| e.to_string())?;
+                .map_err(AppError::db)?;
         }
 
         // Migration 003: folders table and folder_id on history (run once when version < 3)
@@ -161,14 +209,528 @@ impl Db {
                 )",
                 [],
             )
-            .map_err(|e| e.to_string())?;
+            .map_err(AppError::db)?;
             if let Err(e) = conn.execute("ALTER TABLE history ADD COLUMN folder_id INTEGER REFERENCES folders(id)", []) {
                 if !e.to_string().contains("duplicate column") {
-                    return Err(e.to_string());
+                    return Err(AppError::db(e));
                 }
             }
             conn.execute("UPDATE schema_version SET version = 3", [])
-                .map_err(|e| e.to_string())?;
+                .map_err(AppError::db)?;
+        }
+
+        // Migration 004: spending categories and category_id on history (run once when version < 4)
+        let current_version: i64 = conn
+            .query_row("SELECT version FROM schema_version LIMIT 1", [], |r| r.get(0))
+            .unwrap_or(1);
+        if current_version < 4 {
+            conn.execute(
+                "CREATE TABLE IF NOT EXISTS categories (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    name TEXT NOT NULL UNIQUE,
+                    monthly_budget REAL,
+                    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+                )",
+                [],
+            )
+            .map_err(AppError::db)?;
+            if let Err(e) = conn.execute("ALTER TABLE history ADD COLUMN category_id INTEGER REFERENCES categories(id)", []) {
+                if !e.to_string().contains("duplicate column") {
+                    return Err(AppError::db(e));
+                }
+            }
+            conn.execute("UPDATE schema_version SET version = 4", [])
+                .map_err(AppError::db)?;
+        }
+
+        // Migration 005: parent_id on history so batch exports group their per-invoice records
+        let current_version: i64 = conn
+            .query_row("SELECT version FROM schema_version LIMIT 1", [], |r| r.get(0))
+            .unwrap_or(1);
+        if current_version < 5 {
+            if let Err(e) = conn.execute("ALTER TABLE history ADD COLUMN parent_id INTEGER REFERENCES history(id)", []) {
+                if !e.to_string().contains("duplicate column") {
+                    return Err(AppError::db(e));
+                }
+            }
+            conn.execute("CREATE INDEX IF NOT EXISTS idx_history_parent_id ON history(parent_id)", [])
+                .map_err(AppError::db)?;
+            conn.execute("UPDATE schema_version SET version = 5", [])
+                .map_err(AppError::db)?;
+        }
+
+        // Migration 006: cell-level write log for appended / overwritten Excel data
+        let current_version: i64 = conn
+            .query_row("SELECT version FROM schema_version LIMIT 1", [], |r| r.get(0))
+            .unwrap_or(1);
+        if current_version < 6 {
+            conn.execute_batch(
+                "CREATE TABLE IF NOT EXISTS write_log (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    created_at TEXT NOT NULL,
+                    file_path TEXT NOT NULL,
+                    sheet_name TEXT NOT NULL,
+                    cell_ref TEXT NOT NULL,
+                    row_number INTEGER NOT NULL,
+                    old_value TEXT,
+                    new_value TEXT NOT NULL,
+                    history_id INTEGER REFERENCES history(id)
+                );
+                CREATE INDEX IF NOT EXISTS idx_write_log_file_row ON write_log(file_path, sheet_name, row_number);
+                CREATE INDEX IF NOT EXISTS idx_write_log_history ON write_log(history_id);
+                UPDATE schema_version SET version = 6;",
+            )
+            .map_err(AppError::db)?;
+        }
+
+        if current_version < 7 {
+            conn.execute_batch(
+                "CREATE TABLE IF NOT EXISTS settings (
+                    key TEXT PRIMARY KEY,
+                    value TEXT NOT NULL,
+                    updated_at TEXT NOT NULL
+                );
+                UPDATE schema_version SET version = 7;",
+            )
+            .map_err(AppError::db)?;
+        }
+
+        if current_version < 8 {
+            conn.execute_batch(
+                "CREATE TABLE IF NOT EXISTS archived_documents (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    history_id INTEGER NOT NULL REFERENCES history(id),
+                    backend TEXT NOT NULL,
+                    location TEXT NOT NULL,
+                    object_key TEXT NOT NULL,
+                    file_name TEXT NOT NULL,
+                    size_bytes INTEGER NOT NULL,
+                    created_at TEXT NOT NULL
+                );
+                CREATE INDEX IF NOT EXISTS idx_archived_documents_history ON archived_documents(history_id);
+                UPDATE schema_version SET version = 8;",
+            )
+            .map_err(AppError::db)?;
+        }
+        if current_version < 9 {
+            conn.execute_batch(
+                "CREATE TABLE IF NOT EXISTS export_deliveries (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    source_path TEXT NOT NULL,
+                    destination TEXT NOT NULL,
+                    sha256 TEXT NOT NULL,
+                    size_bytes INTEGER NOT NULL,
+                    status TEXT NOT NULL,
+                    attempts INTEGER NOT NULL,
+                    error TEXT,
+                    created_at TEXT NOT NULL
+                );
+                UPDATE schema_version SET version = 9;",
+            )
+            .map_err(AppError::db)?;
+        }
+        if current_version < 10 {
+            conn.execute_batch(
+                "CREATE TABLE IF NOT EXISTS profile_delivery_targets (
+                    profile_id INTEGER PRIMARY KEY REFERENCES profiles(id),
+                    kind TEXT NOT NULL,
+                    share_path TEXT,
+                    sftp_host TEXT,
+                    sftp_port INTEGER,
+                    sftp_username TEXT,
+                    sftp_directory TEXT,
+                    sftp_host_fingerprint TEXT,
+                    updated_at TEXT NOT NULL
+                );
+                ALTER TABLE export_deliveries ADD COLUMN profile_id INTEGER;
+                UPDATE schema_version SET version = 10;",
+            )
+            .map_err(AppError::db)?;
+        }
+        if current_version < 11 {
+            // History is listed newest first and paged; without this every page sorts the whole table.
+            conn.execute_batch(
+                "CREATE INDEX IF NOT EXISTS idx_history_created_at ON history(created_at);
+                UPDATE schema_version SET version = 11;",
+            )
+            .map_err(AppError::db)?;
+        }
+        if current_version < 12 {
+            // Full-text index over the file name and the text values of extracted_data, kept in sync by triggers.
+            conn.execute_batch(&format!(
+                "CREATE VIRTUAL TABLE IF NOT EXISTS history_fts USING fts5(
+                    file_name, content, tokenize = 'unicode61 remove_diacritics 2'
+                );
+                CREATE TRIGGER IF NOT EXISTS history_fts_insert AFTER INSERT ON history BEGIN
+                    INSERT INTO history_fts(rowid, file_name, content)
+                    VALUES (NEW.id, NEW.file_path_or_name, {new});
+                END;
+                CREATE TRIGGER IF NOT EXISTS history_fts_update AFTER UPDATE OF file_path_or_name, extracted_data ON history BEGIN
+                    DELETE FROM history_fts WHERE rowid = OLD.id;
+                    INSERT INTO history_fts(rowid, file_name, content)
+                    VALUES (NEW.id, NEW.file_path_or_name, {new});
+                END;
+                CREATE TRIGGER IF NOT EXISTS history_fts_delete AFTER DELETE ON history BEGIN
+                    DELETE FROM history_fts WHERE rowid = OLD.id;
+                END;
+                INSERT INTO history_fts(rowid, file_name, content)
+                    SELECT id, file_path_or_name, {existing} FROM history;
+                UPDATE schema_version SET version = 12;",
+                new = fts_text_sql("NEW.extracted_data"),
+                existing = fts_text_sql("history.extracted_data"),
+            ))
+            .map_err(AppError::db)?;
+        }
+        if current_version < 13 {
+            conn.execute_batch(
+                "CREATE TABLE IF NOT EXISTS ledger_snapshots (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    profile_id INTEGER NOT NULL,
+                    file_path TEXT NOT NULL,
+                    sha256 TEXT NOT NULL,
+                    size_bytes INTEGER NOT NULL,
+                    reason TEXT NOT NULL,
+                    created_at TEXT NOT NULL
+                );
+                CREATE INDEX IF NOT EXISTS idx_ledger_snapshots_profile ON ledger_snapshots(profile_id);
+                CREATE TABLE IF NOT EXISTS ledger_snapshot_policies (
+                    profile_id INTEGER PRIMARY KEY REFERENCES profiles(id),
+                    every_appends INTEGER,
+                    daily INTEGER NOT NULL DEFAULT 0,
+                    appends_since_snapshot INTEGER NOT NULL DEFAULT 0
+                );
+                UPDATE schema_version SET version = 13;",
+            )
+            .map_err(AppError::db)?;
+        }
+        if current_version < 14 {
+            // Corrections made by hand to OCR values, per history record and field.
+            conn.execute_batch(
+                "CREATE TABLE IF NOT EXISTS invoice_edits (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    history_id INTEGER NOT NULL,
+                    document_type TEXT NOT NULL,
+                    field_key TEXT NOT NULL,
+                    original_value TEXT,
+                    corrected_value TEXT NOT NULL,
+                    created_at TEXT NOT NULL
+                );
+                CREATE INDEX IF NOT EXISTS idx_invoice_edits_history ON invoice_edits(history_id);
+                CREATE INDEX IF NOT EXISTS idx_invoice_edits_field ON invoice_edits(document_type, field_key);
+                UPDATE schema_version SET version = 14;",
+            )
+            .map_err(AppError::db)?;
+        }
+        if current_version < 15 {
+            // Per-vendor corrections learned from invoice_edits, keyed by the seller's normalized EDB.
+            conn.execute_batch(
+                "CREATE TABLE IF NOT EXISTS vendor_corrections (
+                    seller_edb TEXT NOT NULL,
+                    field_key TEXT NOT NULL,
+                    original_value TEXT,
+                    corrected_value TEXT NOT NULL,
+                    correction_count INTEGER NOT NULL DEFAULT 1,
+                    applied_count INTEGER NOT NULL DEFAULT 0,
+                    updated_at TEXT NOT NULL,
+                    PRIMARY KEY (seller_edb, field_key)
+                );
+                UPDATE schema_version SET version = 15;",
+            )
+            .map_err(AppError::db)?;
+        }
+        if current_version < 16 {
+            // Composite uniqueness key per profile (JSON array of field keys) and what appends do with duplicates.
+            conn.execute_batch(
+                "CREATE TABLE IF NOT EXISTS profile_duplicate_keys (
+                    profile_id INTEGER PRIMARY KEY REFERENCES profiles(id),
+                    fields TEXT NOT NULL,
+                    on_duplicate TEXT NOT NULL DEFAULT 'append'
+                );
+                UPDATE schema_version SET version = 16;",
+            )
+            .map_err(AppError::db)?;
+        }
+        if current_version < 17 {
+            // Vendor master data; `aliases` is a JSON array of other spellings seen on invoices.
+            conn.execute_batch(
+                "CREATE TABLE IF NOT EXISTS vendors (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    name TEXT NOT NULL,
+                    tax_id TEXT,
+                    address TEXT,
+                    default_currency TEXT,
+                    aliases TEXT NOT NULL DEFAULT '[]',
+                    created_at TEXT NOT NULL,
+                    updated_at TEXT NOT NULL
+                );
+                CREATE INDEX IF NOT EXISTS idx_vendors_tax_id ON vendors(tax_id);
+                UPDATE schema_version SET version = 17;",
+            )
+            .map_err(AppError::db)?;
+        }
+        if current_version < 18 {
+            // Azure field name -> app field key per analyzer, on top of the built-in map in ocr.rs.
+            conn.execute_batch(
+                "CREATE TABLE IF NOT EXISTS model_field_mappings (
+                    model_id TEXT NOT NULL,
+                    azure_key TEXT NOT NULL,
+                    field_key TEXT NOT NULL,
+                    PRIMARY KEY (model_id, azure_key)
+                );
+                UPDATE schema_version SET version = 18;",
+            )
+            .map_err(AppError::db)?;
+        }
+        if current_version < 19 {
+            // Documents scanned together with a history record (e.g. the delivery note stapled to an invoice).
+            conn.execute_batch(
+                "CREATE TABLE IF NOT EXISTS document_links (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    history_id INTEGER NOT NULL,
+                    link_type TEXT NOT NULL,
+                    linked_number TEXT NOT NULL,
+                    linked_history_id INTEGER,
+                    created_at TEXT NOT NULL
+                );
+                CREATE INDEX IF NOT EXISTS idx_document_links_history ON document_links(history_id);
+                CREATE INDEX IF NOT EXISTS idx_document_links_linked ON document_links(linked_history_id);
+                UPDATE schema_version SET version = 19;",
+            )
+            .map_err(AppError::db)?;
+        }
+        if current_version < 20 {
+            // Azure analyzer registered per document type (overrides the AZURE_CU_ANALYZER_* defaults).
+            conn.execute_batch(
+                "CREATE TABLE IF NOT EXISTS document_models (
+                    document_type TEXT PRIMARY KEY,
+                    model_id TEXT NOT NULL,
+                    label TEXT,
+                    updated_at TEXT NOT NULL
+                );
+                UPDATE schema_version SET version = 20;",
+            )
+            .map_err(AppError::db)?;
+        }
+        if current_version < 21 {
+            // Field schema per document type (JSON list of field definitions); built-in defaults when absent.
+            conn.execute_batch(
+                "CREATE TABLE IF NOT EXISTS field_schemas (
+                    document_type TEXT PRIMARY KEY,
+                    fields TEXT NOT NULL,
+                    updated_at TEXT NOT NULL
+                );
+                UPDATE schema_version SET version = 21;",
+            )
+            .map_err(AppError::db)?;
+        }
+        if current_version < 22 {
+            // Document type registry; takes over the model IDs of document_models.
+            conn.execute_batch(
+                "CREATE TABLE IF NOT EXISTS document_types (
+                    key TEXT PRIMARY KEY,
+                    display_name TEXT NOT NULL,
+                    model_id TEXT,
+                    extractor TEXT NOT NULL DEFAULT 'fields',
+                    default_folder TEXT,
+                    export_layout TEXT NOT NULL DEFAULT 'standard',
+                    builtin INTEGER NOT NULL DEFAULT 0,
+                    updated_at TEXT NOT NULL
+                );
+                INSERT OR IGNORE INTO document_types (key, display_name, extractor, builtin, updated_at) VALUES
+                    ('faktura', 'Invoices', 'fields', 1, datetime('now')),
+                    ('smetka', 'Даночен Биланс', 'tax_balance', 1, datetime('now')),
+                    ('generic', 'ДДВ', 'vat_return', 1, datetime('now')),
+                    ('plata', 'Плати', 'payroll', 1, datetime('now'));
+                INSERT INTO document_types (key, display_name, model_id, updated_at)
+                    SELECT document_type, COALESCE(label, document_type), model_id, updated_at FROM document_models WHERE true
+                    ON CONFLICT(key) DO UPDATE SET model_id = excluded.model_id;
+                DROP TABLE IF EXISTS document_models;
+                UPDATE schema_version SET version = 22;",
+            )
+            .map_err(AppError::db)?;
+        }
+        if current_version < 23 {
+            // Ledger rows that could not be appended yet (workbook locked, missing or offline).
+            conn.execute_batch(
+                "CREATE TABLE IF NOT EXISTS pending_appends (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    profile_id INTEGER NOT NULL REFERENCES profiles(id),
+                    invoice_data TEXT NOT NULL,
+                    history_id INTEGER,
+                    reason TEXT NOT NULL,
+                    last_error TEXT,
+                    attempts INTEGER NOT NULL DEFAULT 0,
+                    created_at TEXT NOT NULL,
+                    last_attempt_at TEXT
+                );
+                CREATE INDEX IF NOT EXISTS idx_pending_appends_profile ON pending_appends(profile_id);
+                UPDATE schema_version SET version = 23;",
+            )
+            .map_err(AppError::db)?;
+        }
+        if current_version < 24 {
+            // Scan sessions: who processed which bundle; history records point at the open session.
+            conn.execute_batch(
+                "CREATE TABLE IF NOT EXISTS scan_sessions (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    operator TEXT NOT NULL,
+                    note TEXT,
+                    started_at TEXT NOT NULL,
+                    ended_at TEXT
+                );
+                ALTER TABLE history ADD COLUMN session_id INTEGER REFERENCES scan_sessions(id);
+                CREATE INDEX IF NOT EXISTS idx_history_session ON history(session_id);
+                UPDATE schema_version SET version = 24;",
+            )
+            .map_err(AppError::db)?;
+        }
+        if current_version < 25 {
+            // One row per learned-mapping update, so the decay and reward constants can be tuned from real use.
+            conn.execute_batch(
+                "CREATE TABLE IF NOT EXISTS learned_mapping_events (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    schema_hash TEXT NOT NULL,
+                    field_type TEXT NOT NULL,
+                    column_letter TEXT NOT NULL,
+                    action TEXT NOT NULL,
+                    reward REAL NOT NULL,
+                    previous_confidence REAL,
+                    effective_before REAL,
+                    confidence REAL NOT NULL,
+                    usage_count INTEGER NOT NULL,
+                    created_at TEXT NOT NULL
+                );
+                CREATE INDEX IF NOT EXISTS idx_learned_mapping_events_key ON learned_mapping_events(schema_hash, field_type, id);
+                UPDATE schema_version SET version = 25;",
+            )
+            .map_err(AppError::db)?;
+        }
+        if current_version < 26 {
+            // What each batch append wrote and with which one-off column overrides.
+            conn.execute_batch(
+                "CREATE TABLE IF NOT EXISTS export_manifests (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    profile_id INTEGER NOT NULL REFERENCES profiles(id),
+                    file_path TEXT NOT NULL,
+                    sheet_name TEXT NOT NULL,
+                    rows TEXT NOT NULL,
+                    history_ids TEXT NOT NULL,
+                    column_overrides TEXT,
+                    created_at TEXT NOT NULL
+                );
+                CREATE INDEX IF NOT EXISTS idx_export_manifests_profile ON export_manifests(profile_id);
+                UPDATE schema_version SET version = 26;",
+            )
+            .map_err(AppError::db)?;
+        }
+        if current_version < 27 {
+            // Webhook POSTs after scans and appends, with their attempts (see services/webhooks.rs).
+            conn.execute_batch(
+                "CREATE TABLE IF NOT EXISTS webhook_deliveries (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    event TEXT NOT NULL,
+                    url TEXT NOT NULL,
+                    profile_id INTEGER,
+                    payload TEXT NOT NULL,
+                    status TEXT NOT NULL,
+                    attempts INTEGER NOT NULL,
+                    response_status INTEGER,
+                    error TEXT,
+                    created_at TEXT NOT NULL,
+                    updated_at TEXT NOT NULL
+                );
+                UPDATE schema_version SET version = 27;",
+            )
+            .map_err(AppError::db)?;
+        }
+        if current_version < 28 {
+            // PDF attachments downloaded from the intake mailbox, waiting to be scanned (see
+            // services/email_intake.rs). One row per attachment of a message.
+            conn.execute_batch(
+                "CREATE TABLE IF NOT EXISTS email_intake (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    mailbox TEXT NOT NULL,
+                    uid_validity INTEGER NOT NULL,
+                    uid INTEGER NOT NULL,
+                    part INTEGER NOT NULL,
+                    sender TEXT,
+                    subject TEXT,
+                    received_at TEXT,
+                    file_name TEXT NOT NULL,
+                    file_path TEXT NOT NULL,
+                    status TEXT NOT NULL,
+                    created_at TEXT NOT NULL,
+                    UNIQUE(mailbox, uid_validity, uid, part)
+                );
+                UPDATE schema_version SET version = 28;",
+            )
+            .map_err(AppError::db)?;
+        }
+        if current_version < 29 {
+            // Content hash and location of the archived original (see services/archive_store.rs).
+            conn.execute_batch(
+                "ALTER TABLE history ADD COLUMN archive_sha256 TEXT;
+                ALTER TABLE history ADD COLUMN archive_path TEXT;
+                CREATE INDEX IF NOT EXISTS idx_history_archive_sha256 ON history(archive_sha256);
+                UPDATE schema_version SET version = 29;",
+            )
+            .map_err(AppError::db)?;
+        }
+        if current_version < 30 {
+            // Full path of the scanned file (file_path_or_name is often just the name) and the earlier
+            // extracted data of records that were scanned again.
+            conn.execute_batch(
+                "ALTER TABLE history ADD COLUMN source_path TEXT;
+                CREATE TABLE IF NOT EXISTS history_revisions (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    history_id INTEGER NOT NULL REFERENCES history(id),
+                    document_type TEXT NOT NULL,
+                    extracted_data TEXT NOT NULL,
+                    reason TEXT NOT NULL,
+                    created_at TEXT NOT NULL
+                );
+                CREATE INDEX IF NOT EXISTS idx_history_revisions_history ON history_revisions(history_id);
+                UPDATE schema_version SET version = 30;",
+            )
+            .map_err(AppError::db)?;
+        }
+        if current_version < 31 {
+            // Every edit of a record's extracted data is a revision; `diff` lists the changed keys.
+            conn.execute_batch(
+                "ALTER TABLE history_revisions ADD COLUMN diff TEXT NOT NULL DEFAULT '[]';
+                UPDATE schema_version SET version = 31;",
+            )
+            .map_err(AppError::db)?;
+        }
+        if current_version < 32 {
+            // Deleting a record moves it to the trash; `purge_trash` removes it for good.
+            conn.execute_batch(
+                "ALTER TABLE history ADD COLUMN deleted_at TEXT;
+                CREATE INDEX IF NOT EXISTS idx_history_deleted_at ON history(deleted_at);
+                UPDATE schema_version SET version = 32;",
+            )
+            .map_err(AppError::db)?;
+        }
+        if current_version < 33 {
+            // Folders nest (year → month → client); names are unique among siblings instead of globally,
+            // which needs the table rebuilt to drop the old UNIQUE constraint. One transaction, so a crash
+            // midway leaves the old table in place and the migration runs again.
+            conn.execute_batch(
+                "BEGIN;
+                CREATE TABLE folders_nested (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    name TEXT NOT NULL,
+                    parent_id INTEGER REFERENCES folders(id),
+                    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+                );
+                INSERT INTO folders_nested (id, name, parent_id, created_at) SELECT id, name, NULL, created_at FROM folders;
+                DROP TABLE folders;
+                ALTER TABLE folders_nested RENAME TO folders;
+                CREATE UNIQUE INDEX IF NOT EXISTS idx_folders_parent_name ON folders(IFNULL(parent_id, 0), name);
+                UPDATE schema_version SET version = 33;
+                COMMIT;",
+            )
+            .map_err(AppError::db)?;
         }
 
         let db = Db {
@@ -179,8 +741,59 @@ impl Db {
         Ok(db)
     }
 
+    /// Backups taken before migrations, newest first.
+    pub fn get_migration_backups(&self) -> Result<Vec<MigrationBackupRow>, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let mut stmt = conn
+            .prepare("SELECT id, from_version, to_version, backup_path, created_at FROM migration_backups ORDER BY id DESC")
+            .map_err(AppError::db)?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?)))
+            .map_err(AppError::db)?;
+        let mut out = Vec::new();
+        for row in rows {
+            out.push(row.map_err(AppError::db)?);
+        }
+        Ok(out)
+    }
+
+    /// Write a consistent copy of the live database to `dest` (must not exist yet).
+    pub fn snapshot_to(&self, dest: &Path) -> Result<(), AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        conn.execute("VACUUM INTO ?1", params![dest.to_string_lossy()])
+            .map_err(|e| AppError::Db(format!("Could not snapshot database: {}", e)))?;
+        Ok(())
+    }
+
+    /// Rewrite profile Excel paths that live under `old_prefix` (e.g. the app data folder of another machine).
+    pub fn rebase_profile_paths(&self, old_prefix: &str, new_prefix: &str) -> Result<usize, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let mut stmt = conn
+            .prepare("SELECT id, excel_path FROM profiles")
+            .map_err(AppError::db)?;
+        let rows: Vec<(i64, String)> = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(AppError::db)?
+            .filter_map(|r| r.ok())
+            .collect();
+        let mut updated = 0;
+        for (id, path) in rows {
+            if let Some(rest) = path.strip_prefix(old_prefix) {
+                let rest = rest.trim_start_matches(['/', '\\']);
+                let new_path = Path::new(new_prefix).join(rest);
+                conn.execute(
+                    "UPDATE profiles SET excel_path = ? WHERE id = ?",
+                    params![new_path.to_string_lossy(), id],
+                )
+                .map_err(AppError::db)?;
+                updated += 1;
+            }
+        }
+        Ok(updated)
+    }
+
     /// Path-based schema cache removed in migration 003; returns None so frontend falls back to analyze_excel_schema.
-    pub fn get_cached_schema(&self, _cache_key: &str) -> Result<Option<String>, String> {
+    pub fn get_cached_schema(&self, _cache_key: &str) -> Result<Option<String>, AppError> {
         Ok(None)
     }
 
@@ -193,7 +806,7 @@ impl Db {
         _worksheet_name: &str,
         _schema_json: &str,
         _last_modified: &str,
-    ) -> Result<(), String> {
+    ) -> Result<(), AppError> {
         Ok(())
     }
 
@@ -201,23 +814,23 @@ impl Db {
     pub fn get_profile_by_id(
         &self,
         id: i64,
-    ) -> Result<(String, String, String), String> {
-        let conn = self.conn.lock().map_err(|e| e.to_string())?;
+    ) -> Result<(String, String, String), AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
         let (excel_path, sheet_name, column_mapping): (String, String, String) = conn
             .query_row(
                 "SELECT excel_path, sheet_name, column_mapping FROM profiles WHERE id = ?",
                 params![id],
                 |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
             )
-            .map_err(|e| format!("Profile not found: {}", e))?;
+            .map_err(|e| AppError::Db(format!("Profile not found: {}", e)))?;
         Ok((excel_path, sheet_name, column_mapping))
     }
 
     /// Save full excel schema for a profile (replaces existing).
-    pub fn save_excel_schema(&self, profile_id: i64, schema: &ExcelSchema) -> Result<(), String> {
-        let conn = self.conn.lock().map_err(|e| e.to_string())?;
+    pub fn save_excel_schema(&self, profile_id: i64, schema: &ExcelSchema) -> Result<(), AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
         let headers_json =
-            serde_json::to_string(&schema.headers).map_err(|e| format!("Serialize headers: {}", e))?;
+            serde_json::to_string(&schema.headers).map_err(|e| AppError::Db(format!("Serialize headers: {}", e)))?;
         conn.execute(
             "INSERT OR REPLACE INTO excel_schemas
              (profile_id, header_row, first_data_row, last_data_row, next_free_row,
@@ -236,10 +849,10 @@ impl Db {
                 schema.file_mtime as i64,
             ],
         )
-        .map_err(|e| format!("Failed to save excel_schemas: {}", e))?;
+        .map_err(|e| AppError::Db(format!("Failed to save excel_schemas: {}", e)))?;
 
         conn.execute("DELETE FROM column_formats WHERE profile_id = ?1", params![profile_id])
-            .map_err(|e| format!("Failed to delete old column_formats: {}", e))?;
+            .map_err(|e| AppError::Db(format!("Failed to delete old column_formats: {}", e)))?;
 
         for col in &schema.columns {
             conn.execute(
@@ -270,7 +883,7 @@ impl Db {
                     col.column_width,
                 ],
             )
-            .map_err(|e| format!("Failed to save column_format: {}", e))?;
+            .map_err(|e| AppError::Db(format!("Failed to save column_format: {}", e)))?;
         }
 
         conn.execute(
@@ -284,22 +897,22 @@ impl Db {
                 schema.row_template.use_alternating_colors as i32,
             ],
         )
-        .map_err(|e| format!("Failed to save row_template: {}", e))?;
+        .map_err(|e| AppError::Db(format!("Failed to save row_template: {}", e)))?;
 
         conn.execute(
             "UPDATE profiles SET file_size = ?1, file_mtime = ?2, last_scanned_at = datetime('now') WHERE id = ?3",
             params![schema.file_size as i64, schema.file_mtime as i64, profile_id],
         )
-        .map_err(|e| format!("Failed to update profile: {}", e))?;
+        .map_err(|e| AppError::Db(format!("Failed to update profile: {}", e)))?;
 
         Ok(())
     }
 
     /// Load excel schema for a profile.
-    pub fn load_excel_schema(&self, profile_id: i64) -> Result<ExcelSchema, String> {
+    pub fn load_excel_schema(&self, profile_id: i64) -> Result<ExcelSchema, AppError> {
         use crate::models::{ColumnFormat, HeaderInfo, RowTemplate};
 
-        let conn = self.conn.lock().map_err(|e| e.to_string())?;
+        let conn = self.conn.lock().map_err(AppError::db)?;
         let (
             header_row,
             first_data_row,
@@ -330,10 +943,10 @@ impl Db {
                     ))
                 },
             )
-            .map_err(|e| format!("Schema not found for profile {}: {}", profile_id, e))?;
+            .map_err(|e| AppError::Db(format!("Schema not found for profile {}: {}", profile_id, e)))?;
 
         let headers: Vec<HeaderInfo> =
-            serde_json::from_str(&headers_json).map_err(|e| format!("Parse headers_json: {}", e))?;
+            serde_json::from_str(&headers_json).map_err(|e| AppError::Db(format!("Parse headers_json: {}", e)))?;
 
         let mut stmt = conn
             .prepare(
@@ -344,7 +957,7 @@ impl Db {
                         data_type, number_format, column_width
                  FROM column_formats WHERE profile_id = ?1 ORDER BY column_index",
             )
-            .map_err(|e| e.to_string())?;
+            .map_err(AppError::db)?;
 
         let columns: Vec<ColumnFormat> = stmt
             .query_map(params![profile_id], |row| {
@@ -367,9 +980,9 @@ impl Db {
                     column_width: row.get(15)?,
                 })
             })
-            .map_err(|e| e.to_string())?
+            .map_err(AppError::db)?
             .collect::<Result<Vec<_>, _>>()
-            .map_err(|e| e.to_string())?;
+            .map_err(AppError::db)?;
 
         let row_template: RowTemplate = conn
             .query_row(
@@ -384,13 +997,14 @@ impl Db {
                     })
                 },
             )
-            .map_err(|e| format!("row_template not found: {}", e))?;
+            .map_err(|e| AppError::Db(format!("row_template not found: {}", e)))?;
 
         Ok(ExcelSchema {
             header_row: header_row as u32,
             first_data_row: first_data_row as u32,
             last_data_row: last_data_row as u32,
             next_free_row: next_free_row as u32,
+            last_row_truncated: false,
             total_rows: total_rows as u32,
             total_columns: total_columns as u16,
             headers,
@@ -401,35 +1015,51 @@ impl Db {
         })
     }
 
+    /// Store column widths (letter, width) after appends widened them.
+    pub fn update_column_widths(&self, profile_id: i64, widths: &[(String, f64)]) -> Result<(), AppError> {
+        if widths.is_empty() {
+            return Ok(());
+        }
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        for (letter, width) in widths {
+            conn.execute(
+                "UPDATE column_formats SET column_width = ?1 WHERE profile_id = ?2 AND UPPER(column_letter) = ?3",
+                params![width, profile_id, letter.to_uppercase()],
+            )
+            .map_err(AppError::db)?;
+        }
+        Ok(())
+    }
+
     /// Update next_free_row and last_data_row after appending a row; log to cache_changes.
     pub fn update_excel_schema_next_free_row(
         &self,
         profile_id: i64,
         new_next_free_row: u32,
         old_next_free_row: u32,
-    ) -> Result<(), String> {
-        let conn = self.conn.lock().map_err(|e| e.to_string())?;
+    ) -> Result<(), AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
         conn.execute(
             "UPDATE excel_schemas SET next_free_row = ?1, last_data_row = ?2 WHERE profile_id = ?3",
             params![new_next_free_row as i64, (new_next_free_row - 1) as i64, profile_id],
         )
-        .map_err(|e| e.to_string())?;
+        .map_err(AppError::db)?;
         conn.execute(
             "INSERT INTO cache_changes (profile_id, changed_at, reason, old_next_free_row, new_next_free_row)
              VALUES (?1, datetime('now'), 'row_added', ?2, ?3)",
             params![profile_id, old_next_free_row as i64, new_next_free_row as i64],
         )
-        .map_err(|e| e.to_string())?;
+        .map_err(AppError::db)?;
         Ok(())
     }
 
-    pub fn get_profiles(&self) -> Result<Vec<(i64, String, String, String, String)>, String> {
-        let conn = self.conn.lock().map_err(|e| e.to_string())?;
+    pub fn get_profiles(&self) -> Result<Vec<(i64, String, String, String, String)>, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
         let mut stmt = conn
             .prepare(
                 "SELECT id, name, excel_path, sheet_name, column_mapping FROM profiles ORDER BY name",
             )
-            .map_err(|e| e.to_string())?;
+            .map_err(AppError::db)?;
         let rows = stmt
             .query_map([], |row| {
                 Ok((
@@ -440,10 +1070,10 @@ impl Db {
                     row.get(4)?,
                 ))
             })
-            .map_err(|e| e.to_string())?;
+            .map_err(AppError::db)?;
         let mut out = Vec::new();
         for row in rows {
-            out.push(row.map_err(|e| e.to_string())?);
+            out.push(row.map_err(AppError::db)?);
         }
         Ok(out)
     }
@@ -455,30 +1085,45 @@ impl Db {
         excel_path: &str,
         sheet_name: &str,
         column_mapping: &Value,
-    ) -> Result<i64, String> {
-        let mapping_str = serde_json::to_string(column_mapping).map_err(|e| e.to_string())?;
-        let conn = self.conn.lock().map_err(|e| e.to_string())?;
+    ) -> Result<i64, AppError> {
+        let mapping_str = serde_json::to_string(column_mapping).map_err(AppError::db)?;
+        let conn = self.conn.lock().map_err(AppError::db)?;
         if let Some(id) = id {
             conn.execute(
                 "UPDATE profiles SET name = ?, excel_path = ?, sheet_name = ?, column_mapping = ? WHERE id = ?",
                 params![name, excel_path, sheet_name, mapping_str, id],
             )
-            .map_err(|e| e.to_string())?;
+            .map_err(AppError::db)?;
             Ok(id)
         } else {
             conn.execute(
                 "INSERT INTO profiles (name, excel_path, sheet_name, column_mapping) VALUES (?, ?, ?, ?)",
                 params![name, excel_path, sheet_name, mapping_str],
             )
-            .map_err(|e| e.to_string())?;
+            .map_err(AppError::db)?;
             Ok(conn.last_insert_rowid())
         }
     }
 
-    pub fn delete_profile(&self, id: i64) -> Result<(), String> {
-        let conn = self.conn.lock().map_err(|e| e.to_string())?;
+    /// Replace only the column mapping of a profile.
+    pub fn update_profile_mapping(&self, id: i64, column_mapping: &Value) -> Result<(), AppError> {
+        let mapping_str = serde_json::to_string(column_mapping).map_err(AppError::db)?;
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        conn.execute("UPDATE profiles SET column_mapping = ? WHERE id = ?", params![mapping_str, id])
+            .map_err(AppError::db)?;
+        Ok(())
+    }
+
+    pub fn delete_profile(&self, id: i64) -> Result<(), AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        conn.execute("DELETE FROM profile_delivery_targets WHERE profile_id = ?", params![id])
+            .map_err(AppError::db)?;
+        conn.execute("DELETE FROM profile_duplicate_keys WHERE profile_id = ?", params![id])
+            .map_err(AppError::db)?;
+        conn.execute("DELETE FROM pending_appends WHERE profile_id = ?", params![id])
+            .map_err(AppError::db)?;
         conn.execute("DELETE FROM profiles WHERE id = ?", params![id])
-            .map_err(|e| e.to_string())?;
+            .map_err(AppError::db)?;
         Ok(())
     }
 
@@ -491,12 +1136,13 @@ impl Db {
         excel_profile_id: Option<i64>,
         error_message: Option<&str>,
         folder_id: Option<i64>,
-    ) -> Result<i64, String> {
+    ) -> Result<i64, AppError> {
         let created_at = chrono::Utc::now().to_rfc3339();
-        let data_str = serde_json::to_string(extracted_data).map_err(|e| e.to_string())?;
-        let conn = self.conn.lock().map_err(|e| e.to_string())?;
+        let data_str = serde_json::to_string(extracted_data).map_err(AppError::db)?;
+        let conn = self.conn.lock().map_err(AppError::db)?;
         conn.execute(
-            "INSERT INTO history (created_at, document_type, file_path_or_name, extracted_data, status, excel_profile_id, error_message, folder_id) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+            "INSERT INTO history (created_at, document_type, file_path_or_name, extracted_data, status, excel_profile_id, error_message, folder_id, session_id)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, (SELECT id FROM scan_sessions WHERE ended_at IS NULL ORDER BY id DESC LIMIT 1))",
             params![
                 created_at,
                 document_type,
@@ -508,267 +1154,1866 @@ impl Db {
                 folder_id
             ],
         )
-        .map_err(|e| e.to_string())?;
+        .map_err(AppError::db)?;
         Ok(conn.last_insert_rowid())
     }
 
-    pub fn create_folder(&self, name: &str) -> Result<i64, String> {
+    /// New folder under `parent_id` (top level when None).
+    pub fn create_folder(&self, name: &str, parent_id: Option<i64>) -> Result<i64, AppError> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(AppError::Validation("Folder name is required.".to_string()));
+        }
         let created_at = chrono::Utc::now().to_rfc3339();
-        let conn = self.conn.lock().map_err(|e| e.to_string())?;
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        if let Some(pid) = parent_id {
+            ensure_folder_exists(&conn, pid)?;
+        }
+        ensure_folder_name_free(&conn, parent_id, name, None)?;
         conn.execute(
-            "INSERT INTO folders (name, created_at) VALUES (?, ?)",
-            params![name.trim(), created_at],
+            "INSERT INTO folders (name, parent_id, created_at) VALUES (?, ?, ?)",
+            params![name, parent_id, created_at],
         )
-        .map_err(|e| e.to_string())?;
+        .map_err(AppError::db)?;
         Ok(conn.last_insert_rowid())
     }
 
-    pub fn get_folders(&self) -> Result<Vec<(i64, String, String)>, String> {
-        let conn = self.conn.lock().map_err(|e| e.to_string())?;
+    /// (id, name, created_at, parent_id), ordered by name.
+    pub fn get_folders(&self) -> Result<Vec<FolderRow>, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
         let mut stmt = conn
-            .prepare("SELECT id, name, created_at FROM folders ORDER BY name")
-            .map_err(|e| e.to_string())?;
+            .prepare("SELECT id, name, created_at, parent_id FROM folders ORDER BY name")
+            .map_err(AppError::db)?;
         let rows = stmt
-            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
-            .map_err(|e| e.to_string())?;
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))
+            .map_err(AppError::db)?;
         let mut out = Vec::new();
         for row in rows {
-            out.push(row.map_err(|e| e.to_string())?);
+            out.push(row.map_err(AppError::db)?);
         }
         Ok(out)
     }
 
-    pub fn delete_folder(&self, id: i64) -> Result<(), String> {
-        let conn = self.conn.lock().map_err(|e| e.to_string())?;
-        conn.execute("UPDATE history SET folder_id = NULL WHERE folder_id = ?", params![id])
-            .map_err(|e| e.to_string())?;
-        conn.execute("DELETE FROM folders WHERE id = ?", params![id])
-            .map_err(|e| e.to_string())?;
+    pub fn rename_folder(&self, id: i64, name: &str) -> Result<(), AppError> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(AppError::Validation("Folder name is required.".to_string()));
+        }
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let parent_id = ensure_folder_exists(&conn, id)?;
+        ensure_folder_name_free(&conn, parent_id, name, Some(id))?;
+        conn.execute("UPDATE folders SET name = ? WHERE id = ?", params![name, id])
+            .map_err(AppError::db)?;
         Ok(())
     }
 
-    pub fn assign_history_to_folder(&self, history_id: i64, folder_id: Option<i64>) -> Result<(), String> {
-        let conn = self.conn.lock().map_err(|e| e.to_string())?;
-        conn.execute("UPDATE history SET folder_id = ? WHERE id = ?", params![folder_id, history_id])
-            .map_err(|e| e.to_string())?;
+    /// Put a folder (with its subfolders) under `parent_id`, or at the top level when None.
+    pub fn move_folder(&self, id: i64, parent_id: Option<i64>) -> Result<(), AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        ensure_folder_exists(&conn, id)?;
+        if let Some(pid) = parent_id {
+            ensure_folder_exists(&conn, pid)?;
+            let inside: bool = conn
+                .query_row(
+                    &format!("SELECT ?2 IN ({})", folder_subtree("?1")),
+                    params![id, pid],
+                    |r| r.get(0),
+                )
+                .map_err(AppError::db)?;
+            if inside {
+                return Err(AppError::Validation("A folder cannot be moved into itself or one of its subfolders.".to_string()));
+            }
+        }
+        let name: String = conn
+            .query_row("SELECT name FROM folders WHERE id = ?", params![id], |r| r.get(0))
+            .map_err(AppError::db)?;
+        ensure_folder_name_free(&conn, parent_id, &name, Some(id))?;
+        conn.execute("UPDATE folders SET parent_id = ? WHERE id = ?", params![parent_id, id])
+            .map_err(AppError::db)?;
         Ok(())
     }
 
-    pub fn get_history(
-        &self,
-        search: Option<&str>,
-        folder_id: Option<i64>,
-    ) -> Result<
-        Vec<(i64, String, String, String, String, String, Option<i64>, Option<String>)>,
-        String,
-    >
-    {
-        let conn = self.conn.lock().map_err(|e| e.to_string())?;
-        let base = "SELECT id, created_at, document_type, file_path_or_name, extracted_data, status, excel_profile_id, error_message FROM history";
-        // folder_id: None = all, Some(-1) = uncategorized (NULL), Some(id) = specific folder
-        let (sql, params): (String, Vec<Box<dyn rusqlite::ToSql + '_>>) = match (search, folder_id) {
-            (None, None) => (format!("{} ORDER BY created_at DESC", base), vec![]),
-            (Some(s), None) => {
-                let pattern = format!("%{}%", s);
-                (
-                    format!("{} WHERE (file_path_or_name LIKE ?1 OR extracted_data LIKE ?1) ORDER BY created_at DESC", base),
-                    vec![Box::new(pattern)],
-                )
-            }
-            (None, Some(-1)) => (
-                format!("{} WHERE folder_id IS NULL ORDER BY created_at DESC", base),
-                vec![],
-            ),
-            (None, Some(fid)) => (
-                format!("{} WHERE folder_id = ?1 ORDER BY created_at DESC", base),
-                vec![Box::new(fid)],
-            ),
-            (Some(s), Some(-1)) => {
-                let pattern = format!("%{}%", s);
-                (
-                    format!("{} WHERE (file_path_or_name LIKE ?1 OR extracted_data LIKE ?1) AND folder_id IS NULL ORDER BY created_at DESC", base),
-                    vec![Box::new(pattern)],
-                )
-            }
-            (Some(s), Some(fid)) => {
-                let pattern = format!("%{}%", s);
-                (
-                    format!("{} WHERE (file_path_or_name LIKE ?1 OR extracted_data LIKE ?1) AND folder_id = ?2 ORDER BY created_at DESC", base),
-                    vec![Box::new(pattern), Box::new(fid)],
-                )
-            }
+    /// Delete a folder; its documents and subfolders move up to its parent folder.
+    pub fn delete_folder(&self, id: i64) -> Result<(), AppError> {
+        let mut conn = self.conn.lock().map_err(AppError::db)?;
+        let tx = conn.transaction().map_err(AppError::db)?;
+        let parent_id: Option<i64> = match tx.query_row("SELECT parent_id FROM folders WHERE id = ?", params![id], |r| r.get(0)) {
+            Ok(p) => p,
+            Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(()),
+            Err(e) => return Err(AppError::db(e)),
         };
-        let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
-        let param_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
-        let rows = stmt
-            .query_map(rusqlite::params_from_iter(param_refs), |row| {
-                Ok((
-                    row.get::<_, i64>(0)?,
-                    row.get::<_, String>(1)?,
-                    row.get::<_, String>(2)?,
-                    row.get::<_, String>(3)?,
-                    row.get::<_, String>(4)?,
-                    row.get::<_, String>(5)?,
-                    row.get::<_, Option<i64>>(6)?,
-                    row.get::<_, Option<String>>(7)?,
-                ))
-            })
-            .map_err(|e| e.to_string())?;
-        let out: Vec<_> = rows.filter_map(|r| r.ok()).collect();
-        Ok(out)
+        let children: Vec<(i64, String)> = {
+            let mut stmt = tx.prepare("SELECT id, name FROM folders WHERE parent_id = ?").map_err(AppError::db)?;
+            let rows = stmt.query_map(params![id], |r| Ok((r.get(0)?, r.get(1)?))).map_err(AppError::db)?;
+            rows.collect::<Result<Vec<_>, _>>().map_err(AppError::db)?
+        };
+        for (child_id, name) in children {
+            ensure_folder_name_free(&tx, parent_id, &name, Some(child_id))?;
+        }
+        tx.execute("UPDATE history SET folder_id = ? WHERE folder_id = ?", params![parent_id, id])
+            .map_err(AppError::db)?;
+        tx.execute("UPDATE folders SET parent_id = ? WHERE parent_id = ?", params![parent_id, id])
+            .map_err(AppError::db)?;
+        tx.execute("DELETE FROM folders WHERE id = ?", params![id])
+            .map_err(AppError::db)?;
+        tx.commit().map_err(AppError::db)
     }
 
-    pub fn get_history_by_id(
-        &self,
-        id: i64,
-    ) -> Result<Option<(String, String, String, String, Option<i64>)>, String> {
-        let conn = self.conn.lock().map_err(|e| e.to_string())?;
-        let mut stmt = conn
-            .prepare("SELECT created_at, document_type, file_path_or_name, extracted_data, excel_profile_id FROM history WHERE id = ?")
-            .map_err(|e| e.to_string())?;
-        let mut rows = stmt.query(params![id]).map_err(|e| e.to_string())?;
-        let next = rows.next().map_err(|e| e.to_string())?;
-        if let Some(row) = next {
-            Ok(Some((
-                row.get::<_, String>(0).map_err(|e: rusqlite::Error| e.to_string())?,
-                row.get::<_, String>(1).map_err(|e: rusqlite::Error| e.to_string())?,
-                row.get::<_, String>(2).map_err(|e: rusqlite::Error| e.to_string())?,
-                row.get::<_, String>(3).map_err(|e: rusqlite::Error| e.to_string())?,
-                row.get::<_, Option<i64>>(4).map_err(|e: rusqlite::Error| e.to_string())?,
-            )))
-        } else {
-            Ok(None)
-        }
+    pub fn assign_history_to_folder(&self, history_id: i64, folder_id: Option<i64>) -> Result<(), AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        conn.execute("UPDATE history SET folder_id = ? WHERE id = ?", params![folder_id, history_id])
+            .map_err(AppError::db)?;
+        Ok(())
     }
 
-    pub fn get_learned_mapping(
-        &self,
-        schema_hash: &str,
-        field_type: &str,
-    ) -> Result<Option<(String, f64)>, String> {
-        let conn = self.conn.lock().map_err(|e| e.to_string())?;
+    pub fn create_category(&self, name: &str, monthly_budget: Option<f64>) -> Result<i64, AppError> {
+        let created_at = chrono::Utc::now().to_rfc3339();
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        conn.execute(
+            "INSERT INTO categories (name, monthly_budget, created_at) VALUES (?, ?, ?)",
+            params![name.trim(), monthly_budget, created_at],
+        )
+        .map_err(AppError::db)?;
+        Ok(conn.last_insert_rowid())
+    }
+    pub fn update_category(&self, id: i64, name: &str, monthly_budget: Option<f64>) -> Result<(), AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        conn.execute(
+            "UPDATE categories SET name = ?, monthly_budget = ? WHERE id = ?",
+            params![name.trim(), monthly_budget, id],
+        )
+        .map_err(AppError::db)?;
+        Ok(())
+    }
+    /// All categories as (id, name, monthly_budget, created_at).
+    pub fn get_categories(&self) -> Result<Vec<CategoryRow>, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
         let mut stmt = conn
-            .prepare(
+            .prepare("SELECT id, name, monthly_budget, created_at FROM categories ORDER BY name")
+            .map_err(AppError::db)?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))
+            .map_err(AppError::db)?;
+        let mut out = Vec::new();
+        for row in rows {
+            out.push(row.map_err(AppError::db)?);
+        }
+        Ok(out)
+    }
+    pub fn delete_category(&self, id: i64) -> Result<(), AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        conn.execute("UPDATE history SET category_id = NULL WHERE category_id = ?", params![id])
+            .map_err(AppError::db)?;
+        conn.execute("DELETE FROM categories WHERE id = ?", params![id])
+            .map_err(AppError::db)?;
+        Ok(())
+    }
+    pub fn assign_history_to_category(&self, history_id: i64, category_id: Option<i64>) -> Result<(), AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        conn.execute("UPDATE history SET category_id = ? WHERE id = ?", params![category_id, history_id])
+            .map_err(AppError::db)?;
+        Ok(())
+    }
+    /// History rows that count as spending (everything except failed scans and batch export entries) as (category_id, created_at, extracted_data).
+    pub fn get_spending_rows(&self) -> Result<Vec<(Option<i64>, String, String)>, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let mut stmt = conn
+            .prepare("SELECT category_id, created_at, extracted_data FROM history WHERE status != 'error' AND document_type != 'batch_export' AND deleted_at IS NULL")
+            .map_err(AppError::db)?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .map_err(AppError::db)?;
+        let mut out = Vec::new();
+        for row in rows {
+            out.push(row.map_err(AppError::db)?);
+        }
+        Ok(out)
+    }
+    /// Documents for the history report (batch export entries excluded) as
+    /// (id, created_at, document_type, file_path_or_name, extracted_data, status).
+    /// folder_id: None = all, Some(-1) = uncategorized; a folder includes its subfolders.
+    pub fn get_history_report_rows(
+        &self,
+        folder_id: Option<i64>,
+        status: Option<&str>,
+    ) -> Result<Vec<HistoryReportRow>, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT id, created_at, document_type, file_path_or_name, extracted_data, status FROM history
+                 WHERE document_type != 'batch_export' AND deleted_at IS NULL
+                   AND (?1 IS NULL OR (?1 = -1 AND folder_id IS NULL) OR folder_id IN ({}))
+                   AND (?2 IS NULL OR status = ?2)
+                 ORDER BY created_at",
+                folder_subtree("?1")
+            ))
+            .map_err(AppError::db)?;
+        let rows = stmt
+            .query_map(params![folder_id, status], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?))
+            })
+            .map_err(AppError::db)?;
+        let mut out = Vec::new();
+        for row in rows {
+            out.push(row.map_err(AppError::db)?);
+        }
+        Ok(out)
+    }
+    /// Records exported to a profile's ledger (batch export entries excluded) as (id, created_at, extracted_data).
+    pub fn get_exported_history_for_profile(&self, profile_id: i64) -> Result<Vec<(i64, String, String)>, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let mut stmt = conn
+            .prepare("SELECT id, created_at, extracted_data FROM history WHERE excel_profile_id = ? AND status = 'added_to_excel' AND document_type != 'batch_export' AND deleted_at IS NULL ORDER BY id")
+            .map_err(AppError::db)?;
+        let rows = stmt
+            .query_map(params![profile_id], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .map_err(AppError::db)?;
+        let mut out = Vec::new();
+        for row in rows {
+            out.push(row.map_err(AppError::db)?);
+        }
+        Ok(out)
+    }
+    /// All stored settings as key -> value.
+    pub fn get_settings(&self) -> Result<HashMap<String, String>, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let mut stmt = conn.prepare("SELECT key, value FROM settings").map_err(AppError::db)?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))
+            .map_err(AppError::db)?;
+        let mut out = HashMap::new();
+        for row in rows {
+            let (key, value) = row.map_err(AppError::db)?;
+            out.insert(key, value);
+        }
+        Ok(out)
+    }
+    /// Upsert settings in one transaction; a None value removes the key.
+    pub fn set_settings(&self, entries: &[(&str, Option<String>)]) -> Result<(), AppError> {
+        let updated_at = chrono::Utc::now().to_rfc3339();
+        let mut conn = self.conn.lock().map_err(AppError::db)?;
+        let tx = conn.transaction().map_err(AppError::db)?;
+        for (key, value) in entries {
+            match value {
+                Some(value) => tx.execute(
+                    "INSERT INTO settings (key, value, updated_at) VALUES (?1, ?2, ?3)
+                     ON CONFLICT(key) DO UPDATE SET value = excluded.value, updated_at = excluded.updated_at",
+                    params![key, value, updated_at],
+                ),
+                None => tx.execute("DELETE FROM settings WHERE key = ?", params![key]),
+            }
+            .map_err(AppError::db)?;
+        }
+        tx.commit().map_err(AppError::db)?;
+        Ok(())
+    }
+    /// Record where a document's original was archived. Returns the new row id.
+    pub fn add_archived_document(
+        &self,
+        history_id: i64,
+        backend: &str,
+        location: &str,
+        object_key: &str,
+        file_name: &str,
+        size_bytes: u64,
+    ) -> Result<i64, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        conn.execute(
+            "INSERT INTO archived_documents (history_id, backend, location, object_key, file_name, size_bytes, created_at) VALUES (?, ?, ?, ?, ?, ?, ?)",
+            params![history_id, backend, location, object_key, file_name, size_bytes as i64, chrono::Utc::now().to_rfc3339()],
+        )
+        .map_err(AppError::db)?;
+        Ok(conn.last_insert_rowid())
+    }
+    /// Latest archived copy of a history record, with the hash and path kept on the record.
+    pub fn get_archived_document(&self, history_id: i64) -> Result<Option<ArchivedDocumentRow>, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let row = conn.query_row(
+            "SELECT a.backend, a.location, a.object_key, a.file_name, a.size_bytes, a.created_at, h.archive_sha256, h.archive_path
+             FROM archived_documents a LEFT JOIN history h ON h.id = a.history_id
+             WHERE a.history_id = ? ORDER BY a.id DESC LIMIT 1",
+            params![history_id],
+            |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get::<_, i64>(4)? as u64,
+                    row.get(5)?,
+                    row.get(6)?,
+                    row.get(7)?,
+                ))
+            },
+        );
+        match row {
+            Ok(r) => Ok(Some(r)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(AppError::db(e)),
+        }
+    }
+    pub fn set_history_archive(&self, history_id: i64, sha256: &str, archive_path: &str) -> Result<(), AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        conn.execute(
+            "UPDATE history SET archive_sha256 = ?, archive_path = ? WHERE id = ?",
+            params![sha256, archive_path, history_id],
+        )
+        .map_err(AppError::db)?;
+        Ok(())
+    }
+    /// Oldest successful history record whose original has this hash, as (id, created_at).
+    pub fn find_history_by_sha256(&self, sha256: &str) -> Result<Option<(i64, String)>, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let row = conn.query_row(
+            "SELECT id, created_at FROM history WHERE archive_sha256 = ? AND status != 'error' AND deleted_at IS NULL ORDER BY id LIMIT 1",
+            params![sha256],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        );
+        match row {
+            Ok(r) => Ok(Some(r)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(AppError::db(e)),
+        }
+    }
+    /// Object key of an earlier archived copy with the same content in the same backend and location.
+    pub fn find_archived_object(&self, sha256: &str, backend: &str, location: &str) -> Result<Option<String>, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let row = conn.query_row(
+            "SELECT a.object_key FROM archived_documents a JOIN history h ON h.id = a.history_id
+             WHERE h.archive_sha256 = ? AND a.backend = ? AND a.location = ? ORDER BY a.id LIMIT 1",
+            params![sha256, backend, location],
+            |row| row.get(0),
+        );
+        match row {
+            Ok(key) => Ok(Some(key)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(AppError::db(e)),
+        }
+    }
+    /// Record a delivery attempt of an export (status "delivered" or "failed").
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_export_delivery(
+        &self,
+        profile_id: Option<i64>,
+        source_path: &str,
+        destination: &str,
+        sha256: &str,
+        size_bytes: u64,
+        status: &str,
+        attempts: u32,
+        error: Option<&str>,
+    ) -> Result<i64, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        conn.execute(
+            "INSERT INTO export_deliveries (profile_id, source_path, destination, sha256, size_bytes, status, attempts, error, created_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            params![profile_id, source_path, destination, sha256, size_bytes as i64, status, attempts, error, chrono::Utc::now().to_rfc3339()],
+        )
+        .map_err(AppError::db)?;
+        Ok(conn.last_insert_rowid())
+    }
+    /// Latest deliveries, newest first; only those of `profile_id` when given.
+    pub fn list_export_deliveries(&self, profile_id: Option<i64>, limit: u32) -> Result<Vec<ExportDeliveryRow>, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let mut stmt = conn
+            .prepare("SELECT id, source_path, destination, sha256, size_bytes, status, attempts, error, created_at, profile_id FROM export_deliveries WHERE ?1 IS NULL OR profile_id = ?1 ORDER BY id DESC LIMIT ?2")
+            .map_err(AppError::db)?;
+        let rows = stmt
+            .query_map(params![profile_id, limit], |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get::<_, i64>(4)? as u64,
+                    row.get(5)?,
+                    row.get(6)?,
+                    row.get(7)?,
+                    row.get(8)?,
+                    row.get(9)?,
+                ))
+            })
+            .map_err(AppError::db)?;
+        rows.collect::<Result<Vec<_>, _>>().map_err(AppError::db)
+    }
+    /// Record a webhook delivery about to be sent (status "pending", no attempts yet).
+    pub fn add_webhook_delivery(&self, event: &str, url: &str, profile_id: Option<i64>, payload: &str) -> Result<i64, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let now = chrono::Utc::now().to_rfc3339();
+        conn.execute(
+            "INSERT INTO webhook_deliveries (event, url, profile_id, payload, status, attempts, created_at, updated_at) VALUES (?, ?, ?, ?, 'pending', 0, ?, ?)",
+            params![event, url, profile_id, payload, now, now],
+        )
+        .map_err(AppError::db)?;
+        Ok(conn.last_insert_rowid())
+    }
+    /// Record the outcome of an attempt; `url` is where it was sent.
+    pub fn update_webhook_delivery(
+        &self,
+        id: i64,
+        url: &str,
+        status: &str,
+        attempts: u32,
+        response_status: Option<u16>,
+        error: Option<&str>,
+    ) -> Result<(), AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        conn.execute(
+            "UPDATE webhook_deliveries SET url = ?, status = ?, attempts = ?, response_status = ?, error = ?, updated_at = ? WHERE id = ?",
+            params![url, status, attempts, response_status, error, chrono::Utc::now().to_rfc3339(), id],
+        )
+        .map_err(AppError::db)?;
+        Ok(())
+    }
+    pub fn get_webhook_delivery(&self, id: i64) -> Result<Option<WebhookDeliveryRow>, AppError> {
+        Ok(self.query_webhook_deliveries("WHERE id = ?1", params![id])?.into_iter().next())
+    }
+    /// Latest webhook deliveries, newest first.
+    pub fn list_webhook_deliveries(&self, limit: u32) -> Result<Vec<WebhookDeliveryRow>, AppError> {
+        self.query_webhook_deliveries("ORDER BY id DESC LIMIT ?1", params![limit])
+    }
+    fn query_webhook_deliveries(&self, clause: &str, args: &[&dyn rusqlite::ToSql]) -> Result<Vec<WebhookDeliveryRow>, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT id, event, url, profile_id, payload, status, attempts, response_status, error, created_at, updated_at FROM webhook_deliveries {}",
+                clause
+            ))
+            .map_err(AppError::db)?;
+        let rows = stmt
+            .query_map(args, |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                    row.get(5)?,
+                    row.get(6)?,
+                    row.get(7)?,
+                    row.get(8)?,
+                    row.get(9)?,
+                    row.get(10)?,
+                ))
+            })
+            .map_err(AppError::db)?;
+        rows.collect::<Result<Vec<_>, _>>().map_err(AppError::db)
+    }
+    /// Record a downloaded attachment as queued. None when that part of the message was taken before.
+    pub fn add_email_intake_item(&self, item: &NewEmailIntakeItem) -> Result<Option<i64>, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let inserted = conn
+            .execute(
+                "INSERT OR IGNORE INTO email_intake (mailbox, uid_validity, uid, part, sender, subject, received_at, file_name, file_path, status, created_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, 'queued', ?)",
+                params![
+                    item.mailbox,
+                    item.uid_validity,
+                    item.uid,
+                    item.part,
+                    item.sender,
+                    item.subject,
+                    item.received_at,
+                    item.file_name,
+                    item.file_path,
+                    chrono::Utc::now().to_rfc3339()
+                ],
+            )
+            .map_err(AppError::db)?;
+        Ok((inserted > 0).then(|| conn.last_insert_rowid()))
+    }
+    /// Intake items, oldest first; only those with `status` when given.
+    pub fn list_email_intake(&self, status: Option<&str>) -> Result<Vec<EmailIntakeRow>, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, sender, subject, received_at, file_name, file_path, status, created_at FROM email_intake WHERE ?1 IS NULL OR status = ?1 ORDER BY id",
+            )
+            .map_err(AppError::db)?;
+        let rows = stmt
+            .query_map(params![status], |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                    row.get(5)?,
+                    row.get(6)?,
+                    row.get(7)?,
+                ))
+            })
+            .map_err(AppError::db)?;
+        rows.collect::<Result<Vec<_>, _>>().map_err(AppError::db)
+    }
+    pub fn set_email_intake_status(&self, ids: &[i64], status: &str) -> Result<(), AppError> {
+        let mut conn = self.conn.lock().map_err(AppError::db)?;
+        let tx = conn.transaction().map_err(AppError::db)?;
+        for id in ids {
+            tx.execute("UPDATE email_intake SET status = ? WHERE id = ?", params![status, id])
+                .map_err(AppError::db)?;
+        }
+        tx.commit().map_err(AppError::db)
+    }
+    /// Delivery destination configured for a profile.
+    pub fn get_profile_delivery_target(&self, profile_id: i64) -> Result<Option<ProfileDeliveryRow>, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let row = conn.query_row(
+            "SELECT kind, share_path, sftp_host, sftp_port, sftp_username, sftp_directory, sftp_host_fingerprint FROM profile_delivery_targets WHERE profile_id = ?",
+            params![profile_id],
+            |row| {
+                Ok(ProfileDeliveryRow {
+                    kind: row.get(0)?,
+                    share_path: row.get(1)?,
+                    sftp_host: row.get(2)?,
+                    sftp_port: row.get::<_, Option<i64>>(3)?.map(|p| p as u16),
+                    sftp_username: row.get(4)?,
+                    sftp_directory: row.get(5)?,
+                    sftp_host_fingerprint: row.get(6)?,
+                })
+            },
+        );
+        match row {
+            Ok(r) => Ok(Some(r)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(AppError::db(e)),
+        }
+    }
+    /// Insert or replace the delivery destination of a profile.
+    pub fn save_profile_delivery_target(&self, profile_id: i64, target: &ProfileDeliveryRow) -> Result<(), AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        conn.execute(
+            "INSERT OR REPLACE INTO profile_delivery_targets (profile_id, kind, share_path, sftp_host, sftp_port, sftp_username, sftp_directory, sftp_host_fingerprint, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                profile_id,
+                target.kind,
+                target.share_path,
+                target.sftp_host,
+                target.sftp_port.map(|p| p as i64),
+                target.sftp_username,
+                target.sftp_directory,
+                target.sftp_host_fingerprint,
+                chrono::Utc::now().to_rfc3339()
+            ],
+        )
+        .map_err(AppError::db)?;
+        Ok(())
+    }
+    pub fn delete_profile_delivery_target(&self, profile_id: i64) -> Result<(), AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        conn.execute("DELETE FROM profile_delivery_targets WHERE profile_id = ?", params![profile_id])
+            .map_err(AppError::db)?;
+        Ok(())
+    }
+    /// Snapshot policy of a profile as (every_appends, daily, appends_since_snapshot); None when not configured.
+    pub fn get_snapshot_policy(&self, profile_id: i64) -> Result<Option<(Option<u32>, bool, u32)>, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let row = conn.query_row(
+            "SELECT every_appends, daily, appends_since_snapshot FROM ledger_snapshot_policies WHERE profile_id = ?",
+            params![profile_id],
+            |r| Ok((r.get(0)?, r.get::<_, i64>(1)? != 0, r.get(2)?)),
+        );
+        match row {
+            Ok(r) => Ok(Some(r)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(AppError::db(e)),
+        }
+    }
+    pub fn save_snapshot_policy(&self, profile_id: i64, every_appends: Option<u32>, daily: bool) -> Result<(), AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        conn.execute(
+            "INSERT INTO ledger_snapshot_policies (profile_id, every_appends, daily) VALUES (?1, ?2, ?3)
+             ON CONFLICT(profile_id) DO UPDATE SET every_appends = ?2, daily = ?3",
+            params![profile_id, every_appends, daily as i64],
+        )
+        .map_err(AppError::db)?;
+        Ok(())
+    }
+    pub fn delete_snapshot_policy(&self, profile_id: i64) -> Result<(), AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        conn.execute("DELETE FROM ledger_snapshot_policies WHERE profile_id = ?", params![profile_id])
+            .map_err(AppError::db)?;
+        Ok(())
+    }
+    /// Duplicate key of a profile as (fields JSON, on_duplicate); None when not configured.
+    pub fn get_duplicate_key(&self, profile_id: i64) -> Result<Option<(String, String)>, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let row = conn.query_row(
+            "SELECT fields, on_duplicate FROM profile_duplicate_keys WHERE profile_id = ?",
+            params![profile_id],
+            |r| Ok((r.get(0)?, r.get(1)?)),
+        );
+        match row {
+            Ok(r) => Ok(Some(r)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(AppError::db(e)),
+        }
+    }
+    pub fn save_duplicate_key(&self, profile_id: i64, fields: &str, on_duplicate: &str) -> Result<(), AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        conn.execute(
+            "INSERT INTO profile_duplicate_keys (profile_id, fields, on_duplicate) VALUES (?1, ?2, ?3)
+             ON CONFLICT(profile_id) DO UPDATE SET fields = ?2, on_duplicate = ?3",
+            params![profile_id, fields, on_duplicate],
+        )
+        .map_err(AppError::db)?;
+        Ok(())
+    }
+    pub fn delete_duplicate_key(&self, profile_id: i64) -> Result<(), AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        conn.execute("DELETE FROM profile_duplicate_keys WHERE profile_id = ?", params![profile_id])
+            .map_err(AppError::db)?;
+        Ok(())
+    }
+    /// Count one append towards the profile's next snapshot; returns the new count.
+    pub fn increment_snapshot_counter(&self, profile_id: i64) -> Result<u32, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        conn.execute(
+            "UPDATE ledger_snapshot_policies SET appends_since_snapshot = appends_since_snapshot + 1 WHERE profile_id = ?",
+            params![profile_id],
+        )
+        .map_err(AppError::db)?;
+        let count = conn.query_row(
+            "SELECT appends_since_snapshot FROM ledger_snapshot_policies WHERE profile_id = ?",
+            params![profile_id],
+            |r| r.get(0),
+        );
+        match count {
+            Ok(n) => Ok(n),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(0),
+            Err(e) => Err(AppError::db(e)),
+        }
+    }
+    pub fn reset_snapshot_counter(&self, profile_id: i64) -> Result<(), AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        conn.execute(
+            "UPDATE ledger_snapshot_policies SET appends_since_snapshot = 0 WHERE profile_id = ?",
+            params![profile_id],
+        )
+        .map_err(AppError::db)?;
+        Ok(())
+    }
+    /// Record a snapshot and reset the profile's append counter.
+    pub fn add_ledger_snapshot(
+        &self,
+        profile_id: i64,
+        file_path: &str,
+        sha256: &str,
+        size_bytes: u64,
+        reason: &str,
+    ) -> Result<i64, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        conn.execute(
+            "INSERT INTO ledger_snapshots (profile_id, file_path, sha256, size_bytes, reason, created_at) VALUES (?, ?, ?, ?, ?, ?)",
+            params![profile_id, file_path, sha256, size_bytes as i64, reason, chrono::Utc::now().to_rfc3339()],
+        )
+        .map_err(AppError::db)?;
+        let id = conn.last_insert_rowid();
+        conn.execute(
+            "UPDATE ledger_snapshot_policies SET appends_since_snapshot = 0 WHERE profile_id = ?",
+            params![profile_id],
+        )
+        .map_err(AppError::db)?;
+        Ok(id)
+    }
+    /// Snapshots of a profile's workbook, newest first.
+    pub fn list_ledger_snapshots(&self, profile_id: i64) -> Result<Vec<LedgerSnapshotRow>, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, profile_id, file_path, sha256, size_bytes, reason, created_at FROM ledger_snapshots
+                 WHERE profile_id = ? ORDER BY id DESC",
+            )
+            .map_err(AppError::db)?;
+        let rows = stmt
+            .query_map(params![profile_id], ledger_snapshot_row)
+            .map_err(AppError::db)?;
+        let mut out = Vec::new();
+        for row in rows {
+            out.push(row.map_err(AppError::db)?);
+        }
+        Ok(out)
+    }
+    pub fn get_ledger_snapshot(&self, id: i64) -> Result<Option<LedgerSnapshotRow>, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let row = conn.query_row(
+            "SELECT id, profile_id, file_path, sha256, size_bytes, reason, created_at FROM ledger_snapshots WHERE id = ?",
+            params![id],
+            ledger_snapshot_row,
+        );
+        match row {
+            Ok(r) => Ok(Some(r)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(AppError::db(e)),
+        }
+    }
+    /// Remember the SFTP host key seen on first connection.
+    pub fn set_profile_delivery_fingerprint(&self, profile_id: i64, fingerprint: &str) -> Result<(), AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        conn.execute(
+            "UPDATE profile_delivery_targets SET sftp_host_fingerprint = ? WHERE profile_id = ?",
+            params![fingerprint, profile_id],
+        )
+        .map_err(AppError::db)?;
+        Ok(())
+    }
+    /// Record cell writes to `file_path` made for `history_id` (None when not tied to a document).
+    pub fn log_cell_writes(&self, file_path: &str, history_id: Option<i64>, writes: &[CellWrite]) -> Result<(), AppError> {
+        if writes.is_empty() {
+            return Ok(());
+        }
+        let created_at = chrono::Utc::now().to_rfc3339();
+        let mut conn = self.conn.lock().map_err(AppError::db)?;
+        let tx = conn.transaction().map_err(AppError::db)?;
+        {
+            let mut stmt = tx
+                .prepare(
+                    "INSERT INTO write_log (created_at, file_path, sheet_name, cell_ref, row_number, old_value, new_value, history_id)
+                     VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+                )
+                .map_err(AppError::db)?;
+            for w in writes {
+                stmt.execute(params![
+                    created_at,
+                    file_path,
+                    w.sheet_name,
+                    w.cell_ref,
+                    w.row_number,
+                    w.old_value,
+                    w.new_value,
+                    history_id
+                ])
+                .map_err(AppError::db)?;
+            }
+        }
+        tx.commit().map_err(AppError::db)
+    }
+
+    /// Write log for a file as (id, created_at, sheet_name, cell_ref, row_number, old_value, new_value, history_id),
+    /// newest first; optionally narrowed to one sheet and/or row.
+    pub fn get_write_log(
+        &self,
+        file_path: &str,
+        sheet_name: Option<&str>,
+        row_number: Option<u32>,
+        limit: u32,
+    ) -> Result<Vec<WriteLogRow>, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, created_at, sheet_name, cell_ref, row_number, old_value, new_value, history_id FROM write_log
+                 WHERE file_path = ?1 AND (?2 IS NULL OR sheet_name = ?2) AND (?3 IS NULL OR row_number = ?3)
+                 ORDER BY id DESC LIMIT ?4",
+            )
+            .map_err(AppError::db)?;
+        let rows = stmt
+            .query_map(params![file_path, sheet_name, row_number, limit], |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                    row.get(5)?,
+                    row.get(6)?,
+                    row.get(7)?,
+                ))
+            })
+            .map_err(AppError::db)?;
+        let mut out = Vec::new();
+        for row in rows {
+            out.push(row.map_err(AppError::db)?);
+        }
+        Ok(out)
+    }
+
+    /// Rows of a sheet written by the app between `from_row` and `to_row` (inclusive), with the time of the
+    /// latest write and the history record it came from.
+    pub fn get_written_rows(
+        &self,
+        file_path: &str,
+        sheet_name: &str,
+        from_row: u32,
+        to_row: u32,
+    ) -> Result<Vec<(u32, String, Option<i64>)>, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT row_number, MAX(created_at), history_id FROM write_log
+                 WHERE file_path = ?1 AND sheet_name = ?2 AND row_number BETWEEN ?3 AND ?4
+                 GROUP BY row_number ORDER BY row_number",
+            )
+            .map_err(AppError::db)?;
+        let rows = stmt
+            .query_map(params![file_path, sheet_name, from_row, to_row], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })
+            .map_err(AppError::db)?;
+        let mut out = Vec::new();
+        for row in rows {
+            out.push(row.map_err(AppError::db)?);
+        }
+        Ok(out)
+    }
+
+    /// `top_level_only` hides records that belong to a batch export (see `get_history_children`).
+    /// `limit`/`offset` page through the newest-first list; without a limit every row is returned.
+    /// `include_subfolders` also matches records filed anywhere below `folder_id`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn get_history(
+        &self,
+        search: Option<&str>,
+        folder_id: Option<i64>,
+        include_subfolders: bool,
+        top_level_only: bool,
+        session_id: Option<i64>,
+        limit: Option<i64>,
+        offset: Option<i64>,
+    ) -> Result<Vec<HistoryRow>, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let (filter, mut params) = history_filter(search, folder_id, include_subfolders, top_level_only, session_id);
+        let mut sql = format!(
+            "SELECT id, created_at, document_type, file_path_or_name, extracted_data, status, excel_profile_id, error_message FROM history{} ORDER BY created_at DESC, id DESC",
+            filter
+        );
+        if let Some(limit) = limit {
+            sql.push_str(&format!(" LIMIT ?{} OFFSET ?{}", params.len() + 1, params.len() + 2));
+            params.push(Box::new(limit.max(0)));
+            params.push(Box::new(offset.unwrap_or(0).max(0)));
+        }
+        let mut stmt = conn.prepare(&sql).map_err(AppError::db)?;
+        let param_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
+        let rows = stmt
+            .query_map(rusqlite::params_from_iter(param_refs), history_row)
+            .map_err(AppError::db)?;
+        let out: Vec<_> = rows.filter_map(|r| r.ok()).collect();
+        Ok(out)
+    }
+
+    /// Number of records `get_history` would return with the same filters and no limit.
+    pub fn count_history(
+        &self,
+        search: Option<&str>,
+        folder_id: Option<i64>,
+        include_subfolders: bool,
+        top_level_only: bool,
+        session_id: Option<i64>,
+    ) -> Result<i64, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let (filter, params) = history_filter(search, folder_id, include_subfolders, top_level_only, session_id);
+        let param_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
+        conn.query_row(
+            &format!("SELECT COUNT(*) FROM history{}", filter),
+            rusqlite::params_from_iter(param_refs),
+            |r| r.get(0),
+        )
+        .map_err(AppError::db)
+    }
+
+    /// Full-text search over file names and extracted values, best matches first. Every word of `query`
+    /// is matched as a prefix; `snippet` marks hits with <mark></mark>.
+    pub fn search_history_fts(&self, query: &str, limit: i64, offset: i64) -> Result<Vec<HistorySearchRow>, AppError> {
+        let match_expr = fts_match_expr(query);
+        if match_expr.is_empty() {
+            return Ok(Vec::new());
+        }
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT h.id, h.created_at, h.document_type, h.file_path_or_name, h.status, h.excel_profile_id,
+                        snippet(history_fts, -1, '<mark>', '</mark>', '…', 12), bm25(history_fts)
+                 FROM history_fts JOIN history h ON h.id = history_fts.rowid
+                 WHERE history_fts MATCH ?1 AND h.deleted_at IS NULL
+                 ORDER BY bm25(history_fts), h.created_at DESC
+                 LIMIT ?2 OFFSET ?3",
+            )
+            .map_err(AppError::db)?;
+        let rows = stmt
+            .query_map(rusqlite::params![match_expr, limit.max(0), offset.max(0)], |r| {
+                Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?, r.get(4)?, r.get(5)?, r.get(6)?, r.get(7)?))
+            })
+            .map_err(AppError::db)?;
+        rows.collect::<Result<Vec<_>, _>>().map_err(AppError::db)
+    }
+
+    /// Records grouped under a batch export, in the order they were written.
+    pub fn get_history_children(
+        &self,
+        parent_id: i64,
+    ) -> Result<Vec<HistoryRow>, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let mut stmt = conn
+            .prepare("SELECT id, created_at, document_type, file_path_or_name, extracted_data, status, excel_profile_id, error_message FROM history WHERE parent_id = ? AND deleted_at IS NULL ORDER BY id")
+            .map_err(AppError::db)?;
+        let rows = stmt
+            .query_map(params![parent_id], history_row)
+            .map_err(AppError::db)?;
+        let out: Vec<_> = rows.filter_map(|r| r.ok()).collect();
+        Ok(out)
+    }
+
+    /// Attach existing history records to a batch export entry.
+    pub fn set_history_parent(&self, parent_id: i64, child_ids: &[i64]) -> Result<(), AppError> {
+        let mut conn = self.conn.lock().map_err(AppError::db)?;
+        let tx = conn.transaction().map_err(AppError::db)?;
+        for id in child_ids {
+            tx.execute(
+                "UPDATE history SET parent_id = ?1 WHERE id = ?2 AND id != ?1",
+                params![parent_id, id],
+            )
+            .map_err(AppError::db)?;
+        }
+        tx.commit().map_err(AppError::db)
+    }
+
+    pub fn get_history_by_id(
+        &self,
+        id: i64,
+    ) -> Result<Option<(String, String, String, String, Option<i64>)>, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let mut stmt = conn
+            .prepare("SELECT created_at, document_type, file_path_or_name, extracted_data, excel_profile_id FROM history WHERE id = ?")
+            .map_err(AppError::db)?;
+        let mut rows = stmt.query(params![id]).map_err(AppError::db)?;
+        let next = rows.next().map_err(AppError::db)?;
+        if let Some(row) = next {
+            Ok(Some((
+                row.get::<_, String>(0).map_err(|e: rusqlite::Error| e.to_string())?,
+                row.get::<_, String>(1).map_err(|e: rusqlite::Error| e.to_string())?,
+                row.get::<_, String>(2).map_err(|e: rusqlite::Error| e.to_string())?,
+                row.get::<_, String>(3).map_err(|e: rusqlite::Error| e.to_string())?,
+                row.get::<_, Option<i64>>(4).map_err(|e: rusqlite::Error| e.to_string())?,
+            )))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub fn get_learned_mapping(
+        &self,
+        schema_hash: &str,
+        field_type: &str,
+    ) -> Result<Option<(String, f64)>, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let mut stmt = conn
+            .prepare(
                 "SELECT column_letter, confidence, last_used, usage_count FROM learned_mappings WHERE schema_hash = ? AND field_type = ?",
             )
-            .map_err(|e| e.to_string())?;
+            .map_err(AppError::db)?;
         let mut rows = stmt
             .query(params![schema_hash, field_type])
-            .map_err(|e| e.to_string())?;
-        let row = rows.next().map_err(|e| e.to_string())?;
+            .map_err(AppError::db)?;
+        let row = rows.next().map_err(AppError::db)?;
         if let Some(r) = row {
             let column_letter: String = r.get(0).map_err(|e: rusqlite::Error| e.to_string())?;
             let confidence: f64 = r.get(1).map_err(|e: rusqlite::Error| e.to_string())?;
             let last_used: String = r.get(2).map_err(|e: rusqlite::Error| e.to_string())?;
             let usage_count: i64 = r.get(3).map_err(|e: rusqlite::Error| e.to_string())?;
-            let now = chrono::Utc::now();
-            let last = chrono::DateTime::parse_from_rfc3339(&last_used)
-                .map(|dt| dt.with_timezone(&chrono::Utc))
-                .unwrap_or(now);
-            let age_days = (now - last).num_days() as f64;
-            let lambda = 0.023;
-            let decay = (-lambda * age_days).exp();
-            let freq_boost = (usage_count as f64 + 1.0).ln() * 0.05;
-            let adj = (confidence * decay + freq_boost).min(0.95);
+            let adj = effective_confidence(confidence, &last_used, usage_count, chrono::Utc::now());
             Ok(Some((column_letter, adj)))
         } else {
             Ok(None)
         }
     }
 
-    pub fn upsert_learned_mapping(
-        &self,
-        schema_hash: &str,
-        field_type: &str,
-        column_index: i32,
-        column_letter: &str,
-        action: &str,
-    ) -> Result<(), String> {
-        let conn = self.conn.lock().map_err(|e| e.to_string())?;
+    /// Every learned mapping of a schema as (field_type, column_letter, confidence), with the confidence
+    /// `get_learned_mapping` reports.
+    pub fn get_learned_mappings_for_schema(&self, schema_hash: &str) -> Result<Vec<(String, String, f64)>, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT field_type, column_letter, confidence, last_used, usage_count FROM learned_mappings WHERE schema_hash = ?",
+            )
+            .map_err(AppError::db)?;
+        let now = chrono::Utc::now();
+        let rows = stmt
+            .query_map(params![schema_hash], |r| {
+                let (confidence, last_used, usage_count): (f64, String, i64) = (r.get(2)?, r.get(3)?, r.get(4)?);
+                Ok((r.get(0)?, r.get(1)?, effective_confidence(confidence, &last_used, usage_count, now)))
+            })
+            .map_err(AppError::db)?;
+        rows.collect::<Result<Vec<_>, _>>().map_err(AppError::db)
+    }
+
+    pub fn upsert_learned_mapping(
+        &self,
+        schema_hash: &str,
+        field_type: &str,
+        column_index: i32,
+        column_letter: &str,
+        action: &str,
+    ) -> Result<(), AppError> {
+        let mut conn = self.conn.lock().map_err(AppError::db)?;
+        let now_at = chrono::Utc::now();
+        let now = now_at.to_rfc3339();
+        let (reward, base_conf): (f64, f64) = match action {
+            "ACCEPT" => (1.0, 0.85),
+            "REJECT" | "MANUAL_SELECT" => (-0.5, 0.70),
+            "EDIT" => (-0.2, 0.75),
+            _ => (0.0, 0.75),
+        };
+        let raw = base_conf + reward * 0.1_f64;
+        let confidence = raw.max(0.05).min(0.95);
+        let tx = conn.transaction().map_err(AppError::db)?;
+        let previous: Option<(f64, String, i64)> = match tx.query_row(
+            "SELECT confidence, last_used, usage_count FROM learned_mappings WHERE schema_hash = ? AND field_type = ?",
+            params![schema_hash, field_type],
+            |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)),
+        ) {
+            Ok(r) => Some(r),
+            Err(rusqlite::Error::QueryReturnedNoRows) => None,
+            Err(e) => return Err(AppError::db(e)),
+        };
+        let usage_count = previous.as_ref().map(|p| p.2 + 1).unwrap_or(1);
+        tx.execute(
+            "INSERT INTO learned_mappings (schema_hash, field_type, column_index, column_letter, confidence, usage_count, last_used)
+             VALUES (?1, ?2, ?3, ?4, ?5, 1, ?6)
+             ON CONFLICT(schema_hash, field_type) DO UPDATE SET
+               column_index = excluded.column_index,
+               column_letter = excluded.column_letter,
+               confidence = excluded.confidence,
+               usage_count = usage_count + 1,
+               last_used = excluded.last_used",
+            params![schema_hash, field_type, column_index, column_letter, confidence, now],
+        )
+        .map_err(AppError::db)?;
+        tx.execute(
+            "INSERT INTO learned_mapping_events (schema_hash, field_type, column_letter, action, reward, previous_confidence, effective_before, confidence, usage_count, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            params![
+                schema_hash,
+                field_type,
+                column_letter,
+                action,
+                reward,
+                previous.as_ref().map(|p| p.0),
+                previous.as_ref().map(|(c, last, n)| effective_confidence(*c, last, *n, now_at)),
+                confidence,
+                usage_count,
+                now
+            ],
+        )
+        .map_err(AppError::db)?;
+        tx.commit().map_err(AppError::db)?;
+        Ok(())
+    }
+
+    /// Confidence updates of learned mappings, newest first, optionally for one schema and/or field.
+    pub fn get_learned_mapping_history(
+        &self,
+        schema_hash: Option<&str>,
+        field_type: Option<&str>,
+        limit: u32,
+    ) -> Result<Vec<LearnedMappingEventRow>, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, created_at, schema_hash, field_type, column_letter, action, reward, previous_confidence, effective_before, confidence, usage_count
+                 FROM learned_mapping_events
+                 WHERE (?1 IS NULL OR schema_hash = ?1) AND (?2 IS NULL OR field_type = ?2)
+                 ORDER BY id DESC LIMIT ?3",
+            )
+            .map_err(AppError::db)?;
+        let rows = stmt
+            .query_map(params![schema_hash, field_type, limit], |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                    row.get(5)?,
+                    row.get(6)?,
+                    row.get(7)?,
+                    row.get(8)?,
+                    row.get(9)?,
+                    row.get(10)?,
+                ))
+            })
+            .map_err(AppError::db)?;
+        let mut out = Vec::new();
+        for row in rows {
+            out.push(row.map_err(AppError::db)?);
+        }
+        Ok(out)
+    }
+
+    pub fn update_history_status(
+        &self,
+        id: i64,
+        status: &str,
+        excel_profile_id: Option<i64>,
+        error_message: Option<&str>,
+    ) -> Result<(), AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        conn.execute(
+            "UPDATE history SET status = ?, excel_profile_id = ?, error_message = ? WHERE id = ?",
+            params![status, excel_profile_id, error_message, id],
+        )
+        .map_err(AppError::db)?;
+        Ok(())
+    }
+
+    /// Update a record; a change of its document type or extracted data is kept as an "edit" revision.
+    pub fn update_history_record(
+        &self,
+        id: i64,
+        document_type: &str,
+        file_path_or_name: &str,
+        extracted_data: &Value,
+        status: &str,
+        excel_profile_id: Option<i64>,
+        error_message: Option<&str>,
+    ) -> Result<(), AppError> {
+        let data_str = serde_json::to_string(extracted_data).map_err(AppError::db)?;
+        let mut conn = self.conn.lock().map_err(AppError::db)?;
+        let tx = conn.transaction().map_err(AppError::db)?;
+        record_history_revision(&tx, id, document_type, extracted_data, "edit")?;
+        tx.execute(
+            "UPDATE history SET document_type = ?, file_path_or_name = ?, extracted_data = ?, status = ?, excel_profile_id = ?, error_message = ? WHERE id = ?",
+            params![
+                document_type,
+                file_path_or_name,
+                data_str,
+                status,
+                excel_profile_id,
+                error_message,
+                id,
+            ],
+        )
+        .map_err(AppError::db)?;
+        tx.commit().map_err(AppError::db)
+    }
+
+    /// Move a record to the trash; it is hidden from history until restored or purged. The records of a
+    /// batch export go to the trash with it.
+    pub fn delete_history_record(&self, id: i64) -> Result<(), AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        conn.execute(
+            "UPDATE history SET deleted_at = ?1 WHERE deleted_at IS NULL AND (id = ?2 OR parent_id = ?2 AND EXISTS
+                 (SELECT 1 FROM history p WHERE p.id = ?2 AND p.document_type = 'batch_export'))",
+            params![chrono::Utc::now().to_rfc3339(), id],
+        )
+        .map_err(AppError::db)?;
+        Ok(())
+    }
+
+    /// Take a record out of the trash, with the batch export records trashed together with it; false when
+    /// it is not there.
+    pub fn restore_history_record(&self, id: i64) -> Result<bool, AppError> {
+        let mut conn = self.conn.lock().map_err(AppError::db)?;
+        let tx = conn.transaction().map_err(AppError::db)?;
+        let trashed = tx.query_row(
+            "SELECT document_type, deleted_at FROM history WHERE id = ? AND deleted_at IS NOT NULL",
+            params![id],
+            |r| Ok((r.get::<_, String>(0)?, r.get::<_, String>(1)?)),
+        );
+        let (document_type, deleted_at) = match trashed {
+            Ok(row) => row,
+            Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(false),
+            Err(e) => return Err(AppError::db(e)),
+        };
+        tx.execute("UPDATE history SET deleted_at = NULL WHERE id = ?", params![id])
+            .map_err(AppError::db)?;
+        if document_type == "batch_export" {
+            tx.execute(
+                "UPDATE history SET deleted_at = NULL WHERE parent_id = ? AND deleted_at = ?",
+                params![id, deleted_at],
+            )
+            .map_err(AppError::db)?;
+        }
+        tx.commit().map_err(AppError::db)?;
+        Ok(true)
+    }
+
+    /// Records in the trash, most recently deleted first.
+    pub fn get_trash(&self) -> Result<Vec<TrashRow>, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, created_at, document_type, file_path_or_name, status, excel_profile_id, deleted_at
+                 FROM history WHERE deleted_at IS NOT NULL ORDER BY deleted_at DESC, id DESC",
+            )
+            .map_err(AppError::db)?;
+        let rows = stmt
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?, r.get(4)?, r.get(5)?, r.get(6)?)))
+            .map_err(AppError::db)?;
+        rows.collect::<Result<Vec<_>, _>>().map_err(AppError::db)
+    }
+
+    /// Permanently delete trashed records deleted before `cutoff` (RFC 3339), with their edits, links,
+    /// revisions, archive entries and pending appends; all of the trash when None. Write log rows and child
+    /// records stay, without the reference. Returns how many were removed and the archived objects no
+    /// record refers to any more, for the caller to delete.
+    pub fn purge_trash(&self, cutoff: Option<&str>) -> Result<(usize, Vec<ArchivedObjectRow>), AppError> {
+        let mut conn = self.conn.lock().map_err(AppError::db)?;
+        let tx = conn.transaction().map_err(AppError::db)?;
+        let ids: Vec<i64> = {
+            let mut stmt = tx
+                .prepare("SELECT id FROM history WHERE deleted_at IS NOT NULL AND (?1 IS NULL OR deleted_at < ?1)")
+                .map_err(AppError::db)?;
+            let rows = stmt.query_map(params![cutoff], |r| r.get(0)).map_err(AppError::db)?;
+            rows.collect::<Result<Vec<_>, _>>().map_err(AppError::db)?
+        };
+        let mut objects: Vec<ArchivedObjectRow> = Vec::new();
+        for id in &ids {
+            tx.execute("DELETE FROM invoice_edits WHERE history_id = ?", params![id])
+                .map_err(AppError::db)?;
+            tx.execute("DELETE FROM history_revisions WHERE history_id = ?", params![id])
+                .map_err(AppError::db)?;
+            tx.execute("DELETE FROM document_links WHERE history_id = ?", params![id])
+                .map_err(AppError::db)?;
+            tx.execute("UPDATE document_links SET linked_history_id = NULL WHERE linked_history_id = ?", params![id])
+                .map_err(AppError::db)?;
+            {
+                let mut stmt = tx
+                    .prepare("SELECT DISTINCT backend, location, object_key FROM archived_documents WHERE history_id = ?")
+                    .map_err(AppError::db)?;
+                let rows = stmt
+                    .query_map(params![id], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))
+                    .map_err(AppError::db)?;
+                for row in rows {
+                    let row = row.map_err(AppError::db)?;
+                    if !objects.contains(&row) {
+                        objects.push(row);
+                    }
+                }
+            }
+            tx.execute("DELETE FROM archived_documents WHERE history_id = ?", params![id])
+                .map_err(AppError::db)?;
+            tx.execute("DELETE FROM pending_appends WHERE history_id = ?", params![id])
+                .map_err(AppError::db)?;
+            tx.execute("UPDATE write_log SET history_id = NULL WHERE history_id = ?", params![id])
+                .map_err(AppError::db)?;
+            tx.execute("UPDATE history SET parent_id = NULL WHERE parent_id = ?", params![id])
+                .map_err(AppError::db)?;
+            tx.execute("DELETE FROM history WHERE id = ?", params![id])
+                .map_err(AppError::db)?;
+        }
+        // Identical originals share one object; keep those another record still uses.
+        let mut orphaned = Vec::new();
+        for object in objects {
+            let still_used: bool = tx
+                .query_row(
+                    "SELECT EXISTS (SELECT 1 FROM archived_documents WHERE backend = ? AND location = ? AND object_key = ?)",
+                    params![object.0, object.1, object.2],
+                    |r| r.get(0),
+                )
+                .map_err(AppError::db)?;
+            if !still_used {
+                orphaned.push(object);
+            }
+        }
+        tx.commit().map_err(AppError::db)?;
+        Ok((ids.len(), orphaned))
+    }
+
+    pub fn set_history_source_path(&self, id: i64, source_path: &str) -> Result<(), AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        conn.execute("UPDATE history SET source_path = ? WHERE id = ?", params![source_path, id])
+            .map_err(AppError::db)?;
+        Ok(())
+    }
+
+    /// (document_type, file_path_or_name, source_path, archive_path) of a history record.
+    pub fn get_history_source(&self, id: i64) -> Result<Option<HistorySourceRow>, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let row = conn.query_row(
+            "SELECT document_type, file_path_or_name, source_path, archive_path FROM history WHERE id = ?",
+            params![id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        );
+        match row {
+            Ok(r) => Ok(Some(r)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(AppError::db(e)),
+        }
+    }
+
+    /// Replace the document type and extracted data of a record, keeping the current ones as a revision.
+    /// Returns the revision id, None when nothing changed.
+    pub fn revise_history_record(
+        &self,
+        id: i64,
+        document_type: &str,
+        extracted_data: &Value,
+        reason: &str,
+    ) -> Result<Option<i64>, AppError> {
+        let data_str = serde_json::to_string(extracted_data).map_err(AppError::db)?;
+        let mut conn = self.conn.lock().map_err(AppError::db)?;
+        let tx = conn.transaction().map_err(AppError::db)?;
+        let revision_id = record_history_revision(&tx, id, document_type, extracted_data, reason)?;
+        tx.execute(
+            "UPDATE history SET document_type = ?, extracted_data = ? WHERE id = ?",
+            params![document_type, data_str, id],
+        )
+        .map_err(AppError::db)?;
+        tx.commit().map_err(AppError::db)?;
+        Ok(revision_id)
+    }
+
+    /// One revision of a record.
+    pub fn get_history_revision(&self, history_id: i64, revision_id: i64) -> Result<Option<HistoryRevisionRow>, AppError> {
+        Ok(self
+            .get_history_revisions(history_id)?
+            .into_iter()
+            .find(|(id, ..)| *id == revision_id))
+    }
+
+    /// Earlier versions of a record's extracted data, newest first.
+    pub fn get_history_revisions(&self, history_id: i64) -> Result<Vec<HistoryRevisionRow>, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, document_type, extracted_data, diff, reason, created_at FROM history_revisions WHERE history_id = ? ORDER BY id DESC",
+            )
+            .map_err(AppError::db)?;
+        let rows = stmt
+            .query_map(params![history_id], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?))
+            })
+            .map_err(AppError::db)?;
+        rows.collect::<Result<Vec<_>, _>>().map_err(AppError::db)
+    }
+
+    /// Newest other history record whose invoice or document number is `number` (case-insensitive).
+    pub fn find_history_by_document_number(&self, number: &str, exclude_id: i64) -> Result<Option<i64>, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let row = conn.query_row(
+            "SELECT id FROM history WHERE id != ?1 AND deleted_at IS NULL AND json_valid(extracted_data) AND (
+                 lower(trim(json_extract(extracted_data, '$.invoice_number'))) = lower(?2)
+                 OR lower(trim(json_extract(extracted_data, '$.document_number'))) = lower(?2))
+             ORDER BY id DESC LIMIT 1",
+            params![exclude_id, number.trim()],
+            |r| r.get(0),
+        );
+        match row {
+            Ok(id) => Ok(Some(id)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(AppError::db(e)),
+        }
+    }
+
+    pub fn add_document_link(
+        &self,
+        history_id: i64,
+        link_type: &str,
+        linked_number: &str,
+        linked_history_id: Option<i64>,
+    ) -> Result<i64, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        conn.execute(
+            "INSERT INTO document_links (history_id, link_type, linked_number, linked_history_id, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![history_id, link_type, linked_number, linked_history_id, chrono::Utc::now().to_rfc3339()],
+        )
+        .map_err(AppError::db)?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    /// Links from or to a history record, oldest first.
+    pub fn get_document_links(&self, history_id: i64) -> Result<Vec<DocumentLinkRow>, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, history_id, link_type, linked_number, linked_history_id, created_at FROM document_links
+                 WHERE history_id = ?1 OR linked_history_id = ?1 ORDER BY id",
+            )
+            .map_err(AppError::db)?;
+        let rows = stmt
+            .query_map(params![history_id], |r| {
+                Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?, r.get(4)?, r.get(5)?))
+            })
+            .map_err(AppError::db)?;
+        let mut out = Vec::new();
+        for row in rows {
+            out.push(row.map_err(AppError::db)?);
+        }
+        Ok(out)
+    }
+
+    /// Open a scan session; a session still open (e.g. after a crash) is ended first.
+    pub fn start_scan_session(&self, operator: &str, note: Option<&str>) -> Result<i64, AppError> {
         let now = chrono::Utc::now().to_rfc3339();
-        let (reward, base_conf): (f64, f64) = match action {
-            "ACCEPT" => (1.0, 0.85),
-            "REJECT" | "MANUAL_SELECT" => (-0.5, 0.70),
-            "EDIT" => (-0.2, 0.75),
-            _ => (0.0, 0.75),
-        };
-        let raw = base_conf + reward * 0.1_f64;
-        let confidence = raw.max(0.05).min(0.95);
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        conn.execute("UPDATE scan_sessions SET ended_at = ? WHERE ended_at IS NULL", params![now])
+            .map_err(AppError::db)?;
         conn.execute(
-            "INSERT INTO learned_mappings (schema_hash, field_type, column_index, column_letter, confidence, usage_count, last_used)
-             VALUES (?1, ?2, ?3, ?4, ?5, 1, ?6)
-             ON CONFLICT(schema_hash, field_type) DO UPDATE SET
-               column_index = excluded.column_index,
-               column_letter = excluded.column_letter,
-               confidence = excluded.confidence,
-               usage_count = usage_count + 1,
-               last_used = excluded.last_used",
-            params![schema_hash, field_type, column_index, column_letter, confidence, now],
+            "INSERT INTO scan_sessions (operator, note, started_at) VALUES (?, ?, ?)",
+            params![operator, note, now],
         )
-        .map_err(|e| e.to_string())?;
-        Ok(())
+        .map_err(AppError::db)?;
+        Ok(conn.last_insert_rowid())
+    }
+    /// End a session; returns false when it was not open.
+    pub fn end_scan_session(&self, id: i64) -> Result<bool, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let changed = conn
+            .execute(
+                "UPDATE scan_sessions SET ended_at = ? WHERE id = ? AND ended_at IS NULL",
+                params![chrono::Utc::now().to_rfc3339(), id],
+            )
+            .map_err(AppError::db)?;
+        Ok(changed > 0)
+    }
+    /// Sessions with their document counts, newest first; `open_only` returns just the open one.
+    pub fn list_scan_sessions(&self, open_only: bool, limit: u32) -> Result<Vec<ScanSessionRow>, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT s.id, s.operator, s.note, s.started_at, s.ended_at, COUNT(h.id),
+                        COUNT(CASE WHEN h.status = 'added_to_excel' THEN 1 END),
+                        COUNT(CASE WHEN h.status = 'error' THEN 1 END)
+                 FROM scan_sessions s LEFT JOIN history h ON h.session_id = s.id AND h.deleted_at IS NULL
+                 WHERE ?1 = 0 OR s.ended_at IS NULL
+                 GROUP BY s.id ORDER BY s.id DESC LIMIT ?2",
+            )
+            .map_err(AppError::db)?;
+        let rows = stmt
+            .query_map(params![open_only, limit], |r| {
+                Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?, r.get(4)?, r.get(5)?, r.get(6)?, r.get(7)?))
+            })
+            .map_err(AppError::db)?;
+        rows.collect::<Result<Vec<_>, _>>().map_err(AppError::db)
     }
 
-    pub fn update_history_status(
+    /// Record a batch append; `rows`, `history_ids` and `column_overrides` are JSON.
+    pub fn add_export_manifest(
         &self,
-        id: i64,
-        status: &str,
-        excel_profile_id: Option<i64>,
-        error_message: Option<&str>,
-    ) -> Result<(), String> {
-        let conn = self.conn.lock().map_err(|e| e.to_string())?;
+        profile_id: i64,
+        file_path: &str,
+        sheet_name: &str,
+        rows: &str,
+        history_ids: &str,
+        column_overrides: Option<&str>,
+    ) -> Result<i64, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
         conn.execute(
-            "UPDATE history SET status = ?, excel_profile_id = ?, error_message = ? WHERE id = ?",
-            params![status, excel_profile_id, error_message, id],
+            "INSERT INTO export_manifests (profile_id, file_path, sheet_name, rows, history_ids, column_overrides, created_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?)",
+            params![profile_id, file_path, sheet_name, rows, history_ids, column_overrides, chrono::Utc::now().to_rfc3339()],
+        )
+        .map_err(AppError::db)?;
+        Ok(conn.last_insert_rowid())
+    }
+    /// Batch appends, newest first; only those of `profile_id` when given.
+    pub fn list_export_manifests(&self, profile_id: Option<i64>, limit: u32) -> Result<Vec<ExportManifestRow>, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, profile_id, file_path, sheet_name, rows, history_ids, column_overrides, created_at
+                 FROM export_manifests WHERE ?1 IS NULL OR profile_id = ?1 ORDER BY id DESC LIMIT ?2",
+            )
+            .map_err(AppError::db)?;
+        let rows = stmt
+            .query_map(params![profile_id, limit], |r| {
+                Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?, r.get(4)?, r.get(5)?, r.get(6)?, r.get(7)?))
+            })
+            .map_err(AppError::db)?;
+        rows.collect::<Result<Vec<_>, _>>().map_err(AppError::db)
+    }
+
+    /// Queue a ledger row for a later append; `invoice_data` is the document as JSON.
+    pub fn add_pending_append(
+        &self,
+        profile_id: i64,
+        invoice_data: &str,
+        history_id: Option<i64>,
+        reason: &str,
+        error: Option<&str>,
+    ) -> Result<i64, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let now = chrono::Utc::now().to_rfc3339();
+        conn.execute(
+            "INSERT INTO pending_appends (profile_id, invoice_data, history_id, reason, last_error, attempts, created_at, last_attempt_at)
+             VALUES (?, ?, ?, ?, ?, 1, ?, ?)",
+            params![profile_id, invoice_data, history_id, reason, error, now, now],
+        )
+        .map_err(AppError::db)?;
+        Ok(conn.last_insert_rowid())
+    }
+    /// Queued rows, oldest first; only those of `profile_id` when given.
+    pub fn list_pending_appends(&self, profile_id: Option<i64>) -> Result<Vec<PendingAppendRow>, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, profile_id, invoice_data, history_id, reason, last_error, attempts, created_at, last_attempt_at
+                 FROM pending_appends WHERE ?1 IS NULL OR profile_id = ?1 ORDER BY id",
+            )
+            .map_err(AppError::db)?;
+        let rows = stmt
+            .query_map(params![profile_id], |r| {
+                Ok((
+                    r.get(0)?,
+                    r.get(1)?,
+                    r.get(2)?,
+                    r.get(3)?,
+                    r.get(4)?,
+                    r.get(5)?,
+                    r.get(6)?,
+                    r.get(7)?,
+                    r.get(8)?,
+                ))
+            })
+            .map_err(AppError::db)?;
+        rows.collect::<Result<Vec<_>, _>>().map_err(AppError::db)
+    }
+    /// Record a failed retry of a queued row.
+    pub fn record_pending_append_attempt(&self, id: i64, reason: &str, error: &str) -> Result<(), AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        conn.execute(
+            "UPDATE pending_appends SET reason = ?, last_error = ?, attempts = attempts + 1, last_attempt_at = ? WHERE id = ?",
+            params![reason, error, chrono::Utc::now().to_rfc3339(), id],
         )
-        .map_err(|e| e.to_string())?;
+        .map_err(AppError::db)?;
+        Ok(())
+    }
+    pub fn delete_pending_append(&self, id: i64) -> Result<(), AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        conn.execute("DELETE FROM pending_appends WHERE id = ?", params![id])
+            .map_err(AppError::db)?;
         Ok(())
     }
 
-    pub fn update_history_record(
+    /// Record hand corrections for a history record as (field_key, original_value, corrected_value).
+    pub fn add_invoice_edits(
         &self,
-        id: i64,
+        history_id: i64,
         document_type: &str,
-        file_path_or_name: &str,
-        extracted_data: &Value,
-        status: &str,
-        excel_profile_id: Option<i64>,
-        error_message: Option<&str>,
-    ) -> Result<(), String> {
-        let data_str = serde_json::to_string(extracted_data).map_err(|e| e.to_string())?;
-        let conn = self.conn.lock().map_err(|e| e.to_string())?;
+        edits: &[(String, Option<String>, String)],
+    ) -> Result<(), AppError> {
+        if edits.is_empty() {
+            return Ok(());
+        }
+        let created_at = chrono::Utc::now().to_rfc3339();
+        let mut conn = self.conn.lock().map_err(AppError::db)?;
+        let tx = conn.transaction().map_err(AppError::db)?;
+        {
+            let mut stmt = tx
+                .prepare(
+                    "INSERT INTO invoice_edits (history_id, document_type, field_key, original_value, corrected_value, created_at)
+                     VALUES (?, ?, ?, ?, ?, ?)",
+                )
+                .map_err(AppError::db)?;
+            for (field_key, original_value, corrected_value) in edits {
+                stmt.execute(params![history_id, document_type, field_key, original_value, corrected_value, created_at])
+                    .map_err(AppError::db)?;
+            }
+        }
+        tx.commit().map_err(AppError::db)?;
+        Ok(())
+    }
+
+    /// Remember that `field_key` of vendor `seller_edb` was corrected; a repeated correction to the same
+    /// value raises its count, a different value replaces it.
+    pub fn upsert_vendor_correction(
+        &self,
+        seller_edb: &str,
+        field_key: &str,
+        original_value: Option<&str>,
+        corrected_value: &str,
+    ) -> Result<(), AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
         conn.execute(
-            "UPDATE history SET document_type = ?, file_path_or_name = ?, extracted_data = ?, status = ?, excel_profile_id = ?, error_message = ? WHERE id = ?",
+            "INSERT INTO vendor_corrections (seller_edb, field_key, original_value, corrected_value, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT(seller_edb, field_key) DO UPDATE SET
+                original_value = excluded.original_value,
+                correction_count = CASE WHEN corrected_value = excluded.corrected_value
+                    THEN correction_count + 1 ELSE 1 END,
+                corrected_value = excluded.corrected_value,
+                updated_at = excluded.updated_at",
+            params![seller_edb, field_key, original_value, corrected_value, chrono::Utc::now().to_rfc3339()],
+        )
+        .map_err(AppError::db)?;
+        Ok(())
+    }
+    /// Learned corrections, for one vendor or all of them.
+    pub fn get_vendor_corrections(&self, seller_edb: Option<&str>) -> Result<Vec<VendorCorrectionRow>, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT seller_edb, field_key, original_value, corrected_value, correction_count, applied_count, updated_at
+                 FROM vendor_corrections WHERE ?1 IS NULL OR seller_edb = ?1 ORDER BY seller_edb, field_key",
+            )
+            .map_err(AppError::db)?;
+        let rows = stmt
+            .query_map(params![seller_edb], |r| {
+                Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?, r.get(4)?, r.get(5)?, r.get(6)?))
+            })
+            .map_err(AppError::db)?;
+        let mut out = Vec::new();
+        for row in rows {
+            out.push(row.map_err(AppError::db)?);
+        }
+        Ok(out)
+    }
+    pub fn mark_vendor_correction_applied(&self, seller_edb: &str, field_key: &str) -> Result<(), AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        conn.execute(
+            "UPDATE vendor_corrections SET applied_count = applied_count + 1 WHERE seller_edb = ? AND field_key = ?",
+            params![seller_edb, field_key],
+        )
+        .map_err(AppError::db)?;
+        Ok(())
+    }
+    pub fn delete_vendor_correction(&self, seller_edb: &str, field_key: &str) -> Result<bool, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let n = conn
+            .execute(
+                "DELETE FROM vendor_corrections WHERE seller_edb = ? AND field_key = ?",
+                params![seller_edb, field_key],
+            )
+            .map_err(AppError::db)?;
+        Ok(n > 0)
+    }
+
+    /// All vendors, by name.
+    pub fn get_vendors(&self) -> Result<Vec<VendorRow>, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, name, tax_id, address, default_currency, aliases, created_at, updated_at
+                 FROM vendors ORDER BY name COLLATE NOCASE",
+            )
+            .map_err(AppError::db)?;
+        let rows = stmt
+            .query_map([], |r| {
+                Ok(VendorRow {
+                    id: r.get(0)?,
+                    name: r.get(1)?,
+                    tax_id: r.get(2)?,
+                    address: r.get(3)?,
+                    default_currency: r.get(4)?,
+                    aliases: r.get(5)?,
+                    created_at: r.get(6)?,
+                    updated_at: r.get(7)?,
+                })
+            })
+            .map_err(AppError::db)?;
+        let mut out = Vec::new();
+        for row in rows {
+            out.push(row.map_err(AppError::db)?);
+        }
+        Ok(out)
+    }
+    /// Insert a vendor (`row.id` ignored) or update the one with `row.id`; returns the id.
+    pub fn save_vendor(&self, row: &VendorRow) -> Result<i64, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let now = chrono::Utc::now().to_rfc3339();
+        if row.id > 0 {
+            let n = conn
+                .execute(
+                    "UPDATE vendors SET name = ?1, tax_id = ?2, address = ?3, default_currency = ?4, aliases = ?5,
+                     updated_at = ?6 WHERE id = ?7",
+                    params![row.name, row.tax_id, row.address, row.default_currency, row.aliases, now, row.id],
+                )
+                .map_err(AppError::db)?;
+            if n == 0 {
+                return Err(AppError::Db(format!("Vendor {} not found.", row.id)));
+            }
+            Ok(row.id)
+        } else {
+            conn.execute(
+                "INSERT INTO vendors (name, tax_id, address, default_currency, aliases, created_at, updated_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?6)",
+                params![row.name, row.tax_id, row.address, row.default_currency, row.aliases, now],
+            )
+            .map_err(AppError::db)?;
+            Ok(conn.last_insert_rowid())
+        }
+    }
+    pub fn delete_vendor(&self, id: i64) -> Result<bool, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let n = conn
+            .execute("DELETE FROM vendors WHERE id = ?", params![id])
+            .map_err(AppError::db)?;
+        Ok(n > 0)
+    }
+
+    pub fn get_document_types(&self) -> Result<Vec<DocumentTypeRow>, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT key, display_name, model_id, extractor, default_folder, export_layout, builtin, updated_at
+                 FROM document_types ORDER BY builtin DESC, key",
+            )
+            .map_err(AppError::db)?;
+        let rows = stmt
+            .query_map([], |r| {
+                Ok(DocumentTypeRow {
+                    key: r.get(0)?,
+                    display_name: r.get(1)?,
+                    model_id: r.get(2)?,
+                    extractor: r.get(3)?,
+                    default_folder: r.get(4)?,
+                    export_layout: r.get(5)?,
+                    builtin: r.get::<_, i64>(6)? != 0,
+                    updated_at: r.get(7)?,
+                })
+            })
+            .map_err(AppError::db)?;
+        let mut out = Vec::new();
+        for row in rows {
+            out.push(row.map_err(AppError::db)?);
+        }
+        Ok(out)
+    }
+    /// Insert or update a document type by key (`builtin` and `updated_at` of the row are ignored).
+    pub fn save_document_type(&self, row: &DocumentTypeRow) -> Result<(), AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        conn.execute(
+            "INSERT INTO document_types (key, display_name, model_id, extractor, default_folder, export_layout, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+             ON CONFLICT(key) DO UPDATE SET display_name = ?2, model_id = ?3, extractor = ?4, default_folder = ?5,
+             export_layout = ?6, updated_at = ?7",
             params![
-                document_type,
-                file_path_or_name,
-                data_str,
-                status,
-                excel_profile_id,
-                error_message,
-                id,
+                row.key,
+                row.display_name,
+                row.model_id,
+                row.extractor,
+                row.default_folder,
+                row.export_layout,
+                chrono::Utc::now().to_rfc3339()
             ],
         )
-        .map_err(|e| e.to_string())?;
+        .map_err(AppError::db)?;
+        Ok(())
+    }
+    /// Delete a user-added document type; built-in types are kept.
+    pub fn delete_document_type(&self, key: &str) -> Result<bool, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let n = conn
+            .execute("DELETE FROM document_types WHERE key = ? AND builtin = 0", params![key])
+            .map_err(AppError::db)?;
+        Ok(n > 0)
+    }
+
+    /// Stored field schemas as (document_type, fields JSON, updated_at).
+    pub fn get_field_schemas(&self) -> Result<Vec<(String, String, String)>, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let mut stmt = conn
+            .prepare("SELECT document_type, fields, updated_at FROM field_schemas ORDER BY document_type")
+            .map_err(AppError::db)?;
+        let rows = stmt
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))
+            .map_err(AppError::db)?;
+        let mut out = Vec::new();
+        for row in rows {
+            out.push(row.map_err(AppError::db)?);
+        }
+        Ok(out)
+    }
+    /// Stored (fields JSON, updated_at) of one document type.
+    pub fn get_field_schema(&self, document_type: &str) -> Result<Option<(String, String)>, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let row = conn.query_row(
+            "SELECT fields, updated_at FROM field_schemas WHERE document_type = ?",
+            params![document_type],
+            |r| Ok((r.get(0)?, r.get(1)?)),
+        );
+        match row {
+            Ok(r) => Ok(Some(r)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(AppError::db(e)),
+        }
+    }
+    pub fn save_field_schema(&self, document_type: &str, fields: &str) -> Result<(), AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        conn.execute(
+            "INSERT INTO field_schemas (document_type, fields, updated_at) VALUES (?1, ?2, ?3)
+             ON CONFLICT(document_type) DO UPDATE SET fields = ?2, updated_at = ?3",
+            params![document_type, fields, chrono::Utc::now().to_rfc3339()],
+        )
+        .map_err(AppError::db)?;
         Ok(())
     }
+    pub fn delete_field_schema(&self, document_type: &str) -> Result<bool, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let n = conn
+            .execute("DELETE FROM field_schemas WHERE document_type = ?", params![document_type])
+            .map_err(AppError::db)?;
+        Ok(n > 0)
+    }
 
-    pub fn delete_history_record(&self, id: i64) -> Result<(), String> {
-        let conn = self.conn.lock().map_err(|e| e.to_string())?;
-        conn.execute("DELETE FROM history WHERE id = ?", params![id])
-            .map_err(|e| e.to_string())?;
+    /// Custom field mappings as (model_id, azure_key, field_key), for one model or all of them.
+    pub fn get_model_field_mappings(&self, model_id: Option<&str>) -> Result<Vec<(String, String, String)>, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT model_id, azure_key, field_key FROM model_field_mappings
+                 WHERE ?1 IS NULL OR model_id = ?1 ORDER BY model_id, azure_key",
+            )
+            .map_err(AppError::db)?;
+        let rows = stmt
+            .query_map(params![model_id], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))
+            .map_err(AppError::db)?;
+        let mut out = Vec::new();
+        for row in rows {
+            out.push(row.map_err(AppError::db)?);
+        }
+        Ok(out)
+    }
+    /// Replace all custom mappings of a model with (azure_key, field_key) pairs.
+    pub fn replace_model_field_mappings(&self, model_id: &str, mappings: &[(String, String)]) -> Result<(), AppError> {
+        let mut conn = self.conn.lock().map_err(AppError::db)?;
+        let tx = conn.transaction().map_err(AppError::db)?;
+        tx.execute("DELETE FROM model_field_mappings WHERE model_id = ?", params![model_id])
+            .map_err(AppError::db)?;
+        for (azure_key, field_key) in mappings {
+            tx.execute(
+                "INSERT INTO model_field_mappings (model_id, azure_key, field_key) VALUES (?1, ?2, ?3)",
+                params![model_id, azure_key, field_key],
+            )
+            .map_err(AppError::db)?;
+        }
+        tx.commit().map_err(AppError::db)?;
         Ok(())
     }
 
-    pub fn clear_learned_mappings(&self) -> Result<u64, String> {
-        let conn = self.conn.lock().map_err(|e| e.to_string())?;
+    /// Corrections recorded for a history record, oldest first.
+    pub fn get_invoice_edits(&self, history_id: i64) -> Result<Vec<InvoiceEditRow>, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, history_id, document_type, field_key, original_value, corrected_value, created_at
+                 FROM invoice_edits WHERE history_id = ? ORDER BY id",
+            )
+            .map_err(AppError::db)?;
+        let rows = stmt
+            .query_map(params![history_id], |r| {
+                Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?, r.get(4)?, r.get(5)?, r.get(6)?))
+            })
+            .map_err(AppError::db)?;
+        let mut out = Vec::new();
+        for row in rows {
+            out.push(row.map_err(AppError::db)?);
+        }
+        Ok(out)
+    }
+
+    pub fn clear_learned_mappings(&self) -> Result<u64, AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
         let count = conn
             .execute("DELETE FROM learned_mappings", [])
-            .map_err(|e| e.to_string())?;
+            .map_err(AppError::db)?;
+        conn.execute("DELETE FROM learned_mapping_events", [])
+            .map_err(AppError::db)?;
         Ok(count as u64)
     }
 }
 
+/// Confidence `get_learned_mapping` reports: the stored value decayed by age (λ = 0.023 per day) plus a
+/// small boost for frequent use, capped at 0.95.
+fn effective_confidence(confidence: f64, last_used: &str, usage_count: i64, now: chrono::DateTime<chrono::Utc>) -> f64 {
+    let last = chrono::DateTime::parse_from_rfc3339(last_used)
+        .map(|dt| dt.with_timezone(&chrono::Utc))
+        .unwrap_or(now);
+    let age_days = (now - last).num_days() as f64;
+    let lambda = 0.023;
+    let decay = (-lambda * age_days).exp();
+    let freq_boost = (usage_count as f64 + 1.0).ln() * 0.05;
+    (confidence * decay + freq_boost).min(0.95)
+}
+
 fn norm_header(s: &str) -> String {
     s.trim().to_lowercase()
 }
@@ -850,14 +3095,14 @@ fn column_index_to_letter(index: u16) -> String {
 impl Db {
     /// Seed default profiles when DB has no profiles. For Даночен биланс we scan the template
     /// to detect header row and save the full Excel schema so export matches the template exactly.
-    fn seed_default_profiles_if_empty(&self, db_path: &PathBuf) -> Result<(), String> {
-        let conn = self.conn.lock().map_err(|e| e.to_string())?;
+    fn seed_default_profiles_if_empty(&self, db_path: &PathBuf) -> Result<(), AppError> {
+        let conn = self.conn.lock().map_err(AppError::db)?;
 
         let templates_dir = db_path
             .parent()
             .unwrap_or(Path::new("."))
             .join("templates");
-        fs::create_dir_all(&templates_dir).map_err(|e| e.to_string())?;
+        fs::create_dir_all(&templates_dir).map_err(AppError::db)?;
 
         let examples_root = find_repo_root_with_examples().map(|repo_root| {
             repo_root
@@ -903,7 +3148,7 @@ impl Db {
                     mapping
                 ],
             )
-            .map_err(|e| e.to_string())?;
+            .map_err(AppError::db)?;
         }
 
         // -------- Tax balance (Даночен биланс) template --------
@@ -979,13 +3224,14 @@ impl Db {
                             mapping
                         ],
                     )
-                    .map_err(|e| e.to_string())?;
+                    .map_err(AppError::db)?;
                     let profile_id = conn.last_insert_rowid();
                     let schema = ExcelSchema {
                         header_row,
                         first_data_row: header_row + 1,
-                        last_data_row,
+                        last_data_row: last_data_row.last_row,
                         next_free_row,
+                        last_row_truncated: last_data_row.truncated,
                         total_rows,
                         total_columns,
                         headers: schema_headers,
@@ -1030,7 +3276,7 @@ impl Db {
                             mapping
                         ],
                     )
-                    .map_err(|e| e.to_string())?;
+                    .map_err(AppError::db)?;
                 }
             }
         }
@@ -1072,7 +3318,7 @@ impl Db {
                     plati_mapping
                 ],
             )
-            .map_err(|e| e.to_string())?;
+            .map_err(AppError::db)?;
         } else {
             let _ = conn.execute(
                 "UPDATE profiles SET excel_path = ?, sheet_name = ?, column_mapping = ? WHERE name = ?",
@@ -1112,8 +3358,288 @@ impl Db {
                     mapping
                 ],
             )
-            .map_err(|e| e.to_string())?;
+            .map_err(AppError::db)?;
         }
         Ok(())
     }
 }
+
+/// (id, created_at, document_type, file_path_or_name, extracted_data, status, excel_profile_id, error_message)
+pub type HistoryRow = (i64, String, String, String, String, String, Option<i64>, Option<String>);
+
+/// (id, name, created_at, parent_id)
+pub type FolderRow = (i64, String, String, Option<i64>);
+
+/// Query for the ids of the folder bound to `param` (e.g. "?1") and every folder below it.
+fn folder_subtree(param: &str) -> String {
+    format!(
+        "WITH RECURSIVE subtree(id) AS (SELECT {} UNION SELECT f.id FROM folders f JOIN subtree s ON f.parent_id = s.id)
+         SELECT id FROM subtree",
+        param
+    )
+}
+
+/// (id, created_at, document_type, file_path_or_name, status, excel_profile_id, deleted_at)
+pub type TrashRow = (i64, String, String, String, String, Option<i64>, String);
+
+/// (id, created_at, document_type, file_path_or_name, status, excel_profile_id, snippet, rank); lower rank is better.
+pub type HistorySearchRow = (i64, String, String, String, String, Option<i64>, String, f64);
+/// (id, from_version, to_version, backup_path, created_at)
+pub type MigrationBackupRow = (i64, i64, i64, String, String);
+/// (id, name, monthly_budget, created_at)
+pub type CategoryRow = (i64, String, Option<f64>, String);
+/// (id, created_at, document_type, file_path_or_name, extracted_data, status)
+pub type HistoryReportRow = (i64, String, String, String, String, String);
+/// (id, profile_id, file_path, sha256, size_bytes, reason, created_at)
+pub type LedgerSnapshotRow = (i64, i64, String, String, u64, String, String);
+/// (document_type, file_path_or_name, source_path, archive_path)
+pub type HistorySourceRow = (String, String, Option<String>, Option<String>);
+
+/// (id, document_type, extracted_data JSON before the change, diff JSON, reason, created_at)
+pub type HistoryRevisionRow = (i64, String, String, String, String, String);
+
+/// (backend, location, object_key, file_name, size_bytes, created_at, sha256, archive_path)
+pub type ArchivedDocumentRow = (String, String, String, String, u64, String, Option<String>, Option<String>);
+
+/// (backend, location, object_key)
+pub type ArchivedObjectRow = (String, String, String);
+
+/// (id, source_path, destination, sha256, size_bytes, status, attempts, error, created_at, profile_id)
+pub type ExportDeliveryRow = (i64, String, String, String, u64, String, u32, Option<String>, String, Option<i64>);
+
+/// (id, event, url, profile_id, payload, status, attempts, response_status, error, created_at, updated_at)
+pub type WebhookDeliveryRow =
+    (i64, String, String, Option<i64>, String, String, u32, Option<u16>, Option<String>, String, String);
+
+/// (id, sender, subject, received_at, file_name, file_path, status, created_at)
+pub type EmailIntakeRow =
+    (i64, Option<String>, Option<String>, Option<String>, String, String, String, String);
+
+/// An attachment saved from the intake mailbox, for `add_email_intake_item`.
+pub struct NewEmailIntakeItem {
+    pub mailbox: String,
+    pub uid_validity: u32,
+    pub uid: u32,
+    /// Position of the attachment in the message.
+    pub part: u32,
+    pub sender: Option<String>,
+    pub subject: Option<String>,
+    pub received_at: Option<String>,
+    pub file_name: String,
+    pub file_path: String,
+}
+
+/// Row of `profile_delivery_targets`; `kind` is "share" or "sftp".
+#[derive(Debug, Clone, Default)]
+pub struct ProfileDeliveryRow {
+    pub kind: String,
+    pub share_path: Option<String>,
+    pub sftp_host: Option<String>,
+    pub sftp_port: Option<u16>,
+    pub sftp_username: Option<String>,
+    pub sftp_directory: Option<String>,
+    pub sftp_host_fingerprint: Option<String>,
+}
+
+pub type WriteLogRow = (i64, String, String, String, u32, Option<String>, String, Option<i64>);
+/// (id, created_at, schema_hash, field_type, column_letter, action, reward, previous_confidence, effective_before,
+/// confidence, usage_count); effective_before is the decayed confidence the mapping had when it was updated.
+pub type LearnedMappingEventRow = (i64, String, String, String, String, String, f64, Option<f64>, Option<f64>, f64, i64);
+/// (id, history_id, document_type, field_key, original_value, corrected_value, created_at)
+pub type InvoiceEditRow = (i64, i64, String, String, Option<String>, String, String);
+/// (seller_edb, field_key, original_value, corrected_value, correction_count, applied_count, updated_at)
+pub type VendorCorrectionRow = (String, String, Option<String>, String, u32, u32, String);
+/// (id, history_id, link_type, linked_number, linked_history_id, created_at)
+pub type DocumentLinkRow = (i64, i64, String, String, Option<i64>, String);
+/// (id, operator, note, started_at, ended_at, documents, exported, errors)
+pub type ScanSessionRow = (i64, String, Option<String>, String, Option<String>, u32, u32, u32);
+/// (id, profile_id, file_path, sheet_name, rows, history_ids, column_overrides, created_at)
+pub type ExportManifestRow = (i64, i64, String, String, String, String, Option<String>, String);
+/// (id, profile_id, invoice_data, history_id, reason, last_error, attempts, created_at, last_attempt_at)
+pub type PendingAppendRow = (i64, i64, String, Option<i64>, String, Option<String>, u32, String, Option<String>);
+
+/// Row of `document_types`.
+#[derive(Debug, Clone, Default)]
+pub struct DocumentTypeRow {
+    pub key: String,
+    pub display_name: String,
+    pub model_id: Option<String>,
+    pub extractor: String,
+    pub default_folder: Option<String>,
+    pub export_layout: String,
+    pub builtin: bool,
+    pub updated_at: String,
+}
+
+/// Row of `vendors`; `aliases` is the JSON array as stored, `id` is 0 for a vendor not saved yet.
+#[derive(Debug, Clone, Default)]
+pub struct VendorRow {
+    pub id: i64,
+    pub name: String,
+    pub tax_id: Option<String>,
+    pub address: Option<String>,
+    pub default_currency: Option<String>,
+    pub aliases: String,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// SQL expression for the searchable text of an extracted_data column: its string values joined,
+/// or the raw text when it is not JSON.
+fn fts_text_sql(column: &str) -> String {
+    format!(
+        "CASE WHEN json_valid({c}) THEN (SELECT group_concat(value, ' ') FROM json_tree({c}) WHERE type = 'text') ELSE {c} END",
+        c = column
+    )
+}
+
+/// FTS5 query from user input: every word quoted (so operators and punctuation are literal) and prefix-matched.
+fn fts_match_expr(query: &str) -> String {
+    query
+        .split_whitespace()
+        .map(|w| format!("\"{}\"*", w.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Parent of folder `id`; Validation when there is no such folder.
+fn ensure_folder_exists(conn: &Connection, id: i64) -> Result<Option<i64>, AppError> {
+    match conn.query_row("SELECT parent_id FROM folders WHERE id = ?", params![id], |r| r.get(0)) {
+        Ok(parent_id) => Ok(parent_id),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Err(AppError::Validation(format!("Folder {} not found.", id))),
+        Err(e) => Err(AppError::db(e)),
+    }
+}
+
+fn ensure_folder_name_free(conn: &Connection, parent_id: Option<i64>, name: &str, except_id: Option<i64>) -> Result<(), AppError> {
+    let taken: bool = conn
+        .query_row(
+            "SELECT EXISTS(SELECT 1 FROM folders WHERE IFNULL(parent_id, 0) = IFNULL(?1, 0) AND name = ?2 AND id != IFNULL(?3, 0))",
+            params![parent_id, name, except_id],
+            |r| r.get(0),
+        )
+        .map_err(AppError::db)?;
+    if taken {
+        return Err(AppError::Validation(format!("A folder named \"{}\" already exists there.", name)));
+    }
+    Ok(())
+}
+
+/// WHERE clause (with leading space) and its parameters for the History list filters.
+/// folder_id: None = all, Some(-1) = uncategorized (NULL), Some(id) = specific folder.
+fn history_filter(
+    search: Option<&str>,
+    folder_id: Option<i64>,
+    include_subfolders: bool,
+    top_level_only: bool,
+    session_id: Option<i64>,
+) -> (String, Vec<Box<dyn rusqlite::ToSql>>) {
+    let mut conditions: Vec<String> = vec!["deleted_at IS NULL".to_string()];
+    let mut params: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
+    if let Some(s) = search {
+        params.push(Box::new(format!("%{}%", s)));
+        conditions.push("(file_path_or_name LIKE ?1 OR extracted_data LIKE ?1)".to_string());
+    }
+    match folder_id {
+        None => {}
+        Some(-1) => conditions.push("folder_id IS NULL".to_string()),
+        Some(fid) => {
+            params.push(Box::new(fid));
+            let param = format!("?{}", params.len());
+            if include_subfolders {
+                conditions.push(format!("folder_id IN ({})", folder_subtree(&param)));
+            } else {
+                conditions.push(format!("folder_id = {}", param));
+            }
+        }
+    }
+    if top_level_only {
+        conditions.push("parent_id IS NULL".to_string());
+    }
+    if let Some(sid) = session_id {
+        params.push(Box::new(sid));
+        conditions.push(format!("session_id = ?{}", params.len()));
+    }
+    (format!(" WHERE {}", conditions.join(" AND ")), params)
+}
+
+fn ledger_snapshot_row(row: &rusqlite::Row) -> rusqlite::Result<LedgerSnapshotRow> {
+    Ok((
+        row.get(0)?,
+        row.get(1)?,
+        row.get(2)?,
+        row.get(3)?,
+        row.get::<_, i64>(4)? as u64,
+        row.get(5)?,
+        row.get(6)?,
+    ))
+}
+
+/// Keys of `before` and `after` whose values differ, as [{key, before, after}] (null when absent).
+/// `_confidence` is left out.
+fn extracted_data_diff(before: &Value, after: &Value) -> Value {
+    let empty = serde_json::Map::new();
+    let before = before.as_object().unwrap_or(&empty);
+    let after = after.as_object().unwrap_or(&empty);
+    let keys: std::collections::BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+    Value::Array(
+        keys.into_iter()
+            .filter(|k| k.as_str() != "_confidence" && before.get(*k) != after.get(*k))
+            .map(|k| {
+                serde_json::json!({
+                    "key": k,
+                    "before": before.get(k).cloned().unwrap_or(Value::Null),
+                    "after": after.get(k).cloned().unwrap_or(Value::Null),
+                })
+            })
+            .collect(),
+    )
+}
+
+/// Keep the current document type and extracted data of `id` as a revision when they differ from the
+/// new ones. Fails when the record does not exist.
+fn record_history_revision(
+    tx: &rusqlite::Transaction,
+    id: i64,
+    document_type: &str,
+    extracted_data: &Value,
+    reason: &str,
+) -> Result<Option<i64>, AppError> {
+    let current = tx.query_row(
+        "SELECT document_type, extracted_data FROM history WHERE id = ?",
+        params![id],
+        |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)),
+    );
+    let (current_type, current_data) = match current {
+        Ok(row) => row,
+        Err(rusqlite::Error::QueryReturnedNoRows) => {
+            return Err(AppError::Validation(format!("History record {} not found.", id)))
+        }
+        Err(e) => return Err(AppError::db(e)),
+    };
+    let before: Value = serde_json::from_str(&current_data).unwrap_or(Value::Null);
+    let diff = extracted_data_diff(&before, extracted_data);
+    let unchanged = diff.as_array().is_some_and(|d| d.is_empty()) && current_type == document_type;
+    if unchanged {
+        return Ok(None);
+    }
+    tx.execute(
+        "INSERT INTO history_revisions (history_id, document_type, extracted_data, diff, reason, created_at) VALUES (?, ?, ?, ?, ?, ?)",
+        params![id, current_type, current_data, diff.to_string(), reason, chrono::Utc::now().to_rfc3339()],
+    )
+    .map_err(AppError::db)?;
+    Ok(Some(tx.last_insert_rowid()))
+}
+
+fn history_row(row: &rusqlite::Row) -> rusqlite::Result<HistoryRow> {
+    Ok((
+        row.get(0)?,
+        row.get(1)?,
+        row.get(2)?,
+        row.get(3)?,
+        row.get(4)?,
+        row.get(5)?,
+        row.get(6)?,
+        row.get(7)?,
+    ))
+}
@@ -0,0 +1,146 @@
+//! Startup recovery when `invoice_scanner.db` is corrupted or locked: the app still launches with no
+//! database, reports what went wrong, and lets the user restore the latest backup or rebuild an empty DB.
+//! The damaged file is always moved aside (never deleted) before anything replaces it.
+
+use crate::db::Db;
+use rusqlite::{Connection, OpenFlags};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DbBackupInfo {
+    pub path: String,
+    pub modified: String,
+    pub size_bytes: u64,
+}
+
+/// Why the database could not be opened at startup, plus what the user can do about it.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DbRecoveryStatus {
+    pub db_path: String,
+    /// "corrupted" (integrity check failed), "locked", or "failed" (the file looks intact but opening or
+    /// migrating it failed; it must not be rebuilt over).
+    pub kind: String,
+    pub error: String,
+    /// Valid backups, newest first.
+    pub backups: Vec<DbBackupInfo>,
+}
+
+/// SQLite reports another process holding the file as "database is locked" / SQLITE_BUSY.
+fn is_lock_error(error: &str) -> bool {
+    let e = error.to_lowercase();
+    e.contains("database is locked") || e.contains("database table is locked") || e.contains("busy")
+}
+
+/// Open read-only and run `PRAGMA quick_check`; Ok only if SQLite reports "ok".
+fn check_database_file(path: &Path) -> Result<(), String> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX)
+        .map_err(|e| e.to_string())?;
+    let result: String = conn
+        .query_row("PRAGMA quick_check(1)", [], |r| r.get(0))
+        .map_err(|e| e.to_string())?;
+    if result.eq_ignore_ascii_case("ok") {
+        Ok(())
+    } else {
+        Err(format!("Integrity check failed: {}", result))
+    }
+}
+
+fn backups_dir(db_path: &Path) -> PathBuf {
+    db_path.parent().map(|p| p.join("backups")).unwrap_or_else(|| PathBuf::from("backups"))
+}
+
+/// Backups in `backups/` that pass an integrity check, newest first.
+pub fn list_valid_backups(db_path: &Path) -> Vec<DbBackupInfo> {
+    let Ok(entries) = fs::read_dir(backups_dir(db_path)) else {
+        return Vec::new();
+    };
+    let mut found: Vec<(std::time::SystemTime, DbBackupInfo)> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.extension().and_then(|e| e.to_str()) == Some("db"))
+        .filter(|p| check_database_file(p).is_ok())
+        .filter_map(|p| {
+            let meta = fs::metadata(&p).ok()?;
+            let modified = meta.modified().ok()?;
+            let info = DbBackupInfo {
+                path: p.to_string_lossy().to_string(),
+                modified: chrono::DateTime::<chrono::Local>::from(modified).to_rfc3339(),
+                size_bytes: meta.len(),
+            };
+            Some((modified, info))
+        })
+        .collect();
+    found.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+    found.into_iter().map(|(_, info)| info).collect()
+}
+
+/// Open the database, treating a failed integrity check as an open failure.
+/// On failure returns the status to show in the recovery prompt instead of aborting startup.
+pub fn open_database(db_path: &Path) -> Result<Db, DbRecoveryStatus> {
+    let status = |error: String, kind: &str| DbRecoveryStatus {
+        db_path: db_path.to_string_lossy().to_string(),
+        kind: if is_lock_error(&error) { "locked" } else { kind }.to_string(),
+        error,
+        backups: list_valid_backups(db_path),
+    };
+    if db_path.exists() {
+        check_database_file(db_path).map_err(|error| status(error, "corrupted"))?;
+    }
+    Db::new(db_path.to_path_buf()).map_err(|e| status(String::from(e), "failed"))
+}
+
+/// Move the damaged database (and its -wal/-shm/-journal side files) to
+/// `<name>.corrupt_<timestamp>.db` so it is preserved for manual inspection.
+fn quarantine_database(db_path: &Path) -> Result<Option<PathBuf>, String> {
+    if !db_path.exists() {
+        return Ok(None);
+    }
+    let stem = db_path.file_stem().and_then(|s| s.to_str()).unwrap_or("invoice_scanner");
+    let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S");
+    let target = db_path.with_file_name(format!("{}.corrupt_{}.db", stem, timestamp));
+    fs::rename(db_path, &target).map_err(|e| format!("Could not move damaged database aside: {}", e))?;
+    for suffix in ["-wal", "-shm", "-journal"] {
+        let side = PathBuf::from(format!("{}{}", db_path.to_string_lossy(), suffix));
+        if side.exists() {
+            let side_target = PathBuf::from(format!("{}{}", target.to_string_lossy(), suffix));
+            let _ = fs::rename(&side, &side_target);
+        }
+    }
+    Ok(Some(target))
+}
+
+/// Apply a recovery action and open the resulting database.
+/// `action`: "retry" (e.g. after closing the other program holding the lock), "restore" (from
+/// `backup_path`, or the newest valid backup when None) or "rebuild" (fresh empty database).
+pub fn recover_database(db_path: &Path, action: &str, backup_path: Option<&str>) -> Result<(Db, Option<PathBuf>), String> {
+    match action {
+        "retry" => {
+            let db = open_database(db_path).map_err(|s| s.error)?;
+            Ok((db, None))
+        }
+        "restore" => {
+            let backup = match backup_path.filter(|p| !p.trim().is_empty()) {
+                Some(p) => PathBuf::from(p),
+                None => list_valid_backups(db_path)
+                    .first()
+                    .map(|b| PathBuf::from(&b.path))
+                    .ok_or("No valid backup found. Choose rebuild instead.")?,
+            };
+            check_database_file(&backup).map_err(|e| format!("Backup is not usable: {}", e))?;
+            let quarantined = quarantine_database(db_path)?;
+            fs::copy(&backup, db_path).map_err(|e| format!("Could not restore backup: {}", e))?;
+            let db = Db::new(db_path.to_path_buf())?;
+            Ok((db, quarantined))
+        }
+        "rebuild" => {
+            let quarantined = quarantine_database(db_path)?;
+            let db = Db::new(db_path.to_path_buf())?;
+            Ok((db, quarantined))
+        }
+        other => Err(format!("Unknown recovery action '{}'. Use retry, restore or rebuild.", other)),
+    }
+}
@@ -0,0 +1,141 @@
+//! Crate-wide error type. Commands return it serialized as `{ code, message }` (plus `details` where a
+//! variant carries data) so the frontend can branch on `code` instead of matching message text. Services
+//! that still use `String` errors convert both ways: `?` on a String gives `Internal`, and an AppError
+//! converts back to its message.
+
+use crate::models::SchemaDiff;
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AppError {
+    /// Azure OCR: credentials, network, analysis failures, cancellation.
+    Ocr(String),
+    /// Reading or writing workbooks (including locked files).
+    Excel(String),
+    /// SQLite and stored-data problems.
+    Db(String),
+    /// File system errors outside workbooks.
+    Io(String),
+    /// Invalid input or a state that does not allow the operation (e.g. read-only mode).
+    Validation(String),
+    /// A file command was given a path outside the allowed scope (`services::path_scope`).
+    PathNotAllowed(String),
+    /// The ledger's columns no longer match the profile's stored schema; serialized with the diff as
+    /// `details` so the frontend can offer `remap_profile_columns`.
+    SchemaChanged(String, Box<SchemaDiff>),
+    /// The workbook format can be read but not written (.xls, .xlsb, .ods); it has to become .xlsx first.
+    ConversionRequired(String),
+    /// Errors not classified yet (service modules that still return String).
+    Internal(String),
+}
+
+impl AppError {
+    /// Stable code sent to the frontend.
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::Ocr(_) => "OCR",
+            AppError::Excel(_) => "EXCEL",
+            AppError::Db(_) => "DB",
+            AppError::Io(_) => "IO",
+            AppError::Validation(_) => "VALIDATION",
+            AppError::PathNotAllowed(_) => "PATH_NOT_ALLOWED",
+            AppError::SchemaChanged(..) => "SCHEMA_CHANGED",
+            AppError::ConversionRequired(_) => "CONVERSION_REQUIRED",
+            AppError::Internal(_) => "INTERNAL",
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            AppError::Ocr(m)
+            | AppError::Excel(m)
+            | AppError::Db(m)
+            | AppError::Io(m)
+            | AppError::Validation(m)
+            | AppError::PathNotAllowed(m)
+            | AppError::SchemaChanged(m, _)
+            | AppError::ConversionRequired(m)
+            | AppError::Internal(m) => m,
+        }
+    }
+
+    // Shorthands for `.map_err(AppError::db)` and the like.
+    pub fn ocr(e: impl fmt::Display) -> Self {
+        AppError::Ocr(e.to_string())
+    }
+
+    pub fn excel(e: impl fmt::Display) -> Self {
+        AppError::Excel(e.to_string())
+    }
+
+    pub fn db(e: impl fmt::Display) -> Self {
+        AppError::Db(e.to_string())
+    }
+
+    pub fn io(e: impl fmt::Display) -> Self {
+        AppError::Io(e.to_string())
+    }
+
+    pub fn validation(e: impl fmt::Display) -> Self {
+        AppError::Validation(e.to_string())
+    }
+
+    pub fn internal(e: impl fmt::Display) -> Self {
+        AppError::Internal(e.to_string())
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl Serialize for AppError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let details = match self {
+            AppError::SchemaChanged(_, diff) => Some(diff),
+            _ => None,
+        };
+        let mut s = serializer.serialize_struct("AppError", if details.is_some() { 3 } else { 2 })?;
+        s.serialize_field("code", self.code())?;
+        s.serialize_field("message", self.message())?;
+        if let Some(details) = details {
+            s.serialize_field("details", details)?;
+        }
+        s.end()
+    }
+}
+
+impl From<String> for AppError {
+    fn from(message: String) -> Self {
+        AppError::Internal(message)
+    }
+}
+
+impl From<&str> for AppError {
+    fn from(message: &str) -> Self {
+        AppError::Internal(message.to_string())
+    }
+}
+
+impl From<rusqlite::Error> for AppError {
+    fn from(e: rusqlite::Error) -> Self {
+        AppError::Db(e.to_string())
+    }
+}
+
+impl From<std::io::Error> for AppError {
+    fn from(e: std::io::Error) -> Self {
+        AppError::Io(e.to_string())
+    }
+}
+
+impl From<AppError> for String {
+    fn from(e: AppError) -> Self {
+        e.message().to_string()
+    }
+}
@@ -1,5 +1,7 @@
+use crate::error::AppError;
+use crate::models::{ColumnFormat, ExcelSchema, HeaderInfo};
 use calamine::{open_workbook_auto, DataType, Reader};
-use edit_xlsx::{FormatAlignType, WorkSheetRow, Write};
+use edit_xlsx::{FormatAlignType, FormatBorderType, Read as XlsxRead, WorkSheetCol, WorkSheetRow, Write};
 use quick_xml::events::{BytesEnd, BytesStart, BytesText, Event};
 use quick_xml::Reader as XmlReader;
 use quick_xml::Writer;
@@ -11,11 +13,17 @@ use zip::read::ZipArchive;
 use zip::write::SimpleFileOptions;
 use zip::ZipWriter;
 
-use crate::types::InvoiceData;
+use crate::services::export_locale::{self, ExportLocale};
+use crate::services::history_report::HistoryReport;
+use crate::services::spending_report::SpendingReport;
+use crate::services::vat_report::{VatReport, VatTotals};
+use crate::services::last_row::{self, LastRowScan};
+use crate::services::{app_warnings, perf_metrics, settings, workbook_compat, workbook_standby};
+use crate::types::{CellWrite, InvoiceData};
 use rust_xlsxwriter::{Format, FormatAlign, Workbook, Worksheet, XlsxError};
 
 /// Column index to Excel letter (0→A, 1→B, 25→Z, 26→AA).
-fn col_index_to_letter(index: u32) -> String {
+pub(crate) fn col_index_to_letter(index: u32) -> String {
     let mut n = index;
     let mut s = String::new();
     loop {
@@ -44,7 +52,7 @@ pub fn get_excel_headers(
     path: &str,
     sheet_name: &str,
     header_row: u32,
-) -> Result<Vec<ExcelHeader>, String> {
+) -> Result<Vec<ExcelHeader>, AppError> {
     let raw = read_excel_headers(path, sheet_name, Some(header_row))?;
     let out = raw
         .into_iter()
@@ -60,21 +68,22 @@ pub fn get_excel_headers(
 
 /// Read a specific row from sheet as headers (1-based row index).
 /// Returns header values in column order (A, B, C, ...).
-pub fn read_excel_headers(path: &str, sheet_name: &str, header_row: Option<u32>) -> Result<Vec<String>, String> {
+pub fn read_excel_headers(path: &str, sheet_name: &str, header_row: Option<u32>) -> Result<Vec<String>, AppError> {
     let path = Path::new(path);
     if !path.exists() {
-        return Err("File not found. Browse to select again.".to_string());
+        return Err(AppError::Excel("File not found. Browse to select again.".to_string()));
     }
-    let mut workbook = open_workbook_auto(path).map_err(|e| format!("Could not open Excel file: {}", e))?;
+    let mut workbook = open_workbook_auto(path).map_err(|e| AppError::Excel(format!("Could not open Excel file: {}", e)))?;
+    let sheet_name = resolve_sheet_name(&workbook.sheet_names(), sheet_name)?;
     let range = workbook
-        .worksheet_range(sheet_name)
-        .map_err(|e| format!("Sheet not found: {}", e))?;
+        .worksheet_range(&sheet_name)
+        .map_err(|e| AppError::Excel(format!("Sheet not found: {}", e)))?;
+    let date1904 = workbook_compat::uses_1904_dates(path);
     let row_index = header_row.unwrap_or(1).saturating_sub(1) as usize; // 1-based -> 0-based
     let mut headers = Vec::new();
     if let Some(row) = range.rows().nth(row_index) {
         for cell in row {
-            let s = cell.as_string().unwrap_or_default();
-            headers.push(s);
+            headers.push(workbook_compat::cell_text(cell, date1904));
         }
     }
     Ok(headers)
@@ -86,15 +95,17 @@ pub fn read_excel_column_samples(
     sheet_name: &str,
     header_row: Option<u32>,
     max_rows: usize,
-) -> Result<Vec<Vec<String>>, String> {
+) -> Result<Vec<Vec<String>>, AppError> {
     let path = Path::new(path);
     if !path.exists() {
-        return Err("File not found. Browse to select again.".to_string());
+        return Err(AppError::Excel("File not found. Browse to select again.".to_string()));
     }
-    let mut workbook = open_workbook_auto(path).map_err(|e| format!("Could not open Excel file: {}", e))?;
+    let mut workbook = open_workbook_auto(path).map_err(|e| AppError::Excel(format!("Could not open Excel file: {}", e)))?;
+    let sheet_name = resolve_sheet_name(&workbook.sheet_names(), sheet_name)?;
     let range = workbook
-        .worksheet_range(sheet_name)
-        .map_err(|e| format!("Sheet not found: {}", e))?;
+        .worksheet_range(&sheet_name)
+        .map_err(|e| AppError::Excel(format!("Sheet not found: {}", e)))?;
+    let date1904 = workbook_compat::uses_1904_dates(path);
     let header_idx = header_row.unwrap_or(1).saturating_sub(1) as usize;
     let rows: Vec<Vec<String>> = range
         .rows()
@@ -102,7 +113,7 @@ pub fn read_excel_column_samples(
         .take(max_rows)
         .map(|row| {
             row.iter()
-                .map(|c| c.as_string().unwrap_or_default())
+                .map(|c| workbook_compat::cell_text(c, date1904))
                 .collect()
         })
         .collect();
@@ -122,29 +133,181 @@ pub fn read_excel_column_samples(
 }
 
 /// Get list of sheet names from workbook.
-pub fn get_sheet_names(path: &str) -> Result<Vec<String>, String> {
+pub fn get_sheet_names(path: &str) -> Result<Vec<String>, AppError> {
     let path = Path::new(path);
     if !path.exists() {
-        return Err("File not found.".to_string());
+        return Err(AppError::Excel("File not found.".to_string()));
     }
-    let workbook = open_workbook_auto(path).map_err(|e| e.to_string())?;
+    let workbook = open_workbook_auto(path).map_err(AppError::excel)?;
     Ok(workbook.sheet_names().to_vec())
 }
 
+/// Normalize a sheet name for comparison: decode XML entities, drop bidi/zero-width marks,
+/// strip surrounding quotes (as in formula refs like 'Фактури 2024'), collapse whitespace, lowercase.
+fn normalize_sheet_name(name: &str) -> String {
+    let decoded = name
+        .replace("&amp;", "&")
+        .replace("&apos;", "'")
+        .replace("&quot;", "\"")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">");
+    let cleaned: String = decoded
+        .chars()
+        .filter(|c| {
+            !matches!(
+                *c,
+                '\u{200B}'..='\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}' | '\u{FEFF}'
+            )
+        })
+        .map(|c| if c == '\u{00A0}' { ' ' } else { c })
+        .collect();
+    let trimmed = cleaned.trim();
+    let unquoted = trimmed
+        .strip_prefix('\'')
+        .and_then(|s| s.strip_suffix('\''))
+        .or_else(|| trimmed.strip_prefix('"').and_then(|s| s.strip_suffix('"')))
+        .unwrap_or(trimmed)
+        .replace("''", "'");
+    unquoted
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Levenshtein distance over chars (Cyrillic-safe).
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut cur = vec![0; b.len() + 1];
+    for i in 1..=a.len() {
+        cur[0] = i;
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            cur[j] = (prev[j] + 1).min(cur[j - 1] + 1).min(prev[j - 1] + cost);
+        }
+        std::mem::swap(&mut prev, &mut cur);
+    }
+    prev[b.len()]
+}
+
+/// Exact match, then normalized match (trim, quotes, case, whitespace, bidi marks); None when neither hits.
+fn match_sheet_name(available: &[String], requested: &str) -> Result<Option<String>, AppError> {
+    if let Some(exact) = available.iter().find(|s| s.as_str() == requested) {
+        return Ok(Some(exact.clone()));
+    }
+    let wanted = normalize_sheet_name(requested);
+    let normalized: Vec<&String> = available
+        .iter()
+        .filter(|s| normalize_sheet_name(s) == wanted)
+        .collect();
+    match normalized.len() {
+        0 => Ok(None),
+        1 => Ok(Some(normalized[0].clone())),
+        _ => Err(AppError::Excel(format!(
+            "Sheet name '{}' is ambiguous; it matches: {}. Select the exact sheet.",
+            requested,
+            normalized.iter().map(|s| format!("'{}'", s)).collect::<Vec<_>>().join(", ")
+        ))),
+    }
+}
+
+/// Sheets within a small edit distance of `requested`, closest first (all at the same distance).
+fn fuzzy_sheet_candidates<'a>(available: &'a [String], requested: &str) -> Vec<&'a String> {
+    let wanted = normalize_sheet_name(requested);
+    let max_distance = (wanted.chars().count() / 4).clamp(1, 2);
+    let mut best: Option<usize> = None;
+    let mut candidates: Vec<&String> = Vec::new();
+    for s in available {
+        let d = edit_distance(&normalize_sheet_name(s), &wanted);
+        if d > max_distance {
+            continue;
+        }
+        match best {
+            Some(b) if d > b => {}
+            Some(b) if d == b => candidates.push(s),
+            _ => {
+                best = Some(d);
+                candidates = vec![s];
+            }
+        }
+    }
+    candidates
+}
+
+fn quoted_list(names: &[&String]) -> String {
+    names.iter().map(|s| format!("'{}'", s)).collect::<Vec<_>>().join(", ")
+}
+
+/// Resolve a requested sheet name against the sheets a workbook actually contains, for reading.
+/// Exact match first, then normalized match (trim, quotes, case, whitespace, bidi marks),
+/// then fuzzy match (small edit distance). Errors list candidates when the match is ambiguous.
+pub fn resolve_sheet_name(available: &[String], requested: &str) -> Result<String, AppError> {
+    if let Some(name) = match_sheet_name(available, requested)? {
+        return Ok(name);
+    }
+    let candidates = fuzzy_sheet_candidates(available, requested);
+    match candidates.len() {
+        1 => Ok(candidates[0].clone()),
+        0 => Err(AppError::Excel(format!(
+            "Sheet '{}' not found. Available sheets: {}",
+            requested,
+            quoted_list(&available.iter().collect::<Vec<_>>())
+        ))),
+        _ => Err(AppError::Excel(format!(
+            "Sheet name '{}' is ambiguous; closest matches: {}. Select the exact sheet.",
+            requested,
+            quoted_list(&candidates)
+        ))),
+    }
+}
+
+/// Like `resolve_sheet_name` but without the fuzzy step, for writes: "Фактури 2025" must never land in
+/// "Фактури 2024". Near misses are returned as a "did you mean" error instead.
+pub fn resolve_sheet_name_for_write(available: &[String], requested: &str) -> Result<String, AppError> {
+    if let Some(name) = match_sheet_name(available, requested)? {
+        return Ok(name);
+    }
+    let candidates = fuzzy_sheet_candidates(available, requested);
+    if candidates.is_empty() {
+        return Err(AppError::Excel(format!(
+            "Sheet '{}' not found. Available sheets: {}",
+            requested,
+            quoted_list(&available.iter().collect::<Vec<_>>())
+        )));
+    }
+    Err(AppError::Excel(format!(
+        "Sheet '{}' not found. Did you mean {}? Select the exact sheet in the profile.",
+        requested,
+        quoted_list(&candidates)
+    )))
+}
+
+/// Resolve a sheet name against an open edit_xlsx workbook, which is only opened to write; same rules as
+/// `resolve_sheet_name_for_write`.
+pub fn resolve_workbook_sheet_name(workbook: &edit_xlsx::Workbook, requested: &str) -> Result<String, AppError> {
+    let names: Vec<String> = workbook
+        .worksheets()
+        .map(|s| s.get_name().to_string())
+        .collect();
+    resolve_sheet_name_for_write(&names, requested)
+}
+
 /// Dump Excel structure to JSON (sheet names + first N rows per sheet, cell-by-cell).
 /// Use this to inspect real layout (merged cells show as one cell with content, rest empty).
-pub fn dump_excel_structure(path: &str, max_rows: usize) -> Result<String, String> {
+pub fn dump_excel_structure(path: &str, max_rows: usize) -> Result<String, AppError> {
     let path = Path::new(path);
     if !path.exists() {
-        return Err("File not found.".to_string());
+        return Err(AppError::Excel("File not found.".to_string()));
     }
-    let mut workbook = open_workbook_auto(path).map_err(|e| format!("Open failed: {}", e))?;
+    let mut workbook = open_workbook_auto(path).map_err(|e| AppError::Excel(format!("Open failed: {}", e)))?;
     let sheet_names = workbook.sheet_names().to_vec();
     let mut sheets = serde_json::Map::new();
     for name in &sheet_names {
         let range = workbook
             .worksheet_range(name)
-            .map_err(|e| format!("Sheet '{}': {}", name, e))?;
+            .map_err(|e| AppError::Excel(format!("Sheet '{}': {}", name, e)))?;
         let mut rows: Vec<Vec<String>> = Vec::new();
         for (row_idx, row) in range.rows().enumerate() {
             if row_idx >= max_rows {
@@ -156,7 +319,7 @@ pub fn dump_excel_structure(path: &str, max_rows: usize) -> Result<String, Strin
                 .collect();
             rows.push(cells);
         }
-        let arr: serde_json::Value = serde_json::to_value(rows).map_err(|e| e.to_string())?;
+        let arr: serde_json::Value = serde_json::to_value(rows).map_err(AppError::excel)?;
         sheets.insert(name.clone(), arr);
     }
     let out = serde_json::json!({
@@ -164,39 +327,140 @@ pub fn dump_excel_structure(path: &str, max_rows: usize) -> Result<String, Strin
         "sheet_names": sheet_names,
         "sheets": sheets,
     });
-    serde_json::to_string_pretty(&out).map_err(|e| e.to_string())
+    serde_json::to_string_pretty(&out).map_err(AppError::excel)
+}
+
+/// Most rows returned by one `read_sheet_range` call.
+const MAX_RANGE_ROWS: u32 = 500;
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SheetRangeRow {
+    /// 1-based row number as shown in Excel.
+    pub row_number: u32,
+    pub cells: Vec<String>,
+}
+
+/// One page of a worksheet's values for the read-only ledger view.
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SheetRange {
+    /// Sheet name as found in the workbook.
+    pub sheet_name: String,
+    pub start_row: u32,
+    pub rows: Vec<SheetRangeRow>,
+    /// Last 1-based row with data (0 when the sheet is empty).
+    pub last_row: u32,
+    /// Columns from A to the last used column.
+    pub column_count: u32,
+}
+
+/// Values of rows `start_row..start_row + row_count` (1-based, at most 500 rows), columns A to the last used one.
+/// Cells are formatted as calamine reads them (numbers unformatted, formulas as their cached value),
+/// except dates (dd.mm.yyyy) and mis-encoded text, see `workbook_compat::cell_text`.
+pub fn read_sheet_range(path: &str, sheet_name: &str, start_row: u32, row_count: u32) -> Result<SheetRange, AppError> {
+    let path = Path::new(path);
+    if !path.exists() {
+        return Err(AppError::Excel("File not found. Browse to select again.".to_string()));
+    }
+    let mut workbook = open_workbook_auto(path).map_err(|e| AppError::Excel(format!("Could not open Excel file: {}", e)))?;
+    let sheet_name = resolve_sheet_name(&workbook.sheet_names(), sheet_name)?;
+    let range = workbook
+        .worksheet_range(&sheet_name)
+        .map_err(|e| AppError::Excel(format!("Sheet not found: {}", e)))?;
+    let (last_row, column_count) = match range.end() {
+        Some((row, col)) => (row + 1, col + 1),
+        None => (0, 0),
+    };
+    let date1904 = workbook_compat::uses_1904_dates(path);
+    let start_row = start_row.max(1);
+    let end_row = start_row
+        .saturating_add(row_count.clamp(1, MAX_RANGE_ROWS))
+        .min(last_row.saturating_add(1));
+    // Absolute positions: calamine ranges start at the first used cell, not at A1.
+    let rows = (start_row..end_row)
+        .map(|row_number| SheetRangeRow {
+            row_number,
+            cells: (0..column_count)
+                .map(|col| {
+                    range
+                        .get_value((row_number - 1, col))
+                        .map(|c| workbook_compat::cell_text(c, date1904))
+                        .unwrap_or_default()
+                })
+                .collect(),
+        })
+        .collect();
+    Ok(SheetRange {
+        sheet_name,
+        start_row,
+        rows,
+        last_row,
+        column_count,
+    })
 }
 
-/// Find the last 1-based row index that contains any data in the sheet, scanning from header_row downward.
-/// Stops after 100 consecutive empty rows. Returns header_row (1-based) if sheet is empty or only has header.
-pub fn find_last_data_row(path: &Path, sheet_name: &str, header_row: u32) -> Result<u32, String> {
-    let mut workbook = open_workbook_auto(path).map_err(|e| format!("Could not open Excel file: {}", e))?;
+/// Headers of a 1-based row the way `excel_scanner::extract_headers` reads them (text cells only, trimmed,
+/// up to column 50, stopping after three empty cells), but through calamine, which only parses the one
+/// sheet, so it is cheap enough to run before every append.
+pub fn read_header_row(path: &str, sheet_name: &str, header_row: u32) -> Result<Vec<HeaderInfo>, AppError> {
+    let path = Path::new(path);
+    if !path.exists() {
+        return Err(AppError::Excel("File not found. Browse to select again.".to_string()));
+    }
+    let mut workbook = open_workbook_auto(path).map_err(|e| AppError::Excel(format!("Could not open Excel file: {}", e)))?;
+    let sheet_name = resolve_sheet_name(&workbook.sheet_names(), sheet_name)?;
     let range = workbook
-        .worksheet_range(sheet_name)
-        .map_err(|e| format!("Sheet not found: {}", e))?;
-    let start_row_0 = header_row.saturating_sub(1) as usize; // 1-based -> 0-based
-    let mut last_data_row_0: Option<usize> = None;
+        .worksheet_range(&sheet_name)
+        .map_err(|e| AppError::Excel(format!("Sheet not found: {}", e)))?;
+    Ok(headers_in_range(&range, header_row))
+}
+
+/// Headers of `header_row` in a calamine range, see `read_header_row`.
+pub(crate) fn headers_in_range(range: &calamine::Range<calamine::Data>, header_row: u32) -> Vec<HeaderInfo> {
+    let mut headers = Vec::new();
     let mut empty_count = 0u32;
-    for (row_idx, row) in range.rows().enumerate().skip(start_row_0) {
-        let has_data = row.iter().any(|c| !c.is_empty());
-        if has_data {
-            last_data_row_0 = Some(row_idx);
-            empty_count = 0;
-        } else {
+    for col in 0..50u32 {
+        let text = match range.get_value((header_row.saturating_sub(1), col)) {
+            Some(calamine::Data::String(s)) => s.trim().to_string(),
+            _ => String::new(),
+        };
+        if text.is_empty() {
             empty_count += 1;
-            if empty_count >= 100 {
+            if empty_count >= 3 {
                 break;
             }
+        } else {
+            empty_count = 0;
+            headers.push(HeaderInfo { column_index: col as u16, column_letter: col_index_to_letter(col), text });
         }
     }
-    let one_based = last_data_row_0
-        .map(|r| (r + 1) as u32)
-        .unwrap_or(header_row);
-    Ok(one_based)
+    headers
+}
+
+/// Find the last 1-based row below header_row that contains any data, per the configured
+/// `last_row::LastRowStrategy`. Returns header_row if the sheet has no data below it.
+pub fn find_last_data_row(path: &Path, sheet_name: &str, header_row: u32) -> Result<LastRowScan, AppError> {
+    let mut workbook = open_workbook_auto(path).map_err(|e| AppError::Excel(format!("Could not open Excel file: {}", e)))?;
+    let sheet_name = resolve_sheet_name(&workbook.sheet_names(), sheet_name)?;
+    let range = workbook
+        .worksheet_range(&sheet_name)
+        .map_err(|e| AppError::Excel(format!("Sheet not found: {}", e)))?;
+    Ok(range_last_row(&range, header_row))
+}
+
+/// Last data row of a calamine range (absolute 1-based rows; ranges start at the first used cell).
+pub(crate) fn range_last_row(range: &calamine::Range<calamine::Data>, header_row: u32) -> LastRowScan {
+    let (Some((first_row, first_col)), Some((last_row, last_col))) = (range.start(), range.end()) else {
+        return LastRowScan { last_row: header_row, truncated: false };
+    };
+    last_row::find_last_row(header_row.max(first_row), last_row + 1, settings::last_row_strategy(), |row| {
+        (first_col..=last_col).any(|col| range.get_value((row - 1, col)).is_some_and(|c| !c.is_empty()))
+    })
 }
 
 /// Schema hash matching frontend computeSchemaHash (deterministic from headers).
-fn schema_hash(headers: &[String]) -> String {
+pub(crate) fn schema_hash(headers: &[String]) -> String {
     let mut sorted = headers.to_vec();
     sorted.sort();
     let normalized = sorted.join("|");
@@ -222,31 +486,94 @@ fn to_radix36(mut n: u32) -> String {
 }
 
 const SAMPLE_ROWS: usize = 5;
-const MAX_LAST_ROW_SCAN: usize = 2000;
+/// Rows below the header read for column statistics.
+const MAX_STATS_ROWS: usize = 2000;
+/// Share of a column's filled cells that must have one type for the column to get that type.
+const DOMINANT_TYPE_SHARE: f64 = 0.6;
+
+/// Fill rate and value type of one column below the header row.
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ColumnStats {
+    pub column_letter: String,
+    pub filled_count: u32,
+    /// filled_count / data_row_count (0 when the sheet has no data rows).
+    pub fill_rate: f64,
+    /// "number", "date", "text", "mixed" or "empty".
+    pub data_type: String,
+}
+
+/// Data rows and per-column statistics for the mapping wizard.
+#[derive(Debug, Clone, Default, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SheetStats {
+    /// Non-empty rows below the header (within the first 2000).
+    pub data_row_count: u32,
+    /// The sheet has more rows than were read for these statistics.
+    pub truncated: bool,
+    pub columns: Vec<ColumnStats>,
+    /// Most likely amount column (letter): a well-filled number column, preferring amount-like headers.
+    pub likely_amount_column: Option<String>,
+    /// Most likely document date column (letter).
+    pub likely_date_column: Option<String>,
+}
+
+/// "number", "date" or "text" for a non-empty cell.
+fn cell_value_type(cell: &calamine::Data, text: &str) -> &'static str {
+    use calamine::Data;
+    match cell {
+        Data::Int(_) | Data::Float(_) => "number",
+        Data::DateTime(_) | Data::DateTimeIso(_) => "date",
+        _ if crate::services::spending_report::parse_invoice_date(text).is_some() => "date",
+        _ if normalize_amount_string(text).parse::<f64>().is_ok() => "number",
+        _ => "text",
+    }
+}
+
+/// Pick the best-filled column of `data_type`, preferring headers that contain one of `hints`.
+fn likely_column(stats: &[ColumnStats], headers: &[String], data_type: &str, hints: &[&str]) -> Option<String> {
+    stats
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| c.data_type == data_type && c.fill_rate >= 0.5)
+        .max_by(|(ia, a), (ib, b)| {
+            let hinted = |i: usize| {
+                let header = headers.get(i).map(|h| h.to_lowercase()).unwrap_or_default();
+                hints.iter().any(|hint| header.contains(hint))
+            };
+            hinted(*ia)
+                .cmp(&hinted(*ib))
+                .then(a.fill_rate.partial_cmp(&b.fill_rate).unwrap_or(std::cmp::Ordering::Equal))
+                .then(ib.cmp(ia))
+        })
+        .map(|(_, c)| c.column_letter.clone())
+}
 
-/// Analyze Excel sheet and return schema (headers, samples, last row, hash).
+/// Analyze Excel sheet and return schema (headers, samples, last row, hash, sheet statistics).
 /// Used by frontend instead of loading full file into webview to avoid OOM.
 pub fn analyze_excel_schema(
     path_str: &str,
     sheet_name: &str,
     header_row: u32,
-) -> Result<(String, Vec<String>, Vec<Vec<String>>, u32, String), String> {
+) -> Result<(String, Vec<String>, Vec<Vec<String>>, LastRowScan, String, SheetStats), AppError> {
     let path = Path::new(path_str);
     if !path.exists() {
-        return Err("File not found. Browse to select again.".to_string());
+        return Err(AppError::Excel("File not found. Browse to select again.".to_string()));
     }
-    let mut workbook = open_workbook_auto(path).map_err(|e| format!("Could not open Excel file: {}", e))?;
+    let mut workbook = open_workbook_auto(path).map_err(|e| AppError::Excel(format!("Could not open Excel file: {}", e)))?;
+    let sheet_name = resolve_sheet_name(&workbook.sheet_names(), sheet_name)?;
     let range = workbook
-        .worksheet_range(sheet_name)
-        .map_err(|e| format!("Sheet not found: {}", e))?;
+        .worksheet_range(&sheet_name)
+        .map_err(|e| AppError::Excel(format!("Sheet not found: {}", e)))?;
     let header_idx = header_row.saturating_sub(1) as usize;
+    let date1904 = workbook_compat::uses_1904_dates(Path::new(path_str));
 
     let headers = range
         .rows()
         .nth(header_idx)
         .map(|row| {
             row.iter()
-                .map(|c| c.as_string().unwrap_or_default())
+                .map(|c| workbook_compat::cell_text(c, date1904))
                 .collect::<Vec<String>>()
         })
         .unwrap_or_default();
@@ -256,34 +583,81 @@ pub fn analyze_excel_schema(
     }
     let headers: Vec<String> = headers.into_iter().take(trim).collect();
 
-    let column_samples = read_excel_column_samples(path_str, sheet_name, Some(header_row), SAMPLE_ROWS)?;
-
-    let mut last_data_row = header_idx as u32 + 1;
-    for (i, row) in range.rows().skip(header_idx + 1).take(MAX_LAST_ROW_SCAN).enumerate() {
-        let has_content = row
-            .iter()
-            .any(|c| !c.as_string().unwrap_or_default().trim().is_empty());
+    let column_samples = read_excel_column_samples(path_str, &sheet_name, Some(header_row), SAMPLE_ROWS)?;
+
+    let last_row = range_last_row(&range, header_row);
+    let mut data_row_count = 0u32;
+    // Per column: counts of number, date and text cells.
+    let mut type_counts: Vec<[u32; 3]> = vec![[0; 3]; headers.len()];
+    for row in range.rows().skip(header_idx + 1).take(MAX_STATS_ROWS) {
+        let mut has_content = false;
+        for (col, cell) in row.iter().enumerate() {
+            let text = workbook_compat::cell_text(cell, date1904);
+            if text.trim().is_empty() {
+                continue;
+            }
+            has_content = true;
+            if col >= type_counts.len() {
+                type_counts.resize(col + 1, [0; 3]);
+            }
+            let slot = match cell_value_type(cell, text.trim()) {
+                "number" => 0,
+                "date" => 1,
+                _ => 2,
+            };
+            type_counts[col][slot] += 1;
+        }
         if has_content {
-            last_data_row = (header_idx + 2 + i) as u32;
+            data_row_count += 1;
         }
     }
 
+    let columns: Vec<ColumnStats> = type_counts
+        .iter()
+        .enumerate()
+        .map(|(col, counts)| {
+            let filled: u32 = counts.iter().sum();
+            let data_type = if filled == 0 {
+                "empty"
+            } else {
+                ["number", "date", "text"]
+                    .iter()
+                    .zip(counts)
+                    .find(|(_, n)| **n as f64 >= filled as f64 * DOMINANT_TYPE_SHARE)
+                    .map(|(t, _)| *t)
+                    .unwrap_or("mixed")
+            };
+            ColumnStats {
+                column_letter: col_index_to_letter(col as u32),
+                filled_count: filled,
+                fill_rate: if data_row_count == 0 { 0.0 } else { filled as f64 / data_row_count as f64 },
+                data_type: data_type.to_string(),
+            }
+        })
+        .collect();
+    let stats = SheetStats {
+        data_row_count,
+        truncated: last_row.last_row as usize > header_idx + 1 + MAX_STATS_ROWS,
+        likely_amount_column: likely_column(&columns, &headers, "number", &["вкупно", "износ", "сума", "total", "amount"]),
+        likely_date_column: likely_column(&columns, &headers, "date", &["датум", "date"]),
+        columns,
+    };
+
     let hash = schema_hash(&headers);
-    let worksheet_name = sheet_name.to_string();
-    Ok((worksheet_name, headers, column_samples, last_data_row, hash))
+    Ok((sheet_name, headers, column_samples, last_row, hash, stats))
 }
 
 /// Strip drawing and image parts from an xlsx (zip) file so Excel won't
 /// show "Repairs to ... Removed Part: Drawing shape" when opening.
 /// We do NOT modify worksheet XML (sheet1.xml etc.) to avoid corrupting cell data.
-fn strip_drawings_from_xlsx(path: &Path) -> Result<(), String> {
+fn strip_drawings_from_xlsx(path: &Path) -> Result<(), AppError> {
     use std::fs::File;
 
-    let file = File::open(path).map_err(|e| format!("Could not open for strip: {}", e))?;
-    let mut archive = ZipArchive::new(file).map_err(|e| format!("Invalid zip: {}", e))?;
+    let file = File::open(path).map_err(|e| AppError::Excel(format!("Could not open for strip: {}", e)))?;
+    let mut archive = ZipArchive::new(file).map_err(|e| AppError::Excel(format!("Invalid zip: {}", e)))?;
 
     let temp_path = path.with_extension("tmp.xlsx");
-    let out_file = File::create(&temp_path).map_err(|e| format!("Could not create temp: {}", e))?;
+    let out_file = File::create(&temp_path).map_err(|e| AppError::Excel(format!("Could not create temp: {}", e)))?;
     let mut zip_writer = ZipWriter::new(out_file);
     let opts = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
 
@@ -292,37 +666,438 @@ fn strip_drawings_from_xlsx(path: &Path) -> Result<(), String> {
     let ct_media_re = Regex::new(r#"<Override\s+PartName="/xl/media/[^"]*"[^>]*/>"#).expect("ct media regex");
 
     for i in 0..archive.len() {
-        let mut entry = archive.by_index(i).map_err(|e| format!("Entry {}: {}", i, e))?;
+        let mut entry = archive.by_index(i).map_err(|e| AppError::Excel(format!("Entry {}: {}", i, e)))?;
         let name = entry.name().replace('\\', "/");
         let is_drawing = name.starts_with("xl/drawings/") || name.starts_with("xl/media/");
         if is_drawing {
             continue;
         }
         let mut data = Vec::new();
-        entry.read_to_end(&mut data).map_err(|e| format!("Read {}: {}", name, e))?;
+        entry.read_to_end(&mut data).map_err(|e| AppError::Excel(format!("Read {}: {}", name, e)))?;
 
         if name == "[Content_Types].xml" {
             let s = String::from_utf8_lossy(&data);
             let out = ct_drawing_re.replace_all(&s, "");
             let out = ct_media_re.replace_all(&out, "");
-            zip_writer.start_file(&name, opts).map_err(|e| e.to_string())?;
-            zip_writer.write_all(out.as_bytes()).map_err(|e| e.to_string())?;
+            zip_writer.start_file(&name, opts).map_err(AppError::excel)?;
+            zip_writer.write_all(out.as_bytes()).map_err(AppError::excel)?;
         } else if name.contains("worksheets/_rels/") && name.ends_with(".rels") {
             let s = String::from_utf8_lossy(&data);
             let out = rel_drawing_re.replace_all(&s, "").to_string();
-            zip_writer.start_file(&name, opts).map_err(|e| e.to_string())?;
-            zip_writer.write_all(out.as_bytes()).map_err(|e| e.to_string())?;
+            zip_writer.start_file(&name, opts).map_err(AppError::excel)?;
+            zip_writer.write_all(out.as_bytes()).map_err(AppError::excel)?;
         } else {
             // Copy all other parts (including sheet*.xml) unchanged - do not modify worksheet XML
-            zip_writer.start_file(&name, opts).map_err(|e| e.to_string())?;
-            zip_writer.write_all(&data).map_err(|e| e.to_string())?;
+            zip_writer.start_file(&name, opts).map_err(AppError::excel)?;
+            zip_writer.write_all(&data).map_err(AppError::excel)?;
+        }
+    }
+    zip_writer.finish().map_err(AppError::excel)?;
+    std::fs::rename(&temp_path, path).map_err(|e| AppError::Excel(format!("Replace file: {}", e)))?;
+    Ok(())
+}
+
+/// Id of the number format `code` in styles.xml, adding a custom one (ids from 164) when missing.
+fn ensure_num_fmt(styles_xml: &mut String, code: &str) -> u32 {
+    let escaped = xml_escape(code);
+    let num_fmt_re = Regex::new(r#"<numFmt\s[^>]*?numFmtId="(\d+)"[^>]*?formatCode="([^"]*)"[^>]*>"#).expect("numFmt regex");
+    let mut next_id = 164;
+    for c in num_fmt_re.captures_iter(styles_xml) {
+        let id: u32 = c[1].parse().unwrap_or(0);
+        if c[2] == escaped {
+            return id;
+        }
+        next_id = next_id.max(id + 1);
+    }
+    let entry = format!(r#"<numFmt numFmtId="{}" formatCode="{}"/>"#, next_id, escaped);
+    let section_re = Regex::new(r#"(?s)<numFmts\b[^>]*?(/>|>(.*?)</numFmts>)"#).expect("numFmts regex");
+    let (range, body) = match section_re.captures(styles_xml) {
+        Some(c) => (c.get(0).map(|m| m.range()).unwrap_or_default(), c.get(2).map_or("", |m| m.as_str()).to_string()),
+        None => {
+            // numFmts must be the first child of styleSheet.
+            let at = styles_xml.find("<styleSheet").and_then(|i| styles_xml[i..].find('>').map(|j| i + j + 1)).unwrap_or(0);
+            (at..at, String::new())
+        }
+    };
+    let count = num_fmt_re.find_iter(&body).count() + 1;
+    styles_xml.replace_range(range, &format!(r#"<numFmts count="{}">{}{}</numFmts>"#, count, body, entry));
+    next_id
+}
+
+/// Text of a zip part (e.g. "xl/workbook.xml").
+fn read_zip_text(archive: &mut ZipArchive<std::fs::File>, name: &str) -> Result<String, AppError> {
+    let mut entry = archive.by_name(name).map_err(|e| AppError::Excel(format!("{}: {}", name, e)))?;
+    let mut text = String::new();
+    entry.read_to_string(&mut text).map_err(|e| AppError::Excel(format!("Read {}: {}", name, e)))?;
+    Ok(text)
+}
+
+/// Value of attribute `name` in an XML start tag.
+fn xml_attr(tag: &str, name: &str) -> Option<String> {
+    // Called for every cell of a sheet, so a plain scan instead of a regex.
+    let needle = format!("{}=\"", name);
+    let mut from = 0;
+    while let Some(found) = tag[from..].find(&needle) {
+        let at = from + found;
+        let start = at + needle.len();
+        if tag[..at].ends_with(char::is_whitespace) {
+            let end = start + tag[start..].find('"')?;
+            return Some(tag[start..end].to_string());
+        }
+        from = start;
+    }
+    None
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Zip part of the worksheet named `sheet_name` (e.g. "xl/worksheets/sheet1.xml"), via workbook.xml and its rels.
+fn worksheet_part(archive: &mut ZipArchive<std::fs::File>, sheet_name: &str) -> Result<String, AppError> {
+    let workbook_xml = read_zip_text(archive, "xl/workbook.xml")?;
+    let escaped_name = xml_escape(sheet_name);
+    let sheet_tag_re = Regex::new(r"<sheet\b[^>]*>").expect("sheet tag regex");
+    let rel_id = sheet_tag_re
+        .find_iter(&workbook_xml)
+        .map(|m| m.as_str())
+        .find(|tag| xml_attr(tag, "name").as_deref() == Some(escaped_name.as_str()))
+        .and_then(|tag| xml_attr(tag, "r:id"))
+        .ok_or_else(|| AppError::Excel(format!("Sheet '{}' not found in workbook.xml", sheet_name)))?;
+    let rels_xml = read_zip_text(archive, "xl/_rels/workbook.xml.rels")?;
+    let rel_re = Regex::new(r"<Relationship\b[^>]*>").expect("relationship regex");
+    let target = rel_re
+        .find_iter(&rels_xml)
+        .map(|m| m.as_str())
+        .find(|tag| xml_attr(tag, "Id").as_deref() == Some(rel_id.as_str()))
+        .and_then(|tag| xml_attr(tag, "Target"))
+        .ok_or_else(|| AppError::Excel(format!("Relationship '{}' not found", rel_id)))?;
+    Ok(match target.strip_prefix('/') {
+        Some(absolute) => absolute.to_string(),
+        None => format!("xl/{}", target),
+    })
+}
+
+/// Rewrite the zip at `path` with the parts in `replaced` (name, new text); other parts are copied as is.
+fn rewrite_zip_parts(path: &Path, archive: &mut ZipArchive<std::fs::File>, replaced: &[(&str, &str)]) -> Result<(), AppError> {
+    let temp_path = path.with_extension("parts.xlsx");
+    let out_file = std::fs::File::create(&temp_path).map_err(|e| AppError::Excel(format!("Could not create temp: {}", e)))?;
+    let mut zip_writer = ZipWriter::new(out_file);
+    let opts = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i).map_err(|e| AppError::Excel(format!("Entry {}: {}", i, e)))?;
+        let name = entry.name().replace('\\', "/");
+        zip_writer.start_file(&name, opts).map_err(AppError::excel)?;
+        match replaced.iter().find(|(part, _)| *part == name) {
+            Some((_, text)) => zip_writer.write_all(text.as_bytes()).map_err(AppError::excel)?,
+            None => {
+                let mut data = Vec::new();
+                entry.read_to_end(&mut data).map_err(|e| AppError::Excel(format!("Read {}: {}", name, e)))?;
+                zip_writer.write_all(&data).map_err(AppError::excel)?;
+            }
+        }
+    }
+    zip_writer.finish().map_err(AppError::excel)?;
+    std::fs::rename(&temp_path, path).map_err(|e| AppError::Excel(format!("Replace file: {}", e)))
+}
+
+/// Give `cell_refs` on `sheet_name` the number format `format_code` (a date pattern such as "dd.mm.yyyy").
+/// edit_xlsx has no number formats, so this rewrites the saved parts: the format is added to styles.xml,
+/// each cell style used by those cells is cloned with it, and the cells are pointed at the clone.
+fn apply_date_format(path: &Path, sheet_name: &str, cell_refs: &[String], format_code: &str) -> Result<(), AppError> {
+    let file = std::fs::File::open(path).map_err(|e| AppError::Excel(format!("Could not open for formatting: {}", e)))?;
+    let mut archive = ZipArchive::new(file).map_err(|e| AppError::Excel(format!("Invalid zip: {}", e)))?;
+    let sheet_part = worksheet_part(&mut archive, sheet_name)?;
+    let mut styles_xml = read_zip_text(&mut archive, "xl/styles.xml")?;
+    let sheet_xml = read_zip_text(&mut archive, &sheet_part)?;
+    let num_fmt_id = ensure_num_fmt(&mut styles_xml, format_code);
+
+    // Clone each cell style once, in the order first seen.
+    let cell_xfs_re = Regex::new(r#"(?s)<cellXfs\b[^>]*>(.*?)</cellXfs>"#).expect("cellXfs regex");
+    let xf_re = Regex::new(r#"(?s)<xf\b[^>]*?/>|<xf\b[^>]*>.*?</xf>"#).expect("xf regex");
+    let num_fmt_re = Regex::new(r#"\s(numFmtId|applyNumberFormat)="[^"]*""#).expect("number format attr regex");
+    let style_re = Regex::new(r#"\ss="[^"]*""#).expect("style attr regex");
+    let cell_re = Regex::new(r#"<c\s[^>]*>"#).expect("cell regex");
+    let caps = cell_xfs_re
+        .captures(&styles_xml)
+        .ok_or_else(|| AppError::Excel("styles.xml has no cellXfs".to_string()))?;
+    let section = caps.get(0).map(|m| m.range()).unwrap_or_default();
+    let xfs: Vec<&str> = xf_re.find_iter(&caps[1]).map(|m| m.as_str()).collect();
+    let mut clones: Vec<(usize, usize)> = Vec::new();
+    let mut new_xfs = String::new();
+    let sheet_xml = cell_re
+        .replace_all(&sheet_xml, |c: &regex::Captures| {
+            let tag = &c[0];
+            if !xml_attr(tag, "r").is_some_and(|r| cell_refs.contains(&r)) {
+                return tag.to_string();
+            }
+            let style: usize = xml_attr(tag, "s").and_then(|v| v.parse().ok()).unwrap_or(0);
+            let clone_index = match clones.iter().find(|(from, _)| *from == style) {
+                Some((_, to)) => *to,
+                None => {
+                    let base = xfs.get(style).copied().unwrap_or("<xf/>");
+                    let open_end = base.find('>').unwrap_or(base.len());
+                    let head = num_fmt_re.replace_all(base[..open_end].trim_end_matches('/'), "");
+                    let tail = if base.ends_with("/>") && !base.contains("</xf>") { "/>" } else { &base[open_end..] };
+                    new_xfs.push_str(&format!(r#"{} numFmtId="{}" applyNumberFormat="1"{}"#, head, num_fmt_id, tail));
+                    let index = xfs.len() + clones.len();
+                    clones.push((style, index));
+                    index
+                }
+            };
+            let tag = style_re.replace(tag, "");
+            match tag.strip_suffix("/>") {
+                Some(head) => format!(r#"{} s="{}"/>"#, head, clone_index),
+                None => format!(r#"{} s="{}">"#, tag.trim_end_matches('>'), clone_index),
+            }
+        })
+        .to_string();
+    if clones.is_empty() {
+        return Ok(());
+    }
+    let cell_xfs = format!(r#"<cellXfs count="{}">{}{}</cellXfs>"#, xfs.len() + clones.len(), &caps[1], new_xfs);
+    styles_xml.replace_range(section, &cell_xfs);
+
+    rewrite_zip_parts(path, &mut archive, &[("xl/styles.xml", &styles_xml), (&sheet_part, &sheet_xml)])
+}
+
+/// `formula` copied `delta` rows down, as Excel's fill-down does: relative row references move (A5, $A5),
+/// absolute ones (A$5) and text in quotes do not. None when a reference would move off the sheet.
+fn shift_formula_rows(formula: &str, delta: i64) -> Option<String> {
+    let ref_re = Regex::new(r"(\$?[A-Za-z]{1,3})(\$?)(\d+)").expect("cell reference regex");
+    let mut off_sheet = false;
+    let mut out = String::with_capacity(formula.len());
+    // Split at quotes: every other part is a string literal or a quoted sheet name.
+    let mut quote: Option<char> = None;
+    let mut part = String::new();
+    let mut flush = |part: &mut String, quoted: bool, out: &mut String| {
+        if quoted {
+            out.push_str(part);
+        } else {
+            let shifted = ref_re.replace_all(part, |c: &regex::Captures| {
+                let whole = c.get(0).expect("match");
+                let before = part[..whole.start()].chars().next_back();
+                let after = part[whole.end()..].chars().next();
+                // Function names (LOG10), defined names (Q1_total) and columns past XFD are not references.
+                let is_reference = !before.is_some_and(|ch| ch.is_alphanumeric() || ch == '_' || ch == '.')
+                    && !after.is_some_and(|ch| ch.is_alphanumeric() || ch == '_' || ch == '(' || ch == '.')
+                    && column_number(&c[1]) <= 16_384;
+                if !is_reference || !c[2].is_empty() {
+                    return whole.as_str().to_string();
+                }
+                let row = c[3].parse::<i64>().unwrap_or(0) + delta;
+                if !(1..=1_048_576).contains(&row) {
+                    off_sheet = true;
+                }
+                format!("{}{}", &c[1], row)
+            });
+            out.push_str(&shifted);
         }
+        part.clear();
+    };
+    for ch in formula.chars() {
+        match quote {
+            Some(q) if ch == q => {
+                part.push(ch);
+                flush(&mut part, true, &mut out);
+                quote = None;
+            }
+            Some(_) => part.push(ch),
+            None if ch == '"' || ch == '\'' => {
+                flush(&mut part, false, &mut out);
+                part.push(ch);
+                quote = Some(ch);
+            }
+            None => part.push(ch),
+        }
+    }
+    flush(&mut part, quote.is_some(), &mut out);
+    (!off_sheet).then_some(out)
+}
+
+/// Set `fullCalcOnLoad` in workbook.xml so Excel computes formulas that were written without results.
+fn with_full_calc_on_load(workbook_xml: &str) -> String {
+    let calc_re = Regex::new(r"<calcPr\b[^>]*>").expect("calcPr regex");
+    let full_calc_re = Regex::new(r#"\sfullCalcOnLoad="[^"]*""#).expect("fullCalcOnLoad regex");
+    if let Some(m) = calc_re.find(workbook_xml) {
+        let tag = full_calc_re.replace(m.as_str(), "");
+        let tag = tag.replacen("<calcPr", r#"<calcPr fullCalcOnLoad="1""#, 1);
+        return format!("{}{}{}", &workbook_xml[..m.start()], tag, &workbook_xml[m.end()..]);
+    }
+    // calcPr follows sheets, externalReferences and definedNames.
+    let at = ["</sheets>", "</externalReferences>", "</definedNames>"]
+        .iter()
+        .filter_map(|close| workbook_xml.rfind(close).map(|i| i + close.len()))
+        .max();
+    match at {
+        Some(at) => format!(r#"{}<calcPr fullCalcOnLoad="1"/>{}"#, &workbook_xml[..at], &workbook_xml[at..]),
+        None => workbook_xml.to_string(),
     }
-    zip_writer.finish().map_err(|e| e.to_string())?;
-    std::fs::rename(&temp_path, path).map_err(|e| format!("Replace file: {}", e))?;
+}
+
+/// Turn the saved cells `formulas` (cell ref, formula without "=") on `sheet_name` into formula cells,
+/// keeping their style, and have Excel recalculate the workbook on open since they carry no results.
+fn apply_formulas(path: &Path, sheet_name: &str, formulas: &[(String, String)]) -> Result<(), AppError> {
+    let file = std::fs::File::open(path).map_err(|e| AppError::Excel(format!("Could not open for formulas: {}", e)))?;
+    let mut archive = ZipArchive::new(file).map_err(|e| AppError::Excel(format!("Invalid zip: {}", e)))?;
+    let sheet_part = worksheet_part(&mut archive, sheet_name)?;
+    let sheet_xml = read_zip_text(&mut archive, &sheet_part)?;
+    let workbook_xml = read_zip_text(&mut archive, "xl/workbook.xml")?;
+
+    let cell_re = Regex::new(r#"(?s)<c\s[^>]*?/>|<c\s[^>]*>.*?</c>"#).expect("cell regex");
+    let type_re = Regex::new(r#"\st="[^"]*""#).expect("cell type regex");
+    let sheet_xml = cell_re
+        .replace_all(&sheet_xml, |c: &regex::Captures| {
+            let open = c[0].split('>').next().unwrap_or("").trim_end_matches('/');
+            match xml_attr(open, "r").and_then(|r| formulas.iter().find(|(cell_ref, _)| *cell_ref == r)) {
+                Some((_, formula)) => format!("{}><f>{}</f></c>", type_re.replace(open, ""), xml_escape(formula)),
+                None => c[0].to_string(),
+            }
+        })
+        .to_string();
+    let workbook_xml = with_full_calc_on_load(&workbook_xml);
+    rewrite_zip_parts(path, &mut archive, &[("xl/workbook.xml", &workbook_xml), (&sheet_part, &sheet_xml)])
+}
+
+/// Replace `path` with a copy written by `write` without risking the original: `write` fills a temp file
+/// next to it, calamine must read `sheet_name` back, then the files are swapped. The original is kept as
+/// `<name>.bak` until the replaced file reads back too, and restored if it does not.
+fn replace_workbook_atomically(
+    path: &Path,
+    sheet_name: &str,
+    write: impl FnOnce(&Path) -> Result<(), AppError>,
+) -> Result<(), AppError> {
+    let file_name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    let temp_path = path.with_file_name(format!("{}.saving.xlsx", file_name));
+    let backup_path = path.with_file_name(format!("{}.bak", file_name));
+    let readable = |p: &Path| {
+        open_workbook_auto(p)
+            .map_err(|e| e.to_string())
+            .and_then(|mut wb| wb.worksheet_range(sheet_name).map(|_| ()).map_err(|e| e.to_string()))
+    };
+
+    let written = write(&temp_path).and_then(|_| {
+        readable(&temp_path).map_err(|e| AppError::Excel(format!("The saved workbook could not be read back: {}", e)))
+    });
+    if let Err(e) = written {
+        let _ = std::fs::remove_file(&temp_path);
+        return Err(e);
+    }
+    if let Err(e) = std::fs::rename(path, &backup_path) {
+        let _ = std::fs::remove_file(&temp_path);
+        return Err(if e.kind() == std::io::ErrorKind::PermissionDenied {
+            AppError::Excel(crate::services::excel_lock::locked_error(path))
+        } else {
+            AppError::Excel(format!("Cannot write to file: {}", e))
+        });
+    }
+    let swapped = std::fs::rename(&temp_path, path)
+        .map_err(|e| format!("Cannot write to file: {}", e))
+        .and_then(|_| readable(path).map_err(|e| format!("The saved workbook could not be read back: {}", e)));
+    if let Err(e) = swapped {
+        let _ = std::fs::remove_file(&temp_path);
+        let _ = std::fs::rename(&backup_path, path);
+        return Err(AppError::Excel(format!("{} The original file was restored.", e)));
+    }
+    let _ = std::fs::remove_file(&backup_path);
     Ok(())
 }
 
+/// Part holding the macros of a macro-enabled (.xlsm) workbook.
+const VBA_PROJECT_PART: &str = "xl/vbaProject.bin";
+
+/// edit_xlsx repacks the original parts, so an .xlsm keeps its VBA project, but it writes `<workbookPr>`
+/// without `codeName`, which unbinds the ThisWorkbook module. Copy the element of `original` into the
+/// saved copy `saved`; a copy that lost the project is refused so the original stays as it is.
+fn restore_vba_workbook_pr(original: &Path, saved: &Path) -> Result<(), AppError> {
+    let open = |p: &Path| {
+        std::fs::File::open(p)
+            .map_err(|e| AppError::Excel(format!("Could not open: {}", e)))
+            .and_then(|f| ZipArchive::new(f).map_err(|e| AppError::Excel(format!("Invalid zip: {}", e))))
+    };
+    let mut source = open(original)?;
+    if source.by_name(VBA_PROJECT_PART).is_err() {
+        return Ok(());
+    }
+    let mut archive = open(saved)?;
+    if archive.by_name(VBA_PROJECT_PART).is_err() {
+        return Err(AppError::Excel(
+            "Saving would have removed the workbook's macros (vbaProject.bin); the file was not changed.".to_string(),
+        ));
+    }
+    let pr_re = Regex::new(r"<workbookPr\b[^>]*>").expect("workbookPr regex");
+    let source_xml = read_zip_text(&mut source, "xl/workbook.xml")?;
+    let Some(original_pr) = pr_re.find(&source_xml) else {
+        return Ok(());
+    };
+    // The element has no children; keep it self-closing whatever the original looked like.
+    let original_pr = format!("{}/>", original_pr.as_str().trim_end_matches('>').trim_end_matches('/').trim_end());
+    let workbook_xml = read_zip_text(&mut archive, "xl/workbook.xml")?;
+    let restored = match pr_re.find(&workbook_xml) {
+        Some(m) if m.as_str() == original_pr => return Ok(()),
+        Some(m) => format!("{}{}{}", &workbook_xml[..m.start()], original_pr, &workbook_xml[m.end()..]),
+        None => {
+            // workbookPr precedes workbookProtection, bookViews and sheets.
+            let Some(at) = ["<workbookProtection", "<bookViews", "<sheets"].iter().filter_map(|t| workbook_xml.find(t)).min()
+            else {
+                return Ok(());
+            };
+            format!("{}{}{}", &workbook_xml[..at], original_pr, &workbook_xml[at..])
+        }
+    };
+    rewrite_zip_parts(saved, &mut archive, &[("xl/workbook.xml", &restored)])
+}
+
+/// Save an edited workbook over `path` through `replace_workbook_atomically`, stripping drawings.
+/// `dates` (cell refs on `sheet_name` and a date format) are given that format after the save, and the
+/// cells in `formulas` their formula.
+fn save_workbook_atomically(
+    workbook: &edit_xlsx::Workbook,
+    path: &Path,
+    sheet_name: &str,
+    dates: Option<(&[String], &str)>,
+    formulas: &[(String, String)],
+) -> Result<(), AppError> {
+    replace_workbook_atomically(path, sheet_name, |temp_path| {
+        workbook
+            .save_as(temp_path)
+            .map_err(|e| AppError::Excel(format!("Cannot write to file: {}", e)))?;
+        restore_vba_workbook_pr(path, temp_path)?;
+        strip_drawings_from_xlsx(temp_path).map_err(|e| AppError::Excel(format!("Could not strip drawings: {}", e)))?;
+        if let Some((cells, format_code)) = dates.filter(|(cells, _)| !cells.is_empty()) {
+            apply_date_format(temp_path, sheet_name, cells, format_code)
+                .map_err(|e| AppError::Excel(format!("Could not format date cells: {}", e)))?;
+        }
+        if !formulas.is_empty() {
+            apply_formulas(temp_path, sheet_name, formulas)
+                .map_err(|e| AppError::Excel(format!("Could not write formulas: {}", e)))?;
+        }
+        Ok(())
+    })
+}
+
+/// Open a workbook for editing; failures that mean Excel holds the file give the "close it" message.
+pub(crate) fn open_edit_workbook(path: &Path) -> Result<edit_xlsx::Workbook, String> {
+    edit_xlsx::Workbook::from_path(path).map_err(|e| {
+        let msg = e.to_string();
+        if msg.contains("Could not open") || msg.contains("permission") || msg.contains("Permission") {
+            crate::services::excel_lock::locked_error(path)
+        } else {
+            format!("Could not open Excel file: {}", msg)
+        }
+    })
+}
+
+/// Current text of a cell before it is overwritten (None when empty), for the write log.
+fn existing_cell_value(worksheet: &edit_xlsx::WorkSheet, cell_ref: &str) -> Option<String> {
+    worksheet
+        .read_cell(cell_ref)
+        .ok()
+        .and_then(|cell| cell.text)
+        .filter(|text| !text.is_empty())
+        .map(|text| workbook_compat::repair_mojibake(&text))
+}
+
 /// Append one row to existing Excel file.
 /// Uses edit_xlsx to preserve template formatting, styles, and formulas.
 /// column_values: (column_letter, value) e.g. ("A", "123"), ("B", "Invoice")
@@ -330,48 +1105,36 @@ pub fn append_row_to_excel(
     path: &str,
     sheet_name: &str,
     column_values: Vec<(String, String)>,
-) -> Result<(), String> {
+) -> Result<Vec<CellWrite>, AppError> {
+    let _timer = perf_metrics::timer(perf_metrics::Metric::Append);
     let path = Path::new(path);
     if !path.exists() {
-        return Err("File not found. Browse to select again.".to_string());
+        return Err(AppError::Excel("File not found. Browse to select again.".to_string()));
     }
 
-    let mut workbook = edit_xlsx::Workbook::from_path(path).map_err(|e| {
-        let msg = e.to_string();
-        if msg.contains("Could not open") || msg.contains("permission") || msg.contains("Permission") {
-            "Please close the file in Excel first.".to_string()
-        } else {
-            format!("Could not open Excel file: {}", msg)
-        }
-    })?;
+    let mut workbook = open_edit_workbook(path)?;
 
+    let sheet_name = resolve_workbook_sheet_name(&workbook, sheet_name)?;
     let worksheet = workbook
-        .get_worksheet_mut_by_name(sheet_name)
-        .map_err(|e| format!("Sheet not found: {}", e))?;
+        .get_worksheet_mut_by_name(&sheet_name)
+        .map_err(|e| AppError::Excel(format!("Sheet not found: {}", e)))?;
 
     let new_row = worksheet.max_row() + 1;
     let format = data_cell_format();
+    let mut writes = Vec::new();
     for (col_letter, value) in column_values {
         let cell_ref = format!("{}{}", col_letter.to_uppercase(), new_row);
         let safe_value = sanitize_cell(&value);
+        writes.push(CellWrite::new(&sheet_name, &cell_ref, new_row, None, &safe_value));
         worksheet
             .write_string_with_format(&cell_ref, safe_value, &format)
-            .map_err(|e| e.to_string())?;
+            .map_err(AppError::excel)?;
     }
     let _ = worksheet.set_row_height_with_format(new_row, 96.0, &format);
 
-    workbook.save_as(path).map_err(|e| {
-        let msg = e.to_string();
-        if msg.contains("Permission denied") || msg.contains("being used") {
-            "Please close the file in Excel first.".to_string()
-        } else {
-            format!("Cannot write to file: {}", msg)
-        }
-    })?;
-
-    // Strip drawing parts so Excel won't show "Repairs... Removed Part: Drawing shape"
-    strip_drawings_from_xlsx(path).map_err(|e| format!("Could not strip drawings: {}", e))?;
-    Ok(())
+    // Drawing parts are stripped so Excel won't show "Repairs... Removed Part: Drawing shape"
+    save_workbook_atomically(&workbook, path, &sheet_name, None, &[])?;
+    Ok(writes)
 }
 
 /// Data row format: smaller font (9pt), normal weight, top+left align so multi-line text is readable and not cut off.
@@ -383,55 +1146,599 @@ fn data_cell_format() -> edit_xlsx::Format {
         .set_align(FormatAlignType::Left)
 }
 
+/// Columns are never widened past this on append (Excel's own limit is 255).
+const MAX_FITTED_COLUMN_WIDTH: f64 = 60.0;
+
+/// Result of an append: its cell writes and the columns it widened.
+pub struct FittedAppend {
+    pub writes: Vec<CellWrite>,
+    /// (letter, new width).
+    pub widened: Vec<(String, f64)>,
+    /// Set when the workbook could not be edited normally and the fallback writer was used; says which
+    /// formatting was not applied.
+    pub fallback_warning: Option<String>,
+}
+
+/// Width a cell needs for its longest line at the 9pt data font.
+fn fitted_column_width(value: &str) -> f64 {
+    let longest = value.lines().map(|l| l.chars().count()).max().unwrap_or(0);
+    (longest as f64 * 1.1 + 2.0).min(MAX_FITTED_COLUMN_WIDTH)
+}
+
 /// Append one row at a specific row number (for fast append when next_free_row is cached).
-/// Uses larger row height so multi-line cells (e.g. Опис) are fully visible, and smaller font.
+/// Cells take the saved format of their column in `schema` (font, colors, borders, alignment, number format).
 pub fn append_row_to_excel_at_row(
     path: &str,
     sheet_name: &str,
     row_number: u32,
     column_values: Vec<(String, String)>,
-) -> Result<(), String> {
+    schema: &ExcelSchema,
+    locale: &ExportLocale,
+) -> Result<Vec<CellWrite>, AppError> {
+    append_rows_fitting_columns(path, sheet_name, vec![(row_number, column_values)], schema, locale)
+        .map(|appended| appended.writes)
+}
+
+/// `append_row_to_excel_at_row` that also widens, in the same save, columns whose new content is wider than
+/// their width in `schema` (the sheet's width when absent), up to 60. Starts from the workbook kept parsed
+/// by `workbook_standby`, for one-by-one appends from review.
+pub fn append_row_fitting_columns(
+    path: &str,
+    sheet_name: &str,
+    row_number: u32,
+    column_values: Vec<(String, String)>,
+    schema: &ExcelSchema,
+    locale: &ExportLocale,
+) -> Result<FittedAppend, AppError> {
+    append_fitting(path, sheet_name, vec![(row_number, column_values)], schema, locale, true)
+}
+
+/// Saved format of a ledger column applied to a data cell; `alternate` takes the alternating background.
+/// Backgrounds that are white or match the font color are left out (unreadable colors come from themes).
+fn column_cell_format(column: &ColumnFormat, alternate: bool) -> edit_xlsx::Format {
+    let mut format = edit_xlsx::Format::default().set_align(FormatAlignType::Top);
+    if !column.font_name.trim().is_empty() {
+        format = format.set_font(column.font_name.trim());
+    }
+    if column.font_size > 0 {
+        format = format.set_size_f64(column.font_size as f64);
+    }
+    if let Some(color) = hex_color(&column.font_color) {
+        format = format.set_color(color);
+    }
+    if column.font_bold {
+        format = format.set_bold();
+    }
+    if column.font_italic {
+        format = format.set_italic();
+    }
+    let background = match (&column.background_color_alt, alternate) {
+        (Some(alt), true) => alt,
+        _ => &column.background_color,
+    };
+    if !background.eq_ignore_ascii_case("#FFFFFF") && !background.eq_ignore_ascii_case(&column.font_color) {
+        if let Some(color) = hex_color(background) {
+            format = format.set_background_color(color);
+        }
+    }
+    let border = match column.border_style.as_str() {
+        "thin" => Some(FormatBorderType::Thin),
+        "medium" => Some(FormatBorderType::Medium),
+        _ => None,
+    };
+    if let Some(border) = border {
+        format = format.set_border(border);
+    }
+    match column.alignment.as_str() {
+        "center" => format.set_align(FormatAlignType::Center),
+        "right" => format.set_align(FormatAlignType::Right),
+        _ => format.set_align(FormatAlignType::Left),
+    }
+}
+
+/// "#RRGGBB" to an edit_xlsx color.
+fn hex_color(hex: &str) -> Option<edit_xlsx::FormatColor> {
+    let hex = hex.trim().strip_prefix('#')?;
+    if hex.len() != 6 {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+    Some(edit_xlsx::FormatColor::RGB(channel(0)?, channel(2)?, channel(4)?))
+}
+
+/// Cell text in the column's saved number format. edit_xlsx cannot attach a number format to a cell, so
+/// amounts get the format's decimals and grouping (with the export locale's separators) and dates its
+/// day/month/year order as text; other values and formats are written unchanged.
+fn column_cell_text(column: &ColumnFormat, value: &str, locale: &ExportLocale) -> String {
+    let Some(code) = column.number_format.as_deref().and_then(|f| f.split(';').next()) else {
+        return value.to_string();
+    };
+    let lower = code.to_lowercase();
+    if lower.contains('y') || lower.contains('d') {
+        let Some(date) = crate::services::spending_report::parse_invoice_date(value) else {
+            return value.to_string();
+        };
+        let pattern = lower.replace("yyyy", "%Y").replace("yy", "%y").replace("dd", "%d").replace("mm", "%m");
+        return date.format(&pattern).to_string();
+    }
+    if code.contains('%') || !(code.contains('0') || code.contains('#')) {
+        return value.to_string();
+    }
+    let Some(n) = crate::services::validation::parse_amount(value) else {
+        return value.to_string();
+    };
+    let decimals = code.split_once('.').map_or(0, |(_, d)| d.chars().filter(|c| *c == '0').count());
+    locale.number(n, decimals, code.contains(','))
+}
+
+/// Value of a cell in a number or date column, written as a real cell so SUM and date filters work.
+enum TypedCell {
+    Number(f64),
+    /// Excel serial day number.
+    Date(f64),
+}
+
+/// Number for `data_type` "number" columns, date serial for "date" columns (and, with the locale's Excel
+/// dates on, for single-word date values in other columns); None (written as text) when the value does not
+/// parse, so text in those columns is never lost.
+fn typed_cell_value(column: &ColumnFormat, value: &str, locale: &ExportLocale) -> Option<TypedCell> {
+    let trimmed = value.trim();
+    match column.data_type.as_str() {
+        "number" => {
+            // Codes like "007" keep their leading zeros as text.
+            if trimmed.len() > 1 && trimmed.starts_with('0') && trimmed.as_bytes()[1].is_ascii_digit() {
+                return None;
+            }
+            normalize_amount_string(trimmed).parse::<f64>().ok().filter(|n| n.is_finite()).map(TypedCell::Number)
+        }
+        "date" => locale.parse_date(trimmed).map(|d| TypedCell::Date(export_locale::excel_serial(d))),
+        _ if locale.excel_dates && !trimmed.contains(char::is_whitespace) => locale
+            .parse_date(trimmed)
+            .map(|d| TypedCell::Date(export_locale::excel_serial(d))),
+        _ => None,
+    }
+}
+
+/// `append_row_fitting_columns` for several rows (row number, column values) in one open/save of the workbook.
+/// Empty cells below formulas get the formula filled down (see `extended_formulas`).
+/// Workbooks edit_xlsx cannot open or save (it fails or panics on some styles) are written by
+/// `append_rows_to_sheet_xml` instead, with `fallback_warning` set; lock errors are returned as they are.
+pub fn append_rows_fitting_columns(
+    path: &str,
+    sheet_name: &str,
+    rows: Vec<(u32, Vec<(String, String)>)>,
+    schema: &ExcelSchema,
+    locale: &ExportLocale,
+) -> Result<FittedAppend, AppError> {
+    append_fitting(path, sheet_name, rows, schema, locale, false)
+}
+
+/// Error text when edit_xlsx panics on a workbook.
+pub(crate) const LIBRARY_STOPPED: &str = "The Excel library stopped on this workbook.";
+
+/// `append_rows_fitting_columns`, from the standby's parsed workbook with `warm`.
+fn append_fitting(
+    path: &str,
+    sheet_name: &str,
+    rows: Vec<(u32, Vec<(String, String)>)>,
+    schema: &ExcelSchema,
+    locale: &ExportLocale,
+    warm: bool,
+) -> Result<FittedAppend, AppError> {
+    let _timer = perf_metrics::timer(perf_metrics::Metric::Append);
     let path = Path::new(path);
     if !path.exists() {
-        return Err("File not found. Browse to select again.".to_string());
+        return Err(AppError::Excel("File not found. Browse to select again.".to_string()));
     }
+    let template_row = schema.row_template.template_row_index;
+    let cold_formulas = |rows: &[(u32, Vec<(String, String)>)]| {
+        extended_formulas(&sheet_formulas(path, sheet_name), rows, template_row)
+    };
+    let (edited, formulas) = if warm {
+        let (file, sheet, warm_rows) = (path.to_path_buf(), sheet_name.to_string(), rows.clone());
+        let (warm_schema, warm_locale) = (schema.clone(), locale.clone());
+        let appended = workbook_standby::with_workbook(path, move |standby| {
+            let formulas = extended_formulas(&standby.formulas(&sheet), &warm_rows, template_row);
+            let edited = append_rows_to_workbook(
+                &mut standby.workbook,
+                &file,
+                &sheet,
+                &warm_rows,
+                &formulas,
+                &warm_schema,
+                &warm_locale,
+            );
+            (edited, formulas)
+        });
+        match appended {
+            Ok(done) => done,
+            Err(e) => (Err(e), cold_formulas(&rows)),
+        }
+    } else {
+        let formulas = cold_formulas(&rows);
+        let edited = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            append_rows_with_edit_xlsx(path, sheet_name, &rows, &formulas, schema, locale)
+        }))
+        .unwrap_or_else(|_| Err(AppError::Excel(LIBRARY_STOPPED.to_string())));
+        (edited, formulas)
+    };
+    let error = match edited {
+        Ok(appended) => return Ok(appended),
+        Err(e) if e.to_string().contains(crate::services::excel_lock::FILE_LOCKED) => return Err(e),
+        Err(e) => e.to_string(),
+    };
+    app_warnings::report("excel", format!("append via edit_xlsx failed ({}); using the fallback writer", error));
+    let (writes, template_row) = append_rows_to_sheet_xml(path, sheet_name, &rows, &formulas, schema, locale)
+        .map_err(|fallback| AppError::Excel(format!("{} The fallback writer failed too: {}", error, fallback)))?;
+    Ok(FittedAppend {
+        writes,
+        widened: Vec::new(),
+        fallback_warning: Some(format!(
+            "This workbook could not be edited normally ({}). The rows were written with the fallback writer: \
+             new cells copy the formatting of row {}, and column widths and row heights were not adjusted.",
+            error, template_row
+        )),
+    })
+}
 
-    let mut workbook = edit_xlsx::Workbook::from_path(path).map_err(|e| {
-        let msg = e.to_string();
-        if msg.contains("Could not open") || msg.contains("permission") || msg.contains("Permission") {
-            "Please close the file in Excel first.".to_string()
-        } else {
-            format!("Could not open Excel file: {}", msg)
+/// Formulas of `sheet_name` by (1-based row, 0-based column); empty when they cannot be read.
+pub(crate) fn sheet_formulas(path: &Path, sheet_name: &str) -> HashMap<(u32, u32), String> {
+    let Ok(mut workbook) = open_workbook_auto(path) else {
+        return HashMap::new();
+    };
+    let Ok(sheet_name) = resolve_sheet_name(&workbook.sheet_names(), sheet_name) else {
+        return HashMap::new();
+    };
+    let Ok(range) = workbook.worksheet_formula(&sheet_name) else {
+        return HashMap::new();
+    };
+    let (first_row, first_col) = range.start().unwrap_or((0, 0));
+    range
+        .cells()
+        .filter(|(_, _, formula)| !formula.is_empty())
+        .map(|(r, c, formula)| ((first_row + r as u32 + 1, first_col + c as u32), formula.clone()))
+        .collect()
+}
+
+/// Formulas for cells the new rows leave empty, in columns where the row above (or else the template row)
+/// has one in `sheet` (see `sheet_formulas`), shifted to the new row so running totals and computed
+/// columns continue. (cell ref, formula without "=").
+fn extended_formulas(
+    sheet: &HashMap<(u32, u32), String>,
+    rows: &[(u32, Vec<(String, String)>)],
+    template_row: u32,
+) -> Vec<(String, String)> {
+    // Rows filled here are the row above for the next ones.
+    let mut filled: HashMap<(u32, u32), String> = HashMap::new();
+    let mut extended = Vec::new();
+    for (row_number, column_values) in rows {
+        let row_number = *row_number;
+        if row_number <= template_row {
+            continue;
         }
-    })?;
+        let formula_at = |filled: &HashMap<(u32, u32), String>, cell: (u32, u32)| {
+            filled.get(&cell).or_else(|| sheet.get(&cell)).cloned()
+        };
+        let mut columns: Vec<u32> = sheet
+            .keys()
+            .chain(filled.keys())
+            .filter(|(r, _)| *r == row_number - 1 || *r == template_row)
+            .map(|(_, c)| *c)
+            .collect();
+        columns.sort_unstable();
+        columns.dedup();
+        for col in columns {
+            let letter = col_index_to_letter(col);
+            let given = column_values.iter().any(|(l, v)| l.eq_ignore_ascii_case(&letter) && !v.trim().is_empty());
+            if given || formula_at(&filled, (row_number, col)).is_some() {
+                continue;
+            }
+            let source = match formula_at(&filled, (row_number - 1, col)) {
+                Some(formula) => Some((row_number - 1, formula)),
+                None => formula_at(&filled, (template_row, col)).map(|formula| (template_row, formula)),
+            };
+            let Some((source_row, source)) = source else {
+                continue;
+            };
+            if let Some(formula) = shift_formula_rows(&source, i64::from(row_number - source_row)) {
+                extended.push((format!("{}{}", letter, row_number), formula.clone()));
+                filled.insert((row_number, col), formula);
+            }
+        }
+    }
+    extended
+}
 
-    let worksheet = workbook
-        .get_worksheet_mut_by_name(sheet_name)
-        .map_err(|e| format!("Sheet not found: {}", e))?;
+/// Entries of `formulas` whose cell is on `row_number`.
+fn formulas_in_row(formulas: &[(String, String)], row_number: u32) -> impl Iterator<Item = &(String, String)> {
+    let row = row_number.to_string();
+    formulas.iter().filter(move |(cell_ref, _)| cell_ref.trim_start_matches(|c: char| c.is_ascii_alphabetic()) == row)
+}
 
-    let format = data_cell_format();
-    for (col_letter, value) in &column_values {
-        let cell_ref = format!("{}{}", col_letter.to_uppercase(), row_number);
-        let safe_value = sanitize_cell(value);
-        worksheet
-            .write_string_with_format(&cell_ref, safe_value, &format)
-            .map_err(|e| e.to_string())?;
+/// Cell letter to 1-based column number (A -> 1, AA -> 27).
+fn column_number(letter: &str) -> u32 {
+    letter.bytes().filter(u8::is_ascii_alphabetic).fold(0, |n, b| n * 26 + u32::from(b.to_ascii_uppercase() - b'A' + 1))
+}
+
+/// Fallback append for workbooks edit_xlsx cannot handle: the rows are spliced into the sheet XML, so every
+/// other part of the file is kept as it is. New cells take the style of the template row's cell in the same
+/// column (alternating rows that of the row below it); an existing row keeps its own cell styles. Numbers
+/// and dates are written as in the normal path, text as inline strings. Returns the writes and the
+/// template row used.
+fn append_rows_to_sheet_xml(
+    path: &Path,
+    sheet_name: &str,
+    rows: &[(u32, Vec<(String, String)>)],
+    formulas: &[(String, String)],
+    schema: &ExcelSchema,
+    locale: &ExportLocale,
+) -> Result<(Vec<CellWrite>, u32), AppError> {
+    // calamine reads these workbooks: it resolves the sheet name and gives the values being replaced.
+    let mut values_wb = open_workbook_auto(path).map_err(|e| AppError::Excel(format!("Could not open Excel file: {}", e)))?;
+    let sheet_name = resolve_sheet_name_for_write(&values_wb.sheet_names(), sheet_name)?;
+    let range = values_wb
+        .worksheet_range(&sheet_name)
+        .map_err(|e| AppError::Excel(format!("Sheet not found: {}", e)))?;
+    let date1904 = workbook_compat::uses_1904_dates(path);
+
+    let file = std::fs::File::open(path).map_err(|e| AppError::Excel(format!("Could not open Excel file: {}", e)))?;
+    let mut archive = ZipArchive::new(file).map_err(|e| AppError::Excel(format!("Invalid zip: {}", e)))?;
+    let sheet_part = worksheet_part(&mut archive, &sheet_name)?;
+    let mut sheet_xml = read_zip_text(&mut archive, &sheet_part)?;
+
+    let row_re = Regex::new(r#"(?s)<row\s[^>]*?/>|<row\s[^>]*>.*?</row>"#).expect("row regex");
+    let cell_re = Regex::new(r#"(?s)<c\s[^>]*?/>|<c\s[^>]*>.*?</c>"#).expect("cell regex");
+    let letters_re = Regex::new(r"^[A-Za-z]+").expect("letters regex");
+    let spans_re = Regex::new(r#"\sspans="[^"]*""#).expect("spans regex");
+    let find_row = |xml: &str, number: u32| {
+        row_re
+            .find_iter(xml)
+            .find(|m| xml_attr(m.as_str(), "r").and_then(|r| r.parse::<u32>().ok()) == Some(number))
+            .map(|m| (m.range(), m.as_str().to_string()))
+    };
+    // (letter, cell XML) of a row element.
+    let row_cells = |row_xml: &str| -> Vec<(String, String)> {
+        cell_re
+            .find_iter(row_xml)
+            .filter_map(|m| {
+                let r = xml_attr(m.as_str(), "r")?;
+                let letter = letters_re.find(&r)?.as_str().to_uppercase();
+                Some((letter, m.as_str().to_string()))
+            })
+            .collect()
+    };
+    let style_of = |xml: &str, number: u32| -> Vec<(String, String)> {
+        find_row(xml, number)
+            .map(|(_, row_xml)| {
+                row_cells(&row_xml)
+                    .into_iter()
+                    .filter_map(|(letter, cell)| Some((letter, xml_attr(cell.split('>').next()?, "s")?)))
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+    let template = &schema.row_template;
+    let template_styles = style_of(&sheet_xml, template.template_row_index);
+    let alternate_styles = style_of(&sheet_xml, template.template_row_index + 1);
+
+    let mut writes = Vec::new();
+    let mut date_cells = Vec::new();
+    for (row_number, column_values) in rows {
+        let alternate = template.use_alternating_colors
+            && row_number.abs_diff(template.template_row_index) % 2 == 1;
+        let styles = if alternate { &alternate_styles } else { &template_styles };
+        let existing = find_row(&sheet_xml, *row_number);
+        let mut cells = existing.as_ref().map(|(_, row_xml)| row_cells(row_xml)).unwrap_or_default();
+        for (col_letter, value) in column_values {
+            let letter = col_letter.to_uppercase();
+            let column = schema.columns.iter().find(|c| c.column_letter.eq_ignore_ascii_case(&letter));
+            let cell_ref = format!("{}{}", letter, row_number);
+            if formulas.iter().any(|(r, _)| *r == cell_ref) {
+                continue;
+            }
+            let text = column.map_or_else(|| value.clone(), |c| column_cell_text(c, value, locale));
+            let safe_value = sanitize_cell(&text);
+            let old_value = range
+                .get_value((row_number - 1, column_number(&letter) - 1))
+                .map(|cell| workbook_compat::cell_text(cell, date1904))
+                .filter(|text| !text.is_empty());
+            writes.push(CellWrite::new(&sheet_name, &cell_ref, *row_number, old_value, &safe_value));
+
+            let current = cells.iter().position(|(l, _)| *l == letter);
+            let style = current
+                .and_then(|i| xml_attr(cells[i].1.split('>').next().unwrap_or(""), "s"))
+                .or_else(|| styles.iter().find(|(l, _)| *l == letter).map(|(_, s)| s.clone()))
+                .map(|s| format!(r#" s="{}""#, s))
+                .unwrap_or_default();
+            let cell = match column.and_then(|c| typed_cell_value(c, value, locale)) {
+                Some(TypedCell::Number(n)) => format!(r#"<c r="{}"{}><v>{}</v></c>"#, cell_ref, style, n),
+                Some(TypedCell::Date(serial)) => {
+                    date_cells.push(cell_ref.clone());
+                    format!(r#"<c r="{}"{}><v>{}</v></c>"#, cell_ref, style, serial)
+                }
+                None if safe_value.is_empty() => format!(r#"<c r="{}"{}/>"#, cell_ref, style),
+                None => format!(
+                    r#"<c r="{}"{} t="inlineStr"><is><t xml:space="preserve">{}</t></is></c>"#,
+                    cell_ref,
+                    style,
+                    xml_escape(&safe_value)
+                ),
+            };
+            match current {
+                Some(i) => cells[i].1 = cell,
+                None => cells.push((letter, cell)),
+            }
+        }
+        for (cell_ref, formula) in formulas_in_row(formulas, *row_number) {
+            let letter = cell_ref.trim_end_matches(|c: char| c.is_ascii_digit()).to_string();
+            writes.push(CellWrite::new(&sheet_name, cell_ref, *row_number, None, &format!("={}", formula)));
+            let style = styles
+                .iter()
+                .find(|(l, _)| *l == letter)
+                .map(|(_, s)| format!(r#" s="{}""#, s))
+                .unwrap_or_default();
+            let cell = format!(r#"<c r="{}"{}><f>{}</f></c>"#, cell_ref, style, xml_escape(formula));
+            match cells.iter().position(|(l, _)| *l == letter) {
+                Some(i) => cells[i].1 = cell,
+                None => cells.push((letter, cell)),
+            }
+        }
+        cells.sort_by_key(|(letter, _)| column_number(letter));
+        let body: String = cells.into_iter().map(|(_, cell)| cell).collect();
+        match existing {
+            Some((range, row_xml)) => {
+                // Keep the row's attributes (height, style) but drop spans, which may no longer cover its cells.
+                let open = row_xml.split('>').next().unwrap_or("<row").trim_end_matches('/');
+                let open = spans_re.replace(open, "");
+                sheet_xml.replace_range(range, &format!("{}>{}</row>", open, body));
+            }
+            None => {
+                let row = format!(r#"<row r="{}">{}</row>"#, row_number, body);
+                let after = row_re
+                    .find_iter(&sheet_xml)
+                    .find(|m| xml_attr(m.as_str(), "r").and_then(|r| r.parse::<u32>().ok()).is_some_and(|r| r > *row_number))
+                    .map(|m| m.start());
+                match (after, sheet_xml.find("</sheetData>"), sheet_xml.find("<sheetData/>")) {
+                    (Some(at), _, _) | (None, Some(at), _) => sheet_xml.insert_str(at, &row),
+                    (None, None, Some(at)) => {
+                        sheet_xml.replace_range(at..at + "<sheetData/>".len(), &format!("<sheetData>{}</sheetData>", row))
+                    }
+                    (None, None, None) => return Err(AppError::Excel("The sheet has no sheetData.".to_string())),
+                }
+            }
+        }
     }
 
-    // Tall row so multi-line text (e.g. Опис) is fully visible; 96pt fits ~6–8 lines at 9pt.
-    let row_height = 96.0;
-    let _ = worksheet.set_row_height_with_format(row_number, row_height, &format);
+    // Grow the used range so Excel's Ctrl+End and printing include the new rows.
+    let last_row = rows.iter().map(|(r, _)| *r).max().unwrap_or(0);
+    let dimension_re = Regex::new(r#"<dimension\s+ref="([A-Za-z]+\d+:)?([A-Za-z]+)(\d+)""#).expect("dimension regex");
+    if let Some(c) = dimension_re.captures(&sheet_xml) {
+        let end: u32 = c[3].parse().unwrap_or(0);
+        if end < last_row {
+            let start = c.get(1).map_or_else(|| format!("{}{}:", &c[2], &c[3]), |m| m.as_str().to_string());
+            let replacement = format!(r#"<dimension ref="{}{}{}""#, start, &c[2], last_row);
+            let span = c.get(0).map(|m| m.range()).unwrap_or_default();
+            sheet_xml.replace_range(span, &replacement);
+        }
+    }
 
-    workbook.save_as(path).map_err(|e| {
-        let msg = e.to_string();
-        if msg.contains("Permission denied") || msg.contains("being used") {
-            "Please close the file in Excel first.".to_string()
-        } else {
-            format!("Cannot write to file: {}", msg)
+    replace_workbook_atomically(path, &sheet_name, |temp_path| {
+        rewrite_zip_parts(temp_path, &mut archive, &[(&sheet_part, &sheet_xml)])?;
+        if !date_cells.is_empty() {
+            apply_date_format(temp_path, &sheet_name, &date_cells, &locale.date_num_format())?;
+        }
+        if !formulas.is_empty() {
+            apply_formulas(temp_path, &sheet_name, formulas)?;
         }
+        Ok(())
     })?;
-    strip_drawings_from_xlsx(path).map_err(|e| format!("Could not strip drawings: {}", e))?;
-    Ok(())
+    Ok((writes, template.template_row_index))
+}
+
+/// `append_rows_fitting_columns` through edit_xlsx, which keeps the template's formatting and widens columns.
+fn append_rows_with_edit_xlsx(
+    path: &Path,
+    sheet_name: &str,
+    rows: &[(u32, Vec<(String, String)>)],
+    formulas: &[(String, String)],
+    schema: &ExcelSchema,
+    locale: &ExportLocale,
+) -> Result<FittedAppend, AppError> {
+    let mut workbook = open_edit_workbook(path)?;
+    append_rows_to_workbook(&mut workbook, path, sheet_name, rows, formulas, schema, locale)
+}
+
+/// The rows of `append_rows_with_edit_xlsx` written into the opened `workbook` of `path`, which is then saved.
+fn append_rows_to_workbook(
+    workbook: &mut edit_xlsx::Workbook,
+    path: &Path,
+    sheet_name: &str,
+    rows: &[(u32, Vec<(String, String)>)],
+    formulas: &[(String, String)],
+    schema: &ExcelSchema,
+    locale: &ExportLocale,
+) -> Result<FittedAppend, AppError> {
+    let sheet_name = resolve_workbook_sheet_name(workbook, sheet_name)?;
+    let worksheet = workbook
+        .get_worksheet_mut_by_name(&sheet_name)
+        .map_err(|e| AppError::Excel(format!("Sheet not found: {}", e)))?;
+
+    let fallback = data_cell_format();
+    let template = &schema.row_template;
+    let mut writes = Vec::new();
+    let mut date_cells = Vec::new();
+    // Widest new content per column across all rows.
+    let mut needed_widths: Vec<(String, f64)> = Vec::new();
+    for (row_number, column_values) in rows {
+        let alternate = template.use_alternating_colors
+            && row_number.abs_diff(template.template_row_index) % 2 == 1;
+        // Lines and font size of the tallest cell, for the row height.
+        let mut tallest = (1usize, 9.0f64);
+        for (col_letter, value) in column_values {
+            let letter = col_letter.to_uppercase();
+            let column = schema.columns.iter().find(|c| c.column_letter.eq_ignore_ascii_case(&letter));
+            let cell_ref = format!("{}{}", letter, row_number);
+            if formulas.iter().any(|(r, _)| *r == cell_ref) {
+                continue;
+            }
+            let text = column.map_or_else(|| value.clone(), |c| column_cell_text(c, value, locale));
+            let safe_value = sanitize_cell(&text);
+            let old_value = existing_cell_value(worksheet, &cell_ref);
+            writes.push(CellWrite::new(&sheet_name, &cell_ref, *row_number, old_value, &safe_value));
+            let needed = fitted_column_width(&safe_value);
+            let font_size = column.map_or(9.0, |c| c.font_size.max(1) as f64);
+            let lines = safe_value.lines().count().max(1);
+            if lines as f64 * font_size > tallest.0 as f64 * tallest.1 {
+                tallest = (lines, font_size);
+            }
+            let format = column.map_or_else(|| fallback.clone(), |c| column_cell_format(c, alternate));
+            match column.and_then(|c| typed_cell_value(c, value, locale)) {
+                Some(TypedCell::Number(n)) => worksheet.write_double_with_format(&cell_ref, n, &format),
+                Some(TypedCell::Date(serial)) => {
+                    date_cells.push(cell_ref.clone());
+                    worksheet.write_double_with_format(&cell_ref, serial, &format)
+                }
+                None => worksheet.write_string_with_format(&cell_ref, safe_value, &format),
+            }
+            .map_err(AppError::excel)?;
+            match needed_widths.iter_mut().find(|(l, _)| *l == letter) {
+                Some((_, width)) => *width = width.max(needed),
+                None => needed_widths.push((letter, needed)),
+            }
+        }
+        for (cell_ref, formula) in formulas_in_row(formulas, *row_number) {
+            let letter = cell_ref.trim_end_matches(|c: char| c.is_ascii_digit());
+            let column = schema.columns.iter().find(|c| c.column_letter.eq_ignore_ascii_case(letter));
+            let old_value = existing_cell_value(worksheet, cell_ref);
+            writes.push(CellWrite::new(&sheet_name, cell_ref, *row_number, old_value, &format!("={}", formula)));
+            // A styled empty cell; the formula is put in after the save.
+            let format = column.map_or_else(|| fallback.clone(), |c| column_cell_format(c, alternate));
+            worksheet.write_string_with_format(cell_ref, String::new(), &format).map_err(AppError::excel)?;
+        }
+
+        // The template's row height, taller when multi-line text (e.g. Опис) needs it; capped at Excel's 409pt.
+        let row_height = (tallest.0 as f64 * tallest.1 * 1.35 + 4.0).max(template.row_height).min(409.0);
+        let _ = worksheet.set_row_height(*row_number, row_height);
+    }
+
+    let mut widened = Vec::new();
+    for (letter, needed) in needed_widths {
+        let range = format!("{0}:{0}", letter);
+        let known = schema.columns.iter().find(|c| c.column_letter.eq_ignore_ascii_case(&letter));
+        let current = known.map(|c| c.column_width).or_else(|| {
+            worksheet
+                .get_columns_width(range.as_str())
+                .ok()
+                .and_then(|widths| widths.into_values().flatten().next())
+        });
+        // Columns without a custom width use Excel's default (~8.43).
+        if needed > current.unwrap_or(8.43) + 0.5 && worksheet.set_columns_width(range.as_str(), needed).is_ok() {
+            widened.push((letter, needed));
+        }
+    }
+
+    save_workbook_atomically(workbook, path, &sheet_name, Some((&date_cells, &locale.date_num_format())), formulas)?;
+    Ok(FittedAppend { writes, widened, fallback_warning: None })
 }
 
 /// Parse declaration period string (e.g. "05/2025", "5/2025", "05.2025") to month 1–12. Returns None if unparseable.
@@ -571,9 +1878,9 @@ pub fn write_plata_to_template(
     sheet_name: &str,
     declaration_period: &str,
     fields: &std::collections::HashMap<String, crate::types::InvoiceFieldValue>,
-) -> Result<(), String> {
+) -> Result<Vec<CellWrite>, AppError> {
     let month = parse_plata_month(declaration_period)
-        .ok_or_else(|| format!("Could not parse declaration period '{}' (use MM/YYYY e.g. 05/2025)", declaration_period))?;
+        .ok_or_else(|| AppError::Excel(format!("Could not parse declaration period '{}' (use MM/YYYY e.g. 05/2025)", declaration_period)))?;
     let col_letter = col_index_to_letter(5 + (month - 1)); // F=Jan (01), G=Feb (02), …, Q=Dec (12)
 
     let get = |key: &str| {
@@ -585,29 +1892,27 @@ pub fn write_plata_to_template(
 
     let path = Path::new(path);
     if !path.exists() {
-        return Err("File not found. Browse to select again.".to_string());
+        return Err(AppError::Excel("File not found. Browse to select again.".to_string()));
     }
-    let mut workbook = edit_xlsx::Workbook::from_path(path).map_err(|e| {
-        let msg = e.to_string();
-        if msg.contains("Could not open") || msg.contains("permission") || msg.contains("Permission") {
-            "Please close the file in Excel first.".to_string()
-        } else {
-            format!("Could not open Excel file: {}", msg)
-        }
-    })?;
+    let mut workbook = open_edit_workbook(path)?;
+    let sheet_name = resolve_workbook_sheet_name(&workbook, sheet_name)?;
     let worksheet = workbook
-        .get_worksheet_mut_by_name(sheet_name)
-        .map_err(|e| format!("Sheet not found: {}", e))?;
+        .get_worksheet_mut_by_name(&sheet_name)
+        .map_err(|e| AppError::Excel(format!("Sheet not found: {}", e)))?;
     let format = data_cell_format();
+    let mut writes = Vec::new();
 
-    let mut write_cell = |row: u32, value: &str| -> Result<(), String> {
+    let mut write_cell = |row: u32, value: &str| -> Result<(), AppError> {
         if value.is_empty() {
             return Ok(());
         }
         let cell_ref = format!("{}{}", col_letter, row);
+        let safe_value = sanitize_cell(value);
+        let old_value = existing_cell_value(worksheet, &cell_ref);
+        writes.push(CellWrite::new(&sheet_name, &cell_ref, row, old_value, &safe_value));
         worksheet
-            .write_string_with_format(&cell_ref, sanitize_cell(value), &format)
-            .map_err(|e| e.to_string())
+            .write_string_with_format(&cell_ref, safe_value, &format)
+            .map_err(AppError::excel)
     };
 
     let bruto = get("brutoPlata");
@@ -626,17 +1931,8 @@ pub fn write_plata_to_template(
     write_cell(PLATA_ROW_DECLARATION_NET, &net_val)?;
     write_cell(PLATA_ROW_EMPLOYEE_COUNT, &get("brojVraboteni"))?;
 
-    workbook.save_as(path).map_err(|e| {
-        let msg = e.to_string();
-        if msg.contains("Permission denied") || msg.contains("being used") {
-            "Please close the file in Excel first.".to_string()
-        } else {
-            format!("Cannot write to file: {}", msg)
-        }
-    })?;
-
-    strip_drawings_from_xlsx(path).map_err(|e| format!("Could not strip drawings: {}", e))?;
-    Ok(())
+    save_workbook_atomically(&workbook, path, &sheet_name, None, &[])?;
+    Ok(writes)
 }
 
 /// Write a single cell in an existing Excel file (e.g. template form: write value to row 10, column D).
@@ -646,12 +1942,12 @@ pub fn write_excel_cell(
     row_1based: u32,
     col_letter: &str,
     value: &str,
-) -> Result<(), String> {
+) -> Result<(), AppError> {
     write_excel_cells(path, sheet_name, &[(row_1based, col_letter, value)])
 }
 
 /// Escape text for use inside XML element content (e.g. <t>value</t>).
-fn escape_xml_text(s: &str) -> String {
+pub(crate) fn escape_xml_text(s: &str) -> String {
     let mut out = String::with_capacity(s.len());
     for c in s.chars() {
         match c {
@@ -668,7 +1964,7 @@ fn escape_xml_text(s: &str) -> String {
 
 /// Patch worksheet XML: replace cell values for given cell refs (e.g. D10, D11) with new values.
 /// Only the first worksheet file (sheet1.xml) is patched; styles.xml and all other parts are untouched.
-fn patch_worksheet_cell_values(xml: &[u8], cell_values: &HashMap<String, String>) -> Result<Vec<u8>, String> {
+fn patch_worksheet_cell_values(xml: &[u8], cell_values: &HashMap<String, String>) -> Result<Vec<u8>, AppError> {
     let mut reader = XmlReader::from_reader(xml);
     reader.config_mut().trim_text(false);
     let mut writer = Writer::new(Cursor::new(Vec::new()));
@@ -680,9 +1976,9 @@ fn patch_worksheet_cell_values(xml: &[u8], cell_values: &HashMap<String, String>
                 let mut cell_ref: Option<String> = None;
                 if is_cell {
                     for attr in e.attributes() {
-                        let attr = attr.map_err(|e| e.to_string())?;
+                        let attr = attr.map_err(AppError::excel)?;
                         if attr.key.as_ref() == b"r" {
-                            cell_ref = Some(std::str::from_utf8(&attr.value).map_err(|e| e.to_string())?.to_string());
+                            cell_ref = Some(std::str::from_utf8(&attr.value).map_err(AppError::excel)?.to_string());
                             break;
                         }
                     }
@@ -694,13 +1990,13 @@ fn patch_worksheet_cell_values(xml: &[u8], cell_values: &HashMap<String, String>
                     let mut c_start = BytesStart::new("c");
                     c_start.push_attribute(("r", r.as_str()));
                     c_start.push_attribute(("t", "inlineStr"));
-                    writer.write_event(Event::Start(c_start)).map_err(|e| e.to_string())?;
-                    writer.write_event(Event::Start(BytesStart::new("is"))).map_err(|e| e.to_string())?;
-                    writer.write_event(Event::Start(BytesStart::new("t"))).map_err(|e| e.to_string())?;
-                    writer.write_event(Event::Text(BytesText::from_escaped(escaped.as_str()))).map_err(|e| e.to_string())?;
-                    writer.write_event(Event::End(BytesEnd::new("t"))).map_err(|e| e.to_string())?;
-                    writer.write_event(Event::End(BytesEnd::new("is"))).map_err(|e| e.to_string())?;
-                    writer.write_event(Event::End(BytesEnd::new("c"))).map_err(|e| e.to_string())?;
+                    writer.write_event(Event::Start(c_start)).map_err(AppError::excel)?;
+                    writer.write_event(Event::Start(BytesStart::new("is"))).map_err(AppError::excel)?;
+                    writer.write_event(Event::Start(BytesStart::new("t"))).map_err(AppError::excel)?;
+                    writer.write_event(Event::Text(BytesText::from_escaped(escaped.as_str()))).map_err(AppError::excel)?;
+                    writer.write_event(Event::End(BytesEnd::new("t"))).map_err(AppError::excel)?;
+                    writer.write_event(Event::End(BytesEnd::new("is"))).map_err(AppError::excel)?;
+                    writer.write_event(Event::End(BytesEnd::new("c"))).map_err(AppError::excel)?;
                     buf.clear();
                     loop {
                         match reader.read_event_into(&mut buf) {
@@ -710,33 +2006,33 @@ fn patch_worksheet_cell_values(xml: &[u8], cell_values: &HashMap<String, String>
                             }
                             Ok(Event::Eof) => break,
                             Ok(_) => { buf.clear(); }
-                                    Err(er) => return Err(er.to_string()),
+                                    Err(er) => return Err(AppError::excel(er)),
                                 }
                             }
                 } else {
-                    writer.write_event(Event::Start(e)).map_err(|e| e.to_string())?;
+                    writer.write_event(Event::Start(e)).map_err(AppError::excel)?;
                     if is_cell {
                         loop {
                             match reader.read_event_into(&mut buf) {
                                 Ok(Event::End(ee)) if ee.name().as_ref() == b"c" => {
-                                    writer.write_event(Event::End(ee)).map_err(|e| e.to_string())?;
+                                    writer.write_event(Event::End(ee)).map_err(AppError::excel)?;
                                     buf.clear();
                                     break;
                                 }
                                 Ok(Event::Eof) => break,
                                 Ok(ev) => {
-                                    writer.write_event(ev).map_err(|e| e.to_string())?;
+                                    writer.write_event(ev).map_err(AppError::excel)?;
                                     buf.clear();
                                 }
-                                Err(er) => return Err(er.to_string()),
+                                Err(er) => return Err(AppError::excel(er)),
                             }
                         }
                     }
                 }
             }
             Ok(Event::Eof) => break,
-            Ok(ev) => writer.write_event(ev).map_err(|e| e.to_string())?,
-            Err(er) => return Err(er.to_string()),
+            Ok(ev) => writer.write_event(ev).map_err(AppError::excel)?,
+            Err(er) => return Err(AppError::excel(er)),
         }
         buf.clear();
     }
@@ -772,7 +2068,7 @@ fn strip_defined_names_from_workbook(xml: &[u8]) -> Vec<u8> {
 pub fn fill_tax_balance_cells_via_zip(
     path: &Path,
     updates: &[(u32, &str, &str)],
-) -> Result<(), String> {
+) -> Result<(), AppError> {
     use std::fs::File;
 
     let cell_values: HashMap<String, String> = updates
@@ -783,37 +2079,37 @@ pub fn fill_tax_balance_cells_via_zip(
         return Ok(());
     }
 
-    let file = File::open(path).map_err(|e| format!("Open: {}", e))?;
-    let mut archive = ZipArchive::new(file).map_err(|e| format!("Invalid zip: {}", e))?;
+    let file = File::open(path).map_err(|e| AppError::Excel(format!("Open: {}", e)))?;
+    let mut archive = ZipArchive::new(file).map_err(|e| AppError::Excel(format!("Invalid zip: {}", e)))?;
 
     let temp_path = path.with_extension("tmp.xlsx");
-    let out_file = File::create(&temp_path).map_err(|e| format!("Create temp: {}", e))?;
+    let out_file = File::create(&temp_path).map_err(|e| AppError::Excel(format!("Create temp: {}", e)))?;
     let mut zip_writer = ZipWriter::new(out_file);
     let opts = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
 
     let worksheet_name = "xl/worksheets/sheet1.xml";
     for i in 0..archive.len() {
-        let mut entry = archive.by_index(i).map_err(|e| format!("Entry {}: {}", i, e))?;
+        let mut entry = archive.by_index(i).map_err(|e| AppError::Excel(format!("Entry {}: {}", i, e)))?;
         let name = entry.name().replace('\\', "/");
         let mut data = Vec::new();
-        entry.read_to_end(&mut data).map_err(|e| format!("Read {}: {}", name, e))?;
+        entry.read_to_end(&mut data).map_err(|e| AppError::Excel(format!("Read {}: {}", name, e)))?;
 
         if name == worksheet_name {
             let patched = patch_worksheet_cell_values(&data, &cell_values)?;
-            zip_writer.start_file(&name, opts).map_err(|e| e.to_string())?;
-            zip_writer.write_all(&patched).map_err(|e| e.to_string())?;
+            zip_writer.start_file(&name, opts).map_err(AppError::excel)?;
+            zip_writer.write_all(&patched).map_err(AppError::excel)?;
         } else if name == "xl/workbook.xml" {
             let patched = strip_defined_names_from_workbook(&data);
-            zip_writer.start_file(&name, opts).map_err(|e| e.to_string())?;
-            zip_writer.write_all(&patched).map_err(|e| e.to_string())?;
+            zip_writer.start_file(&name, opts).map_err(AppError::excel)?;
+            zip_writer.write_all(&patched).map_err(AppError::excel)?;
         } else {
-            zip_writer.start_file(&name, opts).map_err(|e| e.to_string())?;
-            zip_writer.write_all(&data).map_err(|e| e.to_string())?;
+            zip_writer.start_file(&name, opts).map_err(AppError::excel)?;
+            zip_writer.write_all(&data).map_err(AppError::excel)?;
         }
     }
-    zip_writer.finish().map_err(|e| e.to_string())?;
+    zip_writer.finish().map_err(AppError::excel)?;
     drop(archive);
-    std::fs::rename(&temp_path, path).map_err(|e| format!("Replace: {}", e))?;
+    std::fs::rename(&temp_path, path).map_err(|e| AppError::Excel(format!("Replace: {}", e)))?;
     Ok(())
 }
 
@@ -824,29 +2120,42 @@ pub fn write_excel_cells(
     path: &str,
     sheet_name: &str,
     updates: &[(u32, &str, &str)],
-) -> Result<(), String> {
+) -> Result<(), AppError> {
     let path = Path::new(path);
     if !path.exists() {
-        return Err("File not found.".to_string());
+        return Err(AppError::Excel("File not found.".to_string()));
     }
-    let mut workbook = edit_xlsx::Workbook::from_path(path).map_err(|e| e.to_string())?;
+    let mut workbook = edit_xlsx::Workbook::from_path(path).map_err(AppError::excel)?;
+    let sheet_name = resolve_workbook_sheet_name(&workbook, sheet_name)?;
     let worksheet = workbook
-        .get_worksheet_mut_by_name(sheet_name)
-        .map_err(|e| format!("Sheet not found: {}", e))?;
+        .get_worksheet_mut_by_name(&sheet_name)
+        .map_err(|e| AppError::Excel(format!("Sheet not found: {}", e)))?;
     for (row_1based, col_letter, value) in updates {
         let cell_ref = format!("{}{}", col_letter.to_uppercase(), row_1based);
         let safe_value = sanitize_cell(value);
         worksheet
             .write_string(&cell_ref, safe_value)
-            .map_err(|e| e.to_string())?;
+            .map_err(AppError::excel)?;
     }
-    workbook.save_as(path).map_err(|e| e.to_string())?;
-    strip_drawings_from_xlsx(path).map_err(|e| format!("Could not strip drawings: {}", e))?;
-    Ok(())
+    save_workbook_atomically(&workbook, path, &sheet_name, None, &[])
+}
+
+/// Add a worksheet named `sheet_name` to the workbook at `path` with `headers` (column letter, text) in row 1.
+pub fn add_sheet_with_headers(path: &Path, sheet_name: &str, headers: &[(String, String)]) -> Result<(), AppError> {
+    let mut workbook = open_edit_workbook(path).map_err(AppError::Excel)?;
+    let worksheet = workbook
+        .add_worksheet_by_name(sheet_name)
+        .map_err(|e| AppError::Excel(format!("Could not add sheet '{}': {}", sheet_name, e)))?;
+    for (column_letter, text) in headers {
+        worksheet
+            .write_string(&format!("{}1", column_letter.to_uppercase()), sanitize_cell(text))
+            .map_err(AppError::excel)?;
+    }
+    save_workbook_atomically(&workbook, path, sheet_name, None, &[])
 }
 
 /// Column keys for batch export (order matches header row). First column = document type (Тип на документ).
-const EXPORT_FIELDS: &[&str] = &[
+pub(crate) const EXPORT_FIELDS: &[&str] = &[
     "document_type",
     "invoice_number",
     "date",
@@ -860,7 +2169,7 @@ const EXPORT_FIELDS: &[&str] = &[
 
 /// Remove or replace characters that can corrupt Excel's sheet XML and cause "unreadable content".
 /// Drops control chars (except tab, newline, CR). Replaces & < > so raw XML is never broken.
-fn sanitize_cell(s: &str) -> String {
+pub(crate) fn sanitize_cell(s: &str) -> String {
     let mut out = String::with_capacity(s.len());
     for c in s.chars() {
         let u = c as u32;
@@ -892,10 +2201,29 @@ fn write_text_cell_safe(
     worksheet.write_string_with_format(row, col, &cleaned, format).map(|_| ())
 }
 
+/// Text of an export field; date fields become date cells in the locale's date format when it writes them.
+fn write_field_text_cell(
+    worksheet: &mut Worksheet,
+    row: u32,
+    col: u16,
+    field_key: &str,
+    text: &str,
+    locale: &ExportLocale,
+    format: &Format,
+) -> Result<(), XlsxError> {
+    match is_date_field(field_key).then(|| locale.date_serial(text)).flatten() {
+        Some(serial) => {
+            let date_format = format.clone().set_num_format(locale.date_num_format()).set_align(FormatAlign::Left);
+            worksheet.write_number_with_format(row, col, serial, &date_format).map(|_| ())
+        }
+        None => write_text_cell_safe(worksheet, row, col, text, format),
+    }
+}
+
 /// Write number cell: parse as f64 and write number, or write sanitized text on parse failure.
 /// Normalize amount string to parseable form: dot (.) as decimal, no thousands separators.
 /// Handles European "27.826,17" (dot thousands, comma decimal) and US "27,826.17" (comma thousands, dot decimal).
-fn normalize_amount_string(value: &str) -> String {
+pub(crate) fn normalize_amount_string(value: &str) -> String {
     let s = value.trim().replace(' ', "");
     if s.is_empty() {
         return s;
@@ -933,32 +2261,6 @@ fn write_number_cell_safe(
     }
 }
 
-/// Format amount with thousands separator and two decimals (e.g. 27826.17 -> "27,826.17").
-fn format_amount(n: f64) -> String {
-    let s = format!("{:.2}", n);
-    let (int_part, dec_part) = if let Some(dot) = s.find('.') {
-        (&s[..dot], &s[dot..])
-    } else {
-        (s.as_str(), "")
-    };
-    let (sign, digits) = if int_part.starts_with('-') {
-        ("-", &int_part[1..])
-    } else {
-        ("", int_part)
-    };
-    let mut out = String::from(sign);
-    let chars: Vec<char> = digits.chars().collect();
-    let len = chars.len();
-    for (i, c) in chars.into_iter().enumerate() {
-        if i > 0 && (len - i) % 3 == 0 {
-            out.push(',');
-        }
-        out.push(c);
-    }
-    out.push_str(dec_part);
-    out
-}
-
 /// Estimate column width from text length (char count × 1.2, clamped 10–50).
 fn estimate_text_width(text: &str) -> f64 {
     let w = text.chars().count() as f64 * 1.2;
@@ -966,7 +2268,7 @@ fn estimate_text_width(text: &str) -> f64 {
 }
 
 /// Compute per-column widths for export: max of header width and cell widths; amount columns fixed at 14.
-fn calculate_export_column_widths(invoices: &[InvoiceData]) -> Vec<f64> {
+pub(crate) fn calculate_export_column_widths(invoices: &[InvoiceData]) -> Vec<f64> {
     const AMOUNT_WIDTH: f64 = 14.0;
     let mut max_widths: Vec<f64> = EXPORT_HEADERS
         .iter()
@@ -998,7 +2300,7 @@ fn calculate_export_column_widths(invoices: &[InvoiceData]) -> Vec<f64> {
 }
 
 /// Headers for batch export Excel (Macedonian). First column = type of document.
-const EXPORT_HEADERS: &[&str] = &[
+pub(crate) const EXPORT_HEADERS: &[&str] = &[
     "Тип на документ",
     "Број на документ",
     "Дата на документ",
@@ -1012,40 +2314,44 @@ const EXPORT_HEADERS: &[&str] = &[
 
 /// Append invoice rows to an existing Excel file. Uses calamine to find last data row, then edit_xlsx to write.
 /// Creates headers if sheet is empty or only has header row.
+/// Returns the (first, last) row numbers written and every cell written.
 pub fn append_invoices_to_existing_excel(
     path: &str,
     worksheet_name: &str,
     header_row: u32,
     invoices: &[InvoiceData],
-) -> Result<(), String> {
+    locale: &ExportLocale,
+) -> Result<(u32, u32, Vec<CellWrite>), AppError> {
+    if invoices.is_empty() {
+        return Err(AppError::Validation("No invoices to append.".to_string()));
+    }
+    let _timer = perf_metrics::timer(perf_metrics::Metric::Append);
     let path = Path::new(path);
-    let last_row = find_last_data_row(path, worksheet_name, header_row)?;
-    let mut next_row = last_row + 1;
+    let mut workbook = open_edit_workbook(path)?;
+    let worksheet_name = resolve_workbook_sheet_name(&workbook, worksheet_name)?;
 
-    let mut workbook = edit_xlsx::Workbook::from_path(path).map_err(|e| {
-        let msg = e.to_string();
-        if msg.contains("Could not open") || msg.contains("permission") || msg.contains("Permission") {
-            "Please close the file in Excel first.".to_string()
-        } else {
-            format!("Could not open Excel file: {}", msg)
-        }
-    })?;
+    let last_row = find_last_data_row(path, &worksheet_name, header_row)?.last_row;
+    let mut next_row = last_row + 1;
 
     let worksheet = workbook
-        .get_worksheet_mut_by_name(worksheet_name)
-        .map_err(|_| format!("Sheet '{}' not found.", worksheet_name))?;
+        .get_worksheet_mut_by_name(&worksheet_name)
+        .map_err(|_| AppError::Excel(format!("Sheet '{}' not found.", worksheet_name)))?;
 
+    let mut writes = Vec::new();
     // If sheet has no data rows (only header or empty), write headers at header_row and data from header_row+1
     if next_row <= header_row {
         for (col_idx, header) in EXPORT_HEADERS.iter().enumerate() {
             let cell_ref = format!("{}{}", col_index_to_letter(col_idx as u32), header_row);
+            writes.push(CellWrite::new(&worksheet_name, &cell_ref, header_row, None, &sanitize_cell(header)));
             worksheet
                 .write_string(&cell_ref, sanitize_cell(header))
-                .map_err(|e| e.to_string())?;
+                .map_err(AppError::excel)?;
         }
         next_row = header_row + 1;
     }
 
+    let first_row = next_row;
+    let mut date_cells = Vec::new();
     for inv in invoices {
         for (col_idx, &field_key) in EXPORT_FIELDS.iter().enumerate() {
             let value = inv
@@ -1054,90 +2360,61 @@ pub fn append_invoices_to_existing_excel(
                 .map(|f| f.value.as_str())
                 .unwrap_or("");
             let cell_value = if field_key == "net_amount" || field_key == "tax_amount" || field_key == "total_amount" {
-                let num: f64 = value.replace(',', ".").trim().parse().unwrap_or(0.0);
-                format_amount(num)
+                let num: f64 = normalize_amount_string(value).parse().unwrap_or(0.0);
+                locale.amount(num)
+            } else if is_date_field(field_key) {
+                sanitize_cell(&locale.date(value))
             } else {
                 sanitize_cell(value)
             };
             let cell_ref = format!("{}{}", col_index_to_letter(col_idx as u32), next_row);
-            worksheet.write_string(&cell_ref, cell_value).map_err(|e| e.to_string())?;
+            writes.push(CellWrite::new(&worksheet_name, &cell_ref, next_row, None, &cell_value));
+            match is_date_field(field_key).then(|| locale.date_serial(value)).flatten() {
+                Some(serial) => {
+                    date_cells.push(cell_ref.clone());
+                    worksheet.write_double(&cell_ref, serial)
+                }
+                None => worksheet.write_string(&cell_ref, cell_value),
+            }
+            .map_err(AppError::excel)?;
         }
         next_row += 1;
     }
 
-    workbook.save_as(path).map_err(|e| {
-        let msg = e.to_string();
-        if msg.contains("Permission denied") || msg.contains("being used") {
-            "Please close the file in Excel first.".to_string()
-        } else {
-            format!("Cannot write to file: {}", msg)
-        }
-    })?;
+    save_workbook_atomically(&workbook, path, &worksheet_name, Some((&date_cells, &locale.date_num_format())), &[])?;
 
-    Ok(())
+    Ok((first_row, next_row.saturating_sub(1).max(first_row), writes))
 }
 
 /// Append to sheet "Invoices" at default header row 1 (used by legacy export_invoices_to_excel when user picks existing file).
-fn append_invoices_to_existing(path: &Path, invoices: &[InvoiceData]) -> Result<(), String> {
+fn append_invoices_to_existing(path: &Path, invoices: &[InvoiceData], locale: &ExportLocale) -> Result<(), AppError> {
     append_invoices_to_existing_excel(
-        path.to_str().ok_or("Invalid path.")?,
+        path.to_str().ok_or(AppError::Excel("Invalid path.".to_string()))?,
         "Invoices",
         1,
         invoices,
+        locale,
     )
+    .map(|_| ())
 }
 
 /// Create a new Excel workbook with invoice data and save to the given path, or to Downloads if path is None. Returns the file path.
 /// When path_override points to an existing file with sheet "Invoices", appends rows instead of overwriting.
-pub fn export_invoices_to_excel(invoices: &[InvoiceData], path_override: Option<&str>) -> Result<String, String> {
-    let path = if let Some(p) = path_override {
-        let p = p.trim();
-        if p.is_empty() {
-            None
-        } else {
-            let mut pb = std::path::PathBuf::from(p);
-            if pb.extension().map(|e| e.to_str()) != Some(Some("xlsx")) {
-                pb.set_extension("xlsx");
-            }
-            Some(pb)
-        }
-    } else {
-        None
-    };
-
-    let path = match path {
-        Some(p) => p,
-        None => {
-            let dir = dirs::download_dir()
-                .or_else(dirs::desktop_dir)
-                .ok_or("Could not find Downloads or Desktop folder.")?;
-            let now = chrono::Local::now();
-            let base_name = format!(
-                "Invoices_{}.xlsx",
-                now.format("%Y%m%d_%H%M%S")
-            );
-            let mut p = dir.join(&base_name);
-            let mut counter = 2u32;
-            while p.exists() {
-                p = dir.join(format!(
-                    "Invoices_{}_{}.xlsx",
-                    now.format("%Y%m%d_%H%M%S"),
-                    counter
-                ));
-                counter += 1;
-            }
-            p
-        }
-    };
+pub fn export_invoices_to_excel(
+    invoices: &[InvoiceData],
+    path_override: Option<&str>,
+    locale: &ExportLocale,
+) -> Result<String, AppError> {
+    let path = new_export_path(path_override, "xlsx")?;
 
     let path_str = path
         .to_str()
-        .ok_or("Invalid path characters.")?
+        .ok_or(AppError::Excel("Invalid path characters.".to_string()))?
         .to_string();
 
     // If user chose an existing file, append to it instead of overwriting
     if path.exists() && path_override.is_some() {
-        append_invoices_to_existing(&path, invoices)?;
+        append_invoices_to_existing(&path, invoices, locale)?;
         return Ok(path_str);
     }
 
@@ -1185,7 +2462,7 @@ pub fn export_invoices_to_excel(invoices: &[InvoiceData], path_override: Option<
             let cell_format = &text_format_wrap;
             if is_amount {
                 let amount_format_wrap = Format::new()
-                    .set_num_format("#,##0.00")
+                    .set_num_format(locale.amount_num_format())
                     .set_align(FormatAlign::Right)
                     .set_text_wrap();
                 write_number_cell_safe(
@@ -1198,10 +2475,11 @@ pub fn export_invoices_to_excel(invoices: &[InvoiceData], path_override: Option<
                 )
                 .map_err(|e: XlsxError| e.to_string())?;
             } else {
+                let value = if is_date_field(field_key) { locale.date(value) } else { value.to_string() };
                 if value.chars().count() > max_text_len {
                     max_text_len = value.chars().count();
                 }
-                write_text_cell_safe(worksheet, row, col_idx as u16, value, cell_format)
+                write_field_text_cell(worksheet, row, col_idx as u16, field_key, &value, locale, cell_format)
                     .map_err(|e: XlsxError| e.to_string())?;
             }
         }
@@ -1221,45 +2499,70 @@ pub fn export_invoices_to_excel(invoices: &[InvoiceData], path_override: Option<
     Ok(path_str)
 }
 
+/// Target path for a new export: `path_override` with the extension forced to `extension`, or a
+/// fresh Invoices_<timestamp>.<extension> in the export folder.
+pub(crate) fn new_export_path(path_override: Option<&str>, extension: &str) -> Result<std::path::PathBuf, AppError> {
+    if let Some(p) = path_override.filter(|s| !s.trim().is_empty()) {
+        let mut pb = std::path::PathBuf::from(p.trim());
+        if pb.extension().map(|e| e.to_str()) != Some(Some(extension)) {
+            pb.set_extension(extension);
+        }
+        return Ok(pb);
+    }
+    let stem = format!("Invoices_{}", chrono::Local::now().format("%Y%m%d_%H%M%S"));
+    unique_export_path(&stem, extension)
+}
+
+/// Folder for new exports: the document type's default folder, else the default export folder
+/// (Downloads, Desktop as fallback).
+pub(crate) fn export_dir(document_type: Option<&str>) -> Result<std::path::PathBuf, AppError> {
+    document_type
+        .and_then(crate::services::document_types::default_folder)
+        .or_else(crate::services::settings::default_export_folder)
+        .or_else(dirs::download_dir)
+        .or_else(dirs::desktop_dir)
+        .ok_or(AppError::Excel("Could not find Downloads or Desktop folder.".to_string()))
+}
+
+/// `<stem>.<extension>` in the default export folder (Downloads, Desktop as fallback), with `_2`, `_3`, ...
+/// appended while the name is taken.
+pub(crate) fn unique_export_path(stem: &str, extension: &str) -> Result<std::path::PathBuf, AppError> {
+    Ok(unique_export_path_in(&export_dir(None)?, stem, extension))
+}
+
+/// `<stem>.<extension>` in `dir`, with `_2`, `_3`, ... appended while the name is taken.
+pub(crate) fn unique_export_path_in(dir: &std::path::Path, stem: &str, extension: &str) -> std::path::PathBuf {
+    let mut p = dir.join(format!("{}.{}", stem, extension));
+    let mut counter = 2u32;
+    while p.exists() {
+        p = dir.join(format!("{}_{}.{}", stem, counter, extension));
+        counter += 1;
+    }
+    p
+}
+
+/// Worksheet name for new exports ("Invoices" when not given).
+pub(crate) fn export_sheet_name(worksheet_name: Option<&str>) -> &str {
+    let sheet_name = worksheet_name.unwrap_or("Invoices").trim();
+    if sheet_name.is_empty() { "Invoices" } else { sheet_name }
+}
+
 /// Create a new Excel file with the given (or default) path and worksheet name. Never appends.
 /// Returns the saved file path.
 pub fn export_invoices_to_new_excel(
     invoices: &[InvoiceData],
     path_override: Option<&str>,
     worksheet_name: Option<&str>,
-) -> Result<String, String> {
-    let path = if let Some(p) = path_override.filter(|s| !s.trim().is_empty()) {
-        let mut pb = std::path::PathBuf::from(p.trim());
-        if pb.extension().map(|e| e.to_str()) != Some(Some("xlsx")) {
-            pb.set_extension("xlsx");
-        }
-        pb
-    } else {
-        let dir = dirs::download_dir()
-            .or_else(dirs::desktop_dir)
-            .ok_or("Could not find Downloads or Desktop folder.")?;
-        let now = chrono::Local::now();
-        let base_name = format!("Invoices_{}.xlsx", now.format("%Y%m%d_%H%M%S"));
-        let mut p = dir.join(&base_name);
-        let mut counter = 2u32;
-        while p.exists() {
-            p = dir.join(format!(
-                "Invoices_{}_{}.xlsx",
-                now.format("%Y%m%d_%H%M%S"),
-                counter
-            ));
-            counter += 1;
-        }
-        p
-    };
+    locale: &ExportLocale,
+) -> Result<String, AppError> {
+    let path = new_export_path(path_override, "xlsx")?;
 
     let path_str = path
         .to_str()
-        .ok_or("Invalid path characters.")?
+        .ok_or(AppError::Excel("Invalid path characters.".to_string()))?
         .to_string();
 
-    let sheet_name = worksheet_name.unwrap_or("Invoices").trim();
-    let sheet_name = if sheet_name.is_empty() { "Invoices" } else { sheet_name };
+    let sheet_name = export_sheet_name(worksheet_name);
 
     let mut workbook = Workbook::new();
     let worksheet = workbook.add_worksheet();
@@ -1304,7 +2607,7 @@ pub fn export_invoices_to_new_excel(
             let cell_format = &text_format_wrap;
             if is_amount {
                 let amount_format_wrap = Format::new()
-                    .set_num_format("#,##0.00")
+                    .set_num_format(locale.amount_num_format())
                     .set_align(FormatAlign::Right)
                     .set_text_wrap();
                 write_number_cell_safe(
@@ -1317,10 +2620,11 @@ pub fn export_invoices_to_new_excel(
                 )
                 .map_err(|e: XlsxError| e.to_string())?;
             } else {
+                let value = if is_date_field(field_key) { locale.date(value) } else { value.to_string() };
                 if value.chars().count() > max_text_len {
                     max_text_len = value.chars().count();
                 }
-                write_text_cell_safe(worksheet, row, col_idx as u16, value, cell_format)
+                write_field_text_cell(worksheet, row, col_idx as u16, field_key, &value, locale, cell_format)
                     .map_err(|e: XlsxError| e.to_string())?;
             }
         }
@@ -1340,8 +2644,290 @@ pub fn export_invoices_to_new_excel(
     Ok(path_str)
 }
 
+/// Write a spending-by-category report: one row per category plus a trend sheet of monthly totals.
+pub fn export_spending_report(report: &SpendingReport, path: &str) -> Result<String, AppError> {
+    let mut path = std::path::PathBuf::from(path.trim());
+    if path.extension().map(|e| e.to_str()) != Some(Some("xlsx")) {
+        path.set_extension("xlsx");
+    }
+    let path_str = path.to_str().ok_or(AppError::Excel("Invalid path characters.".to_string()))?.to_string();
+
+    let header_format = Format::new()
+        .set_bold()
+        .set_background_color(rust_xlsxwriter::Color::RGB(0x2563EB))
+        .set_font_color(rust_xlsxwriter::Color::RGB(0xFFFFFF));
+    let amount_format = Format::new().set_num_format("#,##0.00").set_align(FormatAlign::Right);
+    let percent_format = Format::new().set_num_format("0.00\"%\"").set_align(FormatAlign::Right);
+    let total_format = Format::new().set_bold().set_num_format("#,##0.00").set_align(FormatAlign::Right);
+    let err = |e: XlsxError| e.to_string();
+
+    let mut workbook = Workbook::new();
+    let summary = workbook.add_worksheet();
+    summary.set_name("Spending").map_err(err)?;
+    let headers = [
+        "Category".to_string(),
+        format!("Total {}", report.period),
+        format!("Total {}", report.previous_period),
+        "Change".to_string(),
+        "Change %".to_string(),
+        "Invoices".to_string(),
+        "Monthly budget".to_string(),
+    ];
+    for (col, header) in headers.iter().enumerate() {
+        summary.write_string_with_format(0, col as u16, header, &header_format).map_err(err)?;
+        summary.set_column_width(col as u16, if col == 0 { 30.0 } else { 16.0 }).map_err(err)?;
+    }
+    for (i, c) in report.categories.iter().enumerate() {
+        let row = (i + 1) as u32;
+        summary.write_string(row, 0, sanitize_cell(&c.category_name)).map_err(err)?;
+        summary.write_number_with_format(row, 1, c.total, &amount_format).map_err(err)?;
+        summary.write_number_with_format(row, 2, c.previous_total, &amount_format).map_err(err)?;
+        summary.write_number_with_format(row, 3, c.delta, &amount_format).map_err(err)?;
+        if let Some(pct) = c.delta_percent {
+            summary.write_number_with_format(row, 4, pct, &percent_format).map_err(err)?;
+        }
+        summary.write_number(row, 5, c.invoice_count).map_err(err)?;
+        if let Some(budget) = c.monthly_budget {
+            summary.write_number_with_format(row, 6, budget, &amount_format).map_err(err)?;
+        }
+    }
+    let total_row = (report.categories.len() + 1) as u32;
+    summary.write_string_with_format(total_row, 0, "Total", &Format::new().set_bold()).map_err(err)?;
+    summary.write_number_with_format(total_row, 1, report.total, &total_format).map_err(err)?;
+    summary.write_number_with_format(total_row, 2, report.previous_total, &total_format).map_err(err)?;
+    summary
+        .write_number_with_format(total_row, 3, report.total - report.previous_total, &total_format)
+        .map_err(err)?;
+    let _ = summary.set_freeze_panes(1, 0);
+
+    let trend = workbook.add_worksheet();
+    trend.set_name("Trend").map_err(err)?;
+    trend.write_string_with_format(0, 0, "Category", &header_format).map_err(err)?;
+    trend.set_column_width(0, 30.0).map_err(err)?;
+    if let Some(first) = report.categories.first() {
+        for (col, m) in first.monthly_totals.iter().enumerate() {
+            trend.write_string_with_format(0, (col + 1) as u16, &m.month, &header_format).map_err(err)?;
+            trend.set_column_width((col + 1) as u16, 14.0).map_err(err)?;
+        }
+    }
+    for (i, c) in report.categories.iter().enumerate() {
+        let row = (i + 1) as u32;
+        trend.write_string(row, 0, sanitize_cell(&c.category_name)).map_err(err)?;
+        for (col, m) in c.monthly_totals.iter().enumerate() {
+            trend.write_number_with_format(row, (col + 1) as u16, m.total, &amount_format).map_err(err)?;
+        }
+    }
+    let _ = trend.set_freeze_panes(1, 1);
+
+    workbook.save(&path).map_err(err)?;
+    Ok(path_str)
+}
+
+/// Write the history report to .xlsx: the documents, totals per seller and totals per month. Returns the saved path.
+pub fn export_history_report(report: &HistoryReport, path: &str) -> Result<String, AppError> {
+    let mut path = std::path::PathBuf::from(path.trim());
+    if path.extension().map(|e| e.to_str()) != Some(Some("xlsx")) {
+        path.set_extension("xlsx");
+    }
+    let path_str = path.to_str().ok_or(AppError::Excel("Invalid path characters.".to_string()))?.to_string();
+
+    let header_format = Format::new()
+        .set_bold()
+        .set_background_color(rust_xlsxwriter::Color::RGB(0x2563EB))
+        .set_font_color(rust_xlsxwriter::Color::RGB(0xFFFFFF));
+    let amount_format = Format::new().set_num_format("#,##0.00").set_align(FormatAlign::Right);
+    let total_format = Format::new().set_bold().set_num_format("#,##0.00").set_align(FormatAlign::Right);
+    let bold = Format::new().set_bold();
+    let err = |e: XlsxError| AppError::excel(e);
+    let write_amount = |sheet: &mut Worksheet, row: u32, col: u16, value: Option<f64>| -> Result<(), AppError> {
+        if let Some(n) = value {
+            sheet.write_number_with_format(row, col, n, &amount_format).map_err(err)?;
+        }
+        Ok(())
+    };
+
+    let mut workbook = Workbook::new();
+    let documents = workbook.add_worksheet();
+    documents.set_name("Documents").map_err(err)?;
+    let headers = ["Date", "Type", "File", "Seller", "Invoice number", "Net", "VAT", "Total", "Status"];
+    let widths = [12.0, 14.0, 30.0, 30.0, 18.0, 14.0, 14.0, 14.0, 16.0];
+    for (col, (header, width)) in headers.iter().zip(widths).enumerate() {
+        documents.write_string_with_format(0, col as u16, *header, &header_format).map_err(err)?;
+        documents.set_column_width(col as u16, width).map_err(err)?;
+    }
+    for (i, e) in report.entries.iter().enumerate() {
+        let row = (i + 1) as u32;
+        documents.write_string(row, 0, &e.date).map_err(err)?;
+        documents.write_string(row, 1, sanitize_cell(&e.document_type)).map_err(err)?;
+        documents.write_string(row, 2, sanitize_cell(&e.file_name)).map_err(err)?;
+        documents.write_string(row, 3, sanitize_cell(&e.seller)).map_err(err)?;
+        documents.write_string(row, 4, sanitize_cell(&e.invoice_number)).map_err(err)?;
+        write_amount(documents, row, 5, e.net_amount)?;
+        write_amount(documents, row, 6, e.tax_amount)?;
+        write_amount(documents, row, 7, e.total_amount)?;
+        documents.write_string(row, 8, sanitize_cell(&e.status)).map_err(err)?;
+    }
+    let total_row = (report.entries.len() + 1) as u32;
+    documents.write_string_with_format(total_row, 0, "Total", &bold).map_err(err)?;
+    documents.write_number_with_format(total_row, 7, report.total_amount, &total_format).map_err(err)?;
+    let _ = documents.set_freeze_panes(1, 0);
+
+    for (name, first_header, groups) in [
+        ("By seller", "Seller", &report.by_seller),
+        ("By month", "Month", &report.by_month),
+    ] {
+        let sheet = workbook.add_worksheet();
+        sheet.set_name(name).map_err(err)?;
+        for (col, header) in [first_header, "Documents", "Net", "VAT", "Total"].iter().enumerate() {
+            sheet.write_string_with_format(0, col as u16, *header, &header_format).map_err(err)?;
+            sheet.set_column_width(col as u16, if col == 0 { 30.0 } else { 14.0 }).map_err(err)?;
+        }
+        for (i, g) in groups.iter().enumerate() {
+            let row = (i + 1) as u32;
+            sheet.write_string(row, 0, sanitize_cell(&g.key)).map_err(err)?;
+            sheet.write_number(row, 1, g.document_count).map_err(err)?;
+            sheet.write_number_with_format(row, 2, g.net_amount, &amount_format).map_err(err)?;
+            sheet.write_number_with_format(row, 3, g.tax_amount, &amount_format).map_err(err)?;
+            sheet.write_number_with_format(row, 4, g.total_amount, &amount_format).map_err(err)?;
+        }
+        let total_row = (groups.len() + 1) as u32;
+        sheet.write_string_with_format(total_row, 0, "Total", &bold).map_err(err)?;
+        sheet
+            .write_number(total_row, 1, groups.iter().map(|g| g.document_count).sum::<u32>())
+            .map_err(err)?;
+        for (col, sum) in [
+            groups.iter().map(|g| g.net_amount).sum::<f64>(),
+            groups.iter().map(|g| g.tax_amount).sum::<f64>(),
+            groups.iter().map(|g| g.total_amount).sum::<f64>(),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            sheet
+                .write_number_with_format(total_row, (col + 2) as u16, (sum * 100.0).round() / 100.0, &total_format)
+                .map_err(err)?;
+        }
+        let _ = sheet.set_freeze_panes(1, 0);
+    }
+
+    workbook.save(&path).map_err(err)?;
+    Ok(path_str)
+}
+
+/// Write the VAT summary: a ДДВ-04 style sheet per rate, then per month and per vendor. Returns the saved path.
+pub fn export_vat_report(report: &VatReport, path: &std::path::Path) -> Result<String, AppError> {
+    let mut path = path.to_path_buf();
+    if path.extension().map(|e| e.to_str()) != Some(Some("xlsx")) {
+        path.set_extension("xlsx");
+    }
+    let path_str = path.to_str().ok_or(AppError::Excel("Invalid path characters.".to_string()))?.to_string();
+
+    let header_format = Format::new()
+        .set_bold()
+        .set_background_color(rust_xlsxwriter::Color::RGB(0x2563EB))
+        .set_font_color(rust_xlsxwriter::Color::RGB(0xFFFFFF));
+    let amount_format = Format::new().set_num_format("#,##0.00").set_align(FormatAlign::Right);
+    let total_format = Format::new().set_bold().set_num_format("#,##0.00").set_align(FormatAlign::Right);
+    let bold = Format::new().set_bold();
+    let err = |e: XlsxError| AppError::excel(e);
+    let rate_label = |rate: Option<u32>| match rate {
+        Some(0) => "Ослободено (0%)".to_string(),
+        Some(r) => format!("Стапка {}%", r),
+        None => "Друго / непрепознаена стапка".to_string(),
+    };
+    let write_totals = |sheet: &mut Worksheet, row: u32, col: u16, t: &VatTotals, fmt: &Format| -> Result<(), AppError> {
+        sheet.write_number_with_format(row, col, t.net_amount, fmt).map_err(err)?;
+        sheet.write_number_with_format(row, col + 1, t.tax_amount, fmt).map_err(err)?;
+        sheet.write_number_with_format(row, col + 2, t.total_amount, fmt).map_err(err)?;
+        sheet.write_number(row, col + 3, t.document_count).map_err(err)?;
+        Ok(())
+    };
+    let amount_headers = ["Основица", "ДДВ", "Вкупно", "Фактури"];
+
+    let mut workbook = Workbook::new();
+    let summary = workbook.add_worksheet();
+    summary.set_name("ДДВ-04").map_err(err)?;
+    summary
+        .write_string_with_format(0, 0, "Преглед на влезни испораки и претходен данок", &bold)
+        .map_err(err)?;
+    summary
+        .write_string(1, 0, format!("Период: {} – {}", report.period_start, report.period_end))
+        .map_err(err)?;
+    summary.write_string_with_format(3, 0, "Стапка", &header_format).map_err(err)?;
+    for (i, h) in amount_headers.iter().enumerate() {
+        summary.write_string_with_format(3, (i + 1) as u16, *h, &header_format).map_err(err)?;
+    }
+    summary.set_column_width(0, 32.0).map_err(err)?;
+    for col in 1..=4u16 {
+        summary.set_column_width(col, 16.0).map_err(err)?;
+    }
+    let mut row = 4u32;
+    for line in &report.rates {
+        summary.write_string(row, 0, rate_label(line.rate)).map_err(err)?;
+        write_totals(summary, row, 1, &line.totals, &amount_format)?;
+        row += 1;
+    }
+    summary.write_string_with_format(row, 0, "Вкупно претходен данок", &bold).map_err(err)?;
+    write_totals(summary, row, 1, &report.totals, &total_format)?;
+    if report.skipped_without_amounts > 0 {
+        summary
+            .write_string(
+                row + 2,
+                0,
+                format!("Документи без износи (не се вклучени): {}", report.skipped_without_amounts),
+            )
+            .map_err(err)?;
+    }
+
+    let months = workbook.add_worksheet();
+    months.set_name("По месеци").map_err(err)?;
+    for (i, h) in ["Месец", "Стапка"].iter().chain(amount_headers.iter()).enumerate() {
+        months.write_string_with_format(0, i as u16, *h, &header_format).map_err(err)?;
+        months.set_column_width(i as u16, if i == 1 { 30.0 } else { 14.0 }).map_err(err)?;
+    }
+    let mut row = 1u32;
+    for m in &report.months {
+        for line in &m.rates {
+            months.write_string(row, 0, &m.month).map_err(err)?;
+            months.write_string(row, 1, rate_label(line.rate)).map_err(err)?;
+            write_totals(months, row, 2, &line.totals, &amount_format)?;
+            row += 1;
+        }
+        months.write_string_with_format(row, 0, &m.month, &bold).map_err(err)?;
+        months.write_string_with_format(row, 1, "Вкупно за месецот", &bold).map_err(err)?;
+        write_totals(months, row, 2, &m.totals, &total_format)?;
+        row += 1;
+    }
+    let _ = months.set_freeze_panes(1, 0);
+
+    let vendors = workbook.add_worksheet();
+    vendors.set_name("По добавувачи").map_err(err)?;
+    for (i, h) in ["Добавувач", "ЕДБ"].iter().chain(amount_headers.iter()).enumerate() {
+        vendors.write_string_with_format(0, i as u16, *h, &header_format).map_err(err)?;
+        vendors.set_column_width(i as u16, match i { 0 => 36.0, 1 => 18.0, _ => 14.0 }).map_err(err)?;
+    }
+    for (i, v) in report.vendors.iter().enumerate() {
+        let row = (i + 1) as u32;
+        vendors.write_string(row, 0, sanitize_cell(&v.vendor)).map_err(err)?;
+        vendors.write_string(row, 1, sanitize_cell(&v.tax_id)).map_err(err)?;
+        write_totals(vendors, row, 2, &v.totals, &amount_format)?;
+    }
+    let total_row = (report.vendors.len() + 1) as u32;
+    vendors.write_string_with_format(total_row, 0, "Вкупно", &bold).map_err(err)?;
+    write_totals(vendors, total_row, 2, &report.totals, &total_format)?;
+    let _ = vendors.set_freeze_panes(1, 0);
+
+    workbook.save(&path).map_err(err)?;
+    Ok(path_str)
+}
+
+/// Field keys holding a date, written in the export locale's date order.
+pub(crate) fn is_date_field(key: &str) -> bool {
+    matches!(key, "date" | "due_date" | "valueDate" | "taxPeriodStart" | "taxPeriodEnd")
+}
+
 /// Field keys that should be written as numbers in Excel (invoice + analyzer amount fields).
-fn is_amount_field(key: &str) -> bool {
+pub(crate) fn is_amount_field(key: &str) -> bool {
     matches!(
         key,
         "net_amount"
@@ -1406,14 +2992,15 @@ pub fn export_to_new_excel_with_columns(
     headers: &[String],
     column_field_keys: &[String],
     invoices: &[InvoiceData],
-) -> Result<String, String> {
+    locale: &ExportLocale,
+) -> Result<String, AppError> {
     if headers.len() != column_field_keys.len() {
-        return Err("headers and column_field_keys must have the same length".to_string());
+        return Err(AppError::Excel("headers and column_field_keys must have the same length".to_string()));
     }
     let path_buf = std::path::PathBuf::from(path);
     let path_str = path_buf
         .to_str()
-        .ok_or("Invalid path")?
+        .ok_or(AppError::Excel("Invalid path".to_string()))?
         .to_string();
 
     let mut workbook = Workbook::new();
@@ -1428,7 +3015,7 @@ pub fn export_to_new_excel_with_columns(
         .set_font_color(rust_xlsxwriter::Color::RGB(0xFFFFFF));
     let text_format_wrap = Format::new().set_text_wrap();
     let amount_format_wrap = Format::new()
-        .set_num_format("#,##0.00")
+        .set_num_format(locale.amount_num_format())
         .set_align(FormatAlign::Right)
         .set_text_wrap();
 
@@ -1454,7 +3041,8 @@ pub fn export_to_new_excel_with_columns(
                     .get(field_key)
                     .map(|f| f.value.as_str())
                     .unwrap_or("");
-                (v.to_string(), is_amount_field(field_key))
+                let v = if is_date_field(field_key) { locale.date(v) } else { v.to_string() };
+                (v, is_amount_field(field_key))
             };
             // DDV export (screen export): write Macedonian month name in Период instead of full date/range.
             if !is_number && worksheet_name == "ДДВ" && field_key == "taxPeriod" {
@@ -1473,7 +3061,7 @@ pub fn export_to_new_excel_with_columns(
                 )
                 .map_err(|e: XlsxError| e.to_string())?;
             } else {
-                write_text_cell_safe(worksheet, row, col_idx as u16, &value, &text_format_wrap)
+                write_field_text_cell(worksheet, row, col_idx as u16, field_key, &value, locale, &text_format_wrap)
                     .map_err(|e: XlsxError| e.to_string())?;
             }
         }
@@ -1528,9 +3116,46 @@ const DDV_PERIOD_ROW_LABELS: [&str; 13] = [
     "Вкупно",
 ];
 
+/// Copy the cell values of every sheet of `source` (any format calamine reads, e.g. a legacy .xls) into a
+/// new .xlsx at `target`. Formulas keep their last calculated value; dates get the dd.mm.yyyy format.
+/// Formatting, merged cells and macros are not carried over.
+pub fn copy_values_to_xlsx(source: &Path, target: &Path) -> Result<(), AppError> {
+    let mut reader =
+        open_workbook_auto(source).map_err(|e| AppError::Excel(format!("Could not open Excel file: {}", e)))?;
+    let mut workbook = Workbook::new();
+    let date_format = Format::new().set_num_format("dd.mm.yyyy");
+    for sheet_name in reader.sheet_names() {
+        let range = reader
+            .worksheet_range(&sheet_name)
+            .map_err(|e| AppError::Excel(format!("Sheet '{}' could not be read: {}", sheet_name, e)))?;
+        let worksheet = workbook.add_worksheet();
+        worksheet.set_name(&sheet_name).map_err(AppError::excel)?;
+        let (row0, col0) = range.start().unwrap_or((0, 0));
+        for (r, row) in range.rows().enumerate() {
+            for (c, cell) in row.iter().enumerate() {
+                let (row, col) = (row0 + r as u32, (col0 as usize + c) as u16);
+                let written = match cell {
+                    calamine::Data::String(s) | calamine::Data::DateTimeIso(s) | calamine::Data::DurationIso(s) => {
+                        worksheet.write_string(row, col, s).map(|_| ())
+                    }
+                    calamine::Data::Float(f) => worksheet.write_number(row, col, *f).map(|_| ()),
+                    calamine::Data::Int(i) => worksheet.write_number(row, col, *i as f64).map(|_| ()),
+                    calamine::Data::Bool(b) => worksheet.write_boolean(row, col, *b).map(|_| ()),
+                    calamine::Data::DateTime(d) => {
+                        worksheet.write_number_with_format(row, col, d.as_f64(), &date_format).map(|_| ())
+                    }
+                    calamine::Data::Error(_) | calamine::Data::Empty => Ok(()),
+                };
+                written.map_err(AppError::excel)?;
+            }
+        }
+    }
+    workbook.save(target).map_err(AppError::excel)
+}
+
 /// Create a DDV (VAT return) Excel template matching РД-ДДВ-Example.xlsx: exact headers, period rows, Вкупно formula.
 /// Formula for column 20 (Вкупно): =(2+4+6+13+15+17+19) → sum of VAT columns B,D,F,M,O,Q,S.
-pub fn create_ddv_template_xlsx(path: &str) -> Result<(), String> {
+pub fn create_ddv_template_xlsx(path: &str) -> Result<(), AppError> {
     let mut workbook = Workbook::new();
     let worksheet = workbook.add_worksheet();
     worksheet
@@ -1579,6 +3204,29 @@ pub fn create_ddv_template_xlsx(path: &str) -> Result<(), String> {
     Ok(())
 }
 
+/// Sample invoice ledger for the first-run demo: sheet `sheet_name` with the batch export headers in row 1
+/// and `rows` (values in `EXPORT_FIELDS` order) below them.
+pub fn create_sample_ledger_xlsx(path: &Path, sheet_name: &str, rows: &[Vec<String>]) -> Result<(), AppError> {
+    let mut workbook = Workbook::new();
+    let worksheet = workbook.add_worksheet();
+    worksheet.set_name(sheet_name).map_err(|e: XlsxError| e.to_string())?;
+    let header_format = Format::new().set_bold().set_background_color(rust_xlsxwriter::Color::RGB(0xE0E0E0));
+    let text_format = Format::new();
+    for (col, header) in EXPORT_HEADERS.iter().enumerate() {
+        let _ = worksheet.set_column_width(col as u16, if col == 3 || col == 4 { 32.0 } else { 18.0 });
+        write_text_cell_safe(worksheet, 0, col as u16, header, &header_format).map_err(|e: XlsxError| e.to_string())?;
+    }
+    for (row_idx, values) in rows.iter().enumerate() {
+        for (col, value) in values.iter().enumerate() {
+            write_text_cell_safe(worksheet, row_idx as u32 + 1, col as u16, value, &text_format)
+                .map_err(|e: XlsxError| e.to_string())?;
+        }
+    }
+    let _ = worksheet.set_freeze_panes(1, 0);
+    workbook.save(path).map_err(|e: XlsxError| e.to_string())?;
+    Ok(())
+}
+
 // --- Plata (РД-Трошоци за вработени) templates ---
 
 /// Simple table layout for Plata export: one header row (A1–K1), one data row per document.
@@ -1599,7 +3247,7 @@ const PLATA_SIMPLE_HEADERS: [&str; 11] = [
 
 /// Create the simple Plata export template: sheet "Плати", row 1 = headers A1–K1 (dark blue bg, white bold centered).
 /// Data rows are appended on export (one row per document). Structure and formatting match user image.
-pub fn create_plata_simple_table_xlsx(path: &str) -> Result<(), String> {
+pub fn create_plata_simple_table_xlsx(path: &str) -> Result<(), AppError> {
     let mut workbook = Workbook::new();
     let worksheet = workbook.add_worksheet();
     worksheet
@@ -1720,7 +3368,7 @@ fn write_plata_main_section(
 }
 
 /// Create Plata template: "just the table" — no metadata. Row 1 = title, row 2 = months, rows 3–13 = main table, 15–18 declaration, 21–22 employee, 25–27 tax.
-pub fn create_plata_template_xlsx(path: &str) -> Result<(), String> {
+pub fn create_plata_template_xlsx(path: &str) -> Result<(), AppError> {
     let mut workbook = Workbook::new();
     let worksheet = workbook.add_worksheet();
     worksheet
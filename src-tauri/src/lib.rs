@@ -1,14 +1,17 @@
 mod cache;
 mod commands;
 mod db;
+mod db_recovery;
+mod error;
 pub mod excel;
 mod models;
 mod ocr;
+mod ods;
 mod services;
 mod types;
 
 use commands::AppState;
-use std::sync::Mutex;
+use services::app_warnings;
 use tauri::Manager;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -18,69 +21,271 @@ pub fn run() {
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_updater::Builder::new().build())
         .setup(|app| {
+            app_warnings::init(app.handle().clone());
             let app_data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
             // Load .env from app data dir so production users can place credentials there (Settings → Open app data folder)
             let env_path = app_data_dir.join(".env");
             if env_path.exists() {
                 let _ = dotenvy::from_path(&env_path);
             }
+            services::temp_store::init(&app_data_dir);
             let db_path = app_data_dir.join("invoice_scanner.db");
-            let db = db::Db::new(db_path)?;
-            app.manage(AppState {
-                db: Mutex::new(Some(db)),
-            });
+            // A corrupted or locked DB must not abort startup: launch without a DB and let the
+            // frontend offer restore/rebuild via get_db_recovery_status / recover_database.
+            let (db, recovery) = match db_recovery::open_database(&db_path) {
+                Ok(db) => (Some(db), None),
+                Err(status) => {
+                    app_warnings::report("db", format!("could not open {}: {}", status.db_path, status.error));
+                    (None, Some(status))
+                }
+            };
+            app.manage(AppState::new(db, db_path, recovery, commands::read_only_mode_from_env()));
+            // Trim thumbnails / cached OCR JSON over quota without delaying startup.
+            if let Ok(db) = app.state::<AppState>().db() {
+                tauri::async_runtime::spawn(async move {
+                    let _permit = services::work_pool::acquire(services::work_pool::WorkClass::Maintenance).await;
+                    let _ = tauri::async_runtime::spawn_blocking(move || {
+                        if let Err(e) = services::storage::enforce_storage_quotas(&db, &app_data_dir) {
+                            app_warnings::report("storage", format!("quota cleanup failed: {}", e));
+                        }
+                    })
+                    .await;
+                });
+            }
+            // Retry ledger rows queued while a workbook was locked, missing or offline.
+            tauri::async_runtime::spawn(commands::flush_pending_appends_on_startup(app.handle().clone()));
+            // Poll the intake mailbox for emailed invoices, when one is set up.
+            tauri::async_runtime::spawn(commands::poll_email_intake_in_background(app.handle().clone()));
             Ok(())
         })
+        // Files dropped on the window were chosen by the user, like files picked in a dialog.
+        .on_window_event(|_window, event| {
+            if let tauri::WindowEvent::DragDrop(tauri::DragDropEvent::Drop { paths, .. }) = event {
+                for path in paths {
+                    services::path_scope::grant(path);
+                }
+            }
+        })
         .invoke_handler(tauri::generate_handler![
             commands::get_app_data_path,
             commands::open_app_data_folder,
             commands::get_app_version,
+            commands::take_app_warnings,
             commands::get_azure_status,
+            commands::get_settings,
+            commands::set_settings,
+            commands::set_azure_credentials,
+            commands::test_azure_credentials,
+            commands::inspect_document,
+            commands::render_document_preview,
+            commands::run_extraction_regression,
+            commands::test_azure_connection,
+            commands::get_document_types,
+            commands::save_document_type,
+            commands::delete_document_type,
+            commands::export_preset_bundle,
+            commands::read_preset_bundle,
+            commands::import_preset_bundle,
+            commands::get_model_field_mappings,
+            commands::save_model_field_mappings,
+            commands::get_field_schemas,
+            commands::get_field_schema,
+            commands::save_field_schema,
+            commands::reset_field_schema,
+            commands::validate_with_field_schema,
+            commands::export_with_field_schema,
+            commands::get_storage_usage,
+            commands::set_storage_quota,
+            commands::cleanup_storage,
+            commands::get_ocr_provider_settings,
+            commands::set_ocr_provider_settings,
+            commands::get_llm_settings,
+            commands::set_llm_settings,
+            commands::get_archive_settings,
+            commands::set_archive_settings,
+            commands::archive_document,
+            commands::get_archived_document,
+            commands::get_archived_document_path,
+            commands::open_archived_document,
+            commands::get_delivery_settings,
+            commands::set_delivery_settings,
+            commands::deliver_export,
+            commands::list_export_deliveries,
+            commands::get_profile_delivery,
+            commands::set_profile_delivery,
+            commands::remove_profile_delivery,
+            commands::test_profile_delivery,
+            commands::get_google_sheets_settings,
+            commands::set_google_sheets_settings,
+            commands::connect_google_sheets,
+            commands::disconnect_google_sheets,
+            commands::get_profile_google_sheet,
+            commands::set_profile_google_sheet,
+            commands::get_google_sheet_headers,
+            commands::get_profile_export_target,
+            commands::set_profile_export_target,
+            commands::remove_profile_export_target,
+            commands::test_profile_export_target,
+            commands::get_webhook_settings,
+            commands::set_webhook_settings,
+            commands::list_webhook_deliveries,
+            commands::retry_webhook_delivery,
+            commands::get_email_intake_settings,
+            commands::set_email_intake_settings,
+            commands::remove_email_intake_settings,
+            commands::test_email_intake,
+            commands::poll_email_intake,
+            commands::list_email_intake,
+            commands::set_email_intake_status,
+            commands::get_read_only_mode,
             commands::clear_learned_mappings,
+            commands::get_migration_backups,
+            commands::get_db_recovery_status,
+            commands::recover_database,
+            commands::get_closing_status,
+            commands::get_closing_steps,
+            commands::set_closing_steps,
+            commands::export_app_state,
+            commands::import_app_state,
+            commands::get_bundle_keys,
+            commands::generate_bundle_key,
+            commands::import_recipient_key,
+            commands::remove_recipient_key,
             commands::run_ocr,
             commands::run_ocr_invoice,
+            commands::cancel_ocr,
+            commands::retry_excel_write_now,
+            commands::cancel_excel_lock_wait,
+            commands::get_excel_lock_owners,
             commands::batch_scan_invoices,
             commands::export_invoices_to_excel,
             commands::export_invoices_to_new_excel,
+            commands::export_invoices_to_ods,
+            commands::get_export_locale,
+            commands::set_export_locale,
+            commands::get_export_sort,
+            commands::set_export_sort,
+            commands::get_sheet_routes,
+            commands::set_sheet_routes,
+            commands::get_strict_export,
+            commands::set_strict_export,
+            commands::check_strict_export,
+            commands::get_export_name_template,
+            commands::set_export_name_template,
+            commands::get_redaction_rules,
+            commands::set_redaction_rules,
             commands::export_to_new_excel_with_columns,
             commands::copy_template_and_append_rows,
             commands::copy_template_and_fill_tax_balance,
             commands::get_plata_template_path,
             commands::append_invoices_to_existing_excel,
+            commands::reconcile_batch_totals,
             commands::validate_document_file,
             commands::validate_excel_file,
             commands::read_file_base64,
             commands::write_file_base64,
             commands::copy_file,
             commands::delete_file,
+            commands::put_temp_file,
+            commands::get_path_scope,
+            commands::pick_document_root,
+            commands::remove_document_root,
+            commands::pick_open_paths,
+            commands::pick_folder,
+            commands::pick_save_path,
             commands::get_excel_schema,
             commands::scan_excel_schema,
+            commands::refresh_all_profiles,
             commands::save_excel_schema,
             commands::get_excel_schema_for_profile,
             commands::append_to_excel_fast,
+            commands::warm_profile_workbook,
+            commands::append_invoices_to_excel_fast,
+            commands::get_export_manifests,
+            commands::get_pending_appends,
+            commands::get_performance_metrics,
+            commands::flush_pending_appends,
+            commands::discard_pending_append,
             commands::analyze_excel_schema,
             commands::cache_excel_schema,
             commands::read_excel_headers,
             commands::get_excel_headers,
             commands::get_sheet_names,
+            commands::diff_exports,
             commands::get_column_samples,
             commands::append_row_to_excel,
             commands::get_profiles,
             commands::save_profile,
+            commands::remap_profile_columns,
+            commands::convert_workbook_to_xlsx,
+            commands::create_profile_from_template,
+            commands::create_demo_workspace,
+            commands::remove_demo_workspace,
+            commands::suggest_column_mapping,
             commands::delete_profile,
             commands::get_history,
+            commands::get_history_count,
+            commands::search_history_fts,
+            commands::get_history_children,
+            commands::get_write_log,
+            commands::read_ledger_range,
             commands::get_history_by_id,
             commands::create_folder,
             commands::get_folders,
+            commands::rename_folder,
+            commands::move_folder,
             commands::delete_folder,
             commands::assign_history_to_folder,
+            commands::create_category,
+            commands::update_category,
+            commands::get_categories,
+            commands::delete_category,
+            commands::assign_history_to_category,
+            commands::get_spending_by_category,
+            commands::export_spending_report,
+            commands::export_history_report,
+            commands::generate_vat_report,
+            commands::get_snapshot_policy,
+            commands::set_snapshot_policy,
+            commands::get_duplicate_key,
+            commands::set_duplicate_key,
+            commands::find_duplicate_rows,
+            commands::take_ledger_snapshot,
+            commands::list_snapshots,
+            commands::restore_snapshot,
+            commands::audit_profile_consistency,
             commands::add_history_record,
+            commands::save_invoice_edits,
+            commands::get_invoice_edits,
+            commands::get_document_links,
+            commands::start_scan_session,
+            commands::end_scan_session,
+            commands::get_active_scan_session,
+            commands::list_scan_sessions,
+            commands::get_vendor_corrections,
+            commands::delete_vendor_correction,
+            commands::get_vendors,
+            commands::save_vendor,
+            commands::delete_vendor,
+            commands::resolve_vendor,
             commands::update_history_status,
             commands::update_history_record,
+            commands::rescan_history_record,
+            commands::get_history_revisions,
+            commands::restore_history_revision,
             commands::delete_history_record,
+            commands::get_trash,
+            commands::restore_history_record,
+            commands::purge_trash,
             commands::get_learned_mapping,
             commands::upsert_learned_mapping,
+            commands::get_learned_mapping_history,
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while running tauri application")
+        .run(|_app, event| {
+            if let tauri::RunEvent::Exit = event {
+                services::temp_store::clear();
+            }
+        });
 }
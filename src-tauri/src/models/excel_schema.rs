@@ -7,6 +7,9 @@ pub struct ExcelSchema {
     pub first_data_row: u32,
     pub last_data_row: u32,
     pub next_free_row: u32,
+    /// The empty-row gap limit stopped the scan while data exists further down the sheet.
+    #[serde(default)]
+    pub last_row_truncated: bool,
     pub total_rows: u32,
     pub total_columns: u16,
 
@@ -18,7 +21,7 @@ pub struct ExcelSchema {
     pub file_mtime: u64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct HeaderInfo {
     pub column_index: u16,
@@ -26,6 +29,27 @@ pub struct HeaderInfo {
     pub text: String,
 }
 
+/// Header row of a ledger compared with the profile's stored schema (see `services::schema_drift`).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SchemaDiff {
+    pub header_row: u32,
+    pub added: Vec<ColumnChange>,
+    pub removed: Vec<ColumnChange>,
+    pub moved: Vec<ColumnChange>,
+}
+
+/// One header of a `SchemaDiff`: `from` is its column in the stored schema (None when added), `to` its
+/// column in the ledger now (None when removed), `field_key` the field the profile maps it to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ColumnChange {
+    pub text: String,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub field_key: Option<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ColumnFormat {
@@ -1,3 +1,3 @@
 pub mod excel_schema;
 
-pub use excel_schema::{ColumnFormat, ExcelSchema, HeaderInfo, RowTemplate};
+pub use excel_schema::{ColumnChange, ColumnFormat, ExcelSchema, HeaderInfo, RowTemplate, SchemaDiff};
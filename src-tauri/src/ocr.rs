@@ -1,10 +1,17 @@
+use crate::error::AppError;
 use crate::types::{InvoiceData, InvoiceFieldValue, OcrInvoiceResult, OcrLine, OcrResult};
+use crate::services::document_format::{detect_format, tiff_page_count, DocumentFormat, UNSUPPORTED_FORMAT};
+use crate::services::ocr_provider::{self, OcrProvider};
+use crate::services::{document_format, document_sets, document_types, invoice_text, mock_ocr, model_mappings, payroll, settings, textract, validation, vat_return};
 use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use lopdf::Document;
 use reqwest::blocking::Client;
+use serde::Serialize;
 use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 
 fn load_env() {
     let _ = dotenvy::dotenv();
@@ -19,23 +26,135 @@ fn parse_ddv_amt(s: &str) -> f64 {
     s.parse::<f64>().unwrap_or(0.0)
 }
 
-fn count_pages_best_effort(file_path: &str) -> Option<u32> {
-    let ext = Path::new(file_path)
-        .extension()
-        .and_then(|e| e.to_str())
-        .map(|e| e.to_ascii_lowercase())
-        .unwrap_or_default();
-    if ext == "pdf" {
-        if let Ok(doc) = Document::load(file_path) {
+fn count_pages_best_effort(file_path: &str, format: DocumentFormat, bytes: &[u8]) -> Option<u32> {
+    match format {
+        DocumentFormat::Pdf => {
+            let doc = Document::load(file_path).ok()?;
             let pages = doc.get_pages().len() as u32;
-            return Some(if pages == 0 { 1 } else { pages });
+            Some(if pages == 0 { 1 } else { pages })
         }
-        return None;
+        DocumentFormat::Tiff => tiff_page_count(bytes),
+        _ => Some(1),
+    }
+}
+
+/// Page size in PDF points (1/72 inch), from the page's MediaBox or the nearest parent's.
+pub(crate) fn pdf_page_size(doc: &Document, page_id: lopdf::ObjectId) -> Option<(f64, f64)> {
+    let mut dict = doc.get_dictionary(page_id).ok()?;
+    for _ in 0..32 {
+        if let Ok(media_box) = dict.get(b"MediaBox") {
+            let values: Vec<f64> = doc
+                .dereference(media_box)
+                .ok()?
+                .1
+                .as_array()
+                .ok()?
+                .iter()
+                .filter_map(|o| o.as_float().ok().map(f64::from))
+                .collect();
+            let [x0, y0, x1, y1] = values[..] else {
+                return None;
+            };
+            return Some(((x1 - x0).abs(), (y1 - y0).abs()));
+        }
+        dict = doc.get_dictionary(dict.get(b"Parent").ok()?.as_reference().ok()?).ok()?;
+    }
+    None
+}
+
+/// Page count above which `inspect_document` flags a document as large.
+const LARGE_DOCUMENT_PAGES: u32 = 20;
+
+/// Estimated USD per 1000 analyzed pages when AZURE_PRICE_PER_1000_PAGES is not set: text-only reads
+/// are the cheapest, prebuilt and custom analyzers with field extraction cost more.
+fn default_price_per_1000_pages(analyzer_id: &str) -> f64 {
+    match analyzer_id {
+        "prebuilt-read" => 1.5,
+        id if id.starts_with("prebuilt-") => 10.0,
+        _ => 30.0,
     }
-    Some(1)
 }
 
-fn azure_env() -> Result<(String, String), String> {
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PageSize {
+    pub width: f64,
+    pub height: f64,
+    /// "pt" for PDF pages, "px" for images.
+    pub unit: &'static str,
+}
+
+/// What a scan of a document would submit to Azure, checked locally before anything is sent.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentInspection {
+    pub mime_type: String,
+    pub file_size_bytes: u64,
+    pub page_count: u32,
+    /// Size of each page; empty when it cannot be read (HEIC, damaged PDFs). Only the first TIFF page.
+    pub pages: Vec<PageSize>,
+    pub analyzer_id: String,
+    pub price_per_1000_pages: f64,
+    /// Estimated analyze cost in USD (pages × price); the actual bill depends on the Azure contract.
+    pub estimated_cost_usd: f64,
+    /// More than `LARGE_DOCUMENT_PAGES` pages (e.g. a bank statement picked by mistake).
+    pub large: bool,
+}
+
+/// Page count, page sizes and estimated Azure cost of scanning `file_path` as `document_type`.
+pub fn inspect_document(file_path: &str, document_type: Option<&str>) -> Result<DocumentInspection, AppError> {
+    load_env();
+    let bytes = fs::read(Path::new(file_path)).map_err(|e| {
+        if e.kind() == std::io::ErrorKind::NotFound {
+            "File not found.".to_string()
+        } else {
+            format!("Could not read file: {}", e)
+        }
+    })?;
+    let format = detect_format(&bytes).ok_or_else(|| UNSUPPORTED_FORMAT.to_string())?;
+    let mut pages: Vec<PageSize> = Vec::new();
+    let page_count = match format {
+        DocumentFormat::Pdf => {
+            let doc = Document::load_mem(&bytes).map_err(|e| AppError::Ocr(format!("Could not read PDF: {}", e)))?;
+            let ids = doc.get_pages();
+            pages = ids
+                .values()
+                .filter_map(|id| pdf_page_size(&doc, *id))
+                .map(|(width, height)| PageSize { width, height, unit: "pt" })
+                .collect();
+            (ids.len() as u32).max(1)
+        }
+        _ => {
+            if let Some((width, height)) = document_format::image_dimensions(&bytes, format) {
+                pages.push(PageSize { width: width as f64, height: height as f64, unit: "px" });
+            }
+            count_pages_best_effort(file_path, format, &bytes).unwrap_or(1)
+        }
+    };
+    let analyzer_id = pick_analyzer_id(document_type);
+    let price_per_1000_pages = std::env::var("AZURE_PRICE_PER_1000_PAGES")
+        .ok()
+        .and_then(|v| v.trim().parse::<f64>().ok())
+        .filter(|p| *p >= 0.0)
+        .unwrap_or_else(|| default_price_per_1000_pages(&analyzer_id));
+    Ok(DocumentInspection {
+        mime_type: format.mime_type().to_string(),
+        file_size_bytes: bytes.len() as u64,
+        page_count,
+        pages,
+        analyzer_id,
+        price_per_1000_pages,
+        estimated_cost_usd: (page_count as f64 * price_per_1000_pages / 1000.0 * 10_000.0).round() / 10_000.0,
+        large: page_count > LARGE_DOCUMENT_PAGES,
+    })
+}
+
+fn azure_env() -> Result<(String, String), AppError> {
+    // 0) Settings screen (settings table) takes precedence.
+    if let Some(credentials) = settings::azure_credentials() {
+        return Ok(credentials);
+    }
+
     // 1) Runtime env / .env (development or power‑user override)
     if let (Ok(endpoint), Ok(key)) = (
         std::env::var("AZURE_OCR_ENDPOINT"),
@@ -64,14 +183,179 @@ fn azure_env() -> Result<(String, String), String> {
         ));
     }
 
-    Err("AZURE_OCR_ENDPOINT / AZURE_OCR_KEY not set (and no build-time AZURE_OCR_*_BUILD configured).".to_string())
+    Err(AppError::Ocr("AZURE_OCR_ENDPOINT / AZURE_OCR_KEY not set (and no build-time AZURE_OCR_*_BUILD configured).".to_string()))
+}
+
+/// Credentials OCR would use now: settings / credential store, then env, then build-time values.
+pub fn current_azure_credentials() -> Result<(String, String), AppError> {
+    load_env();
+    azure_env()
+}
+
+const CU_API_VERSION: &str = "2025-11-01";
+
+/// Error category and user-facing message for a failed management call.
+fn classify_azure_error(endpoint: &str, status: reqwest::StatusCode, body: String) -> (&'static str, String) {
+    match status {
+        reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN => {
+            // Regional endpoints accept any key from that region, so a rejection there usually means
+            // the resource lives in another region.
+            if endpoint.contains(".api.cognitive.microsoft.com") {
+                ("wrong_region", "Key rejected by this regional endpoint. Use the endpoint shown for your resource in the Azure portal.".to_string())
+            } else {
+                ("invalid_key", "Azure rejected the key. Check the key for this resource.".to_string())
+            }
+        }
+        reqwest::StatusCode::NOT_FOUND => ("endpoint_not_found", "Endpoint not found. Check the Azure endpoint URL.".to_string()),
+        s => ("other", submit_error(s, body).to_string()),
+    }
+}
+
+fn management_client() -> Result<Client, AppError> {
+    Client::builder()
+        .timeout(std::time::Duration::from_secs(20))
+        .build()
+        .map_err(AppError::ocr)
+}
+
+/// List analyzers (lightweight, no document is sent). Err is (error kind, message).
+fn list_analyzers(client: &Client, endpoint: &str, key: &str) -> Result<(), (&'static str, String)> {
+    let response = client
+        .get(format!("{}/contentunderstanding/analyzers?api-version={}", endpoint, CU_API_VERSION))
+        .header("Ocp-Apim-Subscription-Key", key)
+        .send()
+        .map_err(|e| ("network", network_error_message(&e).to_string()))?;
+    let status = response.status();
+    if status.is_success() {
+        return Ok(());
+    }
+    Err(classify_azure_error(endpoint, status, response.text().unwrap_or_default()))
+}
+
+/// Verify an endpoint/key pair with a lightweight call (lists analyzers; no document is sent).
+pub fn check_azure_credentials(endpoint: &str, key: &str) -> Result<(), AppError> {
+    let endpoint = endpoint.trim().trim_end_matches('/');
+    if !endpoint.starts_with("https://") {
+        return Err(AppError::Ocr("Azure endpoint must start with https://".to_string()));
+    }
+    list_analyzers(&management_client()?, endpoint, key.trim()).map_err(|(_, message)| AppError::Ocr(message))
+}
+
+/// Whether an analyzer used for a document type exists on the Azure resource.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnalyzerAvailability {
+    /// None for an analyzer requested explicitly.
+    pub document_type: Option<String>,
+    pub analyzer_id: String,
+    pub available: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AzureConnectionTest {
+    pub ok: bool,
+    pub endpoint: Option<String>,
+    /// Round trip of the analyzer list call.
+    pub latency_ms: Option<u64>,
+    /// "not_configured", "invalid_key", "wrong_region", "endpoint_not_found", "network", "model_missing" or "other".
+    pub error_kind: Option<String>,
+    pub error: Option<String>,
+    pub analyzers: Vec<AnalyzerAvailability>,
+}
+
+/// Diagnose the configured Azure connection: key/endpoint check with latency, then availability of
+/// the analyzer for each document type (plus `analyzer_id` when given).
+pub fn test_azure_connection(analyzer_id: Option<&str>) -> AzureConnectionTest {
+    let mut result = AzureConnectionTest {
+        ok: false,
+        endpoint: None,
+        latency_ms: None,
+        error_kind: None,
+        error: None,
+        analyzers: Vec::new(),
+    };
+    let fail = |mut result: AzureConnectionTest, kind: &str, message: String| {
+        result.error_kind = Some(kind.to_string());
+        result.error = Some(message);
+        result
+    };
+    let (endpoint, key) = match current_azure_credentials() {
+        Ok(credentials) => credentials,
+        Err(e) => return fail(result, "not_configured", e.to_string()),
+    };
+    result.endpoint = Some(endpoint.clone());
+    let client = match management_client() {
+        Ok(c) => c,
+        Err(e) => return fail(result, "other", e.to_string()),
+    };
+    let started = std::time::Instant::now();
+    let listed = list_analyzers(&client, &endpoint, &key);
+    result.latency_ms = Some(started.elapsed().as_millis() as u64);
+    if let Err((kind, message)) = listed {
+        return fail(result, kind, message);
+    }
+
+    let mut types: Vec<String> = ["faktura", "smetka", "generic", "plata"].iter().map(|t| t.to_string()).collect();
+    for registered in document_types::registered_keys() {
+        if !types.contains(&registered) {
+            types.push(registered);
+        }
+    }
+    let mut wanted: Vec<(Option<String>, String)> = types
+        .into_iter()
+        .map(|dt| {
+            let id = pick_analyzer_id(Some(&dt));
+            (Some(dt), id)
+        })
+        .collect();
+    if let Some(id) = analyzer_id.map(str::trim).filter(|id| !id.is_empty()) {
+        wanted.push((None, id.to_string()));
+    }
+    let mut checked: HashMap<String, bool> = HashMap::new();
+    for (document_type, analyzer_id) in wanted {
+        let available = match checked.get(&analyzer_id) {
+            Some(available) => *available,
+            None => {
+                let available = client
+                    .get(format!(
+                        "{}/contentunderstanding/analyzers/{}?api-version={}",
+                        endpoint, analyzer_id, CU_API_VERSION
+                    ))
+                    .header("Ocp-Apim-Subscription-Key", &key)
+                    .send()
+                    .map(|r| r.status().is_success())
+                    .unwrap_or(false);
+                checked.insert(analyzer_id.clone(), available);
+                available
+            }
+        };
+        result.analyzers.push(AnalyzerAvailability { document_type, analyzer_id, available });
+    }
+    let missing: Vec<&str> = result
+        .analyzers
+        .iter()
+        .filter(|a| !a.available)
+        .map(|a| a.analyzer_id.as_str())
+        .collect();
+    if !missing.is_empty() {
+        let message = format!("Analyzer not found on this resource: {}", missing.join(", "));
+        return fail(result, "model_missing", message);
+    }
+    result.ok = true;
+    result
 }
 
-/// Analyzer ID for document type. Uses runtime env first (dev .env), then build-time
-/// (production). Set AZURE_CU_ANALYZER_*_BUILD when building the installer so production
-/// uses your custom analyzers (e.g. projectAnalyzer_...).
+/// Analyzer ID for document type. The model set in `document_types` wins; a type without one uses the
+/// default of its extractor's built-in type: runtime env (dev .env), then build-time (production). Set AZURE_CU_ANALYZER_*_BUILD when building the installer so
+/// production uses your custom analyzers (e.g. projectAnalyzer_...).
 fn pick_analyzer_id(document_type: Option<&str>) -> String {
     let dt = document_type.unwrap_or("").trim();
+    if let Some(model_id) = document_types::model_for(dt) {
+        return model_id;
+    }
+    let dt = document_types::extraction_type(Some(dt)).unwrap_or_default();
+    let dt = dt.as_str();
     let fallback_faktura = option_env!("AZURE_CU_ANALYZER_FAKTURA_BUILD")
         .unwrap_or("")
         .trim();
@@ -114,16 +398,14 @@ fn pick_analyzer_id(document_type: Option<&str>) -> String {
     }
 }
 
-fn fetch_poll_json_via_edge(
-    file_path: &str,
-    document_type: Option<&str>,
-    access_token: &str,
-    employee_id: Option<&str>,
-    app_session_id: Option<&str>,
-) -> Result<serde_json::Value, String> {
-    // These parameters are kept for API compatibility but no longer used for OCR.
-    let _ = (access_token, employee_id, app_session_id);
+/// Analyze URL, subscription key and JSON body for one Content Understanding request.
+struct AnalyzeRequest {
+    url: String,
+    key: String,
+    body: String,
+}
 
+fn build_analyze_request(file_path: &str, document_type: Option<&str>) -> Result<AnalyzeRequest, AppError> {
     load_env();
     let (azure_endpoint, azure_key) = azure_env()?;
     let analyzer_id = pick_analyzer_id(document_type);
@@ -131,8 +413,8 @@ fn fetch_poll_json_via_edge(
     // Works with both prebuilt analyzers (e.g. "prebuilt-invoice") and your custom
     // projectAnalyzer_* IDs configured in .env.
     let analyze_url = format!(
-        "{}/contentunderstanding/analyzers/{}:analyze?api-version=2025-11-01",
-        azure_endpoint, analyzer_id
+        "{}/contentunderstanding/analyzers/{}:analyze?api-version={}",
+        azure_endpoint, analyzer_id, CU_API_VERSION
     );
 
     let bytes = fs::read(Path::new(file_path)).map_err(|e| {
@@ -143,96 +425,212 @@ fn fetch_poll_json_via_edge(
         }
     })?;
 
-    let _pages = count_pages_best_effort(file_path);
+    // Detect by content, not extension: phone photos are often HEIC saved as .jpg.
+    let format = detect_format(&bytes).ok_or_else(|| UNSUPPORTED_FORMAT.to_string())?;
+    let _pages = count_pages_best_effort(file_path, format, &bytes);
 
     // Content Understanding API expects JSON body with base64-encoded input, not raw binary.
     let b64 = BASE64.encode(&bytes);
-    let body_json = serde_json::json!({ "inputs": [{ "data": b64 }] });
-    let body_str = body_json.to_string();
-
-    let client = Client::builder()
-        .timeout(std::time::Duration::from_secs(180))
-        .build()
-        .map_err(|e| e.to_string())?;
+    let body_json = serde_json::json!({ "inputs": [{ "data": b64, "mimeType": format.mime_type() }] });
+    Ok(AnalyzeRequest {
+        url: analyze_url,
+        key: azure_key,
+        body: body_json.to_string(),
+    })
+}
 
-    // 1) Submit document to Azure Content Understanding
-    let response = client
-        .post(&analyze_url)
-        .header("Ocp-Apim-Subscription-Key", &azure_key)
-        .header("Content-Type", "application/json")
-        .body(body_str)
-        .send()
-        .map_err(|e| {
+fn network_error_message(e: &reqwest::Error) -> AppError {
+    AppError::Ocr(
         if e.is_connect() || e.is_timeout() {
             "Check your internet connection and try again."
         } else {
             "Network error."
         }
-        .to_string()
-    })?;
+        .to_string(),
+    )
+}
+
+/// Error for a non-success submit response (Azure's body when present).
+fn submit_error(status: reqwest::StatusCode, body: String) -> AppError {
+    AppError::Ocr(if body.trim().is_empty() {
+        format!("OCR failed ({})", status)
+    } else {
+        body
+    })
+}
+
+/// Longest wait honored from a Retry-After header or computed by backoff.
+const MAX_RETRY_DELAY: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Throttling (429) and transient server errors are worth retrying; other statuses are final.
+fn is_retryable_status(status: reqwest::StatusCode) -> bool {
+    status == reqwest::StatusCode::TOO_MANY_REQUESTS
+        || matches!(status.as_u16(), 500 | 502 | 503 | 504)
+}
+
+fn is_retryable_error(e: &reqwest::Error) -> bool {
+    e.is_connect() || e.is_timeout()
+}
+
+/// Retry-After as delta-seconds or an HTTP date.
+fn retry_after(headers: &reqwest::header::HeaderMap) -> Option<std::time::Duration> {
+    let value = headers.get(reqwest::header::RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(std::time::Duration::from_secs(secs));
+    }
+    let at = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    (at.with_timezone(&chrono::Utc) - chrono::Utc::now()).to_std().ok()
+}
+
+/// Delay before retry number `retry` (1-based): Retry-After when Azure sent one, else exponential
+/// backoff from the configured base with up to 25% jitter so parallel batch jobs do not retry in lockstep.
+fn retry_delay(base: std::time::Duration, retry: u32, server_hint: Option<std::time::Duration>) -> std::time::Duration {
+    let delay = server_hint.unwrap_or_else(|| {
+        let backoff = base.saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)));
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or(0);
+        backoff + backoff.mul_f64((nanos % 250) as f64 / 1000.0)
+    });
+    delay.min(MAX_RETRY_DELAY)
+}
+
+/// Send a blocking request built by `build`, retrying throttling and transient failures per the
+/// configured retry policy. The last response (even a failed one) is returned for the caller to handle.
+fn send_with_retry(
+    build: impl Fn() -> reqwest::blocking::RequestBuilder,
+) -> Result<reqwest::blocking::Response, reqwest::Error> {
+    let (max_attempts, base) = settings::azure_retry_policy();
+    let mut attempt = 1;
+    loop {
+        let hint = match build().send() {
+            Ok(resp) if attempt < max_attempts && is_retryable_status(resp.status()) => retry_after(resp.headers()),
+            Err(e) if attempt < max_attempts && is_retryable_error(&e) => None,
+            other => return other,
+        };
+        std::thread::sleep(retry_delay(base, attempt, hint));
+        attempt += 1;
+    }
+}
+
+/// Async `send_with_retry`; waiting between attempts stops early when the job is cancelled.
+async fn send_with_retry_async(
+    job: &OcrJob,
+    build: impl Fn() -> reqwest::RequestBuilder,
+) -> Result<reqwest::Response, AppError> {
+    let (max_attempts, base) = settings::azure_retry_policy();
+    let mut attempt = 1;
+    loop {
+        let hint = match build().send().await {
+            Ok(resp) if attempt < max_attempts && is_retryable_status(resp.status()) => retry_after(resp.headers()),
+            Err(e) if attempt < max_attempts && is_retryable_error(&e) => None,
+            other => return other.map_err(|e| network_error_message(&e)),
+        };
+        cancellable_sleep(job, retry_delay(base, attempt, hint)).await?;
+        attempt += 1;
+    }
+}
+
+/// Outcome of one poll of the Operation-Location URL.
+enum PollStep {
+    Done(serde_json::Value),
+    /// Still queued or running; carries Azure's status string (e.g. "NotStarted", "Running").
+    Pending(String),
+}
+
+fn poll_step(http_status: reqwest::StatusCode, poll_json: serde_json::Value) -> Result<PollStep, AppError> {
+    let status_raw = poll_json
+        .get("status")
+        .and_then(|s| s.as_str())
+        .unwrap_or("")
+        .to_string();
+    let status_str = status_raw.to_lowercase();
+
+    if status_str == "succeeded" {
+        return Ok(PollStep::Done(poll_json));
+    }
+    if status_str == "failed" {
+        let err = poll_json
+            .get("error")
+            .and_then(|e| e.get("message"))
+            .and_then(|m| m.as_str())
+            .unwrap_or("Unknown error");
+        return Err(AppError::Ocr(format!("OCR analysis failed: {}", err)));
+    }
+
+    // If Azure returns a non-success HTTP status during polling, surface it.
+    if !http_status.is_success() && status_str.is_empty() {
+        return Err(AppError::Ocr(format!("OCR failed ({})", http_status)));
+    }
+    Ok(PollStep::Pending(status_raw))
+}
+
+/// How long to poll an analyze operation before giving up.
+const MAX_POLL_SECS: u64 = 120;
+
+/// Poll attempts at `interval` (the configured polling interval) within MAX_POLL_SECS.
+fn max_poll_attempts(interval: std::time::Duration) -> u32 {
+    (MAX_POLL_SECS / interval.as_secs().max(1)).max(1) as u32
+}
+
+fn fetch_poll_json_via_edge(
+    file_path: &str,
+    document_type: Option<&str>,
+    access_token: &str,
+    employee_id: Option<&str>,
+    app_session_id: Option<&str>,
+) -> Result<serde_json::Value, AppError> {
+    // These parameters are kept for API compatibility but no longer used for OCR.
+    let _ = (access_token, employee_id, app_session_id);
+
+    let request = build_analyze_request(file_path, document_type)?;
+
+    let client = Client::builder()
+        .timeout(std::time::Duration::from_secs(180))
+        .build()
+        .map_err(AppError::ocr)?;
+
+    // 1) Submit document to Azure Content Understanding
+    let response = send_with_retry(|| {
+        client
+            .post(&request.url)
+            .header("Ocp-Apim-Subscription-Key", &request.key)
+            .header("Content-Type", "application/json")
+            .body(request.body.clone())
+    })
+    .map_err(|e| network_error_message(&e))?;
 
     let status = response.status();
     if !status.is_success() {
-        let body = response.text().unwrap_or_default();
-        if body.trim().is_empty() {
-            return Err(format!("OCR failed ({})", status));
-        }
-        return Err(body);
+        return Err(submit_error(status, response.text().unwrap_or_default()));
     }
 
     let op_loc = response
         .headers()
         .get("Operation-Location")
         .and_then(|h| h.to_str().ok())
-        .ok_or_else(|| "No Operation-Location from Azure".to_string())?
+        .ok_or_else(|| AppError::Ocr("No Operation-Location from Azure".to_string()))?
         .to_string();
 
     // 2) Poll Azure until the operation completes (max ~120s).
-    for _ in 0..120 {
-        std::thread::sleep(std::time::Duration::from_secs(1));
-        let poll_resp = client
-            .get(&op_loc)
-            .header("Ocp-Apim-Subscription-Key", &azure_key)
-            .send()
-            .map_err(|e| {
-                if e.is_connect() || e.is_timeout() {
-                    "Check your internet connection and try again."
-                } else {
-                    "Network error."
-                }
-                .to_string()
-            })?;
+    let poll_interval = settings::poll_interval();
+    for _ in 0..max_poll_attempts(poll_interval) {
+        std::thread::sleep(poll_interval);
+        let poll_resp = send_with_retry(|| client.get(&op_loc).header("Ocp-Apim-Subscription-Key", &request.key))
+            .map_err(|e| network_error_message(&e))?;
 
         let poll_status = poll_resp.status();
         let poll_json: serde_json::Value = poll_resp
             .json()
-            .map_err(|e| format!("Invalid JSON: {}", e))?;
-
-        let status_str = poll_json
-            .get("status")
-            .and_then(|s| s.as_str())
-            .unwrap_or("")
-            .to_lowercase();
+            .map_err(|e| AppError::Ocr(format!("Invalid JSON: {}", e)))?;
 
-        if status_str == "succeeded" {
-            return Ok(poll_json);
-        }
-        if status_str == "failed" {
-            let err = poll_json
-                .get("error")
-                .and_then(|e| e.get("message"))
-                .and_then(|m| m.as_str())
-                .unwrap_or("Unknown error");
-            return Err(format!("OCR analysis failed: {}", err));
-        }
-
-        // If Azure returns a non-success HTTP status during polling, surface it.
-        if !poll_status.is_success() && status_str.is_empty() {
-            return Err(format!("OCR failed ({})", poll_status));
+        if let PollStep::Done(json) = poll_step(poll_status, poll_json)? {
+            return Ok(json);
         }
     }
 
-    Err("OCR timed out. Try again.".to_string())
+    Err(AppError::Ocr("OCR timed out. Try again.".to_string()))
 }
 
 pub fn run_ocr_via_edge(
@@ -240,7 +638,7 @@ pub fn run_ocr_via_edge(
     access_token: &str,
     employee_id: Option<&str>,
     app_session_id: Option<&str>,
-) -> Result<OcrResult, String> {
+) -> Result<OcrResult, AppError> {
     let poll_json_outer = fetch_poll_json_via_edge(file_path, None, access_token, employee_id, app_session_id)?;
 
     for _ in 0..1 {
@@ -253,7 +651,7 @@ pub fn run_ocr_via_edge(
             let result = poll_json
                 .get("result")
                 .or_else(|| poll_json.get("analyzeResult"))
-                .ok_or("No result")?;
+                .ok_or(AppError::Ocr("No result".to_string()))?;
 
             // Content Understanding returns result.contents[0].markdown plus optional pages/lines.
             let empty_contents: Vec<serde_json::Value> = Vec::new();
@@ -266,13 +664,18 @@ pub fn run_ocr_via_edge(
             if let Some(doc) = doc {
                 if let Some(markdown) = doc.get("markdown").and_then(|m| m.as_str()) {
                     let content = markdown.to_string();
-                    let lines: Vec<OcrLine> = markdown
-                        .lines()
-                        .map(|t| OcrLine {
-                            text: t.to_string(),
-                            confidence: None,
-                        })
-                        .collect();
+                    // Positioned lines when Azure returned pages, else the markdown lines.
+                    let mut lines = page_lines(doc);
+                    if lines.is_empty() {
+                        lines = markdown
+                            .lines()
+                            .map(|t| OcrLine {
+                                text: t.to_string(),
+                                confidence: None,
+                                ..Default::default()
+                            })
+                            .collect();
+                    }
                     return Ok(OcrResult {
                         content: Some(content),
                         lines,
@@ -292,15 +695,15 @@ pub fn run_ocr_via_edge(
                 .and_then(|e| e.get("message"))
                 .and_then(|m| m.as_str())
                 .unwrap_or("Unknown error");
-            return Err(format!("OCR analysis failed: {}", err));
+            return Err(AppError::Ocr(format!("OCR analysis failed: {}", err)));
         }
     }
-    Err("OCR timed out. Try again.".to_string())
+    Err(AppError::Ocr("OCR timed out. Try again.".to_string()))
 }
 
 // Backwards-compatible wrapper used by Tauri commands.
 // Supabase-specific arguments are no longer needed, so we pass empty values.
-pub fn run_ocr(file_path: &str) -> Result<OcrResult, String> {
+pub fn run_ocr(file_path: &str) -> Result<OcrResult, AppError> {
     run_ocr_via_edge(file_path, "", None, None)
 }
 
@@ -997,6 +1400,125 @@ fn extract_field_value_and_confidence(obj: &serde_json::Value) -> (String, Optio
     (value, confidence)
 }
 
+/// Page and outline of an Azure field or line: Content Understanding's `source` ("D(page,x1,y1,…,x4,y4)",
+/// the first region when a value spans several) or Document Intelligence's `boundingRegions`.
+fn azure_location(obj: &serde_json::Value) -> Option<(u32, Vec<f64>)> {
+    if let Some(source) = obj.get("source").and_then(|s| s.as_str()) {
+        let region = source.trim().strip_prefix("D(")?.split(')').next()?;
+        let mut numbers = region.split(',').map(|n| n.trim().parse::<f64>().ok());
+        let page = numbers.next()??;
+        let polygon: Vec<f64> = numbers.collect::<Option<_>>()?;
+        return (page >= 1.0 && polygon.len() >= 8).then_some((page as u32, polygon));
+    }
+    let region = obj.get("boundingRegions")?.as_array()?.first()?;
+    let page = region.get("pageNumber")?.as_u64()? as u32;
+    let polygon: Vec<f64> = region.get("polygon")?.as_array()?.iter().map(|n| n.as_f64()).collect::<Option<_>>()?;
+    (polygon.len() >= 8).then_some((page, polygon))
+}
+
+/// Text lines with their position from the document's `pages[].lines[]` (empty when Azure returned
+/// markdown only).
+fn page_lines(doc: &serde_json::Value) -> Vec<OcrLine> {
+    let Some(pages) = doc.get("pages").and_then(|p| p.as_array()) else {
+        return Vec::new();
+    };
+    let mut lines = Vec::new();
+    for (i, page) in pages.iter().enumerate() {
+        let page_number = page.get("pageNumber").and_then(|n| n.as_u64()).map_or(i as u32 + 1, |n| n as u32);
+        for line in page.get("lines").and_then(|l| l.as_array()).into_iter().flatten() {
+            let Some(text) = line.get("content").and_then(|c| c.as_str()) else {
+                continue;
+            };
+            let (page, polygon) = match azure_location(line) {
+                Some((page, polygon)) => (page, Some(polygon)),
+                // Document Intelligence lines carry the polygon directly.
+                None => (
+                    page_number,
+                    line.get("polygon")
+                        .and_then(|p| p.as_array())
+                        .and_then(|p| p.iter().map(|n| n.as_f64()).collect::<Option<Vec<f64>>>()),
+                ),
+            };
+            lines.push(OcrLine {
+                text: text.to_string(),
+                confidence: line.get("confidence").and_then(|c| c.as_f64()),
+                page: Some(page),
+                polygon,
+            });
+        }
+    }
+    lines
+}
+
+/// An Azure field value with its position, for `attach_locations`.
+struct LocatedValue {
+    value: String,
+    content: Option<String>,
+    page: u32,
+    polygon: Vec<f64>,
+}
+
+fn collect_located_values(fields: &serde_json::Value, out: &mut Vec<LocatedValue>) {
+    let children: Vec<&serde_json::Value> = match fields {
+        serde_json::Value::Object(map) => map.values().collect(),
+        serde_json::Value::Array(items) => items.iter().collect(),
+        _ => return,
+    };
+    for field in children {
+        if let Some((page, polygon)) = azure_location(field) {
+            out.push(LocatedValue {
+                value: extract_azure_field_value(field).trim().to_string(),
+                content: field.get("content").and_then(|c| c.as_str()).map(|c| c.trim().to_string()),
+                page,
+                polygon,
+            });
+        }
+        for nested in ["valueObject", "valueArray"] {
+            if let Some(nested) = field.get(nested) {
+                collect_located_values(nested, out);
+            }
+        }
+    }
+}
+
+/// Record where each field was read, so the UI can highlight it on the preview: the Azure field holding
+/// the same value (or amount), else the first text line containing it. Values the app built itself
+/// (labels, sums, joined text) stay without a location.
+fn attach_locations(fields: &mut HashMap<String, InvoiceFieldValue>, doc: Option<&serde_json::Value>) {
+    let Some(doc) = doc else {
+        return;
+    };
+    let mut located = Vec::new();
+    if let Some(azure_fields) = doc.get("fields") {
+        collect_located_values(azure_fields, &mut located);
+    }
+    let lines = page_lines(doc);
+    for field in fields.values_mut() {
+        let value = field.value.trim();
+        if field.page.is_some() || value.is_empty() || value.contains('\n') {
+            continue;
+        }
+        // Zero amounts are everywhere on tax forms; matching them would point at an arbitrary box.
+        let amount = validation::parse_amount(value).filter(|a| *a != 0.0);
+        let from_field = located
+            .iter()
+            .find(|l| l.value == value || l.content.as_deref() == Some(value))
+            .or_else(|| amount.and_then(|a| located.iter().find(|l| validation::parse_amount(&l.value) == Some(a))))
+            .map(|l| (l.page, l.polygon.clone()));
+        let from_line = || {
+            lines
+                .iter()
+                .filter(|_| value.chars().count() >= 3)
+                .find(|l| l.text.contains(value))
+                .and_then(|l| Some((l.page?, l.polygon.clone()?)))
+        };
+        if let Some((page, polygon)) = from_field.or_else(from_line) {
+            field.page = Some(page);
+            field.polygon = Some(polygon);
+        }
+    }
+}
+
 /// Get string from a line item subfield (Description, ProductCode, etc.).
 fn item_field_string(value_obj: &serde_json::Map<String, serde_json::Value>, key: &str) -> String {
     value_obj
@@ -1109,10 +1631,23 @@ pub fn run_ocr_invoice_via_edge(
     access_token: &str,
     employee_id: Option<&str>,
     app_session_id: Option<&str>,
-) -> Result<OcrInvoiceResult, String> {
-    let poll_json_outer =
+) -> Result<OcrInvoiceResult, AppError> {
+    let poll_json =
         fetch_poll_json_via_edge(file_path, document_type, access_token, employee_id, app_session_id)?;
+    parse_invoice_poll_json(&poll_json, document_type)
+}
+
+/// Parse a stored poll response as a scan would (regression harness, mock provider sidecars).
+pub(crate) fn replay_poll_json(poll_json: &serde_json::Value, document_type: Option<&str>) -> Result<OcrInvoiceResult, AppError> {
+    parse_invoice_poll_json(poll_json, document_type)
+}
 
+/// Turn a finished Content Understanding poll response into invoice fields.
+/// Shared by the blocking path (batch scan) and the async path with progress events.
+fn parse_invoice_poll_json(
+    poll_json_outer: &serde_json::Value,
+    document_type: Option<&str>,
+) -> Result<OcrInvoiceResult, AppError> {
     for _ in 0..1 {
         let poll_json = poll_json_outer.clone();
         let status_str = poll_json
@@ -1123,7 +1658,16 @@ pub fn run_ocr_invoice_via_edge(
             let result = poll_json
                 .get("result")
                 .or_else(|| poll_json.get("analyzeResult"))
-                .ok_or("No result")?;
+                .ok_or(AppError::Ocr("No result".to_string()))?;
+            let model_id = result
+                .get("analyzerId")
+                .and_then(|v| v.as_str())
+                .map(String::from)
+                .unwrap_or_else(|| pick_analyzer_id(document_type));
+            let custom_mappings = model_mappings::for_model(&model_id);
+            // Registered types are read like the built-in type of their extractor.
+            let extraction_type = document_types::extraction_type(document_type);
+            let document_type = extraction_type.as_deref();
 
             // How many logical documents did Azure detect in this file?
             // If >1, the PDF likely contains multiple invoices/pages that should be split.
@@ -1144,10 +1688,11 @@ pub fn run_ocr_invoice_via_edge(
 
             // Content Understanding uses result.contents[0]; legacy Document Intelligence used analyzeResult.documents[0].
             // Some APIs return the document at result level with result.fields directly.
-            let doc = result
-                .get("contents")
-                .and_then(|c| c.as_array())
-                .and_then(|a| a.first())
+            // A stapled invoice + delivery note comes back as two contents; extract the invoice.
+            let contents = result.get("contents").and_then(|c| c.as_array());
+            let (invoice_index, note_index) = contents.map(|a| document_sets::split_pair(a)).unwrap_or((0, None));
+            let doc = contents
+                .and_then(|a| a.get(invoice_index))
                 .or_else(|| {
                     result
                         .get("documents")
@@ -1217,6 +1762,7 @@ pub fn run_ocr_invoice_via_edge(
                         InvoiceFieldValue {
                             value: content_parts.join("\n"),
                             confidence: None,
+                            ..Default::default()
                         },
                     );
                     fields.insert(
@@ -1224,12 +1770,17 @@ pub fn run_ocr_invoice_via_edge(
                         InvoiceFieldValue {
                             value: "Даночен биланс".to_string(),
                             confidence: Some(1.0),
+                            ..Default::default()
                         },
                     );
                     return Ok(OcrInvoiceResult {
                         invoice_data: InvoiceData { fields, source_file: None, source_file_path: None },
                         raw_azure_fields: None,
                         document_count,
+                        warnings: Vec::new(),
+                        applied_corrections: Vec::new(),
+                        raw_text: None,
+                        extraction_path: None,
                     });
                 }
             }
@@ -1246,30 +1797,48 @@ pub fn run_ocr_invoice_via_edge(
                     .unwrap_or("");
                 if !content.trim().is_empty() {
                     let mut fields = HashMap::new();
-                    fields.insert(
-                        "description".to_string(),
-                        InvoiceFieldValue {
-                            value: content.to_string(),
-                            confidence: None,
-                        },
-                    );
+                    // Payroll and ДДВ-04: labelled amounts, boxes, period and names instead of the raw text.
+                    let key_values = doc.map(payroll::layout_key_values).unwrap_or_default();
+                    match document_type {
+                        Some("plata") => fields = payroll::extract(&key_values, content),
+                        Some("generic") => fields = vat_return::extract(&key_values, content),
+                        Some("faktura") | None => fields = invoice_text::extract(content),
+                        _ => {}
+                    }
+                    if fields.is_empty() {
+                        fields.insert(
+                            "description".to_string(),
+                            InvoiceFieldValue {
+                                value: content.to_string(),
+                                confidence: None,
+                                ..Default::default()
+                            },
+                        );
+                    }
                     // Set document type based on input parameter
                     let doc_type_value = match document_type {
                         Some("plata") => "Плата",
                         Some("generic") => "ДДВ",
+                        Some("faktura") if !fields.contains_key("description") => "Фактура",
                         _ => "Документ",
                     };
+                    attach_locations(&mut fields, doc);
                     fields.insert(
                         "document_type".to_string(),
                         InvoiceFieldValue {
                             value: doc_type_value.to_string(),
                             confidence: Some(1.0),
+                            ..Default::default()
                         },
                     );
                     return Ok(OcrInvoiceResult {
                         invoice_data: InvoiceData { fields, source_file: None, source_file_path: None },
                         raw_azure_fields: None,
                         document_count,
+                        warnings: Vec::new(),
+                        applied_corrections: Vec::new(),
+                        raw_text: Some(content.to_string()),
+                        extraction_path: None,
                     });
                 }
                 // If no content either, return empty result
@@ -1277,6 +1846,10 @@ pub fn run_ocr_invoice_via_edge(
                     invoice_data: InvoiceData { fields: HashMap::new(), source_file: None, source_file_path: None },
                     raw_azure_fields: None,
                     document_count,
+                    warnings: Vec::new(),
+                    applied_corrections: Vec::new(),
+                    raw_text: None,
+                    extraction_path: None,
                 });
             }
             
@@ -1322,7 +1895,7 @@ pub fn run_ocr_invoice_via_edge(
                         value = sanitize_description(&value);
                     }
                     if !value.is_empty() && !value.eq_ignore_ascii_case("\"\"text") && !value.starts_with("\"\"") {
-                        fields.insert((*our_key).to_string(), InvoiceFieldValue { value, confidence });
+                        fields.insert((*our_key).to_string(), InvoiceFieldValue { value, confidence, ..Default::default() });
                     }
                 }
             }
@@ -1365,7 +1938,7 @@ pub fn run_ocr_invoice_via_edge(
                         {
                             fields.insert(
                                 (*our_key).to_string(),
-                                InvoiceFieldValue { value, confidence },
+                                InvoiceFieldValue { value, confidence, ..Default::default() },
                             );
                         }
                     }
@@ -1403,7 +1976,7 @@ pub fn run_ocr_invoice_via_edge(
                         {
                             fields.insert(
                                 (*our_key).to_string(),
-                                InvoiceFieldValue { value: value.to_string(), confidence },
+                                InvoiceFieldValue { value: value.to_string(), confidence, ..Default::default() },
                             );
                         }
                     }
@@ -1425,7 +1998,7 @@ pub fn run_ocr_invoice_via_edge(
                         {
                             fields.insert(
                                 (*tax_key).to_string(),
-                                InvoiceFieldValue { value: value.to_string(), confidence },
+                                InvoiceFieldValue { value: value.to_string(), confidence, ..Default::default() },
                             );
                         }
                     }
@@ -1444,6 +2017,7 @@ pub fn run_ocr_invoice_via_edge(
                                     InvoiceFieldValue {
                                         value: format!("01.01.{}", y),
                                         confidence: year_conf,
+                                        ..Default::default()
                                     },
                                 );
                             }
@@ -1453,6 +2027,7 @@ pub fn run_ocr_invoice_via_edge(
                                     InvoiceFieldValue {
                                         value: format!("31.12.{}", y),
                                         confidence: year_conf,
+                                        ..Default::default()
                                     },
                                 );
                             }
@@ -1485,6 +2060,7 @@ pub fn run_ocr_invoice_via_edge(
                                             InvoiceFieldValue {
                                                 value: amount_display.clone(),
                                                 confidence: amount_conf,
+                                                ..Default::default()
                                             },
                                         );
                                     }
@@ -1495,6 +2071,7 @@ pub fn run_ocr_invoice_via_edge(
                                         InvoiceFieldValue {
                                             value: ln.to_string(),
                                             confidence: None,
+                                            ..Default::default()
                                         },
                                     );
                                 }
@@ -1509,6 +2086,7 @@ pub fn run_ocr_invoice_via_edge(
                                             InvoiceFieldValue {
                                                 value: label_val.to_string(),
                                                 confidence: None,
+                                                ..Default::default()
                                             },
                                         );
                                     }
@@ -1518,6 +2096,7 @@ pub fn run_ocr_invoice_via_edge(
                                     InvoiceFieldValue {
                                         value: amount_display,
                                         confidence: amount_conf,
+                                        ..Default::default()
                                     },
                                 );
                             }
@@ -1546,6 +2125,7 @@ pub fn run_ocr_invoice_via_edge(
                                             InvoiceFieldValue {
                                                 value: if value.is_empty() { "0".to_string() } else { value.to_string() },
                                                 confidence,
+                                                ..Default::default()
                                             },
                                         );
                                     }
@@ -1581,6 +2161,7 @@ pub fn run_ocr_invoice_via_edge(
                                 InvoiceFieldValue {
                                     value: if value.is_empty() { "0".to_string() } else { value.to_string() },
                                     confidence,
+                                    ..Default::default()
                                 },
                             );
                         }
@@ -1608,7 +2189,7 @@ pub fn run_ocr_invoice_via_edge(
                         {
                             fields.insert(
                                 (*our_key).to_string(),
-                                InvoiceFieldValue { value: value.to_string(), confidence },
+                                InvoiceFieldValue { value: value.to_string(), confidence, ..Default::default() },
                             );
                         }
                     }
@@ -1662,6 +2243,7 @@ pub fn run_ocr_invoice_via_edge(
                                 InvoiceFieldValue {
                                     value: if value.is_empty() { "0".to_string() } else { value.to_string() },
                                     confidence,
+                                    ..Default::default()
                                 },
                             );
                         }
@@ -1696,6 +2278,7 @@ pub fn run_ocr_invoice_via_edge(
                             InvoiceFieldValue {
                                 value: end_val.to_string(),
                                 confidence: end_conf,
+                                ..Default::default()
                             },
                         );
                     }
@@ -1706,6 +2289,7 @@ pub fn run_ocr_invoice_via_edge(
                         InvoiceFieldValue {
                             value: label,
                             confidence: period_conf,
+                            ..Default::default()
                         },
                     );
                 }
@@ -1729,6 +2313,7 @@ pub fn run_ocr_invoice_via_edge(
                                                 InvoiceFieldValue {
                                                     value: v.to_string(),
                                                     confidence: sub_val.get("confidence").and_then(|c| c.as_f64()),
+                                                    ..Default::default()
                                                 },
                                             );
                                         }
@@ -1738,6 +2323,7 @@ pub fn run_ocr_invoice_via_edge(
                                             InvoiceFieldValue {
                                                 value: n.to_string(),
                                                 confidence: sub_val.get("confidence").and_then(|c| c.as_f64()),
+                                                ..Default::default()
                                             },
                                         );
                                     }
@@ -1785,6 +2371,7 @@ pub fn run_ocr_invoice_via_edge(
                             InvoiceFieldValue {
                                 value: format!("{}", total),
                                 confidence: None,
+                                ..Default::default()
                             },
                         );
                     }
@@ -1834,6 +2421,7 @@ pub fn run_ocr_invoice_via_edge(
                             InvoiceFieldValue {
                                 value: format!("{}", sum as i64),
                                 confidence: None,
+                                ..Default::default()
                             },
                         );
                     }
@@ -1850,6 +2438,7 @@ pub fn run_ocr_invoice_via_edge(
                             InvoiceFieldValue {
                                 value: format!("{}", sum as i64),
                                 confidence: None,
+                                ..Default::default()
                             },
                         );
                     }
@@ -1866,6 +2455,7 @@ pub fn run_ocr_invoice_via_edge(
                             InvoiceFieldValue {
                                 value: format!("{}", sum as i64),
                                 confidence: None,
+                                ..Default::default()
                             },
                         );
                     }
@@ -1888,6 +2478,7 @@ pub fn run_ocr_invoice_via_edge(
                             InvoiceFieldValue {
                                 value: format!("{}", value as i64),
                                 confidence: None,
+                                ..Default::default()
                             },
                         );
                     }
@@ -1910,6 +2501,7 @@ pub fn run_ocr_invoice_via_edge(
                         InvoiceFieldValue {
                             value: default_desc,
                             confidence: None,
+                            ..Default::default()
                         },
                     );
                 }
@@ -1930,6 +2522,7 @@ pub fn run_ocr_invoice_via_edge(
                             InvoiceFieldValue {
                                 value: if value.is_empty() { "0".to_string() } else { value.to_string() },
                                 confidence,
+                                ..Default::default()
                             },
                         );
                     }
@@ -1943,6 +2536,7 @@ pub fn run_ocr_invoice_via_edge(
                             InvoiceFieldValue {
                                 value: if value.is_empty() { "0".to_string() } else { value.to_string() },
                                 confidence,
+                                ..Default::default()
                             },
                         );
                     }
@@ -1973,6 +2567,7 @@ pub fn run_ocr_invoice_via_edge(
                         InvoiceFieldValue {
                             value: total_cost.to_string(),
                             confidence: None,
+                            ..Default::default()
                         },
                     );
                 }
@@ -1981,7 +2576,7 @@ pub fn run_ocr_invoice_via_edge(
                     let (value, confidence) = extract_field_value_and_confidence(obj);
                     let value = value.trim();
                     if !value.is_empty() {
-                        let fv = InvoiceFieldValue { value: value.to_string(), confidence };
+                        let fv = InvoiceFieldValue { value: value.to_string(), confidence, ..Default::default() };
                         fields.insert("declarationPeriod".to_string(), fv.clone());
                         fields.insert("year".to_string(), fv.clone());
                         fields.insert("date".to_string(), fv);
@@ -2002,6 +2597,7 @@ pub fn run_ocr_invoice_via_edge(
                                 InvoiceFieldValue {
                                     value: if value.is_empty() { "0".to_string() } else { value.to_string() },
                                     confidence,
+                                    ..Default::default()
                                 },
                             );
                         }
@@ -2026,7 +2622,7 @@ pub fn run_ocr_invoice_via_edge(
                         {
                             fields.insert(
                                 (*our_key).to_string(),
-                                InvoiceFieldValue { value: value.to_string(), confidence },
+                                InvoiceFieldValue { value: value.to_string(), confidence, ..Default::default() },
                             );
                         }
                     }
@@ -2042,13 +2638,13 @@ pub fn run_ocr_invoice_via_edge(
                                         if !v.is_empty() {
                                             fields.insert(
                                                 format!("monthlyRows_{}_{}", idx, sub_key),
-                                                InvoiceFieldValue { value: v.to_string(), confidence: sub_val.get("confidence").and_then(|c| c.as_f64()) },
+                                                InvoiceFieldValue { value: v.to_string(), confidence: sub_val.get("confidence").and_then(|c| c.as_f64()), ..Default::default() },
                                             );
                                         }
                                     } else if let Some(n) = sub_val.get("valueNumber").and_then(|v| v.as_f64()) {
                                         fields.insert(
                                             format!("monthlyRows_{}_{}", idx, sub_key),
-                                            InvoiceFieldValue { value: n.to_string(), confidence: sub_val.get("confidence").and_then(|c| c.as_f64()) },
+                                            InvoiceFieldValue { value: n.to_string(), confidence: sub_val.get("confidence").and_then(|c| c.as_f64()), ..Default::default() },
                                         );
                                     }
                                 }
@@ -2058,18 +2654,33 @@ pub fn run_ocr_invoice_via_edge(
                 }
             }
 
+            // Mappings configured for this analyzer come first and replace built-in ones for the same Azure field.
+            for (azure_key, our_key) in &custom_mappings {
+                if our_key.is_empty() {
+                    continue;
+                }
+                if let Some(obj) = fields_obj.get(azure_key) {
+                    let (value, confidence) = extract_field_value_and_confidence(obj);
+                    if !value.trim().is_empty() {
+                        fields.insert(our_key.clone(), InvoiceFieldValue { value, confidence, ..Default::default() });
+                    }
+                }
+            }
             // Extract all mapped fields from Azure, including Currency and TypeOfDocument
             for (azure_key, our_key) in AZURE_TO_FIELD {
                 if *our_key == "seller_name" || *our_key == "buyer_name" {
                     continue;
                 }
+                if custom_mappings.iter().any(|(k, _)| k == azure_key) {
+                    continue;
+                }
                 if let Some(obj) = fields_obj.get(*azure_key) {
                     let (value, confidence) = extract_field_value_and_confidence(obj);
                     // Only insert if value is not empty
                     if !value.trim().is_empty() {
                         fields.insert(
                             (*our_key).to_string(),
-                            InvoiceFieldValue { value, confidence },
+                            InvoiceFieldValue { value, confidence, ..Default::default() },
                         );
                     }
                 }
@@ -2082,6 +2693,7 @@ pub fn run_ocr_invoice_via_edge(
                         InvoiceFieldValue {
                             value: doc_num.value.clone(),
                             confidence: doc_num.confidence,
+                            ..Default::default()
                         },
                     );
                 }
@@ -2095,6 +2707,7 @@ pub fn run_ocr_invoice_via_edge(
                     InvoiceFieldValue {
                         value: name,
                         confidence: vendor_conf,
+                        ..Default::default()
                     },
                 );
             }
@@ -2107,6 +2720,7 @@ pub fn run_ocr_invoice_via_edge(
                     InvoiceFieldValue {
                         value: name,
                         confidence: customer_conf,
+                        ..Default::default()
                     },
                 );
             }
@@ -2140,6 +2754,7 @@ pub fn run_ocr_invoice_via_edge(
                             InvoiceFieldValue {
                                 value: description,
                                 confidence: desc_confidence,
+                                ..Default::default()
                             },
                         );
                     }
@@ -2165,6 +2780,7 @@ pub fn run_ocr_invoice_via_edge(
                                 InvoiceFieldValue {
                                     value: s.to_string(),
                                     confidence: obj.get("confidence").and_then(|c| c.as_f64()),
+                                    ..Default::default()
                                 },
                             );
                             break;
@@ -2192,6 +2808,7 @@ pub fn run_ocr_invoice_via_edge(
                                 InvoiceFieldValue {
                                     value: cleaned,
                                     confidence: None,
+                                    ..Default::default()
                                 },
                             );
                         }
@@ -2221,6 +2838,7 @@ pub fn run_ocr_invoice_via_edge(
                                 InvoiceFieldValue {
                                     value: cleaned,
                                     confidence: None,
+                                    ..Default::default()
                                 },
                             );
                         }
@@ -2233,6 +2851,7 @@ pub fn run_ocr_invoice_via_edge(
             let mapped_azure_keys: std::collections::HashSet<&str> = AZURE_TO_FIELD
                 .iter()
                 .map(|(k, _)| *k)
+                .chain(custom_mappings.iter().map(|(k, _)| k.as_str()))
                 .chain(std::iter::once("Items"))
                 .chain(std::iter::once("nonRecognizedExpenseRows"))
                 .chain(std::iter::once("periodRows"))
@@ -2272,12 +2891,34 @@ pub fn run_ocr_invoice_via_edge(
                 } else {
                     value.to_string()
                 };
-                fields.insert(canonical_key, InvoiceFieldValue { value, confidence });
+                fields.insert(canonical_key, InvoiceFieldValue { value, confidence, ..Default::default() });
+            }
+            if !matches!(document_type, Some("smetka") | Some("generic") | Some("plata"))
+                && !fields.contains_key(document_sets::DELIVERY_NOTE_FIELD)
+            {
+                let note_number = note_index
+                    .and_then(|i| contents.and_then(|a| a.get(i)))
+                    .and_then(document_sets::delivery_note_number)
+                    .or_else(|| {
+                        doc.and_then(|d| d.get("markdown").and_then(|m| m.as_str()))
+                            .and_then(document_sets::find_note_number)
+                    });
+                if let Some(number) = note_number {
+                    fields.insert(
+                        document_sets::DELIVERY_NOTE_FIELD.to_string(),
+                        InvoiceFieldValue { value: number, confidence: None, ..Default::default() },
+                    );
+                }
             }
+            attach_locations(&mut fields, doc);
             return Ok(OcrInvoiceResult {
                 invoice_data: InvoiceData { fields, source_file: None, source_file_path: None },
                 raw_azure_fields,
                 document_count,
+                warnings: Vec::new(),
+                applied_corrections: Vec::new(),
+                raw_text: None,
+                extraction_path: None,
             });
         }
         if status_str.eq_ignore_ascii_case("failed") {
@@ -2286,10 +2927,10 @@ pub fn run_ocr_invoice_via_edge(
                 .and_then(|e| e.get("message"))
                 .and_then(|m| m.as_str())
                 .unwrap_or("Unknown error");
-            return Err(format!("OCR analysis failed: {}", err));
+            return Err(AppError::Ocr(format!("OCR analysis failed: {}", err)));
         }
     }
-    Err("OCR timed out. Try again.".to_string())
+    Err(AppError::Ocr("OCR timed out. Try again.".to_string()))
 }
 
 // Backwards-compatible wrapper used by Tauri commands.
@@ -2297,6 +2938,276 @@ pub fn run_ocr_invoice_via_edge(
 pub fn run_ocr_invoice(
     file_path: &str,
     document_type: Option<&str>,
-) -> Result<OcrInvoiceResult, String> {
-    run_ocr_invoice_via_edge(file_path, document_type, "", None, None)
+) -> Result<OcrInvoiceResult, AppError> {
+    let _timer = crate::services::perf_metrics::timer(crate::services::perf_metrics::Metric::Ocr);
+    if let Some(local) = run_local_text_layer(file_path, document_type) {
+        return local;
+    }
+    match ocr_provider::current() {
+        OcrProvider::Textract(cfg) => return textract::analyze_invoice(&cfg?, file_path, document_type),
+        OcrProvider::Mock => return mock_ocr::analyze(file_path, document_type),
+        OcrProvider::Azure => {}
+    }
+    run_ocr_invoice_via_edge(file_path, document_type, "", None, None).map(from_azure)
+}
+
+fn from_azure(mut result: OcrInvoiceResult) -> OcrInvoiceResult {
+    result.extraction_path = Some("azure".to_string());
+    result
+}
+
+/// Minimum letters and digits per page for a PDF text layer to be read instead of scanned.
+const MIN_TEXT_LAYER_CHARS_PER_PAGE: usize = 40;
+
+/// Embedded text of a PDF when it has enough to read fields from; None for images and scanned PDFs.
+fn pdf_text_layer(file_path: &str) -> Option<String> {
+    let bytes = fs::read(Path::new(file_path)).ok()?;
+    if detect_format(&bytes) != Some(DocumentFormat::Pdf) {
+        return None;
+    }
+    let doc = Document::load_mem(&bytes).ok()?;
+    let pages: Vec<u32> = doc.get_pages().keys().copied().collect();
+    if pages.is_empty() {
+        return None;
+    }
+    let text = doc.extract_text(&pages).ok()?;
+    let chars = text.chars().filter(|c| c.is_alphanumeric()).count();
+    (chars >= MIN_TEXT_LAYER_CHARS_PER_PAGE * pages.len()).then_some(text)
+}
+
+/// Fields from the PDF's own text layer through the text-only heuristics, without calling OCR.
+/// None when "prefer local text layer" is off or the file has no usable text layer.
+fn run_local_text_layer(file_path: &str, document_type: Option<&str>) -> Option<Result<OcrInvoiceResult, AppError>> {
+    if !settings::prefer_local_text_layer() {
+        return None;
+    }
+    let text = pdf_text_layer(file_path)?;
+    let poll_json = serde_json::json!({
+        "status": "Succeeded",
+        "result": { "analyzerId": "local-text-layer", "contents": [{ "kind": "document", "markdown": text }] },
+    });
+    Some(parse_invoice_poll_json(&poll_json, document_type).map(|mut r| {
+        r.extraction_path = Some("local_text_layer".to_string());
+        r
+    }))
+}
+
+/// Progress of one Azure polling cycle, emitted to the frontend as `ocr-progress`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OcrProgress {
+    pub job_id: String,
+    pub file_path: String,
+    /// "uploading" or "polling"
+    pub stage: String,
+    pub attempt: u32,
+    pub max_attempts: u32,
+    pub elapsed_ms: u64,
+    /// Azure operation status ("NotStarted", "Running", ...); empty while uploading.
+    pub azure_status: String,
+    /// OCR jobs started before this one that are still running (0 = first in line).
+    pub queue_position: usize,
+}
+
+/// In-flight async OCR jobs: job id -> (start sequence, cancel flag).
+type OcrJobMap = HashMap<String, (u64, Arc<AtomicBool>)>;
+static OCR_JOBS: std::sync::OnceLock<Mutex<OcrJobMap>> = std::sync::OnceLock::new();
+static OCR_JOB_SEQ: AtomicU64 = AtomicU64::new(0);
+
+fn ocr_jobs() -> &'static Mutex<OcrJobMap> {
+    OCR_JOBS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Registers a job for cancellation and queue-position tracking; unregisters on drop.
+pub struct OcrJob {
+    pub id: String,
+    seq: u64,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl OcrJob {
+    pub fn register(id: String) -> Self {
+        let seq = OCR_JOB_SEQ.fetch_add(1, Ordering::SeqCst);
+        let cancelled = Arc::new(AtomicBool::new(false));
+        if let Ok(mut jobs) = ocr_jobs().lock() {
+            jobs.insert(id.clone(), (seq, cancelled.clone()));
+        }
+        OcrJob { id, seq, cancelled }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    fn queue_position(&self) -> usize {
+        ocr_jobs()
+            .lock()
+            .map(|jobs| jobs.values().filter(|(seq, _)| *seq < self.seq).count())
+            .unwrap_or(0)
+    }
+}
+
+impl Drop for OcrJob {
+    fn drop(&mut self) {
+        if let Ok(mut jobs) = ocr_jobs().lock() {
+            jobs.remove(&self.id);
+        }
+    }
+}
+
+/// Request cancellation of a running async OCR job. Returns false if no such job is running.
+pub fn cancel_ocr_job(job_id: &str) -> bool {
+    ocr_jobs()
+        .lock()
+        .ok()
+        .and_then(|jobs| jobs.get(job_id).map(|(_, flag)| flag.store(true, Ordering::SeqCst)))
+        .is_some()
+}
+
+pub const OCR_CANCELLED: &str = "OCR cancelled.";
+
+/// Sleep for `total`, waking early (with an error) when the job is cancelled.
+async fn cancellable_sleep(job: &OcrJob, total: std::time::Duration) -> Result<(), AppError> {
+    let step = std::time::Duration::from_millis(250);
+    let mut slept = std::time::Duration::ZERO;
+    while slept < total {
+        if job.is_cancelled() {
+            return Err(AppError::Ocr(OCR_CANCELLED.to_string()));
+        }
+        tokio::time::sleep(step).await;
+        slept += step;
+    }
+    if job.is_cancelled() {
+        return Err(AppError::Ocr(OCR_CANCELLED.to_string()));
+    }
+    Ok(())
+}
+
+/// Non-blocking variant of `fetch_poll_json_via_edge`: async HTTP, cancellable, and reports every
+/// polling cycle through `on_progress`.
+async fn fetch_poll_json_async(
+    file_path: &str,
+    document_type: Option<&str>,
+    job: &OcrJob,
+    on_progress: &(dyn Fn(OcrProgress) + Send + Sync),
+) -> Result<serde_json::Value, AppError> {
+    let started = std::time::Instant::now();
+    let poll_interval = settings::poll_interval();
+    let max_attempts = max_poll_attempts(poll_interval);
+    let progress = |stage: &str, attempt: u32, azure_status: &str| {
+        on_progress(OcrProgress {
+            job_id: job.id.clone(),
+            file_path: file_path.to_string(),
+            stage: stage.to_string(),
+            attempt,
+            max_attempts,
+            elapsed_ms: started.elapsed().as_millis() as u64,
+            azure_status: azure_status.to_string(),
+            queue_position: job.queue_position(),
+        })
+    };
+
+    let path = file_path.to_string();
+    let doc_type = document_type.map(str::to_string);
+    // Reading and base64-encoding a large PDF is CPU/disk work; keep it off the async runtime.
+    let request = tokio::task::spawn_blocking(move || build_analyze_request(&path, doc_type.as_deref()))
+        .await
+        .map_err(AppError::ocr)??;
+
+    let client = reqwest::Client::builder()
+        .timeout(std::time::Duration::from_secs(180))
+        .build()
+        .map_err(AppError::ocr)?;
+
+    progress("uploading", 0, "");
+    if job.is_cancelled() {
+        return Err(AppError::Ocr(OCR_CANCELLED.to_string()));
+    }
+    let response = send_with_retry_async(job, || {
+        client
+            .post(&request.url)
+            .header("Ocp-Apim-Subscription-Key", &request.key)
+            .header("Content-Type", "application/json")
+            .body(request.body.clone())
+    })
+    .await?;
+
+    let status = response.status();
+    if !status.is_success() {
+        return Err(submit_error(status, response.text().await.unwrap_or_default()));
+    }
+
+    let op_loc = response
+        .headers()
+        .get("Operation-Location")
+        .and_then(|h| h.to_str().ok())
+        .ok_or_else(|| AppError::Ocr("No Operation-Location from Azure".to_string()))?
+        .to_string();
+
+    for attempt in 1..=max_attempts {
+        cancellable_sleep(job, poll_interval).await?;
+        let poll_resp =
+            send_with_retry_async(job, || client.get(&op_loc).header("Ocp-Apim-Subscription-Key", &request.key)).await?;
+
+        let poll_status = poll_resp.status();
+        let poll_json: serde_json::Value = poll_resp
+            .json()
+            .await
+            .map_err(|e| AppError::Ocr(format!("Invalid JSON: {}", e)))?;
+
+        match poll_step(poll_status, poll_json)? {
+            PollStep::Done(json) => return Ok(json),
+            PollStep::Pending(azure_status) => progress("polling", attempt, &azure_status),
+        }
+    }
+
+    Err(AppError::Ocr("OCR timed out. Try again.".to_string()))
+}
+
+/// Async `run_ocr_invoice`: same result, but polls without blocking a thread, reports progress per
+/// polling cycle and stops early when `cancel_ocr_job(job.id)` is called.
+pub async fn run_ocr_invoice_async(
+    file_path: &str,
+    document_type: Option<&str>,
+    job: &OcrJob,
+    on_progress: &(dyn Fn(OcrProgress) + Send + Sync),
+) -> Result<OcrInvoiceResult, AppError> {
+    if settings::prefer_local_text_layer() {
+        let path = file_path.to_string();
+        let doc_type = document_type.map(str::to_string);
+        let local = tokio::task::spawn_blocking(move || run_local_text_layer(&path, doc_type.as_deref()))
+            .await
+            .map_err(AppError::ocr)?;
+        if let Some(local) = local {
+            return local;
+        }
+    }
+    if let OcrProvider::Mock = ocr_provider::current() {
+        let path = file_path.to_string();
+        let doc_type = document_type.map(str::to_string);
+        return tokio::task::spawn_blocking(move || mock_ocr::analyze(&path, doc_type.as_deref()))
+            .await
+            .map_err(AppError::ocr)?;
+    }
+    if let OcrProvider::Textract(cfg) = ocr_provider::current() {
+        let cfg = cfg?;
+        let path = file_path.to_string();
+        let doc_type = document_type.map(str::to_string);
+        // One synchronous call, no polling: progress is only the upload step.
+        on_progress(OcrProgress {
+            job_id: job.id.clone(),
+            file_path: file_path.to_string(),
+            stage: "uploading".to_string(),
+            attempt: 0,
+            max_attempts: 1,
+            elapsed_ms: 0,
+            azure_status: String::new(),
+            queue_position: job.queue_position(),
+        });
+        return tokio::task::spawn_blocking(move || textract::analyze_invoice(&cfg, &path, doc_type.as_deref()))
+            .await
+            .map_err(AppError::ocr)?;
+    }
+    let poll_json = fetch_poll_json_async(file_path, document_type, job, on_progress).await?;
+    parse_invoice_poll_json(&poll_json, document_type).map(from_azure)
 }
@@ -0,0 +1,166 @@
+//! OpenDocument Spreadsheet (.ods) export for LibreOffice users: same columns as the xlsx batch
+//! export, written as plain ODF XML so LibreOffice keeps the header style, widths and number format.
+
+use crate::excel::{
+    calculate_export_column_widths, escape_xml_text, export_sheet_name, new_export_path, normalize_amount_string,
+    sanitize_cell, EXPORT_FIELDS, EXPORT_HEADERS,
+};
+use crate::types::InvoiceData;
+use std::fmt::Write as _;
+use std::io::Write;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+const MIMETYPE: &str = "application/vnd.oasis.opendocument.spreadsheet";
+
+const MANIFEST_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<manifest:manifest xmlns:manifest="urn:oasis:names:tc:opendocument:xmlns:manifest:1.0" manifest:version="1.2">
+ <manifest:file-entry manifest:full-path="/" manifest:version="1.2" manifest:media-type="application/vnd.oasis.opendocument.spreadsheet"/>
+ <manifest:file-entry manifest:full-path="content.xml" manifest:media-type="text/xml"/>
+ <manifest:file-entry manifest:full-path="styles.xml" manifest:media-type="text/xml"/>
+ <manifest:file-entry manifest:full-path="meta.xml" manifest:media-type="text/xml"/>
+</manifest:manifest>
+"#;
+
+const STYLES_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<office:document-styles xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0" xmlns:style="urn:oasis:names:tc:opendocument:xmlns:style:1.0" xmlns:fo="urn:oasis:names:tc:opendocument:xmlns:xsl-fo-compatible:1.0" office:version="1.2">
+ <office:styles>
+  <style:default-style style:family="table-cell">
+   <style:text-properties fo:font-size="10pt"/>
+  </style:default-style>
+ </office:styles>
+</office:document-styles>
+"#;
+
+/// Automatic styles: header (bold, white on blue), wrapped text, amounts as #,##0.00.
+const AUTOMATIC_STYLES: &str = r##" <office:automatic-styles>
+  <number:number-style style:name="N_AMOUNT">
+   <number:number number:decimal-places="2" number:min-decimal-places="2" number:min-integer-digits="1" number:grouping="true"/>
+  </number:number-style>
+  <style:style style:name="ce_header" style:family="table-cell">
+   <style:table-cell-properties fo:background-color="#2563eb"/>
+   <style:text-properties fo:font-weight="bold" fo:color="#ffffff"/>
+  </style:style>
+  <style:style style:name="ce_text" style:family="table-cell">
+   <style:table-cell-properties fo:wrap-option="wrap" style:vertical-align="top"/>
+  </style:style>
+  <style:style style:name="ce_amount" style:family="table-cell" style:data-style-name="N_AMOUNT">
+   <style:table-cell-properties style:vertical-align="top"/>
+   <style:paragraph-properties fo:text-align="end"/>
+  </style:style>
+"##;
+
+fn is_amount_column(field_key: &str) -> bool {
+    matches!(field_key, "net_amount" | "tax_amount" | "total_amount")
+}
+
+fn text_cell(out: &mut String, style: &str, value: &str) {
+    let text = escape_xml_text(&sanitize_cell(value));
+    let _ = write!(out, r#"<table:table-cell table:style-name="{}" office:value-type="string">"#, style);
+    // Line breaks become separate paragraphs in ODF.
+    for line in text.split('\n') {
+        let _ = write!(out, "<text:p>{}</text:p>", line.trim_end_matches('\r'));
+    }
+    out.push_str("</table:table-cell>");
+}
+
+fn amount_cell(out: &mut String, value: &str) {
+    match normalize_amount_string(value).parse::<f64>() {
+        Ok(num) => {
+            let _ = write!(
+                out,
+                r#"<table:table-cell table:style-name="ce_amount" office:value-type="float" office:value="{}"><text:p>{:.2}</text:p></table:table-cell>"#,
+                num, num
+            );
+        }
+        Err(_) => text_cell(out, "ce_text", value),
+    }
+}
+
+fn content_xml(invoices: &[InvoiceData], sheet_name: &str) -> String {
+    let mut out = String::with_capacity(4096 + invoices.len() * 1024);
+    out.push_str(r#"<?xml version="1.0" encoding="UTF-8"?>
+<office:document-content xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0" xmlns:style="urn:oasis:names:tc:opendocument:xmlns:style:1.0" xmlns:text="urn:oasis:names:tc:opendocument:xmlns:text:1.0" xmlns:table="urn:oasis:names:tc:opendocument:xmlns:table:1.0" xmlns:fo="urn:oasis:names:tc:opendocument:xmlns:xsl-fo-compatible:1.0" xmlns:number="urn:oasis:names:tc:opendocument:xmlns:datastyle:1.0" office:version="1.2">
+"#);
+    out.push_str(AUTOMATIC_STYLES);
+    // Same widths as the xlsx export (character units, ~0.2 cm each).
+    let widths = calculate_export_column_widths(invoices);
+    for (idx, w) in widths.iter().enumerate() {
+        let _ = writeln!(
+            out,
+            r#"  <style:style style:name="co{}" style:family="table-column"><style:table-column-properties style:column-width="{:.2}cm"/></style:style>"#,
+            idx, w * 0.2
+        );
+    }
+    out.push_str(" </office:automatic-styles>\n <office:body>\n  <office:spreadsheet>\n");
+    let _ = writeln!(out, r#"   <table:table table:name="{}">"#, escape_xml_text(sheet_name));
+    for idx in 0..widths.len() {
+        let _ = writeln!(out, r#"    <table:table-column table:style-name="co{}"/>"#, idx);
+    }
+
+    out.push_str("    <table:table-header-rows><table:table-row>");
+    for header in EXPORT_HEADERS {
+        text_cell(&mut out, "ce_header", header);
+    }
+    out.push_str("</table:table-row></table:table-header-rows>\n");
+
+    for inv in invoices {
+        out.push_str("    <table:table-row>");
+        for &field_key in EXPORT_FIELDS {
+            let value = inv.fields.get(field_key).map(|f| f.value.as_str()).unwrap_or("");
+            if is_amount_column(field_key) {
+                amount_cell(&mut out, value);
+            } else {
+                text_cell(&mut out, "ce_text", value);
+            }
+        }
+        out.push_str("</table:table-row>\n");
+    }
+    out.push_str("   </table:table>\n  </office:spreadsheet>\n </office:body>\n</office:document-content>\n");
+    out
+}
+
+fn meta_xml() -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<office:document-meta xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0" xmlns:meta="urn:oasis:names:tc:opendocument:xmlns:meta:1.0" office:version="1.2">
+ <office:meta>
+  <meta:generator>Document Scanner Desktop</meta:generator>
+  <meta:creation-date>{}</meta:creation-date>
+ </office:meta>
+</office:document-meta>
+"#,
+        chrono::Local::now().format("%Y-%m-%dT%H:%M:%S")
+    )
+}
+
+/// Create a new .ods file with the batch export columns (sibling of `export_invoices_to_new_excel`).
+/// Returns the saved file path.
+pub fn export_invoices_to_ods(
+    invoices: &[InvoiceData],
+    path_override: Option<&str>,
+    worksheet_name: Option<&str>,
+) -> Result<String, String> {
+    let path = new_export_path(path_override, "ods")?;
+    let path_str = path.to_str().ok_or("Invalid path characters.")?.to_string();
+    let sheet_name = export_sheet_name(worksheet_name);
+
+    let file = std::fs::File::create(&path).map_err(|e| format!("Cannot write to file: {}", e))?;
+    let mut zip = ZipWriter::new(file);
+    // ODF requires "mimetype" as the first entry, stored uncompressed.
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let entries: [(&str, SimpleFileOptions, String); 5] = [
+        ("mimetype", stored, MIMETYPE.to_string()),
+        ("META-INF/manifest.xml", deflated, MANIFEST_XML.to_string()),
+        ("styles.xml", deflated, STYLES_XML.to_string()),
+        ("meta.xml", deflated, meta_xml()),
+        ("content.xml", deflated, content_xml(invoices, sheet_name)),
+    ];
+    for (name, options, body) in entries {
+        zip.start_file(name, options).map_err(|e| e.to_string())?;
+        zip.write_all(body.as_bytes()).map_err(|e| e.to_string())?;
+    }
+    zip.finish().map_err(|e| e.to_string())?;
+    Ok(path_str)
+}
@@ -0,0 +1,259 @@
+//! Export/import of the complete app state (database, .env settings, secrets, templates and optionally
+//! archived documents) as a single AES-256 encrypted zip, for machine replacement and new workstations.
+//! Secrets are stored decrypted inside the encrypted zip and protected again for the importing machine.
+//! The zip password is either chosen by the user or, for bundles sent to recipients, a random key
+//! wrapped for each recipient's public key in an unencrypted `recipients.json` entry.
+
+use crate::services::bundle_keys::WrappedKey;
+use crate::services::settings::PortableSecrets;
+use crate::services::{archive_store, export_delivery, llm_extraction, ocr_provider, secrets};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use zip::write::SimpleFileOptions;
+use zip::{AesMode, ZipArchive, ZipWriter};
+
+const MANIFEST_NAME: &str = "manifest.json";
+const RECIPIENTS_ENTRY: &str = "recipients.json";
+const DB_ENTRY: &str = "invoice_scanner.db";
+const ENV_ENTRY: &str = ".env";
+const SECRETS_ENTRY: &str = "secrets.json";
+/// App-data subfolders copied as-is. Archived documents are only included on request.
+const TEMPLATES_DIR: &str = "templates";
+const DOCUMENTS_DIR: &str = crate::services::archive_store::LOCAL_ARCHIVE_DIR;
+const FORMAT_VERSION: u32 = 1;
+const MIN_PASSWORD_LEN: usize = 8;
+/// Settings key: time of the last successful export (the month-end checklist's backup step).
+pub const LAST_EXPORT_KEY: &str = "last_app_state_export";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AppStateManifest {
+    pub format_version: u32,
+    pub app_version: String,
+    pub created_at: String,
+    /// App data folder on the exporting machine; used to rewrite absolute profile paths on import.
+    pub source_app_data_dir: String,
+    pub includes_documents: bool,
+    pub files: Vec<String>,
+    /// Settings keys and credential names whose secret is not in the archive, or could not be protected
+    /// on import; the user has to enter them again.
+    #[serde(default)]
+    pub reenter_credentials: Vec<String>,
+}
+
+/// Credential store entries carried in the archive. The bundle identity stays with the machine: a new
+/// workstation generates its own.
+pub fn credential_names(profile_ids: &[i64]) -> Vec<String> {
+    let mut names: Vec<String> = [
+        secrets::AZURE_OCR_KEY,
+        ocr_provider::TEXTRACT_SECRET_NAME,
+        llm_extraction::API_KEY_NAME,
+        archive_store::S3_SECRET_NAME,
+    ]
+    .iter()
+    .map(|n| n.to_string())
+    .collect();
+    for &id in profile_ids {
+        names.push(export_delivery::private_key_secret(id));
+        names.push(export_delivery::passphrase_secret(id));
+    }
+    names
+}
+
+fn check_password(password: &str) -> Result<(), String> {
+    if password.chars().count() < MIN_PASSWORD_LEN {
+        return Err(format!("Password must be at least {} characters.", MIN_PASSWORD_LEN));
+    }
+    Ok(())
+}
+
+/// Relative paths (with '/' separators) of all files under `dir`, prefixed with `prefix`.
+fn collect_files(dir: &Path, prefix: &str, out: &mut Vec<(String, PathBuf)>) -> Result<(), String> {
+    if !dir.is_dir() {
+        return Ok(());
+    }
+    for entry in fs::read_dir(dir).map_err(|e| format!("Could not read {}: {}", dir.display(), e))? {
+        let entry = entry.map_err(|e| e.to_string())?;
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        let rel = format!("{}/{}", prefix, name);
+        if path.is_dir() {
+            collect_files(&path, &rel, out)?;
+        } else {
+            out.push((rel, path));
+        }
+    }
+    Ok(())
+}
+
+/// Write the archive. `db_snapshot` must be a consistent copy of the database (see `Db::snapshot_to`).
+/// `wrapped_keys` holds `password` wrapped for recipients (empty for a password chosen by the user).
+#[allow(clippy::too_many_arguments)]
+pub fn write_archive(
+    archive_path: &Path,
+    password: &str,
+    wrapped_keys: &[WrappedKey],
+    app_data_dir: &Path,
+    db_snapshot: &Path,
+    app_version: &str,
+    include_documents: bool,
+    secrets: &PortableSecrets,
+) -> Result<AppStateManifest, String> {
+    check_password(password)?;
+    let mut files: Vec<(String, PathBuf)> = vec![(DB_ENTRY.to_string(), db_snapshot.to_path_buf())];
+    let env_path = app_data_dir.join(ENV_ENTRY);
+    if env_path.is_file() {
+        files.push((ENV_ENTRY.to_string(), env_path));
+    }
+    collect_files(&app_data_dir.join(TEMPLATES_DIR), TEMPLATES_DIR, &mut files)?;
+    if include_documents {
+        collect_files(&app_data_dir.join(DOCUMENTS_DIR), DOCUMENTS_DIR, &mut files)?;
+    }
+
+    let manifest = AppStateManifest {
+        format_version: FORMAT_VERSION,
+        app_version: app_version.to_string(),
+        created_at: chrono::Utc::now().to_rfc3339(),
+        source_app_data_dir: app_data_dir.to_string_lossy().to_string(),
+        includes_documents: include_documents,
+        files: files.iter().map(|(name, _)| name.clone()).collect(),
+        reenter_credentials: secrets.unreadable.clone(),
+    };
+    let manifest_json = serde_json::to_vec_pretty(&manifest).map_err(|e| e.to_string())?;
+    let secrets_json = serde_json::to_vec(secrets).map_err(|e| e.to_string())?;
+
+    let file = fs::File::create(archive_path).map_err(|e| format!("Could not create {}: {}", archive_path.display(), e))?;
+    let mut zip = ZipWriter::new(file);
+    if !wrapped_keys.is_empty() {
+        let recipients_json = serde_json::to_vec_pretty(wrapped_keys).map_err(|e| e.to_string())?;
+        zip.start_file(RECIPIENTS_ENTRY, SimpleFileOptions::default()).map_err(|e| e.to_string())?;
+        zip.write_all(&recipients_json).map_err(|e| e.to_string())?;
+    }
+    let options = SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated)
+        .large_file(true)
+        .with_aes_encryption(AesMode::Aes256, password);
+    zip.start_file(MANIFEST_NAME, options).map_err(|e| e.to_string())?;
+    zip.write_all(&manifest_json).map_err(|e| e.to_string())?;
+    zip.start_file(SECRETS_ENTRY, options).map_err(|e| e.to_string())?;
+    zip.write_all(&secrets_json).map_err(|e| e.to_string())?;
+    for (name, path) in &files {
+        zip.start_file(name.as_str(), options).map_err(|e| e.to_string())?;
+        let mut src = fs::File::open(path).map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        std::io::copy(&mut src, &mut zip).map_err(|e| e.to_string())?;
+    }
+    zip.finish().map_err(|e| e.to_string())?;
+    Ok(manifest)
+}
+
+fn read_entry(archive: &mut ZipArchive<fs::File>, name: &str, password: &str) -> Result<Vec<u8>, String> {
+    let mut entry = archive.by_name_decrypt(name, password.as_bytes()).map_err(|e| match e {
+        zip::result::ZipError::InvalidPassword => "Wrong password for this archive.".to_string(),
+        zip::result::ZipError::FileNotFound => format!("Archive is missing {}", name),
+        other => format!("Could not read {} from archive: {}", name, other),
+    })?;
+    let mut buf = Vec::new();
+    entry.read_to_end(&mut buf).map_err(|e| format!("Could not read {} from archive: {}", name, e))?;
+    Ok(buf)
+}
+
+/// Keys wrapped for recipients; empty for an archive protected by a password only.
+pub fn read_wrapped_keys(archive_path: &Path) -> Result<Vec<WrappedKey>, String> {
+    let file = fs::File::open(archive_path).map_err(|e| format!("Could not open {}: {}", archive_path.display(), e))?;
+    let mut archive = ZipArchive::new(file).map_err(|e| format!("Not a valid app state archive: {}", e))?;
+    let Ok(mut entry) = archive.by_name(RECIPIENTS_ENTRY) else {
+        return Ok(Vec::new());
+    };
+    let mut buf = Vec::new();
+    entry.read_to_end(&mut buf).map_err(|e| format!("Could not read {} from archive: {}", RECIPIENTS_ENTRY, e))?;
+    serde_json::from_slice(&buf).map_err(|e| format!("Invalid {} in archive: {}", RECIPIENTS_ENTRY, e))
+}
+
+/// Open the archive and return its manifest; fails on a wrong password before anything is changed.
+pub fn read_manifest(archive_path: &Path, password: &str) -> Result<AppStateManifest, String> {
+    let file = fs::File::open(archive_path).map_err(|e| format!("Could not open {}: {}", archive_path.display(), e))?;
+    let mut archive = ZipArchive::new(file).map_err(|e| format!("Not a valid app state archive: {}", e))?;
+    let bytes = read_entry(&mut archive, MANIFEST_NAME, password)?;
+    let manifest: AppStateManifest =
+        serde_json::from_slice(&bytes).map_err(|e| format!("Invalid archive manifest: {}", e))?;
+    if manifest.format_version > FORMAT_VERSION {
+        return Err("This archive was created by a newer version of the app. Update before importing.".to_string());
+    }
+    Ok(manifest)
+}
+
+/// Secrets stored in the archive; none for an archive written before they were included.
+pub fn read_secrets(archive_path: &Path, password: &str) -> Result<PortableSecrets, String> {
+    let file = fs::File::open(archive_path).map_err(|e| format!("Could not open {}: {}", archive_path.display(), e))?;
+    let mut archive = ZipArchive::new(file).map_err(|e| format!("Not a valid app state archive: {}", e))?;
+    if archive.index_for_name(SECRETS_ENTRY).is_none() {
+        return Ok(PortableSecrets::default());
+    }
+    let bytes = read_entry(&mut archive, SECRETS_ENTRY, password)?;
+    serde_json::from_slice(&bytes).map_err(|e| format!("Invalid {} in archive: {}", SECRETS_ENTRY, e))
+}
+
+/// Reject entry names that would escape the app data folder.
+fn safe_relative_path(name: &str) -> Option<PathBuf> {
+    let path = Path::new(name);
+    path.components()
+        .all(|c| matches!(c, std::path::Component::Normal(_)))
+        .then(|| path.to_path_buf())
+}
+
+/// Extract every file listed in the manifest into `app_data_dir`. The database is written to
+/// `db_path`; the caller must have closed the current connection and moved the old file aside.
+pub fn extract_archive(
+    archive_path: &Path,
+    password: &str,
+    manifest: &AppStateManifest,
+    app_data_dir: &Path,
+    db_path: &Path,
+) -> Result<(), String> {
+    let file = fs::File::open(archive_path).map_err(|e| format!("Could not open {}: {}", archive_path.display(), e))?;
+    let mut archive = ZipArchive::new(file).map_err(|e| format!("Not a valid app state archive: {}", e))?;
+    for name in &manifest.files {
+        let target = if name == DB_ENTRY {
+            db_path.to_path_buf()
+        } else {
+            let rel = safe_relative_path(name).ok_or_else(|| format!("Unsafe path in archive: {}", name))?;
+            app_data_dir.join(rel)
+        };
+        let bytes = read_entry(&mut archive, name, password)?;
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        fs::write(&target, bytes).map_err(|e| format!("Could not write {}: {}", target.display(), e))?;
+    }
+    Ok(())
+}
+
+/// Move the current database to `backups/pre_import_<timestamp>.db` before an import replaces it.
+pub fn move_current_db_aside(db_path: &Path) -> Result<Option<PathBuf>, String> {
+    if !db_path.exists() {
+        return Ok(None);
+    }
+    let backups_dir = db_path
+        .parent()
+        .ok_or("Database path has no parent directory")?
+        .join("backups");
+    fs::create_dir_all(&backups_dir).map_err(|e| format!("Could not create backups folder: {}", e))?;
+    let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S");
+    let target = backups_dir.join(format!("pre_import_{}.db", timestamp));
+    fs::rename(db_path, &target).map_err(|e| format!("Could not move current database aside: {}", e))?;
+    Ok(Some(target))
+}
+
+/// Undo `move_current_db_aside` after a failed import: drop whatever was written to `db_path` and put the
+/// previous database back.
+pub fn restore_db_moved_aside(db_path: &Path, previous: Option<&Path>) -> Result<(), String> {
+    if db_path.exists() {
+        fs::remove_file(db_path).map_err(|e| format!("Could not remove the partly imported database: {}", e))?;
+    }
+    if let Some(previous) = previous {
+        fs::rename(previous, db_path).map_err(|e| format!("Could not put the previous database back: {}", e))?;
+    }
+    Ok(())
+}
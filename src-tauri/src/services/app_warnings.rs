@@ -0,0 +1,53 @@
+//! Failures of side work that must not fail the command that triggered it: audit log writes, stored
+//! secrets that cannot be decrypted, webhook deliveries, background polls. Release builds have no console,
+//! so each one is kept for the frontend and announced with an `app-warning` event; the frontend takes
+//! the kept warnings on startup and on every event, so none raised before the window listens are lost.
+
+use serde::Serialize;
+use std::sync::{Mutex, OnceLock};
+use tauri::{AppHandle, Emitter};
+
+const EVENT: &str = "app-warning";
+/// Oldest warnings are dropped beyond this many untaken ones.
+const MAX_PENDING: usize = 100;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AppWarning {
+    /// Area that failed, e.g. "write_log" or "webhooks".
+    pub source: String,
+    pub message: String,
+    pub created_at: String,
+}
+
+static APP: OnceLock<AppHandle> = OnceLock::new();
+static PENDING: Mutex<Vec<AppWarning>> = Mutex::new(Vec::new());
+
+/// Called once at startup so warnings can be emitted.
+pub fn init(app: AppHandle) {
+    let _ = APP.set(app);
+}
+
+/// Keep a warning for the frontend and emit it.
+pub fn report(source: &str, message: impl Into<String>) {
+    let warning = AppWarning {
+        source: source.to_string(),
+        message: message.into(),
+        created_at: chrono::Utc::now().to_rfc3339(),
+    };
+    {
+        let mut pending = PENDING.lock().unwrap_or_else(|e| e.into_inner());
+        if pending.len() >= MAX_PENDING {
+            pending.remove(0);
+        }
+        pending.push(warning.clone());
+    }
+    if let Some(app) = APP.get() {
+        let _ = app.emit(EVENT, &warning);
+    }
+}
+
+/// Warnings not taken yet, oldest first; they are cleared.
+pub fn take() -> Vec<AppWarning> {
+    std::mem::take(&mut *PENDING.lock().unwrap_or_else(|e| e.into_inner()))
+}
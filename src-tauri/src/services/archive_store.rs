@@ -0,0 +1,496 @@
+//! Where copies of scanned originals are kept: local app data, a network share, or S3-compatible
+//! object storage, chosen in settings. Each archived copy is recorded per history record together with
+//! the backend it went to, so retrieval by history_id keeps working after the setting changes.
+//! Originals are stored by content (`YYYY/MM/<sha256>.<ext>`); the hash and the archive path are also
+//! kept on the history record, and the same document scanned twice is stored once.
+
+use crate::db::{ArchivedObjectRow, Db};
+use crate::services::{app_warnings, document_format};
+use crate::services::secrets;
+use crate::types::SkippedDuplicate;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Local archive folder under app data (also included in app state exports).
+pub const LOCAL_ARCHIVE_DIR: &str = "archive";
+/// Downloaded copies of remote originals (re-derivable, trimmed by storage quotas).
+pub const ARCHIVE_CACHE_DIR: &str = "archive_cache";
+
+const BACKEND: &str = "archive_backend";
+const SHARE_PATH: &str = "archive_share_path";
+const S3_ENDPOINT: &str = "archive_s3_endpoint";
+const S3_REGION: &str = "archive_s3_region";
+const S3_BUCKET: &str = "archive_s3_bucket";
+const S3_ACCESS_KEY_ID: &str = "archive_s3_access_key_id";
+/// Credential-store name of the S3 secret access key.
+pub(crate) const S3_SECRET_NAME: &str = "archive_s3_secret_access_key";
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchiveSettings {
+    /// "local", "share" or "s3"
+    pub backend: String,
+    pub share_path: Option<String>,
+    pub s3_endpoint: Option<String>,
+    pub s3_region: Option<String>,
+    pub s3_bucket: Option<String>,
+    pub s3_access_key_id: Option<String>,
+    pub s3_secret_configured: bool,
+}
+
+/// None leaves a value unchanged, an empty string clears it.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchiveSettingsUpdate {
+    pub backend: Option<String>,
+    pub share_path: Option<String>,
+    pub s3_endpoint: Option<String>,
+    pub s3_region: Option<String>,
+    pub s3_bucket: Option<String>,
+    pub s3_access_key_id: Option<String>,
+    pub s3_secret_access_key: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchivedDocument {
+    pub history_id: i64,
+    pub backend: String,
+    /// Root folder or bucket.
+    pub location: String,
+    pub object_key: String,
+    pub file_name: String,
+    pub size_bytes: u64,
+    pub created_at: String,
+    /// SHA-256 of the original; None for copies archived before content hashing.
+    pub sha256: Option<String>,
+    /// Full path of the copy (s3://bucket/key for object storage).
+    pub archive_path: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct S3Config {
+    /// e.g. https://s3.eu-central-1.amazonaws.com or a MinIO URL; requests use path-style addressing.
+    pub endpoint: String,
+    pub region: String,
+    pub bucket: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+}
+
+#[derive(Debug, Clone)]
+pub enum ArchiveBackend {
+    Local(PathBuf),
+    Share(PathBuf),
+    S3(S3Config),
+}
+
+fn non_empty(value: Option<&String>) -> Option<String> {
+    value.map(|v| v.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+fn s3_config(stored: &HashMap<String, String>, bucket: Option<String>) -> Result<S3Config, String> {
+    let region = non_empty(stored.get(S3_REGION)).ok_or("S3 region is not configured.")?;
+    let bucket = bucket
+        .or_else(|| non_empty(stored.get(S3_BUCKET)))
+        .ok_or("S3 bucket is not configured.")?;
+    let access_key_id = non_empty(stored.get(S3_ACCESS_KEY_ID)).ok_or("S3 access key is not configured.")?;
+    let secret_access_key = secrets::get_secret(S3_SECRET_NAME)?.ok_or("S3 secret key is not configured.")?;
+    let endpoint = non_empty(stored.get(S3_ENDPOINT))
+        .unwrap_or_else(|| format!("https://s3.{}.amazonaws.com", region))
+        .trim_end_matches('/')
+        .to_string();
+    Ok(S3Config {
+        endpoint,
+        region,
+        bucket,
+        access_key_id,
+        secret_access_key,
+    })
+}
+
+/// Backend selected in settings.
+pub fn configured_backend(db: &Db, app_data_dir: &Path) -> Result<ArchiveBackend, String> {
+    let stored = db.get_settings()?;
+    match non_empty(stored.get(BACKEND)).as_deref().unwrap_or("local") {
+        "share" => {
+            let path = non_empty(stored.get(SHARE_PATH)).ok_or("Network share path is not configured.")?;
+            Ok(ArchiveBackend::Share(PathBuf::from(path)))
+        }
+        "s3" => Ok(ArchiveBackend::S3(s3_config(&stored, None)?)),
+        _ => Ok(ArchiveBackend::Local(app_data_dir.join(LOCAL_ARCHIVE_DIR))),
+    }
+}
+
+pub fn get_archive_settings(db: &Db) -> Result<ArchiveSettings, String> {
+    let stored = db.get_settings()?;
+    Ok(ArchiveSettings {
+        backend: non_empty(stored.get(BACKEND)).unwrap_or_else(|| "local".to_string()),
+        share_path: non_empty(stored.get(SHARE_PATH)),
+        s3_endpoint: non_empty(stored.get(S3_ENDPOINT)),
+        s3_region: non_empty(stored.get(S3_REGION)),
+        s3_bucket: non_empty(stored.get(S3_BUCKET)),
+        s3_access_key_id: non_empty(stored.get(S3_ACCESS_KEY_ID)),
+        s3_secret_configured: secrets::get_secret(S3_SECRET_NAME).ok().flatten().is_some(),
+    })
+}
+
+/// Store archive settings; the S3 secret goes to the OS credential store.
+pub fn save_archive_settings(db: &Db, update: ArchiveSettingsUpdate) -> Result<ArchiveSettings, String> {
+    let cleared = |v: Option<String>| v.map(|v| Some(v.trim().to_string()).filter(|v| !v.is_empty()));
+    let mut entries: Vec<(&str, Option<String>)> = Vec::new();
+    if let Some(backend) = cleared(update.backend) {
+        if let Some(b) = &backend {
+            if !["local", "share", "s3"].contains(&b.as_str()) {
+                return Err(format!("Unknown archive backend: {}", b));
+            }
+        }
+        entries.push((BACKEND, backend));
+    }
+    if let Some(share) = cleared(update.share_path) {
+        if let Some(p) = &share {
+            if !Path::new(p).is_dir() {
+                return Err(format!("Network share is not reachable: {}", p));
+            }
+        }
+        entries.push((SHARE_PATH, share));
+    }
+    for (key, value) in [
+        (S3_ENDPOINT, update.s3_endpoint),
+        (S3_REGION, update.s3_region),
+        (S3_BUCKET, update.s3_bucket),
+        (S3_ACCESS_KEY_ID, update.s3_access_key_id),
+    ] {
+        if let Some(value) = cleared(value) {
+            entries.push((key, value));
+        }
+    }
+    if let Some(secret) = cleared(update.s3_secret_access_key) {
+        match secret {
+            Some(secret) => secrets::set_secret(S3_SECRET_NAME, &secret)?,
+            None => secrets::delete_secret(S3_SECRET_NAME)?,
+        }
+    }
+    db.set_settings(&entries)?;
+    get_archive_settings(db)
+}
+
+/// `YYYY/MM/<sha256>.<extension>`
+fn object_key(sha256: &str, extension: &str) -> String {
+    format!("{}/{}.{}", chrono::Local::now().format("%Y/%m"), sha256, extension)
+}
+
+/// Hex SHA-256 of the file's content.
+pub fn file_sha256(path: &Path) -> Result<String, String> {
+    let mut file = fs::File::open(path).map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher).map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+    Ok(hex(&hasher.finalize()))
+}
+
+impl ArchiveBackend {
+    fn kind(&self) -> &'static str {
+        match self {
+            ArchiveBackend::Local(_) => "local",
+            ArchiveBackend::Share(_) => "share",
+            ArchiveBackend::S3(_) => "s3",
+        }
+    }
+
+    fn location(&self) -> String {
+        match self {
+            ArchiveBackend::Local(root) | ArchiveBackend::Share(root) => root.to_string_lossy().to_string(),
+            ArchiveBackend::S3(cfg) => cfg.bucket.clone(),
+        }
+    }
+
+    /// Where `key` lives, as shown to the user.
+    fn path_of(&self, key: &str) -> String {
+        match self {
+            ArchiveBackend::Local(root) | ArchiveBackend::Share(root) => root.join(key).to_string_lossy().to_string(),
+            ArchiveBackend::S3(cfg) => format!("s3://{}/{}", cfg.bucket, key),
+        }
+    }
+
+    fn put(&self, key: &str, src: &Path) -> Result<(), String> {
+        match self {
+            ArchiveBackend::Local(root) | ArchiveBackend::Share(root) => {
+                let dest = root.join(key);
+                if dest.is_file() {
+                    return Ok(());
+                }
+                if let Some(parent) = dest.parent() {
+                    fs::create_dir_all(parent).map_err(|e| format!("Could not create {}: {}", parent.display(), e))?;
+                }
+                fs::copy(src, &dest).map_err(|e| format!("Could not copy to {}: {}", dest.display(), e))?;
+                Ok(())
+            }
+            ArchiveBackend::S3(cfg) => {
+                let body = fs::read(src).map_err(|e| format!("Could not read {}: {}", src.display(), e))?;
+                s3_request(cfg, reqwest::Method::PUT, key, body).map(|_| ())
+            }
+        }
+    }
+
+    /// Remove the object, and its download in `cache_dir` for S3. A missing object is not an error.
+    fn delete(&self, key: &str, cache_dir: &Path) -> Result<(), String> {
+        let path = match self {
+            ArchiveBackend::Local(root) | ArchiveBackend::Share(root) => root.join(key),
+            ArchiveBackend::S3(cfg) => {
+                s3_request(cfg, reqwest::Method::DELETE, key, Vec::new())?;
+                cache_dir.join(&cfg.bucket).join(key)
+            }
+        };
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(format!("Could not delete {}: {}", path.display(), e))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Local path of the object: in place for disk backends, downloaded into `cache_dir` for S3.
+    fn local_copy(&self, key: &str, cache_dir: &Path) -> Result<PathBuf, String> {
+        match self {
+            ArchiveBackend::Local(root) | ArchiveBackend::Share(root) => {
+                let path = root.join(key);
+                if !path.is_file() {
+                    return Err(format!("Archived file not found: {}", path.display()));
+                }
+                Ok(path)
+            }
+            ArchiveBackend::S3(cfg) => {
+                let path = cache_dir.join(&cfg.bucket).join(key);
+                if path.is_file() {
+                    return Ok(path);
+                }
+                let bytes = s3_request(cfg, reqwest::Method::GET, key, Vec::new())?;
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+                }
+                fs::write(&path, bytes).map_err(|e| format!("Could not write {}: {}", path.display(), e))?;
+                Ok(path)
+            }
+        }
+    }
+}
+
+/// Copy `file_path` to the configured backend and record it for `history_id`. A document whose content
+/// is already archived in the same place is linked to the existing copy instead of stored again.
+pub fn archive_document(db: &Db, app_data_dir: &Path, history_id: i64, file_path: &str) -> Result<ArchivedDocument, String> {
+    let src = Path::new(file_path);
+    let size_bytes = fs::metadata(src)
+        .map_err(|e| format!("Could not read {}: {}", src.display(), e))?
+        .len();
+    let file_name = src
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .ok_or("Invalid file path.")?;
+    let extension = match document_format::detect_file_format(src)? {
+        Some(format) => format.extension().to_string(),
+        None => src
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .filter(|e| !e.is_empty() && e.chars().all(|c| c.is_ascii_alphanumeric()))
+            .unwrap_or_else(|| "bin".to_string()),
+    };
+    let sha256 = file_sha256(src)?;
+    let backend = configured_backend(db, app_data_dir)?;
+    let location = backend.location();
+    let key = match db.find_archived_object(&sha256, backend.kind(), &location)? {
+        Some(key) => key,
+        None => {
+            let key = object_key(&sha256, &extension);
+            backend.put(&key, src)?;
+            key
+        }
+    };
+    let archive_path = backend.path_of(&key);
+    db.add_archived_document(history_id, backend.kind(), &location, &key, &file_name, size_bytes)?;
+    db.set_history_archive(history_id, &sha256, &archive_path)?;
+    Ok(ArchivedDocument {
+        history_id,
+        backend: backend.kind().to_string(),
+        location,
+        object_key: key,
+        file_name,
+        size_bytes,
+        created_at: chrono::Utc::now().to_rfc3339(),
+        sha256: Some(sha256),
+        archive_path: Some(archive_path),
+    })
+}
+
+/// Archive the scanned original of a new history record; only scanned documents (PDF or image) that
+/// still exist are archived.
+pub fn archive_scanned_original(db: &Db, app_data_dir: &Path, history_id: i64, file_path: &str) -> Result<Option<ArchivedDocument>, String> {
+    let src = Path::new(file_path);
+    if !src.is_file() || document_format::detect_file_format(src)?.is_none() {
+        return Ok(None);
+    }
+    archive_document(db, app_data_dir, history_id, file_path).map(Some)
+}
+
+/// Backend an object was stored in, from the backend and location recorded with it.
+fn recorded_backend(db: &Db, backend: &str, location: &str) -> Result<ArchiveBackend, String> {
+    match backend {
+        "local" => Ok(ArchiveBackend::Local(PathBuf::from(location))),
+        "share" => Ok(ArchiveBackend::Share(PathBuf::from(location))),
+        "s3" => Ok(ArchiveBackend::S3(s3_config(&db.get_settings()?, Some(location.to_string()))?)),
+        other => Err(format!("Unknown archive backend: {}", other)),
+    }
+}
+
+/// Local path of the archived original of `history_id`, wherever it was stored.
+pub fn archived_document_path(db: &Db, app_data_dir: &Path, history_id: i64) -> Result<PathBuf, String> {
+    let (backend, location, key, ..) = db
+        .get_archived_document(history_id)?
+        .ok_or("No archived original for this document.")?;
+    recorded_backend(db, &backend, &location)?.local_copy(&key, &app_data_dir.join(ARCHIVE_CACHE_DIR))
+}
+
+/// Delete archived objects of purged records (see `Db::purge_trash`). Failures are shown as app warnings, not returned:
+/// the records are already gone.
+pub fn delete_archived_objects(db: &Db, app_data_dir: &Path, objects: &[ArchivedObjectRow]) {
+    for (backend, location, key) in objects {
+        let deleted = recorded_backend(db, backend, location)
+            .and_then(|b| b.delete(key, &app_data_dir.join(ARCHIVE_CACHE_DIR)));
+        if let Err(e) = deleted {
+            app_warnings::report("archive_store", format!("could not delete archived {}: {}", key, e));
+        }
+    }
+}
+
+/// Files of `paths` whose content is already in history (by the hash kept when originals are archived) or
+/// repeats an earlier path. Files that cannot be read are left to the scan to report.
+pub fn find_duplicates(db: &Db, paths: &[String]) -> Result<Vec<SkippedDuplicate>, String> {
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut duplicates = Vec::new();
+    for path in paths {
+        let Ok(sha256) = file_sha256(Path::new(path)) else {
+            continue;
+        };
+        let file_name = Path::new(path).file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        let earlier = if seen.contains(&sha256) {
+            Some((None, None))
+        } else {
+            db.find_history_by_sha256(&sha256)?.map(|(id, created_at)| (Some(id), Some(created_at)))
+        };
+        match earlier {
+            Some((history_id, scanned_at)) => {
+                duplicates.push(SkippedDuplicate { file_path: path.clone(), file_name, sha256, history_id, scanned_at })
+            }
+            None => {
+                seen.insert(sha256);
+            }
+        }
+    }
+    Ok(duplicates)
+}
+
+pub fn get_archived_document(db: &Db, history_id: i64) -> Result<Option<ArchivedDocument>, String> {
+    Ok(db.get_archived_document(history_id)?.map(
+        |(backend, location, object_key, file_name, size_bytes, created_at, sha256, archive_path)| ArchivedDocument {
+            history_id,
+            backend,
+            location,
+            object_key,
+            file_name,
+            size_bytes,
+            created_at,
+            sha256,
+            archive_path,
+        },
+    ))
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub(crate) fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// AWS Signature V4 signing key for one day, region and service (shared with the Textract client).
+pub(crate) fn sigv4_signing_key(secret_access_key: &str, date: &str, region: &str, service: &str) -> Vec<u8> {
+    let k_date = hmac_sha256(format!("AWS4{}", secret_access_key).as_bytes(), date);
+    let k_region = hmac_sha256(&k_date, region);
+    let k_service = hmac_sha256(&k_region, service);
+    hmac_sha256(&k_service, "aws4_request")
+}
+
+/// RFC 3986 encoding of each path segment, as required by SigV4.
+fn uri_encode_path(path: &str) -> String {
+    let mut out = String::with_capacity(path.len());
+    for b in path.bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~/".contains(&b) {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+    out
+}
+
+/// Signed (AWS Signature V4) path-style request; returns the response body.
+fn s3_request(cfg: &S3Config, method: reqwest::Method, key: &str, body: Vec<u8>) -> Result<Vec<u8>, String> {
+    let url = reqwest::Url::parse(&cfg.endpoint).map_err(|e| format!("Invalid S3 endpoint: {}", e))?;
+    let host = match (url.host_str(), url.port()) {
+        (Some(h), Some(p)) => format!("{}:{}", h, p),
+        (Some(h), None) => h.to_string(),
+        _ => return Err("Invalid S3 endpoint.".to_string()),
+    };
+    let canonical_uri = uri_encode_path(&format!("/{}/{}", cfg.bucket, key));
+    let now = chrono::Utc::now();
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let date = now.format("%Y%m%d").to_string();
+    let payload_hash = hex(&Sha256::digest(&body));
+    let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+    let canonical_request = format!(
+        "{}\n{}\n\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+        method, canonical_uri, host, payload_hash, amz_date, signed_headers, payload_hash
+    );
+    let scope = format!("{}/{}/s3/aws4_request", date, cfg.region);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope,
+        hex(&Sha256::digest(canonical_request.as_bytes()))
+    );
+    let k_signing = sigv4_signing_key(&cfg.secret_access_key, &date, &cfg.region, "s3");
+    let signature = hex(&hmac_sha256(&k_signing, &string_to_sign));
+    let authorization = format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        cfg.access_key_id, scope, signed_headers, signature
+    );
+
+    let client = reqwest::blocking::Client::builder()
+        .timeout(std::time::Duration::from_secs(300))
+        .build()
+        .map_err(|e| e.to_string())?;
+    let response = client
+        .request(method, format!("{}{}", cfg.endpoint, canonical_uri))
+        .header("x-amz-date", &amz_date)
+        .header("x-amz-content-sha256", &payload_hash)
+        .header("Authorization", authorization)
+        .body(body)
+        .send()
+        .map_err(|e| format!("Could not reach object storage: {}", e))?;
+    let status = response.status();
+    let bytes = response.bytes().map_err(|e| e.to_string())?.to_vec();
+    if !status.is_success() {
+        return Err(format!(
+            "Object storage returned {}: {}",
+            status,
+            String::from_utf8_lossy(&bytes).chars().take(300).collect::<String>()
+        ));
+    }
+    Ok(bytes)
+}
@@ -0,0 +1,183 @@
+//! Recipient keys for exported bundles, in the style of age: each installation can generate an X25519
+//! key pair and import the public keys of the people it sends bundles to (e.g. the tax advisor). A
+//! bundle encrypted to recipients gets a random key, which is wrapped for each recipient with an
+//! ephemeral X25519 exchange, HKDF-SHA256 and ChaCha20-Poly1305. Only a holder of a matching private
+//! key can open it, so an intercepted e-mail attachment is useless. The private key is kept in the OS
+//! credential store; imported recipients are kept in settings.
+
+use crate::db::Db;
+use crate::services::secrets;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD as B64, Engine};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Nonce};
+use hkdf::Hkdf;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use x25519_dalek::{EphemeralSecret, PublicKey, StaticSecret};
+
+/// Prefix of a shareable public key.
+pub const PUBLIC_KEY_PREFIX: &str = "dspub1";
+/// Credential name of this installation's private key.
+const IDENTITY_SECRET: &str = "bundle_identity";
+/// Settings key: JSON array of imported recipients.
+const RECIPIENTS_KEY: &str = "bundle_recipients";
+const HKDF_INFO: &[u8] = b"document-scanner bundle key v1";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleRecipient {
+    pub label: String,
+    pub public_key: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleKeys {
+    /// This installation's public key to hand out; None until one is generated.
+    pub public_key: Option<String>,
+    pub recipients: Vec<BundleRecipient>,
+}
+
+/// The bundle key wrapped for one recipient.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WrappedKey {
+    pub recipient: String,
+    pub ephemeral: String,
+    pub ciphertext: String,
+}
+
+fn encode_public(key: &PublicKey) -> String {
+    format!("{}{}", PUBLIC_KEY_PREFIX, B64.encode(key.as_bytes()))
+}
+
+fn decode_public(value: &str) -> Result<PublicKey, String> {
+    let invalid = || format!("Not a valid public key: {}", value);
+    let bytes = B64.decode(value.trim().strip_prefix(PUBLIC_KEY_PREFIX).ok_or_else(invalid)?).map_err(|_| invalid())?;
+    let bytes: [u8; 32] = bytes.try_into().map_err(|_| invalid())?;
+    Ok(PublicKey::from(bytes))
+}
+
+fn identity() -> Result<Option<StaticSecret>, String> {
+    let Some(stored) = secrets::get_secret(IDENTITY_SECRET)? else {
+        return Ok(None);
+    };
+    let bytes: [u8; 32] = B64
+        .decode(stored.trim())
+        .ok()
+        .and_then(|b| b.try_into().ok())
+        .ok_or("The stored bundle key is damaged; generate a new one.")?;
+    Ok(Some(StaticSecret::from(bytes)))
+}
+
+fn recipients(db: &Db) -> Result<Vec<BundleRecipient>, String> {
+    Ok(db
+        .get_settings()?
+        .get(RECIPIENTS_KEY)
+        .and_then(|v| serde_json::from_str(v).ok())
+        .unwrap_or_default())
+}
+
+fn save_recipients(db: &Db, list: &[BundleRecipient]) -> Result<(), String> {
+    let value = (!list.is_empty()).then(|| serde_json::to_string(list)).transpose().map_err(|e| e.to_string())?;
+    Ok(db.set_settings(&[(RECIPIENTS_KEY, value)])?)
+}
+
+pub fn get_keys(db: &Db) -> Result<BundleKeys, String> {
+    Ok(BundleKeys {
+        public_key: identity()?.map(|secret| encode_public(&PublicKey::from(&secret))),
+        recipients: recipients(db)?,
+    })
+}
+
+/// Create this installation's key pair. An existing key is only replaced with `replace`, since bundles
+/// already sent to it can no longer be opened afterwards.
+pub fn generate(db: &Db, replace: bool) -> Result<BundleKeys, String> {
+    if !replace && identity()?.is_some() {
+        return Err("A bundle key already exists; replacing it makes bundles sent to it unreadable.".to_string());
+    }
+    let secret = StaticSecret::random_from_rng(OsRng);
+    secrets::set_secret(IDENTITY_SECRET, &B64.encode(secret.to_bytes()))?;
+    get_keys(db)
+}
+
+/// Add (or relabel) a recipient by their public key.
+pub fn import_recipient(db: &Db, label: &str, public_key: &str) -> Result<BundleKeys, String> {
+    let key = encode_public(&decode_public(public_key)?);
+    let label = label.trim();
+    if label.is_empty() {
+        return Err("Enter a name for the recipient.".to_string());
+    }
+    let mut list = recipients(db)?;
+    match list.iter_mut().find(|r| r.public_key == key) {
+        Some(existing) => existing.label = label.to_string(),
+        None => list.push(BundleRecipient { label: label.to_string(), public_key: key }),
+    }
+    save_recipients(db, &list)?;
+    get_keys(db)
+}
+
+pub fn remove_recipient(db: &Db, public_key: &str) -> Result<BundleKeys, String> {
+    let mut list = recipients(db)?;
+    list.retain(|r| r.public_key != public_key.trim());
+    save_recipients(db, &list)?;
+    get_keys(db)
+}
+
+fn wrapping_cipher(shared: &[u8; 32], ephemeral: &PublicKey, recipient: &PublicKey) -> Result<ChaCha20Poly1305, String> {
+    let salt = [ephemeral.as_bytes().as_slice(), recipient.as_bytes().as_slice()].concat();
+    let mut key = [0u8; 32];
+    Hkdf::<Sha256>::new(Some(&salt), shared)
+        .expand(HKDF_INFO, &mut key)
+        .map_err(|e| e.to_string())?;
+    ChaCha20Poly1305::new_from_slice(&key).map_err(|e| e.to_string())
+}
+
+/// A fresh random bundle key (as text, usable as an archive password) wrapped for each recipient.
+pub fn new_bundle_key(recipient_keys: &[String]) -> Result<(String, Vec<WrappedKey>), String> {
+    if recipient_keys.is_empty() {
+        return Err("Choose at least one recipient.".to_string());
+    }
+    let mut file_key = [0u8; 32];
+    OsRng.fill_bytes(&mut file_key);
+    let password = B64.encode(file_key);
+    let mut wrapped = Vec::new();
+    for value in recipient_keys {
+        let recipient = decode_public(value)?;
+        let ephemeral = EphemeralSecret::random_from_rng(OsRng);
+        let ephemeral_public = PublicKey::from(&ephemeral);
+        let shared = ephemeral.diffie_hellman(&recipient);
+        // Each wrapping key is used once, so a zero nonce is safe.
+        let ciphertext = wrapping_cipher(shared.as_bytes(), &ephemeral_public, &recipient)?
+            .encrypt(Nonce::from_slice(&[0u8; 12]), password.as_bytes())
+            .map_err(|e| e.to_string())?;
+        wrapped.push(WrappedKey {
+            recipient: encode_public(&recipient),
+            ephemeral: B64.encode(ephemeral_public.as_bytes()),
+            ciphertext: B64.encode(ciphertext),
+        });
+    }
+    Ok((password, wrapped))
+}
+
+/// The bundle key, unwrapped with this installation's private key.
+pub fn open_bundle_key(wrapped: &[WrappedKey]) -> Result<String, String> {
+    let secret = identity()?.ok_or("This installation has no bundle key; the bundle needs its password.")?;
+    let own = PublicKey::from(&secret);
+    let own_encoded = encode_public(&own);
+    let entry = wrapped
+        .iter()
+        .find(|w| w.recipient == own_encoded)
+        .ok_or("This bundle was not encrypted for this installation's key.")?;
+    let damaged = || "The bundle's key entry is damaged.".to_string();
+    let ephemeral: [u8; 32] = B64.decode(&entry.ephemeral).ok().and_then(|b| b.try_into().ok()).ok_or_else(damaged)?;
+    let ephemeral = PublicKey::from(ephemeral);
+    let ciphertext = B64.decode(&entry.ciphertext).map_err(|_| damaged())?;
+    let shared = secret.diffie_hellman(&ephemeral);
+    let password = wrapping_cipher(shared.as_bytes(), &ephemeral, &own)?
+        .decrypt(Nonce::from_slice(&[0u8; 12]), ciphertext.as_slice())
+        .map_err(|_| damaged())?;
+    String::from_utf8(password).map_err(|_| damaged())
+}
+
@@ -0,0 +1,238 @@
+//! Month-end closing checklist. Each step is evaluated from data the app already keeps (history, the
+//! pending append queue, export deliveries, app-state backups), so the closing screen shows what is
+//! still open for a period instead of relying on memory. Which steps apply is kept in settings
+//! (`closing_checklist`); by default all of them.
+
+use crate::db::Db;
+use crate::services::app_state_archive::LAST_EXPORT_KEY;
+use crate::services::duplicate_keys::DOCUMENT_NUMBER_KEYS;
+use crate::services::pending_appends;
+use crate::services::spending_report::parse_invoice_date;
+use chrono::{Datelike, NaiveDate};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+const STEPS_KEY: &str = "closing_checklist";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClosingStep {
+    /// No document of the period is still pending or failed.
+    InvoicesReviewed,
+    /// No two documents of the period share seller and document number.
+    DuplicatesResolved,
+    /// No ledger row is waiting in the pending append queue.
+    PendingAppendsFlushed,
+    /// An export was delivered after the period's last document was written to a ledger.
+    ExportDelivered,
+    /// The app state was backed up after the period's last document was scanned.
+    BackupTaken,
+}
+
+const ALL_STEPS: [ClosingStep; 5] = [
+    ClosingStep::InvoicesReviewed,
+    ClosingStep::DuplicatesResolved,
+    ClosingStep::PendingAppendsFlushed,
+    ClosingStep::ExportDelivered,
+    ClosingStep::BackupTaken,
+];
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StepStatus {
+    pub step: ClosingStep,
+    pub done: bool,
+    pub detail: String,
+    /// History records that keep the step open.
+    pub history_ids: Vec<i64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClosingStatus {
+    /// "YYYY-MM"
+    pub period: String,
+    pub documents: u32,
+    pub steps: Vec<StepStatus>,
+    /// Every configured step is done.
+    pub complete: bool,
+}
+
+/// Configured steps in display order.
+pub fn get_steps(db: &Db) -> Result<Vec<ClosingStep>, String> {
+    Ok(db
+        .get_settings()?
+        .get(STEPS_KEY)
+        .and_then(|v| serde_json::from_str(v).ok())
+        .unwrap_or_else(|| ALL_STEPS.to_vec()))
+}
+
+/// Store the steps to check; None goes back to all of them.
+pub fn set_steps(db: &Db, steps: Option<Vec<ClosingStep>>) -> Result<Vec<ClosingStep>, String> {
+    let value = match steps {
+        Some(steps) => {
+            let mut unique: Vec<ClosingStep> = Vec::new();
+            for step in steps {
+                if !unique.contains(&step) {
+                    unique.push(step);
+                }
+            }
+            Some(serde_json::to_string(&unique).map_err(|e| e.to_string())?)
+        }
+        None => None,
+    };
+    db.set_settings(&[(STEPS_KEY, value)])?;
+    get_steps(db)
+}
+
+/// First and last day of a "YYYY-MM" period.
+fn period_bounds(period: &str) -> Result<(NaiveDate, NaiveDate), String> {
+    let invalid = || format!("Period must be YYYY-MM: {}", period);
+    let first = NaiveDate::parse_from_str(&format!("{}-01", period.trim()), "%Y-%m-%d").map_err(|_| invalid())?;
+    let next = if first.month() == 12 {
+        NaiveDate::from_ymd_opt(first.year() + 1, 1, 1)
+    } else {
+        NaiveDate::from_ymd_opt(first.year(), first.month() + 1, 1)
+    }
+    .ok_or_else(invalid)?;
+    Ok((first, next.pred_opt().ok_or_else(invalid)?))
+}
+
+fn text<'a>(data: &'a Value, keys: &[&str]) -> Option<&'a str> {
+    keys.iter()
+        .find_map(|k| data.get(*k).and_then(|v| v.as_str()).map(str::trim).filter(|v| !v.is_empty()))
+}
+
+/// Document of the period: (history id, scanned at, status, duplicate key).
+struct PeriodDocument {
+    id: i64,
+    created_at: String,
+    status: String,
+    key: Option<String>,
+}
+
+fn period_documents(db: &Db, from: NaiveDate, to: NaiveDate) -> Result<Vec<PeriodDocument>, String> {
+    let mut out = Vec::new();
+    for (id, created_at, _, _, extracted_data, status) in db.get_history_report_rows(None, None)? {
+        let data: Value = serde_json::from_str(&extracted_data).unwrap_or(Value::Null);
+        let date = text(&data, &["date"])
+            .and_then(parse_invoice_date)
+            .or_else(|| chrono::DateTime::parse_from_rfc3339(&created_at).ok().map(|dt| dt.date_naive()));
+        if !date.is_some_and(|d| d >= from && d <= to) {
+            continue;
+        }
+        let key = text(&data, DOCUMENT_NUMBER_KEYS).map(|number| {
+            let seller = text(&data, &["seller_edb", "seller_name"]).unwrap_or("");
+            format!("{}|{}", seller.to_lowercase(), number.to_lowercase())
+        });
+        out.push(PeriodDocument { id, created_at, status, key });
+    }
+    Ok(out)
+}
+
+fn step_status(step: ClosingStep, done: bool, detail: String, history_ids: Vec<i64>) -> StepStatus {
+    StepStatus { step, done, detail, history_ids }
+}
+
+/// The checklist for a "YYYY-MM" period; documents belong to the month of their document date (scan
+/// date when they have none).
+pub fn get_closing_status(db: &Db, period: &str) -> Result<ClosingStatus, String> {
+    let (from, to) = period_bounds(period)?;
+    let documents = period_documents(db, from, to)?;
+    let last_scanned = documents.iter().map(|d| d.created_at.as_str()).max();
+    let last_exported = documents
+        .iter()
+        .filter(|d| d.status == "added_to_excel")
+        .map(|d| d.created_at.as_str())
+        .max();
+
+    let mut steps = Vec::new();
+    for step in get_steps(db)? {
+        let status = match step {
+            ClosingStep::InvoicesReviewed => {
+                let open: Vec<i64> = documents
+                    .iter()
+                    .filter(|d| d.status == "pending" || d.status == "error")
+                    .map(|d| d.id)
+                    .collect();
+                let detail = if open.is_empty() {
+                    format!("All {} documents reviewed.", documents.len())
+                } else {
+                    format!("{} of {} documents are pending or failed.", open.len(), documents.len())
+                };
+                step_status(step, open.is_empty(), detail, open)
+            }
+            ClosingStep::DuplicatesResolved => {
+                let mut groups: HashMap<&str, Vec<i64>> = HashMap::new();
+                for d in documents.iter().filter(|d| d.status != "error") {
+                    if let Some(key) = &d.key {
+                        groups.entry(key.as_str()).or_default().push(d.id);
+                    }
+                }
+                let mut ids: Vec<i64> = groups.into_values().filter(|g| g.len() > 1).flatten().collect();
+                ids.sort_unstable();
+                let detail = if ids.is_empty() {
+                    "No duplicate documents.".to_string()
+                } else {
+                    format!("{} documents share a seller and document number.", ids.len())
+                };
+                step_status(step, ids.is_empty(), detail, ids)
+            }
+            ClosingStep::PendingAppendsFlushed => {
+                let pending = pending_appends::list(db, None)?;
+                let ids: Vec<i64> = pending.iter().filter_map(|p| p.history_id).collect();
+                let detail = if pending.is_empty() {
+                    "No rows waiting for a ledger.".to_string()
+                } else {
+                    format!("{} rows are still waiting for their ledger.", pending.len())
+                };
+                step_status(step, pending.is_empty(), detail, ids)
+            }
+            ClosingStep::ExportDelivered => match last_exported {
+                None => step_status(step, true, "Nothing was exported for this period.".to_string(), Vec::new()),
+                Some(last) => {
+                    let delivered = db
+                        .list_export_deliveries(None, 1000)?
+                        .into_iter()
+                        .filter(|row| row.5 == "delivered" && row.8.as_str() >= last)
+                        .map(|row| row.8)
+                        .max();
+                    match delivered {
+                        Some(at) => step_status(step, true, format!("Delivered {}.", at), Vec::new()),
+                        None => step_status(
+                            step,
+                            false,
+                            "No export was delivered after the last document was exported.".to_string(),
+                            Vec::new(),
+                        ),
+                    }
+                }
+            },
+            ClosingStep::BackupTaken => {
+                let backup = db.get_settings()?.get(LAST_EXPORT_KEY).cloned();
+                match (backup, last_scanned) {
+                    (Some(at), Some(last)) if at.as_str() >= last => {
+                        step_status(step, true, format!("Backed up {}.", at), Vec::new())
+                    }
+                    (Some(at), None) => step_status(step, true, format!("Backed up {}.", at), Vec::new()),
+                    (None, None) => step_status(step, true, "No documents to back up.".to_string(), Vec::new()),
+                    _ => step_status(
+                        step,
+                        false,
+                        "Export the app state after the period's last scan.".to_string(),
+                        Vec::new(),
+                    ),
+                }
+            }
+        };
+        steps.push(status);
+    }
+    Ok(ClosingStatus {
+        period: from.format("%Y-%m").to_string(),
+        documents: documents.len() as u32,
+        complete: steps.iter().all(|s| s.done),
+        steps,
+    })
+}
+
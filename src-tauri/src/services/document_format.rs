@@ -0,0 +1,162 @@
+//! Input document formats accepted for OCR, detected from magic bytes rather than the file extension
+//! (phone photos are often saved as .jpg while actually being HEIC, or without any extension).
+
+use std::io::Read;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocumentFormat {
+    Pdf,
+    Jpeg,
+    Png,
+    Tiff,
+    Heic,
+}
+
+pub const UNSUPPORTED_FORMAT: &str = "Unsupported file type. Use PDF, JPG, PNG, TIFF or HEIC.";
+
+/// Bytes needed by `detect_format`.
+const SNIFF_LEN: usize = 16;
+
+impl DocumentFormat {
+    /// MIME type sent to Azure with the document.
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            DocumentFormat::Pdf => "application/pdf",
+            DocumentFormat::Jpeg => "image/jpeg",
+            DocumentFormat::Png => "image/png",
+            DocumentFormat::Tiff => "image/tiff",
+            DocumentFormat::Heic => "image/heic",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            DocumentFormat::Pdf => "pdf",
+            DocumentFormat::Jpeg => "jpg",
+            DocumentFormat::Png => "png",
+            DocumentFormat::Tiff => "tiff",
+            DocumentFormat::Heic => "heic",
+        }
+    }
+}
+
+/// Detect the format from the first bytes of the file.
+pub fn detect_format(bytes: &[u8]) -> Option<DocumentFormat> {
+    if bytes.starts_with(b"%PDF-") {
+        return Some(DocumentFormat::Pdf);
+    }
+    if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        return Some(DocumentFormat::Jpeg);
+    }
+    if bytes.starts_with(&[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]) {
+        return Some(DocumentFormat::Png);
+    }
+    if bytes.starts_with(b"II*\0") || bytes.starts_with(b"MM\0*") {
+        return Some(DocumentFormat::Tiff);
+    }
+    // ISO BMFF: "ftyp" box at offset 4 followed by a HEIF brand.
+    if bytes.len() >= 12 && &bytes[4..8] == b"ftyp" {
+        let brand = &bytes[8..12];
+        if [b"heic", b"heix", b"hevc", b"hevx", b"heim", b"heis", b"mif1", b"msf1"]
+            .iter()
+            .any(|b| brand == *b)
+        {
+            return Some(DocumentFormat::Heic);
+        }
+    }
+    None
+}
+
+/// Read the header of `path` and detect its format.
+pub fn detect_file_format(path: &Path) -> Result<Option<DocumentFormat>, String> {
+    let mut f = std::fs::File::open(path).map_err(|e| format!("Could not open: {}", e))?;
+    let mut header = Vec::with_capacity(SNIFF_LEN);
+    f.by_ref()
+        .take(SNIFF_LEN as u64)
+        .read_to_end(&mut header)
+        .map_err(|e| format!("Could not read file: {}", e))?;
+    Ok(detect_format(&header))
+}
+
+/// Number of pages (IFDs) in a TIFF; multi-page scans from office scanners are common.
+pub fn tiff_page_count(bytes: &[u8]) -> Option<u32> {
+    let little_endian = match bytes.get(0..2)? {
+        b"II" => true,
+        b"MM" => false,
+        _ => return None,
+    };
+    let read_u16 = |at: usize| -> Option<u16> {
+        let b: [u8; 2] = bytes.get(at..at + 2)?.try_into().ok()?;
+        Some(if little_endian { u16::from_le_bytes(b) } else { u16::from_be_bytes(b) })
+    };
+    let read_u32 = |at: usize| -> Option<u32> {
+        let b: [u8; 4] = bytes.get(at..at + 4)?.try_into().ok()?;
+        Some(if little_endian { u32::from_le_bytes(b) } else { u32::from_be_bytes(b) })
+    };
+    let mut offset = read_u32(4)? as usize;
+    let mut pages = 0u32;
+    // Bounded walk so a corrupt (looping) IFD chain cannot hang OCR.
+    while offset != 0 && pages < 10_000 {
+        let entries = read_u16(offset)? as usize;
+        pages += 1;
+        offset = read_u32(offset + 2 + entries * 12)? as usize;
+    }
+    Some(pages.max(1))
+}
+
+/// Pixel size (width, height) of a PNG, JPEG or TIFF (first page) from its header.
+pub fn image_dimensions(bytes: &[u8], format: DocumentFormat) -> Option<(u32, u32)> {
+    let be_u16 = |at: usize| -> Option<u32> { Some(u16::from_be_bytes(bytes.get(at..at + 2)?.try_into().ok()?) as u32) };
+    match format {
+        DocumentFormat::Png => {
+            // IHDR is the first chunk: width and height at offsets 16 and 20.
+            let w = u32::from_be_bytes(bytes.get(16..20)?.try_into().ok()?);
+            let h = u32::from_be_bytes(bytes.get(20..24)?.try_into().ok()?);
+            Some((w, h))
+        }
+        DocumentFormat::Jpeg => {
+            // Walk the segments to the first start-of-frame marker (C0–CF except C4, C8, CC).
+            let mut at = 2;
+            while at + 9 < bytes.len() {
+                if bytes[at] != 0xFF {
+                    return None;
+                }
+                let marker = bytes[at + 1];
+                if (0xC0..=0xCF).contains(&marker) && !matches!(marker, 0xC4 | 0xC8 | 0xCC) {
+                    return Some((be_u16(at + 7)?, be_u16(at + 5)?));
+                }
+                at += 2 + be_u16(at + 2)? as usize;
+            }
+            None
+        }
+        DocumentFormat::Tiff => {
+            let little_endian = bytes.get(0..2)? == b"II";
+            let read_u16 = |at: usize| -> Option<u32> {
+                let b: [u8; 2] = bytes.get(at..at + 2)?.try_into().ok()?;
+                Some(if little_endian { u16::from_le_bytes(b) } else { u16::from_be_bytes(b) } as u32)
+            };
+            let read_u32 = |at: usize| -> Option<u32> {
+                let b: [u8; 4] = bytes.get(at..at + 4)?.try_into().ok()?;
+                Some(if little_endian { u32::from_le_bytes(b) } else { u32::from_be_bytes(b) })
+            };
+            let ifd = read_u32(4)? as usize;
+            let (mut width, mut height) = (None, None);
+            for i in 0..read_u16(ifd)? as usize {
+                let entry = ifd + 2 + i * 12;
+                // SHORT (3) values sit in the first two bytes of the value field, LONG (4) use all four.
+                let value = match read_u16(entry + 2)? {
+                    3 => read_u16(entry + 8)?,
+                    _ => read_u32(entry + 8)?,
+                };
+                match read_u16(entry)? {
+                    256 => width = Some(value),
+                    257 => height = Some(value),
+                    _ => {}
+                }
+            }
+            Some((width?, height?))
+        }
+        DocumentFormat::Pdf | DocumentFormat::Heic => None,
+    }
+}
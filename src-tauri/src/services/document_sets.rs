@@ -0,0 +1,142 @@
+//! Stapled document sets: an invoice scanned together with its delivery note (фактура + испратница) in
+//! one PDF. Azure returns each as its own entry in `result.contents`; the invoice is extracted as usual
+//! and the delivery note's number is kept as the `delivery_note_number` field. When the history record is
+//! saved, the pair is stored in `document_links`.
+
+use crate::db::Db;
+use serde::Serialize;
+use serde_json::Value;
+
+/// Field holding the number of the delivery note scanned with an invoice.
+pub const DELIVERY_NOTE_FIELD: &str = "delivery_note_number";
+/// Link type stored in `document_links` for an invoice and its delivery note.
+pub const DELIVERY_NOTE_LINK: &str = "delivery_note";
+
+/// Words that name a delivery note (Macedonian, Serbian/Croatian and English), lowercase.
+const DELIVERY_NOTE_WORDS: &[&str] = &["испратница", "отпремница", "ispratnica", "otpremnica", "delivery note"];
+/// Azure fields that may hold the document type or the document number.
+const TYPE_FIELDS: &[&str] = &["TypeOfDocument", "DocumentType", "documentType", "Type", "InvoiceType"];
+const NUMBER_FIELDS: &[&str] = &["DocumentNumber", "InvoiceId", "document_number", "documentNumber", "DeliveryNoteNumber"];
+/// How much of a document's text is searched for its title.
+const TITLE_CHARS: usize = 400;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentLink {
+    pub id: i64,
+    pub history_id: i64,
+    /// "delivery_note".
+    pub link_type: String,
+    pub linked_number: String,
+    /// History record of the linked document, when it was scanned on its own too.
+    pub linked_history_id: Option<i64>,
+    pub created_at: String,
+}
+
+fn field_text(content: &Value, names: &[&str]) -> Option<String> {
+    let fields = content.get("fields")?.as_object()?;
+    names.iter().find_map(|n| {
+        let f = fields.get(*n)?;
+        f.get("valueString")
+            .or_else(|| f.get("content"))
+            .and_then(|v| v.as_str())
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+    })
+}
+
+fn markdown(content: &Value) -> &str {
+    content
+        .get("markdown")
+        .or_else(|| content.get("content"))
+        .and_then(|c| c.as_str())
+        .unwrap_or("")
+}
+
+fn names_delivery_note(text: &str) -> bool {
+    let text = text.to_lowercase();
+    DELIVERY_NOTE_WORDS.iter().any(|w| text.contains(w))
+}
+
+/// True when an Azure content entry is a delivery note rather than an invoice: its type field, or the
+/// title at the top of the page, says so.
+pub fn is_delivery_note(content: &Value) -> bool {
+    match field_text(content, TYPE_FIELDS) {
+        Some(doc_type) => names_delivery_note(&doc_type),
+        None => {
+            let head: String = markdown(content).chars().take(TITLE_CHARS).collect();
+            names_delivery_note(&head) && !head.to_lowercase().contains("фактура")
+        }
+    }
+}
+
+/// Indexes of the invoice and (if any) the delivery note among `result.contents`.
+pub fn split_pair(contents: &[Value]) -> (usize, Option<usize>) {
+    if contents.len() < 2 {
+        return (0, None);
+    }
+    let invoice = contents.iter().position(|c| !is_delivery_note(c)).unwrap_or(0);
+    let note = (0..contents.len()).find(|&i| i != invoice && is_delivery_note(&contents[i]));
+    (invoice, note)
+}
+
+/// Number written after "испратница" (e.g. "Испратница бр. 123/2024" -> "123/2024").
+pub fn find_note_number(text: &str) -> Option<String> {
+    let lower = text.to_lowercase();
+    let mut rest = DELIVERY_NOTE_WORDS
+        .iter()
+        .filter_map(|w| lower.find(w).map(|i| i + w.len()))
+        .min()
+        .map(|start| &lower[start..])?;
+    // Skip "бр.", "број", "no.", ":" and similar up to the first token with a digit, within a few words.
+    for _ in 0..4 {
+        let trimmed = rest.trim_start_matches(|c: char| c.is_whitespace() || matches!(c, ':' | '.' | '#' | '|' | '*'));
+        let end = trimmed.find(|c: char| c.is_whitespace() || c == '|').unwrap_or(trimmed.len());
+        let token = trimmed[..end].trim_end_matches(['.', ',', ';', ')']);
+        if token.chars().any(|c| c.is_ascii_digit()) {
+            let token = token.trim_start_matches(|c: char| !c.is_ascii_alphanumeric());
+            return (!token.is_empty()).then(|| token.to_uppercase());
+        }
+        if end == 0 {
+            return None;
+        }
+        rest = &trimmed[end..];
+    }
+    None
+}
+
+/// Delivery note number from the note's own content entry (its number field, else its text).
+pub fn delivery_note_number(content: &Value) -> Option<String> {
+    field_text(content, NUMBER_FIELDS).or_else(|| find_note_number(markdown(content)))
+}
+
+/// Store the link for a saved history record whose data has a delivery note number. The note's own
+/// history record, if it was scanned separately, is linked too.
+pub fn record_links(db: &Db, history_id: i64, extracted_data: &Value) -> Result<(), String> {
+    let Some(number) = extracted_data
+        .get(DELIVERY_NOTE_FIELD)
+        .and_then(|v| v.as_str())
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+    else {
+        return Ok(());
+    };
+    let linked_history_id = db.find_history_by_document_number(number, history_id)?;
+    db.add_document_link(history_id, DELIVERY_NOTE_LINK, number, linked_history_id)?;
+    Ok(())
+}
+
+pub fn links_for(db: &Db, history_id: i64) -> Result<Vec<DocumentLink>, String> {
+    Ok(db
+        .get_document_links(history_id)?
+        .into_iter()
+        .map(|(id, history_id, link_type, linked_number, linked_history_id, created_at)| DocumentLink {
+            id,
+            history_id,
+            link_type,
+            linked_number,
+            linked_history_id,
+            created_at,
+        })
+        .collect())
+}
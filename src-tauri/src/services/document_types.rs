@@ -0,0 +1,191 @@
+//! Registry of document types (`document_types` table): display name, the Azure analyzer to scan with,
+//! which extraction path reads the result, a default export folder and the export layout. The four
+//! built-in types are seeded by the migration and can be edited but not deleted; new types reuse one of
+//! the built-in extractors and map their analyzer's fields with `model_mappings`. OCR reads the registry
+//! from an in-memory copy refreshed on startup and after every change.
+
+use crate::db::{Db, DocumentTypeRow};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{OnceLock, RwLock};
+
+/// How an analyzer result is turned into fields.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Extractor {
+    /// Analyzer fields through the generic mapping (invoices and custom analyzers).
+    Fields,
+    /// Даночен биланс (tax balance) mapping and AOP rows.
+    TaxBalance,
+    /// ДДВ-04 boxes.
+    VatReturn,
+    /// Payroll (МПИН) fields.
+    Payroll,
+}
+
+impl Extractor {
+    fn parse(value: &str) -> Extractor {
+        match value {
+            "tax_balance" => Extractor::TaxBalance,
+            "vat_return" => Extractor::VatReturn,
+            "payroll" => Extractor::Payroll,
+            _ => Extractor::Fields,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Extractor::Fields => "fields",
+            Extractor::TaxBalance => "tax_balance",
+            Extractor::VatReturn => "vat_return",
+            Extractor::Payroll => "payroll",
+        }
+    }
+
+    /// Built-in type whose code path in ocr.rs implements this extractor.
+    fn builtin_type(self) -> Option<&'static str> {
+        match self {
+            Extractor::Fields => None,
+            Extractor::TaxBalance => Some("smetka"),
+            Extractor::VatReturn => Some("generic"),
+            Extractor::Payroll => Some("plata"),
+        }
+    }
+}
+
+/// Layout of new exports of this type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportLayout {
+    /// Standard invoice columns (type, number, date, seller, buyer, description, amounts).
+    Standard,
+    /// Columns of the type's field schema (`field_schemas`).
+    Schema,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentType {
+    /// Stable key stored with scans and profiles (e.g. "faktura", "ispratnica").
+    pub key: String,
+    pub display_name: String,
+    /// Azure analyzer; None uses the AZURE_CU_ANALYZER_* default.
+    #[serde(default)]
+    pub model_id: Option<String>,
+    pub extractor: Extractor,
+    /// Folder for new exports of this type when no path is chosen.
+    #[serde(default)]
+    pub default_folder: Option<String>,
+    pub export_layout: ExportLayout,
+    #[serde(default)]
+    pub builtin: bool,
+    #[serde(default)]
+    pub updated_at: String,
+}
+
+impl From<DocumentTypeRow> for DocumentType {
+    fn from(r: DocumentTypeRow) -> Self {
+        DocumentType {
+            key: r.key,
+            display_name: r.display_name,
+            model_id: r.model_id,
+            extractor: Extractor::parse(&r.extractor),
+            default_folder: r.default_folder,
+            export_layout: if r.export_layout == "schema" { ExportLayout::Schema } else { ExportLayout::Standard },
+            builtin: r.builtin,
+            updated_at: r.updated_at,
+        }
+    }
+}
+
+/// key -> registry entry, read by OCR and export code without a database handle.
+static RUNTIME: OnceLock<RwLock<HashMap<String, DocumentType>>> = OnceLock::new();
+
+fn runtime() -> &'static RwLock<HashMap<String, DocumentType>> {
+    RUNTIME.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+/// Reload the in-memory copy from the database.
+pub fn load(db: &Db) -> Result<(), String> {
+    let next = list(db)?.into_iter().map(|t| (t.key.clone(), t)).collect();
+    *runtime().write().map_err(|e| e.to_string())? = next;
+    Ok(())
+}
+
+fn with_entry<T>(key: &str, f: impl FnOnce(&DocumentType) -> Option<T>) -> Option<T> {
+    runtime().read().ok()?.get(key.trim()).and_then(f)
+}
+
+/// Analyzer registered for a document type, if any.
+pub fn model_for(key: &str) -> Option<String> {
+    with_entry(key, |t| t.model_id.clone().filter(|m| !m.trim().is_empty()))
+}
+
+/// Keys of all registered types.
+pub fn registered_keys() -> Vec<String> {
+    let mut keys: Vec<String> = runtime().read().map(|m| m.keys().cloned().collect()).unwrap_or_default();
+    keys.sort();
+    keys
+}
+
+/// Type whose extraction path reads results of `key`: the built-in type of its extractor
+/// (a registered payroll type reads like "plata"), else `key` itself.
+pub fn extraction_type(key: Option<&str>) -> Option<String> {
+    let key = key?;
+    Some(with_entry(key, |t| t.extractor.builtin_type().map(str::to_string)).unwrap_or_else(|| key.to_string()))
+}
+
+/// Default export folder of a type, when set and present.
+pub fn default_folder(key: &str) -> Option<PathBuf> {
+    with_entry(key, |t| t.default_folder.as_ref().map(PathBuf::from)).filter(|p| p.is_dir())
+}
+
+pub fn export_layout(key: &str) -> ExportLayout {
+    with_entry(key, |t| Some(t.export_layout)).unwrap_or(ExportLayout::Standard)
+}
+
+pub fn list(db: &Db) -> Result<Vec<DocumentType>, String> {
+    Ok(db.get_document_types()?.into_iter().map(DocumentType::from).collect())
+}
+
+/// Add or change a document type; effective from the next scan. Returns the whole registry.
+pub fn save(db: &Db, document_type: DocumentType) -> Result<Vec<DocumentType>, String> {
+    let key = document_type.key.trim();
+    if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-')) {
+        return Err("Key may only contain Latin letters, digits, '_' and '-'.".to_string());
+    }
+    let display_name = document_type.display_name.trim();
+    if display_name.is_empty() {
+        return Err("Display name is required.".to_string());
+    }
+    let trimmed = |v: Option<String>| v.map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+    let default_folder = trimmed(document_type.default_folder);
+    if let Some(folder) = &default_folder {
+        if !std::path::Path::new(folder).is_dir() {
+            return Err(format!("Folder does not exist: {}", folder));
+        }
+    }
+    db.save_document_type(&DocumentTypeRow {
+        key: key.to_string(),
+        display_name: display_name.to_string(),
+        model_id: trimmed(document_type.model_id),
+        extractor: document_type.extractor.as_str().to_string(),
+        default_folder,
+        export_layout: match document_type.export_layout {
+            ExportLayout::Standard => "standard",
+            ExportLayout::Schema => "schema",
+        }
+        .to_string(),
+        ..Default::default()
+    })?;
+    load(db)?;
+    list(db)
+}
+
+/// Delete a user-added type. Built-in types cannot be deleted; returns false for them and unknown keys.
+pub fn remove(db: &Db, key: &str) -> Result<bool, String> {
+    let removed = db.delete_document_type(key.trim())?;
+    load(db)?;
+    Ok(removed)
+}
@@ -0,0 +1,201 @@
+//! Per-profile uniqueness key for ledger rows. Document numbers are only unique per seller (and many
+//! vendors restart numbering every year), so a profile can combine several fields, e.g.
+//! `["invoice_number", "seller_edb", "year:date"]`. The key drives duplicate detection before an append,
+//! what an append does with a duplicate, and row matching in the consistency audit. Profiles without a
+//! key fall back to the document number alone.
+
+use crate::db::Db;
+use crate::services::profile_audit::{self, LedgerRow};
+use crate::services::spending_report::parse_invoice_date;
+use crate::services::workbook_compat;
+use calamine::{open_workbook_auto, Reader};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Field keys that hold the document number, in order of preference; the key when none is configured.
+pub const DOCUMENT_NUMBER_KEYS: &[&str] = &["invoice_number", "document_number"];
+/// Prefix of a key part that uses only the year of a date field.
+const YEAR_PREFIX: &str = "year:";
+
+/// What an append does when the ledger already has a row with the same key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OnDuplicate {
+    /// Append anyway (duplicates are only reported).
+    Append,
+    /// Refuse the append.
+    Reject,
+    /// Overwrite the existing row with the new non-empty values.
+    Update,
+}
+
+impl OnDuplicate {
+    fn as_str(self) -> &'static str {
+        match self {
+            OnDuplicate::Append => "append",
+            OnDuplicate::Reject => "reject",
+            OnDuplicate::Update => "update",
+        }
+    }
+
+    fn parse(value: &str) -> Self {
+        match value {
+            "reject" => OnDuplicate::Reject,
+            "update" => OnDuplicate::Update,
+            _ => OnDuplicate::Append,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicateKey {
+    /// Field keys; `year:<field>` uses the year of a date field.
+    pub fields: Vec<String>,
+    pub on_duplicate: OnDuplicate,
+}
+
+/// Field a key part reads (`year:date` reads `date`).
+pub fn source_field(part: &str) -> &str {
+    part.strip_prefix(YEAR_PREFIX).unwrap_or(part)
+}
+
+/// Comparable form of one key part: whitespace removed and lowercased, or the year for `year:` parts.
+fn normalize_part(part: &str, value: &str) -> Option<String> {
+    let value = value.trim();
+    if value.is_empty() {
+        return None;
+    }
+    if part.starts_with(YEAR_PREFIX) {
+        return parse_invoice_date(value).map(|d| chrono::Datelike::year(&d).to_string());
+    }
+    Some(value.chars().filter(|c| !c.is_whitespace()).collect::<String>().to_lowercase())
+}
+
+/// Composite key of a record, or None when any part is missing. `value` returns the raw value of a field.
+pub fn key_of(fields: &[String], value: impl Fn(&str) -> Option<String>) -> Option<String> {
+    let parts: Option<Vec<String>> = fields
+        .iter()
+        .map(|part| value(source_field(part)).and_then(|v| normalize_part(part, &v)))
+        .collect();
+    parts.map(|p| p.join("|"))
+}
+
+/// The profile's key, or None when it uses the document number alone.
+pub fn get_key(db: &Db, profile_id: i64) -> Result<Option<DuplicateKey>, String> {
+    Ok(db.get_duplicate_key(profile_id)?.map(|(fields, on_duplicate)| DuplicateKey {
+        fields: serde_json::from_str(&fields).unwrap_or_default(),
+        on_duplicate: OnDuplicate::parse(&on_duplicate),
+    }))
+}
+
+/// Set the profile's key; None (or no fields) goes back to the document number alone.
+pub fn save_key(db: &Db, profile_id: i64, key: Option<DuplicateKey>) -> Result<Option<DuplicateKey>, String> {
+    db.get_profile_by_id(profile_id)?;
+    let key = key.map(|k| DuplicateKey {
+        fields: k
+            .fields
+            .iter()
+            .map(|f| f.trim().to_string())
+            .filter(|f| !source_field(f).is_empty())
+            .fold(Vec::new(), |mut acc, f| {
+                if !acc.contains(&f) {
+                    acc.push(f);
+                }
+                acc
+            }),
+        on_duplicate: k.on_duplicate,
+    });
+    match key {
+        Some(k) if !k.fields.is_empty() => {
+            let fields = serde_json::to_string(&k.fields).map_err(|e| e.to_string())?;
+            db.save_duplicate_key(profile_id, &fields, k.on_duplicate.as_str())?;
+            Ok(Some(k))
+        }
+        _ => {
+            db.delete_duplicate_key(profile_id)?;
+            Ok(None)
+        }
+    }
+}
+
+/// Key fields to match on: the configured key, else the first document-number field the mapping has.
+pub fn effective_fields(key: Option<&DuplicateKey>, column_mapping: &HashMap<String, String>) -> Result<Vec<String>, String> {
+    if let Some(key) = key {
+        return Ok(key.fields.clone());
+    }
+    DOCUMENT_NUMBER_KEYS
+        .iter()
+        .find(|k| column_mapping.values().any(|v| v == *k))
+        .map(|k| vec![k.to_string()])
+        .ok_or_else(|| "The profile has no column mapped to the document number.".to_string())
+}
+
+/// Column letter each key part is read from in the ledger; fails when a part's field is not mapped.
+pub fn key_columns(fields: &[String], column_mapping: &HashMap<String, String>) -> Result<Vec<String>, String> {
+    fields
+        .iter()
+        .map(|part| {
+            let field = source_field(part);
+            let mut letters: Vec<&String> = column_mapping.iter().filter(|(_, v)| *v == field).map(|(k, _)| k).collect();
+            letters.sort();
+            letters
+                .first()
+                .map(|l| l.trim().to_ascii_uppercase())
+                .ok_or_else(|| format!("Key field '{}' is not mapped to a column of the profile.", field))
+        })
+        .collect()
+}
+
+/// Key of a ledger row read with `profile_audit::read_ledger_rows`.
+pub fn row_key(fields: &[String], columns: &[String], row: &LedgerRow) -> Option<String> {
+    key_of(fields, |field| {
+        let i = fields.iter().position(|f| source_field(f) == field)?;
+        row.cells.get(&columns[i]).cloned()
+    })
+}
+
+/// 1-based ledger rows whose key equals `key`.
+pub fn find_matching_rows(
+    excel_path: &str,
+    sheet_name: &str,
+    header_row: u32,
+    fields: &[String],
+    columns: &[String],
+    key: &str,
+) -> Result<Vec<u32>, String> {
+    Ok(ledger_keys(excel_path, sheet_name, header_row, fields, columns)?
+        .into_iter()
+        .filter(|(row_key, _)| row_key == key)
+        .map(|(_, row)| row)
+        .collect())
+}
+
+/// (key, 1-based row) of every ledger row that has a complete key.
+pub fn ledger_keys(
+    excel_path: &str,
+    sheet_name: &str,
+    header_row: u32,
+    fields: &[String],
+    columns: &[String],
+) -> Result<Vec<(String, u32)>, String> {
+    let path = Path::new(excel_path);
+    if !path.exists() {
+        return Err("File not found. Browse to select again.".to_string());
+    }
+    let mut workbook = open_workbook_auto(path).map_err(|e| format!("Could not open Excel file: {}", e))?;
+    let sheet_name = crate::excel::resolve_sheet_name(&workbook.sheet_names(), sheet_name)?;
+    let range = workbook
+        .worksheet_range(&sheet_name)
+        .map_err(|e| format!("Sheet not found: {}", e))?;
+    let indexed: Vec<(String, u32)> = columns
+        .iter()
+        .filter_map(|l| Some((l.clone(), profile_audit::letter_to_index(l)?)))
+        .collect();
+    let date1904 = workbook_compat::uses_1904_dates(path);
+    Ok(profile_audit::read_ledger_rows(&range, header_row.max(1), &indexed, date1904)
+        .iter()
+        .filter_map(|row| Some((row_key(fields, columns, row)?, row.row_number)))
+        .collect())
+}
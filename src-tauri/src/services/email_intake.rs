@@ -0,0 +1,329 @@
+//! Email intake: many suppliers send invoices only by email, so an IMAP mailbox (TLS only) can be polled
+//! for PDF attachments. Each one is saved under `<app data>/email_intake` and queued in the
+//! `email_intake` table until it is scanned or dismissed. Messages are read with BODY.PEEK, so they stay
+//! unread unless `mark_seen` is set; the last UID taken is kept per mailbox (`email_intake_cursor`), and
+//! the first poll of a mailbox only looks at the last `FIRST_POLL_DAYS` days.
+
+use crate::db::{Db, EmailIntakeRow, NewEmailIntakeItem};
+use crate::services::export_naming::sanitize_file_stem;
+use crate::services::{app_warnings, settings};
+use mail_parser::{MessageParser, MimeHeaders};
+use serde::{Deserialize, Serialize};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const CONFIG_KEY: &str = "email_intake";
+const PASSWORD_KEY: &str = "email_intake_password";
+const CURSOR_KEY: &str = "email_intake_cursor";
+const FOLDER: &str = "email_intake";
+const FIRST_POLL_DAYS: i64 = 14;
+const DEFAULT_PORT: u16 = 993;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+const READ_TIMEOUT: Duration = Duration::from_secs(60);
+
+pub const STATUS_QUEUED: &str = "queued";
+pub const STATUS_SCANNED: &str = "scanned";
+pub const STATUS_DISMISSED: &str = "dismissed";
+
+type Session = imap::Session<native_tls::TlsStream<TcpStream>>;
+
+fn default_mailbox() -> String {
+    "INBOX".to_string()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MailboxConfig {
+    pub host: String,
+    /// 993 when not set.
+    pub port: Option<u16>,
+    pub username: String,
+    #[serde(default = "default_mailbox")]
+    pub mailbox: String,
+    /// Minutes between background polls; 0 polls only when asked.
+    #[serde(default)]
+    pub poll_minutes: u32,
+    /// Flag messages as read once their attachments are taken.
+    #[serde(default)]
+    pub mark_seen: bool,
+}
+
+/// The mailbox as shown in the UI (the password is never returned).
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmailIntakeSettings {
+    #[serde(flatten)]
+    pub config: MailboxConfig,
+    pub password_configured: bool,
+}
+
+/// Replaces the mailbox. For the password None keeps the stored value and an empty string removes it.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmailIntakeSettingsUpdate {
+    #[serde(flatten)]
+    pub config: MailboxConfig,
+    pub password: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IntakeItem {
+    pub id: i64,
+    pub sender: Option<String>,
+    pub subject: Option<String>,
+    pub received_at: Option<String>,
+    pub file_name: String,
+    /// Saved PDF, ready for `batch_scan_invoices`.
+    pub file_path: String,
+    /// "queued", "scanned" or "dismissed"
+    pub status: String,
+    pub created_at: String,
+}
+
+/// Last UID taken from a mailbox; reset when the server changes the mailbox's UIDVALIDITY.
+#[derive(Debug, Serialize, Deserialize)]
+struct Cursor {
+    host: String,
+    mailbox: String,
+    uid_validity: u32,
+    last_uid: u32,
+}
+
+fn item((id, sender, subject, received_at, file_name, file_path, status, created_at): EmailIntakeRow) -> IntakeItem {
+    IntakeItem { id, sender, subject, received_at, file_name, file_path, status, created_at }
+}
+
+pub fn intake_dir(app_data_dir: &Path) -> PathBuf {
+    app_data_dir.join(FOLDER)
+}
+
+pub fn get_settings(db: &Db, app_data_dir: &Path) -> Result<Option<EmailIntakeSettings>, String> {
+    let Some(config) = db.get_settings()?.get(CONFIG_KEY).and_then(|v| serde_json::from_str(v).ok()) else {
+        return Ok(None);
+    };
+    Ok(Some(EmailIntakeSettings { config, password_configured: password(db, app_data_dir)?.is_some() }))
+}
+
+fn password(db: &Db, app_data_dir: &Path) -> Result<Option<String>, String> {
+    let Some(encrypted) = db.get_settings()?.get(PASSWORD_KEY).filter(|v| !v.is_empty()).cloned() else {
+        return Ok(None);
+    };
+    Ok(settings::decrypt_secret(app_data_dir, &encrypted)
+        .map_err(|e| app_warnings::report("email_intake", format!("could not decrypt the mailbox password: {}", e)))
+        .ok())
+}
+
+pub fn save_settings(
+    db: &Db,
+    app_data_dir: &Path,
+    update: EmailIntakeSettingsUpdate,
+) -> Result<EmailIntakeSettings, String> {
+    let mut config = update.config;
+    for value in [&mut config.host, &mut config.username, &mut config.mailbox] {
+        *value = value.trim().to_string();
+    }
+    if config.host.is_empty() || config.username.is_empty() {
+        return Err("Server and user name are required.".to_string());
+    }
+    if config.mailbox.is_empty() {
+        config.mailbox = default_mailbox();
+    }
+    let mut entries: Vec<(&str, Option<String>)> =
+        vec![(CONFIG_KEY, Some(serde_json::to_string(&config).map_err(|e| e.to_string())?))];
+    if let Some(password) = update.password {
+        let encrypted = match password.as_str() {
+            "" => None,
+            password => Some(settings::encrypt_secret(app_data_dir, password)?),
+        };
+        entries.push((PASSWORD_KEY, encrypted));
+    }
+    db.set_settings(&entries)?;
+    get_settings(db, app_data_dir)?.ok_or_else(|| "Email intake settings were not saved.".to_string())
+}
+
+/// Forget the mailbox and its password; downloaded attachments stay queued.
+pub fn remove_settings(db: &Db) -> Result<(), String> {
+    db.set_settings(&[(CONFIG_KEY, None), (PASSWORD_KEY, None), (CURSOR_KEY, None)])?;
+    Ok(())
+}
+
+fn load(db: &Db, app_data_dir: &Path) -> Result<(MailboxConfig, String), String> {
+    let config = get_settings(db, app_data_dir)?.ok_or_else(|| "Email intake is not set up.".to_string())?.config;
+    let password = password(db, app_data_dir)?.ok_or_else(|| "The mailbox password is not set.".to_string())?;
+    Ok((config, password))
+}
+
+fn connect(config: &MailboxConfig, password: &str) -> Result<Session, String> {
+    let port = config.port.unwrap_or(DEFAULT_PORT);
+    let addr = (config.host.as_str(), port)
+        .to_socket_addrs()
+        .map_err(|e| format!("Could not resolve {}: {}", config.host, e))?
+        .next()
+        .ok_or_else(|| format!("Could not resolve {}.", config.host))?;
+    let tcp = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)
+        .map_err(|e| format!("Could not connect to {}:{}: {}", config.host, port, e))?;
+    tcp.set_read_timeout(Some(READ_TIMEOUT)).map_err(|e| e.to_string())?;
+    let tls = native_tls::TlsConnector::new().map_err(|e| e.to_string())?;
+    let stream = tls
+        .connect(&config.host, tcp)
+        .map_err(|e| format!("TLS handshake with {} failed: {}", config.host, e))?;
+    let mut client = imap::Client::new(stream);
+    client.read_greeting().map_err(|e| format!("{} did not greet as an IMAP server: {}", config.host, e))?;
+    client
+        .login(&config.username, password)
+        .map_err(|(e, _)| format!("Login to {} failed: {}", config.host, e))
+}
+
+/// Log in and open the mailbox; returns how many messages it holds.
+pub fn test(db: &Db, app_data_dir: &Path) -> Result<u32, String> {
+    let (config, password) = load(db, app_data_dir)?;
+    let mut session = connect(&config, &password)?;
+    let mailbox = session.select(&config.mailbox).map_err(|e| format!("Could not open {}: {}", config.mailbox, e))?;
+    let _ = session.logout();
+    Ok(mailbox.exists)
+}
+
+fn is_pdf(name: Option<&str>, content_type: Option<(&str, Option<&str>)>, contents: &[u8]) -> bool {
+    let by_type = content_type.is_some_and(|(ctype, subtype)| {
+        ctype.eq_ignore_ascii_case("application") && subtype.is_some_and(|s| s.eq_ignore_ascii_case("pdf"))
+    });
+    let by_name = name.is_some_and(|n| n.to_lowercase().ends_with(".pdf"));
+    (by_type || by_name) && contents.starts_with(b"%PDF")
+}
+
+/// Save the PDF attachments of one raw message and queue them; returns the new items.
+fn take_attachments(
+    db: &Db,
+    dir: &Path,
+    config: &MailboxConfig,
+    uid_validity: u32,
+    uid: u32,
+    raw: &[u8],
+) -> Result<Vec<IntakeItem>, String> {
+    let Some(message) = MessageParser::default().parse(raw) else {
+        app_warnings::report("email_intake", format!("message {} could not be parsed", uid));
+        return Ok(Vec::new());
+    };
+    let sender = message.from().and_then(|a| a.first()).and_then(|a| a.address()).map(str::to_string);
+    let subject = message.subject().map(str::to_string);
+    let received_at = message.date().map(|d| d.to_rfc3339());
+    let mut ids = Vec::new();
+    for (part, attachment) in message.attachments().enumerate() {
+        let name = attachment.attachment_name();
+        let content_type = attachment.content_type().map(|c| (c.ctype(), c.subtype()));
+        let contents = attachment.contents();
+        if !is_pdf(name, content_type, contents) {
+            continue;
+        }
+        let stem = name
+            .map(|n| n.trim().trim_end_matches(".pdf").trim_end_matches(".PDF"))
+            .map(sanitize_file_stem)
+            .filter(|n| !n.is_empty())
+            .unwrap_or_else(|| "attachment".to_string());
+        let file_name = format!("{}.pdf", stem);
+        let path = dir.join(format!("{}_{}_{}_{}", uid_validity, uid, part, file_name));
+        std::fs::write(&path, contents).map_err(|e| format!("Could not save {}: {}", path.display(), e))?;
+        let new_item = NewEmailIntakeItem {
+            mailbox: format!("{}/{}", config.host, config.mailbox),
+            uid_validity,
+            uid,
+            part: part as u32,
+            sender: sender.clone(),
+            subject: subject.clone(),
+            received_at: received_at.clone(),
+            file_name,
+            file_path: path.to_string_lossy().to_string(),
+        };
+        match db.add_email_intake_item(&new_item)? {
+            Some(id) => ids.push(id),
+            // Taken before (the cursor was reset); keep the file of the first download.
+            None => {
+                let _ = std::fs::remove_file(&path);
+            }
+        }
+    }
+    Ok(list(db, None)?.into_iter().filter(|i| ids.contains(&i.id)).collect())
+}
+
+/// Download new PDF attachments from the mailbox and queue them; returns the items added.
+pub fn poll(db: &Db, app_data_dir: &Path) -> Result<Vec<IntakeItem>, String> {
+    let (config, password) = load(db, app_data_dir)?;
+    let dir = intake_dir(app_data_dir);
+    std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let mut session = connect(&config, &password)?;
+    let mailbox = session.select(&config.mailbox).map_err(|e| format!("Could not open {}: {}", config.mailbox, e))?;
+    let uid_validity = mailbox.uid_validity.unwrap_or(0);
+    let stored: Option<Cursor> = db.get_settings()?.get(CURSOR_KEY).and_then(|v| serde_json::from_str(v).ok());
+    let last_uid = stored
+        .filter(|c| c.host == config.host && c.mailbox == config.mailbox && c.uid_validity == uid_validity)
+        .map(|c| c.last_uid);
+    let query = match last_uid {
+        Some(last) => format!("UID {}:*", last + 1),
+        None => {
+            let since = chrono::Local::now().date_naive() - chrono::Duration::days(FIRST_POLL_DAYS);
+            format!("SINCE {}", since.format("%d-%b-%Y"))
+        }
+    };
+    // "n:*" always matches the newest message, even when it is older than n.
+    let mut uids: Vec<u32> = session
+        .uid_search(&query)
+        .map_err(|e| format!("Search in {} failed: {}", config.mailbox, e))?
+        .into_iter()
+        .filter(|uid| last_uid.map_or(true, |last| *uid > last))
+        .collect();
+    uids.sort_unstable();
+
+    let mut added = Vec::new();
+    for uid in uids {
+        let fetches = session
+            .uid_fetch(uid.to_string(), "BODY.PEEK[]")
+            .map_err(|e| format!("Could not download message {}: {}", uid, e))?;
+        for raw in fetches.iter().filter_map(|f| f.body()) {
+            added.extend(take_attachments(db, &dir, &config, uid_validity, uid, raw)?);
+        }
+        if config.mark_seen {
+            if let Err(e) = session.uid_store(uid.to_string(), "+FLAGS (\\Seen)") {
+                app_warnings::report("email_intake", format!("could not mark message {} as read: {}", uid, e));
+            }
+        }
+        // Moved after each message, so a dropped connection does not download it again.
+        let cursor = Cursor {
+            host: config.host.clone(),
+            mailbox: config.mailbox.clone(),
+            uid_validity,
+            last_uid: uid,
+        };
+        db.set_settings(&[(CURSOR_KEY, Some(serde_json::to_string(&cursor).map_err(|e| e.to_string())?))])?;
+    }
+    let _ = session.logout();
+    Ok(added)
+}
+
+pub fn list(db: &Db, status: Option<&str>) -> Result<Vec<IntakeItem>, String> {
+    Ok(db.list_email_intake(status)?.into_iter().map(item).collect())
+}
+
+/// Mark items "scanned" or "dismissed"; the saved PDF of a dismissed item is deleted.
+pub fn set_status(db: &Db, ids: &[i64], status: &str) -> Result<(), String> {
+    if ![STATUS_QUEUED, STATUS_SCANNED, STATUS_DISMISSED].contains(&status) {
+        return Err(format!("Unknown intake status '{}'.", status));
+    }
+    db.set_email_intake_status(ids, status)?;
+    if status == STATUS_DISMISSED {
+        for item in list(db, Some(STATUS_DISMISSED))?.into_iter().filter(|i| ids.contains(&i.id)) {
+            let _ = std::fs::remove_file(&item.file_path);
+        }
+    }
+    Ok(())
+}
+
+/// Minutes between background polls; None when intake is off or has no password.
+pub fn poll_interval(db: &Db, app_data_dir: &Path) -> Result<Option<Duration>, String> {
+    let Some(settings) = get_settings(db, app_data_dir)? else {
+        return Ok(None);
+    };
+    Ok((settings.config.poll_minutes > 0 && settings.password_configured)
+        .then(|| Duration::from_secs(u64::from(settings.config.poll_minutes) * 60)))
+}
@@ -0,0 +1,318 @@
+//! Waiting for a workbook that is open in Excel: poll the write lock for a bounded time instead of
+//! failing immediately, with a "retry now" hook for when the user has just closed the file.
+//! Also reports who holds the lock (Restart Manager on Windows, Excel's `~$` owner file everywhere).
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+pub const FILE_LOCKED: &str = "Please close the file in Excel first.";
+pub const LOCK_WAIT_CANCELLED: &str = "Waiting for the Excel file was cancelled.";
+/// Upper bound for `wait_for_unlock_secs` so a forgotten dialog cannot hold a command forever.
+pub const MAX_WAIT_SECS: u64 = 300;
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+const TICK: Duration = Duration::from_millis(250);
+
+const SIGNAL_NONE: u8 = 0;
+const SIGNAL_RETRY_NOW: u8 = 1;
+const SIGNAL_CANCEL: u8 = 2;
+
+static LOCK_WAITS: OnceLock<Mutex<HashMap<String, Arc<AtomicU8>>>> = OnceLock::new();
+
+fn lock_waits() -> &'static Mutex<HashMap<String, Arc<AtomicU8>>> {
+    LOCK_WAITS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Payload of the `excel-lock-wait` event.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LockWaitProgress {
+    pub wait_id: String,
+    pub path: String,
+    /// "waiting", "unlocked", "timeout" or "cancelled"
+    pub status: String,
+    pub elapsed_secs: u64,
+    pub timeout_secs: u64,
+    pub attempts: u32,
+}
+
+/// True when the file exists and cannot be opened for writing (open in Excel, sharing violation).
+pub fn is_write_locked(path: &Path) -> bool {
+    if !path.exists() {
+        return false;
+    }
+    match std::fs::OpenOptions::new().write(true).open(path) {
+        Ok(_) => false,
+        // 32 = ERROR_SHARING_VIOLATION, 33 = ERROR_LOCK_VIOLATION on Windows.
+        Err(e) => e.kind() == std::io::ErrorKind::PermissionDenied || matches!(e.raw_os_error(), Some(32) | Some(33)),
+    }
+}
+
+/// A process (or remote user) holding the workbook open.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LockOwner {
+    /// Executable name, e.g. "EXCEL.EXE" (local processes only).
+    pub process_name: Option<String>,
+    pub process_id: Option<u32>,
+    /// Windows session user for local processes, or the name Excel records in its owner file
+    /// (which also works for users on other machines of a shared network drive).
+    pub user_name: Option<String>,
+}
+
+impl LockOwner {
+    fn describe(&self) -> String {
+        match (&self.process_name, &self.user_name) {
+            (Some(process), Some(user)) => format!("{} ({}'s session)", process, user),
+            (Some(process), None) => process.clone(),
+            (None, Some(user)) => format!("{} (Excel)", user),
+            (None, None) => "another program".to_string(),
+        }
+    }
+}
+
+/// Who currently holds `path` open; empty when unknown.
+pub fn find_lock_owners(path: &Path) -> Vec<LockOwner> {
+    #[allow(unused_mut)]
+    let mut owners: Vec<LockOwner> = Vec::new();
+    #[cfg(windows)]
+    owners.extend(restart_manager::lock_owners(path));
+    if let Some(user) = excel_owner_file_user(path) {
+        // Excel's owner file names the user even when the process is on another machine.
+        if owners.is_empty() {
+            owners.push(LockOwner {
+                process_name: None,
+                process_id: None,
+                user_name: Some(user),
+            });
+        } else {
+            for owner in owners.iter_mut().filter(|o| o.user_name.is_none()) {
+                owner.user_name = Some(user.clone());
+            }
+        }
+    }
+    owners
+}
+
+/// `FILE_LOCKED` plus who holds the lock, e.g. "... Locked by EXCEL.EXE (Maria's session)."
+pub fn locked_error<P: AsRef<Path>>(path: P) -> String {
+    let owners = find_lock_owners(path.as_ref());
+    if owners.is_empty() {
+        return FILE_LOCKED.to_string();
+    }
+    let who: Vec<String> = owners.iter().map(LockOwner::describe).collect();
+    format!("{} Locked by {}.", FILE_LOCKED, who.join(", "))
+}
+
+/// User name from Excel's `~$<name>.xlsx` owner file: a length byte and ANSI name at offset 0,
+/// followed by a UTF-16 copy (length-prefixed) at offset 54.
+fn excel_owner_file_user(path: &Path) -> Option<String> {
+    let file_name = path.file_name()?.to_string_lossy().to_string();
+    // Excel replaces the first two characters of long names with "~$".
+    let candidates = [
+        format!("~${}", file_name),
+        format!("~${}", file_name.chars().skip(2).collect::<String>()),
+    ];
+    let bytes = candidates
+        .iter()
+        .find_map(|name| std::fs::read(path.with_file_name(name)).ok())?;
+    let utf16 = bytes.get(54..56).and_then(|len| {
+        let len = u16::from_le_bytes([len[0], len[1]]) as usize;
+        let units: Vec<u16> = bytes
+            .get(56..56 + len * 2)?
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .collect();
+        String::from_utf16(&units).ok()
+    });
+    let name = utf16.or_else(|| {
+        let len = *bytes.first()? as usize;
+        bytes.get(1..1 + len).map(|b| String::from_utf8_lossy(b).to_string())
+    })?;
+    let name = name.trim().to_string();
+    (!name.is_empty()).then_some(name)
+}
+
+#[cfg(windows)]
+mod restart_manager {
+    use super::LockOwner;
+    use std::os::windows::ffi::OsStrExt;
+    use std::path::Path;
+    use windows_sys::Win32::Foundation::{CloseHandle, ERROR_MORE_DATA};
+    use windows_sys::Win32::System::RemoteDesktop::{
+        WTSFreeMemory, WTSQuerySessionInformationW, WTSUserName, WTS_CURRENT_SERVER_HANDLE,
+    };
+    use windows_sys::Win32::System::RestartManager::{
+        RmEndSession, RmGetList, RmRegisterResources, RmStartSession, CCH_RM_SESSION_KEY, RM_PROCESS_INFO,
+    };
+    use windows_sys::Win32::System::Threading::{
+        OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32, PROCESS_QUERY_LIMITED_INFORMATION,
+    };
+
+    fn wide_to_string(buf: &[u16]) -> String {
+        let end = buf.iter().position(|&c| c == 0).unwrap_or(buf.len());
+        String::from_utf16_lossy(&buf[..end])
+    }
+
+    fn process_image_name(pid: u32) -> Option<String> {
+        unsafe {
+            let handle = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+            if handle.is_null() {
+                return None;
+            }
+            let mut buf = [0u16; 1024];
+            let mut len = buf.len() as u32;
+            let ok = QueryFullProcessImageNameW(handle, PROCESS_NAME_WIN32, buf.as_mut_ptr(), &mut len);
+            CloseHandle(handle);
+            if ok == 0 {
+                return None;
+            }
+            let full = String::from_utf16_lossy(&buf[..len as usize]);
+            Path::new(&full).file_name().map(|n| n.to_string_lossy().to_string())
+        }
+    }
+
+    fn session_user_name(session_id: u32) -> Option<String> {
+        unsafe {
+            let mut buffer: *mut u16 = std::ptr::null_mut();
+            let mut bytes = 0u32;
+            if WTSQuerySessionInformationW(WTS_CURRENT_SERVER_HANDLE, session_id, WTSUserName, &mut buffer, &mut bytes) == 0
+                || buffer.is_null()
+            {
+                return None;
+            }
+            let units = std::slice::from_raw_parts(buffer, (bytes as usize) / 2);
+            let name = wide_to_string(units);
+            WTSFreeMemory(buffer.cast());
+            (!name.is_empty()).then_some(name)
+        }
+    }
+
+    /// Processes holding `path`, via the Restart Manager (local processes only).
+    pub fn lock_owners(path: &Path) -> Vec<LockOwner> {
+        let wide: Vec<u16> = path.as_os_str().encode_wide().chain(std::iter::once(0)).collect();
+        let mut session = 0u32;
+        let mut key = [0u16; CCH_RM_SESSION_KEY as usize + 1];
+        unsafe {
+            if RmStartSession(&mut session, 0, key.as_mut_ptr()) != 0 {
+                return Vec::new();
+            }
+            let files = [wide.as_ptr()];
+            let mut infos: Vec<RM_PROCESS_INFO> = Vec::new();
+            if RmRegisterResources(session, 1, files.as_ptr(), 0, std::ptr::null(), 0, std::ptr::null()) == 0 {
+                let mut needed = 0u32;
+                let mut count = 0u32;
+                let mut reasons = 0u32;
+                let mut result = RmGetList(session, &mut needed, &mut count, std::ptr::null_mut(), &mut reasons);
+                if result == ERROR_MORE_DATA && needed > 0 {
+                    infos = vec![std::mem::zeroed(); needed as usize];
+                    count = needed;
+                    result = RmGetList(session, &mut needed, &mut count, infos.as_mut_ptr(), &mut reasons);
+                    infos.truncate(if result == 0 { count as usize } else { 0 });
+                }
+            }
+            RmEndSession(session);
+            infos
+                .iter()
+                .map(|info| {
+                    let pid = info.Process.dwProcessId;
+                    let app_name = wide_to_string(&info.strAppName);
+                    LockOwner {
+                        process_name: process_image_name(pid).or((!app_name.is_empty()).then_some(app_name)),
+                        process_id: Some(pid),
+                        user_name: session_user_name(info.TSSessionId),
+                    }
+                })
+                .collect()
+        }
+    }
+}
+
+/// Ask a running wait to re-check the lock immediately. Returns false if no such wait is active.
+pub fn retry_now(wait_id: &str) -> bool {
+    signal(wait_id, SIGNAL_RETRY_NOW)
+}
+
+/// Stop a running wait; the command then fails with `LOCK_WAIT_CANCELLED`.
+pub fn cancel_wait(wait_id: &str) -> bool {
+    signal(wait_id, SIGNAL_CANCEL)
+}
+
+fn signal(wait_id: &str, value: u8) -> bool {
+    lock_waits()
+        .lock()
+        .ok()
+        .and_then(|waits| waits.get(wait_id).cloned())
+        .map(|flag| flag.store(value, Ordering::SeqCst))
+        .is_some()
+}
+
+struct Registration(String);
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        if let Ok(mut waits) = lock_waits().lock() {
+            waits.remove(&self.0);
+        }
+    }
+}
+
+/// Wait until `path` is writable, polling every couple of seconds for at most `timeout_secs`.
+/// Returns immediately when the file is not locked; fails with `FILE_LOCKED` on timeout.
+pub async fn wait_for_unlock(
+    path: &Path,
+    wait_id: &str,
+    timeout_secs: u64,
+    on_progress: &(dyn Fn(LockWaitProgress) + Send + Sync),
+) -> Result<(), String> {
+    if !is_write_locked(path) {
+        return Ok(());
+    }
+    let timeout_secs = timeout_secs.min(MAX_WAIT_SECS);
+    let flag = Arc::new(AtomicU8::new(SIGNAL_NONE));
+    if let Ok(mut waits) = lock_waits().lock() {
+        waits.insert(wait_id.to_string(), flag.clone());
+    }
+    let _registration = Registration(wait_id.to_string());
+    let started = Instant::now();
+    let mut attempts = 1u32;
+    let report = |status: &str, attempts: u32| {
+        on_progress(LockWaitProgress {
+            wait_id: wait_id.to_string(),
+            path: path.to_string_lossy().to_string(),
+            status: status.to_string(),
+            elapsed_secs: started.elapsed().as_secs(),
+            timeout_secs,
+            attempts,
+        })
+    };
+    report("waiting", attempts);
+
+    let mut since_poll = Duration::ZERO;
+    loop {
+        tokio::time::sleep(TICK).await;
+        since_poll += TICK;
+        let signal = flag.swap(SIGNAL_NONE, Ordering::SeqCst);
+        if signal == SIGNAL_CANCEL {
+            report("cancelled", attempts);
+            return Err(LOCK_WAIT_CANCELLED.to_string());
+        }
+        if signal != SIGNAL_RETRY_NOW && since_poll < POLL_INTERVAL {
+            continue;
+        }
+        since_poll = Duration::ZERO;
+        attempts += 1;
+        if !is_write_locked(path) {
+            report("unlocked", attempts);
+            return Ok(());
+        }
+        if started.elapsed().as_secs() >= timeout_secs {
+            report("timeout", attempts);
+            return Err(locked_error(path));
+        }
+        report("waiting", attempts);
+    }
+}
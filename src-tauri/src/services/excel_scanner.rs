@@ -1,7 +1,10 @@
 //! Excel structure and format scanning using edit-xlsx (1-based row/col).
 
 use crate::models::{ColumnFormat, HeaderInfo, RowTemplate};
-use edit_xlsx::Read;
+use crate::services::last_row::{self, LastRowScan};
+use crate::services::{header_match, perf_metrics, settings};
+use calamine::{open_workbook_auto, Data, Reader};
+use edit_xlsx::{Read, WorkSheetCol};
 use std::path::Path;
 
 const HEADER_KEYWORDS: &[&str] = &[
@@ -24,33 +27,40 @@ fn column_index_to_letter(index: u16) -> String {
     s
 }
 
-/// Detect header row by scanning rows 1..=20 for keyword matches (edit-xlsx uses 1-based rows).
-pub fn detect_header_row(workbook: &edit_xlsx::Workbook, sheet_name: &str) -> Result<u32, String> {
-    let sheet = workbook
-        .get_worksheet_by_name(sheet_name)
-        .map_err(|e| format!("Worksheet '{}' not found: {}", sheet_name, e))?;
+/// First of rows 1..=20 where at least three of columns 1..=20 hold a header keyword, else 1. Cells and
+/// keywords are compared folded (see `header_match`), allowing a typo in longer keywords. `cell_text`
+/// gives the text of a (1-based) cell.
+fn find_header_row(cell_text: impl Fn(u32, u32) -> String) -> u32 {
+    let keywords: Vec<String> = HEADER_KEYWORDS.iter().map(|k| header_match::fold(k)).collect();
     for row in 1..=20u32 {
         let mut keyword_count = 0u32;
         for col in 1..=20u32 {
-            if let Ok(cell) = sheet.read_cell((row, col)) {
-                let value = cell
-                    .text
-                    .as_deref()
-                    .unwrap_or("")
-                    .to_lowercase();
-                for keyword in HEADER_KEYWORDS {
-                    if value.contains(keyword) {
-                        keyword_count += 1;
-                        break;
-                    }
-                }
+            let value = header_match::fold(&cell_text(row, col));
+            if value.is_empty() {
+                continue;
+            }
+            if keywords
+                .iter()
+                .any(|keyword| value.contains(keyword.as_str()) || header_match::typo_distance(&value, keyword).is_some())
+            {
+                keyword_count += 1;
             }
         }
         if keyword_count >= 3 {
-            return Ok(row);
+            return row;
         }
     }
-    Ok(1)
+    1
+}
+
+/// Detect header row by scanning rows 1..=20 for keyword matches (edit-xlsx uses 1-based rows).
+pub fn detect_header_row(workbook: &edit_xlsx::Workbook, sheet_name: &str) -> Result<u32, String> {
+    let sheet = workbook
+        .get_worksheet_by_name(sheet_name)
+        .map_err(|e| format!("Worksheet '{}' not found: {}", sheet_name, e))?;
+    Ok(find_header_row(|row, col| {
+        sheet.read_cell((row, col)).ok().and_then(|cell| cell.text).unwrap_or_default()
+    }))
 }
 
 /// Extract headers from the given header row (1-based). Stops after 3 consecutive empty cells.
@@ -89,40 +99,25 @@ pub fn extract_headers(
     Ok(headers)
 }
 
-/// Find last row that has data in the first 20 columns. Stops after 100 consecutive empty rows.
+/// Find the last row with data in any used column, per the configured `last_row::LastRowStrategy`.
 pub fn find_last_data_row(
     workbook: &edit_xlsx::Workbook,
     sheet_name: &str,
     header_row: u32,
-) -> Result<u32, String> {
+) -> Result<LastRowScan, String> {
     let sheet = workbook
         .get_worksheet_by_name(sheet_name)
         .map_err(|e| format!("Worksheet not found: {}", e))?;
-    let start_row = header_row + 1;
-    let max_scan = start_row + 10_000;
-    let mut last_row = header_row;
-    let mut consecutive_empty = 0u32;
-    for row in start_row..=max_scan {
-        let mut has_data = false;
-        for col in 1..=20u32 {
-            if let Ok(cell) = sheet.read_cell((row, col)) {
-                let s = cell.text.as_deref().unwrap_or("").trim();
-                if !s.is_empty() {
-                    has_data = true;
-                    last_row = row;
-                    consecutive_empty = 0;
-                    break;
-                }
-            }
-        }
-        if !has_data {
-            consecutive_empty += 1;
-            if consecutive_empty >= 100 {
-                break;
-            }
-        }
-    }
-    Ok(last_row)
+    let max_col = sheet.max_column().max(1);
+    Ok(last_row::find_last_row(header_row, sheet.max_row(), settings::last_row_strategy(), |row| {
+        (1..=max_col).any(|col| {
+            sheet
+                .read_cell((row, col))
+                .ok()
+                .and_then(|cell| cell.text)
+                .is_some_and(|text| !text.trim().is_empty())
+        })
+    }))
 }
 
 /// FormatColor to hex string (best effort).
@@ -137,6 +132,14 @@ fn format_color_to_hex(color: &edit_xlsx::FormatColor) -> String {
     }
 }
 
+/// Cell fill as hex; theme and indexed fills have no RGB here and count as no fill rather than black.
+fn fill_to_hex(format: &edit_xlsx::Format) -> String {
+    match format.get_background_color() {
+        color @ edit_xlsx::FormatColor::RGB(..) => format_color_to_hex(color),
+        _ => "#FFFFFF".to_string(),
+    }
+}
+
 /// Extract ColumnFormat from a data row cell (1-based row/col).
 fn cell_to_column_format(
     workbook: &edit_xlsx::Workbook,
@@ -158,10 +161,15 @@ fn cell_to_column_format(
             let font_color = format_color_to_hex(fmt.get_color());
             let font_bold = fmt.is_bold();
             let font_italic = fmt.is_italic();
-            let background_color = format_color_to_hex(fmt.get_background_color());
+            let background_color = fill_to_hex(fmt);
             let border_style = "thin".to_string();
             let border_color = "#000000".to_string();
-            let alignment = "left".to_string();
+            let alignment = match fmt.align.horizontal {
+                Some(edit_xlsx::FormatAlignType::Center) => "center",
+                Some(edit_xlsx::FormatAlignType::Right) => "right",
+                _ => "left",
+            }
+            .to_string();
             let number_format = None::<String>;
             (font_name, font_size, font_color, font_bold, font_italic, background_color, border_style, border_color, alignment, number_format)
         } else {
@@ -181,7 +189,7 @@ fn cell_to_column_format(
     let alt_bg = if template_row + 1 <= sheet.max_row() {
         if let Ok(next_cell) = sheet.read_cell((template_row + 1, col_1based as u32)) {
             if let Some(ref next_fmt) = next_cell.format {
-                let next_bg = format_color_to_hex(next_fmt.get_background_color());
+                let next_bg = fill_to_hex(next_fmt);
                 if next_bg != background_color {
                     Some(next_bg)
                 } else {
@@ -198,7 +206,11 @@ fn cell_to_column_format(
     };
     let cell_text = cell.text.as_deref().unwrap_or("");
     let data_type = detect_data_type(cell_text);
-    let column_width = 10.0;
+    let column_width = sheet
+        .get_columns_width(format!("{0}:{0}", header.column_letter).as_str())
+        .ok()
+        .and_then(|widths| widths.into_values().flatten().next())
+        .unwrap_or(10.0);
     Ok(ColumnFormat {
         column_index: header.column_index,
         column_letter: header.column_letter.clone(),
@@ -224,6 +236,10 @@ fn detect_data_type(value: &str) -> String {
     if v.is_empty() {
         return "text".to_string();
     }
+    // "15.03.2024" would otherwise pass the number check below.
+    if crate::services::spending_report::parse_invoice_date(v).is_some() {
+        return "date".to_string();
+    }
     if v.parse::<f64>().is_ok() {
         return "number".to_string();
     }
@@ -252,6 +268,66 @@ pub fn analyze_column_formats(
     Ok(columns)
 }
 
+/// Column format for workbooks scanned read-only: no formats are read, so the defaults of an unformatted
+/// cell.
+fn default_column_format(header: &HeaderInfo) -> ColumnFormat {
+    ColumnFormat {
+        column_index: header.column_index,
+        column_letter: header.column_letter.clone(),
+        header_text: header.text.clone(),
+        font_name: "Arial".to_string(),
+        font_size: 11,
+        font_color: "#000000".to_string(),
+        font_bold: false,
+        font_italic: false,
+        background_color: "#FFFFFF".to_string(),
+        background_color_alt: None,
+        border_style: "thin".to_string(),
+        border_color: "#000000".to_string(),
+        alignment: "left".to_string(),
+        data_type: "text".to_string(),
+        number_format: None,
+        column_width: 10.0,
+    }
+}
+
+/// `scan_excel_file` for formats edit-xlsx cannot open (.xls, .xlsb, .ods, see `workbook_format`), read
+/// through calamine: header row, headers and last row the same way, default column formats. Such
+/// workbooks are only read, so nothing relies on the formats.
+#[allow(clippy::type_complexity)]
+pub fn scan_read_only(
+    path: &Path,
+    sheet_name: &str,
+) -> Result<(u32, Vec<HeaderInfo>, LastRowScan, u32, u32, Vec<ColumnFormat>, RowTemplate, u64, u64), String> {
+    let _timer = perf_metrics::timer(perf_metrics::Metric::SchemaScan);
+    let mut workbook = open_workbook_auto(path).map_err(|e| format!("Could not open Excel file: {}", e))?;
+    let sheet_name = crate::excel::resolve_sheet_name(&workbook.sheet_names(), sheet_name)?;
+    let range = workbook
+        .worksheet_range(&sheet_name)
+        .map_err(|e| format!("Worksheet not found: {}", e))?;
+    let header_row = find_header_row(|row, col| match range.get_value((row - 1, col - 1)) {
+        Some(Data::String(s)) => s.clone(),
+        _ => String::new(),
+    });
+    let headers = crate::excel::headers_in_range(&range, header_row);
+    if headers.is_empty() {
+        return Err("No headers found".to_string());
+    }
+    let last_data_row = crate::excel::range_last_row(&range, header_row);
+    let next_free_row = last_data_row.last_row + 1;
+    let columns = headers.iter().map(default_column_format).collect();
+    let row_template = RowTemplate { template_row_index: header_row + 1, row_height: 15.0, use_alternating_colors: false };
+    let total_rows = range.end().map_or(0, |(row, _)| row + 1);
+    let metadata = std::fs::metadata(path).map_err(|e| format!("Failed to read file metadata: {}", e))?;
+    let file_mtime = metadata
+        .modified()
+        .map_err(|e| format!("Failed to get modification time: {}", e))?
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    Ok((header_row, headers, last_data_row, next_free_row, total_rows, columns, row_template, metadata.len(), file_mtime))
+}
+
 /// Full scan: open workbook and return (header_row, headers, last_data_row, next_free_row, total_rows, columns, row_template, file_size, file_mtime).
 pub fn scan_excel_file(
     path: &Path,
@@ -260,7 +336,7 @@ pub fn scan_excel_file(
     (
         u32,
         Vec<HeaderInfo>,
-        u32,
+        LastRowScan,
         u32,
         u32,
         Vec<ColumnFormat>,
@@ -270,16 +346,19 @@ pub fn scan_excel_file(
     ),
     String,
 > {
+    let _timer = perf_metrics::timer(perf_metrics::Metric::SchemaScan);
     let mut workbook =
         edit_xlsx::Workbook::from_path(path).map_err(|e| format!("Could not open Excel file: {}", e))?;
     workbook.finish();
+    let sheet_name = crate::excel::resolve_workbook_sheet_name(&workbook, sheet_name)?;
+    let sheet_name = sheet_name.as_str();
     let header_row = detect_header_row(&workbook, sheet_name)?;
     let headers = extract_headers(&workbook, sheet_name, header_row)?;
     if headers.is_empty() {
         return Err("No headers found".to_string());
     }
     let last_data_row = find_last_data_row(&workbook, sheet_name, header_row)?;
-    let next_free_row = last_data_row + 1;
+    let next_free_row = last_data_row.last_row + 1;
     let template_row = header_row + 1;
     let columns = analyze_column_formats(&workbook, sheet_name, &headers, template_row)?;
     let sheet = workbook
@@ -0,0 +1,389 @@
+//! Delivery of finished exports to the accountant's destination: a network share, or per profile a share
+//! or an SFTP drop folder of the ERP. The copy is written under a temporary name, verified by SHA-256
+//! against the source, then renamed into place; transient failures are retried and every delivery is
+//! recorded in the database.
+
+use crate::db::{Db, ProfileDeliveryRow};
+use crate::services::secrets;
+use crate::services::sftp::{self, SftpTarget};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const SHARE_PATH: &str = "delivery_share_path";
+const MAX_ATTEMPTS: u32 = 3;
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(2);
+const DEFAULT_SFTP_PORT: u16 = 22;
+
+pub(crate) fn private_key_secret(profile_id: i64) -> String {
+    format!("sftp_private_key.{}", profile_id)
+}
+
+pub(crate) fn passphrase_secret(profile_id: i64) -> String {
+    format!("sftp_key_passphrase.{}", profile_id)
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeliverySettings {
+    pub share_path: Option<String>,
+}
+
+/// None leaves a value unchanged, an empty string clears it.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeliverySettingsUpdate {
+    pub share_path: Option<String>,
+}
+
+/// Delivery destination of a profile as shown in the UI (secrets are never returned).
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileDelivery {
+    pub profile_id: i64,
+    /// "share" or "sftp"
+    pub kind: String,
+    pub share_path: Option<String>,
+    pub sftp_host: Option<String>,
+    pub sftp_port: Option<u16>,
+    pub sftp_username: Option<String>,
+    pub sftp_directory: Option<String>,
+    pub sftp_host_fingerprint: Option<String>,
+    pub private_key_configured: bool,
+}
+
+/// Replaces the profile's destination. For the key and passphrase None keeps the stored value and
+/// an empty string removes it.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileDeliveryUpdate {
+    pub kind: String,
+    pub share_path: Option<String>,
+    pub sftp_host: Option<String>,
+    pub sftp_port: Option<u16>,
+    pub sftp_username: Option<String>,
+    pub sftp_directory: Option<String>,
+    pub private_key: Option<String>,
+    pub passphrase: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportDelivery {
+    pub id: i64,
+    pub profile_id: Option<i64>,
+    pub source_path: String,
+    /// Full path of the delivered copy.
+    pub destination: String,
+    pub sha256: String,
+    pub size_bytes: u64,
+    /// "delivered" or "failed"
+    pub status: String,
+    pub attempts: u32,
+    pub error: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone)]
+pub enum DeliveryTarget {
+    Share(PathBuf),
+    Sftp { profile_id: i64, target: SftpTarget },
+}
+
+pub fn get_delivery_settings(db: &Db) -> Result<DeliverySettings, String> {
+    let stored = db.get_settings()?;
+    Ok(DeliverySettings {
+        share_path: stored.get(SHARE_PATH).filter(|v| !v.trim().is_empty()).cloned(),
+    })
+}
+
+pub fn save_delivery_settings(db: &Db, update: DeliverySettingsUpdate) -> Result<DeliverySettings, String> {
+    if let Some(share) = update.share_path {
+        let share = share.trim().to_string();
+        if !share.is_empty() && !Path::new(&share).is_dir() {
+            return Err(format!("Network share is not reachable: {}", share));
+        }
+        db.set_settings(&[(SHARE_PATH, Some(share).filter(|s| !s.is_empty()))])?;
+    }
+    get_delivery_settings(db)
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+    value.map(|v| v.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+pub fn get_profile_delivery(db: &Db, profile_id: i64) -> Result<Option<ProfileDelivery>, String> {
+    let Some(row) = db.get_profile_delivery_target(profile_id)? else {
+        return Ok(None);
+    };
+    Ok(Some(ProfileDelivery {
+        profile_id,
+        kind: row.kind,
+        share_path: row.share_path,
+        sftp_host: row.sftp_host,
+        sftp_port: row.sftp_port,
+        sftp_username: row.sftp_username,
+        sftp_directory: row.sftp_directory,
+        sftp_host_fingerprint: row.sftp_host_fingerprint,
+        private_key_configured: secrets::get_secret(&private_key_secret(profile_id)).ok().flatten().is_some(),
+    }))
+}
+
+/// Validate and store the destination of a profile; the key and passphrase go to the credential store.
+/// Changing the host or port forgets the pinned host key.
+pub fn save_profile_delivery(db: &Db, profile_id: i64, update: ProfileDeliveryUpdate) -> Result<ProfileDelivery, String> {
+    db.get_profile_by_id(profile_id)?;
+    let previous = db.get_profile_delivery_target(profile_id)?;
+    let row = match update.kind.as_str() {
+        "share" => {
+            let share = non_empty(update.share_path).ok_or("Network share path is required.")?;
+            if !Path::new(&share).is_dir() {
+                return Err(format!("Network share is not reachable: {}", share));
+            }
+            ProfileDeliveryRow {
+                kind: update.kind.clone(),
+                share_path: Some(share),
+                ..Default::default()
+            }
+        }
+        "sftp" => {
+            let host = non_empty(update.sftp_host).ok_or("SFTP host is required.")?;
+            let port = update.sftp_port.unwrap_or(DEFAULT_SFTP_PORT);
+            let username = non_empty(update.sftp_username).ok_or("SFTP user name is required.")?;
+            let directory = non_empty(update.sftp_directory).unwrap_or_else(|| ".".to_string());
+            let fingerprint = previous
+                .filter(|p| p.sftp_host.as_deref() == Some(host.as_str()) && p.sftp_port == Some(port))
+                .and_then(|p| p.sftp_host_fingerprint);
+            ProfileDeliveryRow {
+                kind: update.kind.clone(),
+                sftp_host: Some(host),
+                sftp_port: Some(port),
+                sftp_username: Some(username),
+                sftp_directory: Some(directory),
+                sftp_host_fingerprint: fingerprint,
+                ..Default::default()
+            }
+        }
+        other => return Err(format!("Unknown delivery destination: {}", other)),
+    };
+    for (name, value) in [
+        (private_key_secret(profile_id), update.private_key),
+        (passphrase_secret(profile_id), update.passphrase),
+    ] {
+        match value.map(|v| v.trim().to_string()) {
+            Some(v) if !v.is_empty() => secrets::set_secret(&name, &v)?,
+            Some(_) => secrets::delete_secret(&name)?,
+            None => {}
+        }
+    }
+    if row.kind == "sftp" && secrets::get_secret(&private_key_secret(profile_id))?.is_none() {
+        return Err("An SSH private key is required for SFTP delivery.".to_string());
+    }
+    db.save_profile_delivery_target(profile_id, &row)?;
+    get_profile_delivery(db, profile_id)?.ok_or_else(|| "Profile not found.".to_string())
+}
+
+/// Remove the profile's destination and its stored key.
+pub fn remove_profile_delivery(db: &Db, profile_id: i64) -> Result<(), String> {
+    secrets::delete_secret(&private_key_secret(profile_id))?;
+    secrets::delete_secret(&passphrase_secret(profile_id))?;
+    Ok(db.delete_profile_delivery_target(profile_id)?)
+}
+
+fn profile_target(db: &Db, profile_id: i64) -> Result<Option<DeliveryTarget>, String> {
+    let Some(row) = db.get_profile_delivery_target(profile_id)? else {
+        return Ok(None);
+    };
+    if row.kind != "sftp" {
+        return Ok(row.share_path.map(|p| DeliveryTarget::Share(PathBuf::from(p))));
+    }
+    let private_key = secrets::get_secret(&private_key_secret(profile_id))?
+        .ok_or("The SSH private key of this destination is missing. Save the destination again.")?;
+    Ok(Some(DeliveryTarget::Sftp {
+        profile_id,
+        target: SftpTarget {
+            host: row.sftp_host.unwrap_or_default(),
+            port: row.sftp_port.unwrap_or(DEFAULT_SFTP_PORT),
+            username: row.sftp_username.unwrap_or_default(),
+            directory: row.sftp_directory.unwrap_or_else(|| ".".to_string()),
+            host_fingerprint: row.sftp_host_fingerprint,
+            private_key,
+            passphrase: secrets::get_secret(&passphrase_secret(profile_id))?,
+        },
+    }))
+}
+
+/// Destination from `override_path`, else the profile's destination, else the default share in settings.
+fn delivery_target(db: &Db, profile_id: Option<i64>, override_path: Option<&str>) -> Result<DeliveryTarget, String> {
+    if let Some(p) = override_path.map(str::trim).filter(|p| !p.is_empty()) {
+        return Ok(DeliveryTarget::Share(PathBuf::from(p)));
+    }
+    if let Some(target) = profile_id.map(|id| profile_target(db, id)).transpose()?.flatten() {
+        return Ok(target);
+    }
+    let share = get_delivery_settings(db)?
+        .share_path
+        .ok_or("No delivery destination is configured.")?;
+    Ok(DeliveryTarget::Share(PathBuf::from(share)))
+}
+
+/// Pin the host key after the first successful SFTP connection.
+fn remember_fingerprint(db: &Db, profile_id: i64, target: &SftpTarget, fingerprint: &str) -> Result<(), String> {
+    if target.host_fingerprint.is_none() {
+        db.set_profile_delivery_fingerprint(profile_id, fingerprint)?;
+    }
+    Ok(())
+}
+
+/// Check that the profile's destination is reachable (SFTP: log in and stat the folder).
+pub fn test_profile_delivery(db: &Db, profile_id: i64) -> Result<ProfileDelivery, String> {
+    match profile_target(db, profile_id)?.ok_or("No delivery destination is configured for this profile.")? {
+        DeliveryTarget::Share(root) => {
+            if !root.is_dir() {
+                return Err(format!("Network share is not reachable: {}", root.display()));
+            }
+        }
+        DeliveryTarget::Sftp { profile_id, target } => {
+            let fingerprint = sftp::check(&target).map_err(|e| e.message)?;
+            remember_fingerprint(db, profile_id, &target, &fingerprint)?;
+        }
+    }
+    get_profile_delivery(db, profile_id)?.ok_or_else(|| "Profile not found.".to_string())
+}
+
+pub(crate) fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// Copy under a `.partial` name, verify the hash, then rename over any previous copy.
+pub(crate) fn copy_verified(src: &Path, dest: &Path, expected_sha256: &str) -> Result<(), String> {
+    let dir = dest.parent().ok_or("Invalid destination path.")?;
+    fs::create_dir_all(dir).map_err(|e| format!("Could not create {}: {}", dir.display(), e))?;
+    let partial = dest.with_extension(format!(
+        "{}.partial",
+        dest.extension().map(|e| e.to_string_lossy().to_string()).unwrap_or_default()
+    ));
+    let result = fs::copy(src, &partial)
+        .map_err(|e| format!("Copy failed: {}", e))
+        .and_then(|_| sha256_file(&partial).map_err(|e| format!("Could not read the copy: {}", e)))
+        .and_then(|actual| {
+            if actual == expected_sha256 {
+                Ok(())
+            } else {
+                Err("Checksum mismatch: the copy differs from the source.".to_string())
+            }
+        })
+        .and_then(|_| fs::rename(&partial, dest).map_err(|e| format!("Could not replace {}: {}", dest.display(), e)));
+    if result.is_err() {
+        let _ = fs::remove_file(&partial);
+    }
+    result
+}
+
+impl DeliveryTarget {
+    fn destination_for(&self, file_name: &str) -> String {
+        match self {
+            DeliveryTarget::Share(root) => root.join(file_name).to_string_lossy().to_string(),
+            DeliveryTarget::Sftp { target, .. } => target.url(file_name),
+        }
+    }
+
+    /// Err carries (message, retryable).
+    fn deliver(&self, db: &Db, src: &Path, file_name: &str, sha256: &str) -> Result<(), (String, bool)> {
+        match self {
+            DeliveryTarget::Share(root) => copy_verified(src, &root.join(file_name), sha256).map_err(|e| (e, true)),
+            DeliveryTarget::Sftp { profile_id, target } => {
+                let fingerprint =
+                    sftp::upload_verified(target, src, file_name, sha256).map_err(|e| (e.message, e.retryable))?;
+                remember_fingerprint(db, *profile_id, target, &fingerprint).map_err(|e| (e, false))
+            }
+        }
+    }
+}
+
+/// Deliver the export at `source_path` (retrying transient failures) and record the outcome.
+/// `destination` overrides the profile's or the default destination with a folder.
+pub fn deliver_export(
+    db: &Db,
+    profile_id: Option<i64>,
+    source_path: &str,
+    destination: Option<&str>,
+) -> Result<ExportDelivery, String> {
+    let src = Path::new(source_path);
+    let size_bytes = fs::metadata(src)
+        .map_err(|e| format!("Could not read {}: {}", src.display(), e))?
+        .len();
+    let sha256 = sha256_file(src).map_err(|e| format!("Could not read {}: {}", src.display(), e))?;
+    let file_name = src
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .ok_or("Invalid file path.")?;
+    let target = delivery_target(db, profile_id, destination)?;
+    let dest = target.destination_for(&file_name);
+
+    let mut attempts = 0;
+    let mut last_error = None;
+    while attempts < MAX_ATTEMPTS {
+        if attempts > 0 {
+            std::thread::sleep(RETRY_BASE_DELAY * 2u32.pow(attempts - 1));
+        }
+        attempts += 1;
+        match target.deliver(db, src, &file_name, &sha256) {
+            Ok(()) => {
+                last_error = None;
+                break;
+            }
+            Err((message, retryable)) => {
+                last_error = Some(message);
+                if !retryable {
+                    break;
+                }
+            }
+        }
+    }
+
+    let status = if last_error.is_none() { "delivered" } else { "failed" };
+    let id = db.add_export_delivery(profile_id, source_path, &dest, &sha256, size_bytes, status, attempts, last_error.as_deref())?;
+    if let Some(e) = last_error {
+        return Err(format!("Delivery to {} failed after {} attempts: {}", dest, attempts, e));
+    }
+    Ok(ExportDelivery {
+        id,
+        profile_id,
+        source_path: source_path.to_string(),
+        destination: dest,
+        sha256,
+        size_bytes,
+        status: status.to_string(),
+        attempts,
+        error: None,
+        created_at: chrono::Utc::now().to_rfc3339(),
+    })
+}
+
+pub fn list_export_deliveries(db: &Db, profile_id: Option<i64>, limit: u32) -> Result<Vec<ExportDelivery>, String> {
+    Ok(db
+        .list_export_deliveries(profile_id, limit)?
+        .into_iter()
+        .map(
+            |(id, source_path, destination, sha256, size_bytes, status, attempts, error, created_at, profile_id)| ExportDelivery {
+                id,
+                profile_id,
+                source_path,
+                destination,
+                sha256,
+                size_bytes,
+                status,
+                attempts,
+                error,
+                created_at,
+            },
+        )
+        .collect())
+}
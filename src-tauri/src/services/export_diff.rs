@@ -0,0 +1,356 @@
+//! Row-level diff of two exports (CSV or Excel) keyed by a shared column.
+//! Replaces the manual VLOOKUP comparison of our export against an ERP dump.
+
+use crate::services::workbook_compat;
+use calamine::{open_workbook_auto, Reader};
+use serde::Serialize;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// A row present in only one of the two files.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffRow {
+    pub key: String,
+    /// 1-based row number in the source file.
+    pub row_number: u32,
+    pub values: HashMap<String, String>,
+}
+
+/// One differing cell of a row present in both files.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CellChange {
+    pub column: String,
+    pub value_a: String,
+    pub value_b: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChangedRow {
+    pub key: String,
+    pub row_number_a: u32,
+    pub row_number_b: u32,
+    pub changes: Vec<CellChange>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportDiff {
+    pub key_column: String,
+    /// Columns compared (present in both files, by header text).
+    pub compared_columns: Vec<String>,
+    pub only_in_a_columns: Vec<String>,
+    pub only_in_b_columns: Vec<String>,
+    /// Rows whose key is in B but not in A.
+    pub added: Vec<DiffRow>,
+    /// Rows whose key is in A but not in B.
+    pub removed: Vec<DiffRow>,
+    pub changed: Vec<ChangedRow>,
+    pub unchanged_count: usize,
+    /// Keys that appear more than once in a file; only the first occurrence is compared.
+    pub duplicate_keys_a: Vec<String>,
+    pub duplicate_keys_b: Vec<String>,
+}
+
+/// Parsed table: header row plus data rows with their 1-based row numbers.
+struct Table {
+    headers: Vec<String>,
+    rows: Vec<(u32, Vec<String>)>,
+}
+
+fn is_csv(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase()).as_deref(),
+        Some("csv") | Some("txt")
+    )
+}
+
+/// Pick the delimiter that appears most often in the header line (ERP dumps often use ';').
+fn detect_delimiter(first_line: &str) -> char {
+    [';', ',', '\t']
+        .into_iter()
+        .max_by_key(|d| first_line.matches(*d).count())
+        .unwrap_or(',')
+}
+
+/// Minimal RFC 4180 parser: quoted fields, doubled quotes, newlines inside quotes.
+fn parse_csv(text: &str, delimiter: char) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            if c == '"' {
+                if chars.peek() == Some(&'"') {
+                    field.push('"');
+                    chars.next();
+                } else {
+                    in_quotes = false;
+                }
+            } else {
+                field.push(c);
+            }
+        } else if c == '"' {
+            in_quotes = true;
+        } else if c == delimiter {
+            row.push(std::mem::take(&mut field));
+        } else if c == '\n' || c == '\r' {
+            if c == '\r' && chars.peek() == Some(&'\n') {
+                chars.next();
+            }
+            row.push(std::mem::take(&mut field));
+            rows.push(std::mem::take(&mut row));
+        } else {
+            field.push(c);
+        }
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    rows
+}
+
+fn read_csv_rows(path: &Path) -> Result<Vec<Vec<String>>, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+    let bytes = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(&bytes);
+    let text = String::from_utf8_lossy(bytes);
+    let first_line = text.lines().next().unwrap_or("");
+    Ok(parse_csv(&text, detect_delimiter(first_line)))
+}
+
+/// Rows of the first sheet, padded so that row and column indexes are sheet positions even when the
+/// used range does not start at A1.
+fn read_excel_rows(path: &Path) -> Result<Vec<Vec<String>>, String> {
+    let mut workbook = open_workbook_auto(path).map_err(|e| format!("Could not open Excel file: {}", e))?;
+    let sheet_name = workbook
+        .sheet_names()
+        .first()
+        .cloned()
+        .ok_or_else(|| format!("{} has no sheets", path.display()))?;
+    let range = workbook
+        .worksheet_range(&sheet_name)
+        .map_err(|e| format!("Sheet not found: {}", e))?;
+    let date1904 = workbook_compat::uses_1904_dates(path);
+    let (first_row, first_col) = range.start().unwrap_or((0, 0));
+    let leading_rows = (0..first_row).map(|_| Vec::new());
+    let rows = range.rows().map(|row| {
+        vec![String::new(); first_col as usize]
+            .into_iter()
+            .chain(row.iter().map(|c| workbook_compat::cell_text(c, date1904)))
+            .collect()
+    });
+    Ok(leading_rows.chain(rows).collect())
+}
+
+/// Load a CSV or Excel export. The first non-empty row is treated as the header row.
+fn load_table(path: &str) -> Result<Table, String> {
+    let path = Path::new(path);
+    if !path.exists() {
+        return Err(format!("File not found: {}", path.display()));
+    }
+    let raw = if is_csv(path) { read_csv_rows(path)? } else { read_excel_rows(path)? };
+    let is_blank = |r: &Vec<String>| r.iter().all(|c| c.trim().is_empty());
+    let header_idx = raw
+        .iter()
+        .position(|r| !is_blank(r))
+        .ok_or_else(|| format!("{} is empty", path.display()))?;
+    let headers = raw[header_idx].iter().map(|h| h.trim().to_string()).collect();
+    let rows = raw
+        .into_iter()
+        .enumerate()
+        .skip(header_idx + 1)
+        .filter(|(_, r)| !is_blank(r))
+        .map(|(i, r)| (i as u32 + 1, r))
+        .collect();
+    Ok(Table { headers, rows })
+}
+
+fn header_key(header: &str) -> String {
+    header.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+/// Locate the key column by header text (case/whitespace-insensitive) or by column letter (e.g. "B").
+fn find_key_index(headers: &[String], key_column: &str) -> Option<usize> {
+    let wanted = header_key(key_column);
+    if let Some(i) = headers.iter().position(|h| header_key(h) == wanted) {
+        return Some(i);
+    }
+    let letters = key_column.trim();
+    if letters.is_empty() || letters.len() > 3 || !letters.chars().all(|c| c.is_ascii_alphabetic()) {
+        return None;
+    }
+    let idx = letters
+        .to_ascii_uppercase()
+        .bytes()
+        .fold(0usize, |acc, b| acc * 26 + (b - b'A' + 1) as usize);
+    (idx >= 1 && idx <= headers.len()).then(|| idx - 1)
+}
+
+fn normalize_key(value: &str) -> String {
+    value.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+/// Cells are equal if their trimmed text matches or both parse to the same amount
+/// (so "1.234,50" in the ERP dump matches 1234.5 in our export).
+fn values_equal(a: &str, b: &str) -> bool {
+    let (a, b) = (a.trim(), b.trim());
+    if a == b {
+        return true;
+    }
+    let parse = |s: &str| crate::excel::normalize_amount_string(s).parse::<f64>().ok();
+    match (parse(a), parse(b)) {
+        (Some(x), Some(y)) => (x - y).abs() < 0.005,
+        _ => false,
+    }
+}
+
+/// Index rows by normalized key. Returns the index and the keys seen more than once.
+fn index_rows(table: &Table, key_idx: usize) -> (HashMap<String, usize>, Vec<String>) {
+    let mut index = HashMap::new();
+    let mut duplicates = Vec::new();
+    for (pos, (_, row)) in table.rows.iter().enumerate() {
+        let key = normalize_key(row.get(key_idx).map(String::as_str).unwrap_or(""));
+        if key.is_empty() {
+            continue;
+        }
+        match index.entry(key) {
+            Entry::Occupied(e) => {
+                if !duplicates.contains(e.key()) {
+                    duplicates.push(e.key().clone());
+                }
+            }
+            Entry::Vacant(e) => {
+                e.insert(pos);
+            }
+        }
+    }
+    (index, duplicates)
+}
+
+fn row_values(headers: &[String], row: &[String]) -> HashMap<String, String> {
+    headers
+        .iter()
+        .enumerate()
+        .filter(|(_, h)| !h.is_empty())
+        .map(|(i, h)| (h.clone(), row.get(i).cloned().unwrap_or_default()))
+        .collect()
+}
+
+/// Compare two exports keyed by `key_column` (header text or column letter).
+/// Rows without a key are ignored; columns are matched by header text, not position.
+pub fn diff_exports(path_a: &str, path_b: &str, key_column: &str) -> Result<ExportDiff, String> {
+    let a = load_table(path_a)?;
+    let b = load_table(path_b)?;
+    let key_a = find_key_index(&a.headers, key_column)
+        .ok_or_else(|| format!("Key column '{}' not found in {}", key_column, path_a))?;
+    let key_b = find_key_index(&b.headers, key_column)
+        .ok_or_else(|| format!("Key column '{}' not found in {}", key_column, path_b))?;
+
+    let b_columns: HashMap<String, usize> = b
+        .headers
+        .iter()
+        .enumerate()
+        .filter(|(_, h)| !h.is_empty())
+        .map(|(i, h)| (header_key(h), i))
+        .collect();
+    let a_keys: Vec<String> = a.headers.iter().map(|h| header_key(h)).collect();
+    // (column label, index in A, index in B), excluding the key column itself
+    let compared: Vec<(String, usize, usize)> = a
+        .headers
+        .iter()
+        .enumerate()
+        .filter(|(i, h)| *i != key_a && !h.is_empty())
+        .filter_map(|(i, h)| b_columns.get(&header_key(h)).map(|&j| (h.clone(), i, j)))
+        .filter(|(_, _, j)| *j != key_b)
+        .collect();
+    let only_in_a_columns = a
+        .headers
+        .iter()
+        .filter(|h| !h.is_empty() && !b_columns.contains_key(&header_key(h)))
+        .cloned()
+        .collect();
+    let only_in_b_columns = b
+        .headers
+        .iter()
+        .filter(|h| !h.is_empty() && !a_keys.contains(&header_key(h)))
+        .cloned()
+        .collect();
+
+    let (index_a, duplicate_keys_a) = index_rows(&a, key_a);
+    let (index_b, duplicate_keys_b) = index_rows(&b, key_b);
+
+    let mut removed = Vec::new();
+    let mut changed = Vec::new();
+    let mut unchanged_count = 0usize;
+    let mut ordered_a: Vec<(&String, &usize)> = index_a.iter().collect();
+    ordered_a.sort_by_key(|(_, pos)| **pos);
+    for (key, &pos_a) in ordered_a {
+        let (row_number_a, row_a) = &a.rows[pos_a];
+        let display_key = row_a.get(key_a).cloned().unwrap_or_default().trim().to_string();
+        match index_b.get(key) {
+            None => removed.push(DiffRow {
+                key: display_key,
+                row_number: *row_number_a,
+                values: row_values(&a.headers, row_a),
+            }),
+            Some(&pos_b) => {
+                let (row_number_b, row_b) = &b.rows[pos_b];
+                let changes: Vec<CellChange> = compared
+                    .iter()
+                    .filter_map(|(column, i, j)| {
+                        let va = row_a.get(*i).map(String::as_str).unwrap_or("");
+                        let vb = row_b.get(*j).map(String::as_str).unwrap_or("");
+                        (!values_equal(va, vb)).then(|| CellChange {
+                            column: column.clone(),
+                            value_a: va.to_string(),
+                            value_b: vb.to_string(),
+                        })
+                    })
+                    .collect();
+                if changes.is_empty() {
+                    unchanged_count += 1;
+                } else {
+                    changed.push(ChangedRow {
+                        key: display_key,
+                        row_number_a: *row_number_a,
+                        row_number_b: *row_number_b,
+                        changes,
+                    });
+                }
+            }
+        }
+    }
+
+    let mut ordered_b: Vec<(&String, &usize)> = index_b.iter().filter(|(k, _)| !index_a.contains_key(*k)).collect();
+    ordered_b.sort_by_key(|(_, pos)| **pos);
+    let added = ordered_b
+        .into_iter()
+        .map(|(_, &pos_b)| {
+            let (row_number, row) = &b.rows[pos_b];
+            DiffRow {
+                key: row.get(key_b).cloned().unwrap_or_default().trim().to_string(),
+                row_number: *row_number,
+                values: row_values(&b.headers, row),
+            }
+        })
+        .collect();
+
+    Ok(ExportDiff {
+        key_column: a.headers[key_a].clone(),
+        compared_columns: compared.into_iter().map(|(c, _, _)| c).collect(),
+        only_in_a_columns,
+        only_in_b_columns,
+        added,
+        removed,
+        changed,
+        unchanged_count,
+        duplicate_keys_a,
+        duplicate_keys_b,
+    })
+}
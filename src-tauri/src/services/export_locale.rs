@@ -0,0 +1,190 @@
+//! Output locale of a profile's exports: the separators of amounts written as text (`27.826,17` vs
+//! `27,826.17`), whether number cells show thousands grouping, and the order of date parts. Number cells
+//! keep their value and are shown with the separators of the Excel that opens them, so only text needs
+//! converting. Dates are rewritten in the locale's order, or with `excel_dates` written as Excel date cells
+//! (shown in that order, and sorting as dates). Kept in settings (`export_locale.<profile id>`); without one
+//! exports keep `27,826.17` and dates as scanned.
+
+use crate::db::Db;
+use crate::services::spending_report::parse_invoice_date;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+const LOCALE_KEY: &str = "export_locale";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DateOrder {
+    /// 15.03.2024
+    Dmy,
+    /// 03/15/2024
+    Mdy,
+    /// 2024-03-15
+    Ymd,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportLocale {
+    /// "." or ",".
+    pub decimal_separator: String,
+    /// "", ",", ".", " " or "'"; must differ from the decimal separator.
+    pub thousands_separator: String,
+    /// None keeps dates as scanned.
+    #[serde(default)]
+    pub date_order: Option<DateOrder>,
+    /// ".", "/" or "-".
+    #[serde(default = "default_date_separator")]
+    pub date_separator: String,
+    /// Write recognized dates as date cells instead of text.
+    #[serde(default)]
+    pub excel_dates: bool,
+}
+
+fn default_date_separator() -> String {
+    ".".to_string()
+}
+
+impl Default for ExportLocale {
+    fn default() -> Self {
+        ExportLocale {
+            decimal_separator: ".".to_string(),
+            thousands_separator: ",".to_string(),
+            date_order: None,
+            date_separator: default_date_separator(),
+            excel_dates: false,
+        }
+    }
+}
+
+impl ExportLocale {
+    /// Two decimals with this locale's separators (e.g. 27826.17 -> "27.826,17").
+    pub fn amount(&self, n: f64) -> String {
+        self.number(n, 2, true)
+    }
+
+    /// `decimals` places with this locale's decimal separator, grouped by its thousands separator when
+    /// `grouped` (e.g. a template column formatted `#,##0`).
+    pub fn number(&self, n: f64, decimals: usize, grouped: bool) -> String {
+        let s = format!("{:.*}", decimals, n.abs());
+        let (digits, fraction) = s.split_once('.').unwrap_or((s.as_str(), ""));
+        let mut out = String::new();
+        if n < 0.0 && s.chars().any(|c| c.is_ascii_digit() && c != '0') {
+            out.push('-');
+        }
+        let len = digits.len();
+        for (i, c) in digits.chars().enumerate() {
+            if grouped && i > 0 && (len - i) % 3 == 0 {
+                out.push_str(&self.thousands_separator);
+            }
+            out.push(c);
+        }
+        if !fraction.is_empty() {
+            out.push_str(&self.decimal_separator);
+            out.push_str(fraction);
+        }
+        out
+    }
+
+    /// chrono pattern of this locale's dates; None keeps dates as scanned.
+    fn date_pattern(&self) -> Option<String> {
+        let sep = &self.date_separator;
+        Some(match self.date_order? {
+            DateOrder::Dmy => format!("%d{0}%m{0}%Y", sep),
+            DateOrder::Mdy => format!("%m{0}%d{0}%Y", sep),
+            DateOrder::Ymd => format!("%Y{0}%m{0}%d", sep),
+        })
+    }
+
+    /// Date text in this locale's order; values that are not a recognized date are kept.
+    pub fn date(&self, value: &str) -> String {
+        match (self.date_pattern(), self.parse_date(value)) {
+            (Some(pattern), Some(date)) => date.format(&pattern).to_string(),
+            _ => value.to_string(),
+        }
+    }
+
+    /// A scanned or already localized date: this locale's own order first (so "03/04/2024" written as
+    /// month/day reads back the same), then the scanned formats.
+    pub fn parse_date(&self, value: &str) -> Option<NaiveDate> {
+        self.date_pattern()
+            .and_then(|pattern| NaiveDate::parse_from_str(value.trim(), &pattern).ok())
+            .or_else(|| parse_invoice_date(value))
+    }
+
+    /// Excel serial of `value` when this locale writes date cells and the value is a date.
+    pub fn date_serial(&self, value: &str) -> Option<f64> {
+        self.excel_dates.then(|| self.parse_date(value)).flatten().map(excel_serial)
+    }
+
+    /// Number format of date cells, in this locale's order (day first when none is set).
+    pub fn date_num_format(&self) -> String {
+        let sep = &self.date_separator;
+        match self.date_order.unwrap_or(DateOrder::Dmy) {
+            DateOrder::Dmy => format!("dd{0}mm{0}yyyy", sep),
+            DateOrder::Mdy => format!("mm{0}dd{0}yyyy", sep),
+            DateOrder::Ymd => format!("yyyy{0}mm{0}dd", sep),
+        }
+    }
+
+    /// Number format of amount cells; the codes use Excel's invariant separators.
+    pub fn amount_num_format(&self) -> &'static str {
+        if self.thousands_separator.is_empty() {
+            "0.00"
+        } else {
+            "#,##0.00"
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if ![".", ","].contains(&self.decimal_separator.as_str()) {
+            return Err("Decimal separator must be \".\" or \",\".".to_string());
+        }
+        if !["", ",", ".", " ", "'"].contains(&self.thousands_separator.as_str()) {
+            return Err("Thousands separator must be empty, \",\", \".\", a space or \"'\".".to_string());
+        }
+        if self.thousands_separator == self.decimal_separator {
+            return Err("Thousands and decimal separators must differ.".to_string());
+        }
+        if ![".", "/", "-"].contains(&self.date_separator.as_str()) {
+            return Err("Date separator must be \".\", \"/\" or \"-\".".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Days since Excel's epoch (1899-12-30, which absorbs Excel's 1900 leap-year bug for later dates).
+pub fn excel_serial(date: NaiveDate) -> f64 {
+    let epoch = NaiveDate::from_ymd_opt(1899, 12, 30).expect("valid epoch");
+    (date - epoch).num_days() as f64
+}
+
+fn locale_key(profile_id: i64) -> String {
+    format!("{}.{}", LOCALE_KEY, profile_id)
+}
+
+/// The profile's locale, else the default (also without a profile).
+pub fn get(db: &Db, profile_id: Option<i64>) -> Result<ExportLocale, String> {
+    let Some(id) = profile_id else {
+        return Ok(ExportLocale::default());
+    };
+    Ok(db
+        .get_settings()?
+        .get(&locale_key(id))
+        .and_then(|v| serde_json::from_str(v).ok())
+        .unwrap_or_default())
+}
+
+/// Store the profile's locale; None goes back to the default.
+pub fn set(db: &Db, profile_id: i64, locale: Option<ExportLocale>) -> Result<ExportLocale, String> {
+    db.get_profile_by_id(profile_id)?;
+    let value = match &locale {
+        Some(l) => {
+            l.validate()?;
+            Some(serde_json::to_string(l).map_err(|e| e.to_string())?)
+        }
+        None => None,
+    };
+    db.set_settings(&[(locale_key(profile_id).as_str(), value)])?;
+    get(db, Some(profile_id))
+}
@@ -0,0 +1,93 @@
+//! Export manifest: one record per batch append with the rows written, the documents behind them and the
+//! one-off column overrides of that batch. An override (e.g. descriptions in column K for this month only)
+//! changes where values go for a single append and is never saved to the profile, so the manifest is
+//! where it can be traced afterwards.
+
+use crate::db::Db;
+use crate::models::HeaderInfo;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+/// Column letter -> field key for one batch; an empty field leaves the column blank.
+pub type ColumnOverrides = BTreeMap<String, String>;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportManifest {
+    pub id: i64,
+    pub profile_id: i64,
+    pub file_path: String,
+    pub sheet_name: String,
+    /// Ledger row of each document, in the order the documents were given.
+    pub rows: Vec<u32>,
+    /// History record of each document (pairs with `rows`).
+    pub history_ids: Vec<Option<i64>>,
+    pub column_overrides: ColumnOverrides,
+    pub created_at: String,
+}
+
+/// The profile's column mapping with `overrides` applied, and the overrides as applied (letters as in the
+/// ledger, fields trimmed). Columns must be ledger columns.
+pub fn apply_overrides(
+    headers: &[HeaderInfo],
+    column_mapping: &HashMap<String, String>,
+    overrides: &ColumnOverrides,
+) -> Result<(HashMap<String, String>, ColumnOverrides), String> {
+    let mut mapping = column_mapping.clone();
+    let mut applied = ColumnOverrides::new();
+    for (letter, field) in overrides {
+        let header = headers
+            .iter()
+            .find(|h| h.column_letter.eq_ignore_ascii_case(letter.trim()))
+            .ok_or_else(|| format!("Column {} is not a column of the ledger.", letter.trim()))?;
+        mapping.retain(|l, _| !l.eq_ignore_ascii_case(&header.column_letter));
+        let field = field.trim().to_string();
+        if !field.is_empty() {
+            mapping.insert(header.column_letter.clone(), field.clone());
+        }
+        applied.insert(header.column_letter.clone(), field);
+    }
+    Ok((mapping, applied))
+}
+
+pub fn record(
+    db: &Db,
+    profile_id: i64,
+    file_path: &str,
+    sheet_name: &str,
+    rows: &[u32],
+    history_ids: &[Option<i64>],
+    column_overrides: &ColumnOverrides,
+) -> Result<i64, String> {
+    let overrides = if column_overrides.is_empty() {
+        None
+    } else {
+        Some(serde_json::to_string(column_overrides).map_err(|e| e.to_string())?)
+    };
+    Ok(db.add_export_manifest(
+        profile_id,
+        file_path,
+        sheet_name,
+        &serde_json::to_string(rows).map_err(|e| e.to_string())?,
+        &serde_json::to_string(history_ids).map_err(|e| e.to_string())?,
+        overrides.as_deref(),
+    )?)
+}
+
+/// Manifests, newest first; only those of `profile_id` when given.
+pub fn list(db: &Db, profile_id: Option<i64>, limit: u32) -> Result<Vec<ExportManifest>, String> {
+    Ok(db
+        .list_export_manifests(profile_id, limit)?
+        .into_iter()
+        .map(|(id, profile_id, file_path, sheet_name, rows, history_ids, column_overrides, created_at)| ExportManifest {
+            id,
+            profile_id,
+            file_path,
+            sheet_name,
+            rows: serde_json::from_str(&rows).unwrap_or_default(),
+            history_ids: serde_json::from_str(&history_ids).unwrap_or_default(),
+            column_overrides: column_overrides.and_then(|o| serde_json::from_str(&o).ok()).unwrap_or_default(),
+            created_at,
+        })
+        .collect())
+}
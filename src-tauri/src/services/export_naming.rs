@@ -0,0 +1,222 @@
+//! File names for new exports from a template such as `{client}_{year}-{month}_{count}faktura.xlsx`.
+//! Templates are kept in settings, one per profile with a global default; without one the export keeps
+//! the `Invoices_<timestamp>` name.
+
+use crate::db::Db;
+use crate::services::spending_report::parse_invoice_date;
+use crate::types::InvoiceData;
+use chrono::{Datelike, NaiveDate};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+const DEFAULT_TEMPLATE_KEY: &str = "export_name_template";
+
+/// Variables a template may use.
+pub const VARIABLES: &[&str] = &[
+    "client", "profile", "doctype", "count", "year", "month", "day", "date", "timestamp",
+];
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportNameTemplate {
+    pub profile_id: Option<i64>,
+    /// Template stored for the profile (or the global default when profile_id is None).
+    pub template: Option<String>,
+    /// Template actually used: the profile's, else the global default.
+    pub effective_template: Option<String>,
+    /// Example file name rendered with today's date and sample values.
+    pub preview: Option<String>,
+}
+
+/// Values substituted into a template.
+pub struct NamingContext {
+    pub client: String,
+    pub profile: String,
+    pub doc_type: String,
+    pub count: usize,
+    /// Latest document date in the batch, else today.
+    pub date: NaiveDate,
+}
+
+fn template_key(profile_id: Option<i64>) -> String {
+    match profile_id {
+        Some(id) => format!("{}.{}", DEFAULT_TEMPLATE_KEY, id),
+        None => DEFAULT_TEMPLATE_KEY.to_string(),
+    }
+}
+
+/// Reject unknown `{variables}` and unbalanced braces.
+fn validate_template(template: &str) -> Result<(), String> {
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| format!("Missing closing brace in: {}", template))?;
+        let name = &rest[start + 1..start + end];
+        if !VARIABLES.contains(&name) {
+            return Err(format!(
+                "Unknown variable {{{}}}. Available: {}",
+                name,
+                VARIABLES.iter().map(|v| format!("{{{}}}", v)).collect::<Vec<_>>().join(", ")
+            ));
+        }
+        rest = &rest[start + end + 1..];
+    }
+    if rest.contains('}') {
+        return Err(format!("Unexpected closing brace in: {}", template));
+    }
+    Ok(())
+}
+
+/// Characters Windows does not allow in file names become '_'; trailing dots and spaces are dropped.
+pub(crate) fn sanitize_file_stem(name: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .map(|c| if c.is_control() || "<>:\"/\\|?*".contains(c) { '_' } else { c })
+        .collect();
+    cleaned.trim().trim_end_matches(['.', ' ']).to_string()
+}
+
+/// Template with variables replaced, without extension.
+pub fn render(template: &str, ctx: &NamingContext) -> String {
+    let stem = template
+        .trim()
+        .strip_suffix(".xlsx")
+        .or_else(|| template.trim().strip_suffix(".ods"))
+        .unwrap_or(template.trim());
+    let values: HashMap<&str, String> = [
+        ("client", ctx.client.clone()),
+        ("profile", ctx.profile.clone()),
+        ("doctype", ctx.doc_type.clone()),
+        ("count", ctx.count.to_string()),
+        ("year", format!("{:04}", ctx.date.year())),
+        ("month", format!("{:02}", ctx.date.month())),
+        ("day", format!("{:02}", ctx.date.day())),
+        ("date", ctx.date.format("%Y-%m-%d").to_string()),
+        ("timestamp", chrono::Local::now().format("%Y%m%d_%H%M%S").to_string()),
+    ]
+    .into_iter()
+    .collect();
+    let mut out = String::new();
+    let mut rest = stem;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        match rest[start..].find('}') {
+            Some(end) => {
+                let name = &rest[start + 1..start + end];
+                out.push_str(values.get(name).map(String::as_str).unwrap_or_default());
+                rest = &rest[start + end + 1..];
+            }
+            None => {
+                rest = &rest[start..];
+                break;
+            }
+        }
+    }
+    out.push_str(rest);
+    sanitize_file_stem(&out)
+}
+
+/// Profile template, else the global default.
+fn effective_template(stored: &HashMap<String, String>, profile_id: Option<i64>) -> Option<String> {
+    profile_id
+        .and_then(|id| stored.get(&template_key(Some(id))))
+        .or_else(|| stored.get(DEFAULT_TEMPLATE_KEY))
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+}
+
+fn sample_context() -> NamingContext {
+    NamingContext {
+        client: "Klient".to_string(),
+        profile: "Profil".to_string(),
+        doc_type: "faktura".to_string(),
+        count: 12,
+        date: chrono::Local::now().date_naive(),
+    }
+}
+
+pub fn get_template(db: &Db, profile_id: Option<i64>) -> Result<ExportNameTemplate, String> {
+    let stored = db.get_settings()?;
+    let effective = effective_template(&stored, profile_id);
+    Ok(ExportNameTemplate {
+        profile_id,
+        template: stored.get(&template_key(profile_id)).cloned(),
+        preview: effective.as_ref().map(|t| format!("{}.xlsx", render(t, &sample_context()))),
+        effective_template: effective,
+    })
+}
+
+/// Store (or with None / empty, remove) the template of a profile or the global default.
+pub fn set_template(db: &Db, profile_id: Option<i64>, template: Option<String>) -> Result<ExportNameTemplate, String> {
+    let template = template.map(|t| t.trim().to_string()).filter(|t| !t.is_empty());
+    if let Some(t) = &template {
+        validate_template(t)?;
+        if render(t, &sample_context()).is_empty() {
+            return Err("The template produces an empty file name.".to_string());
+        }
+    }
+    if let Some(id) = profile_id {
+        db.get_profile_by_id(id)?;
+    }
+    let key = template_key(profile_id);
+    db.set_settings(&[(key.as_str(), template)])?;
+    get_template(db, profile_id)
+}
+
+/// The one value shared by every invoice for `key`, if any.
+fn common_field(invoices: &[InvoiceData], key: &str) -> Option<String> {
+    let mut values = invoices
+        .iter()
+        .map(|inv| inv.fields.get(key).map(|v| v.value.trim().to_string()).unwrap_or_default());
+    let first = values.next().filter(|v| !v.is_empty())?;
+    values.all(|v| v == first).then_some(first)
+}
+
+/// Path for a new export of `invoices` named by the profile's (or default) template, in the default
+/// export folder. None when no template is configured.
+pub fn export_path(
+    db: &Db,
+    profile_id: Option<i64>,
+    document_type: Option<&str>,
+    invoices: &[InvoiceData],
+    extension: &str,
+) -> Result<Option<PathBuf>, String> {
+    let stored = db.get_settings()?;
+    let Some(template) = effective_template(&stored, profile_id) else {
+        return Ok(None);
+    };
+    let profile = match profile_id {
+        Some(id) => db
+            .get_profiles()?
+            .into_iter()
+            .find(|(pid, ..)| *pid == id)
+            .map(|(_, name, ..)| name)
+            .unwrap_or_default(),
+        None => String::new(),
+    };
+    // Ledgers are kept per client, so the profile name is the client; otherwise the buyer shared by the batch.
+    let client = Some(profile.clone())
+        .filter(|p| !p.is_empty())
+        .or_else(|| common_field(invoices, "buyer_name"))
+        .unwrap_or_default();
+    let date = invoices
+        .iter()
+        .filter_map(|inv| inv.fields.get("date").and_then(|v| parse_invoice_date(&v.value)))
+        .max()
+        .unwrap_or_else(|| chrono::Local::now().date_naive());
+    let ctx = NamingContext {
+        client,
+        profile,
+        doc_type: document_type.unwrap_or_default().to_string(),
+        count: invoices.len(),
+        date,
+    };
+    let stem = render(&template, &ctx);
+    if stem.is_empty() {
+        return Ok(None);
+    }
+    let dir = crate::excel::export_dir(document_type)?;
+    Ok(Some(crate::excel::unique_export_path_in(&dir, &stem, extension)))
+}
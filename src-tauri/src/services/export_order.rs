@@ -0,0 +1,163 @@
+//! Row order of batch exports and appends. Ledgers are kept chronologically, so documents are sorted by
+//! the profile's keys (document date by default) instead of the order they were dropped in. Ties fall
+//! back to the document number, the source file and finally the original position, so the same batch
+//! always gives the same rows. Kept in settings (`export_sort.<profile id>`).
+
+use crate::db::Db;
+use crate::services::spending_report::parse_invoice_date;
+use crate::types::InvoiceData;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+
+const SORT_KEY: &str = "export_sort";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortKey {
+    /// Document date; documents without a readable date go last (first when descending).
+    Date,
+    /// Seller name, case-insensitive.
+    Vendor,
+    /// Document number, with digit runs compared as numbers ("2" before "10").
+    Number,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportSort {
+    /// Keys in priority order; empty keeps the order the documents were given in.
+    pub by: Vec<SortKey>,
+    #[serde(default)]
+    pub descending: bool,
+}
+
+impl Default for ExportSort {
+    fn default() -> Self {
+        ExportSort { by: vec![SortKey::Date], descending: false }
+    }
+}
+
+fn sort_key(profile_id: i64) -> String {
+    format!("{}.{}", SORT_KEY, profile_id)
+}
+
+/// The profile's order, else the default (also without a profile).
+pub fn get(db: &Db, profile_id: Option<i64>) -> Result<ExportSort, String> {
+    let Some(id) = profile_id else {
+        return Ok(ExportSort::default());
+    };
+    Ok(db
+        .get_settings()?
+        .get(&sort_key(id))
+        .and_then(|v| serde_json::from_str(v).ok())
+        .unwrap_or_default())
+}
+
+/// Store the profile's order; None goes back to the default.
+pub fn set(db: &Db, profile_id: i64, sort: Option<ExportSort>) -> Result<ExportSort, String> {
+    db.get_profile_by_id(profile_id)?;
+    let value = match &sort {
+        Some(s) => {
+            let mut by: Vec<SortKey> = Vec::new();
+            for key in &s.by {
+                if !by.contains(key) {
+                    by.push(*key);
+                }
+            }
+            let cleaned = ExportSort { by, descending: s.descending };
+            Some(serde_json::to_string(&cleaned).map_err(|e| e.to_string())?)
+        }
+        None => None,
+    };
+    db.set_settings(&[(sort_key(profile_id).as_str(), value)])?;
+    get(db, Some(profile_id))
+}
+
+fn field<'a>(invoice: &'a InvoiceData, keys: &[&str]) -> &'a str {
+    keys.iter()
+        .find_map(|k| invoice.fields.get(*k).map(|v| v.value.trim()).filter(|v| !v.is_empty()))
+        .unwrap_or("")
+}
+
+fn number(invoice: &InvoiceData) -> &str {
+    field(invoice, crate::services::duplicate_keys::DOCUMENT_NUMBER_KEYS)
+}
+
+/// Leading digits of `chars` (consumed), without leading zeros.
+fn digit_run(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut digits = String::new();
+    while let Some(c) = chars.next_if(|c| c.is_ascii_digit()) {
+        digits.push(c);
+    }
+    digits.trim_start_matches('0').to_string()
+}
+
+/// Compare with runs of digits as numbers, so "2/2024" sorts before "10/2024".
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a.chars().peekable(), b.chars().peekable());
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let (da, db) = (digit_run(&mut a), digit_run(&mut b));
+                let ord = da.len().cmp(&db.len()).then_with(|| da.cmp(&db));
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+            }
+            (Some(x), Some(y)) => {
+                let ord = x.to_lowercase().cmp(y.to_lowercase());
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
+
+fn compare(a: &InvoiceData, b: &InvoiceData, key: SortKey) -> Ordering {
+    match key {
+        SortKey::Date => {
+            let date = |inv: &InvoiceData| parse_invoice_date(field(inv, &["date"]));
+            match (date(a), date(b)) {
+                (Some(x), Some(y)) => x.cmp(&y),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            }
+        }
+        SortKey::Vendor => {
+            let vendor = |inv: &InvoiceData| field(inv, &["seller_name", "companyName"]).to_lowercase();
+            vendor(a).cmp(&vendor(b))
+        }
+        SortKey::Number => natural_cmp(number(a), number(b)),
+    }
+}
+
+/// Positions of `invoices` in export order.
+pub fn order(invoices: &[InvoiceData], sort: &ExportSort) -> Vec<usize> {
+    let mut positions: Vec<usize> = (0..invoices.len()).collect();
+    if sort.by.is_empty() {
+        return positions;
+    }
+    positions.sort_by(|&i, &j| {
+        let (a, b) = (&invoices[i], &invoices[j]);
+        let by_keys = sort.by.iter().fold(Ordering::Equal, |ord, key| ord.then_with(|| compare(a, b, *key)));
+        let by_keys = if sort.descending { by_keys.reverse() } else { by_keys };
+        by_keys
+            .then_with(|| natural_cmp(number(a), number(b)))
+            .then_with(|| a.source_file.cmp(&b.source_file))
+    });
+    positions
+}
+
+/// `invoices` in export order.
+pub fn sorted(invoices: Vec<InvoiceData>, sort: &ExportSort) -> Vec<InvoiceData> {
+    let positions = order(&invoices, sort);
+    let mut slots: Vec<Option<InvoiceData>> = invoices.into_iter().map(Some).collect();
+    positions.into_iter().filter_map(|i| slots[i].take()).collect()
+}
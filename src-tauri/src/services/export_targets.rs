@@ -0,0 +1,413 @@
+//! Database export targets: a profile can insert its documents into a table of the accounting system's
+//! database (PostgreSQL or MySQL/MariaDB), instead of or besides appending them to its ledger. The table's
+//! columns are mapped to field keys; dates are sent as ISO dates and amounts as plain numbers, empty
+//! values as NULL, and each batch is inserted in one transaction. Kept in settings
+//! (`export_target.<profile id>`, the password encrypted like the Azure key).
+
+use crate::db::Db;
+use crate::excel;
+use crate::services::{app_warnings, settings};
+use crate::services::spending_report::parse_invoice_date;
+use crate::services::validation::parse_amount;
+use crate::types::InvoiceData;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
+
+const TARGET_KEY: &str = "export_target";
+const PASSWORD_KEY: &str = "export_target_password";
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+
+fn target_key(profile_id: i64) -> String {
+    format!("{}.{}", TARGET_KEY, profile_id)
+}
+
+fn password_key(profile_id: i64) -> String {
+    format!("{}.{}", PASSWORD_KEY, profile_id)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DatabaseKind {
+    Postgres,
+    Mysql,
+}
+
+impl DatabaseKind {
+    fn default_port(self) -> u16 {
+        match self {
+            DatabaseKind::Postgres => 5432,
+            DatabaseKind::Mysql => 3306,
+        }
+    }
+
+    fn quote(self, identifier: &str) -> String {
+        match self {
+            DatabaseKind::Postgres => format!("\"{}\"", identifier),
+            DatabaseKind::Mysql => format!("`{}`", identifier),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DatabaseTarget {
+    pub kind: DatabaseKind,
+    pub host: String,
+    pub port: Option<u16>,
+    pub database: String,
+    pub username: String,
+    /// "table" or "schema.table".
+    pub table: String,
+    #[serde(default)]
+    pub require_tls: bool,
+    /// Table column -> field key.
+    pub columns: BTreeMap<String, String>,
+    /// Append to the profile's ledger as well; otherwise the database replaces it.
+    #[serde(default)]
+    pub also_append_to_ledger: bool,
+}
+
+/// A profile's target as shown in the UI (the password is never returned).
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileExportTarget {
+    pub profile_id: i64,
+    #[serde(flatten)]
+    pub target: DatabaseTarget,
+    pub password_configured: bool,
+}
+
+/// Replaces the profile's target. For the password None keeps the stored value and an empty string
+/// removes it.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileExportTargetUpdate {
+    #[serde(flatten)]
+    pub target: DatabaseTarget,
+    pub password: Option<String>,
+}
+
+/// A destination that documents are inserted into, connected and ready.
+pub trait ExportTarget {
+    /// Columns of the target table, in table order.
+    fn columns(&mut self) -> Result<Vec<String>, String>;
+    /// Insert `rows` (one value per column of `columns`, None for NULL) in one transaction.
+    fn insert(&mut self, columns: &[String], rows: &[Vec<Option<String>>]) -> Result<(), String>;
+}
+
+fn valid_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+}
+
+/// Schema (if given) and table name of `table`.
+fn split_table(table: &str) -> (Option<&str>, &str) {
+    match table.split_once('.') {
+        Some((schema, name)) => (Some(schema), name),
+        None => (None, table),
+    }
+}
+
+impl DatabaseTarget {
+    fn validate(&mut self) -> Result<(), String> {
+        for value in [&mut self.host, &mut self.database, &mut self.username, &mut self.table] {
+            *value = value.trim().to_string();
+        }
+        if self.host.is_empty() || self.database.is_empty() || self.username.is_empty() {
+            return Err("Host, database and user are required.".to_string());
+        }
+        let (schema, name) = split_table(&self.table);
+        if !schema.map_or(true, valid_identifier) || !valid_identifier(name) {
+            return Err(format!("Not a valid table name: '{}'.", self.table));
+        }
+        self.columns.retain(|_, field| !field.trim().is_empty());
+        if self.columns.is_empty() {
+            return Err("Map at least one table column to a field.".to_string());
+        }
+        if let Some(column) = self.columns.keys().find(|c| !valid_identifier(c)) {
+            return Err(format!("Not a valid column name: '{}'.", column));
+        }
+        Ok(())
+    }
+
+    fn quoted_table(&self) -> String {
+        let (schema, name) = split_table(&self.table);
+        match schema {
+            Some(schema) => format!("{}.{}", self.kind.quote(schema), self.kind.quote(name)),
+            None => self.kind.quote(name),
+        }
+    }
+
+    fn port(&self) -> u16 {
+        self.port.unwrap_or(self.kind.default_port())
+    }
+}
+
+pub fn get(db: &Db, app_data_dir: &Path, profile_id: i64) -> Result<Option<ProfileExportTarget>, String> {
+    let stored = db.get_settings()?;
+    let Some(target) = stored.get(&target_key(profile_id)).and_then(|v| serde_json::from_str(v).ok()) else {
+        return Ok(None);
+    };
+    Ok(Some(ProfileExportTarget {
+        profile_id,
+        target,
+        password_configured: password(db, app_data_dir, profile_id)?.is_some(),
+    }))
+}
+
+fn password(db: &Db, app_data_dir: &Path, profile_id: i64) -> Result<Option<String>, String> {
+    let Some(encrypted) = db.get_settings()?.get(&password_key(profile_id)).filter(|v| !v.is_empty()).cloned() else {
+        return Ok(None);
+    };
+    Ok(settings::decrypt_secret(app_data_dir, &encrypted)
+        .map_err(|e| app_warnings::report("export_targets", format!("could not decrypt the password of profile {}: {}", profile_id, e)))
+        .ok())
+}
+
+pub fn set(
+    db: &Db,
+    app_data_dir: &Path,
+    profile_id: i64,
+    update: ProfileExportTargetUpdate,
+) -> Result<ProfileExportTarget, String> {
+    db.get_profile_by_id(profile_id)?;
+    let mut target = update.target;
+    target.validate()?;
+    let key = target_key(profile_id);
+    let password_key = password_key(profile_id);
+    let mut entries: Vec<(&str, Option<String>)> =
+        vec![(key.as_str(), Some(serde_json::to_string(&target).map_err(|e| e.to_string())?))];
+    if let Some(password) = update.password {
+        let encrypted = match password.as_str() {
+            "" => None,
+            password => Some(settings::encrypt_secret(app_data_dir, password)?),
+        };
+        entries.push((password_key.as_str(), encrypted));
+    }
+    db.set_settings(&entries)?;
+    get(db, app_data_dir, profile_id)?.ok_or_else(|| "Export target was not saved.".to_string())
+}
+
+pub fn remove(db: &Db, profile_id: i64) -> Result<(), String> {
+    db.set_settings(&[(target_key(profile_id).as_str(), None), (password_key(profile_id).as_str(), None)])?;
+    Ok(())
+}
+
+/// The profile's target with its password, for `connect`; None when it has none.
+pub fn load(db: &Db, app_data_dir: &Path, profile_id: i64) -> Result<Option<(DatabaseTarget, Option<String>)>, String> {
+    let Some(profile_target) = get(db, app_data_dir, profile_id)? else {
+        return Ok(None);
+    };
+    Ok(Some((profile_target.target, password(db, app_data_dir, profile_id)?)))
+}
+
+/// Value sent for `field_key` of a document: ISO date, plain number, or None when empty.
+fn column_value(field_key: &str, invoice_data: &InvoiceData) -> Option<String> {
+    let value = invoice_data.fields.get(field_key)?.value.trim();
+    if value.is_empty() {
+        return None;
+    }
+    if excel::is_date_field(field_key) {
+        if let Some(date) = parse_invoice_date(value) {
+            return Some(date.format("%Y-%m-%d").to_string());
+        }
+    } else if excel::is_amount_field(field_key) {
+        if let Some(amount) = parse_amount(value) {
+            return Some(amount.to_string());
+        }
+    }
+    Some(value.to_string())
+}
+
+pub fn connect(target: &DatabaseTarget, password: Option<&str>) -> Result<Box<dyn ExportTarget>, String> {
+    match target.kind {
+        DatabaseKind::Postgres => Ok(Box::new(postgres_target::connect(target, password)?)),
+        DatabaseKind::Mysql => Ok(Box::new(mysql_target::connect(target, password)?)),
+    }
+}
+
+/// Columns of the target table; also checks that the mapped ones exist. Blocking.
+pub fn test(target: &DatabaseTarget, password: Option<&str>) -> Result<Vec<String>, String> {
+    let columns = connect(target, password)?.columns()?;
+    check_columns(target, &columns)?;
+    Ok(columns)
+}
+
+fn check_columns(target: &DatabaseTarget, table_columns: &[String]) -> Result<(), String> {
+    if table_columns.is_empty() {
+        return Err(format!("Table {} was not found.", target.table));
+    }
+    let missing: Vec<&str> = target
+        .columns
+        .keys()
+        .filter(|c| !table_columns.iter().any(|t| t == *c))
+        .map(String::as_str)
+        .collect();
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("Table {} has no column {}.", target.table, missing.join(", ")))
+    }
+}
+
+/// Insert one row per document in one transaction. Blocking.
+pub fn insert_documents(
+    target: &DatabaseTarget,
+    password: Option<&str>,
+    invoices: &[InvoiceData],
+) -> Result<(), String> {
+    let mut connection = connect(target, password)?;
+    check_columns(target, &connection.columns()?)?;
+    let columns: Vec<String> = target.columns.keys().cloned().collect();
+    let rows: Vec<Vec<Option<String>>> = invoices
+        .iter()
+        .map(|invoice_data| target.columns.values().map(|field| column_value(field, invoice_data)).collect())
+        .collect();
+    connection.insert(&columns, &rows)
+}
+
+mod postgres_target {
+    use super::{DatabaseTarget, ExportTarget, CONNECT_TIMEOUT};
+    use postgres::config::SslMode;
+    use postgres::types::ToSql;
+    use postgres::{Client, Config, NoTls};
+    use postgres_native_tls::MakeTlsConnector;
+    use std::collections::HashMap;
+
+    pub struct PostgresTarget {
+        client: Client,
+        table: String,
+        quoted_table: String,
+        /// Column -> SQL type, filled by `columns`.
+        types: HashMap<String, String>,
+    }
+
+    /// The server's message for errors it reported ("db error" alone otherwise).
+    fn message(e: &postgres::Error) -> String {
+        e.as_db_error().map_or_else(|| e.to_string(), |db| db.message().to_string())
+    }
+
+    pub fn connect(target: &DatabaseTarget, password: Option<&str>) -> Result<PostgresTarget, String> {
+        let mut config = Config::new();
+        config
+            .host(&target.host)
+            .port(target.port())
+            .dbname(&target.database)
+            .user(&target.username)
+            .connect_timeout(CONNECT_TIMEOUT);
+        if let Some(password) = password {
+            config.password(password);
+        }
+        let client = if target.require_tls {
+            let connector = native_tls::TlsConnector::new().map_err(|e| e.to_string())?;
+            config.ssl_mode(SslMode::Require).connect(MakeTlsConnector::new(connector))
+        } else {
+            config.connect(NoTls)
+        }
+        .map_err(|e| format!("Could not connect to PostgreSQL at {}: {}", target.host, message(&e)))?;
+        Ok(PostgresTarget {
+            client,
+            table: target.table.clone(),
+            quoted_table: target.quoted_table(),
+            types: HashMap::new(),
+        })
+    }
+
+    impl ExportTarget for PostgresTarget {
+        fn columns(&mut self) -> Result<Vec<String>, String> {
+            let rows = self
+                .client
+                .query(
+                    "SELECT a.attname::text, format_type(a.atttypid, a.atttypmod) FROM pg_attribute a \
+                     WHERE a.attrelid = to_regclass($1) AND a.attnum > 0 AND NOT a.attisdropped ORDER BY a.attnum",
+                    &[&self.quoted_table],
+                )
+                .map_err(|e| format!("Could not read table {}: {}", self.table, message(&e)))?;
+            let columns: Vec<(String, String)> = rows.iter().map(|row| (row.get(0), row.get(1))).collect();
+            self.types = columns.iter().cloned().collect();
+            Ok(columns.into_iter().map(|(name, _)| name).collect())
+        }
+
+        fn insert(&mut self, columns: &[String], rows: &[Vec<Option<String>>]) -> Result<(), String> {
+            // Values go as text and are cast to each column's type by the server.
+            let values: Vec<String> = columns
+                .iter()
+                .enumerate()
+                .map(|(i, c)| format!("CAST(${}::text AS {})", i + 1, self.types.get(c).map_or("text", String::as_str)))
+                .collect();
+            let sql = format!(
+                "INSERT INTO {} ({}) VALUES ({})",
+                self.quoted_table,
+                columns.iter().map(|c| format!("\"{}\"", c)).collect::<Vec<_>>().join(", "),
+                values.join(", ")
+            );
+            let mut transaction = self.client.transaction().map_err(|e| message(&e))?;
+            let statement = transaction
+                .prepare(&sql)
+                .map_err(|e| format!("Could not prepare the insert into {}: {}", self.table, message(&e)))?;
+            for row in rows {
+                let params: Vec<&(dyn ToSql + Sync)> = row.iter().map(|v| v as &(dyn ToSql + Sync)).collect();
+                transaction
+                    .execute(&statement, &params)
+                    .map_err(|e| format!("Insert into {} failed: {}", self.table, message(&e)))?;
+            }
+            transaction.commit().map_err(|e| message(&e))
+        }
+    }
+}
+
+mod mysql_target {
+    use super::{split_table, DatabaseTarget, ExportTarget, CONNECT_TIMEOUT};
+    use mysql::prelude::Queryable;
+    use mysql::{Conn, OptsBuilder, SslOpts, TxOpts, Value};
+
+    pub struct MysqlTarget {
+        conn: Conn,
+        table: String,
+        quoted_table: String,
+    }
+
+    pub fn connect(target: &DatabaseTarget, password: Option<&str>) -> Result<MysqlTarget, String> {
+        let opts = OptsBuilder::new()
+            .ip_or_hostname(Some(target.host.clone()))
+            .tcp_port(target.port())
+            .db_name(Some(target.database.clone()))
+            .user(Some(target.username.clone()))
+            .pass(password.map(String::from))
+            .tcp_connect_timeout(Some(CONNECT_TIMEOUT))
+            .ssl_opts(target.require_tls.then(SslOpts::default));
+        let conn = Conn::new(opts).map_err(|e| format!("Could not connect to MySQL at {}: {}", target.host, e))?;
+        Ok(MysqlTarget { conn, table: target.table.clone(), quoted_table: target.quoted_table() })
+    }
+
+    impl ExportTarget for MysqlTarget {
+        fn columns(&mut self) -> Result<Vec<String>, String> {
+            let (schema, name) = split_table(&self.table);
+            self.conn
+                .exec(
+                    "SELECT COLUMN_NAME FROM information_schema.COLUMNS \
+                     WHERE TABLE_SCHEMA = COALESCE(?, DATABASE()) AND TABLE_NAME = ? ORDER BY ORDINAL_POSITION",
+                    (schema, name),
+                )
+                .map_err(|e| format!("Could not read table {}: {}", self.table, e))
+        }
+
+        fn insert(&mut self, columns: &[String], rows: &[Vec<Option<String>>]) -> Result<(), String> {
+            let sql = format!(
+                "INSERT INTO {} ({}) VALUES ({})",
+                self.quoted_table,
+                columns.iter().map(|c| format!("`{}`", c)).collect::<Vec<_>>().join(", "),
+                vec!["?"; columns.len()].join(", ")
+            );
+            let mut transaction = self.conn.start_transaction(TxOpts::default()).map_err(|e| e.to_string())?;
+            let params = rows.iter().map(|row| row.iter().cloned().map(Value::from).collect::<Vec<Value>>());
+            transaction
+                .exec_batch(&sql, params)
+                .map_err(|e| format!("Insert into {} failed: {}", self.table, e))?;
+            transaction.commit().map_err(|e| e.to_string())
+        }
+    }
+}
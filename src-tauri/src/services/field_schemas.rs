@@ -0,0 +1,257 @@
+//! Field schema per document type: which fields a document has, their types, which are required and
+//! how they are exported. Built-in schemas cover faktura, smetka, plata and generic (ДДВ); a schema saved in the
+//! database replaces the built-in one. Schemas drive the validation warnings of a scan, the review form
+//! and `export_with_field_schema`.
+
+use crate::db::Db;
+use crate::services::document_types;
+use crate::services::spending_report::parse_invoice_date;
+use crate::services::validation::parse_amount;
+use crate::types::{InvoiceData, ValidationWarning};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldType {
+    Text,
+    Date,
+    Amount,
+    Number,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SchemaField {
+    /// Key in `InvoiceData.fields` (e.g. "invoice_number", "totalGrossSalary").
+    pub key: String,
+    pub label: String,
+    pub field_type: FieldType,
+    #[serde(default)]
+    pub required: bool,
+    /// Regular expression the whole value must match (e.g. "^MK\\d{13}$").
+    #[serde(default)]
+    pub pattern: Option<String>,
+    /// Column header on export; None keeps the field out of exports.
+    #[serde(default)]
+    pub export_header: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldSchema {
+    pub document_type: String,
+    pub fields: Vec<SchemaField>,
+    /// True when saved by the user, false for the built-in schema.
+    pub custom: bool,
+    pub updated_at: Option<String>,
+}
+
+/// (key, label, type, required, export header) of the built-in schemas.
+type BuiltinField = (&'static str, &'static str, FieldType, bool, Option<&'static str>);
+
+const FAKTURA_FIELDS: &[BuiltinField] = &[
+    ("document_type", "Тип на документ", FieldType::Text, false, Some("Тип на документ")),
+    ("invoice_number", "Број на документ", FieldType::Text, true, Some("Број на документ")),
+    ("date", "Дата на документ", FieldType::Date, true, Some("Дата на документ")),
+    ("seller_name", "Продавач", FieldType::Text, true, Some("Продавач")),
+    ("seller_tax_id", "ЕДБ на продавач", FieldType::Text, false, None),
+    ("buyer_name", "Купувач", FieldType::Text, false, Some("Купувач")),
+    ("description", "Опис", FieldType::Text, false, Some("Опис")),
+    ("net_amount", "Нето износ", FieldType::Amount, false, Some("Нето износ")),
+    ("tax_amount", "ДДВ", FieldType::Amount, false, Some("ДДВ")),
+    ("total_amount", "Бруто износ", FieldType::Amount, true, Some("бруто износ")),
+    ("currency", "Валута", FieldType::Text, false, None),
+    ("due_date", "Рок на плаќање", FieldType::Date, false, None),
+];
+
+const SMETKA_FIELDS: &[BuiltinField] = &[
+    ("companyName", "Назив на обврзник", FieldType::Text, true, Some("Назив")),
+    ("companyTaxId", "ЕДБ", FieldType::Text, true, Some("ЕДБ")),
+    ("taxPeriodStart", "Период од", FieldType::Date, false, Some("Период од")),
+    ("taxPeriodEnd", "Период до", FieldType::Date, false, Some("Период до")),
+    ("net_amount", "Даночна основа", FieldType::Amount, false, Some("Даночна основа")),
+    ("tax_amount", "Платени аконтации", FieldType::Amount, false, Some("Аконтации")),
+    ("total_amount", "Данок за плаќање", FieldType::Amount, true, Some("Данок")),
+];
+
+const PLATA_FIELDS: &[BuiltinField] = &[
+    ("declarationPeriod", "Период", FieldType::Text, true, Some("Период")),
+    ("totalGrossSalary", "Бруто плата", FieldType::Amount, true, Some("Бруто плата")),
+    ("totalNetSalary", "Нето плата", FieldType::Amount, false, Some("Нето плата")),
+    ("totalPayrollCost", "Вкупен трошок", FieldType::Amount, false, Some("Вкупен трошок")),
+];
+
+const GENERIC_FIELDS: &[BuiltinField] = &[
+    ("taxPeriod", "Даночен период", FieldType::Text, false, Some("Даночен период")),
+    ("companyName", "Назив на компанија", FieldType::Text, false, Some("Назив")),
+    ("companyTaxId", "ЕДБ", FieldType::Text, false, Some("ЕДБ")),
+    ("totalTaxBase", "Оданочив промет", FieldType::Amount, false, Some("Оданочив промет")),
+    ("totalOutputVat", "Излезен ДДВ", FieldType::Amount, false, Some("Излезен ДДВ")),
+    ("totalInputVat", "Влезен ДДВ", FieldType::Amount, false, Some("Влезен ДДВ")),
+    ("vatPayableOrRefund", "ДДВ за плаќање / побарување", FieldType::Amount, true, Some("Салдо")),
+];
+
+/// Document types with a built-in schema.
+pub const BUILTIN_TYPES: &[&str] = &["faktura", "smetka", "plata", "generic"];
+
+/// Built-in schema of a document type; empty for types without one.
+pub fn builtin(document_type: &str) -> Vec<SchemaField> {
+    let fields = match document_type {
+        "faktura" => FAKTURA_FIELDS,
+        "smetka" => SMETKA_FIELDS,
+        "plata" => PLATA_FIELDS,
+        "generic" => GENERIC_FIELDS,
+        _ => &[],
+    };
+    fields
+        .iter()
+        .map(|&(key, label, field_type, required, export_header)| SchemaField {
+            key: key.to_string(),
+            label: label.to_string(),
+            field_type,
+            required,
+            pattern: None,
+            export_header: export_header.map(str::to_string),
+        })
+        .collect()
+}
+
+fn parse_fields(json: &str) -> Result<Vec<SchemaField>, String> {
+    serde_json::from_str(json).map_err(|e| format!("Stored field schema is invalid: {}", e))
+}
+
+/// Effective schema of a document type: the saved one, else the built-in one.
+pub fn get(db: &Db, document_type: &str) -> Result<FieldSchema, String> {
+    let document_type = document_type.trim();
+    Ok(match db.get_field_schema(document_type)? {
+        Some((fields, updated_at)) => FieldSchema {
+            document_type: document_type.to_string(),
+            fields: parse_fields(&fields)?,
+            custom: true,
+            updated_at: Some(updated_at),
+        },
+        // Registered types start from the built-in schema of their extractor.
+        None => FieldSchema {
+            document_type: document_type.to_string(),
+            fields: builtin(&document_types::extraction_type(Some(document_type)).unwrap_or_default()),
+            custom: false,
+            updated_at: None,
+        },
+    })
+}
+
+/// Schemas of the built-in types and of every type with a saved schema.
+pub fn list(db: &Db) -> Result<Vec<FieldSchema>, String> {
+    let mut out: Vec<FieldSchema> = Vec::new();
+    for (document_type, fields, updated_at) in db.get_field_schemas()? {
+        out.push(FieldSchema {
+            document_type,
+            fields: parse_fields(&fields)?,
+            custom: true,
+            updated_at: Some(updated_at),
+        });
+    }
+    for dt in BUILTIN_TYPES {
+        if !out.iter().any(|s| s.document_type == *dt) {
+            out.push(FieldSchema {
+                document_type: dt.to_string(),
+                fields: builtin(dt),
+                custom: false,
+                updated_at: None,
+            });
+        }
+    }
+    out.sort_by(|a, b| a.document_type.cmp(&b.document_type));
+    Ok(out)
+}
+
+/// Save the schema of a document type; it replaces the built-in one from the next scan on.
+pub fn save(db: &Db, document_type: &str, fields: Vec<SchemaField>) -> Result<FieldSchema, String> {
+    let document_type = document_type.trim();
+    if document_type.is_empty() {
+        return Err("Document type is required.".to_string());
+    }
+    let mut cleaned: Vec<SchemaField> = Vec::new();
+    for mut f in fields {
+        f.key = f.key.trim().to_string();
+        if f.key.is_empty() {
+            return Err("Every field needs a key.".to_string());
+        }
+        if cleaned.iter().any(|c| c.key == f.key) {
+            return Err(format!("Field '{}' is defined more than once.", f.key));
+        }
+        if f.label.trim().is_empty() {
+            f.label = f.key.clone();
+        }
+        f.pattern = f.pattern.map(|p| p.trim().to_string()).filter(|p| !p.is_empty());
+        if let Some(p) = &f.pattern {
+            Regex::new(p).map_err(|e| format!("Pattern of '{}' is invalid: {}", f.key, e))?;
+        }
+        f.export_header = f.export_header.map(|h| h.trim().to_string()).filter(|h| !h.is_empty());
+        cleaned.push(f);
+    }
+    let json = serde_json::to_string(&cleaned).map_err(|e| e.to_string())?;
+    db.save_field_schema(document_type, &json)?;
+    get(db, document_type)
+}
+
+/// Drop the saved schema and return the built-in one.
+pub fn reset(db: &Db, document_type: &str) -> Result<FieldSchema, String> {
+    db.delete_field_schema(document_type.trim())?;
+    get(db, document_type)
+}
+
+fn warning(code: &str, field: &SchemaField, message: String) -> ValidationWarning {
+    ValidationWarning {
+        code: code.to_string(),
+        field_key: field.key.clone(),
+        message,
+    }
+}
+
+/// Check extracted fields against a schema: required fields present, values of the declared type, patterns.
+pub fn validate(schema: &FieldSchema, data: &InvoiceData) -> Vec<ValidationWarning> {
+    let mut warnings = Vec::new();
+    for field in &schema.fields {
+        let value = data.fields.get(&field.key).map(|f| f.value.trim()).unwrap_or("");
+        if value.is_empty() {
+            if field.required {
+                warnings.push(warning("missing_required", field, format!("{} was not extracted.", field.label)));
+            }
+            continue;
+        }
+        let type_ok = match field.field_type {
+            FieldType::Text => true,
+            FieldType::Date => parse_invoice_date(value).is_some(),
+            FieldType::Amount | FieldType::Number => parse_amount(value).is_some(),
+        };
+        if !type_ok {
+            let code = match field.field_type {
+                FieldType::Date => "invalid_date",
+                _ => "invalid_number",
+            };
+            warnings.push(warning(code, field, format!("{} '{}' could not be read.", field.label, value)));
+            continue;
+        }
+        if let Some(re) = field.pattern.as_deref().and_then(|p| Regex::new(p).ok()) {
+            if !re.is_match(value) {
+                warnings.push(warning(
+                    "pattern_mismatch",
+                    field,
+                    format!("{} '{}' does not have the expected format.", field.label, value),
+                ));
+            }
+        }
+    }
+    warnings
+}
+
+/// (headers, field keys) of the schema's exported fields, in schema order.
+pub fn export_columns(schema: &FieldSchema) -> (Vec<String>, Vec<String>) {
+    schema
+        .fields
+        .iter()
+        .filter_map(|f| f.export_header.clone().map(|h| (h, f.key.clone())))
+        .unzip()
+}
@@ -0,0 +1,474 @@
+//! Google Sheets as a profile's ledger, for registers kept in Google Drive. A profile with a spreadsheet
+//! set (`google_sheets.spreadsheet.<profile id>`) appends its rows there through the Sheets API instead of
+//! to its local workbook; the sheet is the profile's sheet and the columns are the letters of its mapping.
+//! Access uses the OAuth installed-app flow with the user's own Google Cloud client ("Desktop app"): the
+//! browser signs in and redirects to a loopback port, and the refresh token is kept in settings encrypted
+//! like the Azure key. Access tokens are only held in memory.
+
+use crate::db::Db;
+use crate::models::HeaderInfo;
+use crate::services::profile_audit::letter_to_index;
+use crate::services::{app_warnings, settings};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD as B64, Engine};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
+use reqwest::blocking::Client;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const CLIENT_ID: &str = "google_sheets_client_id";
+/// Encrypted (see `settings::encrypt_secret`), as is the refresh token.
+const CLIENT_SECRET: &str = "google_sheets_client_secret";
+const REFRESH_TOKEN: &str = "google_sheets_refresh_token";
+const SPREADSHEET_KEY: &str = "google_sheets.spreadsheet";
+
+const AUTH_URL: &str = "https://accounts.google.com/o/oauth2/v2/auth";
+const TOKEN_URL: &str = "https://oauth2.googleapis.com/token";
+const REVOKE_URL: &str = "https://oauth2.googleapis.com/revoke";
+const SHEETS_API: &str = "https://sheets.googleapis.com/v4/spreadsheets";
+const SCOPE: &str = "https://www.googleapis.com/auth/spreadsheets";
+const SIGN_IN_TIMEOUT: Duration = Duration::from_secs(300);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// Renew access tokens this long before Google expires them.
+const EXPIRY_MARGIN: Duration = Duration::from_secs(60);
+
+/// Access token of the current refresh token and when it stops being used.
+static ACCESS_TOKEN: Mutex<Option<(String, String, Instant)>> = Mutex::new(None);
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GoogleSheetsSettings {
+    pub client_id: Option<String>,
+    pub client_secret_configured: bool,
+    /// Signed in (a refresh token is stored).
+    pub connected: bool,
+}
+
+/// None leaves a value unchanged, an empty string clears it. A new client id signs out.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GoogleSheetsSettingsUpdate {
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct OAuthClient {
+    client_id: String,
+    client_secret: String,
+}
+
+/// Where a profile's rows go: its sheet (and header row) in the spreadsheet.
+#[derive(Debug, Clone)]
+pub struct SheetTarget {
+    pub spreadsheet_id: String,
+    pub sheet_name: String,
+    pub header_row: u32,
+}
+
+/// A signed-in client, used for Sheets API calls.
+#[derive(Debug, Clone)]
+pub struct Connection {
+    client: OAuthClient,
+    refresh_token: String,
+}
+
+fn non_empty(value: Option<&String>) -> Option<String> {
+    value.map(|v| v.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+/// Decrypted settings value; one that cannot be decrypted (another Windows user) counts as not set.
+fn stored_secret(stored: &HashMap<String, String>, app_data_dir: &Path, key: &str) -> Option<String> {
+    let encrypted = non_empty(stored.get(key))?;
+    settings::decrypt_secret(app_data_dir, &encrypted)
+        .map_err(|e| app_warnings::report("google_sheets", format!("could not decrypt {}: {}", key, e)))
+        .ok()
+}
+
+fn http_client() -> Result<Client, String> {
+    Client::builder().timeout(REQUEST_TIMEOUT).build().map_err(|e| e.to_string())
+}
+
+pub fn get_settings(db: &Db, app_data_dir: &Path) -> Result<GoogleSheetsSettings, String> {
+    let stored = db.get_settings()?;
+    Ok(GoogleSheetsSettings {
+        client_id: non_empty(stored.get(CLIENT_ID)),
+        client_secret_configured: stored_secret(&stored, app_data_dir, CLIENT_SECRET).is_some(),
+        connected: stored_secret(&stored, app_data_dir, REFRESH_TOKEN).is_some(),
+    })
+}
+
+pub fn save_settings(
+    db: &Db,
+    app_data_dir: &Path,
+    update: GoogleSheetsSettingsUpdate,
+) -> Result<GoogleSheetsSettings, String> {
+    let stored = db.get_settings()?;
+    let cleared = |v: String| Some(v.trim().to_string()).filter(|v| !v.is_empty());
+    let mut entries: Vec<(&str, Option<String>)> = Vec::new();
+    if let Some(client_id) = update.client_id {
+        let client_id = cleared(client_id);
+        if client_id != non_empty(stored.get(CLIENT_ID)) {
+            // Refresh tokens belong to the client that issued them.
+            entries.push((REFRESH_TOKEN, None));
+            forget_access_token();
+        }
+        entries.push((CLIENT_ID, client_id));
+    }
+    if let Some(secret) = update.client_secret {
+        let encrypted = match cleared(secret) {
+            Some(secret) => Some(settings::encrypt_secret(app_data_dir, &secret)?),
+            None => None,
+        };
+        entries.push((CLIENT_SECRET, encrypted));
+    }
+    db.set_settings(&entries)?;
+    get_settings(db, app_data_dir)
+}
+
+/// The configured OAuth client.
+pub fn client(db: &Db, app_data_dir: &Path) -> Result<OAuthClient, String> {
+    let stored = db.get_settings()?;
+    let client_id = non_empty(stored.get(CLIENT_ID)).ok_or("Google OAuth client id is not configured.")?;
+    let client_secret =
+        stored_secret(&stored, app_data_dir, CLIENT_SECRET).ok_or("Google OAuth client secret is not configured.")?;
+    Ok(OAuthClient { client_id, client_secret })
+}
+
+pub fn connection(db: &Db, app_data_dir: &Path) -> Result<Connection, String> {
+    let client = client(db, app_data_dir)?;
+    let stored = db.get_settings()?;
+    let refresh_token = stored_secret(&stored, app_data_dir, REFRESH_TOKEN)
+        .ok_or("Google Sheets is not connected. Sign in to Google in the settings first.")?;
+    Ok(Connection { client, refresh_token })
+}
+
+/// Store the refresh token of a sign-in, or remove it (None).
+pub fn save_refresh_token(db: &Db, app_data_dir: &Path, refresh_token: Option<&str>) -> Result<(), String> {
+    let encrypted = refresh_token.map(|t| settings::encrypt_secret(app_data_dir, t)).transpose()?;
+    forget_access_token();
+    db.set_settings(&[(REFRESH_TOKEN, encrypted)])?;
+    Ok(())
+}
+
+fn forget_access_token() {
+    if let Ok(mut cached) = ACCESS_TOKEN.lock() {
+        *cached = None;
+    }
+}
+
+fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    B64.encode(bytes)
+}
+
+/// Value of query parameter `name` in the request line of the loopback redirect.
+fn redirect_param(request_line: &str, name: &str) -> Option<String> {
+    let target = request_line.split_whitespace().nth(1)?;
+    let url = Url::parse(&format!("http://127.0.0.1{}", target)).ok()?;
+    url.query_pairs().find(|(k, _)| k == name).map(|(_, v)| v.to_string())
+}
+
+fn respond(stream: &mut std::net::TcpStream, message: &str) {
+    let body = format!(
+        "<!doctype html><meta charset=\"utf-8\"><title>Document Scanner</title><p style=\"font-family:sans-serif\">{}</p>",
+        message
+    );
+    let _ = write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    );
+}
+
+/// Wait for the browser's redirect to `listener`; returns the authorization code.
+fn wait_for_code(listener: &TcpListener, state: &str) -> Result<String, String> {
+    listener.set_nonblocking(true).map_err(|e| e.to_string())?;
+    let deadline = Instant::now() + SIGN_IN_TIMEOUT;
+    while Instant::now() < deadline {
+        let mut stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                std::thread::sleep(Duration::from_millis(200));
+                continue;
+            }
+            Err(e) => return Err(e.to_string()),
+        };
+        stream.set_nonblocking(false).map_err(|e| e.to_string())?;
+        stream.set_read_timeout(Some(Duration::from_secs(5))).map_err(|e| e.to_string())?;
+        let mut request_line = String::new();
+        if BufReader::new(&stream).read_line(&mut request_line).is_err() {
+            continue;
+        }
+        // The browser may ask for other paths (favicon) before or after the redirect.
+        if redirect_param(&request_line, "state").as_deref() != Some(state) {
+            continue;
+        }
+        if let Some(error) = redirect_param(&request_line, "error") {
+            respond(&mut stream, "Sign-in was cancelled. You can close this tab.");
+            return Err(format!("Google sign-in failed: {}", error));
+        }
+        let Some(code) = redirect_param(&request_line, "code") else {
+            continue;
+        };
+        respond(&mut stream, "Signed in to Google. You can close this tab and return to the app.");
+        return Ok(code);
+    }
+    Err("Google sign-in timed out.".to_string())
+}
+
+/// Sign in to Google in the browser (PKCE, loopback redirect). Blocks until the browser comes back or
+/// the sign-in times out; returns the refresh token.
+pub fn sign_in(client: &OAuthClient) -> Result<String, String> {
+    let listener = TcpListener::bind("127.0.0.1:0").map_err(|e| format!("Could not open a local port: {}", e))?;
+    let redirect_uri = format!("http://127.0.0.1:{}", listener.local_addr().map_err(|e| e.to_string())?.port());
+    let verifier = random_token();
+    let state = random_token();
+    let challenge = B64.encode(Sha256::digest(verifier.as_bytes()));
+    let auth_url = Url::parse_with_params(
+        AUTH_URL,
+        &[
+            ("client_id", client.client_id.as_str()),
+            ("redirect_uri", redirect_uri.as_str()),
+            ("response_type", "code"),
+            ("scope", SCOPE),
+            ("code_challenge", challenge.as_str()),
+            ("code_challenge_method", "S256"),
+            ("state", state.as_str()),
+            ("access_type", "offline"),
+            // Without consent Google omits the refresh token when the app was approved before.
+            ("prompt", "consent"),
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    opener::open(auth_url.as_str()).map_err(|e| format!("Could not open the browser: {}", e))?;
+    let code = wait_for_code(&listener, &state)?;
+
+    let json = token_request(&[
+        ("grant_type", "authorization_code"),
+        ("code", code.as_str()),
+        ("redirect_uri", redirect_uri.as_str()),
+        ("code_verifier", verifier.as_str()),
+        ("client_id", client.client_id.as_str()),
+        ("client_secret", client.client_secret.as_str()),
+    ])?;
+    json.get("refresh_token")
+        .and_then(Value::as_str)
+        .map(String::from)
+        .ok_or_else(|| "Google did not return a refresh token.".to_string())
+}
+
+fn token_request(form: &[(&str, &str)]) -> Result<Value, String> {
+    let response = http_client()?
+        .post(TOKEN_URL)
+        .form(form)
+        .send()
+        .map_err(|e| format!("Could not reach Google: {}", e))?;
+    let status = response.status();
+    let json: Value = response.json().map_err(|e| format!("Unexpected Google response: {}", e))?;
+    if status.is_success() {
+        return Ok(json);
+    }
+    match json.get("error").and_then(Value::as_str) {
+        Some("invalid_grant") => Err("Google access was revoked or expired. Sign in to Google again.".to_string()),
+        Some("invalid_client") => Err("Google rejected the OAuth client id or secret.".to_string()),
+        _ => Err(format!("Google sign-in returned {}: {}", status, json)),
+    }
+}
+
+/// Current access token, renewed with the refresh token when missing or about to expire.
+fn access_token(connection: &Connection) -> Result<String, String> {
+    let mut cached = ACCESS_TOKEN.lock().map_err(|e| e.to_string())?;
+    if let Some((refresh_token, token, until)) = cached.as_ref() {
+        if *refresh_token == connection.refresh_token && Instant::now() < *until {
+            return Ok(token.clone());
+        }
+    }
+    let json = token_request(&[
+        ("grant_type", "refresh_token"),
+        ("refresh_token", connection.refresh_token.as_str()),
+        ("client_id", connection.client.client_id.as_str()),
+        ("client_secret", connection.client.client_secret.as_str()),
+    ])?;
+    let token = json
+        .get("access_token")
+        .and_then(Value::as_str)
+        .ok_or("Google did not return an access token.")?
+        .to_string();
+    let lifetime = Duration::from_secs(json.get("expires_in").and_then(Value::as_u64).unwrap_or(3600));
+    let until = Instant::now() + lifetime.saturating_sub(EXPIRY_MARGIN);
+    *cached = Some((connection.refresh_token.clone(), token.clone(), until));
+    Ok(token)
+}
+
+/// Revoke the refresh token at Google. Best effort: signing out also works offline.
+pub fn revoke(connection: &Connection) {
+    let result = http_client().and_then(|client| {
+        client
+            .post(REVOKE_URL)
+            .form(&[("token", connection.refresh_token.as_str())])
+            .send()
+            .map_err(|e| e.to_string())
+    });
+    if let Err(e) = result {
+        app_warnings::report("google_sheets", format!("could not revoke the refresh token: {}", e));
+    }
+}
+
+fn spreadsheet_key(profile_id: i64) -> String {
+    format!("{}.{}", SPREADSHEET_KEY, profile_id)
+}
+
+/// Spreadsheet id in a Google Sheets link (".../spreadsheets/d/<id>/edit") or given as is.
+fn parse_spreadsheet_id(input: &str) -> Option<String> {
+    let input = input.trim();
+    let id = match input.split_once("/spreadsheets/d/") {
+        Some((_, rest)) => rest.split(['/', '?', '#']).next().unwrap_or_default(),
+        None => input,
+    };
+    (id.len() >= 20 && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')).then(|| id.to_string())
+}
+
+/// The spreadsheet the profile appends to, None while it uses its local workbook.
+pub fn spreadsheet_id(db: &Db, profile_id: i64) -> Result<Option<String>, String> {
+    Ok(non_empty(db.get_settings()?.get(&spreadsheet_key(profile_id))))
+}
+
+/// Point the profile at a spreadsheet (its id or link), or back at its local workbook (None or empty).
+pub fn set_spreadsheet(db: &Db, profile_id: i64, spreadsheet: Option<&str>) -> Result<Option<String>, String> {
+    db.get_profile_by_id(profile_id)?;
+    let id = match spreadsheet.map(str::trim).filter(|s| !s.is_empty()) {
+        Some(input) => Some(parse_spreadsheet_id(input).ok_or("Not a Google Sheets link or spreadsheet id.")?),
+        None => None,
+    };
+    db.set_settings(&[(spreadsheet_key(profile_id).as_str(), id)])?;
+    spreadsheet_id(db, profile_id)
+}
+
+/// The profile's sheet in its spreadsheet with the sign-in to reach it; None when the profile appends to
+/// its local workbook.
+pub fn destination(db: &Db, app_data_dir: &Path, profile_id: i64) -> Result<Option<(SheetTarget, Connection)>, String> {
+    let Some(spreadsheet_id) = spreadsheet_id(db, profile_id)? else {
+        return Ok(None);
+    };
+    let (_, sheet_name, column_mapping_json) = db.get_profile_by_id(profile_id)?;
+    let header_row = serde_json::from_str::<Value>(&column_mapping_json)
+        .ok()
+        .and_then(|mapping| mapping.get("_headerRow")?.as_u64())
+        .map_or(1, |row| row.max(1) as u32);
+    let target = SheetTarget { spreadsheet_id, sheet_name, header_row };
+    Ok(Some((target, connection(db, app_data_dir)?)))
+}
+
+/// A1 range on `sheet_name`, quoted as the API expects.
+fn sheet_range(sheet_name: &str, cells: &str) -> String {
+    format!("'{}'!{}", sheet_name.replace('\'', "''"), cells)
+}
+
+/// Sheets API request on the values of `range`; `action` is appended to the range (":append").
+fn values_request(
+    connection: &Connection,
+    method: reqwest::Method,
+    spreadsheet_id: &str,
+    range: &str,
+    action: &str,
+    query: &[(&str, &str)],
+    body: Option<Value>,
+) -> Result<Value, String> {
+    let mut url = Url::parse(SHEETS_API).map_err(|e| e.to_string())?;
+    url.path_segments_mut()
+        .map_err(|_| "Invalid Sheets API URL".to_string())?
+        .push(spreadsheet_id)
+        .push("values")
+        .push(&format!("{}{}", range, action));
+    url.query_pairs_mut().extend_pairs(query);
+    let mut request = http_client()?.request(method, url).bearer_auth(access_token(connection)?);
+    if let Some(body) = body {
+        request = request.json(&body);
+    }
+    let response = request.send().map_err(|e| format!("Could not reach Google Sheets: {}", e))?;
+    let status = response.status();
+    let json: Value = response.json().unwrap_or(Value::Null);
+    if status.is_success() {
+        return Ok(json);
+    }
+    let detail = json.pointer("/error/message").and_then(Value::as_str).unwrap_or_default();
+    Err(match status {
+        reqwest::StatusCode::UNAUTHORIZED => {
+            forget_access_token();
+            "Google Sheets rejected the sign-in. Sign in to Google again.".to_string()
+        }
+        reqwest::StatusCode::FORBIDDEN => {
+            format!("The signed-in Google account cannot edit spreadsheet {}. {}", spreadsheet_id, detail)
+        }
+        reqwest::StatusCode::NOT_FOUND => format!("Spreadsheet {} was not found.", spreadsheet_id),
+        _ => format!("Google Sheets returned {}: {}", status, detail),
+    })
+}
+
+/// Headers in the header row of the sheet (text cells, as `excel::read_header_row` reads them).
+pub fn read_headers(connection: &Connection, target: &SheetTarget) -> Result<Vec<HeaderInfo>, String> {
+    let range = sheet_range(&target.sheet_name, &format!("{}:{}", target.header_row, target.header_row));
+    let json = values_request(connection, reqwest::Method::GET, &target.spreadsheet_id, &range, "", &[], None)?;
+    let cells = json.pointer("/values/0").and_then(Value::as_array).cloned().unwrap_or_default();
+    Ok(cells
+        .iter()
+        .enumerate()
+        .filter_map(|(i, cell)| {
+            let text = cell.as_str()?.trim();
+            (!text.is_empty()).then(|| HeaderInfo {
+                column_index: i as u16,
+                column_letter: crate::excel::col_index_to_letter(i as u32),
+                text: text.to_string(),
+            })
+        })
+        .collect())
+}
+
+/// Append `rows` (column letter, value) below the table that starts at the header row, values parsed as if
+/// typed. Returns the sheet row of each.
+pub fn append_rows(connection: &Connection, target: &SheetTarget, rows: &[Vec<(String, String)>]) -> Result<Vec<u32>, String> {
+    let values: Vec<Vec<String>> = rows
+        .iter()
+        .map(|row| {
+            let cells: Vec<(usize, &String)> = row
+                .iter()
+                .filter_map(|(letter, value)| Some((letter_to_index(letter)? as usize, value)))
+                .collect();
+            let mut line = vec![String::new(); cells.iter().map(|(i, _)| i + 1).max().unwrap_or(0)];
+            for (i, value) in cells {
+                line[i] = value.clone();
+            }
+            line
+        })
+        .collect();
+    let range = sheet_range(&target.sheet_name, &format!("A{}", target.header_row));
+    let json = values_request(
+        connection,
+        reqwest::Method::POST,
+        &target.spreadsheet_id,
+        &range,
+        ":append",
+        &[("valueInputOption", "USER_ENTERED"), ("insertDataOption", "INSERT_ROWS")],
+        Some(serde_json::json!({ "majorDimension": "ROWS", "values": values })),
+    )?;
+    // e.g. 'Фактури'!A12:I14
+    let updated = json.pointer("/updates/updatedRange").and_then(Value::as_str).unwrap_or_default();
+    let first_row = updated
+        .rsplit('!')
+        .next()
+        .and_then(|cells| cells.split(':').next())
+        .and_then(|cell| cell.trim_start_matches(|c: char| c.is_ascii_alphabetic()).parse::<u32>().ok())
+        .ok_or_else(|| format!("Unexpected Google Sheets response: {}", json))?;
+    Ok((0..rows.len() as u32).map(|i| first_row + i).collect())
+}
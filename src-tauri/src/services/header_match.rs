@@ -0,0 +1,77 @@
+//! Loose comparison of ledger header text, used by header row detection (`excel_scanner`) and column
+//! mapping suggestions. Headers are folded to plain lowercase Latin — Macedonian and Serbian Cyrillic
+//! transliterated, diacritics dropped — so "Продавач", "Prodavač" and "Prodavac" compare equal, and a
+//! keyword may differ from the header by a typo or two depending on its length.
+
+/// Plain Latin for a lowercase letter, or None to keep it. Digraphs are avoided (ч → c, not ch) so a
+/// transliterated header matches the way it is usually typed without diacritics.
+fn fold_char(c: char) -> Option<&'static str> {
+    let s = match c {
+        'а' => "a", 'б' => "b", 'в' => "v", 'г' => "g", 'д' => "d", 'ѓ' => "g", 'ђ' => "dj", 'е' => "e", 'ж' => "z",
+        'з' => "z", 'ѕ' | 'џ' => "dz", 'и' => "i", 'ј' => "j", 'к' => "k", 'л' => "l", 'љ' => "lj", 'м' => "m",
+        'н' => "n", 'њ' => "nj", 'о' => "o", 'п' => "p", 'р' => "r", 'с' => "s", 'т' => "t", 'ќ' => "k", 'ћ' => "c",
+        'у' => "u", 'ф' => "f", 'х' => "h", 'ц' => "c", 'ч' => "c", 'ш' => "s",
+        'č' | 'ć' => "c", 'š' => "s", 'ž' => "z", 'đ' => "dj",
+        'á' | 'à' | 'â' | 'ä' | 'ã' | 'å' => "a", 'é' | 'è' | 'ê' | 'ë' => "e", 'í' | 'ì' | 'î' | 'ï' => "i",
+        'ó' | 'ò' | 'ô' | 'ö' | 'õ' => "o", 'ú' | 'ù' | 'û' | 'ü' => "u", 'ß' => "ss", 'ç' => "c", 'ñ' => "n",
+        _ => return None,
+    };
+    Some(s)
+}
+
+/// Lowercase, transliterated, without diacritics, whitespace collapsed.
+pub fn fold(text: &str) -> String {
+    let mut folded = String::with_capacity(text.len());
+    for c in text.to_lowercase().chars() {
+        match fold_char(c) {
+            Some(s) => folded.push_str(s),
+            None => folded.push(c),
+        }
+    }
+    folded.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Levenshtein distance over chars.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            cur.push((prev[j] + usize::from(ca != *cb)).min(prev[j + 1] + 1).min(cur[j] + 1));
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
+
+/// Typos allowed in a keyword of `len` characters: none below five, one up to eight, then two.
+fn tolerance(len: usize) -> usize {
+    match len {
+        0..=4 => 0,
+        5..=8 => 1,
+        _ => 2,
+    }
+}
+
+/// Fewest typos with which folded `keyword` appears in folded `header` as whole words (same number of
+/// consecutive words, punctuation around words ignored). None when more than the keyword's length allows.
+pub fn typo_distance(header: &str, keyword: &str) -> Option<usize> {
+    let allowed = tolerance(keyword.chars().count());
+    if allowed == 0 {
+        return None;
+    }
+    let words: Vec<&str> = header
+        .split_whitespace()
+        .map(|w| w.trim_matches(|c: char| !c.is_alphanumeric()))
+        .collect();
+    let n = keyword.split_whitespace().count();
+    if n == 0 || words.len() < n {
+        return None;
+    }
+    words
+        .windows(n)
+        .map(|window| edit_distance(&window.join(" "), keyword))
+        .filter(|d| *d <= allowed)
+        .min()
+}
@@ -0,0 +1,191 @@
+//! End-of-month summaries from history: the filtered documents with totals per seller and per month,
+//! exported to .xlsx (see `excel::export_history_report`) or CSV.
+
+use crate::db::HistoryReportRow;
+use crate::services::spending_report::{field_amount, parse_invoice_date};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryReportFilter {
+    /// Inclusive "YYYY-MM-DD" bounds on the document date (scan date when the document has none).
+    pub date_from: Option<String>,
+    pub date_to: Option<String>,
+    /// None = all folders, -1 = uncategorized; a folder includes its subfolders.
+    pub folder_id: Option<i64>,
+    pub status: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryReportEntry {
+    pub history_id: i64,
+    /// "YYYY-MM-DD"
+    pub date: String,
+    pub document_type: String,
+    pub file_name: String,
+    pub seller: String,
+    pub invoice_number: String,
+    pub net_amount: Option<f64>,
+    pub tax_amount: Option<f64>,
+    pub total_amount: Option<f64>,
+    pub status: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportGroupTotal {
+    /// Seller name, or "YYYY-MM" for monthly totals.
+    pub key: String,
+    pub document_count: u32,
+    pub net_amount: f64,
+    pub tax_amount: f64,
+    pub total_amount: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryReport {
+    pub entries: Vec<HistoryReportEntry>,
+    /// Largest total first.
+    pub by_seller: Vec<ReportGroupTotal>,
+    /// Oldest month first.
+    pub by_month: Vec<ReportGroupTotal>,
+    pub total_amount: f64,
+}
+
+fn parse_bound(value: Option<&str>, name: &str) -> Result<Option<NaiveDate>, String> {
+    match value.map(str::trim).filter(|v| !v.is_empty()) {
+        Some(v) => NaiveDate::parse_from_str(v, "%Y-%m-%d")
+            .map(Some)
+            .map_err(|_| format!("Invalid {} '{}'. Use YYYY-MM-DD.", name, v)),
+        None => Ok(None),
+    }
+}
+
+fn round2(n: f64) -> f64 {
+    (n * 100.0).round() / 100.0
+}
+
+fn text_field(data: &Value, key: &str) -> String {
+    data.get(key).and_then(|v| v.as_str()).unwrap_or_default().trim().to_string()
+}
+
+fn add_to(group: &mut ReportGroupTotal, entry: &HistoryReportEntry) {
+    group.document_count += 1;
+    group.net_amount += entry.net_amount.unwrap_or(0.0);
+    group.tax_amount += entry.tax_amount.unwrap_or(0.0);
+    group.total_amount += entry.total_amount.unwrap_or(0.0);
+}
+
+fn finish(mut group: ReportGroupTotal) -> ReportGroupTotal {
+    group.net_amount = round2(group.net_amount);
+    group.tax_amount = round2(group.tax_amount);
+    group.total_amount = round2(group.total_amount);
+    group
+}
+
+/// `rows` come from `Db::get_history_report_rows`, already narrowed by folder and status.
+pub fn build_history_report(filter: &HistoryReportFilter, rows: &[HistoryReportRow]) -> Result<HistoryReport, String> {
+    let from = parse_bound(filter.date_from.as_deref(), "start date")?;
+    let to = parse_bound(filter.date_to.as_deref(), "end date")?;
+    if let (Some(f), Some(t)) = (from, to) {
+        if f > t {
+            return Err("The start date is after the end date.".to_string());
+        }
+    }
+
+    let mut entries = Vec::new();
+    for (id, created_at, document_type, file_path_or_name, extracted_data, status) in rows {
+        let data: Value = serde_json::from_str(extracted_data).unwrap_or(Value::Null);
+        let date = data
+            .get("date")
+            .and_then(|v| v.as_str())
+            .and_then(parse_invoice_date)
+            .or_else(|| chrono::DateTime::parse_from_rfc3339(created_at).ok().map(|dt| dt.date_naive()));
+        let Some(date) = date else { continue };
+        if from.is_some_and(|f| date < f) || to.is_some_and(|t| date > t) {
+            continue;
+        }
+        entries.push(HistoryReportEntry {
+            history_id: *id,
+            date: date.format("%Y-%m-%d").to_string(),
+            document_type: document_type.clone(),
+            file_name: Path::new(file_path_or_name)
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_else(|| file_path_or_name.clone()),
+            seller: text_field(&data, "seller_name"),
+            invoice_number: text_field(&data, "invoice_number"),
+            net_amount: field_amount(&data, "net_amount"),
+            tax_amount: field_amount(&data, "tax_amount"),
+            total_amount: field_amount(&data, "total_amount"),
+            status: status.clone(),
+        });
+    }
+    entries.sort_by(|a, b| a.date.cmp(&b.date).then(a.history_id.cmp(&b.history_id)));
+
+    let empty = |key: String| ReportGroupTotal {
+        key,
+        document_count: 0,
+        net_amount: 0.0,
+        tax_amount: 0.0,
+        total_amount: 0.0,
+    };
+    let mut sellers: BTreeMap<String, ReportGroupTotal> = BTreeMap::new();
+    let mut months: BTreeMap<String, ReportGroupTotal> = BTreeMap::new();
+    for entry in &entries {
+        let seller = if entry.seller.is_empty() { "(unknown seller)".to_string() } else { entry.seller.clone() };
+        add_to(sellers.entry(seller.clone()).or_insert_with(|| empty(seller)), entry);
+        let month = entry.date[..7].to_string();
+        add_to(months.entry(month.clone()).or_insert_with(|| empty(month)), entry);
+    }
+    let mut by_seller: Vec<ReportGroupTotal> = sellers.into_values().map(finish).collect();
+    by_seller.sort_by(|a, b| b.total_amount.partial_cmp(&a.total_amount).unwrap_or(std::cmp::Ordering::Equal));
+    let by_month: Vec<ReportGroupTotal> = months.into_values().map(finish).collect();
+
+    Ok(HistoryReport {
+        total_amount: round2(entries.iter().filter_map(|e| e.total_amount).sum()),
+        entries,
+        by_seller,
+        by_month,
+    })
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn csv_amount(value: Option<f64>) -> String {
+    value.map(|n| format!("{:.2}", n)).unwrap_or_default()
+}
+
+/// The report's documents as CSV (UTF-8 with BOM so Excel shows Cyrillic correctly). Returns the saved path.
+pub fn write_history_report_csv(report: &HistoryReport, path: &Path) -> Result<String, String> {
+    let mut out = String::from("\u{FEFF}Date,Type,File,Seller,Invoice number,Net,VAT,Total,Status\r\n");
+    for e in &report.entries {
+        let cells = [
+            e.date.clone(),
+            e.document_type.clone(),
+            e.file_name.clone(),
+            e.seller.clone(),
+            e.invoice_number.clone(),
+            csv_amount(e.net_amount),
+            csv_amount(e.tax_amount),
+            csv_amount(e.total_amount),
+            e.status.clone(),
+        ];
+        out.push_str(&cells.iter().map(|c| csv_field(c)).collect::<Vec<_>>().join(","));
+        out.push_str("\r\n");
+    }
+    std::fs::write(path, out).map_err(|e| format!("Cannot write to file: {}", e))?;
+    Ok(path.to_string_lossy().to_string())
+}
@@ -0,0 +1,113 @@
+//! Invoice fields from plain text (a PDF's own text layer or a prebuilt-read result): document number,
+//! dates, tax numbers, parties and amounts found by their labels. Less precise than the invoice
+//! analyzers, so only labelled values are taken; anything not found is left for review.
+
+use crate::services::validation::parse_amount;
+use crate::types::InvoiceFieldValue;
+use regex::Regex;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// Label keywords (lowercase) per amount field; the first matching line wins.
+const TOTAL_LABELS: &[&str] = &["вкупно за плаќање", "за плаќање", "вкупен износ", "вкупно", "total"];
+const NET_LABELS: &[&str] = &["без ддв", "износ без", "даночна основа", "основа", "subtotal", "нето"];
+const TAX_LABELS: &[&str] = &["ддв", "vat", "данок"];
+const SELLER_LABELS: &[&str] = &["продавач", "издавач", "добавувач", "seller", "supplier"];
+const BUYER_LABELS: &[&str] = &["купувач", "примач", "налогодавач", "buyer", "customer"];
+const DATE_LABELS: &[&str] = &["датум на фактура", "дата на фактура", "датум", "дата", "date"];
+const DUE_LABELS: &[&str] = &["рок на плаќање", "достасува", "валута", "due"];
+
+fn number_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(r"(?i)(?:фактура|faktura|invoice)\s*(?:бр\.?|број|no\.?|nr\.?|#)?\s*:?\s*([A-Za-z0-9][A-Za-z0-9/.\-]*\d[A-Za-z0-9/\-]*)")
+            .expect("valid regex")
+    })
+}
+
+fn date_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"\b(\d{1,2}[./]\d{1,2}[./]\d{4})\b").expect("valid regex"))
+}
+
+fn tax_id_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"\b((?:MK|МК)\s?)?(\d{13})\b").expect("valid regex"))
+}
+
+fn amount_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"-?\d{1,3}(?:[.\s]\d{3})*,\d{2}\b|-?\d+\.\d{2}\b|-?\d{1,3}(?:,\d{3})+\.\d{2}\b").expect("valid regex"))
+}
+
+fn value(value: String) -> InvoiceFieldValue {
+    InvoiceFieldValue { value, confidence: None, ..Default::default() }
+}
+
+/// Lines that contain one of `labels`, in text order.
+fn labelled<'a>(lines: &'a [&'a str], labels: &'a [&'a str]) -> impl Iterator<Item = &'a str> + 'a {
+    lines.iter().copied().filter(move |l| {
+        let lower = l.to_lowercase();
+        labels.iter().any(|k| lower.contains(k))
+    })
+}
+
+/// Last amount on a line (labels come first, the value at the end of the line or table row).
+fn last_amount(line: &str) -> Option<f64> {
+    amount_re().find_iter(line).last().and_then(|m| parse_amount(m.as_str()))
+}
+
+/// Text after the label's ':' or in the next table cell.
+fn labelled_text(line: &str) -> Option<String> {
+    let rest = line.split_once(':').map(|(_, r)| r).or_else(|| line.split('|').map(str::trim).filter(|c| !c.is_empty()).nth(1))?;
+    let rest = rest.split('|').next().unwrap_or(rest).trim();
+    (rest.chars().filter(|c| c.is_alphabetic()).count() >= 3).then(|| rest.to_string())
+}
+
+/// Invoice fields found in `text`; empty when nothing labelled was found.
+pub fn extract(text: &str) -> HashMap<String, InvoiceFieldValue> {
+    let lines: Vec<&str> = text.lines().map(str::trim).filter(|l| !l.is_empty()).collect();
+    let mut fields: HashMap<String, InvoiceFieldValue> = HashMap::new();
+
+    if let Some(c) = number_re().captures(text) {
+        fields.insert("invoice_number".to_string(), value(c[1].trim_end_matches('.').to_string()));
+    }
+    let date_on = |labels: &[&str]| labelled(&lines, labels).find_map(|l| date_re().captures(l).map(|c| c[1].replace('/', ".")));
+    if let Some(date) = date_on(DATE_LABELS).or_else(|| date_re().captures(text).map(|c| c[1].replace('/', "."))) {
+        fields.insert("date".to_string(), value(date));
+    }
+    if let Some(due) = date_on(DUE_LABELS).filter(|d| fields.get("date").map_or(true, |f| f.value != *d)) {
+        fields.insert("due_date".to_string(), value(due));
+    }
+
+    // Tax numbers: the first is usually the seller's (letterhead), a second one the buyer's.
+    let mut tax_ids = tax_id_re().captures_iter(text).map(|c| c[2].to_string());
+    if let Some(id) = tax_ids.next() {
+        fields.insert("seller_tax_id".to_string(), value(id));
+    }
+    if let Some(id) = tax_ids.find(|id| fields.get("seller_tax_id").map_or(true, |f| f.value != *id)) {
+        fields.insert("buyer_tax_id".to_string(), value(id));
+    }
+    if let Some(name) = labelled(&lines, SELLER_LABELS).find_map(labelled_text) {
+        fields.insert("seller_name".to_string(), value(name));
+    }
+    if let Some(name) = labelled(&lines, BUYER_LABELS).find_map(labelled_text) {
+        fields.insert("buyer_name".to_string(), value(name));
+    }
+
+    let total = labelled(&lines, TOTAL_LABELS).find_map(last_amount);
+    let net = labelled(&lines, NET_LABELS).find_map(last_amount);
+    // A tax line mentions ДДВ but is not the net ("без ДДВ") or total line.
+    let tax = labelled(&lines, TAX_LABELS)
+        .filter(|l| {
+            let lower = l.to_lowercase();
+            !NET_LABELS.iter().chain(TOTAL_LABELS).any(|k| lower.contains(k))
+        })
+        .find_map(last_amount);
+    for (key, amount) in [("total_amount", total), ("net_amount", net), ("tax_amount", tax)] {
+        if let Some(a) = amount {
+            fields.insert(key.to_string(), value(format!("{:.2}", a)));
+        }
+    }
+    fields
+}
@@ -0,0 +1,60 @@
+//! Last data row of a ledger sheet, shared by the calamine and edit-xlsx readers. By default the whole
+//! used range (the sheet's dimension) is searched from the bottom up, so gaps such as quarter separators
+//! and ledgers longer than any fixed scan limit are handled. The older "stop after N empty rows"
+//! behaviour can be chosen in settings for sheets with notes far below the table; when it ignores
+//! data further down, the result says so.
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LastRowStrategy {
+    /// Last non-empty row anywhere in the sheet's used range.
+    Dimension,
+    /// Scan down from the header and stop after this many consecutive empty rows.
+    GapLimit(u32),
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LastRowScan {
+    /// 1-based; the header row when there is no data below it.
+    pub last_row: u32,
+    /// True when the gap strategy stopped early and data exists further down the sheet.
+    pub truncated: bool,
+}
+
+/// Find the last row below `header_row` for which `row_has_data` is true. `max_row` is the sheet's last
+/// used row (1-based, from its dimension).
+pub fn find_last_row(
+    header_row: u32,
+    max_row: u32,
+    strategy: LastRowStrategy,
+    mut row_has_data: impl FnMut(u32) -> bool,
+) -> LastRowScan {
+    match strategy {
+        LastRowStrategy::Dimension => LastRowScan {
+            last_row: (header_row + 1..=max_row).rev().find(|r| row_has_data(*r)).unwrap_or(header_row),
+            truncated: false,
+        },
+        LastRowStrategy::GapLimit(gap) => {
+            let gap = gap.max(1);
+            let mut last_row = header_row;
+            let mut empty = 0u32;
+            let mut row = header_row + 1;
+            while row <= max_row {
+                if row_has_data(row) {
+                    last_row = row;
+                    empty = 0;
+                } else {
+                    empty += 1;
+                    if empty >= gap {
+                        break;
+                    }
+                }
+                row += 1;
+            }
+            let truncated = row < max_row && (row + 1..=max_row).rev().any(row_has_data);
+            LastRowScan { last_row, truncated }
+        }
+    }
+}
@@ -0,0 +1,172 @@
+//! Versioned copies of a profile's ledger workbook, taken after every N appends and/or once a day, so a
+//! workbook mangled by hand can be rolled back. Copies are stored once per content hash under
+//! `snapshots/` in app data; unchanged workbooks do not take extra space.
+
+use crate::cache::schema_cache;
+use crate::db::{Db, LedgerSnapshotRow};
+use crate::services::excel_lock;
+use crate::services::export_delivery::{copy_verified, sha256_file};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const SNAPSHOTS_DIR: &str = "snapshots";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotPolicy {
+    /// Snapshot after this many appends; None disables the count trigger.
+    pub every_appends: Option<u32>,
+    /// Snapshot on the first append of each day.
+    pub daily: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LedgerSnapshot {
+    pub id: i64,
+    pub profile_id: i64,
+    pub file_path: String,
+    pub sha256: String,
+    pub size_bytes: u64,
+    /// "appends", "daily", "manual" or "before_restore".
+    pub reason: String,
+    pub created_at: String,
+}
+
+impl From<LedgerSnapshotRow> for LedgerSnapshot {
+    fn from((id, profile_id, file_path, sha256, size_bytes, reason, created_at): LedgerSnapshotRow) -> Self {
+        LedgerSnapshot {
+            id,
+            profile_id,
+            file_path,
+            sha256,
+            size_bytes,
+            reason,
+            created_at,
+        }
+    }
+}
+
+fn blob_path(app_data_dir: &Path, sha256: &str, file_path: &str) -> PathBuf {
+    let extension = Path::new(file_path)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_else(|| "xlsx".to_string());
+    app_data_dir.join(SNAPSHOTS_DIR).join(format!("{}.{}", sha256, extension))
+}
+
+pub fn get_policy(db: &Db, profile_id: i64) -> Result<Option<SnapshotPolicy>, String> {
+    Ok(db
+        .get_snapshot_policy(profile_id)?
+        .map(|(every_appends, daily, _)| SnapshotPolicy { every_appends, daily }))
+}
+
+/// Set the profile's policy; None (or a policy with no trigger) turns automatic snapshots off.
+pub fn save_policy(db: &Db, profile_id: i64, policy: Option<SnapshotPolicy>) -> Result<Option<SnapshotPolicy>, String> {
+    db.get_profile_by_id(profile_id)?;
+    match policy {
+        Some(p) if p.every_appends.is_some_and(|n| n > 0) || p.daily => {
+            let every_appends = p.every_appends.filter(|n| *n > 0);
+            db.save_snapshot_policy(profile_id, every_appends, p.daily)?;
+            Ok(Some(SnapshotPolicy { every_appends, daily: p.daily }))
+        }
+        _ => {
+            db.delete_snapshot_policy(profile_id)?;
+            Ok(None)
+        }
+    }
+}
+
+/// Drop a deleted profile's policy; its snapshots are kept and can still be restored by id.
+pub fn remove_policy(db: &Db, profile_id: i64) -> Result<(), String> {
+    Ok(db.delete_snapshot_policy(profile_id)?)
+}
+
+/// Copy the profile's workbook into the snapshot store. When the newest snapshot already has the same
+/// content, that one is returned instead of adding a duplicate.
+pub fn take_snapshot(db: &Db, app_data_dir: &Path, profile_id: i64, reason: &str) -> Result<LedgerSnapshot, String> {
+    let (file_path, _, _) = db.get_profile_by_id(profile_id)?;
+    snapshot_file(db, app_data_dir, profile_id, &file_path, reason)
+}
+
+fn snapshot_file(
+    db: &Db,
+    app_data_dir: &Path,
+    profile_id: i64,
+    file_path: &str,
+    reason: &str,
+) -> Result<LedgerSnapshot, String> {
+    let src = Path::new(file_path);
+    let size_bytes = fs::metadata(src)
+        .map_err(|e| format!("Could not read {}: {}", src.display(), e))?
+        .len();
+    let sha256 = sha256_file(src).map_err(|e| format!("Could not read {}: {}", src.display(), e))?;
+    if let Some(latest) = db.list_ledger_snapshots(profile_id)?.into_iter().next() {
+        if latest.3 == sha256 && latest.2 == file_path {
+            db.reset_snapshot_counter(profile_id)?;
+            return Ok(latest.into());
+        }
+    }
+    let blob = blob_path(app_data_dir, &sha256, file_path);
+    if !blob.exists() {
+        copy_verified(src, &blob, &sha256)?;
+    }
+    let id = db.add_ledger_snapshot(profile_id, file_path, &sha256, size_bytes, reason)?;
+    db.get_ledger_snapshot(id)?
+        .map(LedgerSnapshot::from)
+        .ok_or_else(|| "Snapshot was not saved.".to_string())
+}
+
+/// Count an append to the profile's workbook and take a snapshot when the policy says one is due.
+pub fn record_append(db: &Db, app_data_dir: &Path, profile_id: i64) -> Result<Option<LedgerSnapshot>, String> {
+    let Some((every_appends, daily, _)) = db.get_snapshot_policy(profile_id)? else {
+        return Ok(None);
+    };
+    let count = db.increment_snapshot_counter(profile_id)?;
+    let reason = if every_appends.is_some_and(|n| count >= n) {
+        "appends"
+    } else if daily && !snapshot_taken_today(db, profile_id)? {
+        "daily"
+    } else {
+        return Ok(None);
+    };
+    take_snapshot(db, app_data_dir, profile_id, reason).map(Some)
+}
+
+fn snapshot_taken_today(db: &Db, profile_id: i64) -> Result<bool, String> {
+    let today = chrono::Local::now().date_naive();
+    Ok(db.list_ledger_snapshots(profile_id)?.first().is_some_and(|s| {
+        chrono::DateTime::parse_from_rfc3339(&s.6)
+            .map(|dt| dt.with_timezone(&chrono::Local).date_naive() == today)
+            .unwrap_or(false)
+    }))
+}
+
+pub fn list_snapshots(db: &Db, profile_id: i64) -> Result<Vec<LedgerSnapshot>, String> {
+    Ok(db.list_ledger_snapshots(profile_id)?.into_iter().map(LedgerSnapshot::from).collect())
+}
+
+/// Put a snapshot back in place of its workbook. The current workbook is snapshotted first
+/// ("before_restore"), so a restore can itself be undone.
+pub fn restore_snapshot(db: &Db, app_data_dir: &Path, snapshot_id: i64) -> Result<LedgerSnapshot, String> {
+    let snapshot: LedgerSnapshot = db
+        .get_ledger_snapshot(snapshot_id)?
+        .map(LedgerSnapshot::from)
+        .ok_or_else(|| format!("Snapshot {} not found.", snapshot_id))?;
+    let target = Path::new(&snapshot.file_path);
+    if excel_lock::is_write_locked(target) {
+        return Err(excel_lock::locked_error(target));
+    }
+    let blob = blob_path(app_data_dir, &snapshot.sha256, &snapshot.file_path);
+    if !blob.exists() {
+        return Err(format!("The stored copy of snapshot {} is missing.", snapshot_id));
+    }
+    if target.exists() {
+        snapshot_file(db, app_data_dir, snapshot.profile_id, &snapshot.file_path, "before_restore")?;
+    }
+    copy_verified(&blob, target, &snapshot.sha256)?;
+    // The cached schema's next free row no longer matches the restored workbook.
+    schema_cache::invalidate_cache(snapshot.profile_id);
+    Ok(snapshot)
+}
@@ -0,0 +1,195 @@
+//! Optional LLM post-processing for documents Azure reads as plain text (plata, generic): the text is
+//! sent to an OpenAI-compatible chat completions endpoint (a local Ollama / LM Studio / llama.cpp
+//! server or a remote service) together with the document type's field schema, and the JSON answer
+//! becomes fields. Disabled while no endpoint is configured; the API key lives in the OS credential store.
+
+use crate::db::Db;
+use crate::services::field_schemas::SchemaField;
+use crate::services::secrets;
+use crate::types::{InvoiceData, InvoiceFieldValue, OcrInvoiceResult};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+const ENDPOINT: &str = "llm_endpoint";
+const MODEL: &str = "llm_model";
+/// Credential-store name of the API key (not needed by most local servers).
+pub(crate) const API_KEY_NAME: &str = "llm_api_key";
+
+/// Document types Azure returns only as text.
+pub const TEXT_ONLY_TYPES: &[&str] = &["plata", "generic"];
+/// Longest text sent to the model; the rest of very long documents is cut off.
+const MAX_TEXT_CHARS: usize = 24_000;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LlmSettings {
+    /// Full chat completions URL, e.g. http://localhost:11434/v1/chat/completions. None = disabled.
+    pub endpoint: Option<String>,
+    pub model: Option<String>,
+    pub api_key_configured: bool,
+}
+
+/// None leaves a value unchanged, an empty string clears it.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LlmSettingsUpdate {
+    pub endpoint: Option<String>,
+    pub model: Option<String>,
+    pub api_key: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct LlmConfig {
+    pub endpoint: String,
+    pub model: String,
+    pub api_key: Option<String>,
+}
+
+fn non_empty(value: Option<&String>) -> Option<String> {
+    value.map(|v| v.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+pub fn get_settings(db: &Db) -> Result<LlmSettings, String> {
+    let stored = db.get_settings()?;
+    Ok(LlmSettings {
+        endpoint: non_empty(stored.get(ENDPOINT)),
+        model: non_empty(stored.get(MODEL)),
+        api_key_configured: secrets::get_secret(API_KEY_NAME).ok().flatten().is_some(),
+    })
+}
+
+pub fn save_settings(db: &Db, update: LlmSettingsUpdate) -> Result<LlmSettings, String> {
+    let cleared = |v: Option<String>| v.map(|v| Some(v.trim().to_string()).filter(|v| !v.is_empty()));
+    let mut entries: Vec<(&str, Option<String>)> = Vec::new();
+    if let Some(endpoint) = cleared(update.endpoint) {
+        if let Some(e) = &endpoint {
+            if !e.starts_with("http://") && !e.starts_with("https://") {
+                return Err("LLM endpoint must start with http:// or https://".to_string());
+            }
+        }
+        entries.push((ENDPOINT, endpoint));
+    }
+    if let Some(model) = cleared(update.model) {
+        entries.push((MODEL, model));
+    }
+    if let Some(key) = cleared(update.api_key) {
+        match key {
+            Some(key) => secrets::set_secret(API_KEY_NAME, &key)?,
+            None => secrets::delete_secret(API_KEY_NAME)?,
+        }
+    }
+    db.set_settings(&entries)?;
+    get_settings(db)
+}
+
+/// Endpoint settings when post-processing is enabled. The API key falls back to LLM_API_KEY.
+pub fn config(db: &Db) -> Result<Option<LlmConfig>, String> {
+    let stored = db.get_settings()?;
+    let Some(endpoint) = non_empty(stored.get(ENDPOINT)) else {
+        return Ok(None);
+    };
+    let api_key = secrets::get_secret(API_KEY_NAME)
+        .ok()
+        .flatten()
+        .or_else(|| std::env::var("LLM_API_KEY").ok())
+        .filter(|k| !k.trim().is_empty());
+    Ok(Some(LlmConfig {
+        endpoint,
+        model: non_empty(stored.get(MODEL)).unwrap_or_else(|| "llama3.1".to_string()),
+        api_key,
+    }))
+}
+
+/// True for a text-only result of a type that gets post-processed.
+pub fn applies(document_type: Option<&str>, result: &OcrInvoiceResult) -> bool {
+    document_type.is_some_and(|t| TEXT_ONLY_TYPES.contains(&t)) && result.raw_text.as_deref().is_some_and(|t| !t.trim().is_empty())
+}
+
+fn prompt(schema: &[SchemaField], text: &str) -> String {
+    let wanted = if schema.is_empty() {
+        "Return every labelled value on the document as {\"camelCaseKey\": \"value\"}.".to_string()
+    } else {
+        let lines: Vec<String> = schema
+            .iter()
+            .map(|f| format!("- {} ({}): {}", f.key, serde_json::to_string(&f.field_type).unwrap_or_default().trim_matches('"'), f.label))
+            .collect();
+        format!("Fields:\n{}\nReturn {{\"<key>\": \"<value>\"}} for these keys only.", lines.join("\n"))
+    };
+    let text: String = text.chars().take(MAX_TEXT_CHARS).collect();
+    format!(
+        "{}\nWrite values as they appear in the text; dates as DD.MM.YYYY, amounts as plain numbers with a dot as \
+         decimal separator. Leave out fields that are not in the text.\n\nText:\n{}",
+        wanted, text
+    )
+}
+
+/// The JSON object in a model answer (which may wrap it in ``` fences or prose).
+fn answer_object(content: &str) -> Option<serde_json::Map<String, Value>> {
+    let start = content.find('{')?;
+    let end = content.rfind('}')?;
+    serde_json::from_str::<Value>(content.get(start..=end)?).ok()?.as_object().cloned()
+}
+
+/// Ask the model for the schema's fields in `text`. Blocking; call off the async runtime.
+pub fn extract(cfg: &LlmConfig, schema: &[SchemaField], text: &str) -> Result<HashMap<String, InvoiceFieldValue>, String> {
+    let body = serde_json::json!({
+        "model": cfg.model,
+        "temperature": 0,
+        "response_format": { "type": "json_object" },
+        "messages": [
+            { "role": "system", "content": "You extract fields from OCR text of Macedonian business documents. Reply with one JSON object only." },
+            { "role": "user", "content": prompt(schema, text) },
+        ],
+    });
+    let client = reqwest::blocking::Client::builder()
+        .timeout(std::time::Duration::from_secs(180))
+        .build()
+        .map_err(|e| e.to_string())?;
+    let mut request = client.post(&cfg.endpoint).json(&body);
+    if let Some(key) = &cfg.api_key {
+        request = request.bearer_auth(key);
+    }
+    let response = request.send().map_err(|e| format!("Could not reach the LLM endpoint: {}", e))?;
+    let status = response.status();
+    let json: Value = response.json().map_err(|e| format!("Unexpected LLM response: {}", e))?;
+    if !status.is_success() {
+        return Err(format!("LLM endpoint returned {}: {}", status, json.to_string().chars().take(300).collect::<String>()));
+    }
+    let content = json
+        .pointer("/choices/0/message/content")
+        .and_then(|c| c.as_str())
+        .ok_or("LLM response has no message content.")?;
+    let object = answer_object(content).ok_or("LLM answer is not a JSON object.")?;
+
+    let mut fields = HashMap::new();
+    for (key, value) in object {
+        if !schema.is_empty() && !schema.iter().any(|f| f.key == key) {
+            continue;
+        }
+        let value = match value {
+            Value::String(s) => s.trim().to_string(),
+            Value::Number(n) => n.to_string(),
+            _ => continue,
+        };
+        if !value.is_empty() {
+            fields.insert(key, InvoiceFieldValue { value, confidence: None, ..Default::default() });
+        }
+    }
+    Ok(fields)
+}
+
+/// Add extracted fields that are still missing; the raw-text description goes once anything was added.
+pub fn merge(data: &mut InvoiceData, extracted: HashMap<String, InvoiceFieldValue>) {
+    let keeps_description = extracted.contains_key("description");
+    let mut added = false;
+    for (key, value) in extracted {
+        if data.fields.get(&key).map_or(true, |f| f.value.trim().is_empty()) {
+            data.fields.insert(key, value);
+            added = true;
+        }
+    }
+    if added && !keeps_description {
+        data.fields.remove("description");
+    }
+}
@@ -0,0 +1,403 @@
+//! Column mapping suggestions: which field each ledger column most likely holds. Three signals are
+//! combined per column: header keywords (Macedonian first, plus English, Serbian and German), the kind of
+//! values in the column's samples, and mappings learned for a workbook with the same headers (same
+//! schema hash) or saved by other profiles for the same header text. The frontend's mapping dialog and
+//! `create_profile_from_template` use the same ranking.
+
+use crate::db::Db;
+use crate::excel;
+use crate::services::header_match;
+use crate::services::spending_report::parse_invoice_date;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Header keywords per field (the old `HEADER_KEYWORDS` of the frontend), lowercase.
+const HEADER_KEYWORDS: &[(&str, &[&str])] = &[
+    (
+        "document_type",
+        &[
+            "document type", "тип на документ", "вид документ", "rechnung", "faktura", "invoice",
+            "тип документ", "вид на документ", "даночен биланс", "type of document", "typeofdocument",
+        ],
+    ),
+    (
+        "invoice_number",
+        &[
+            "invoice number", "invoice no", "invoice no.", "invoice #", "број на документ", "број на фактура",
+            "document number", "broj dokument", "broj fakture", "br.", "no.", "#", "broj", "number",
+            "rechnungsnummer", "фактура бр", "документ бр",
+        ],
+    ),
+    (
+        "document_number",
+        &[
+            "invoice id", "document number", "број на документ", "број на фактура", "invoice no",
+        ],
+    ),
+    (
+        "date",
+        &[
+            "date", "document date", "дата на документ", "датум на фактура", "datum", "дата", "датум",
+            "rechnungsdatum", "invoice date", "period", "период",
+        ],
+    ),
+    (
+        "seller_name",
+        &[
+            "seller name", "seller", "продавач", "prodavac", "dobavljac", "испраќач", "издавач", "supplier",
+            "vendor",
+        ],
+    ),
+    (
+        "seller_address",
+        &[
+            "seller address", "адреса продавач", "адреса на продавач", "seller addr", "supplier address",
+        ],
+    ),
+    (
+        "seller_tax_id",
+        &[
+            "seller tax", "seller tax id", "даночен број", "мат. број", "pib", "vat number", "tax id",
+            "company id",
+        ],
+    ),
+    (
+        "seller_edb",
+        &[
+            "едб", "vendor tax id", "даночен број", "pib", "tax id", "company id",
+        ],
+    ),
+    (
+        "buyer_name",
+        &[
+            "buyer name", "buyer", "купувач", "kupac", "client", "клиент", "примач", "customer",
+        ],
+    ),
+    (
+        "buyer_address",
+        &[
+            "buyer address", "адреса купувач", "адреса на купувач", "buyer addr", "client address",
+        ],
+    ),
+    (
+        "buyer_tax_id",
+        &[
+            "buyer tax", "buyer tax id", "даночен број купувач", "куп tax", "client tax",
+        ],
+    ),
+    (
+        "description",
+        &[
+            "description", "опис", "опис во документ", "opis", "item", "items", "услуги", "services",
+            "предмет", "subject",
+        ],
+    ),
+    (
+        "net_amount",
+        &[
+            "net amount", "net", "нето износ", "neto", "nettobetrag", "net value", "subtotal",
+        ],
+    ),
+    (
+        "total_amount",
+        &[
+            "total amount", "total", "gross amount", "бруто износ", "ukupno", "износ", "amount",
+            "bruttobetrag", "sum", "total sum", "вкупно", "износ за плаќање", "јануари", "фебруари", "март",
+            "април", "мај", "јуни", "јули", "август", "септември", "октомври", "ноември", "декември",
+            "бруто плата",
+        ],
+    ),
+    (
+        "tax_amount",
+        &[
+            "tax amount", "tax", "vat", "ддв", "ddv", "pdv", "mwst", "ддв 18%", "18%", "tax (vat)",
+        ],
+    ),
+    (
+        "currency",
+        &[
+            "currency", "валута", "valuta", "curr",
+        ],
+    ),
+    (
+        "due_date",
+        &[
+            "due date", "рок плаќање", "рок на плаќање", "fällig", "payment due", "datum dospelosti",
+        ],
+    ),
+    (
+        "reference",
+        &[
+            "reference", "референца", "збор", "ref", "poziv na broj",
+        ],
+    ),
+    (
+        "delivery_note_number",
+        &[
+            "испратница", "број на испратница", "delivery note", "otpremnica",
+        ],
+    ),
+    (
+        "payment_method",
+        &[
+            "payment method", "начин плаќање", "payment", "zahlungsart", "плаќање",
+        ],
+    ),
+];
+
+/// Fields whose values are amounts, dates or identification numbers; the rest are text.
+const AMOUNT_FIELDS: &[&str] = &["net_amount", "tax_amount", "total_amount"];
+const DATE_FIELDS: &[&str] = &["date", "due_date"];
+const ID_FIELDS: &[&str] = &["seller_edb", "seller_tax_id", "buyer_tax_id"];
+const TEXT_FIELDS: &[&str] =
+    &["seller_name", "seller_address", "buyer_name", "buyer_address", "description", "document_type"];
+
+/// Score of a header equal to a keyword; a keyword inside a longer header scores less the more else
+/// the header holds.
+const EXACT_KEYWORD: f64 = 0.9;
+const PARTIAL_KEYWORD: f64 = 0.55;
+/// Taken off a match found only after folding, and per typo.
+const FOLDED_PENALTY: f64 = 0.05;
+const TYPO_PENALTY: f64 = 0.1;
+/// Confidence of a field another profile maps a column with the same header text to.
+const PROFILE_MATCH: f64 = 0.6;
+/// Added when the samples look like the field's values, subtracted when they clearly do not.
+const SAMPLES_MATCH: f64 = 0.1;
+const SAMPLES_CONFLICT: f64 = 0.3;
+/// Lowest score `best_mapping` assigns.
+const MIN_SCORE: f64 = 0.5;
+/// Suggestions kept per column.
+const MAX_PER_COLUMN: usize = 5;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldSuggestion {
+    pub field_key: String,
+    /// 0..1
+    pub score: f64,
+    /// Signals behind the score: "keyword", "samples", "learned", "profile".
+    pub sources: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ColumnSuggestions {
+    pub column_letter: String,
+    pub header: String,
+    /// Best first.
+    pub suggestions: Vec<FieldSuggestion>,
+}
+
+/// The field chosen for one column by `best_mapping`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MappingSuggestion {
+    pub column_letter: String,
+    pub field_key: String,
+    pub confidence: f64,
+    /// Strongest signal: "learned", "profile" or "keyword".
+    pub source: String,
+}
+
+fn normalize(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+/// Best score of `header` (normalized) against a field's keywords. Both are also compared folded (see
+/// `header_match`), so transliterated and diacritic-free spellings match, and longer keywords may be off
+/// by a typo.
+fn keyword_score(header: &str, keywords: &[&str]) -> f64 {
+    let folded = header_match::fold(header);
+    let header_len = folded.chars().count();
+    let whole_words = header.split_whitespace().count();
+    keywords
+        .iter()
+        .map(|keyword| {
+            if header == *keyword {
+                return EXACT_KEYWORD;
+            }
+            let keyword = header_match::fold(keyword);
+            if folded == keyword {
+                return EXACT_KEYWORD - FOLDED_PENALTY;
+            }
+            let len = keyword.chars().count();
+            // Short keywords ("#", "br.", "ддв") only count as whole words.
+            let found = if len >= 4 {
+                folded.contains(&keyword)
+            } else {
+                folded.split(|c: char| c.is_whitespace() || c == '/' || c == '(' || c == ')').any(|w| w == keyword)
+            };
+            let partial = PARTIAL_KEYWORD + 0.3 * len as f64 / header_len.max(1) as f64;
+            if found {
+                return partial;
+            }
+            match header_match::typo_distance(&folded, &keyword) {
+                Some(typos) if keyword.split_whitespace().count() == whole_words => {
+                    EXACT_KEYWORD - FOLDED_PENALTY - TYPO_PENALTY * typos as f64
+                }
+                Some(typos) => partial - TYPO_PENALTY * typos as f64,
+                None => 0.0,
+            }
+        })
+        .fold(0.0, f64::max)
+}
+
+/// "number", "date", "id" (long digit runs such as an ЕДБ) or "text" when most samples agree.
+fn samples_kind(samples: &[String]) -> Option<&'static str> {
+    let values: Vec<&str> = samples.iter().map(|s| s.trim()).filter(|s| !s.is_empty()).collect();
+    if values.is_empty() {
+        return None;
+    }
+    let kind = |v: &str| {
+        let digits = v.chars().filter(|c| c.is_ascii_digit()).count();
+        if digits >= 7 && v.chars().all(|c| c.is_ascii_alphanumeric() || c == ' ' || c == '-') && !v.contains('.') {
+            "id"
+        } else if parse_invoice_date(v).is_some() {
+            "date"
+        } else if excel::normalize_amount_string(v).parse::<f64>().is_ok() {
+            "number"
+        } else {
+            "text"
+        }
+    };
+    let mut counts: HashMap<&'static str, usize> = HashMap::new();
+    for v in &values {
+        *counts.entry(kind(v)).or_default() += 1;
+    }
+    counts.into_iter().find(|(_, n)| *n * 5 >= values.len() * 4).map(|(k, _)| k)
+}
+
+fn expected_kind(field_key: &str) -> Option<&'static str> {
+    if AMOUNT_FIELDS.contains(&field_key) {
+        Some("number")
+    } else if DATE_FIELDS.contains(&field_key) {
+        Some("date")
+    } else if ID_FIELDS.contains(&field_key) {
+        Some("id")
+    } else if TEXT_FIELDS.contains(&field_key) {
+        Some("text")
+    } else {
+        None
+    }
+}
+
+/// Folded header text -> field key -> number of profiles mapping a column with that header to that field.
+fn profile_votes(db: &Db) -> Result<HashMap<String, HashMap<String, u32>>, String> {
+    let mut votes: HashMap<String, HashMap<String, u32>> = HashMap::new();
+    for (profile_id, _, _, _, column_mapping) in db.get_profiles()? {
+        let Ok(schema) = db.load_excel_schema(profile_id) else {
+            continue;
+        };
+        let mapping: HashMap<String, serde_json::Value> = serde_json::from_str(&column_mapping).unwrap_or_default();
+        for header in &schema.headers {
+            let Some(field_key) = mapping.get(&header.column_letter).and_then(|v| v.as_str()) else {
+                continue;
+            };
+            let text = header_match::fold(&header.text);
+            if !text.is_empty() {
+                *votes.entry(text).or_default().entry(field_key.to_string()).or_default() += 1;
+            }
+        }
+    }
+    Ok(votes)
+}
+
+/// Ranked field suggestions for each column of a header row. `headers` are the header cells from column A
+/// on, `column_samples` the sample values per column (same order; may be empty).
+pub fn rank(db: &Db, headers: &[String], column_samples: &[Vec<String>]) -> Result<Vec<ColumnSuggestions>, String> {
+    let mut learned: HashMap<String, Vec<(String, f64)>> = HashMap::new();
+    for (field_key, column_letter, confidence) in db.get_learned_mappings_for_schema(&excel::schema_hash(headers))? {
+        learned.entry(column_letter.to_uppercase()).or_default().push((field_key, confidence));
+    }
+    let votes = profile_votes(db)?;
+
+    let mut columns = Vec::new();
+    for (index, header) in headers.iter().enumerate() {
+        let column_letter = excel::col_index_to_letter(index as u32);
+        let text = normalize(header);
+        if text.is_empty() {
+            continue;
+        }
+        let kind = column_samples.get(index).and_then(|s| samples_kind(s));
+        let mut scores: HashMap<String, (f64, Vec<String>)> = HashMap::new();
+
+        for (field_key, keywords) in HEADER_KEYWORDS {
+            let score = keyword_score(&text, keywords);
+            if score > 0.0 {
+                scores.insert(field_key.to_string(), (score, vec!["keyword".to_string()]));
+            }
+        }
+        for (field_key, confidence) in learned.get(&column_letter).into_iter().flatten() {
+            let entry = scores.entry(field_key.clone()).or_insert((0.0, Vec::new()));
+            entry.0 = if entry.0 > 0.0 { entry.0.max(*confidence) + 0.05 } else { *confidence };
+            entry.1.push("learned".to_string());
+        }
+        if let Some(fields) = votes.get(&header_match::fold(&text)) {
+            let total: u32 = fields.values().sum();
+            for (field_key, count) in fields {
+                let confidence = PROFILE_MATCH * *count as f64 / total as f64;
+                let entry = scores.entry(field_key.clone()).or_insert((0.0, Vec::new()));
+                entry.0 = if entry.0 > 0.0 { entry.0.max(confidence) + 0.05 } else { confidence };
+                entry.1.push("profile".to_string());
+            }
+        }
+        if let Some(kind) = kind {
+            for (field_key, (score, sources)) in scores.iter_mut() {
+                match expected_kind(field_key) {
+                    Some(expected) if expected == kind => {
+                        *score += SAMPLES_MATCH;
+                        sources.push("samples".to_string());
+                    }
+                    // Amounts are sometimes typed with a currency suffix and read as text.
+                    Some("number") if kind == "text" => {}
+                    Some(_) => *score -= SAMPLES_CONFLICT,
+                    None => {}
+                }
+            }
+        }
+
+        let mut suggestions: Vec<FieldSuggestion> = scores
+            .into_iter()
+            .filter(|(_, (score, _))| *score > 0.0)
+            .map(|(field_key, (score, sources))| FieldSuggestion { field_key, score: score.min(1.0), sources })
+            .collect();
+        // Ties go to the field listed first (invoice_number before document_number).
+        let order = |field_key: &str| HEADER_KEYWORDS.iter().position(|(f, _)| *f == field_key).unwrap_or(usize::MAX);
+        suggestions.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then_with(|| order(&a.field_key).cmp(&order(&b.field_key)))
+                .then_with(|| a.field_key.cmp(&b.field_key))
+        });
+        suggestions.truncate(MAX_PER_COLUMN);
+        columns.push(ColumnSuggestions { column_letter, header: header.clone(), suggestions });
+    }
+    Ok(columns)
+}
+
+/// One field per column and one column per field from `ranked`, strongest first, above `MIN_SCORE`.
+pub fn best_mapping(ranked: &[ColumnSuggestions]) -> Vec<MappingSuggestion> {
+    let mut candidates: Vec<(&ColumnSuggestions, &FieldSuggestion)> =
+        ranked.iter().flat_map(|c| c.suggestions.iter().map(move |s| (c, s))).filter(|(_, s)| s.score >= MIN_SCORE).collect();
+    candidates.sort_by(|a, b| b.1.score.total_cmp(&a.1.score));
+    let mut chosen: Vec<MappingSuggestion> = Vec::new();
+    for (column, suggestion) in candidates {
+        if chosen.iter().any(|m| m.column_letter == column.column_letter || m.field_key == suggestion.field_key) {
+            continue;
+        }
+        let source = ["learned", "profile", "keyword"]
+            .into_iter()
+            .find(|s| suggestion.sources.iter().any(|x| x == s))
+            .unwrap_or("keyword");
+        chosen.push(MappingSuggestion {
+            column_letter: column.column_letter.clone(),
+            field_key: suggestion.field_key.clone(),
+            confidence: suggestion.score,
+            source: source.to_string(),
+        });
+    }
+    chosen.sort_by_key(|m| ranked.iter().position(|c| c.column_letter == m.column_letter));
+    chosen
+}
+
+
@@ -0,0 +1,149 @@
+//! Mock OCR provider for demos and frontend development: no network, no credentials. A sidecar next
+//! to the document (`<file>.ocr.json`, or `<file>.json`) is used when present — either a stored Azure
+//! poll response, parsed exactly like a real scan, or `{"fields": {"key": "value"}}`. Otherwise the
+//! fields are generated from a hash of the file name, so the same file always gives the same data.
+
+use crate::error::AppError;
+use crate::ocr;
+use crate::types::{InvoiceData, InvoiceFieldValue, OcrInvoiceResult};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+const VENDORS: &[(&str, &str)] = &[
+    ("Македонски Телеком АД Скопје", "4030997334450"),
+    ("ЕВН Македонија АД Скопје", "4030003475540"),
+    ("Тинекс-МТ ДООЕЛ Скопје", "4030993160730"),
+    ("Макпетрол АД Скопје", "4030987123456"),
+    ("А1 Македонија ДООЕЛ Скопје", "4057008502345"),
+];
+const BUYERS: &[&str] = &["Пример ДООЕЛ Битола", "Демо Трејд ДОО Скопје", "Тест Консалтинг ДООЕЛ Охрид"];
+const DESCRIPTIONS: &[&str] = &[
+    "Телекомуникациски услуги",
+    "Електрична енергија",
+    "Канцелариски материјал",
+    "Гориво",
+    "Сметководствени услуги",
+];
+
+/// Sidecar file of `path`, if one exists.
+fn sidecar(path: &Path) -> Option<PathBuf> {
+    let name = path.file_name()?.to_string_lossy().to_string();
+    [format!("{}.ocr.json", name), format!("{}.json", name)]
+        .into_iter()
+        .map(|n| path.with_file_name(n))
+        .find(|p| p.is_file() && p != path)
+}
+
+fn field(value: String) -> InvoiceFieldValue {
+    InvoiceFieldValue { value, confidence: Some(0.99), ..Default::default() }
+}
+
+fn result_with(fields: HashMap<String, InvoiceFieldValue>) -> OcrInvoiceResult {
+    OcrInvoiceResult {
+        invoice_data: InvoiceData {
+            fields,
+            source_file: None,
+            source_file_path: None,
+        },
+        raw_azure_fields: None,
+        document_count: None,
+        warnings: Vec::new(),
+        applied_corrections: Vec::new(),
+        raw_text: None,
+        extraction_path: Some("mock".to_string()),
+    }
+}
+
+/// "Scan" `file_path` without calling any service.
+pub fn analyze(file_path: &str, document_type: Option<&str>) -> Result<OcrInvoiceResult, AppError> {
+    let path = Path::new(file_path);
+    if !path.is_file() {
+        return Err(AppError::Ocr("File not found.".to_string()));
+    }
+    if let Some(sidecar) = sidecar(path) {
+        let text = std::fs::read_to_string(&sidecar).map_err(|e| AppError::Io(e.to_string()))?;
+        let json: Value = serde_json::from_str(&text)
+            .map_err(|e| AppError::Ocr(format!("Invalid mock data in {}: {}", sidecar.display(), e)))?;
+        if json.get("result").is_some() {
+            let mut result = ocr::replay_poll_json(&json, document_type)?;
+            result.extraction_path = Some("mock".to_string());
+            return Ok(result);
+        }
+        let fields = json
+            .get("fields")
+            .and_then(|f| f.as_object())
+            .ok_or_else(|| AppError::Ocr(format!("{} has no \"fields\" object.", sidecar.display())))?
+            .iter()
+            .filter_map(|(k, v)| {
+                let value = match v {
+                    Value::String(s) => s.clone(),
+                    Value::Number(n) => n.to_string(),
+                    _ => return None,
+                };
+                Some((k.clone(), field(value)))
+            })
+            .collect();
+        return Ok(result_with(fields));
+    }
+    let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    Ok(result_with(generated(&name, document_type)))
+}
+
+/// Fields derived from the file name's hash.
+pub(crate) fn generated(file_name: &str, document_type: Option<&str>) -> HashMap<String, InvoiceFieldValue> {
+    let hash = Sha256::digest(file_name.as_bytes());
+    let pick = |i: usize, n: usize| hash[i] as usize % n;
+    let amount = |i: usize| (u16::from_be_bytes([hash[i], hash[i + 1]]) as f64 % 50_000.0).max(500.0).round();
+    let month = 1 + pick(4, 12);
+    let day = 1 + pick(5, 28);
+    let year = 2024 + pick(6, 2);
+    let (vendor, vendor_tax_id) = VENDORS[pick(0, VENDORS.len())];
+
+    let mut fields: HashMap<String, InvoiceFieldValue> = HashMap::new();
+    let mut set = |key: &str, value: String| {
+        fields.insert(key.to_string(), field(value));
+    };
+    match document_type.unwrap_or("faktura") {
+        "plata" => {
+            let gross = amount(8) * 4.0;
+            let contributions = (gross * 0.28).round();
+            let tax = ((gross - contributions) * 0.10).round();
+            set("document_type", "Плата".to_string());
+            set("declarationPeriod", format!("{:02}/{}", month, year));
+            set("companyName", BUYERS[pick(1, BUYERS.len())].to_string());
+            set("totalGrossSalary", format!("{:.2}", gross));
+            set("totalNetSalary", format!("{:.2}", gross - contributions - tax));
+            set("totalPayrollCost", format!("{:.2}", gross));
+        }
+        "generic" => {
+            let base = amount(8) * 10.0;
+            let output = (base * 0.18).round();
+            let input = (output * 0.6).round();
+            set("document_type", "ДДВ".to_string());
+            set("companyName", BUYERS[pick(1, BUYERS.len())].to_string());
+            set("taxPeriod", format!("01.{:02}.{} - 28.{:02}.{}", month, year, month, year));
+            set("totalTaxBase", format!("{:.2}", base));
+            set("totalOutputVat", format!("{:.2}", output));
+            set("totalInputVat", format!("{:.2}", input));
+            set("vatPayableOrRefund", format!("{:.2}", output - input));
+        }
+        _ => {
+            let net = amount(8);
+            let tax = (net * 0.18 * 100.0).round() / 100.0;
+            set("document_type", "Фактура".to_string());
+            set("invoice_number", format!("{:04}/{}", u16::from_be_bytes([hash[10], hash[11]]) % 10_000, year));
+            set("date", format!("{:02}.{:02}.{}", day, month, year));
+            set("seller_name", vendor.to_string());
+            set("seller_tax_id", vendor_tax_id.to_string());
+            set("buyer_name", BUYERS[pick(1, BUYERS.len())].to_string());
+            set("description", DESCRIPTIONS[pick(2, DESCRIPTIONS.len())].to_string());
+            set("net_amount", format!("{:.2}", net));
+            set("tax_amount", format!("{:.2}", tax));
+            set("total_amount", format!("{:.2}", net + tax));
+            set("currency", "MKD".to_string());
+        }
+    }
+    fields
+}
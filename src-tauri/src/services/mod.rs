@@ -1 +1,63 @@
+pub mod app_state_archive;
+pub mod app_warnings;
+pub mod archive_store;
+pub mod bundle_keys;
+pub mod closing_checklist;
+pub mod document_format;
+pub mod document_sets;
+pub mod document_types;
+pub mod duplicate_keys;
+pub mod email_intake;
+pub mod excel_lock;
 pub mod excel_scanner;
+pub mod export_delivery;
+pub mod export_diff;
+pub mod export_locale;
+pub mod export_manifest;
+pub mod export_naming;
+pub mod export_order;
+pub mod export_targets;
+pub mod field_schemas;
+pub mod google_sheets;
+pub mod header_match;
+pub mod history_report;
+pub mod invoice_text;
+pub mod last_row;
+pub mod ledger_snapshots;
+pub mod llm_extraction;
+pub mod mapping_suggestions;
+pub mod mock_ocr;
+pub mod model_mappings;
+pub mod ocr_provider;
+pub mod onboarding;
+pub mod path_scope;
+pub mod pending_appends;
+pub mod payroll;
+pub mod perf_metrics;
+pub mod preset_bundles;
+pub mod preview;
+pub mod profile_audit;
+pub mod redaction;
+#[cfg(feature = "regression")]
+pub mod regression;
+pub mod scan_sessions;
+pub mod schema_drift;
+pub mod secrets;
+pub mod settings;
+pub mod sheet_routes;
+pub mod sftp;
+pub mod spending_report;
+pub mod storage;
+pub mod strict_export;
+pub mod temp_store;
+pub mod textract;
+pub mod validation;
+pub mod vat_report;
+pub mod vat_return;
+pub mod vendor_corrections;
+pub mod vendors;
+pub mod webhooks;
+pub mod work_pool;
+pub mod workbook_standby;
+pub mod workbook_compat;
+pub mod workbook_format;
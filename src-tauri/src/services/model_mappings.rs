@@ -0,0 +1,97 @@
+//! Field-name maps for custom Azure analyzers, stored in the database so a new model can be used without
+//! recompiling. A model's entries are applied before the built-in `AZURE_TO_FIELD` map in ocr.rs and
+//! replace it for the same Azure field; an empty field key drops that Azure field.
+
+use crate::db::Db;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{OnceLock, RwLock};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldMapping {
+    /// Field name in the analyzer's result (e.g. "BrojNaFaktura").
+    pub azure_key: String,
+    /// App field key (e.g. "invoice_number"); empty to ignore the Azure field.
+    pub field_key: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelFieldMappings {
+    pub model_id: String,
+    pub mappings: Vec<FieldMapping>,
+}
+
+/// model id -> (azure_key, field_key) pairs.
+type MappingsByModel = HashMap<String, Vec<(String, String)>>;
+
+/// Mappings per model id, read by OCR parsing without a database handle.
+static RUNTIME: OnceLock<RwLock<MappingsByModel>> = OnceLock::new();
+
+fn runtime() -> &'static RwLock<MappingsByModel> {
+    RUNTIME.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+/// Reload the in-memory copy from the database.
+pub fn load(db: &Db) -> Result<(), String> {
+    let mut next = MappingsByModel::new();
+    for (model_id, azure_key, field_key) in db.get_model_field_mappings(None)? {
+        next.entry(model_id).or_default().push((azure_key, field_key));
+    }
+    *runtime().write().map_err(|e| e.to_string())? = next;
+    Ok(())
+}
+
+/// Custom (azure_key, field_key) pairs of a model; empty when it has none.
+pub fn for_model(model_id: &str) -> Vec<(String, String)> {
+    runtime()
+        .read()
+        .ok()
+        .and_then(|m| m.get(model_id.trim()).cloned())
+        .unwrap_or_default()
+}
+
+/// Stored mappings, grouped by model; one model when `model_id` is given.
+pub fn get(db: &Db, model_id: Option<&str>) -> Result<Vec<ModelFieldMappings>, String> {
+    let mut out: Vec<ModelFieldMappings> = Vec::new();
+    for (model, azure_key, field_key) in db.get_model_field_mappings(model_id.map(str::trim))? {
+        let mapping = FieldMapping { azure_key, field_key };
+        match out.last_mut() {
+            Some(last) if last.model_id == model => last.mappings.push(mapping),
+            _ => out.push(ModelFieldMappings {
+                model_id: model,
+                mappings: vec![mapping],
+            }),
+        }
+    }
+    Ok(out)
+}
+
+/// Replace a model's mappings (an empty list removes them) and make them effective for the next scan.
+pub fn save(db: &Db, model_id: &str, mappings: Vec<FieldMapping>) -> Result<ModelFieldMappings, String> {
+    let model_id = model_id.trim();
+    if model_id.is_empty() {
+        return Err("Model ID is required.".to_string());
+    }
+    let mut pairs: Vec<(String, String)> = Vec::new();
+    for m in mappings {
+        let azure_key = m.azure_key.trim().to_string();
+        if azure_key.is_empty() {
+            continue;
+        }
+        if pairs.iter().any(|(k, _)| *k == azure_key) {
+            return Err(format!("Azure field '{}' is mapped more than once.", azure_key));
+        }
+        pairs.push((azure_key, m.field_key.trim().to_string()));
+    }
+    db.replace_model_field_mappings(model_id, &pairs)?;
+    load(db)?;
+    Ok(ModelFieldMappings {
+        model_id: model_id.to_string(),
+        mappings: pairs
+            .into_iter()
+            .map(|(azure_key, field_key)| FieldMapping { azure_key, field_key })
+            .collect(),
+    })
+}
@@ -0,0 +1,142 @@
+//! Which OCR service scans documents: Azure Content Understanding (default), AWS Textract or the offline
+//! mock (`services::mock_ocr`, for demos and frontend work), chosen in settings. OCR code reads the selection from an in-memory copy refreshed on startup and after every
+//! change; the Textract secret key lives in the OS credential store.
+
+use crate::db::Db;
+use crate::services::secrets;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{OnceLock, RwLock};
+
+const PROVIDER: &str = "ocr_provider";
+const TEXTRACT_REGION: &str = "textract_region";
+const TEXTRACT_ACCESS_KEY_ID: &str = "textract_access_key_id";
+/// Credential-store name of the Textract secret access key.
+pub(crate) const TEXTRACT_SECRET_NAME: &str = "textract_secret_access_key";
+
+const PROVIDERS: &[&str] = &["azure", "textract", "mock"];
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OcrProviderSettings {
+    /// "azure", "textract" or "mock"
+    pub provider: String,
+    pub textract_region: Option<String>,
+    pub textract_access_key_id: Option<String>,
+    pub textract_secret_configured: bool,
+}
+
+/// None leaves a value unchanged, an empty string clears it.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OcrProviderSettingsUpdate {
+    pub provider: Option<String>,
+    pub textract_region: Option<String>,
+    pub textract_access_key_id: Option<String>,
+    pub textract_secret_access_key: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TextractConfig {
+    /// e.g. "eu-central-1"
+    pub region: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub enum OcrProvider {
+    #[default]
+    Azure,
+    /// Textract selected; Err when its credentials are incomplete (reported when a scan starts).
+    Textract(Result<TextractConfig, String>),
+    /// Deterministic local data; no network or credentials.
+    Mock,
+}
+
+static RUNTIME: OnceLock<RwLock<OcrProvider>> = OnceLock::new();
+
+fn runtime() -> &'static RwLock<OcrProvider> {
+    RUNTIME.get_or_init(|| RwLock::new(OcrProvider::default()))
+}
+
+fn non_empty(value: Option<&String>) -> Option<String> {
+    value.map(|v| v.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+/// Textract credentials from settings, else the standard AWS_* environment variables.
+fn textract_config(stored: &HashMap<String, String>) -> Result<TextractConfig, String> {
+    let env = |name: &str| std::env::var(name).ok().filter(|v| !v.trim().is_empty());
+    let region = non_empty(stored.get(TEXTRACT_REGION))
+        .or_else(|| env("AWS_REGION"))
+        .ok_or("Textract region is not configured.")?;
+    let access_key_id = non_empty(stored.get(TEXTRACT_ACCESS_KEY_ID))
+        .or_else(|| env("AWS_ACCESS_KEY_ID"))
+        .ok_or("Textract access key is not configured.")?;
+    let secret_access_key = secrets::get_secret(TEXTRACT_SECRET_NAME)?
+        .or_else(|| env("AWS_SECRET_ACCESS_KEY"))
+        .ok_or("Textract secret key is not configured.")?;
+    Ok(TextractConfig {
+        region: region.trim().to_string(),
+        access_key_id: access_key_id.trim().to_string(),
+        secret_access_key: secret_access_key.trim().to_string(),
+    })
+}
+
+/// Reload the in-memory selection from the database and the credential store.
+pub fn load(db: &Db) -> Result<(), String> {
+    let stored = db.get_settings()?;
+    let next = match non_empty(stored.get(PROVIDER)).as_deref() {
+        Some("textract") => OcrProvider::Textract(textract_config(&stored)),
+        Some("mock") => OcrProvider::Mock,
+        _ => OcrProvider::Azure,
+    };
+    *runtime().write().map_err(|e| e.to_string())? = next;
+    Ok(())
+}
+
+/// Provider for the next scan.
+pub fn current() -> OcrProvider {
+    runtime().read().map(|p| p.clone()).unwrap_or_default()
+}
+
+pub fn get_settings(db: &Db) -> Result<OcrProviderSettings, String> {
+    let stored = db.get_settings()?;
+    Ok(OcrProviderSettings {
+        provider: non_empty(stored.get(PROVIDER)).unwrap_or_else(|| "azure".to_string()),
+        textract_region: non_empty(stored.get(TEXTRACT_REGION)),
+        textract_access_key_id: non_empty(stored.get(TEXTRACT_ACCESS_KEY_ID)),
+        textract_secret_configured: secrets::get_secret(TEXTRACT_SECRET_NAME).ok().flatten().is_some(),
+    })
+}
+
+/// Store the provider selection; the Textract secret goes to the OS credential store.
+pub fn save_settings(db: &Db, update: OcrProviderSettingsUpdate) -> Result<OcrProviderSettings, String> {
+    let cleared = |v: Option<String>| v.map(|v| Some(v.trim().to_string()).filter(|v| !v.is_empty()));
+    let mut entries: Vec<(&str, Option<String>)> = Vec::new();
+    if let Some(provider) = cleared(update.provider) {
+        if let Some(p) = &provider {
+            if !PROVIDERS.contains(&p.as_str()) {
+                return Err(format!("Unknown OCR provider: {}", p));
+            }
+        }
+        entries.push((PROVIDER, provider));
+    }
+    for (key, value) in [
+        (TEXTRACT_REGION, update.textract_region),
+        (TEXTRACT_ACCESS_KEY_ID, update.textract_access_key_id),
+    ] {
+        if let Some(value) = cleared(value) {
+            entries.push((key, value));
+        }
+    }
+    if let Some(secret) = cleared(update.textract_secret_access_key) {
+        match secret {
+            Some(secret) => secrets::set_secret(TEXTRACT_SECRET_NAME, &secret)?,
+            None => secrets::delete_secret(TEXTRACT_SECRET_NAME)?,
+        }
+    }
+    db.set_settings(&entries)?;
+    load(db)?;
+    get_settings(db)
+}
@@ -0,0 +1,155 @@
+//! First-run demo workspace: a sample ledger, a few demo invoices and a profile for that ledger, all in
+//! `<app data>/demo`, so a new user can go through scan → map → append before connecting an Azure key and
+//! their own ledgers. The invoices are small PDFs whose data comes from the mock provider (`mock_ocr`), so
+//! scanning them with the mock provider gives the same fields that are returned here. The demo profile
+//! id is kept in settings (`onboarding.demo_profile_id`) so the workspace can be rebuilt or removed.
+
+use crate::db::Db;
+use crate::error::AppError;
+use crate::excel;
+use crate::services::mock_ocr;
+use crate::types::InvoiceData;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+const DEMO_PROFILE_KEY: &str = "onboarding.demo_profile_id";
+pub const DEMO_PROFILE_NAME: &str = "Демо — фактури";
+pub const LEDGER_SHEET: &str = "Фактури";
+const LEDGER_FILE: &str = "Demo-Ledger.xlsx";
+/// Rows already in the sample ledger, so appends land below existing data.
+const LEDGER_ROWS: usize = 3;
+pub const DEFAULT_DOCUMENTS: u32 = 5;
+pub const MAX_DOCUMENTS: u32 = 20;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DemoWorkspace {
+    pub folder: String,
+    pub ledger_path: String,
+    pub sheet_name: String,
+    pub profile_id: i64,
+    /// The demo invoices as the mock provider scans them (source file set).
+    pub documents: Vec<InvoiceData>,
+}
+
+pub fn folder(app_data_dir: &Path) -> PathBuf {
+    app_data_dir.join("demo")
+}
+
+/// Ledger values of a mock invoice in `EXPORT_FIELDS` order.
+fn ledger_row(file_name: &str) -> Vec<String> {
+    let fields = mock_ocr::generated(file_name, Some("faktura"));
+    excel::EXPORT_FIELDS
+        .iter()
+        .map(|key| fields.get(*key).map(|v| v.value.clone()).unwrap_or_default())
+        .collect()
+}
+
+/// A one-page PDF showing `lines` (ASCII; other characters become '?').
+fn demo_pdf(lines: &[String]) -> Vec<u8> {
+    let escape = |line: &str| {
+        line.chars()
+            .map(|c| match c {
+                '(' | ')' | '\\' => format!("\\{}", c),
+                c if c.is_ascii() && !c.is_ascii_control() => c.to_string(),
+                _ => "?".to_string(),
+            })
+            .collect::<String>()
+    };
+    let mut content = String::from("BT /F1 13 Tf 72 770 Td 18 TL");
+    for line in lines {
+        content.push_str(&format!(" ({}) Tj T*", escape(line)));
+    }
+    content.push_str(" ET");
+    let objects = [
+        "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+        "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+        "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 595 842] /Resources << /Font << /F1 5 0 R >> >> /Contents 4 0 R >>"
+            .to_string(),
+        format!("<< /Length {} >>\nstream\n{}\nendstream", content.len(), content),
+        "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica >>".to_string(),
+    ];
+    let mut pdf = String::from("%PDF-1.4\n");
+    let mut offsets = Vec::new();
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf.push_str(&format!("{} 0 obj\n{}\nendobj\n", i + 1, object));
+    }
+    let xref = pdf.len();
+    pdf.push_str(&format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1));
+    for offset in offsets {
+        pdf.push_str(&format!("{:010} 00000 n \n", offset));
+    }
+    pdf.push_str(&format!(
+        "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+        objects.len() + 1,
+        xref
+    ));
+    pdf.into_bytes()
+}
+
+/// Write the sample ledger and `document_count` demo invoices into `folder` (replacing earlier ones) and
+/// scan the invoices with the mock provider. Returns the ledger path and the scanned invoices.
+pub fn write_files(folder: &Path, document_count: u32) -> Result<(PathBuf, Vec<InvoiceData>), AppError> {
+    if folder.exists() {
+        std::fs::remove_dir_all(folder).map_err(AppError::io)?;
+    }
+    std::fs::create_dir_all(folder).map_err(AppError::io)?;
+    let ledger_path = folder.join(LEDGER_FILE);
+    let rows: Vec<Vec<String>> = (1..=LEDGER_ROWS).map(|i| ledger_row(&format!("sample-ledger-{}.pdf", i))).collect();
+    excel::create_sample_ledger_xlsx(&ledger_path, LEDGER_SHEET, &rows)?;
+
+    let mut documents = Vec::new();
+    for i in 1..=document_count.clamp(1, MAX_DOCUMENTS) {
+        let file_name = format!("demo-invoice-{:02}.pdf", i);
+        let values = ledger_row(&file_name);
+        let lines: Vec<String> = std::iter::once("DEMO INVOICE - sample data for trying out the app".to_string())
+            .chain(
+                ["Number", "Date", "Net", "VAT", "Total"]
+                    .iter()
+                    .zip([1, 2, 6, 7, 8])
+                    .map(|(label, i)| format!("{}: {}", label, values[i])),
+            )
+            .collect();
+        let path = folder.join(&file_name);
+        std::fs::write(&path, demo_pdf(&lines)).map_err(AppError::io)?;
+        let mut invoice = mock_ocr::analyze(&path.to_string_lossy(), Some("faktura"))?.invoice_data;
+        invoice.source_file = Some(file_name);
+        invoice.source_file_path = Some(path.to_string_lossy().to_string());
+        documents.push(invoice);
+    }
+    Ok((ledger_path, documents))
+}
+
+/// The demo profile, while it still exists.
+pub fn profile_id(db: &Db) -> Result<Option<i64>, String> {
+    let Some(id) = db.get_settings()?.get(DEMO_PROFILE_KEY).and_then(|v| v.parse::<i64>().ok()) else {
+        return Ok(None);
+    };
+    Ok(db.get_profile_by_id(id).is_ok().then_some(id))
+}
+
+/// Create or update the demo profile for `ledger_path`, mapping the sample ledger's columns.
+pub fn save_profile(db: &Db, ledger_path: &Path) -> Result<i64, String> {
+    let mut mapping = serde_json::Map::new();
+    for (i, key) in excel::EXPORT_FIELDS.iter().enumerate() {
+        mapping.insert(excel::col_index_to_letter(i as u32), serde_json::Value::String(key.to_string()));
+    }
+    mapping.insert("_headerRow".to_string(), serde_json::Value::from(1));
+    let id = db.save_profile(
+        profile_id(db)?,
+        DEMO_PROFILE_NAME,
+        &ledger_path.to_string_lossy(),
+        LEDGER_SHEET,
+        &serde_json::Value::Object(mapping),
+    )?;
+    db.set_settings(&[(DEMO_PROFILE_KEY, Some(id.to_string()))])?;
+    Ok(id)
+}
+
+/// Forget the demo profile id (after the profile was deleted).
+pub fn forget(db: &Db) -> Result<(), String> {
+    db.set_settings(&[(DEMO_PROFILE_KEY, None)])?;
+    Ok(())
+}
+
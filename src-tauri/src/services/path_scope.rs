@@ -0,0 +1,163 @@
+//! Which paths the webview may hand to a command. Every command that reads, writes or stores a path it
+//! got from the webview runs it through `check`. Allowed are app data, the document roots, the export
+//! folders (default and per document type), profile workbooks with their folders, and files and folders
+//! the user chose in a native dialog (or dropped on the window) this session. Document roots are only
+//! added through a native folder picker. Everything else is refused with `PATH_NOT_ALLOWED`, so a
+//! compromised page cannot touch arbitrary files or widen the scope itself.
+
+use crate::db::Db;
+use crate::error::AppError;
+use crate::services::{document_types, settings};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::{OnceLock, RwLock};
+
+/// Settings key: JSON array of folders whose whole tree is allowed.
+const DOCUMENT_ROOTS: &str = "document_roots";
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PathScope {
+    pub app_data_dir: String,
+    pub document_roots: Vec<String>,
+    pub export_folders: Vec<String>,
+    pub profile_workbooks: Vec<String>,
+    /// Files picked in a native dialog or dropped on the window since the app started.
+    pub granted_files: Vec<String>,
+    /// Folders picked in a native dialog since the app started, with everything below them.
+    pub granted_folders: Vec<String>,
+}
+
+static GRANTED: OnceLock<RwLock<HashSet<PathBuf>>> = OnceLock::new();
+static GRANTED_FOLDERS: OnceLock<RwLock<HashSet<PathBuf>>> = OnceLock::new();
+
+fn granted() -> &'static RwLock<HashSet<PathBuf>> {
+    GRANTED.get_or_init(|| RwLock::new(HashSet::new()))
+}
+
+fn granted_folders() -> &'static RwLock<HashSet<PathBuf>> {
+    GRANTED_FOLDERS.get_or_init(|| RwLock::new(HashSet::new()))
+}
+
+fn sorted_strings(set: &RwLock<HashSet<PathBuf>>) -> Vec<String> {
+    let mut out: Vec<String> = set
+        .read()
+        .map(|set| set.iter().map(|p| p.to_string_lossy().to_string()).collect())
+        .unwrap_or_default();
+    out.sort();
+    out
+}
+
+/// Absolute path with `..` and links resolved; for a file that does not exist yet, its resolved folder
+/// plus the file name. None for relative paths and missing folders.
+fn resolve(path: &Path) -> Option<PathBuf> {
+    if !path.is_absolute() {
+        return None;
+    }
+    if let Ok(p) = path.canonicalize() {
+        return Some(p);
+    }
+    let name = path.file_name()?;
+    Some(path.parent()?.canonicalize().ok()?.join(name))
+}
+
+/// Allow `path` for the rest of the session (a file the user chose in a native dialog).
+pub fn grant(path: &Path) {
+    if let (Some(p), Ok(mut set)) = (resolve(path), granted().write()) {
+        set.insert(p);
+    }
+}
+
+/// Allow `folder` and everything below it for the rest of the session (chosen in a native folder picker).
+pub fn grant_folder(folder: &Path) {
+    if let (Some(p), Ok(mut set)) = (resolve(folder), granted_folders().write()) {
+        set.insert(p);
+    }
+}
+
+pub fn document_roots(db: &Db) -> Result<Vec<String>, String> {
+    Ok(db
+        .get_settings()?
+        .get(DOCUMENT_ROOTS)
+        .and_then(|v| serde_json::from_str(v).ok())
+        .unwrap_or_default())
+}
+
+fn save_document_roots(db: &Db, roots: &[String]) -> Result<(), String> {
+    let value = (!roots.is_empty()).then(|| serde_json::to_string(roots)).transpose().map_err(|e| e.to_string())?;
+    db.set_settings(&[(DOCUMENT_ROOTS, value)])?;
+    Ok(())
+}
+
+/// Add a folder the user chose in a native folder picker; never call it with a path from the webview.
+pub fn add_document_root(db: &Db, folder: &Path) -> Result<Vec<String>, String> {
+    if !folder.is_absolute() || !folder.is_dir() {
+        return Err(format!("Folder not found: {}", folder.display()));
+    }
+    let folder = folder.to_string_lossy().to_string();
+    let mut roots = document_roots(db)?;
+    if !roots.contains(&folder) {
+        roots.push(folder);
+        save_document_roots(db, &roots)?;
+    }
+    Ok(roots)
+}
+
+/// Removing a root only narrows the scope, so it may come from the webview.
+pub fn remove_document_root(db: &Db, root: &str) -> Result<Vec<String>, String> {
+    let mut roots = document_roots(db)?;
+    roots.retain(|r| r != root.trim());
+    save_document_roots(db, &roots)?;
+    Ok(roots)
+}
+
+fn export_folders(db: &Db) -> Result<Vec<String>, String> {
+    let mut folders: Vec<String> = settings::default_export_folder()
+        .map(|p| p.to_string_lossy().to_string())
+        .into_iter()
+        .collect();
+    folders.extend(document_types::list(db)?.into_iter().filter_map(|t| t.default_folder));
+    Ok(folders)
+}
+
+pub fn get_scope(db: &Db, app_data_dir: &Path) -> Result<PathScope, String> {
+    Ok(PathScope {
+        app_data_dir: app_data_dir.to_string_lossy().to_string(),
+        document_roots: document_roots(db)?,
+        export_folders: export_folders(db)?,
+        profile_workbooks: db.get_profiles()?.into_iter().map(|(_, _, excel_path, ..)| excel_path).collect(),
+        granted_files: sorted_strings(granted()),
+        granted_folders: sorted_strings(granted_folders()),
+    })
+}
+
+/// Resolved `path` when it is inside the allowed scope, else `PATH_NOT_ALLOWED`.
+pub fn check(db: &Db, app_data_dir: &Path, path: &str) -> Result<PathBuf, AppError> {
+    let not_allowed = || AppError::PathNotAllowed(format!("Access to {} is not allowed.", path));
+    let resolved = resolve(Path::new(path.trim())).ok_or_else(not_allowed)?;
+    if granted().read().map(|set| set.contains(&resolved)).unwrap_or(false) {
+        return Ok(resolved);
+    }
+    let scope = get_scope(db, app_data_dir)?;
+    // Whole trees.
+    let trees = std::iter::once(scope.app_data_dir)
+        .chain(scope.document_roots)
+        .chain(scope.export_folders)
+        .chain(scope.granted_folders);
+    for root in trees {
+        if resolve(Path::new(&root)).is_some_and(|r| resolved.starts_with(r)) {
+            return Ok(resolved);
+        }
+    }
+    // A profile's workbook and the files next to it (backups, lock files), not its subfolders.
+    for workbook in scope.profile_workbooks {
+        let Some(folder) = resolve(Path::new(&workbook)).and_then(|w| w.parent().map(Path::to_path_buf)) else {
+            continue;
+        };
+        if resolved.parent() == Some(folder.as_path()) {
+            return Ok(resolved);
+        }
+    }
+    Err(not_allowed())
+}
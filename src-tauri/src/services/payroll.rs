@@ -0,0 +1,175 @@
+//! Payroll (плата) fields from documents read without a payroll analyzer (prebuilt-read / layout): the
+//! layout's key-value pairs and the text's "label: value" lines and table rows are matched against
+//! label keywords. Output uses the same keys as the PayRoll analyzer path in ocr.rs, so payroll
+//! templates and ledger profiles map them to columns the same way.
+
+use crate::services::validation::parse_amount;
+use crate::types::InvoiceFieldValue;
+use regex::Regex;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Amount,
+    Count,
+    Period,
+    Text,
+}
+
+/// (field key, value kind, lowercase label keywords). Order matters: a label is used by the first rule
+/// that matches it, so narrower rules (professional illness) come before broader ones (health).
+const RULES: &[(&str, Kind, &[&str])] = &[
+    ("pridonesProfesionalnoZaboluvanje", Kind::Amount, &["професионал", "повреда"]),
+    ("pridonesPIO", Kind::Amount, &["пензиск", "пио"]),
+    ("pridonesZdravstvo", Kind::Amount, &["здравств"]),
+    ("pridonesVrabotuvanje", Kind::Amount, &["вработување", "невработеност"]),
+    ("personalenDanok", Kind::Amount, &["персонален данок", "данок на личен доход", "пдд"]),
+    ("vkupnaNetoPlata", Kind::Amount, &["нето плата", "нето ефективна", "нето износ", "net salary"]),
+    ("brutoPlata", Kind::Amount, &["бруто плата", "бруто износ", "вкупна бруто", "gross salary"]),
+    ("brojVraboteni", Kind::Count, &["број на вработени", "број на лица"]),
+    ("declarationPeriod", Kind::Period, &["период", "месец"]),
+    ("employeeName", Kind::Text, &["име и презиме", "презиме и име", "име на вработен", "employee name"]),
+    ("companyName", Kind::Text, &["назив на обврзник", "назив на работодавач", "работодавач"]),
+    ("companyTaxId", Kind::Text, &["едб", "даночен број"]),
+];
+
+/// Contributions added to the gross salary for the total payroll cost, as in the analyzer path.
+const COST_KEYS: &[&str] = &[
+    "pridonesPIO",
+    "pridonesZdravstvo",
+    "pridonesProfesionalnoZaboluvanje",
+    "pridonesVrabotuvanje",
+    "personalenDanok",
+];
+
+fn period_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"\b(0?[1-9]|1[0-2])\s*[./-]\s*((?:19|20)\d{2})\b").expect("valid regex"))
+}
+
+fn trailing_amount_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"^(.*\p{L}.*?)\s+(-?\d[\d.,\s]*\d)\s*(?:ден\.?|мкд|mkd)?$").expect("valid regex"))
+}
+
+/// (label, value) candidates from the text: table rows, "label: value" lines, "label 1.234,00" lines.
+fn line_pairs(text: &str) -> Vec<(String, String)> {
+    let mut out = Vec::new();
+    for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        if line.contains('|') {
+            let cells: Vec<&str> = line.split('|').map(str::trim).filter(|c| !c.is_empty()).collect();
+            let label = cells.iter().position(|c| c.chars().any(char::is_alphabetic));
+            if let Some(i) = label {
+                if let Some(value) = cells[i + 1..].iter().rev().find(|c| !c.chars().all(|ch| ch == '-' || ch == ':')) {
+                    out.push((cells[i].to_string(), value.to_string()));
+                }
+            }
+        } else if let Some((label, value)) = line.split_once(':').filter(|(_, v)| !v.trim().is_empty()) {
+            out.push((label.trim().to_string(), value.trim().to_string()));
+        } else if let Some(c) = trailing_amount_re().captures(line) {
+            out.push((c[1].trim().to_string(), c[2].trim().to_string()));
+        }
+    }
+    out
+}
+
+fn value_of(kind: Kind, raw: &str) -> Option<String> {
+    let raw = raw.trim().trim_matches('*').trim();
+    match kind {
+        Kind::Amount => parse_amount(raw).map(|n| format!("{:.2}", n)),
+        Kind::Count => {
+            let digits: String = raw.chars().take_while(|c| c.is_ascii_digit() || c.is_whitespace()).filter(char::is_ascii_digit).collect();
+            (!digits.is_empty()).then_some(digits)
+        }
+        Kind::Period => period_re()
+            .captures(raw)
+            .map(|c| format!("{:02}/{}", c[1].parse::<u32>().unwrap_or(0), &c[2])),
+        Kind::Text => (!raw.is_empty() && raw.chars().any(char::is_alphanumeric)).then(|| raw.to_string()),
+    }
+}
+
+/// Payroll fields from key-value pairs (label, value, confidence) and the document text. Key-value
+/// pairs are tried first; a label is used for one field only.
+pub fn extract(key_values: &[(String, String, Option<f64>)], text: &str) -> HashMap<String, InvoiceFieldValue> {
+    let mut candidates: Vec<(String, String, Option<f64>)> = key_values.to_vec();
+    candidates.extend(line_pairs(text).into_iter().map(|(l, v)| (l, v, None)));
+    let mut used = vec![false; candidates.len()];
+    let mut fields: HashMap<String, InvoiceFieldValue> = HashMap::new();
+
+    for (key, kind, keywords) in RULES {
+        for (i, (label, value, confidence)) in candidates.iter().enumerate() {
+            if used[i] {
+                continue;
+            }
+            let label = label.to_lowercase();
+            if !keywords.iter().any(|k| label.contains(k)) {
+                continue;
+            }
+            if let Some(value) = value_of(*kind, value) {
+                used[i] = true;
+                fields.insert(key.to_string(), InvoiceFieldValue { value, confidence: *confidence, ..Default::default() });
+                break;
+            }
+        }
+    }
+    // A period written anywhere (e.g. in the title "МПИН за 03/2025") when no labelled one was found.
+    if !fields.contains_key("declarationPeriod") {
+        if let Some(period) = value_of(Kind::Period, text.lines().take(15).collect::<Vec<_>>().join(" ").as_str()) {
+            fields.insert("declarationPeriod".to_string(), InvoiceFieldValue { value: period, confidence: None, ..Default::default() });
+        }
+    }
+    add_summary_keys(&mut fields);
+    fields
+}
+
+/// Summary and generic keys the PayRoll analyzer path also sets (totals, year/date, seller, amounts).
+fn add_summary_keys(fields: &mut HashMap<String, InvoiceFieldValue>) {
+    let copy = |fields: &mut HashMap<String, InvoiceFieldValue>, from: &str, to: &str| {
+        if let Some(v) = fields.get(from).cloned() {
+            fields.entry(to.to_string()).or_insert(v);
+        }
+    };
+    copy(fields, "brutoPlata", "totalGrossSalary");
+    copy(fields, "vkupnaNetoPlata", "totalNetSalary");
+    if let Some(bruto) = fields.get("brutoPlata").and_then(|f| f.value.parse::<f64>().ok()) {
+        let cost = bruto
+            + COST_KEYS
+                .iter()
+                .filter_map(|k| fields.get(*k).and_then(|f| f.value.parse::<f64>().ok()))
+                .sum::<f64>();
+        fields.insert(
+            "totalPayrollCost".to_string(),
+            InvoiceFieldValue { value: format!("{:.2}", cost), confidence: None, ..Default::default() },
+        );
+    }
+    copy(fields, "declarationPeriod", "year");
+    copy(fields, "declarationPeriod", "date");
+    copy(fields, "companyName", "seller_name");
+    copy(fields, "totalGrossSalary", "total_amount");
+    copy(fields, "totalNetSalary", "net_amount");
+    copy(fields, "totalPayrollCost", "tax_amount");
+}
+
+/// Layout key-value pairs (`keyValuePairs` of a document analysis result) as (label, value, confidence).
+pub fn layout_key_values(doc: &serde_json::Value) -> Vec<(String, String, Option<f64>)> {
+    let content = |v: Option<&serde_json::Value>| {
+        v.and_then(|v| v.get("content"))
+            .and_then(|c| c.as_str())
+            .map(|s| s.trim().to_string())
+            .unwrap_or_default()
+    };
+    doc.get("keyValuePairs")
+        .and_then(|k| k.as_array())
+        .into_iter()
+        .flatten()
+        .map(|kv| {
+            (
+                content(kv.get("key")),
+                content(kv.get("value")),
+                kv.get("confidence").and_then(|c| c.as_f64()),
+            )
+        })
+        .filter(|(k, v, _)| !k.is_empty() && !v.is_empty())
+        .collect()
+}
@@ -0,0 +1,102 @@
+//! Durable queue of ledger rows that could not be written: the workbook stayed locked in Excel, the file
+//! was moved or deleted, or the network share it lives on was offline. Queued rows are retried with
+//! `flush_pending_appends` and on every startup, oldest first per profile, so a scanned document is
+//! never silently lost.
+
+use crate::db::Db;
+use crate::error::AppError;
+use crate::services::excel_lock;
+use crate::types::InvoiceData;
+use serde::Serialize;
+use std::path::Path;
+
+/// The workbook stayed open in Excel.
+pub const REASON_LOCKED: &str = "locked";
+/// The workbook is gone but its folder is reachable.
+pub const REASON_MISSING: &str = "missing";
+/// The workbook's folder (e.g. a network share) cannot be reached.
+pub const REASON_OFFLINE: &str = "offline";
+
+/// Queued row as shown in the UI; also the payload of the `excel-append-queued` event.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingAppend {
+    pub id: i64,
+    pub profile_id: i64,
+    pub invoice_data: InvoiceData,
+    pub history_id: Option<i64>,
+    pub reason: String,
+    pub last_error: Option<String>,
+    pub attempts: u32,
+    pub created_at: String,
+    pub last_attempt_at: Option<String>,
+}
+
+pub fn queue(
+    db: &Db,
+    profile_id: i64,
+    invoice_data: &InvoiceData,
+    history_id: Option<i64>,
+    reason: &str,
+    error: &str,
+) -> Result<PendingAppend, String> {
+    let json = serde_json::to_string(invoice_data).map_err(|e| e.to_string())?;
+    let id = db.add_pending_append(profile_id, &json, history_id, reason, Some(error))?;
+    list(db, Some(profile_id))?
+        .into_iter()
+        .find(|p| p.id == id)
+        .ok_or_else(|| "Queued row not found.".to_string())
+}
+
+/// Why an append to `path` that failed with `error` can be retried later; None for failures that
+/// will not go away by waiting (duplicates, paths outside the allowed folders, broken templates).
+pub fn unavailable_reason(path: &str, error: &AppError) -> Option<&'static str> {
+    match error {
+        AppError::Validation(_)
+        | AppError::PathNotAllowed(_)
+        | AppError::SchemaChanged(..)
+        | AppError::ConversionRequired(_) => return None,
+        AppError::Excel(msg) if msg.starts_with(excel_lock::FILE_LOCKED) => return Some(REASON_LOCKED),
+        _ => {}
+    }
+    let path = Path::new(path);
+    if path.exists() {
+        return None;
+    }
+    match path.parent() {
+        Some(dir) if dir.as_os_str().is_empty() || dir.exists() => Some(REASON_MISSING),
+        _ => Some(REASON_OFFLINE),
+    }
+}
+
+/// Queued rows, oldest first. Rows whose document can no longer be read are skipped.
+pub fn list(db: &Db, profile_id: Option<i64>) -> Result<Vec<PendingAppend>, String> {
+    Ok(db
+        .list_pending_appends(profile_id)?
+        .into_iter()
+        .filter_map(
+            |(id, profile_id, invoice_data, history_id, reason, last_error, attempts, created_at, last_attempt_at)| {
+                Some(PendingAppend {
+                    id,
+                    profile_id,
+                    invoice_data: serde_json::from_str(&invoice_data).ok()?,
+                    history_id,
+                    reason,
+                    last_error,
+                    attempts,
+                    created_at,
+                    last_attempt_at,
+                })
+            },
+        )
+        .collect())
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FlushSummary {
+    /// (pending id, ledger row) of the rows written; the row is 0 for Plata.
+    pub written: Vec<(i64, i64)>,
+    /// Rows still queued, with the error of this attempt.
+    pub remaining: Vec<PendingAppend>,
+}
@@ -0,0 +1,131 @@
+//! In-process timing of slow paths (OCR calls, ledger appends, schema scans, database work of async
+//! commands) and queue depths, for diagnosing slow installations. Each metric keeps its last `WINDOW`
+//! samples in memory; nothing is persisted, so the numbers describe the current run only.
+
+use crate::db::Db;
+use crate::services::work_pool::{self, ClassDepth};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// Samples kept per metric for the rolling figures.
+const WINDOW: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    Ocr,
+    Append,
+    SchemaScan,
+    DbQuery,
+}
+
+impl Metric {
+    const ALL: [Metric; 4] = [Metric::Ocr, Metric::Append, Metric::SchemaScan, Metric::DbQuery];
+
+    fn name(self) -> &'static str {
+        match self {
+            Metric::Ocr => "ocr",
+            Metric::Append => "append",
+            Metric::SchemaScan => "schemaScan",
+            Metric::DbQuery => "dbQuery",
+        }
+    }
+}
+
+#[derive(Default)]
+struct Series {
+    samples: VecDeque<f64>,
+    total_count: u64,
+}
+
+fn series() -> &'static Mutex<[Series; 4]> {
+    static SERIES: OnceLock<Mutex<[Series; 4]>> = OnceLock::new();
+    SERIES.get_or_init(|| Mutex::new(Default::default()))
+}
+
+pub fn record(metric: Metric, elapsed: Duration) {
+    let mut all = series().lock().unwrap_or_else(|e| e.into_inner());
+    let s = &mut all[metric as usize];
+    if s.samples.len() == WINDOW {
+        s.samples.pop_front();
+    }
+    s.samples.push_back(elapsed.as_secs_f64() * 1000.0);
+    s.total_count += 1;
+}
+
+/// Records the time until it is dropped, so early returns and errors are timed too.
+pub struct Timer {
+    metric: Metric,
+    started: Instant,
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        record(self.metric, self.started.elapsed());
+    }
+}
+
+pub fn timer(metric: Metric) -> Timer {
+    Timer { metric, started: Instant::now() }
+}
+
+/// Rolling figures of one metric over its last samples, in milliseconds.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TimingStats {
+    pub name: &'static str,
+    /// Samples in the window (at most 200).
+    pub samples: usize,
+    /// Samples since the app started.
+    pub total_count: u64,
+    pub avg_ms: f64,
+    pub p95_ms: f64,
+    pub max_ms: f64,
+    pub last_ms: Option<f64>,
+}
+
+pub fn timings() -> Vec<TimingStats> {
+    let all = series().lock().unwrap_or_else(|e| e.into_inner());
+    Metric::ALL
+        .iter()
+        .map(|&metric| {
+            let s = &all[metric as usize];
+            let mut sorted: Vec<f64> = s.samples.iter().copied().collect();
+            sorted.sort_by(f64::total_cmp);
+            let round = |ms: f64| (ms * 10.0).round() / 10.0;
+            let avg = if sorted.is_empty() { 0.0 } else { sorted.iter().sum::<f64>() / sorted.len() as f64 };
+            let p95 = sorted
+                .get((sorted.len() * 95).div_ceil(100).saturating_sub(1))
+                .copied()
+                .unwrap_or(0.0);
+            TimingStats {
+                name: metric.name(),
+                samples: sorted.len(),
+                total_count: s.total_count,
+                avg_ms: round(avg),
+                p95_ms: round(p95),
+                max_ms: round(sorted.last().copied().unwrap_or(0.0)),
+                last_ms: s.samples.back().copied().map(round),
+            }
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PerformanceMetrics {
+    pub timings: Vec<TimingStats>,
+    /// Worker pool slots per class.
+    pub queues: Vec<ClassDepth>,
+    /// Rows waiting in `pending_appends` for an unavailable workbook.
+    pub pending_appends: usize,
+}
+
+pub fn snapshot(db: &Db) -> Result<PerformanceMetrics, String> {
+    Ok(PerformanceMetrics {
+        timings: timings(),
+        queues: work_pool::depths(),
+        pending_appends: db.list_pending_appends(None)?.len(),
+    })
+}
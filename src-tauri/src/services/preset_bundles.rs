@@ -0,0 +1,171 @@
+//! Shareable preset bundles: one JSON file with a document type's registry entry, its field schema
+//! (validation rules and export columns), the analyzer's field mappings and optionally a profile's sheet
+//! and column mapping. A firm publishes e.g. "Стандарден дневник на влезни фактури" once and every client
+//! imports it with one command. Machine-specific parts (workbook path, default folder) are left out.
+
+use crate::db::Db;
+use crate::services::document_types::{self, DocumentType};
+use crate::services::field_schemas::{self, SchemaField};
+use crate::services::model_mappings::{self, FieldMapping};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::Path;
+
+/// Value of `format`, so other JSON files are rejected on import.
+const FORMAT: &str = "document-scanner-preset";
+/// Bumped on incompatible changes; bundles of a newer version are refused.
+const FORMAT_VERSION: u32 = 1;
+pub const EXTENSION: &str = "dspreset";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PresetProfile {
+    pub sheet_name: String,
+    pub column_mapping: Value,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PresetBundle {
+    pub format: String,
+    pub format_version: u32,
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    pub app_version: String,
+    pub created_at: String,
+    pub document_type: DocumentType,
+    /// Saved schema of the type; None keeps the importer's (built-in) schema.
+    #[serde(default)]
+    pub field_schema: Option<Vec<SchemaField>>,
+    /// Field mappings of the type's analyzer (empty for built-in models).
+    #[serde(default)]
+    pub model_mappings: Vec<FieldMapping>,
+    #[serde(default)]
+    pub profile: Option<PresetProfile>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PresetImportResult {
+    pub name: String,
+    pub document_type: String,
+    pub schema_imported: bool,
+    pub mappings_imported: usize,
+    /// Profile created from the bundle; None when the bundle has none or no workbook was given.
+    pub profile_id: Option<i64>,
+}
+
+/// Bundle of `document_type` and, when given, the sheet and column mapping of `profile_id`.
+pub fn build(
+    db: &Db,
+    document_type: &str,
+    profile_id: Option<i64>,
+    name: &str,
+    description: Option<String>,
+    app_version: &str,
+) -> Result<PresetBundle, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Preset name is required.".to_string());
+    }
+    let mut entry = document_types::list(db)?
+        .into_iter()
+        .find(|t| t.key == document_type.trim())
+        .ok_or_else(|| format!("Unknown document type: {}", document_type))?;
+    entry.default_folder = None;
+    let schema = field_schemas::get(db, &entry.key)?;
+    let model_mappings = match &entry.model_id {
+        Some(model_id) => model_mappings::get(db, Some(model_id))?
+            .into_iter()
+            .flat_map(|m| m.mappings)
+            .collect(),
+        None => Vec::new(),
+    };
+    let profile = match profile_id {
+        Some(id) => {
+            let (_, _, _, sheet_name, column_mapping) = db
+                .get_profiles()?
+                .into_iter()
+                .find(|(pid, ..)| *pid == id)
+                .ok_or("Profile not found.")?;
+            Some(PresetProfile {
+                sheet_name,
+                column_mapping: serde_json::from_str(&column_mapping).map_err(|e| e.to_string())?,
+            })
+        }
+        None => None,
+    };
+    Ok(PresetBundle {
+        format: FORMAT.to_string(),
+        format_version: FORMAT_VERSION,
+        name: name.to_string(),
+        description: description.map(|d| d.trim().to_string()).filter(|d| !d.is_empty()),
+        app_version: app_version.to_string(),
+        created_at: chrono::Local::now().to_rfc3339(),
+        document_type: entry,
+        field_schema: schema.custom.then_some(schema.fields),
+        model_mappings,
+        profile,
+    })
+}
+
+/// Write a bundle as pretty-printed JSON (readable in review and diffs).
+pub fn write(bundle: &PresetBundle, path: &Path) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(bundle).map_err(|e| e.to_string())?;
+    std::fs::write(path, json).map_err(|e| format!("Could not write preset: {}", e))
+}
+
+/// Read and check a bundle without importing it.
+pub fn read(path: &Path) -> Result<PresetBundle, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("Could not read preset: {}", e))?;
+    let bundle: PresetBundle = serde_json::from_str(&text).map_err(|_| "File is not a preset bundle.".to_string())?;
+    if bundle.format != FORMAT {
+        return Err("File is not a preset bundle.".to_string());
+    }
+    if bundle.format_version > FORMAT_VERSION {
+        return Err(format!(
+            "Preset was created by a newer version of the app ({}). Update the app to import it.",
+            bundle.app_version
+        ));
+    }
+    Ok(bundle)
+}
+
+/// Import a bundle: the document type (keeping this machine's default folder), its schema and field
+/// mappings, and a profile on `excel_path` when the bundle has one.
+pub fn import(db: &Db, bundle: PresetBundle, excel_path: Option<&str>) -> Result<PresetImportResult, String> {
+    let mut entry = bundle.document_type;
+    entry.default_folder = document_types::list(db)?
+        .into_iter()
+        .find(|t| t.key == entry.key)
+        .and_then(|t| t.default_folder);
+    let key = entry.key.clone();
+    let model_id = entry.model_id.clone();
+    document_types::save(db, entry)?;
+
+    let schema_imported = match bundle.field_schema {
+        Some(fields) => {
+            field_schemas::save(db, &key, fields)?;
+            true
+        }
+        None => false,
+    };
+    let mut mappings_imported = 0;
+    if let Some(model_id) = model_id.filter(|_| !bundle.model_mappings.is_empty()) {
+        mappings_imported = model_mappings::save(db, &model_id, bundle.model_mappings)?.mappings.len();
+    }
+    let profile_id = match (bundle.profile, excel_path.map(str::trim).filter(|p| !p.is_empty())) {
+        (Some(profile), Some(excel_path)) => {
+            Some(db.save_profile(None, &bundle.name, excel_path, &profile.sheet_name, &profile.column_mapping)?)
+        }
+        _ => None,
+    };
+    Ok(PresetImportResult {
+        name: bundle.name,
+        document_type: key,
+        schema_imported,
+        mappings_imported,
+        profile_id,
+    })
+}
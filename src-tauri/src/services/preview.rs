@@ -0,0 +1,167 @@
+//! Page previews as PNG for History and the review screen, so the webview never loads whole PDFs.
+//! PDFs are rendered with pdfium, loaded at runtime from next to the executable (bundled with the
+//! installer) or from the system; without it, scanned PDFs still preview from their embedded page image.
+//! Rendered pages are cached in the thumbnails store, which storage quotas trim.
+
+use crate::services::document_format::{self, detect_format, DocumentFormat, UNSUPPORTED_FORMAT};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use image::{DynamicImage, ImageFormat};
+use pdfium_render::prelude::{PdfRenderConfig, Pdfium};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Preview cache folder under app data (a storage store, trimmed by quota).
+pub const THUMBNAILS_DIR: &str = "thumbnails";
+
+pub const DEFAULT_DPI: u32 = 96;
+const MIN_DPI: u32 = 24;
+const MAX_DPI: u32 = 300;
+/// Images are scaled as if scanned at this resolution.
+const IMAGE_SCAN_DPI: u32 = 300;
+const PDF_POINTS_PER_INCH: f32 = 72.0;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RenderedPreview {
+    pub png_base64: String,
+    pub width: u32,
+    pub height: u32,
+    /// 1-based page that was rendered.
+    pub page: u32,
+    pub cached: bool,
+}
+
+/// pdfium bindings, loaded once; None when the library is not installed.
+fn pdfium() -> Option<&'static Pdfium> {
+    static PDFIUM: OnceLock<Option<Pdfium>> = OnceLock::new();
+    PDFIUM
+        .get_or_init(|| {
+            let beside_exe = std::env::current_exe()
+                .ok()
+                .and_then(|exe| exe.parent().map(Pdfium::pdfium_platform_library_name_at_path));
+            beside_exe
+                .and_then(|path| Pdfium::bind_to_library(path).ok())
+                .or_else(|| Pdfium::bind_to_system_library().ok())
+                .map(Pdfium::new)
+        })
+        .as_ref()
+}
+
+/// Cache file for this version of the file (size and modification time), page and resolution.
+fn cache_path(app_data_dir: &Path, path: &Path, page: u32, dpi: u32) -> Option<PathBuf> {
+    let meta = fs::metadata(path).ok()?;
+    let modified = meta.modified().ok()?.duration_since(std::time::UNIX_EPOCH).ok()?.as_millis();
+    let key = format!("{}|{}|{}|{}|{}", path.to_string_lossy(), meta.len(), modified, page, dpi);
+    let hash: String = Sha256::digest(key.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect();
+    Some(app_data_dir.join(THUMBNAILS_DIR).join(format!("{}.png", hash)))
+}
+
+/// PNG of `page` (1-based, default 1) of a PDF or image at `dpi` (default 96, clamped to 24–300).
+pub fn render(app_data_dir: &Path, file_path: &str, page: Option<u32>, dpi: Option<u32>) -> Result<RenderedPreview, String> {
+    let path = Path::new(file_path);
+    let page = page.unwrap_or(1).max(1);
+    let dpi = dpi.unwrap_or(DEFAULT_DPI).clamp(MIN_DPI, MAX_DPI);
+    let cache = cache_path(app_data_dir, path, page, dpi);
+    if let Some(png) = cache.as_ref().and_then(|c| fs::read(c).ok()) {
+        if let Some((width, height)) = document_format::image_dimensions(&png, DocumentFormat::Png) {
+            return Ok(RenderedPreview { png_base64: BASE64.encode(&png), width, height, page, cached: true });
+        }
+    }
+
+    let bytes = fs::read(path).map_err(|e| {
+        if e.kind() == std::io::ErrorKind::NotFound {
+            "File not found.".to_string()
+        } else {
+            format!("Could not read file: {}", e)
+        }
+    })?;
+    let image = match detect_format(&bytes).ok_or_else(|| UNSUPPORTED_FORMAT.to_string())? {
+        DocumentFormat::Pdf => render_pdf_page(&bytes, page, dpi)?,
+        DocumentFormat::Heic => return Err("Preview is not available for HEIC images.".to_string()),
+        format => {
+            if page > 1 {
+                return Err(format!("Page {} not found; previews of images show the first page.", page));
+            }
+            let image_format = match format {
+                DocumentFormat::Jpeg => ImageFormat::Jpeg,
+                DocumentFormat::Png => ImageFormat::Png,
+                _ => ImageFormat::Tiff,
+            };
+            let image = image::load_from_memory_with_format(&bytes, image_format)
+                .map_err(|e| format!("Could not read image: {}", e))?;
+            scaled(image, dpi as f32 / IMAGE_SCAN_DPI as f32)
+        }
+    };
+
+    let mut png = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .map_err(|e| format!("Could not encode preview: {}", e))?;
+    if let Some(cache) = cache {
+        if let Some(dir) = cache.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let _ = fs::write(&cache, &png);
+    }
+    Ok(RenderedPreview {
+        png_base64: BASE64.encode(&png),
+        width: image.width(),
+        height: image.height(),
+        page,
+        cached: false,
+    })
+}
+
+/// Downscale by `factor` (never enlarges).
+fn scaled(image: DynamicImage, factor: f32) -> DynamicImage {
+    if factor >= 1.0 {
+        return image;
+    }
+    let width = ((image.width() as f32 * factor).round() as u32).max(1);
+    let height = ((image.height() as f32 * factor).round() as u32).max(1);
+    image.resize(width, height, image::imageops::FilterType::Triangle)
+}
+
+fn render_pdf_page(bytes: &[u8], page: u32, dpi: u32) -> Result<DynamicImage, String> {
+    let Some(pdfium) = pdfium() else {
+        return embedded_page_image(bytes, page, dpi);
+    };
+    let document = pdfium
+        .load_pdf_from_byte_slice(bytes, None)
+        .map_err(|e| format!("Could not read PDF: {}", e))?;
+    let index = u16::try_from(page - 1).map_err(|_| format!("Page {} not found.", page))?;
+    let pdf_page = document
+        .pages()
+        .get(index)
+        .map_err(|_| format!("Page {} not found; the document has {} page(s).", page, document.pages().len()))?;
+    let width = (pdf_page.width().value / PDF_POINTS_PER_INCH * dpi as f32).round().max(1.0) as i32;
+    let bitmap = pdf_page
+        .render_with_config(&PdfRenderConfig::new().set_target_width(width))
+        .map_err(|e| format!("Could not render page {}: {}", page, e))?;
+    Ok(bitmap.as_image())
+}
+
+/// Largest JPEG on the page (the scan itself in scanner-made PDFs), for when pdfium is not available.
+fn embedded_page_image(bytes: &[u8], page: u32, dpi: u32) -> Result<DynamicImage, String> {
+    let doc = lopdf::Document::load_mem(bytes).map_err(|e| format!("Could not read PDF: {}", e))?;
+    let pages = doc.get_pages();
+    let page_id = *pages
+        .get(&page)
+        .ok_or_else(|| format!("Page {} not found; the document has {} page(s).", page, pages.len()))?;
+    let image = doc
+        .get_page_images(page_id)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|img| img.filters.as_deref() == Some(&["DCTDecode".to_string()][..]))
+        .max_by_key(|img| img.width * img.height)
+        .ok_or("PDF preview needs the pdfium library; this page has no embedded scan image.")?;
+    let decoded = image::load_from_memory_with_format(image.content, ImageFormat::Jpeg)
+        .map_err(|e| format!("Could not read page image: {}", e))?;
+    let page_width_pt = crate::ocr::pdf_page_size(&doc, page_id).map(|(w, _)| w as f32).unwrap_or(595.0);
+    let factor = page_width_pt / PDF_POINTS_PER_INCH * dpi as f32 / decoded.width().max(1) as f32;
+    Ok(scaled(decoded, factor))
+}
@@ -0,0 +1,309 @@
+//! Cross-check of history records marked as added to Excel against the profile's ledger, matched by the
+//! profile's duplicate key (the document number unless configured otherwise, see `duplicate_keys`). Run
+//! before the monthly VAT filing to catch rows deleted, pasted twice or edited by hand.
+
+use crate::services::{duplicate_keys, workbook_compat};
+use calamine::{open_workbook_auto, Data, Range, Reader};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Exported history record whose key is not in the ledger. `document_number` holds the key values joined by " / ".
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MissingDocument {
+    pub history_id: i64,
+    pub created_at: String,
+    pub document_number: String,
+}
+
+/// Key that appears on more than one ledger row.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicatedDocument {
+    pub document_number: String,
+    /// 1-based ledger rows.
+    pub row_numbers: Vec<u32>,
+    pub history_ids: Vec<i64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldChange {
+    pub column_letter: String,
+    pub field_key: String,
+    /// Value in the history record.
+    pub expected: String,
+    /// Value currently in the ledger.
+    pub actual: String,
+}
+
+/// Ledger row whose mapped cells no longer match the history record.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AlteredRow {
+    pub history_id: i64,
+    pub document_number: String,
+    pub row_number: u32,
+    pub changes: Vec<FieldChange>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileAuditReport {
+    pub profile_id: i64,
+    pub excel_path: String,
+    pub sheet_name: String,
+    pub checked_at: String,
+    /// Column holding the first key part, normally the document number (e.g. "B").
+    pub document_number_column: String,
+    /// Fields of the key rows were matched by.
+    pub key_fields: Vec<String>,
+    pub history_count: usize,
+    pub ledger_row_count: usize,
+    pub matched_count: usize,
+    pub missing: Vec<MissingDocument>,
+    pub duplicated: Vec<DuplicatedDocument>,
+    pub altered: Vec<AlteredRow>,
+    /// Exported history records missing a key value (cannot be checked).
+    pub without_document_number: Vec<i64>,
+}
+
+/// One non-empty ledger row: 1-based row number and the text of each mapped column.
+pub(crate) struct LedgerRow {
+    pub row_number: u32,
+    pub cells: HashMap<String, String>,
+}
+
+pub(crate) fn letter_to_index(letter: &str) -> Option<u32> {
+    let letter = letter.trim();
+    if letter.is_empty() || letter.len() > 3 || !letter.chars().all(|c| c.is_ascii_alphabetic()) {
+        return None;
+    }
+    Some(
+        letter
+            .to_ascii_uppercase()
+            .bytes()
+            .fold(0u32, |acc, b| acc * 26 + (b - b'A' + 1) as u32)
+            - 1,
+    )
+}
+
+fn normalize_text(value: &str) -> String {
+    value.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+fn parse_date(value: &str) -> Option<chrono::NaiveDate> {
+    ["%d.%m.%Y", "%d/%m/%Y", "%Y-%m-%d", "%d.%m.%Y."]
+        .iter()
+        .find_map(|f| chrono::NaiveDate::parse_from_str(value.trim(), f).ok())
+}
+
+/// Text match (case/whitespace-insensitive), same amount, or same date in another format.
+fn values_equal(expected: &str, actual: &str) -> bool {
+    if normalize_text(expected) == normalize_text(actual) {
+        return true;
+    }
+    if let (Some(a), Some(b)) = (parse_date(expected), parse_date(actual)) {
+        return a == b;
+    }
+    let parse = |s: &str| crate::excel::normalize_amount_string(s).parse::<f64>().ok();
+    match (parse(expected), parse(actual)) {
+        (Some(x), Some(y)) => (x - y).abs() < 0.005,
+        _ => false,
+    }
+}
+
+/// Read the mapped columns of every non-empty row below `header_row`.
+pub(crate) fn read_ledger_rows(
+    range: &Range<Data>,
+    header_row: u32,
+    columns: &[(String, u32)],
+    date1904: bool,
+) -> Vec<LedgerRow> {
+    let last_row = range.end().map(|(r, _)| r + 1).unwrap_or(0);
+    let mut rows = Vec::new();
+    for row_number in (header_row + 1)..=last_row {
+        let cells: HashMap<String, String> = columns
+            .iter()
+            .map(|(letter, col)| {
+                let text = range
+                    .get_value((row_number - 1, *col))
+                    .map(|c| workbook_compat::cell_text(c, date1904))
+                    .unwrap_or_default();
+                (letter.clone(), text.trim().to_string())
+            })
+            .collect();
+        if cells.values().any(|v| !v.is_empty()) {
+            rows.push(LedgerRow { row_number, cells });
+        }
+    }
+    rows
+}
+
+/// Value the app would have written for `field_key` from a history record.
+fn expected_value(data: &Value, field_key: &str) -> Option<String> {
+    let value = match data.get(field_key)? {
+        Value::String(s) => s.trim().to_string(),
+        Value::Number(n) => n.to_string(),
+        _ => return None,
+    };
+    if value.is_empty() {
+        return None;
+    }
+    // Same conversion as append_to_excel_fast for the DDV period column.
+    if field_key == "taxPeriod" {
+        if let Some(month_name) = crate::excel::period_to_month_name_mk(&value) {
+            return Some(month_name);
+        }
+    }
+    Some(value)
+}
+
+fn compare_row(data: &Value, row: &LedgerRow, mapping: &[(String, String)]) -> Vec<FieldChange> {
+    mapping
+        .iter()
+        .filter_map(|(letter, field_key)| {
+            let expected = expected_value(data, field_key)?;
+            let actual = row.cells.get(letter).cloned().unwrap_or_default();
+            (!values_equal(&expected, &actual)).then(|| FieldChange {
+                column_letter: letter.clone(),
+                field_key: field_key.clone(),
+                expected,
+                actual,
+            })
+        })
+        .collect()
+}
+
+/// Audit `history` rows (id, created_at, extracted_data JSON) against the ledger sheet.
+/// `column_mapping` is the profile mapping (column letter -> field key); rows are matched by `key_fields`.
+pub fn audit_profile(
+    profile_id: i64,
+    excel_path: &str,
+    sheet_name: &str,
+    header_row: u32,
+    column_mapping: &HashMap<String, String>,
+    key_fields: &[String],
+    history: &[(i64, String, String)],
+) -> Result<ProfileAuditReport, String> {
+    let mut mapping: Vec<(String, String)> = column_mapping
+        .iter()
+        .filter(|(letter, key)| letter_to_index(letter).is_some() && !key.is_empty() && !key.starts_with("col_"))
+        .map(|(letter, key)| (letter.trim().to_ascii_uppercase(), key.clone()))
+        .collect();
+    mapping.sort_by_key(|(letter, _)| letter_to_index(letter));
+    let key_columns = duplicate_keys::key_columns(key_fields, column_mapping)?;
+
+    let path = Path::new(excel_path);
+    if !path.exists() {
+        return Err("File not found. Browse to select again.".to_string());
+    }
+    let mut workbook = open_workbook_auto(path).map_err(|e| format!("Could not open Excel file: {}", e))?;
+    let sheet_name = crate::excel::resolve_sheet_name(&workbook.sheet_names(), sheet_name)?;
+    let range = workbook
+        .worksheet_range(&sheet_name)
+        .map_err(|e| format!("Sheet not found: {}", e))?;
+    let mut columns: Vec<(String, u32)> = mapping
+        .iter()
+        .filter_map(|(letter, _)| Some((letter.clone(), letter_to_index(letter)?)))
+        .collect();
+    for letter in &key_columns {
+        if !columns.iter().any(|(l, _)| l == letter) {
+            columns.extend(letter_to_index(letter).map(|i| (letter.clone(), i)));
+        }
+    }
+    let date1904 = workbook_compat::uses_1904_dates(path);
+    let ledger = read_ledger_rows(&range, header_row.max(1), &columns, date1904);
+
+    let mut rows_by_key: HashMap<String, Vec<&LedgerRow>> = HashMap::new();
+    for row in &ledger {
+        if let Some(key) = duplicate_keys::row_key(key_fields, &key_columns, row) {
+            rows_by_key.entry(key).or_default().push(row);
+        }
+    }
+    let display_key = |value: &dyn Fn(&str) -> Option<String>| {
+        key_fields
+            .iter()
+            .filter_map(|f| value(duplicate_keys::source_field(f)))
+            .collect::<Vec<_>>()
+            .join(" / ")
+    };
+
+    let mut report = ProfileAuditReport {
+        profile_id,
+        excel_path: excel_path.to_string(),
+        sheet_name: sheet_name.clone(),
+        checked_at: chrono::Utc::now().to_rfc3339(),
+        document_number_column: key_columns.first().cloned().unwrap_or_default(),
+        key_fields: key_fields.to_vec(),
+        history_count: history.len(),
+        ledger_row_count: ledger.len(),
+        matched_count: 0,
+        missing: Vec::new(),
+        duplicated: Vec::new(),
+        altered: Vec::new(),
+        without_document_number: Vec::new(),
+    };
+    let mut history_ids_by_key: HashMap<String, Vec<i64>> = HashMap::new();
+    for (history_id, created_at, extracted_data) in history {
+        let data: Value = serde_json::from_str(extracted_data).unwrap_or(Value::Null);
+        let value = |field: &str| {
+            expected_value(&data, field).or_else(|| {
+                // Records scanned before the mapping changed may hold the number under the other key.
+                duplicate_keys::DOCUMENT_NUMBER_KEYS
+                    .contains(&field)
+                    .then(|| duplicate_keys::DOCUMENT_NUMBER_KEYS.iter().find_map(|k| expected_value(&data, k)))
+                    .flatten()
+            })
+        };
+        let Some(key) = duplicate_keys::key_of(key_fields, value) else {
+            report.without_document_number.push(*history_id);
+            continue;
+        };
+        let document_number = display_key(&value);
+        let Some(rows) = rows_by_key.get(&key) else {
+            report.missing.push(MissingDocument {
+                history_id: *history_id,
+                created_at: created_at.clone(),
+                document_number,
+            });
+            continue;
+        };
+        report.matched_count += 1;
+        history_ids_by_key.entry(key).or_default().push(*history_id);
+        // With duplicates, compare against the closest row so an edited copy does not hide an intact one.
+        let closest = rows
+            .iter()
+            .map(|row| (row.row_number, compare_row(&data, row, &mapping)))
+            .min_by_key(|(_, changes)| changes.len());
+        if let Some((row_number, changes)) = closest {
+            if !changes.is_empty() {
+                report.altered.push(AlteredRow {
+                    history_id: *history_id,
+                    document_number,
+                    row_number,
+                    changes,
+                });
+            }
+        }
+    }
+
+    for (key, history_ids) in history_ids_by_key {
+        let rows = &rows_by_key[&key];
+        if rows.len() > 1 {
+            let first = rows[0];
+            report.duplicated.push(DuplicatedDocument {
+                document_number: display_key(&|field: &str| {
+                    let i = key_fields.iter().position(|f| duplicate_keys::source_field(f) == field)?;
+                    first.cells.get(&key_columns[i]).cloned().filter(|v| !v.is_empty())
+                }),
+                row_numbers: rows.iter().map(|r| r.row_number).collect(),
+                history_ids,
+            });
+        }
+    }
+    report.duplicated.sort_by_key(|d| d.row_numbers[0]);
+    Ok(report)
+}
@@ -0,0 +1,115 @@
+//! Per-profile redaction rules for exports shared with third parties (e.g. omit the buyer's tax ID,
+//! keep only the last digits of an IBAN). Rules only change the exported copy; history keeps the full data.
+
+use crate::db::Db;
+use crate::types::InvoiceData;
+use serde::{Deserialize, Serialize};
+
+const RULES_KEY_PREFIX: &str = "redaction_rules.";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RedactionAction {
+    /// Export the field empty.
+    Omit,
+    /// Replace every character but the last `keep` with '*'.
+    Mask,
+    /// Keep only the first `keep` characters.
+    Truncate,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RedactionRule {
+    pub field_key: String,
+    pub action: RedactionAction,
+    /// Characters left visible by Mask / Truncate.
+    #[serde(default)]
+    pub keep: usize,
+}
+
+fn rules_key(profile_id: i64) -> String {
+    format!("{}{}", RULES_KEY_PREFIX, profile_id)
+}
+
+pub fn get_rules(db: &Db, profile_id: i64) -> Result<Vec<RedactionRule>, String> {
+    let stored = db.get_settings()?;
+    match stored.get(&rules_key(profile_id)) {
+        Some(json) => serde_json::from_str(json).map_err(|e| format!("Invalid redaction rules: {}", e)),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Replace the profile's rules; an empty list removes them.
+pub fn save_rules(db: &Db, profile_id: i64, rules: Vec<RedactionRule>) -> Result<Vec<RedactionRule>, String> {
+    db.get_profile_by_id(profile_id)?;
+    let mut cleaned: Vec<RedactionRule> = Vec::new();
+    for mut rule in rules {
+        rule.field_key = rule.field_key.trim().to_string();
+        if rule.field_key.is_empty() {
+            return Err("Every redaction rule needs a field.".to_string());
+        }
+        if cleaned.iter().any(|r| r.field_key == rule.field_key) {
+            return Err(format!("More than one rule for field {}.", rule.field_key));
+        }
+        cleaned.push(rule);
+    }
+    let value = if cleaned.is_empty() {
+        None
+    } else {
+        Some(serde_json::to_string(&cleaned).map_err(|e| e.to_string())?)
+    };
+    let key = rules_key(profile_id);
+    db.set_settings(&[(key.as_str(), value)])?;
+    Ok(cleaned)
+}
+
+/// Drop a deleted profile's rules.
+pub fn remove_rules(db: &Db, profile_id: i64) -> Result<(), String> {
+    let key = rules_key(profile_id);
+    Ok(db.set_settings(&[(key.as_str(), None)])?)
+}
+
+pub fn redact_value(value: &str, rule: &RedactionRule) -> String {
+    let chars: Vec<char> = value.chars().collect();
+    match rule.action {
+        RedactionAction::Omit => String::new(),
+        RedactionAction::Truncate => chars.iter().take(rule.keep).collect(),
+        RedactionAction::Mask => {
+            let hidden = chars.len().saturating_sub(rule.keep);
+            chars
+                .iter()
+                .enumerate()
+                .map(|(i, c)| if i < hidden && !c.is_whitespace() { '*' } else { *c })
+                .collect()
+        }
+    }
+}
+
+/// Copies of `invoices` with the rules applied.
+pub fn apply(rules: &[RedactionRule], invoices: &[InvoiceData]) -> Vec<InvoiceData> {
+    invoices
+        .iter()
+        .map(|inv| {
+            let mut inv = inv.clone();
+            for rule in rules {
+                if let Some(field) = inv.fields.get_mut(&rule.field_key) {
+                    field.value = redact_value(&field.value, rule);
+                    if rule.action == RedactionAction::Omit {
+                        field.confidence = None;
+                    }
+                }
+            }
+            inv
+        })
+        .collect()
+}
+
+/// `invoices` with the profile's rules applied (unchanged when the profile has none).
+pub fn redact_for_profile(db: &Db, profile_id: i64, invoices: Vec<InvoiceData>) -> Result<Vec<InvoiceData>, String> {
+    let rules = get_rules(db, profile_id)?;
+    if rules.is_empty() {
+        return Ok(invoices);
+    }
+    Ok(apply(&rules, &invoices))
+}
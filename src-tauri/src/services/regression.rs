@@ -0,0 +1,219 @@
+//! Extraction regression harness (cargo feature `regression`): replays stored Azure poll responses
+//! through the parsing in ocr.rs and compares the fields with expected values, so heuristics can be
+//! changed without re-scanning documents. A fixture folder holds `<case>.json` (the raw poll response)
+//! and `<case>.expected.json` (`documentType`, expected `fields` — null for a field that must be absent —
+//! and optionally `ledgerRow`, the row the case must append to the folder's `ledger.xlsx`).
+//! Fixtures for the built-in types are in src-tauri/fixtures/regression.
+
+use crate::error::AppError;
+use crate::excel;
+use crate::ocr;
+use crate::services::export_locale::ExportLocale;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+const EXPECTED_SUFFIX: &str = ".expected.json";
+const LEDGER_FILE: &str = "ledger.xlsx";
+const LEDGER_SHEET: &str = "Invoices";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Expected {
+    #[serde(default)]
+    document_type: Option<String>,
+    #[serde(default)]
+    fields: BTreeMap<String, Option<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ledger_row: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldDiff {
+    pub field_key: String,
+    pub expected: Option<String>,
+    pub actual: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegressionCase {
+    pub name: String,
+    pub document_type: Option<String>,
+    pub passed: bool,
+    /// Parse or fixture error; the case counts as failed.
+    pub error: Option<String>,
+    pub diffs: Vec<FieldDiff>,
+    /// Ledger cells that differ, as "column: expected != actual".
+    pub ledger_diffs: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegressionReport {
+    pub dir: String,
+    pub passed: usize,
+    pub failed: usize,
+    /// Expected files written (update mode).
+    pub updated: usize,
+    pub cases: Vec<RegressionCase>,
+}
+
+fn read_json<T: for<'de> Deserialize<'de>>(path: &Path) -> Result<T, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    serde_json::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Replay every case in `dir`. With `update`, missing or differing expected files are rewritten from the
+/// current output (review the git diff of the fixtures afterwards).
+pub fn run(dir: &Path, update: bool) -> Result<RegressionReport, AppError> {
+    let mut inputs: Vec<PathBuf> = std::fs::read_dir(dir)
+        .map_err(|e| AppError::Io(format!("Could not read {}: {}", dir.display(), e)))?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| {
+            let name = p.file_name().and_then(|n| n.to_str()).unwrap_or("");
+            name.ends_with(".json") && !name.ends_with(EXPECTED_SUFFIX)
+        })
+        .collect();
+    inputs.sort();
+    let ledger = Some(dir.join(LEDGER_FILE)).filter(|p| p.is_file());
+
+    let mut report = RegressionReport {
+        dir: dir.to_string_lossy().to_string(),
+        passed: 0,
+        failed: 0,
+        updated: 0,
+        cases: Vec::new(),
+    };
+    for input in inputs {
+        let name = input.file_stem().and_then(|s| s.to_str()).unwrap_or_default().to_string();
+        let expected_path = dir.join(format!("{}{}", name, EXPECTED_SUFFIX));
+        let case = run_case(&name, &input, &expected_path, ledger.as_deref(), update, &mut report.updated);
+        if case.passed {
+            report.passed += 1;
+        } else {
+            report.failed += 1;
+        }
+        report.cases.push(case);
+    }
+    Ok(report)
+}
+
+fn run_case(
+    name: &str,
+    input: &Path,
+    expected_path: &Path,
+    ledger: Option<&Path>,
+    update: bool,
+    updated: &mut usize,
+) -> RegressionCase {
+    let mut case = RegressionCase {
+        name: name.to_string(),
+        document_type: None,
+        passed: false,
+        error: None,
+        diffs: Vec::new(),
+        ledger_diffs: Vec::new(),
+    };
+    let expected: Expected = if expected_path.exists() {
+        match read_json(expected_path) {
+            Ok(e) => e,
+            Err(e) => {
+                case.error = Some(e);
+                return case;
+            }
+        }
+    } else if update {
+        Expected::default()
+    } else {
+        case.error = Some(format!("{} is missing; run with update to create it.", expected_path.display()));
+        return case;
+    };
+    case.document_type = expected.document_type.clone();
+
+    let poll_json: Value = match read_json(input) {
+        Ok(v) => v,
+        Err(e) => {
+            case.error = Some(e);
+            return case;
+        }
+    };
+    let result = match ocr::replay_poll_json(&poll_json, expected.document_type.as_deref()) {
+        Ok(r) => r,
+        Err(e) => {
+            case.error = Some(e.to_string());
+            return case;
+        }
+    };
+    let actual: BTreeMap<String, String> = result
+        .invoice_data
+        .fields
+        .iter()
+        .filter(|(_, v)| !v.value.trim().is_empty())
+        .map(|(k, v)| (k.clone(), v.value.clone()))
+        .collect();
+
+    if update {
+        let mut next = expected.clone();
+        next.fields = actual.iter().map(|(k, v)| (k.clone(), Some(v.clone()))).collect();
+        if let (Some(ledger), Some(_)) = (ledger, &expected.ledger_row) {
+            match append_to_ledger_copy(ledger, name, &result.invoice_data) {
+                Ok(row) => next.ledger_row = Some(row),
+                Err(e) => case.error = Some(e),
+            }
+        }
+        let changed = !expected_path.exists() || next.fields != expected.fields || next.ledger_row != expected.ledger_row;
+        if changed {
+            let written = serde_json::to_string_pretty(&next)
+                .map_err(|e| e.to_string())
+                .and_then(|json| std::fs::write(expected_path, json + "\n").map_err(|e| e.to_string()));
+            match written {
+                Ok(()) => *updated += 1,
+                Err(e) => case.error = Some(e),
+            }
+        }
+        case.passed = case.error.is_none();
+        return case;
+    }
+
+    for (key, want) in &expected.fields {
+        let got = actual.get(key).cloned();
+        if got != *want {
+            case.diffs.push(FieldDiff {
+                field_key: key.clone(),
+                expected: want.clone(),
+                actual: got,
+            });
+        }
+    }
+    if let (Some(ledger), Some(want)) = (ledger, &expected.ledger_row) {
+        match append_to_ledger_copy(ledger, name, &result.invoice_data) {
+            Ok(row) => {
+                for (i, (w, g)) in want.iter().zip(row.iter().chain(std::iter::repeat(&String::new()))).enumerate() {
+                    if w != g {
+                        case.ledger_diffs.push(format!("{}: {:?} != {:?}", i + 1, w, g));
+                    }
+                }
+            }
+            Err(e) => case.error = Some(e),
+        }
+    }
+    case.passed = case.error.is_none() && case.diffs.is_empty() && case.ledger_diffs.is_empty();
+    case
+}
+
+/// Append the case's fields to a temporary copy of the fixture ledger and read the written row back.
+fn append_to_ledger_copy(ledger: &Path, name: &str, invoice: &crate::types::InvoiceData) -> Result<Vec<String>, String> {
+    let copy = std::env::temp_dir().join(format!("regression_{}_{}.xlsx", name, std::process::id()));
+    std::fs::copy(ledger, &copy).map_err(|e| format!("Could not copy {}: {}", ledger.display(), e))?;
+    let copy_str = copy.to_string_lossy().to_string();
+    let locale = ExportLocale::default();
+    let row = excel::append_invoices_to_existing_excel(&copy_str, LEDGER_SHEET, 1, std::slice::from_ref(invoice), &locale)
+        .and_then(|(_, last, _)| excel::read_sheet_range(&copy_str, LEDGER_SHEET, last, 1))
+        .map(|range| range.rows.into_iter().next().map(|r| r.cells).unwrap_or_default())
+        .map_err(|e| e.to_string());
+    let _ = std::fs::remove_file(&copy);
+    row
+}
@@ -0,0 +1,69 @@
+//! Scan sessions: a bundle of documents processed in one sitting, with the operator who scanned it,
+//! start and end times and counts. History records added while a session is open point at it, so the
+//! supervising accountant can see who processed which bundle.
+
+use crate::db::{Db, ScanSessionRow};
+use crate::services::settings;
+use serde::Serialize;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanSession {
+    pub id: i64,
+    pub operator: String,
+    pub note: Option<String>,
+    pub started_at: String,
+    /// None while the session is open.
+    pub ended_at: Option<String>,
+    pub documents: u32,
+    /// Documents written to a ledger.
+    pub exported: u32,
+    pub errors: u32,
+}
+
+fn from_row((id, operator, note, started_at, ended_at, documents, exported, errors): ScanSessionRow) -> ScanSession {
+    ScanSession { id, operator, note, started_at, ended_at, documents, exported, errors }
+}
+
+fn non_empty(value: Option<&str>) -> Option<String> {
+    value.map(str::trim).filter(|v| !v.is_empty()).map(str::to_string)
+}
+
+/// Operator of a new session: the given name, else the one from settings, else the OS login.
+fn operator_name(db: &Db, given: Option<&str>) -> Result<String, String> {
+    if let Some(name) = non_empty(given) {
+        return Ok(name);
+    }
+    if let Some(name) = settings::get_settings(db)?.operator_name {
+        return Ok(name);
+    }
+    ["USERNAME", "USER"]
+        .iter()
+        .find_map(|var| non_empty(std::env::var(var).ok().as_deref()))
+        .ok_or_else(|| "Enter the operator's name (Settings → Operator).".to_string())
+}
+
+pub fn start(db: &Db, operator: Option<&str>, note: Option<&str>) -> Result<ScanSession, String> {
+    let operator = operator_name(db, operator)?;
+    db.start_scan_session(&operator, non_empty(note).as_deref())?;
+    active(db)?.ok_or_else(|| "The scan session could not be started.".to_string())
+}
+
+pub fn end(db: &Db, id: i64) -> Result<ScanSession, String> {
+    if !db.end_scan_session(id)? {
+        return Err("This scan session is not open.".to_string());
+    }
+    list(db, u32::MAX)?
+        .into_iter()
+        .find(|s| s.id == id)
+        .ok_or_else(|| "Scan session not found.".to_string())
+}
+
+pub fn active(db: &Db) -> Result<Option<ScanSession>, String> {
+    Ok(db.list_scan_sessions(true, 1)?.into_iter().next().map(from_row))
+}
+
+pub fn list(db: &Db, limit: u32) -> Result<Vec<ScanSession>, String> {
+    Ok(db.list_scan_sessions(false, limit)?.into_iter().map(from_row).collect())
+}
+
@@ -0,0 +1,128 @@
+//! Column drift between a profile's stored schema and its ledger. Appends write by the column letters of
+//! the stored schema, so a column inserted, deleted or dragged in Excel since the last scan would put
+//! values under the wrong headers. Before appending, the header row is read again and compared with the
+//! stored one; when a mapped column moved or disappeared the append is refused with
+//! `AppError::SchemaChanged` until the mapping is moved onto the new columns (`remap`).
+
+use crate::error::AppError;
+use crate::models::{ColumnChange, ExcelSchema, HeaderInfo, SchemaDiff};
+use crate::services::sheet_routes;
+use std::collections::HashMap;
+
+fn normalize(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+fn field_of(column_mapping: &HashMap<String, String>, letter: &str) -> Option<String> {
+    column_mapping
+        .iter()
+        .find(|(l, _)| l.eq_ignore_ascii_case(letter))
+        .map(|(_, field)| field.clone())
+        .filter(|field| !field.is_empty())
+}
+
+/// Differences between the stored headers and `current` ones, None when every header is still in its
+/// column. Headers are paired by text (repeated texts in column order); a renamed header shows as removed
+/// and added.
+pub fn diff(
+    stored: &ExcelSchema,
+    current: &[HeaderInfo],
+    column_mapping: &HashMap<String, String>,
+) -> Option<SchemaDiff> {
+    let layout = |headers: &[HeaderInfo]| -> Vec<(String, String)> {
+        headers.iter().map(|h| (h.column_letter.to_uppercase(), normalize(&h.text))).collect()
+    };
+    if layout(&stored.headers) == layout(current) {
+        return None;
+    }
+    let mut unmatched: Vec<&HeaderInfo> = current.iter().collect();
+    let mut diff = SchemaDiff { header_row: stored.header_row, ..SchemaDiff::default() };
+    for old in &stored.headers {
+        let field_key = field_of(column_mapping, &old.column_letter);
+        let text = normalize(&old.text);
+        match unmatched.iter().position(|h| normalize(&h.text) == text) {
+            Some(i) => {
+                let new = unmatched.remove(i);
+                if !new.column_letter.eq_ignore_ascii_case(&old.column_letter) {
+                    diff.moved.push(ColumnChange {
+                        text: new.text.clone(),
+                        from: Some(old.column_letter.clone()),
+                        to: Some(new.column_letter.clone()),
+                        field_key,
+                    });
+                }
+            }
+            None => diff.removed.push(ColumnChange {
+                text: old.text.clone(),
+                from: Some(old.column_letter.clone()),
+                to: None,
+                field_key,
+            }),
+        }
+    }
+    for new in unmatched {
+        diff.added.push(ColumnChange {
+            text: new.text.clone(),
+            from: None,
+            to: Some(new.column_letter.clone()),
+            field_key: None,
+        });
+    }
+    Some(diff)
+}
+
+/// Whether appending with the stored schema would put a mapped field in the wrong column or drop it.
+/// Added columns and moves of unmapped ones do not: the stored letters of mapped columns still hold.
+pub fn breaks_mapping(diff: &SchemaDiff) -> bool {
+    diff.moved.iter().chain(&diff.removed).any(|c| c.field_key.is_some())
+}
+
+/// The error an append returns for `diff`.
+pub fn changed_error(sheet_name: &str, diff: SchemaDiff) -> AppError {
+    let mut columns: Vec<String> = diff
+        .moved
+        .iter()
+        .chain(&diff.removed)
+        .filter(|c| c.field_key.is_some())
+        .map(|c| match &c.to {
+            Some(to) => format!("'{}' {} → {}", c.text, c.from.as_deref().unwrap_or("?"), to),
+            None => format!("'{}' ({}) removed", c.text, c.from.as_deref().unwrap_or("?")),
+        })
+        .collect();
+    if columns.len() > 5 {
+        let more = columns.len() - 4;
+        columns.truncate(4);
+        columns.push(format!("{} more", more));
+    }
+    AppError::SchemaChanged(
+        format!(
+            "The columns of sheet '{}' changed since the profile was mapped: {}. Remap the profile's columns before appending.",
+            sheet_name,
+            columns.join(", ")
+        ),
+        Box::new(diff),
+    )
+}
+
+/// The profile's column mapping moved onto `current` headers: each column gets the field of the stored
+/// column with the same header text.
+pub fn remap(
+    stored: &[HeaderInfo],
+    current: &[HeaderInfo],
+    column_mapping: &HashMap<String, String>,
+) -> HashMap<String, String> {
+    sheet_routes::map_columns(stored, current, column_mapping)
+}
+
+/// Fields of `old` that `new` no longer maps to any column.
+pub fn lost_fields(old: &HashMap<String, String>, new: &HashMap<String, String>) -> Vec<String> {
+    let mut lost: Vec<String> = old
+        .values()
+        .filter(|field| !field.is_empty() && !new.values().any(|f| f == *field))
+        .cloned()
+        .collect();
+    lost.sort();
+    lost.dedup();
+    lost
+}
+
@@ -0,0 +1,139 @@
+//! Secrets kept in the OS credential store: Windows Credential Manager (generic credentials of the
+//! current user) or the macOS login Keychain. Other platforms report `UNSUPPORTED` so callers can fall
+//! back to the encrypted settings table.
+
+/// Target / service name prefix of every credential written by the app.
+const SERVICE: &str = "DocumentScannerDesktop";
+
+/// Credential name of the Azure OCR subscription key.
+pub const AZURE_OCR_KEY: &str = "azure_ocr_key";
+
+pub const UNSUPPORTED: &str = "The OS credential store is not available on this system.";
+
+/// Store or replace a secret.
+pub fn set_secret(name: &str, value: &str) -> Result<(), String> {
+    platform::set(&format!("{}/{}", SERVICE, name), value)
+}
+
+/// Read a secret; Ok(None) when it was never stored.
+pub fn get_secret(name: &str) -> Result<Option<String>, String> {
+    platform::get(&format!("{}/{}", SERVICE, name))
+}
+
+/// Remove a secret; removing a missing secret is not an error.
+pub fn delete_secret(name: &str) -> Result<(), String> {
+    platform::delete(&format!("{}/{}", SERVICE, name))
+}
+
+/// Whether the OS credential store can be used on this system.
+pub fn is_supported() -> bool {
+    cfg!(any(windows, target_os = "macos"))
+}
+
+#[cfg(windows)]
+mod platform {
+    use windows_sys::Win32::Foundation::ERROR_NOT_FOUND;
+    use windows_sys::Win32::Security::Credentials::{
+        CredDeleteW, CredFree, CredReadW, CredWriteW, CREDENTIALW, CRED_PERSIST_LOCAL_MACHINE, CRED_TYPE_GENERIC,
+    };
+
+    fn wide(s: &str) -> Vec<u16> {
+        s.encode_utf16().chain(std::iter::once(0)).collect()
+    }
+
+    fn not_found() -> bool {
+        std::io::Error::last_os_error().raw_os_error() == Some(ERROR_NOT_FOUND as i32)
+    }
+
+    pub fn set(target: &str, value: &str) -> Result<(), String> {
+        let mut target_w = wide(target);
+        let mut user_w = wide(super::SERVICE);
+        let mut blob = value.as_bytes().to_vec();
+        // SAFETY: all pointers reference buffers that outlive the call; zeroed fields are optional.
+        let ok = unsafe {
+            let mut cred: CREDENTIALW = std::mem::zeroed();
+            cred.Type = CRED_TYPE_GENERIC;
+            cred.TargetName = target_w.as_mut_ptr();
+            cred.UserName = user_w.as_mut_ptr();
+            cred.CredentialBlobSize = blob.len() as u32;
+            cred.CredentialBlob = blob.as_mut_ptr();
+            cred.Persist = CRED_PERSIST_LOCAL_MACHINE;
+            CredWriteW(&cred, 0)
+        };
+        if ok == 0 {
+            return Err(format!("Could not save to Windows Credential Manager: {}", std::io::Error::last_os_error()));
+        }
+        Ok(())
+    }
+
+    pub fn get(target: &str) -> Result<Option<String>, String> {
+        let target_w = wide(target);
+        let mut cred: *mut CREDENTIALW = std::ptr::null_mut();
+        // SAFETY: CredReadW allocates `cred`, which is released with CredFree after copying the blob.
+        unsafe {
+            if CredReadW(target_w.as_ptr(), CRED_TYPE_GENERIC, 0, &mut cred) == 0 {
+                if not_found() {
+                    return Ok(None);
+                }
+                return Err(format!("Could not read from Windows Credential Manager: {}", std::io::Error::last_os_error()));
+            }
+            let blob = std::slice::from_raw_parts((*cred).CredentialBlob, (*cred).CredentialBlobSize as usize).to_vec();
+            CredFree(cred as *const _);
+            String::from_utf8(blob).map(Some).map_err(|e| e.to_string())
+        }
+    }
+
+    pub fn delete(target: &str) -> Result<(), String> {
+        let target_w = wide(target);
+        // SAFETY: target_w is a NUL-terminated UTF-16 string.
+        if unsafe { CredDeleteW(target_w.as_ptr(), CRED_TYPE_GENERIC, 0) } == 0 && !not_found() {
+            return Err(format!("Could not delete from Windows Credential Manager: {}", std::io::Error::last_os_error()));
+        }
+        Ok(())
+    }
+}
+
+/// Security.framework generic passwords (service = target, account = `SERVICE`), so the secret is never
+/// passed on a command line.
+#[cfg(target_os = "macos")]
+mod platform {
+    use security_framework::passwords::{delete_generic_password, get_generic_password, set_generic_password};
+
+    /// errSecItemNotFound
+    const ITEM_NOT_FOUND: i32 = -25300;
+
+    pub fn set(target: &str, value: &str) -> Result<(), String> {
+        set_generic_password(target, super::SERVICE, value.as_bytes())
+            .map_err(|e| format!("Could not save to Keychain: {}", e))
+    }
+
+    pub fn get(target: &str) -> Result<Option<String>, String> {
+        match get_generic_password(target, super::SERVICE) {
+            Ok(bytes) => String::from_utf8(bytes).map(Some).map_err(|e| e.to_string()),
+            Err(e) if e.code() == ITEM_NOT_FOUND => Ok(None),
+            Err(e) => Err(format!("Could not read from Keychain: {}", e)),
+        }
+    }
+
+    pub fn delete(target: &str) -> Result<(), String> {
+        match delete_generic_password(target, super::SERVICE) {
+            Err(e) if e.code() != ITEM_NOT_FOUND => Err(format!("Could not delete from Keychain: {}", e)),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+mod platform {
+    pub fn set(_target: &str, _value: &str) -> Result<(), String> {
+        Err(super::UNSUPPORTED.to_string())
+    }
+
+    pub fn get(_target: &str) -> Result<Option<String>, String> {
+        Ok(None)
+    }
+
+    pub fn delete(_target: &str) -> Result<(), String> {
+        Ok(())
+    }
+}
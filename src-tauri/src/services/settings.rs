@@ -0,0 +1,599 @@
+//! App settings persisted in the `settings` table: Azure endpoint/key, default export folder, OCR poll
+//! interval, Azure retry policy, language, the local PDF text layer preference, the operator name and the
+//! background worker budgets. OCR and export code
+//! read the decrypted values from an in-memory snapshot that is refreshed on startup and after every
+//! change; `.env` stays as a fallback for the Azure credentials.
+//! The Azure key lives in the OS credential store (see `secrets`); where that is unavailable it is kept
+//! in the table encrypted: DPAPI (current Windows user) on Windows, a local key file elsewhere.
+
+use crate::db::Db;
+use crate::services::app_warnings;
+use crate::services::last_row::LastRowStrategy;
+use crate::services::secrets;
+use crate::services::work_pool::WorkerBudgets;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{OnceLock, RwLock};
+use std::time::Duration;
+
+const AZURE_ENDPOINT: &str = "azure_endpoint";
+const AZURE_KEY: &str = "azure_key";
+const DEFAULT_EXPORT_FOLDER: &str = "default_export_folder";
+const POLL_INTERVAL_SECS: &str = "poll_interval_secs";
+const LANGUAGE: &str = "language";
+const RETRY_MAX_ATTEMPTS: &str = "azure_retry_max_attempts";
+const RETRY_BASE_DELAY_MS: &str = "azure_retry_base_delay_ms";
+const LAST_ROW_GAP_ROWS: &str = "last_row_gap_rows";
+const PREFER_LOCAL_TEXT_LAYER: &str = "prefer_local_text_layer";
+const OPERATOR_NAME: &str = "operator_name";
+const WORKER_BUDGETS: &str = "worker_budgets";
+
+pub const DEFAULT_POLL_INTERVAL_SECS: u32 = 1;
+const MAX_POLL_INTERVAL_SECS: u32 = 30;
+const LANGUAGES: &[&str] = &["mk", "en"];
+pub const DEFAULT_RETRY_MAX_ATTEMPTS: u32 = 4;
+const MAX_RETRY_ATTEMPTS: u32 = 10;
+pub const DEFAULT_RETRY_BASE_DELAY_MS: u32 = 1000;
+const MAX_RETRY_BASE_DELAY_MS: u32 = 30_000;
+const MAX_LAST_ROW_GAP_ROWS: u32 = 10_000;
+
+/// Settings as shown in the UI. The Azure key itself is never sent back.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AppSettings {
+    pub azure_endpoint: Option<String>,
+    pub azure_key_configured: bool,
+    pub default_export_folder: Option<String>,
+    pub poll_interval_secs: u32,
+    /// Tries per Azure request (1 = no retry).
+    pub retry_max_attempts: u32,
+    /// First backoff delay; doubles on every retry unless Azure sends Retry-After.
+    pub retry_base_delay_ms: u32,
+    pub language: Option<String>,
+    /// Last-row detection in ledgers: 0 searches the sheet's whole used range (default); N stops after
+    /// N consecutive empty rows below the header.
+    pub last_row_gap_rows: u32,
+    /// Read PDFs that have their own text layer locally instead of sending them to OCR.
+    pub prefer_local_text_layer: bool,
+    /// Who scans on this workstation, recorded on scan sessions (None = the OS login name).
+    pub operator_name: Option<String>,
+    /// Concurrent OCR calls, appends, preview renders and maintenance jobs.
+    pub worker_budgets: WorkerBudgets,
+}
+
+/// Changes from the settings screen: None leaves a value unchanged, an empty string clears it.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SettingsUpdate {
+    pub azure_endpoint: Option<String>,
+    pub azure_key: Option<String>,
+    pub default_export_folder: Option<String>,
+    pub poll_interval_secs: Option<u32>,
+    pub retry_max_attempts: Option<u32>,
+    pub retry_base_delay_ms: Option<u32>,
+    pub language: Option<String>,
+    pub last_row_gap_rows: Option<u32>,
+    pub prefer_local_text_layer: Option<bool>,
+    pub operator_name: Option<String>,
+    pub worker_budgets: Option<WorkerBudgets>,
+}
+
+/// Decrypted values read by OCR and export code.
+#[derive(Debug, Clone, Default)]
+struct RuntimeSettings {
+    azure_endpoint: Option<String>,
+    azure_key: Option<String>,
+    default_export_folder: Option<String>,
+    poll_interval_secs: Option<u32>,
+    retry_max_attempts: Option<u32>,
+    retry_base_delay_ms: Option<u32>,
+    last_row_gap_rows: Option<u32>,
+    prefer_local_text_layer: bool,
+    worker_budgets: WorkerBudgets,
+}
+
+static RUNTIME: OnceLock<RwLock<RuntimeSettings>> = OnceLock::new();
+
+fn runtime() -> &'static RwLock<RuntimeSettings> {
+    RUNTIME.get_or_init(|| RwLock::new(RuntimeSettings::default()))
+}
+
+fn non_empty(value: Option<&String>) -> Option<String> {
+    value.map(|v| v.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+/// Azure endpoint and key from settings, when both are set.
+pub fn azure_credentials() -> Option<(String, String)> {
+    let rt = runtime().read().ok()?;
+    match (&rt.azure_endpoint, &rt.azure_key) {
+        (Some(endpoint), Some(key)) => Some((endpoint.trim_end_matches('/').to_string(), key.clone())),
+        _ => None,
+    }
+}
+
+/// Delay between polls of an Azure analyze operation.
+pub fn poll_interval() -> Duration {
+    let secs = runtime()
+        .read()
+        .ok()
+        .and_then(|rt| rt.poll_interval_secs)
+        .unwrap_or(DEFAULT_POLL_INTERVAL_SECS);
+    Duration::from_secs(secs as u64)
+}
+
+/// (max attempts, base delay) for retrying throttled or failed Azure requests.
+pub fn azure_retry_policy() -> (u32, Duration) {
+    let rt = runtime().read().ok();
+    let attempts = rt
+        .as_ref()
+        .and_then(|rt| rt.retry_max_attempts)
+        .unwrap_or(DEFAULT_RETRY_MAX_ATTEMPTS);
+    let base_ms = rt
+        .as_ref()
+        .and_then(|rt| rt.retry_base_delay_ms)
+        .unwrap_or(DEFAULT_RETRY_BASE_DELAY_MS);
+    (attempts, Duration::from_millis(base_ms as u64))
+}
+
+/// How the last data row of a ledger sheet is found.
+pub fn last_row_strategy() -> LastRowStrategy {
+    match runtime().read().ok().and_then(|rt| rt.last_row_gap_rows) {
+        Some(gap) if gap > 0 => LastRowStrategy::GapLimit(gap),
+        _ => LastRowStrategy::Dimension,
+    }
+}
+
+/// True when PDFs with a text layer are read locally instead of by OCR.
+pub fn prefer_local_text_layer() -> bool {
+    runtime().read().map(|rt| rt.prefer_local_text_layer).unwrap_or(false)
+}
+
+/// Concurrency budgets of the background worker pool.
+pub fn worker_budgets() -> WorkerBudgets {
+    runtime().read().map(|rt| rt.worker_budgets).unwrap_or_default()
+}
+
+fn stored_worker_budgets(stored: &HashMap<String, String>) -> WorkerBudgets {
+    stored
+        .get(WORKER_BUDGETS)
+        .and_then(|v| serde_json::from_str(v).ok())
+        .unwrap_or_default()
+}
+
+/// Folder for new exports when the user did not pick a path.
+pub fn default_export_folder() -> Option<PathBuf> {
+    let rt = runtime().read().ok()?;
+    rt.default_export_folder.as_ref().map(PathBuf::from).filter(|p| p.is_dir())
+}
+
+/// Azure key from the OS credential store, else the encrypted settings value. A key that cannot be
+/// decrypted (e.g. database copied from another Windows user) is treated as not set.
+fn stored_azure_key(stored: &HashMap<String, String>, app_data_dir: &Path) -> Option<String> {
+    match secrets::get_secret(secrets::AZURE_OCR_KEY) {
+        Ok(Some(key)) if !key.trim().is_empty() => return Some(key.trim().to_string()),
+        Ok(_) => {}
+        Err(e) => app_warnings::report("settings", format!("could not read Azure key from the credential store: {}", e)),
+    }
+    let encrypted = non_empty(stored.get(AZURE_KEY))?;
+    decrypt_secret(app_data_dir, &encrypted)
+        .map_err(|e| app_warnings::report("settings", format!("could not decrypt Azure key: {}", e)))
+        .ok()
+}
+
+/// Move a plaintext AZURE_OCR_KEY (and its endpoint) from the app-data `.env` into the credential
+/// store, then drop the key line from the file. Does nothing when the store is unavailable.
+fn migrate_env_credentials(db: &Db, app_data_dir: &Path) -> Result<(), String> {
+    let env_path = app_data_dir.join(".env");
+    if !secrets::is_supported() || !env_path.is_file() {
+        return Ok(());
+    }
+    let content = std::fs::read_to_string(&env_path).map_err(|e| e.to_string())?;
+    let value_of = |name: &str| {
+        content.lines().find_map(|line| {
+            let (k, v) = line.split_once('=')?;
+            (k.trim() == name).then(|| v.trim().trim_matches('"').trim_matches('\'').to_string())
+        })
+    };
+    let Some(key) = value_of("AZURE_OCR_KEY").filter(|k| !k.is_empty()) else {
+        return Ok(());
+    };
+    if secrets::get_secret(secrets::AZURE_OCR_KEY)?.is_none() {
+        secrets::set_secret(secrets::AZURE_OCR_KEY, &key)?;
+    }
+    let stored = db.get_settings()?;
+    if let (None, Some(endpoint)) = (non_empty(stored.get(AZURE_ENDPOINT)), value_of("AZURE_OCR_ENDPOINT")) {
+        db.set_settings(&[(AZURE_ENDPOINT, Some(endpoint.trim_end_matches('/').to_string()))])?;
+    }
+    let kept: Vec<&str> = content
+        .lines()
+        .filter(|line| line.split_once('=').map(|(k, _)| k.trim()) != Some("AZURE_OCR_KEY"))
+        .collect();
+    std::fs::write(&env_path, kept.join("\n") + "\n").map_err(|e| e.to_string())
+}
+
+/// Reload the in-memory snapshot from the database and the credential store.
+pub fn load(db: &Db, app_data_dir: &Path) -> Result<(), String> {
+    if let Err(e) = migrate_env_credentials(db, app_data_dir) {
+        app_warnings::report("settings", format!("could not move the .env Azure key to the credential store: {}", e));
+    }
+    let stored = db.get_settings()?;
+    let azure_key = stored_azure_key(&stored, app_data_dir);
+    let next = RuntimeSettings {
+        azure_endpoint: non_empty(stored.get(AZURE_ENDPOINT)),
+        azure_key,
+        default_export_folder: non_empty(stored.get(DEFAULT_EXPORT_FOLDER)),
+        poll_interval_secs: stored.get(POLL_INTERVAL_SECS).and_then(|v| v.parse().ok()),
+        retry_max_attempts: stored.get(RETRY_MAX_ATTEMPTS).and_then(|v| v.parse().ok()),
+        retry_base_delay_ms: stored.get(RETRY_BASE_DELAY_MS).and_then(|v| v.parse().ok()),
+        last_row_gap_rows: stored.get(LAST_ROW_GAP_ROWS).and_then(|v| v.parse().ok()),
+        prefer_local_text_layer: stored.get(PREFER_LOCAL_TEXT_LAYER).is_some_and(|v| v == "1"),
+        worker_budgets: stored_worker_budgets(&stored),
+    };
+    *runtime().write().map_err(|e| e.to_string())? = next;
+    Ok(())
+}
+
+/// Current settings for the settings screen.
+pub fn get_settings(db: &Db) -> Result<AppSettings, String> {
+    let stored: HashMap<String, String> = db.get_settings()?;
+    let key_in_store = secrets::get_secret(secrets::AZURE_OCR_KEY).ok().flatten().is_some();
+    Ok(AppSettings {
+        azure_endpoint: non_empty(stored.get(AZURE_ENDPOINT)),
+        azure_key_configured: key_in_store || non_empty(stored.get(AZURE_KEY)).is_some(),
+        default_export_folder: non_empty(stored.get(DEFAULT_EXPORT_FOLDER)),
+        poll_interval_secs: stored
+            .get(POLL_INTERVAL_SECS)
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_POLL_INTERVAL_SECS),
+        retry_max_attempts: stored
+            .get(RETRY_MAX_ATTEMPTS)
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_RETRY_MAX_ATTEMPTS),
+        retry_base_delay_ms: stored
+            .get(RETRY_BASE_DELAY_MS)
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_RETRY_BASE_DELAY_MS),
+        language: non_empty(stored.get(LANGUAGE)),
+        last_row_gap_rows: stored.get(LAST_ROW_GAP_ROWS).and_then(|v| v.parse().ok()).unwrap_or(0),
+        prefer_local_text_layer: stored.get(PREFER_LOCAL_TEXT_LAYER).is_some_and(|v| v == "1"),
+        operator_name: non_empty(stored.get(OPERATOR_NAME)),
+        worker_budgets: stored_worker_budgets(&stored),
+    })
+}
+
+/// Validate and store `update`, then refresh the in-memory snapshot.
+pub fn save_settings(db: &Db, app_data_dir: &Path, update: SettingsUpdate) -> Result<AppSettings, String> {
+    let mut entries: Vec<(&str, Option<String>)> = Vec::new();
+    let cleared = |v: String| Some(v.trim().to_string()).filter(|v| !v.is_empty());
+    if let Some(endpoint) = update.azure_endpoint {
+        let endpoint = cleared(endpoint);
+        if let Some(e) = &endpoint {
+            if !e.starts_with("https://") {
+                return Err("Azure endpoint must start with https://".to_string());
+            }
+        }
+        entries.push((AZURE_ENDPOINT, endpoint.map(|e| e.trim_end_matches('/').to_string())));
+    }
+    if let Some(key) = update.azure_key {
+        let key = cleared(key);
+        if secrets::is_supported() {
+            match &key {
+                Some(key) => secrets::set_secret(secrets::AZURE_OCR_KEY, key)?,
+                None => secrets::delete_secret(secrets::AZURE_OCR_KEY)?,
+            }
+            // Drop any copy kept in the table before the credential store was used.
+            entries.push((AZURE_KEY, None));
+        } else {
+            let encrypted = match key {
+                Some(key) => Some(encrypt_secret(app_data_dir, &key)?),
+                None => None,
+            };
+            entries.push((AZURE_KEY, encrypted));
+        }
+    }
+    if let Some(folder) = update.default_export_folder {
+        let folder = cleared(folder);
+        if let Some(f) = &folder {
+            if !Path::new(f).is_dir() {
+                return Err(format!("Export folder does not exist: {}", f));
+            }
+        }
+        entries.push((DEFAULT_EXPORT_FOLDER, folder));
+    }
+    if let Some(secs) = update.poll_interval_secs {
+        if !(1..=MAX_POLL_INTERVAL_SECS).contains(&secs) {
+            return Err(format!("Polling interval must be between 1 and {} seconds.", MAX_POLL_INTERVAL_SECS));
+        }
+        entries.push((POLL_INTERVAL_SECS, Some(secs.to_string())));
+    }
+    if let Some(attempts) = update.retry_max_attempts {
+        if !(1..=MAX_RETRY_ATTEMPTS).contains(&attempts) {
+            return Err(format!("Retry attempts must be between 1 and {}.", MAX_RETRY_ATTEMPTS));
+        }
+        entries.push((RETRY_MAX_ATTEMPTS, Some(attempts.to_string())));
+    }
+    if let Some(ms) = update.retry_base_delay_ms {
+        if !(100..=MAX_RETRY_BASE_DELAY_MS).contains(&ms) {
+            return Err(format!("Retry delay must be between 100 and {} ms.", MAX_RETRY_BASE_DELAY_MS));
+        }
+        entries.push((RETRY_BASE_DELAY_MS, Some(ms.to_string())));
+    }
+    if let Some(language) = update.language {
+        let language = cleared(language);
+        if let Some(l) = &language {
+            if !LANGUAGES.contains(&l.as_str()) {
+                return Err(format!("Unsupported language: {}", l));
+            }
+        }
+        entries.push((LANGUAGE, language));
+    }
+    if let Some(gap) = update.last_row_gap_rows {
+        if gap > MAX_LAST_ROW_GAP_ROWS {
+            return Err(format!("Empty-row gap must be at most {} rows (0 searches the whole sheet).", MAX_LAST_ROW_GAP_ROWS));
+        }
+        entries.push((LAST_ROW_GAP_ROWS, (gap > 0).then(|| gap.to_string())));
+    }
+    if let Some(prefer) = update.prefer_local_text_layer {
+        entries.push((PREFER_LOCAL_TEXT_LAYER, prefer.then(|| "1".to_string())));
+    }
+    if let Some(name) = update.operator_name {
+        entries.push((OPERATOR_NAME, cleared(name)));
+    }
+    if let Some(budgets) = update.worker_budgets {
+        budgets.validate()?;
+        let value = (budgets != WorkerBudgets::default())
+            .then(|| serde_json::to_string(&budgets))
+            .transpose()
+            .map_err(|e| e.to_string())?;
+        entries.push((WORKER_BUDGETS, value));
+    }
+    db.set_settings(&entries)?;
+    load(db, app_data_dir)?;
+    get_settings(db)
+}
+
+const DPAPI_PREFIX: &str = "dpapi:";
+const LOCAL_KEY_PREFIX: &str = "xchacha:";
+
+/// Encrypt a secret for the settings table; the result is a prefixed base64 string.
+pub(crate) fn encrypt_secret(app_data_dir: &Path, plain: &str) -> Result<String, String> {
+    #[cfg(windows)]
+    {
+        let _ = app_data_dir;
+        Ok(format!("{}{}", DPAPI_PREFIX, BASE64.encode(dpapi::protect(plain.as_bytes())?)))
+    }
+    #[cfg(not(windows))]
+    {
+        Ok(format!("{}{}", LOCAL_KEY_PREFIX, BASE64.encode(local_key::protect(app_data_dir, plain.as_bytes())?)))
+    }
+}
+
+fn is_encrypted(value: &str) -> bool {
+    value.starts_with(DPAPI_PREFIX) || value.starts_with(LOCAL_KEY_PREFIX)
+}
+
+/// Secrets of this installation in plain text, carried inside the password-protected app state archive
+/// because the encrypted values and the credential store only work on the machine that wrote them.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PortableSecrets {
+    /// Encrypted settings values, decrypted, by settings key.
+    pub settings: BTreeMap<String, String>,
+    /// Credential store entries by name.
+    pub credential_store: BTreeMap<String, String>,
+    /// Settings keys and credential names that could not be read; they have to be entered again.
+    #[serde(skip)]
+    pub unreadable: Vec<String>,
+}
+
+/// Decrypt every encrypted settings value and read the credential store entries `credential_names`.
+pub fn export_secrets(db: &Db, app_data_dir: &Path, credential_names: &[String]) -> Result<PortableSecrets, String> {
+    let mut secrets = PortableSecrets::default();
+    for (key, value) in db.get_settings()? {
+        if !is_encrypted(&value) {
+            continue;
+        }
+        match decrypt_secret(app_data_dir, &value) {
+            Ok(plain) => {
+                secrets.settings.insert(key, plain);
+            }
+            Err(_) => secrets.unreadable.push(key),
+        }
+    }
+    if secrets::is_supported() {
+        for name in credential_names {
+            match secrets::get_secret(name) {
+                Ok(Some(value)) => {
+                    secrets.credential_store.insert(name.clone(), value);
+                }
+                Ok(None) => {}
+                Err(_) => secrets.unreadable.push(name.clone()),
+            }
+        }
+    }
+    Ok(secrets)
+}
+
+/// Protect secrets from another installation for this one: settings values are encrypted again into
+/// `db`, credential store entries are written to the store (the Azure key falls back to the table).
+/// Returns the settings keys and credential names that still cannot be used and must be entered again.
+pub fn import_secrets(db: &Db, app_data_dir: &Path, secrets: &PortableSecrets) -> Result<Vec<String>, String> {
+    let mut entries: Vec<(String, Option<String>)> = Vec::new();
+    for (key, plain) in &secrets.settings {
+        entries.push((key.clone(), Some(encrypt_secret(app_data_dir, plain)?)));
+    }
+    let mut reenter = Vec::new();
+    for (name, value) in &secrets.credential_store {
+        if secrets::is_supported() {
+            if secrets::set_secret(name, value).is_err() {
+                reenter.push(name.clone());
+            }
+        } else if name == secrets::AZURE_OCR_KEY {
+            entries.push((AZURE_KEY.to_string(), Some(encrypt_secret(app_data_dir, value)?)));
+        } else {
+            reenter.push(name.clone());
+        }
+    }
+    let entries: Vec<(&str, Option<String>)> = entries.iter().map(|(k, v)| (k.as_str(), v.clone())).collect();
+    db.set_settings(&entries)?;
+    for (key, value) in db.get_settings()? {
+        if is_encrypted(&value) && decrypt_secret(app_data_dir, &value).is_err() {
+            reenter.push(key);
+        }
+    }
+    reenter.sort();
+    reenter.dedup();
+    Ok(reenter)
+}
+
+pub(crate) fn decrypt_secret(app_data_dir: &Path, stored: &str) -> Result<String, String> {
+    let decode = |b64: &str| BASE64.decode(b64).map_err(|e| format!("Invalid encrypted value: {}", e));
+    let plain = if let Some(b64) = stored.strip_prefix(DPAPI_PREFIX) {
+        #[cfg(windows)]
+        {
+            let _ = app_data_dir;
+            dpapi::unprotect(&decode(b64)?)?
+        }
+        #[cfg(not(windows))]
+        {
+            let _ = b64;
+            return Err("Value was encrypted on Windows and cannot be read here.".to_string());
+        }
+    } else if let Some(b64) = stored.strip_prefix(LOCAL_KEY_PREFIX) {
+        #[cfg(windows)]
+        {
+            let _ = b64;
+            return Err("Value was encrypted on another system and cannot be read here.".to_string());
+        }
+        #[cfg(not(windows))]
+        {
+            local_key::unprotect(app_data_dir, &decode(b64)?)?
+        }
+    } else {
+        return Err("Unknown encryption format.".to_string());
+    };
+    String::from_utf8(plain).map_err(|e| e.to_string())
+}
+
+#[cfg(windows)]
+mod dpapi {
+    use windows_sys::Win32::Foundation::LocalFree;
+    use windows_sys::Win32::Security::Cryptography::{
+        CryptProtectData, CryptUnprotectData, CRYPTPROTECT_UI_FORBIDDEN, CRYPT_INTEGER_BLOB,
+    };
+
+    fn run(data: &[u8], protect: bool) -> Result<Vec<u8>, String> {
+        let input = CRYPT_INTEGER_BLOB {
+            cbData: data.len() as u32,
+            pbData: data.as_ptr() as *mut u8,
+        };
+        let mut output = CRYPT_INTEGER_BLOB {
+            cbData: 0,
+            pbData: std::ptr::null_mut(),
+        };
+        // SAFETY: input points to `data` for the duration of the call; output is allocated by DPAPI
+        // and released with LocalFree after copying.
+        let ok = unsafe {
+            if protect {
+                CryptProtectData(
+                    &input,
+                    std::ptr::null(),
+                    std::ptr::null(),
+                    std::ptr::null(),
+                    std::ptr::null(),
+                    CRYPTPROTECT_UI_FORBIDDEN,
+                    &mut output,
+                )
+            } else {
+                CryptUnprotectData(
+                    &input,
+                    std::ptr::null_mut(),
+                    std::ptr::null(),
+                    std::ptr::null(),
+                    std::ptr::null(),
+                    CRYPTPROTECT_UI_FORBIDDEN,
+                    &mut output,
+                )
+            }
+        };
+        if ok == 0 || output.pbData.is_null() {
+            return Err(format!(
+                "Windows could not {} the value: {}",
+                if protect { "encrypt" } else { "decrypt" },
+                std::io::Error::last_os_error()
+            ));
+        }
+        let bytes = unsafe { std::slice::from_raw_parts(output.pbData, output.cbData as usize) }.to_vec();
+        unsafe { LocalFree(output.pbData as _) };
+        Ok(bytes)
+    }
+
+    pub fn protect(data: &[u8]) -> Result<Vec<u8>, String> {
+        run(data, true)
+    }
+
+    pub fn unprotect(data: &[u8]) -> Result<Vec<u8>, String> {
+        run(data, false)
+    }
+}
+
+/// XChaCha20-Poly1305 with a random key kept in `settings.key` (owner-only) in app data.
+#[cfg(not(windows))]
+mod local_key {
+    use chacha20poly1305::aead::rand_core::RngCore;
+    use chacha20poly1305::aead::{Aead, KeyInit, OsRng};
+    use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+    use std::path::Path;
+
+    const KEY_FILE: &str = "settings.key";
+    const KEY_LEN: usize = 32;
+    const NONCE_LEN: usize = 24;
+
+    fn load_or_create_key(app_data_dir: &Path) -> Result<[u8; KEY_LEN], String> {
+        let path = app_data_dir.join(KEY_FILE);
+        if let Ok(bytes) = std::fs::read(&path) {
+            return bytes.try_into().map_err(|_| format!("{} is corrupted", path.display()));
+        }
+        let mut key = [0u8; KEY_LEN];
+        OsRng.fill_bytes(&mut key);
+        std::fs::create_dir_all(app_data_dir).map_err(|e| e.to_string())?;
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options
+            .open(&path)
+            .map_err(|e| format!("Could not create {}: {}", path.display(), e))?;
+        std::io::Write::write_all(&mut file, &key).map_err(|e| e.to_string())?;
+        Ok(key)
+    }
+
+    fn cipher(app_data_dir: &Path) -> Result<XChaCha20Poly1305, String> {
+        let key = load_or_create_key(app_data_dir)?;
+        XChaCha20Poly1305::new_from_slice(&key).map_err(|e| e.to_string())
+    }
+
+    /// nonce || ciphertext with tag
+    pub fn protect(app_data_dir: &Path, data: &[u8]) -> Result<Vec<u8>, String> {
+        let mut nonce = [0u8; NONCE_LEN];
+        OsRng.fill_bytes(&mut nonce);
+        let sealed = cipher(app_data_dir)?
+            .encrypt(XNonce::from_slice(&nonce), data)
+            .map_err(|_| "Could not encrypt the value.".to_string())?;
+        let mut out = nonce.to_vec();
+        out.extend_from_slice(&sealed);
+        Ok(out)
+    }
+
+    pub fn unprotect(app_data_dir: &Path, blob: &[u8]) -> Result<Vec<u8>, String> {
+        if blob.len() < NONCE_LEN {
+            return Err("Encrypted value is truncated.".to_string());
+        }
+        let (nonce, sealed) = blob.split_at(NONCE_LEN);
+        cipher(app_data_dir)?
+            .decrypt(XNonce::from_slice(nonce), sealed)
+            .map_err(|_| "Encrypted value does not match this machine's key.".to_string())
+    }
+}
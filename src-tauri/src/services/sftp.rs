@@ -0,0 +1,155 @@
+//! SFTP uploads for export delivery to ERP drop folders. Authentication is by private key only (kept in
+//! the OS credential store); the server's host key is pinned on first connection and checked afterwards.
+
+use base64::{engine::general_purpose::STANDARD_NO_PAD as BASE64, Engine};
+use sha2::{Digest, Sha256};
+use ssh2::{HashType, Session, Sftp};
+use std::fs;
+use std::io;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::time::Duration;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+const SESSION_TIMEOUT_MS: u32 = 60_000;
+
+#[derive(Debug, Clone)]
+pub struct SftpTarget {
+    pub host: String,
+    pub port: u16,
+    pub username: String,
+    /// Remote folder, e.g. /incoming/invoices
+    pub directory: String,
+    /// Pinned host key ("SHA256:..."); None until the first successful connection.
+    pub host_fingerprint: Option<String>,
+    /// PEM/OpenSSH private key.
+    pub private_key: String,
+    pub passphrase: Option<String>,
+}
+
+/// Failure of an SFTP operation; `retryable` is false for authentication and host key problems.
+#[derive(Debug)]
+pub struct SftpError {
+    pub message: String,
+    pub retryable: bool,
+}
+
+impl SftpError {
+    fn transient(message: String) -> Self {
+        SftpError { message, retryable: true }
+    }
+
+    fn permanent(message: String) -> Self {
+        SftpError { message, retryable: false }
+    }
+}
+
+impl SftpTarget {
+    pub fn url(&self, file_name: &str) -> String {
+        format!("sftp://{}@{}:{}{}", self.username, self.host, self.port, self.remote_path(file_name))
+    }
+
+    fn remote_path(&self, file_name: &str) -> String {
+        format!("{}/{}", self.directory.trim_end_matches('/'), file_name)
+    }
+}
+
+/// Open an authenticated session; returns it with the server's host key fingerprint.
+fn connect(target: &SftpTarget) -> Result<(Session, String), SftpError> {
+    let addr = (target.host.as_str(), target.port)
+        .to_socket_addrs()
+        .map_err(|e| SftpError::transient(format!("Could not resolve {}: {}", target.host, e)))?
+        .next()
+        .ok_or_else(|| SftpError::transient(format!("Could not resolve {}", target.host)))?;
+    let tcp = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)
+        .map_err(|e| SftpError::transient(format!("Could not connect to {}: {}", target.host, e)))?;
+    let mut session = Session::new().map_err(|e| SftpError::transient(e.to_string()))?;
+    session.set_tcp_stream(tcp);
+    session.set_timeout(SESSION_TIMEOUT_MS);
+    session
+        .handshake()
+        .map_err(|e| SftpError::transient(format!("SSH handshake failed: {}", e)))?;
+
+    let fingerprint = session
+        .host_key_hash(HashType::Sha256)
+        .map(|hash| format!("SHA256:{}", BASE64.encode(hash)))
+        .ok_or_else(|| SftpError::permanent("The server did not present a host key.".to_string()))?;
+    if let Some(pinned) = &target.host_fingerprint {
+        if *pinned != fingerprint {
+            return Err(SftpError::permanent(format!(
+                "Host key of {} changed (expected {}, got {}). Confirm with the server administrator, then save the destination again.",
+                target.host, pinned, fingerprint
+            )));
+        }
+    }
+
+    session
+        .userauth_pubkey_memory(&target.username, None, &target.private_key, target.passphrase.as_deref())
+        .map_err(|e| SftpError::permanent(format!("SFTP login failed: {}", e)))?;
+    if !session.authenticated() {
+        return Err(SftpError::permanent("SFTP login failed.".to_string()));
+    }
+    Ok((session, fingerprint))
+}
+
+fn open_sftp(session: &Session) -> Result<Sftp, SftpError> {
+    session
+        .sftp()
+        .map_err(|e| SftpError::transient(format!("Could not start SFTP: {}", e)))
+}
+
+/// Log in and check that the remote folder exists. Returns the host key fingerprint.
+pub fn check(target: &SftpTarget) -> Result<String, SftpError> {
+    let (session, fingerprint) = connect(target)?;
+    let sftp = open_sftp(&session)?;
+    let stat = sftp
+        .stat(Path::new(&target.directory))
+        .map_err(|e| SftpError::permanent(format!("Remote folder {} is not accessible: {}", target.directory, e)))?;
+    if !stat.is_dir() {
+        return Err(SftpError::permanent(format!("{} is not a folder.", target.directory)));
+    }
+    Ok(fingerprint)
+}
+
+/// Upload `src` as `file_name` under a `.partial` name, read it back to compare SHA-256 with
+/// `expected_sha256`, then move it into place. Returns the host key fingerprint.
+pub fn upload_verified(
+    target: &SftpTarget,
+    src: &Path,
+    file_name: &str,
+    expected_sha256: &str,
+) -> Result<String, SftpError> {
+    let (session, fingerprint) = connect(target)?;
+    let sftp = open_sftp(&session)?;
+    let dest = target.remote_path(file_name);
+    let partial = format!("{}.partial", dest);
+    let io_err = |what: &str, e: &dyn std::fmt::Display| SftpError::transient(format!("{}: {}", what, e));
+
+    let upload = || -> Result<(), SftpError> {
+        let mut local = fs::File::open(src).map_err(|e| SftpError::permanent(format!("Could not read {}: {}", src.display(), e)))?;
+        let mut remote = sftp.create(Path::new(&partial)).map_err(|e| io_err("Could not create the remote file", &e))?;
+        io::copy(&mut local, &mut remote).map_err(|e| io_err("Upload failed", &e))?;
+        drop(remote);
+
+        let mut remote = sftp.open(Path::new(&partial)).map_err(|e| io_err("Could not read back the upload", &e))?;
+        let mut hasher = Sha256::new();
+        io::copy(&mut remote, &mut hasher).map_err(|e| io_err("Could not read back the upload", &e))?;
+        let actual: String = hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect();
+        if actual != expected_sha256 {
+            return Err(SftpError::transient(
+                "Checksum mismatch: the uploaded copy differs from the export.".to_string(),
+            ));
+        }
+        // SFTP v3 servers refuse to rename over an existing file.
+        if sftp.stat(Path::new(&dest)).is_ok() {
+            sftp.unlink(Path::new(&dest)).map_err(|e| io_err("Could not replace the previous copy", &e))?;
+        }
+        sftp.rename(Path::new(&partial), Path::new(&dest), None)
+            .map_err(|e| io_err("Could not move the upload into place", &e))
+    };
+    if let Err(e) = upload() {
+        let _ = sftp.unlink(Path::new(&partial));
+        return Err(e);
+    }
+    Ok(fingerprint)
+}
@@ -0,0 +1,115 @@
+//! Per-profile routing of document types to worksheets. Many clients keep invoices, tax balances, payroll
+//! and VAT returns on separate sheets of one workbook, so a profile can map a document type key (see
+//! `document_types`) to a sheet and single-row appends of that type go there instead of the profile's
+//! sheet. A sheet that does not exist yet is created with the profile sheet's headers. Kept in settings
+//! (`sheet_routes.<profile id>`).
+
+use crate::db::Db;
+use crate::models::HeaderInfo;
+use crate::services::document_types;
+use std::collections::{BTreeMap, HashMap};
+
+const ROUTES_KEY: &str = "sheet_routes";
+
+/// Document type key -> worksheet name.
+pub type SheetRoutes = BTreeMap<String, String>;
+
+fn routes_key(profile_id: i64) -> String {
+    format!("{}.{}", ROUTES_KEY, profile_id)
+}
+
+pub fn get(db: &Db, profile_id: i64) -> Result<SheetRoutes, String> {
+    Ok(db
+        .get_settings()?
+        .get(&routes_key(profile_id))
+        .and_then(|v| serde_json::from_str(v).ok())
+        .unwrap_or_default())
+}
+
+/// Store the profile's routes. Entries without a sheet or pointing at the profile's own sheet are dropped;
+/// unknown document types and names Excel does not allow are refused.
+pub fn set(db: &Db, profile_id: i64, routes: SheetRoutes) -> Result<SheetRoutes, String> {
+    let (_, profile_sheet, _) = db.get_profile_by_id(profile_id)?;
+    let known = document_types::registered_keys();
+    let mut cleaned = SheetRoutes::new();
+    for (document_type, sheet) in routes {
+        let (document_type, sheet) = (document_type.trim().to_string(), sheet.trim().to_string());
+        if sheet.is_empty() || sheet == profile_sheet {
+            continue;
+        }
+        if !known.contains(&document_type) {
+            return Err(format!("Unknown document type '{}'.", document_type));
+        }
+        check_sheet_name(&sheet)?;
+        cleaned.insert(document_type, sheet);
+    }
+    let value = if cleaned.is_empty() {
+        None
+    } else {
+        Some(serde_json::to_string(&cleaned).map_err(|e| e.to_string())?)
+    };
+    db.set_settings(&[(routes_key(profile_id).as_str(), value)])?;
+    get(db, profile_id)
+}
+
+/// Sheet for a document of `document_type`, when the profile routes that type elsewhere.
+pub fn sheet_for(db: &Db, profile_id: i64, document_type: Option<&str>) -> Result<Option<String>, String> {
+    let Some(document_type) = document_type else {
+        return Ok(None);
+    };
+    Ok(get(db, profile_id)?.remove(document_type))
+}
+
+/// Excel's sheet name rules: 1-31 characters, none of `: \ / ? * [ ]`, no leading or trailing apostrophe.
+fn check_sheet_name(name: &str) -> Result<(), String> {
+    if name.chars().count() > 31 {
+        return Err(format!("Sheet name '{}' is longer than 31 characters.", name));
+    }
+    if name.contains([':', '\\', '/', '?', '*', '[', ']']) || name.starts_with('\'') || name.ends_with('\'') {
+        return Err(format!("Sheet name '{}' contains characters Excel does not allow.", name));
+    }
+    Ok(())
+}
+
+/// The workbook's sheet for a route: the exact name, else the only one that differs in case or surrounding
+/// spaces. None means the sheet has to be created.
+pub fn existing_sheet(available: &[String], wanted: &str) -> Option<String> {
+    if let Some(exact) = available.iter().find(|s| s.as_str() == wanted) {
+        return Some(exact.clone());
+    }
+    let wanted = wanted.trim().to_lowercase();
+    let mut matches = available.iter().filter(|s| s.trim().to_lowercase() == wanted);
+    match (matches.next(), matches.next()) {
+        (Some(only), None) => Some(only.clone()),
+        _ => None,
+    }
+}
+
+/// The profile's column mapping (column letter -> field key) moved onto a routed sheet's headers: each
+/// routed column gets the field of the profile column with the same header text.
+pub fn map_columns(
+    profile_headers: &[HeaderInfo],
+    sheet_headers: &[HeaderInfo],
+    column_mapping: &HashMap<String, String>,
+) -> HashMap<String, String> {
+    let normalize = |text: &str| text.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+    let mut mapped = HashMap::new();
+    for header in sheet_headers {
+        let text = normalize(&header.text);
+        if text.is_empty() {
+            continue;
+        }
+        let field = profile_headers
+            .iter()
+            .find(|p| normalize(&p.text) == text)
+            .and_then(|p| {
+                column_mapping
+                    .get(&p.column_letter)
+                    .or_else(|| column_mapping.get(&p.column_letter.to_uppercase()))
+            });
+        if let Some(field) = field {
+            mapped.insert(header.column_letter.clone(), field.clone());
+        }
+    }
+    mapped
+}
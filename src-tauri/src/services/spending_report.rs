@@ -0,0 +1,215 @@
+//! Spending per category for a month, with month-over-month deltas and a short trend for dashboards.
+
+use chrono::{Datelike, NaiveDate};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Months of history (including the requested one) returned in `monthly_totals`.
+const TREND_MONTHS: usize = 6;
+
+/// (year, month)
+type Month = (i32, u32);
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MonthTotal {
+    /// "YYYY-MM"
+    pub month: String,
+    pub total: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CategorySpending {
+    /// None for documents without a category.
+    pub category_id: Option<i64>,
+    pub category_name: String,
+    pub total: f64,
+    pub invoice_count: u32,
+    pub previous_total: f64,
+    pub delta: f64,
+    /// Percent change vs previous month; None when the previous month had no spending.
+    pub delta_percent: Option<f64>,
+    pub monthly_budget: Option<f64>,
+    /// Oldest first, ending with the requested month.
+    pub monthly_totals: Vec<MonthTotal>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpendingReport {
+    pub period: String,
+    pub previous_period: String,
+    pub categories: Vec<CategorySpending>,
+    pub total: f64,
+    pub previous_total: f64,
+    /// Documents in the period without a parseable total_amount (not included in totals).
+    pub skipped_without_amount: u32,
+}
+
+/// Parse "YYYY-MM" (also accepts "MM/YYYY" and "MM.YYYY") into (year, month).
+fn parse_period(period: &str) -> Option<Month> {
+    let s = period.trim();
+    let (year, month) = if let Some((y, m)) = s.split_once('-') {
+        (y, m)
+    } else if let Some((m, y)) = s.split_once('/').or_else(|| s.split_once('.')) {
+        (y, m)
+    } else {
+        return None;
+    };
+    let year = year.trim().parse::<i32>().ok()?;
+    let month = month.trim().parse::<u32>().ok()?;
+    ((1..=12).contains(&month) && year > 1900).then_some((year, month))
+}
+
+fn shift_month((year, month): Month, back: u32) -> Month {
+    let index = year * 12 + month as i32 - 1 - back as i32;
+    (index.div_euclid(12), index.rem_euclid(12) as u32 + 1)
+}
+
+fn month_label((year, month): Month) -> String {
+    format!("{:04}-{:02}", year, month)
+}
+
+/// Invoice date as extracted by OCR ("15.03.2024", "15/03/2024", "2024-03-15", "15. 03. 2024 г.",
+/// "2024-03-15T00:00:00", ...). A trailing time or word after the date is ignored.
+pub(crate) fn parse_invoice_date(value: &str) -> Option<NaiveDate> {
+    // Join "15. 03. 2024" back into one token; stop at the first word not attached to a separator.
+    let mut joined = String::new();
+    for part in value.split_whitespace() {
+        let attached = joined.ends_with(['.', '/', '-']) || part.starts_with(['.', '/', '-']);
+        let complete = joined.split(['.', '/', '-']).filter(|g| !g.is_empty()).count() >= 3;
+        if !joined.is_empty() && (!attached || complete) {
+            break;
+        }
+        joined.push_str(part);
+    }
+    let s = joined.split('T').next().unwrap_or("");
+    let s = s.trim_end_matches(|c: char| !c.is_ascii_digit());
+    [
+        "%d.%m.%Y", "%d/%m/%Y", "%Y-%m-%d", "%d-%m-%Y", "%Y/%m/%d", "%Y.%m.%d", "%d.%m.%y", "%d/%m/%y",
+    ]
+    .iter()
+    .find_map(|fmt| NaiveDate::parse_from_str(s, fmt).ok())
+}
+
+/// Month a history row belongs to: the invoice date when present, otherwise the scan date.
+fn row_month(data: &Value, created_at: &str) -> Option<Month> {
+    let from_invoice = data
+        .get("date")
+        .and_then(|v| v.as_str())
+        .and_then(parse_invoice_date);
+    let date = from_invoice.or_else(|| {
+        chrono::DateTime::parse_from_rfc3339(created_at)
+            .ok()
+            .map(|dt| dt.date_naive())
+    })?;
+    Some((date.year(), date.month()))
+}
+
+fn row_amount(data: &Value) -> Option<f64> {
+    field_amount(data, "total_amount")
+}
+
+/// Amount field of extracted_data ("1.234,50 ден" and the like) as a number.
+pub(crate) fn field_amount(data: &Value, key: &str) -> Option<f64> {
+    let raw = match data.get(key)? {
+        Value::Number(n) => return n.as_f64(),
+        Value::String(s) => s,
+        _ => return None,
+    };
+    parse_amount(raw)
+}
+
+/// Amount as written on a document ("1.234,50 ден", "27,826.17"); None when it holds no number.
+pub(crate) fn parse_amount(raw: &str) -> Option<f64> {
+    let cleaned: String = raw
+        .chars()
+        .filter(|c| c.is_ascii_digit() || matches!(c, '.' | ',' | '-'))
+        .collect();
+    crate::excel::normalize_amount_string(&cleaned).parse::<f64>().ok()
+}
+
+fn round2(n: f64) -> f64 {
+    (n * 100.0).round() / 100.0
+}
+
+/// Build the report for `period` ("YYYY-MM").
+/// `categories` are (id, name, monthly_budget); `rows` are (category_id, created_at, extracted_data JSON).
+pub fn build_spending_report(
+    period: &str,
+    categories: &[(i64, String, Option<f64>)],
+    rows: &[(Option<i64>, String, String)],
+) -> Result<SpendingReport, String> {
+    let current = parse_period(period).ok_or_else(|| format!("Invalid period '{}'. Use YYYY-MM.", period))?;
+    let months: Vec<Month> = (0..TREND_MONTHS as u32).rev().map(|back| shift_month(current, back)).collect();
+    let previous = shift_month(current, 1);
+
+    // (category_id, month) -> (total, count)
+    let mut totals: HashMap<(Option<i64>, Month), (f64, u32)> = HashMap::new();
+    let mut skipped_without_amount = 0u32;
+    for (category_id, created_at, extracted_data) in rows {
+        let data: Value = serde_json::from_str(extracted_data).unwrap_or(Value::Null);
+        let Some(month) = row_month(&data, created_at) else { continue };
+        if !months.contains(&month) {
+            continue;
+        }
+        // Rows pointing at a deleted category are reported as uncategorized.
+        let category_id = category_id.filter(|id| categories.iter().any(|(cid, _, _)| cid == id));
+        match row_amount(&data) {
+            Some(amount) => {
+                let entry = totals.entry((category_id, month)).or_insert((0.0, 0));
+                entry.0 += amount;
+                entry.1 += 1;
+            }
+            None if month == current => skipped_without_amount += 1,
+            None => {}
+        }
+    }
+
+    let mut buckets: Vec<(Option<i64>, String, Option<f64>)> = categories
+        .iter()
+        .map(|(id, name, budget)| (Some(*id), name.clone(), *budget))
+        .collect();
+    buckets.push((None, "Uncategorized".to_string(), None));
+
+    let mut out = Vec::new();
+    for (category_id, category_name, monthly_budget) in buckets {
+        let monthly_totals: Vec<MonthTotal> = months
+            .iter()
+            .map(|m| MonthTotal {
+                month: month_label(*m),
+                total: round2(totals.get(&(category_id, *m)).map(|t| t.0).unwrap_or(0.0)),
+            })
+            .collect();
+        let (total, invoice_count) = totals.get(&(category_id, current)).copied().unwrap_or((0.0, 0));
+        let previous_total = totals.get(&(category_id, previous)).map(|t| t.0).unwrap_or(0.0);
+        // Hide the uncategorized bucket when it has nothing to show.
+        if category_id.is_none() && monthly_totals.iter().all(|m| m.total == 0.0) {
+            continue;
+        }
+        out.push(CategorySpending {
+            category_id,
+            category_name,
+            total: round2(total),
+            invoice_count,
+            previous_total: round2(previous_total),
+            delta: round2(total - previous_total),
+            delta_percent: (previous_total.abs() > f64::EPSILON)
+                .then(|| round2((total - previous_total) / previous_total * 100.0)),
+            monthly_budget,
+            monthly_totals,
+        });
+    }
+    out.sort_by(|a, b| b.total.partial_cmp(&a.total).unwrap_or(std::cmp::Ordering::Equal));
+
+    Ok(SpendingReport {
+        period: month_label(current),
+        previous_period: month_label(previous),
+        total: round2(out.iter().map(|c| c.total).sum()),
+        previous_total: round2(out.iter().map(|c| c.previous_total).sum()),
+        categories: out,
+        skipped_without_amount,
+    })
+}
@@ -0,0 +1,194 @@
+//! Disk usage of the stores under app data, with soft quotas. Over-quota stores of re-derivable artifacts
+//! (thumbnails, cached raw OCR JSON, temp files) are trimmed least-recently-used first; archived
+//! documents and backups are never deleted automatically, only reported so the UI can warn.
+
+use crate::db::Db;
+use crate::services::temp_store;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Settings-table key prefix for per-store quotas (value in MB).
+const QUOTA_KEY_PREFIX: &str = "storage_quota_mb.";
+const MB: u64 = 1024 * 1024;
+
+struct StoreDef {
+    name: &'static str,
+    /// Folder under app data.
+    dir: &'static str,
+    /// Files can be regenerated, so LRU cleanup is allowed.
+    derivable: bool,
+    default_quota_mb: Option<u64>,
+}
+
+const STORES: &[StoreDef] = &[
+    StoreDef { name: "archive", dir: "archive", derivable: false, default_quota_mb: None },
+    StoreDef { name: "archive_cache", dir: "archive_cache", derivable: true, default_quota_mb: Some(500) },
+    StoreDef { name: "ocr_cache", dir: "ocr_cache", derivable: true, default_quota_mb: Some(500) },
+    StoreDef { name: "thumbnails", dir: "thumbnails", derivable: true, default_quota_mb: Some(200) },
+    StoreDef { name: "backups", dir: "backups", derivable: false, default_quota_mb: None },
+    StoreDef { name: "snapshots", dir: "snapshots", derivable: false, default_quota_mb: Some(1024) },
+    StoreDef { name: "templates", dir: "templates", derivable: false, default_quota_mb: None },
+    StoreDef { name: "temp", dir: temp_store::TEMP_DIR, derivable: true, default_quota_mb: Some(temp_store::CAP_MB) },
+];
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoreUsage {
+    pub name: String,
+    pub path: String,
+    pub bytes: u64,
+    pub file_count: u64,
+    pub quota_bytes: Option<u64>,
+    pub over_quota: bool,
+    pub derivable: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageUsage {
+    pub stores: Vec<StoreUsage>,
+    /// Database file plus its WAL/SHM side files.
+    pub database_bytes: u64,
+    pub total_bytes: u64,
+    /// Non-derivable stores over their quota (need the user's attention).
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoreCleanup {
+    pub name: String,
+    pub removed_files: u64,
+    pub freed_bytes: u64,
+}
+
+/// (path, size, last used)
+fn collect_files(dir: &Path, out: &mut Vec<(PathBuf, u64, SystemTime)>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Ok(meta) = entry.metadata() else {
+            continue;
+        };
+        if meta.is_dir() {
+            collect_files(&path, out);
+        } else {
+            // Access time is often disabled on Windows volumes; fall back to the modification time.
+            let used = meta
+                .accessed()
+                .ok()
+                .max(meta.modified().ok())
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+            out.push((path, meta.len(), used));
+        }
+    }
+}
+
+/// `stored` is the settings table (key -> value).
+fn quota_bytes(stored: &HashMap<String, String>, store: &StoreDef) -> Option<u64> {
+    let configured = stored
+        .get(&format!("{}{}", QUOTA_KEY_PREFIX, store.name))
+        .and_then(|v| v.parse::<u64>().ok());
+    // 0 disables the quota for a store.
+    configured.or(store.default_quota_mb).filter(|mb| *mb > 0).map(|mb| mb * MB)
+}
+
+fn store_def(name: &str) -> Result<&'static StoreDef, String> {
+    STORES
+        .iter()
+        .find(|s| s.name == name)
+        .ok_or_else(|| format!("Unknown storage area: {}", name))
+}
+
+/// Size of every store, the database, and warnings for stores that need manual cleanup.
+pub fn get_storage_usage(db: &Db, app_data_dir: &Path, db_path: &Path) -> Result<StorageUsage, String> {
+    let stored = db.get_settings()?;
+    let mut stores = Vec::new();
+    let mut warnings = Vec::new();
+    for def in STORES {
+        let dir = app_data_dir.join(def.dir);
+        let mut files = Vec::new();
+        collect_files(&dir, &mut files);
+        let bytes: u64 = files.iter().map(|(_, size, _)| size).sum();
+        let quota = quota_bytes(&stored, def);
+        let over_quota = quota.is_some_and(|q| bytes > q);
+        if over_quota && !def.derivable {
+            warnings.push(format!(
+                "{} uses {} MB, over its {} MB limit.",
+                def.name,
+                bytes / MB,
+                quota.unwrap_or(0) / MB
+            ));
+        }
+        stores.push(StoreUsage {
+            name: def.name.to_string(),
+            path: dir.to_string_lossy().to_string(),
+            bytes,
+            file_count: files.len() as u64,
+            quota_bytes: quota,
+            over_quota,
+            derivable: def.derivable,
+        });
+    }
+    let db_file = db_path.to_string_lossy();
+    let database_bytes = ["", "-wal", "-shm"]
+        .iter()
+        .filter_map(|suffix| fs::metadata(format!("{}{}", db_file, suffix)).ok())
+        .map(|m| m.len())
+        .sum();
+    let total_bytes = database_bytes + stores.iter().map(|s| s.bytes).sum::<u64>();
+    Ok(StorageUsage {
+        stores,
+        database_bytes,
+        total_bytes,
+        warnings,
+    })
+}
+
+/// Set (Some MB, 0 = unlimited) or reset to default (None) the quota of a store.
+pub fn set_storage_quota(db: &Db, store: &str, quota_mb: Option<u64>) -> Result<(), String> {
+    let def = store_def(store)?;
+    let key = format!("{}{}", QUOTA_KEY_PREFIX, def.name);
+    Ok(db.set_settings(&[(key.as_str(), quota_mb.map(|mb| mb.to_string()))])?)
+}
+
+/// Trim derivable stores that are over quota, least recently used files first, down to 90% of the quota.
+pub fn enforce_storage_quotas(db: &Db, app_data_dir: &Path) -> Result<Vec<StoreCleanup>, String> {
+    let stored = db.get_settings()?;
+    let mut out = Vec::new();
+    for def in STORES.iter().filter(|s| s.derivable) {
+        let Some(quota) = quota_bytes(&stored, def) else {
+            continue;
+        };
+        let mut files = Vec::new();
+        collect_files(&app_data_dir.join(def.dir), &mut files);
+        let mut total: u64 = files.iter().map(|(_, size, _)| size).sum();
+        if total <= quota {
+            continue;
+        }
+        let target = quota / 10 * 9;
+        files.sort_by_key(|(_, _, used)| *used);
+        let mut cleanup = StoreCleanup {
+            name: def.name.to_string(),
+            removed_files: 0,
+            freed_bytes: 0,
+        };
+        for (path, size, _) in files {
+            if total <= target {
+                break;
+            }
+            if fs::remove_file(&path).is_ok() {
+                total -= size;
+                cleanup.removed_files += 1;
+                cleanup.freed_bytes += size;
+            }
+        }
+        out.push(cleanup);
+    }
+    Ok(out)
+}
@@ -0,0 +1,138 @@
+//! Strict export mode per profile. By default an export or append writes every document it is given and
+//! validation only warns, which leaves bad rows in the ledger to clean up by hand. In strict mode the whole
+//! batch is refused when any document fails the amount, duplicate or mandatory-field checks, and the
+//! caller gets a report of every problem. Kept in settings (`strict_export.<profile id>`).
+
+use crate::db::Db;
+use crate::services::duplicate_keys;
+use crate::services::field_schemas::{self, FieldSchema};
+use crate::services::validation;
+use crate::types::InvoiceData;
+use serde::Serialize;
+use std::collections::HashMap;
+
+const STRICT_KEY: &str = "strict_export";
+
+/// Warning codes of `validation::validate_invoice` and `field_schemas::validate` that block a strict batch.
+/// Unusual VAT rates and format mismatches stay warnings.
+const BLOCKING_CODES: &[&str] = &["amounts_mismatch", "invalid_date", "missing_document_number", "missing_required"];
+
+fn strict_key(profile_id: i64) -> String {
+    format!("{}.{}", STRICT_KEY, profile_id)
+}
+
+/// Whether the profile is in strict mode (never without a profile).
+pub fn get(db: &Db, profile_id: Option<i64>) -> Result<bool, String> {
+    let Some(id) = profile_id else {
+        return Ok(false);
+    };
+    Ok(db.get_settings()?.get(&strict_key(id)).is_some_and(|v| v == "1"))
+}
+
+pub fn set(db: &Db, profile_id: i64, strict: bool) -> Result<bool, String> {
+    db.get_profile_by_id(profile_id)?;
+    db.set_settings(&[(strict_key(profile_id).as_str(), strict.then(|| "1".to_string()))])?;
+    get(db, Some(profile_id))
+}
+
+/// One problem with one document of a batch.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StrictIssue {
+    /// Position of the document in the batch as given.
+    pub index: usize,
+    pub source_file: Option<String>,
+    /// A blocking validation code, "duplicate_in_batch" or "duplicate_in_ledger".
+    pub code: String,
+    pub field_key: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StrictReport {
+    pub passed: bool,
+    pub document_count: usize,
+    pub issues: Vec<StrictIssue>,
+}
+
+/// Check a batch. `document_type` is the type it was scanned as (see `validation::validate_invoice`),
+/// `field_schema` adds its required fields, `key_fields` is the duplicate key and `ledger_keys` (key, row)
+/// are the rows already in the ledger when appending to it.
+pub fn check_batch(
+    invoices: &[InvoiceData],
+    document_type: Option<&str>,
+    field_schema: Option<&FieldSchema>,
+    key_fields: &[String],
+    ledger_keys: &[(String, u32)],
+) -> StrictReport {
+    let mut issues = Vec::new();
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    for (index, invoice) in invoices.iter().enumerate() {
+        let issue = |code: &str, field_key: &str, message: String| StrictIssue {
+            index,
+            source_file: invoice.source_file.clone(),
+            code: code.to_string(),
+            field_key: field_key.to_string(),
+            message,
+        };
+        let mut warnings = validation::validate_invoice(document_type, invoice);
+        if let Some(schema) = field_schema {
+            for w in field_schemas::validate(schema, invoice) {
+                if !warnings.iter().any(|existing| existing.code == w.code && existing.field_key == w.field_key) {
+                    warnings.push(w);
+                }
+            }
+        }
+        issues.extend(
+            warnings
+                .into_iter()
+                .filter(|w| BLOCKING_CODES.contains(&w.code.as_str()))
+                .map(|w| issue(&w.code, &w.field_key, w.message)),
+        );
+
+        let key_field = key_fields.first().map(|f| duplicate_keys::source_field(f)).unwrap_or("invoice_number");
+        let Some(key) = duplicate_keys::key_of(key_fields, |f| invoice.fields.get(f).map(|v| v.value.clone())) else {
+            continue;
+        };
+        if let Some(first) = seen.get(&key) {
+            issues.push(issue(
+                "duplicate_in_batch",
+                key_field,
+                format!("Same document as #{} of this batch.", first + 1),
+            ));
+        } else {
+            seen.insert(key.clone(), index);
+        }
+        if let Some((_, row)) = ledger_keys.iter().find(|(k, _)| *k == key) {
+            issues.push(issue(
+                "duplicate_in_ledger",
+                key_field,
+                format!("The ledger already has this document on row {}.", row),
+            ));
+        }
+    }
+    StrictReport {
+        passed: issues.is_empty(),
+        document_count: invoices.len(),
+        issues,
+    }
+}
+
+/// Error message for a refused batch, one line per problem.
+pub fn describe(report: &StrictReport) -> String {
+    let documents = {
+        let mut indexes: Vec<usize> = report.issues.iter().map(|i| i.index).collect();
+        indexes.dedup();
+        indexes.len()
+    };
+    let mut message = format!(
+        "Strict mode: nothing was exported because {} of {} documents failed validation.",
+        documents, report.document_count
+    );
+    for issue in &report.issues {
+        let name = issue.source_file.as_deref().map(|f| format!(" ({})", f)).unwrap_or_default();
+        message.push_str(&format!("\n#{}{}: {}", issue.index + 1, name, issue.message));
+    }
+    message
+}
@@ -0,0 +1,108 @@
+//! Managed temp files under app data (`temp/`) instead of loose files in the OS temp folder. Content put
+//! into the store is named by its SHA-256, so the same bytes (a re-processed page, a scan pasted twice)
+//! map to one file. The store is emptied on startup and exit and kept under a size cap by evicting the
+//! least recently used files.
+
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::SystemTime;
+
+/// Folder under app data (listed as the "temp" storage store).
+pub const TEMP_DIR: &str = "temp";
+pub const CAP_MB: u64 = 512;
+const CAP_BYTES: u64 = CAP_MB * 1024 * 1024;
+
+static ROOT: OnceLock<PathBuf> = OnceLock::new();
+static SCRATCH_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+fn root() -> PathBuf {
+    ROOT.get()
+        .cloned()
+        .unwrap_or_else(|| std::env::temp_dir().join("invoice_scanner_temp"))
+}
+
+/// Set the store folder and remove what a previous session left behind (crash, killed process).
+pub fn init(app_data_dir: &Path) {
+    let _ = ROOT.set(app_data_dir.join(TEMP_DIR));
+    clear();
+}
+
+/// Remove every temp file. Returns the bytes freed.
+pub fn clear() -> u64 {
+    let freed = entries().iter().map(|(_, size, _)| size).sum();
+    let _ = fs::remove_dir_all(root());
+    freed
+}
+
+/// (path, size, last used) of the files in the store.
+fn entries() -> Vec<(PathBuf, u64, SystemTime)> {
+    let Ok(dir) = fs::read_dir(root()) else {
+        return Vec::new();
+    };
+    dir.flatten()
+        .filter_map(|e| {
+            let meta = e.metadata().ok().filter(|m| m.is_file())?;
+            Some((e.path(), meta.len(), meta.modified().unwrap_or(SystemTime::UNIX_EPOCH)))
+        })
+        .collect()
+}
+
+fn ensure_root() -> Result<PathBuf, String> {
+    let dir = root();
+    fs::create_dir_all(&dir).map_err(|e| format!("Could not create temp folder: {}", e))?;
+    Ok(dir)
+}
+
+/// Store `bytes` as `<sha256>.<extension>` and return its path; existing identical content is reused.
+pub fn put(bytes: &[u8], extension: &str) -> Result<PathBuf, String> {
+    let hash: String = Sha256::digest(bytes).iter().map(|b| format!("{:02x}", b)).collect();
+    let extension = extension.trim_start_matches('.');
+    if extension.is_empty() || !extension.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(format!("Invalid file extension: {}", extension));
+    }
+    let path = ensure_root()?.join(format!("{}.{}", hash, extension));
+    if path.metadata().map(|m| m.len() == bytes.len() as u64).unwrap_or(false) {
+        // Mark as recently used so eviction keeps it.
+        if let Ok(f) = fs::File::options().append(true).open(&path) {
+            let _ = f.set_modified(SystemTime::now());
+        }
+    } else {
+        fs::write(&path, bytes).map_err(|e| format!("Could not write temp file: {}", e))?;
+    }
+    enforce_cap(&path);
+    Ok(path)
+}
+
+/// Fresh path in the store for output written by other code (e.g. a database snapshot); cleaned up
+/// with the rest of the store.
+pub fn scratch_path(prefix: &str, extension: &str) -> Result<PathBuf, String> {
+    let n = SCRATCH_COUNTER.fetch_add(1, Ordering::Relaxed);
+    Ok(ensure_root()?.join(format!(
+        "{}_{}_{}.{}",
+        prefix,
+        chrono::Local::now().format("%Y%m%d_%H%M%S_%f"),
+        n,
+        extension.trim_start_matches('.')
+    )))
+}
+
+/// Evict least recently used files (never `keep`) while the store is over its cap.
+fn enforce_cap(keep: &Path) {
+    let mut files = entries();
+    let mut total: u64 = files.iter().map(|(_, size, _)| size).sum();
+    if total <= CAP_BYTES {
+        return;
+    }
+    files.sort_by_key(|(_, _, used)| *used);
+    for (path, size, _) in files {
+        if total <= CAP_BYTES {
+            break;
+        }
+        if path != keep && fs::remove_file(&path).is_ok() {
+            total -= size;
+        }
+    }
+}
@@ -0,0 +1,253 @@
+//! AWS Textract backend: one synchronous AnalyzeExpense call per document, its summary fields mapped
+//! onto the same field keys as the Azure invoice analyzers. Synchronous calls take JPEG, PNG, TIFF and
+//! single-page PDF documents up to 10 MB; other documents have to be scanned with Azure.
+
+use crate::error::AppError;
+use crate::services::archive_store::{hex, hmac_sha256, sigv4_signing_key};
+use crate::services::document_format::{detect_format, DocumentFormat, UNSUPPORTED_FORMAT};
+use crate::services::ocr_provider::TextractConfig;
+use crate::services::validation::parse_amount;
+use crate::types::{InvoiceData, InvoiceFieldValue, OcrInvoiceResult};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Largest document accepted by the synchronous Textract API.
+const MAX_BYTES: usize = 10 * 1024 * 1024;
+const TARGET: &str = "Textract.AnalyzeExpense";
+const CONTENT_TYPE: &str = "application/x-amz-json-1.1";
+
+/// Textract summary field type -> app field key. Earlier entries win for the same key.
+const TEXTRACT_TO_FIELD: &[(&str, &str)] = &[
+    ("INVOICE_RECEIPT_ID", "invoice_number"),
+    ("INVOICE_RECEIPT_DATE", "date"),
+    ("VENDOR_NAME", "seller_name"),
+    ("VENDOR_ADDRESS", "seller_address"),
+    ("VENDOR_VAT_NUMBER", "seller_tax_id"),
+    ("TAX_PAYER_ID", "seller_tax_id"),
+    ("RECEIVER_NAME", "buyer_name"),
+    ("RECEIVER_ADDRESS", "buyer_address"),
+    ("RECEIVER_VAT_NUMBER", "buyer_tax_id"),
+    ("SUBTOTAL", "net_amount"),
+    ("TAX", "tax_amount"),
+    ("TOTAL", "total_amount"),
+    ("AMOUNT_DUE", "total_amount"),
+    ("DUE_DATE", "due_date"),
+    ("PO_NUMBER", "reference"),
+];
+
+const AMOUNT_FIELDS: &[&str] = &["net_amount", "tax_amount", "total_amount"];
+
+/// Scan `file_path` with Textract. Only invoices are supported (no tax balance, payroll or VAT forms).
+pub fn analyze_invoice(
+    cfg: &TextractConfig,
+    file_path: &str,
+    document_type: Option<&str>,
+) -> Result<OcrInvoiceResult, AppError> {
+    if document_type.is_some_and(|t| t != "faktura") {
+        return Err(AppError::Ocr(
+            "AWS Textract reads invoices only. Switch the OCR provider to Azure for this document type.".to_string(),
+        ));
+    }
+    let bytes = std::fs::read(Path::new(file_path)).map_err(|e| {
+        if e.kind() == std::io::ErrorKind::NotFound {
+            "File not found.".to_string()
+        } else {
+            format!("Could not read file: {}", e)
+        }
+    })?;
+    match detect_format(&bytes) {
+        None | Some(DocumentFormat::Heic) => return Err(AppError::Ocr(UNSUPPORTED_FORMAT.to_string())),
+        Some(_) => {}
+    }
+    if bytes.len() > MAX_BYTES {
+        return Err(AppError::Ocr("AWS Textract accepts documents up to 10 MB.".to_string()));
+    }
+    let body = serde_json::json!({ "Document": { "Bytes": BASE64.encode(&bytes) } }).to_string();
+    let response = analyze_expense(cfg, body)?;
+    Ok(parse_expense(&response))
+}
+
+/// Signed (AWS Signature V4) AnalyzeExpense request; returns the response JSON.
+fn analyze_expense(cfg: &TextractConfig, body: String) -> Result<Value, AppError> {
+    let host = format!("textract.{}.amazonaws.com", cfg.region);
+    let now = chrono::Utc::now();
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let date = now.format("%Y%m%d").to_string();
+    let signed_headers = "content-type;host;x-amz-date;x-amz-target";
+    let canonical_request = format!(
+        "POST\n/\n\ncontent-type:{}\nhost:{}\nx-amz-date:{}\nx-amz-target:{}\n\n{}\n{}",
+        CONTENT_TYPE,
+        host,
+        amz_date,
+        TARGET,
+        signed_headers,
+        hex(&Sha256::digest(body.as_bytes()))
+    );
+    let scope = format!("{}/{}/textract/aws4_request", date, cfg.region);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope,
+        hex(&Sha256::digest(canonical_request.as_bytes()))
+    );
+    let k_signing = sigv4_signing_key(&cfg.secret_access_key, &date, &cfg.region, "textract");
+    let authorization = format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        cfg.access_key_id,
+        scope,
+        signed_headers,
+        hex(&hmac_sha256(&k_signing, &string_to_sign))
+    );
+
+    let client = reqwest::blocking::Client::builder()
+        .timeout(std::time::Duration::from_secs(180))
+        .build()
+        .map_err(AppError::ocr)?;
+    let response = client
+        .post(format!("https://{}/", host))
+        .header("Content-Type", CONTENT_TYPE)
+        .header("X-Amz-Date", &amz_date)
+        .header("X-Amz-Target", TARGET)
+        .header("Authorization", authorization)
+        .body(body)
+        .send()
+        .map_err(|e| AppError::Ocr(format!("Could not reach AWS Textract: {}", e)))?;
+    let status = response.status();
+    let text = response.text().map_err(AppError::ocr)?;
+    if !status.is_success() {
+        return Err(AppError::Ocr(textract_error(status, &text)));
+    }
+    serde_json::from_str(&text).map_err(|e| AppError::Ocr(format!("Unexpected Textract response: {}", e)))
+}
+
+fn textract_error(status: reqwest::StatusCode, body: &str) -> String {
+    let json: Value = serde_json::from_str(body).unwrap_or(Value::Null);
+    let kind = json.get("__type").and_then(|t| t.as_str()).unwrap_or("");
+    let kind = kind.rsplit('#').next().unwrap_or(kind);
+    match kind {
+        "UnsupportedDocumentException" => {
+            "AWS Textract could not read this document (multi-page PDFs are not supported).".to_string()
+        }
+        "UnrecognizedClientException" | "InvalidSignatureException" | "AccessDeniedException" => {
+            "AWS Textract rejected the credentials. Check the access key, secret key and region in Settings.".to_string()
+        }
+        "ThrottlingException" | "ProvisionedThroughputExceededException" => {
+            "AWS Textract is busy. Try again in a moment.".to_string()
+        }
+        _ => format!(
+            "AWS Textract returned {}: {}",
+            status,
+            json.get("message")
+                .or_else(|| json.get("Message"))
+                .and_then(|m| m.as_str())
+                .unwrap_or(kind)
+        ),
+    }
+}
+
+fn detection_text(obj: &Value, name: &str) -> Option<(String, Option<f64>)> {
+    let d = obj.get(name)?;
+    let text = d.get("Text")?.as_str()?.trim();
+    if text.is_empty() {
+        return None;
+    }
+    let confidence = d.get("Confidence").and_then(|c| c.as_f64()).map(|c| c / 100.0);
+    Some((text.replace('\n', " "), confidence))
+}
+
+/// Map the first expense document of an AnalyzeExpense response onto app field keys.
+pub fn parse_expense(response: &Value) -> OcrInvoiceResult {
+    let documents = response
+        .get("ExpenseDocuments")
+        .and_then(|d| d.as_array())
+        .cloned()
+        .unwrap_or_default();
+    let mut fields: HashMap<String, InvoiceFieldValue> = HashMap::new();
+    // Rank of the Textract type each field came from (lower = listed earlier in TEXTRACT_TO_FIELD).
+    let mut ranks: HashMap<&str, usize> = HashMap::new();
+    let mut currency: Option<String> = None;
+
+    let summary = documents
+        .first()
+        .and_then(|d| d.get("SummaryFields"))
+        .and_then(|s| s.as_array())
+        .cloned()
+        .unwrap_or_default();
+    for field in &summary {
+        let Some(kind) = field.get("Type").and_then(|t| t.get("Text")).and_then(|t| t.as_str()) else {
+            continue;
+        };
+        let Some(rank) = TEXTRACT_TO_FIELD.iter().position(|(t, _)| *t == kind) else {
+            continue;
+        };
+        let key = TEXTRACT_TO_FIELD[rank].1;
+        let Some((mut value, confidence)) = detection_text(field, "ValueDetection") else {
+            continue;
+        };
+        if key == "total_amount" && currency.is_none() {
+            currency = field
+                .get("Currency")
+                .and_then(|c| c.get("Code"))
+                .and_then(|c| c.as_str())
+                .map(str::to_string);
+        }
+        if AMOUNT_FIELDS.contains(&key) {
+            if let Some(n) = parse_amount(&value) {
+                value = format!("{:.2}", n);
+            }
+        }
+        let better = match (ranks.get(key), fields.get(key)) {
+            (Some(&r), _) if r < rank => false,
+            (Some(&r), Some(existing)) if r == rank => confidence.unwrap_or(0.0) > existing.confidence.unwrap_or(0.0),
+            _ => true,
+        };
+        if better {
+            ranks.insert(key, rank);
+            fields.insert(key.to_string(), InvoiceFieldValue { value, confidence, ..Default::default() });
+        }
+    }
+    if let Some(code) = currency {
+        fields.insert("currency".to_string(), InvoiceFieldValue { value: code, confidence: None, ..Default::default() });
+    }
+
+    // Line item names as the description, as the Azure path does when no description is extracted.
+    let items: Vec<String> = documents
+        .first()
+        .and_then(|d| d.get("LineItemGroups"))
+        .and_then(|g| g.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|g| g.get("LineItems").and_then(|l| l.as_array()))
+        .flatten()
+        .filter_map(|item| {
+            item.get("LineItemExpenseFields")?.as_array()?.iter().find_map(|f| {
+                let kind = f.get("Type")?.get("Text")?.as_str()?;
+                (kind == "ITEM").then(|| detection_text(f, "ValueDetection")).flatten()
+            })
+        })
+        .map(|(text, _)| text)
+        .collect();
+    if !items.is_empty() {
+        fields.insert(
+            "description".to_string(),
+            InvoiceFieldValue { value: items.join("; "), confidence: None, ..Default::default() },
+        );
+    }
+
+    OcrInvoiceResult {
+        invoice_data: InvoiceData {
+            fields,
+            source_file: None,
+            source_file_path: None,
+        },
+        raw_azure_fields: None,
+        document_count: Some(documents.len().max(1) as u32),
+        warnings: Vec::new(),
+        applied_corrections: Vec::new(),
+        raw_text: None,
+        extraction_path: Some("textract".to_string()),
+    }
+}
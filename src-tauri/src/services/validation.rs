@@ -0,0 +1,233 @@
+//! Sanity checks on extracted invoice fields (amounts add up, date parses, VAT rate is a Macedonian one,
+//! document number present), returned as warnings with the OCR result so the UI can flag a record
+//! before it is appended. Batches are also checked as a whole against a cover-sheet total.
+
+use crate::services::spending_report::parse_invoice_date;
+use crate::types::{InvoiceData, ValidationWarning};
+use serde::Serialize;
+
+/// VAT rates a single-rate invoice can have.
+const TAX_RATES: [f64; 3] = [5.0, 10.0, 18.0];
+/// Allowed distance from a rate, in percentage points (rounding on small amounts).
+const RATE_TOLERANCE: f64 = 0.5;
+
+/// Document types that are not invoices (tax balance, payroll, VAT forms) and are not checked.
+const SKIPPED_TYPES: &[&str] = &["smetka", "plata", "generic"];
+
+fn warning(code: &str, field_key: &str, message: String) -> ValidationWarning {
+    ValidationWarning {
+        code: code.to_string(),
+        field_key: field_key.to_string(),
+        message,
+    }
+}
+
+fn text<'a>(data: &'a InvoiceData, key: &str) -> Option<&'a str> {
+    data.fields.get(key).map(|f| f.value.trim()).filter(|v| !v.is_empty())
+}
+
+fn amount(data: &InvoiceData, key: &str) -> Option<f64> {
+    parse_amount(text(data, key)?)
+}
+
+/// Amount as written on a document ("1.234,50 ден.", "1,234.50") as a number.
+pub(crate) fn parse_amount(raw: &str) -> Option<f64> {
+    let cleaned: String = raw
+        .chars()
+        .filter(|c| c.is_ascii_digit() || matches!(c, '.' | ',' | '-'))
+        .collect();
+    crate::excel::normalize_amount_string(&cleaned).parse::<f64>().ok()
+}
+
+/// Largest difference between net + tax and total still treated as rounding.
+fn amount_tolerance(total: f64) -> f64 {
+    (total.abs() * 0.001).max(0.05)
+}
+
+/// Checks for one extracted document; `document_type` is the type the scan was run with.
+pub fn validate_invoice(document_type: Option<&str>, data: &InvoiceData) -> Vec<ValidationWarning> {
+    if document_type.is_some_and(|t| SKIPPED_TYPES.contains(&t)) || data.fields.is_empty() {
+        return Vec::new();
+    }
+    let mut warnings = Vec::new();
+
+    let net = amount(data, "net_amount");
+    let tax = amount(data, "tax_amount");
+    let total = amount(data, "total_amount");
+    if let (Some(net), Some(tax), Some(total)) = (net, tax, total) {
+        if (net + tax - total).abs() > amount_tolerance(total) {
+            warnings.push(warning(
+                "amounts_mismatch",
+                "total_amount",
+                format!("Net {:.2} + VAT {:.2} = {:.2}, but the total is {:.2}.", net, tax, net + tax, total),
+            ));
+        }
+    }
+    if let (Some(net), Some(tax)) = (net, tax) {
+        if net.abs() >= 0.01 && tax.abs() >= 0.01 {
+            let rate = tax / net * 100.0;
+            if !TAX_RATES.iter().any(|r| (rate - r).abs() <= RATE_TOLERANCE) {
+                warnings.push(warning(
+                    "unusual_tax_rate",
+                    "tax_amount",
+                    format!("VAT is {:.1}% of the net amount; expected 5%, 10% or 18% (or the invoice mixes rates).", rate),
+                ));
+            }
+        }
+    }
+
+    match text(data, "date") {
+        Some(date) if parse_invoice_date(date).is_none() => {
+            warnings.push(warning("invalid_date", "date", format!("Date '{}' could not be read.", date)));
+        }
+        None => warnings.push(warning("invalid_date", "date", "No document date was extracted.".to_string())),
+        _ => {}
+    }
+
+    if text(data, "invoice_number").is_none() {
+        warnings.push(warning(
+            "missing_document_number",
+            "invoice_number",
+            "No document number was extracted.".to_string(),
+        ));
+    }
+    warnings
+}
+
+/// One document of a reconciled batch and what may be wrong with it.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentTotal {
+    /// Position in the batch as passed in.
+    pub index: usize,
+    pub source_file: Option<String>,
+    pub invoice_number: Option<String>,
+    pub total: Option<f64>,
+    /// "missing_total", "amounts_mismatch", "duplicate" or "matches_difference"; empty when nothing stands out.
+    pub issues: Vec<String>,
+    pub message: Option<String>,
+}
+
+/// Sum of extracted totals against the total on the batch's cover sheet.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchReconciliation {
+    pub expected_total: f64,
+    pub extracted_total: f64,
+    /// Extracted minus expected, rounded to cents.
+    pub difference: f64,
+    pub balanced: bool,
+    /// Cover sheet taken from the batch (excluded from the sum), when one was given.
+    pub cover_index: Option<usize>,
+    pub documents: Vec<DocumentTotal>,
+}
+
+fn round_cents(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+/// Reconcile a batch against `expected_total`, or, when it is None, against the total of the document at
+/// `cover_index` (a scanned summary page). Documents that explain a difference are flagged.
+pub fn reconcile_batch(
+    invoices: &[InvoiceData],
+    expected_total: Option<f64>,
+    cover_index: Option<usize>,
+) -> Result<BatchReconciliation, String> {
+    let cover_index = cover_index.filter(|i| *i < invoices.len());
+    let expected_total = match (expected_total, cover_index) {
+        (Some(total), _) => total,
+        (None, Some(i)) => amount(&invoices[i], "total_amount")
+            .ok_or("No total was extracted from the cover sheet; enter it by hand.")?,
+        (None, None) => return Err("Enter the cover sheet total or pick the cover sheet from the batch.".to_string()),
+    };
+
+    let mut documents = Vec::new();
+    for (index, invoice) in invoices.iter().enumerate() {
+        if Some(index) == cover_index {
+            continue;
+        }
+        let total = amount(invoice, "total_amount");
+        let mut doc = DocumentTotal {
+            index,
+            source_file: invoice.source_file.clone(),
+            invoice_number: text(invoice, "invoice_number").or_else(|| text(invoice, "document_number")).map(String::from),
+            total,
+            issues: Vec::new(),
+            message: None,
+        };
+        match total {
+            None => {
+                doc.issues.push("missing_total".to_string());
+                doc.message = Some("No total was extracted; it is counted as 0.".to_string());
+            }
+            Some(total) => {
+                if let (Some(net), Some(tax)) = (amount(invoice, "net_amount"), amount(invoice, "tax_amount")) {
+                    if (net + tax - total).abs() > amount_tolerance(total) {
+                        doc.issues.push("amounts_mismatch".to_string());
+                        doc.message = Some(format!("Net + VAT is {:.2}, the total reads {:.2}.", net + tax, total));
+                    }
+                }
+            }
+        }
+        documents.push(doc);
+    }
+
+    // The same document scanned twice counts its total twice.
+    for i in 0..documents.len() {
+        let duplicate_of = (0..i).find(|&j| {
+            documents[j].invoice_number.is_some()
+                && documents[j].invoice_number == documents[i].invoice_number
+                && documents[j].total == documents[i].total
+        });
+        if let Some(j) = duplicate_of {
+            let first = documents[j].index;
+            documents[i].issues.push("duplicate".to_string());
+            documents[i].message.get_or_insert(format!("Same number and total as document {}.", first + 1));
+        }
+    }
+
+    let extracted_total = round_cents(documents.iter().filter_map(|d| d.total).sum());
+    let difference = round_cents(extracted_total - expected_total);
+    let tolerance = amount_tolerance(expected_total).max(0.01 * documents.len() as f64);
+    let balanced = difference.abs() <= tolerance;
+    if !balanced {
+        // A document whose total equals the difference is the likely culprit.
+        for doc in &mut documents {
+            let Some(total) = doc.total else { continue };
+            if (total.abs() - difference.abs()).abs() <= amount_tolerance(total) {
+                doc.issues.push("matches_difference".to_string());
+                doc.message.get_or_insert(format!(
+                    "Its total {:.2} equals the difference; it may not belong to this batch or be missing from the cover sheet.",
+                    total
+                ));
+            }
+        }
+    }
+
+    Ok(BatchReconciliation {
+        expected_total,
+        extracted_total,
+        difference,
+        balanced,
+        cover_index,
+        documents,
+    })
+}
+
+/// One-line summary of an unbalanced batch for an error message.
+pub fn describe_imbalance(r: &BatchReconciliation) -> String {
+    let flagged: Vec<String> = r
+        .documents
+        .iter()
+        .filter(|d| !d.issues.is_empty())
+        .map(|d| d.source_file.clone().unwrap_or_else(|| format!("document {}", d.index + 1)))
+        .collect();
+    let mut message = format!(
+        "Extracted totals add up to {:.2}, the cover sheet says {:.2} (difference {:+.2}).",
+        r.extracted_total, r.expected_total, r.difference
+    );
+    if !flagged.is_empty() {
+        message.push_str(&format!(" Check: {}.", flagged.join(", ")));
+    }
+    message
+}
@@ -0,0 +1,230 @@
+//! Input VAT (претходен данок) summary for a month or quarter, laid out like the purchases part of the
+//! ДДВ-04 return: base and VAT per rate, per month and per vendor. Written by `excel::export_vat_report`.
+
+use crate::db::HistoryReportRow;
+use crate::services::spending_report::{field_amount, parse_invoice_date};
+use chrono::{Datelike, NaiveDate};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// Macedonian VAT rates (general, hospitality, preferential, exempt).
+const VAT_RATES: [u32; 4] = [18, 10, 5, 0];
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VatTotals {
+    pub document_count: u32,
+    /// Основица (net amount).
+    pub net_amount: f64,
+    pub tax_amount: f64,
+    pub total_amount: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VatRateLine {
+    /// Percent; None for documents whose VAT does not match a Macedonian rate.
+    pub rate: Option<u32>,
+    #[serde(flatten)]
+    pub totals: VatTotals,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VatMonthLine {
+    /// "YYYY-MM"
+    pub month: String,
+    pub rates: Vec<VatRateLine>,
+    #[serde(flatten)]
+    pub totals: VatTotals,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VatVendorLine {
+    pub vendor: String,
+    /// ЕДБ of the vendor when extracted.
+    pub tax_id: String,
+    #[serde(flatten)]
+    pub totals: VatTotals,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VatReport {
+    /// Inclusive "YYYY-MM-DD" bounds.
+    pub period_start: String,
+    pub period_end: String,
+    pub rates: Vec<VatRateLine>,
+    pub months: Vec<VatMonthLine>,
+    /// Largest VAT first.
+    pub vendors: Vec<VatVendorLine>,
+    pub totals: VatTotals,
+    /// Documents in the period without usable amounts (not included).
+    pub skipped_without_amounts: u32,
+    /// Set once the workbook is written.
+    pub saved_path: Option<String>,
+}
+
+fn round2(n: f64) -> f64 {
+    (n * 100.0).round() / 100.0
+}
+
+impl VatTotals {
+    fn add(&mut self, net: f64, tax: f64, total: f64) {
+        self.document_count += 1;
+        self.net_amount += net;
+        self.tax_amount += tax;
+        self.total_amount += total;
+    }
+
+    fn rounded(mut self) -> Self {
+        self.net_amount = round2(self.net_amount);
+        self.tax_amount = round2(self.tax_amount);
+        self.total_amount = round2(self.total_amount);
+        self
+    }
+}
+
+/// "YYYY-MM-DD", or "YYYY-MM" meaning the first (`end` = false) or last day of that month.
+fn parse_period_bound(value: &str, end: bool) -> Result<NaiveDate, String> {
+    let v = value.trim();
+    if let Ok(date) = NaiveDate::parse_from_str(v, "%Y-%m-%d") {
+        return Ok(date);
+    }
+    let first = NaiveDate::parse_from_str(&format!("{}-01", v), "%Y-%m-%d")
+        .map_err(|_| format!("Invalid period '{}'. Use YYYY-MM or YYYY-MM-DD.", value))?;
+    if !end {
+        return Ok(first);
+    }
+    let next = if first.month() == 12 {
+        NaiveDate::from_ymd_opt(first.year() + 1, 1, 1)
+    } else {
+        NaiveDate::from_ymd_opt(first.year(), first.month() + 1, 1)
+    };
+    next.and_then(|d| d.pred_opt())
+        .ok_or_else(|| format!("Invalid period '{}'.", value))
+}
+
+/// Nearest Macedonian rate for the tax/net ratio, if within one percentage point.
+fn detect_rate(net: f64, tax: f64) -> Option<u32> {
+    if net.abs() < 0.005 {
+        return None;
+    }
+    let percent = tax / net * 100.0;
+    VAT_RATES.into_iter().find(|r| (percent - *r as f64).abs() <= 1.0)
+}
+
+fn text_field(data: &Value, key: &str) -> String {
+    data.get(key).and_then(|v| v.as_str()).unwrap_or_default().trim().to_string()
+}
+
+fn rate_lines(map: BTreeMap<Option<u32>, VatTotals>) -> Vec<VatRateLine> {
+    // Highest rate first, unmatched last.
+    let mut lines: Vec<VatRateLine> = map
+        .into_iter()
+        .map(|(rate, totals)| VatRateLine { rate, totals: totals.rounded() })
+        .collect();
+    lines.sort_by_key(|l| std::cmp::Reverse(l.rate.map(|r| r as i64).unwrap_or(-1)));
+    lines
+}
+
+/// `rows` come from `Db::get_history_report_rows`; failed scans are ignored.
+pub fn build_vat_report(period_start: &str, period_end: &str, rows: &[HistoryReportRow]) -> Result<VatReport, String> {
+    let start = parse_period_bound(period_start, false)?;
+    let end = parse_period_bound(period_end, true)?;
+    if start > end {
+        return Err("The period start is after the period end.".to_string());
+    }
+
+    let mut rates: BTreeMap<Option<u32>, VatTotals> = BTreeMap::new();
+    let mut months: BTreeMap<String, BTreeMap<Option<u32>, VatTotals>> = BTreeMap::new();
+    let mut vendors: BTreeMap<String, (String, VatTotals)> = BTreeMap::new();
+    let mut totals = VatTotals::default();
+    let mut skipped_without_amounts = 0u32;
+    for (_, created_at, _, _, extracted_data, status) in rows {
+        if status == "error" {
+            continue;
+        }
+        let data: Value = serde_json::from_str(extracted_data).unwrap_or(Value::Null);
+        let date = data
+            .get("date")
+            .and_then(|v| v.as_str())
+            .and_then(parse_invoice_date)
+            .or_else(|| chrono::DateTime::parse_from_rfc3339(created_at).ok().map(|dt| dt.date_naive()));
+        let Some(date) = date.filter(|d| *d >= start && *d <= end) else { continue };
+
+        let net = field_amount(&data, "net_amount");
+        let tax = field_amount(&data, "tax_amount");
+        let total = field_amount(&data, "total_amount");
+        let (net, tax, total) = match (net, tax, total) {
+            (Some(n), Some(t), total) => (n, t, total.unwrap_or(n + t)),
+            (None, Some(t), Some(total)) => (total - t, t, total),
+            (Some(n), None, Some(total)) => (n, total - n, total),
+            _ => {
+                skipped_without_amounts += 1;
+                continue;
+            }
+        };
+        let rate = detect_rate(net, tax);
+        rates.entry(rate).or_default().add(net, tax, total);
+        months
+            .entry(format!("{:04}-{:02}", date.year(), date.month()))
+            .or_default()
+            .entry(rate)
+            .or_default()
+            .add(net, tax, total);
+        let vendor = text_field(&data, "seller_name");
+        let vendor = if vendor.is_empty() { "(непознат добавувач)".to_string() } else { vendor };
+        let tax_id = [text_field(&data, "seller_edb"), text_field(&data, "seller_tax_id")]
+            .into_iter()
+            .find(|v| !v.is_empty())
+            .unwrap_or_default();
+        let entry = vendors.entry(vendor).or_insert_with(|| (tax_id.clone(), VatTotals::default()));
+        if entry.0.is_empty() {
+            entry.0 = tax_id;
+        }
+        entry.1.add(net, tax, total);
+        totals.add(net, tax, total);
+    }
+
+    let months = months
+        .into_iter()
+        .map(|(month, by_rate)| {
+            let mut month_totals = VatTotals::default();
+            for t in by_rate.values() {
+                month_totals.document_count += t.document_count;
+                month_totals.net_amount += t.net_amount;
+                month_totals.tax_amount += t.tax_amount;
+                month_totals.total_amount += t.total_amount;
+            }
+            VatMonthLine {
+                month,
+                rates: rate_lines(by_rate),
+                totals: month_totals.rounded(),
+            }
+        })
+        .collect();
+    let mut vendors: Vec<VatVendorLine> = vendors
+        .into_iter()
+        .map(|(vendor, (tax_id, totals))| VatVendorLine { vendor, tax_id, totals: totals.rounded() })
+        .collect();
+    vendors.sort_by(|a, b| {
+        b.totals
+            .tax_amount
+            .partial_cmp(&a.totals.tax_amount)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    Ok(VatReport {
+        period_start: start.format("%Y-%m-%d").to_string(),
+        period_end: end.format("%Y-%m-%d").to_string(),
+        rates: rate_lines(rates),
+        months,
+        vendors,
+        totals: totals.rounded(),
+        skipped_without_amounts,
+        saved_path: None,
+    })
+}
@@ -0,0 +1,214 @@
+//! ДДВ-04 (VAT return) boxes from documents read without the VAT return analyzer (prebuilt-read /
+//! layout). Box numbers 01–31 are found in the layout's key-value pairs and in table rows or lines of the
+//! text ("01 | Оданочив промет ... | 1.250.000,00"); the totals are derived from the boxes. Keys match
+//! the analyzer path in ocr.rs, so the DDV template, schemas and exports treat both alike.
+
+use crate::services::validation::parse_amount;
+use crate::types::InvoiceFieldValue;
+use regex::Regex;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// Box number -> field key (boxes 01–19 output side, 21–31 input side).
+const BOXES: &[(u32, &str)] = &[
+    (1, "prometOpshtaStapkaOsnova"),
+    (2, "prometOpshtaStapkaDDV"),
+    (3, "prometPovlastenaStapka10Osnova"),
+    (4, "prometPovlastenaStapka10DDV"),
+    (5, "prometPovlastenaStapka5Osnova"),
+    (6, "prometPovlastenaStapka5DDV"),
+    (7, "izvoz"),
+    (8, "oslobodenSOPravoNaOdbivka"),
+    (9, "oslobodenBezPravoNaOdbivka"),
+    (10, "prometNerezidentiNeOdanocliv"),
+    (11, "prometPrenesuvanjeDanocnaObvrska"),
+    (12, "primenPrometNerezidentiOpshtaOsnova"),
+    (13, "primenPrometNerezidentiOpshtaDDV"),
+    (14, "primenPrometNerezidentiPovlastenaOsnova"),
+    (15, "primenPrometNerezidentiPovlastenaDDV"),
+    (16, "primenPrometZemjaOpshtaOsnova"),
+    (17, "primenPrometZemjaOpshtaDDV"),
+    (18, "primenPrometZemjaPovlastenaOsnova"),
+    (19, "primenPrometZemjaPovlastenaDDV"),
+    (21, "vlezenPrometOsnova"),
+    (22, "vlezenPrometDDV"),
+    (23, "vlezenPrometPrijamatelStranstvoOsnova"),
+    (24, "vlezenPrometPrijamatelStranstvoDDV"),
+    (25, "vlezenPrometPrijamatelZemjaOsnova"),
+    (26, "vlezenPrometPrijamatelZemjaDDV"),
+    (27, "uvozOsnova"),
+    (28, "uvozDDV"),
+    (29, "prethodniDanociZaOdbivanje"),
+    (30, "ostanatiDanociIznosiZaOdbivanje"),
+    (31, "danochenDolgIliPobaruvanje"),
+];
+
+/// Taxable turnover: bases at the general and reduced rates.
+const TAX_BASE_BOXES: &[u32] = &[1, 3, 5];
+/// VAT charged on supplies and on received supplies taxed with the recipient (reverse charge).
+const OUTPUT_VAT_BOXES: &[u32] = &[2, 4, 6, 13, 15, 17, 19];
+/// Input VAT when box 29 (total input tax) is not on the document.
+const INPUT_VAT_BOXES: &[u32] = &[22, 24, 26, 28];
+
+fn key_of(number: u32) -> Option<&'static str> {
+    BOXES.iter().find(|(n, _)| *n == number).map(|(_, k)| *k)
+}
+
+/// A cell that is only a box number ("01", "1", "31.").
+fn box_number(cell: &str) -> Option<u32> {
+    let cell = cell.trim().trim_end_matches(['.', ')']);
+    if cell.is_empty() || cell.len() > 2 || !cell.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    cell.parse().ok().filter(|n| key_of(*n).is_some())
+}
+
+/// An amount cell ("1.250.000,00", "0", "-3.400"); cells with letters are labels.
+fn amount_cell(cell: &str) -> Option<f64> {
+    let cell = cell.trim().trim_end_matches("ден.").trim_end_matches("ден").trim();
+    if cell.is_empty() || cell.chars().any(char::is_alphabetic) {
+        return None;
+    }
+    parse_amount(cell)
+}
+
+fn numbered_line_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"^(\d{1,2})[.)]?\s+.*?\s(-?\d[\d.]*(?:,\d{1,2})?)$").expect("valid regex"))
+}
+
+fn tax_id_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"\b(?:MK|МК)?\s?(\d{13})\b").expect("valid regex"))
+}
+
+fn period_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(r"(\d{1,2}\.\d{1,2}\.\d{4})\s*(?:-|–|до)\s*(\d{1,2}\.\d{1,2}\.\d{4})").expect("valid regex")
+    })
+}
+
+/// Box values found in one line of text.
+fn boxes_in_line(line: &str, out: &mut HashMap<u32, f64>) {
+    if line.contains('|') {
+        let cells: Vec<&str> = line.split('|').map(str::trim).filter(|c| !c.is_empty()).collect();
+        for (i, cell) in cells.iter().enumerate() {
+            let Some(number) = box_number(cell) else {
+                continue;
+            };
+            // Value: the first amount after the box number, before the next box number.
+            let value = cells[i + 1..]
+                .iter()
+                .take_while(|c| box_number(c).is_none())
+                .find_map(|c| amount_cell(c));
+            if let Some(value) = value {
+                out.entry(number).or_insert(value);
+            }
+        }
+    } else if let Some(c) = numbered_line_re().captures(line.trim()) {
+        if let (Some(number), Some(value)) = (box_number(&c[1]), amount_cell(&c[2])) {
+            out.entry(number).or_insert(value);
+        }
+    }
+}
+
+fn amount_value(value: f64) -> InvoiceFieldValue {
+    InvoiceFieldValue { value: format!("{:.2}", value), confidence: None, ..Default::default() }
+}
+
+/// ДДВ-04 fields from layout key-value pairs (label, value, confidence) and the document text.
+pub fn extract(key_values: &[(String, String, Option<f64>)], text: &str) -> HashMap<String, InvoiceFieldValue> {
+    let mut fields: HashMap<String, InvoiceFieldValue> = HashMap::new();
+    let mut boxes: HashMap<u32, f64> = HashMap::new();
+
+    // Key-value pairs whose key starts with the box number ("01 Оданочив промет ...").
+    for (label, value, confidence) in key_values {
+        let number = label.split_whitespace().next().and_then(box_number);
+        if let (Some(number), Some(amount)) = (number, amount_cell(value)) {
+            if let Entry::Vacant(e) = boxes.entry(number) {
+                e.insert(amount);
+                if let Some(key) = key_of(number) {
+                    fields.insert(key.to_string(), InvoiceFieldValue { value: format!("{:.2}", amount), confidence: *confidence, ..Default::default() });
+                }
+            }
+        }
+    }
+    for line in text.lines() {
+        boxes_in_line(line, &mut boxes);
+    }
+    for (number, value) in &boxes {
+        if let Some(key) = key_of(*number) {
+            fields.entry(key.to_string()).or_insert_with(|| amount_value(*value));
+        }
+    }
+
+    let sum = |numbers: &[u32]| -> Option<f64> {
+        let present: Vec<f64> = numbers.iter().filter_map(|n| boxes.get(n).copied()).collect();
+        (!present.is_empty()).then(|| present.iter().sum())
+    };
+    let tax_base = sum(TAX_BASE_BOXES);
+    let output_vat = sum(OUTPUT_VAT_BOXES);
+    let input_vat = boxes.get(&29).copied().or_else(|| sum(INPUT_VAT_BOXES));
+    let balance = boxes.get(&31).copied().or(match (output_vat, input_vat) {
+        (Some(o), Some(i)) => Some(o - i),
+        _ => None,
+    });
+    for (key, value) in [
+        ("totalTaxBase", tax_base),
+        ("totalOutputVat", output_vat),
+        ("totalInputVat", input_vat),
+        ("vatPayableOrRefund", balance),
+    ] {
+        if let Some(v) = value {
+            fields.insert(key.to_string(), amount_value(v));
+        }
+    }
+
+    // Header: taxpayer, tax number and period.
+    for line in text.lines().take(40) {
+        let lower = line.to_lowercase();
+        if !fields.contains_key("companyName") && (lower.contains("назив") || lower.contains("даночен обврзник")) {
+            let name = line
+                .split(['|', ':'])
+                .map(str::trim)
+                .filter(|c| !c.is_empty())
+                .nth(1)
+                .filter(|c| c.chars().any(char::is_alphabetic));
+            if let Some(name) = name {
+                fields.insert("companyName".to_string(), InvoiceFieldValue { value: name.to_string(), confidence: None, ..Default::default() });
+            }
+        }
+        if !fields.contains_key("companyTaxId") && (lower.contains("едб") || lower.contains("даночен број")) {
+            if let Some(c) = tax_id_re().captures(line) {
+                fields.insert("companyTaxId".to_string(), InvoiceFieldValue { value: c[1].to_string(), confidence: None, ..Default::default() });
+            }
+        }
+        if !fields.contains_key("taxPeriod") {
+            if let Some(c) = period_re().captures(line) {
+                fields.insert("taxPeriodStart".to_string(), InvoiceFieldValue { value: c[1].to_string(), confidence: None, ..Default::default() });
+                fields.insert("taxPeriodEnd".to_string(), InvoiceFieldValue { value: c[2].to_string(), confidence: None, ..Default::default() });
+                fields.insert(
+                    "taxPeriod".to_string(),
+                    InvoiceFieldValue { value: format!("{} - {}", &c[1], &c[2]), confidence: None, ..Default::default() },
+                );
+            }
+        }
+    }
+
+    // Generic keys as set by the VAT return analyzer path.
+    for (from, to) in [
+        ("companyName", "seller_name"),
+        ("companyTaxId", "seller_tax_id"),
+        ("totalTaxBase", "net_amount"),
+        ("totalOutputVat", "tax_amount"),
+        ("vatPayableOrRefund", "total_amount"),
+        ("taxPeriodEnd", "date"),
+    ] {
+        if let Some(v) = fields.get(from).cloned() {
+            fields.entry(to.to_string()).or_insert(v);
+        }
+    }
+    fields
+}
@@ -0,0 +1,127 @@
+//! Vendor-level corrections learned from hand edits: when the user fixes e.g. `seller_name` on an invoice
+//! from a vendor, the fix is remembered under the vendor's EDB and applied to that vendor's next scans.
+//! Only vendor-stable fields are learned; per-document values (numbers, dates, amounts) never are.
+
+use crate::db::{Db, VendorCorrectionRow};
+use crate::types::{AppliedCorrection, InvoiceData, InvoiceFieldValue};
+use serde::Serialize;
+use serde_json::Value;
+
+/// Fields that identify the vendor and are looked up by, so never learned.
+const KEY_FIELDS: &[&str] = &["seller_edb"];
+/// Corrections applied to any OCR value once the vendor was corrected this many times.
+const ALWAYS_APPLY_AFTER: u32 = 2;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VendorCorrection {
+    pub seller_edb: String,
+    pub field_key: String,
+    /// OCR value that was last corrected.
+    pub original_value: Option<String>,
+    pub corrected_value: String,
+    pub correction_count: u32,
+    pub applied_count: u32,
+    pub updated_at: String,
+}
+
+impl From<VendorCorrectionRow> for VendorCorrection {
+    fn from(
+        (seller_edb, field_key, original_value, corrected_value, correction_count, applied_count, updated_at): VendorCorrectionRow,
+    ) -> Self {
+        VendorCorrection {
+            seller_edb,
+            field_key,
+            original_value,
+            corrected_value,
+            correction_count,
+            applied_count,
+            updated_at,
+        }
+    }
+}
+
+/// EDB with spaces, dashes and an "MK" prefix removed, so "MK 4030-0123456" and "40300123456" match.
+pub fn normalize_edb(value: &str) -> String {
+    let cleaned: String = value.chars().filter(|c| c.is_ascii_alphanumeric()).collect::<String>().to_uppercase();
+    cleaned.strip_prefix("MK").unwrap_or(&cleaned).to_string()
+}
+
+fn is_learnable(field_key: &str) -> bool {
+    (field_key.starts_with("seller_") || field_key == "currency") && !KEY_FIELDS.contains(&field_key)
+}
+
+fn vendor_key(values: impl Fn(&str) -> Option<String>) -> Option<String> {
+    ["seller_edb", "seller_tax_id"]
+        .iter()
+        .filter_map(|k| values(k))
+        .map(|v| normalize_edb(&v))
+        .find(|v| v.len() >= 7)
+}
+
+/// Learn from edits saved for a history record; `extracted_data` is the record's corrected data.
+/// Edits are (field_key, original_value, corrected_value). Returns how many corrections were stored.
+pub fn record(db: &Db, extracted_data: &str, edits: &[(String, Option<String>, String)]) -> Result<usize, String> {
+    let data: Value = serde_json::from_str(extracted_data).unwrap_or(Value::Null);
+    let Some(edb) = vendor_key(|k| data.get(k).and_then(|v| v.as_str()).map(String::from)) else {
+        return Ok(0);
+    };
+    let mut stored = 0;
+    for (field_key, original_value, corrected_value) in edits {
+        if !is_learnable(field_key) || corrected_value.trim().is_empty() {
+            continue;
+        }
+        db.upsert_vendor_correction(&edb, field_key, original_value.as_deref(), corrected_value.trim())?;
+        stored += 1;
+    }
+    Ok(stored)
+}
+
+/// Apply the vendor's remembered corrections to a scan result. A correction replaces the OCR value when
+/// the field is empty, holds the same misread as last time, or the vendor has been corrected repeatedly.
+pub fn apply(db: &Db, invoice: &mut InvoiceData) -> Result<Vec<AppliedCorrection>, String> {
+    let Some(edb) = vendor_key(|k| invoice.fields.get(k).map(|f| f.value.clone())) else {
+        return Ok(Vec::new());
+    };
+    let mut applied = Vec::new();
+    for (_, field_key, original_value, corrected_value, correction_count, ..) in db.get_vendor_corrections(Some(&edb))? {
+        let current = invoice.fields.get(&field_key).map(|f| f.value.trim().to_string()).unwrap_or_default();
+        if current == corrected_value {
+            continue;
+        }
+        let same_misread = original_value.as_deref().map(str::trim) == Some(current.as_str());
+        if !(current.is_empty() || same_misread || correction_count >= ALWAYS_APPLY_AFTER) {
+            continue;
+        }
+        let previous = invoice.fields.get(&field_key).cloned().unwrap_or_default();
+        invoice.fields.insert(
+            field_key.clone(),
+            InvoiceFieldValue {
+                value: corrected_value.clone(),
+                confidence: None,
+                ..previous
+            },
+        );
+        db.mark_vendor_correction_applied(&edb, &field_key)?;
+        applied.push(AppliedCorrection {
+            field_key,
+            ocr_value: current,
+            value: corrected_value,
+        });
+    }
+    Ok(applied)
+}
+
+pub fn list(db: &Db, seller_edb: Option<&str>) -> Result<Vec<VendorCorrection>, String> {
+    let edb = seller_edb.map(normalize_edb);
+    Ok(db
+        .get_vendor_corrections(edb.as_deref())?
+        .into_iter()
+        .map(VendorCorrection::from)
+        .collect())
+}
+
+/// Forget a learned correction. Returns false when there was none.
+pub fn remove(db: &Db, seller_edb: &str, field_key: &str) -> Result<bool, String> {
+    Ok(db.delete_vendor_correction(&normalize_edb(seller_edb), field_key)?)
+}
@@ -0,0 +1,327 @@
+//! Vendor master data. Every scanned invoice is matched to a vendor by tax number, or failing that by a
+//! fuzzy comparison of names that ignores case, script (Cyrillic/Latin), legal forms and city names, so
+//! "DSV ROAD DOOEL" and "DSV Road dooel Skopje" are one vendor. Unknown vendors are added; the scan then
+//! gets the vendor's canonical name (and its address and currency when OCR missed them) before export.
+
+use crate::db::{Db, VendorRow};
+use crate::services::vendor_corrections::normalize_edb;
+use crate::types::{AppliedCorrection, InvoiceData, InvoiceFieldValue};
+use serde::{Deserialize, Serialize};
+
+/// Lowest name similarity (Dice coefficient over name tokens) treated as the same vendor.
+const MATCH_THRESHOLD: f64 = 0.8;
+/// Tokens that say nothing about which company it is: legal forms and common words.
+const LEGAL_FORMS: &[&str] = &[
+    "dooel", "doo", "ad", "ag", "as", "tp", "jp", "ltd", "llc", "inc", "gmbh", "sro", "srl", "spa", "bv", "dtd",
+    "drustvo", "trgovsko", "trgovija", "za", "i", "uvoz", "izvoz", "export", "import", "co", "company",
+];
+/// City names often appended to a vendor name.
+const CITIES: &[&str] = &[
+    "skopje", "bitola", "kumanovo", "prilep", "tetovo", "ohrid", "veles", "shtip", "stip", "strumica", "gostivar",
+    "kavadarci", "kochani", "kocani", "struga", "kichevo", "kicevo", "gevgelija", "negotino", "radovish", "debar",
+];
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Vendor {
+    pub id: i64,
+    pub name: String,
+    pub tax_id: Option<String>,
+    pub address: Option<String>,
+    pub default_currency: Option<String>,
+    /// Other spellings of the name seen on invoices.
+    pub aliases: Vec<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl From<VendorRow> for Vendor {
+    fn from(row: VendorRow) -> Self {
+        Vendor {
+            id: row.id,
+            name: row.name,
+            tax_id: row.tax_id,
+            address: row.address,
+            default_currency: row.default_currency,
+            aliases: serde_json::from_str(&row.aliases).unwrap_or_default(),
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+        }
+    }
+}
+
+impl Vendor {
+    fn to_row(&self) -> VendorRow {
+        VendorRow {
+            id: self.id,
+            name: self.name.clone(),
+            tax_id: self.tax_id.clone(),
+            address: self.address.clone(),
+            default_currency: self.default_currency.clone(),
+            aliases: serde_json::to_string(&self.aliases).unwrap_or_else(|_| "[]".to_string()),
+            created_at: self.created_at.clone(),
+            updated_at: self.updated_at.clone(),
+        }
+    }
+}
+
+/// Vendor as edited in the UI; `id` None creates a new one.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VendorInput {
+    pub id: Option<i64>,
+    pub name: String,
+    pub tax_id: Option<String>,
+    pub address: Option<String>,
+    pub default_currency: Option<String>,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+}
+
+fn transliterate(c: char) -> &'static str {
+    match c {
+        'а' => "a", 'б' => "b", 'в' => "v", 'г' => "g", 'д' => "d", 'ѓ' => "gj", 'е' => "e", 'ж' => "zh",
+        'з' => "z", 'ѕ' => "dz", 'и' => "i", 'ј' => "j", 'к' => "k", 'л' => "l", 'љ' => "lj", 'м' => "m",
+        'н' => "n", 'њ' => "nj", 'о' => "o", 'п' => "p", 'р' => "r", 'с' => "s", 'т' => "t", 'ќ' => "kj",
+        'у' => "u", 'ф' => "f", 'х' => "h", 'ц' => "c", 'ч' => "ch", 'џ' => "dj", 'ш' => "sh",
+        'š' => "sh", 'č' => "ch", 'ž' => "zh", 'ć' => "kj", 'đ' => "dj",
+        _ => "",
+    }
+}
+
+/// Distinguishing words of a vendor name: lowercase Latin, without legal forms and city names.
+pub fn name_tokens(name: &str) -> Vec<String> {
+    let mut latin = String::new();
+    for c in name.to_lowercase().chars() {
+        match transliterate(c) {
+            "" if c.is_ascii_alphanumeric() => latin.push(c),
+            "" => latin.push(' '),
+            t => latin.push_str(t),
+        }
+    }
+    latin
+        .split_whitespace()
+        .filter(|t| !LEGAL_FORMS.contains(t) && !CITIES.contains(t))
+        .map(String::from)
+        .collect()
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            cur.push((prev[j] + usize::from(ca != *cb)).min(prev[j + 1] + 1).min(cur[j] + 1));
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
+
+/// Tokens match when equal, or one typo apart in words of five or more letters.
+fn tokens_match(a: &str, b: &str) -> bool {
+    a == b || (a.len().min(b.len()) >= 5 && edit_distance(a, b) <= 1)
+}
+
+/// Similarity of two names between 0 and 1 (Dice coefficient over matching tokens).
+pub fn name_similarity(a: &str, b: &str) -> f64 {
+    let (a, b) = (name_tokens(a), name_tokens(b));
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    let mut unused: Vec<&String> = b.iter().collect();
+    let mut matched = 0;
+    for token in &a {
+        if let Some(i) = unused.iter().position(|u| tokens_match(token, u)) {
+            unused.remove(i);
+            matched += 1;
+        }
+    }
+    2.0 * matched as f64 / (a.len() + b.len()) as f64
+}
+
+fn normalized_tax_id(value: Option<&str>) -> Option<String> {
+    value.map(normalize_edb).filter(|v| v.len() >= 7)
+}
+
+/// Vendor with this tax number, else the one whose name or alias is most similar to `name`.
+fn best_match(vendors: &[Vendor], name: Option<&str>, tax_id: Option<&str>) -> Option<usize> {
+    if let Some(tax_id) = normalized_tax_id(tax_id) {
+        if let Some(i) = vendors
+            .iter()
+            .position(|v| normalized_tax_id(v.tax_id.as_deref()).as_deref() == Some(tax_id.as_str()))
+        {
+            return Some(i);
+        }
+    }
+    let name = name.filter(|n| !n.trim().is_empty())?;
+    vendors
+        .iter()
+        .enumerate()
+        .filter(|(_, v)| {
+            // Two different tax numbers are two vendors, however alike the names.
+            normalized_tax_id(v.tax_id.as_deref()).is_none() || normalized_tax_id(tax_id).is_none()
+        })
+        .map(|(i, v)| {
+            let score = std::iter::once(&v.name)
+                .chain(&v.aliases)
+                .map(|n| name_similarity(name, n))
+                .fold(0.0, f64::max);
+            (i, score)
+        })
+        .filter(|(_, score)| *score >= MATCH_THRESHOLD)
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(i, _)| i)
+}
+
+pub fn list(db: &Db, search: Option<&str>) -> Result<Vec<Vendor>, String> {
+    let vendors = db.get_vendors()?.into_iter().map(Vendor::from);
+    let search = search.map(str::trim).filter(|s| !s.is_empty());
+    Ok(match search {
+        Some(q) => {
+            let q_lower = q.to_lowercase();
+            vendors
+                .filter(|v| {
+                    v.name.to_lowercase().contains(&q_lower)
+                        || v.tax_id.as_deref().is_some_and(|t| t.contains(q))
+                        || v.aliases.iter().any(|a| name_similarity(q, a) >= MATCH_THRESHOLD)
+                        || name_similarity(q, &v.name) >= MATCH_THRESHOLD
+                })
+                .collect()
+        }
+        None => vendors.collect(),
+    })
+}
+
+/// The known vendor for a name and/or tax number, if any.
+pub fn resolve(db: &Db, name: Option<&str>, tax_id: Option<&str>) -> Result<Option<Vendor>, String> {
+    let vendors: Vec<Vendor> = db.get_vendors()?.into_iter().map(Vendor::from).collect();
+    Ok(best_match(&vendors, name, tax_id).map(|i| vendors[i].clone()))
+}
+
+fn clean(value: Option<String>) -> Option<String> {
+    value.map(|v| v.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+pub fn save(db: &Db, input: VendorInput) -> Result<Vendor, String> {
+    let name = input.name.trim().to_string();
+    if name.is_empty() {
+        return Err("Vendor name is required.".to_string());
+    }
+    let mut aliases: Vec<String> = Vec::new();
+    for alias in input.aliases.into_iter().filter_map(|a| clean(Some(a))) {
+        if alias != name && !aliases.contains(&alias) {
+            aliases.push(alias);
+        }
+    }
+    let vendor = Vendor {
+        id: input.id.unwrap_or(0),
+        name,
+        tax_id: clean(input.tax_id),
+        address: clean(input.address),
+        default_currency: clean(input.default_currency).map(|c| c.to_uppercase()),
+        aliases,
+        created_at: String::new(),
+        updated_at: String::new(),
+    };
+    let id = db.save_vendor(&vendor.to_row())?;
+    db.get_vendors()?
+        .into_iter()
+        .find(|v| v.id == id)
+        .map(Vendor::from)
+        .ok_or_else(|| format!("Vendor {} not found.", id))
+}
+
+pub fn remove(db: &Db, id: i64) -> Result<bool, String> {
+    Ok(db.delete_vendor(id)?)
+}
+
+fn field(invoice: &InvoiceData, key: &str) -> Option<String> {
+    invoice.fields.get(key).map(|f| f.value.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+/// Replace (or fill) a field with master data, recording the change for the UI.
+fn set_field(invoice: &mut InvoiceData, key: &str, value: &str, applied: &mut Vec<AppliedCorrection>) {
+    let current = field(invoice, key).unwrap_or_default();
+    if current == value {
+        return;
+    }
+    // Keep the OCR confidence and where the value was on the page.
+    let previous = invoice.fields.get(key).cloned().unwrap_or_default();
+    invoice.fields.insert(
+        key.to_string(),
+        InvoiceFieldValue {
+            value: value.to_string(),
+            ..previous
+        },
+    );
+    applied.push(AppliedCorrection {
+        field_key: key.to_string(),
+        ocr_value: current,
+        value: value.to_string(),
+    });
+}
+
+/// Match a scanned invoice to its vendor (adding the vendor when new, and any missing master data or new
+/// spelling when known), then give the invoice the vendor's canonical name and fill its empty address and
+/// currency. Returns the fields that were changed.
+pub fn observe(db: &Db, invoice: &mut InvoiceData) -> Result<Vec<AppliedCorrection>, String> {
+    let name = field(invoice, "seller_name");
+    let tax_id = field(invoice, "seller_edb").or_else(|| field(invoice, "seller_tax_id"));
+    let address = field(invoice, "seller_address");
+    let currency = field(invoice, "currency").map(|c| c.to_uppercase());
+    if name.is_none() {
+        return Ok(Vec::new());
+    }
+    let mut vendors: Vec<Vendor> = db.get_vendors()?.into_iter().map(Vendor::from).collect();
+    let vendor = match best_match(&vendors, name.as_deref(), tax_id.as_deref()) {
+        Some(i) => {
+            let mut vendor = vendors.swap_remove(i);
+            let before = vendor.to_row();
+            if let Some(name) = &name {
+                if *name != vendor.name && !vendor.aliases.contains(name) {
+                    vendor.aliases.push(name.clone());
+                }
+            }
+            vendor.tax_id = vendor.tax_id.or(tax_id);
+            vendor.address = vendor.address.or(address);
+            vendor.default_currency = vendor.default_currency.or(currency);
+            let after = vendor.to_row();
+            if (before.aliases, before.tax_id, before.address, before.default_currency)
+                != (after.aliases, after.tax_id, after.address, after.default_currency)
+            {
+                db.save_vendor(&vendor.to_row())?;
+            }
+            vendor
+        }
+        None => {
+            let mut vendor = Vendor {
+                id: 0,
+                name: name.clone().unwrap_or_default(),
+                tax_id,
+                address,
+                default_currency: currency,
+                aliases: Vec::new(),
+                created_at: String::new(),
+                updated_at: String::new(),
+            };
+            vendor.id = db.save_vendor(&vendor.to_row())?;
+            vendor
+        }
+    };
+
+    let mut applied = Vec::new();
+    set_field(invoice, "seller_name", &vendor.name, &mut applied);
+    if field(invoice, "seller_address").is_none() {
+        if let Some(address) = &vendor.address {
+            set_field(invoice, "seller_address", address, &mut applied);
+        }
+    }
+    if field(invoice, "currency").is_none() {
+        if let Some(currency) = &vendor.default_currency {
+            set_field(invoice, "currency", currency, &mut applied);
+        }
+    }
+    Ok(applied)
+}
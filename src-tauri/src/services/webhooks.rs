@@ -0,0 +1,240 @@
+//! Webhooks for downstream systems (ERP, chat bots): after a scan or a ledger append succeeds, each
+//! document's `InvoiceData` is POSTed as JSON to the URL from settings. The body is signed with HMAC-SHA256
+//! under the webhook secret (`X-Signature-256: sha256=<hex>`, as GitHub does) so the receiver can check it
+//! came from the app. Every delivery is recorded in `webhook_deliveries`; network errors, 429 and 5xx are
+//! retried with backoff, and a failed delivery can be sent again with the same body.
+
+use crate::db::{Db, WebhookDeliveryRow};
+use crate::services::archive_store::hex;
+use crate::services::{app_warnings, settings};
+use crate::types::InvoiceData;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::path::Path;
+use std::time::Duration;
+
+const URL: &str = "webhook_url";
+const EVENTS: &str = "webhook_events";
+/// Encrypted (see `settings::encrypt_secret`).
+const SECRET: &str = "webhook_secret";
+const MAX_ATTEMPTS: u32 = 4;
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(2);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WebhookEvent {
+    #[serde(rename = "scan.succeeded")]
+    ScanSucceeded,
+    #[serde(rename = "append.succeeded")]
+    AppendSucceeded,
+}
+
+impl WebhookEvent {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            WebhookEvent::ScanSucceeded => "scan.succeeded",
+            WebhookEvent::AppendSucceeded => "append.succeeded",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookSettings {
+    pub url: Option<String>,
+    /// Events that are sent; none while no URL is set.
+    pub events: Vec<WebhookEvent>,
+    pub secret_configured: bool,
+}
+
+/// None leaves a value unchanged, an empty string clears it.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookSettingsUpdate {
+    pub url: Option<String>,
+    pub events: Option<Vec<WebhookEvent>>,
+    pub secret: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookDelivery {
+    pub id: i64,
+    pub event: String,
+    pub url: String,
+    pub profile_id: Option<i64>,
+    /// "pending", "delivered" or "failed"
+    pub status: String,
+    pub attempts: u32,
+    /// HTTP status of the last response, if any came.
+    pub response_status: Option<u16>,
+    pub error: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// Body of a webhook POST.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Payload<'a> {
+    event: &'static str,
+    created_at: String,
+    profile_id: Option<i64>,
+    invoice: &'a InvoiceData,
+}
+
+fn delivery(row: WebhookDeliveryRow) -> WebhookDelivery {
+    let (id, event, url, profile_id, _payload, status, attempts, response_status, error, created_at, updated_at) = row;
+    WebhookDelivery { id, event, url, profile_id, status, attempts, response_status, error, created_at, updated_at }
+}
+
+fn stored_secret(db: &Db, app_data_dir: &Path) -> Result<Option<String>, String> {
+    let Some(encrypted) = db.get_settings()?.get(SECRET).filter(|v| !v.is_empty()).cloned() else {
+        return Ok(None);
+    };
+    Ok(settings::decrypt_secret(app_data_dir, &encrypted)
+        .map_err(|e| app_warnings::report("webhooks", format!("could not decrypt the webhook secret: {}", e)))
+        .ok())
+}
+
+pub fn get_settings(db: &Db, app_data_dir: &Path) -> Result<WebhookSettings, String> {
+    let stored = db.get_settings()?;
+    let url = stored.get(URL).map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+    let events = match &url {
+        Some(_) => stored.get(EVENTS).and_then(|v| serde_json::from_str(v).ok()).unwrap_or_default(),
+        None => Vec::new(),
+    };
+    Ok(WebhookSettings { url, events, secret_configured: stored_secret(db, app_data_dir)?.is_some() })
+}
+
+pub fn save_settings(db: &Db, app_data_dir: &Path, update: WebhookSettingsUpdate) -> Result<WebhookSettings, String> {
+    let mut entries: Vec<(&str, Option<String>)> = Vec::new();
+    if let Some(url) = update.url {
+        let url = url.trim().to_string();
+        if !url.is_empty() {
+            let parsed = reqwest::Url::parse(&url).map_err(|e| format!("Invalid webhook URL: {}", e))?;
+            let local = matches!(parsed.host_str(), Some("localhost" | "127.0.0.1" | "[::1]"));
+            if parsed.scheme() != "https" && !(parsed.scheme() == "http" && local) {
+                return Err("Webhook URL must start with https:// (http:// only for localhost).".to_string());
+            }
+        }
+        entries.push((URL, Some(url).filter(|u| !u.is_empty())));
+    }
+    if let Some(selected) = update.events {
+        let mut events: Vec<WebhookEvent> = Vec::new();
+        for event in selected {
+            if !events.contains(&event) {
+                events.push(event);
+            }
+        }
+        entries.push((EVENTS, Some(serde_json::to_string(&events).map_err(|e| e.to_string())?)));
+    }
+    if let Some(secret) = update.secret {
+        let encrypted = match secret.trim() {
+            "" => None,
+            secret => Some(settings::encrypt_secret(app_data_dir, secret)?),
+        };
+        entries.push((SECRET, encrypted));
+    }
+    db.set_settings(&entries)?;
+    get_settings(db, app_data_dir)
+}
+
+/// Record one pending delivery per document for `event`; returns their ids, none when the webhook is not
+/// set up for the event. Send them with `deliver`.
+pub fn queue(
+    db: &Db,
+    app_data_dir: &Path,
+    event: WebhookEvent,
+    profile_id: Option<i64>,
+    invoices: &[InvoiceData],
+) -> Result<Vec<i64>, String> {
+    let settings = get_settings(db, app_data_dir)?;
+    let Some(url) = settings.url.filter(|_| settings.events.contains(&event)) else {
+        return Ok(Vec::new());
+    };
+    let created_at = chrono::Utc::now().to_rfc3339();
+    let mut ids = Vec::with_capacity(invoices.len());
+    for invoice in invoices {
+        let payload = Payload { event: event.as_str(), created_at: created_at.clone(), profile_id, invoice };
+        let body = serde_json::to_string(&payload).map_err(|e| e.to_string())?;
+        ids.push(db.add_webhook_delivery(event.as_str(), &url, profile_id, &body)?);
+    }
+    Ok(ids)
+}
+
+/// `X-Signature-256` value of `body`.
+fn signature(secret: &str, body: &str) -> String {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(body.as_bytes());
+    format!("sha256={}", hex(&mac.finalize().into_bytes()))
+}
+
+/// One POST; on failure the error, the response status if any, and whether to retry.
+fn post(
+    client: &reqwest::blocking::Client,
+    url: &str,
+    id: i64,
+    event: &str,
+    body: &str,
+    secret: Option<&str>,
+) -> Result<u16, (String, Option<u16>, bool)> {
+    let mut request = client
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header("X-Webhook-Event", event)
+        .header("X-Webhook-Delivery", id.to_string())
+        .body(body.to_string());
+    if let Some(secret) = secret {
+        request = request.header("X-Signature-256", signature(secret, body));
+    }
+    let response = request.send().map_err(|e| (format!("Could not reach {}: {}", url, e), None, true))?;
+    let status = response.status();
+    if status.is_success() {
+        return Ok(status.as_u16());
+    }
+    let retryable = status == reqwest::StatusCode::TOO_MANY_REQUESTS || status.is_server_error();
+    let text: String = response.text().unwrap_or_default().chars().take(300).collect();
+    Err((format!("{} answered {}: {}", url, status, text.trim()), Some(status.as_u16()), retryable))
+}
+
+/// Send a recorded delivery to the current webhook URL, retrying transient failures, and record the
+/// outcome. Blocking (sleeps between attempts); run it off the async runtime.
+pub fn deliver(db: &Db, app_data_dir: &Path, id: i64) -> Result<WebhookDelivery, String> {
+    let (_, event, recorded_url, _, body, _, previous_attempts, ..) =
+        db.get_webhook_delivery(id)?.ok_or_else(|| format!("Webhook delivery {} not found.", id))?;
+    let url = get_settings(db, app_data_dir)?.url.unwrap_or(recorded_url);
+    let secret = stored_secret(db, app_data_dir)?;
+    let client = reqwest::blocking::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .map_err(|e| e.to_string())?;
+
+    let mut attempts = previous_attempts;
+    let mut outcome = Err((String::new(), None, true));
+    for attempt in 0..MAX_ATTEMPTS {
+        if attempt > 0 {
+            std::thread::sleep(RETRY_BASE_DELAY * 2u32.pow(attempt - 1));
+        }
+        attempts += 1;
+        outcome = post(&client, &url, id, &event, &body, secret.as_deref());
+        let status = if outcome.is_ok() { "delivered" } else { "pending" };
+        let (response_status, error) = match &outcome {
+            Ok(code) => (Some(*code), None),
+            Err((message, code, _)) => (*code, Some(message.as_str())),
+        };
+        db.update_webhook_delivery(id, &url, status, attempts, response_status, error)?;
+        if matches!(&outcome, Ok(_) | Err((_, _, false))) {
+            break;
+        }
+    }
+    if let Err((message, code, _)) = &outcome {
+        db.update_webhook_delivery(id, &url, "failed", attempts, *code, Some(message))?;
+    }
+    db.get_webhook_delivery(id)?.map(delivery).ok_or_else(|| format!("Webhook delivery {} not found.", id))
+}
+
+pub fn list_deliveries(db: &Db, limit: u32) -> Result<Vec<WebhookDelivery>, String> {
+    Ok(db.list_webhook_deliveries(limit)?.into_iter().map(delivery).collect())
+}
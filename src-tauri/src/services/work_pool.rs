@@ -0,0 +1,170 @@
+//! Prioritized worker pool for background work. Every OCR call, ledger append, preview render and
+//! maintenance job takes a permit first: each class has a concurrency budget (from settings), all
+//! classes share a total of max(OCR budget, CPU count) slots, and a free slot goes to the most
+//! important waiting class (OCR > appends > previews > maintenance). Dropping a 300-file batch
+//! therefore queues its previews behind OCR instead of competing with it.
+
+use serde::{Deserialize, Serialize};
+use std::sync::{Mutex, OnceLock};
+use tokio::sync::Notify;
+
+/// Work classes in priority order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum WorkClass {
+    Ocr,
+    Append,
+    Preview,
+    Maintenance,
+}
+
+impl WorkClass {
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Concurrency budget per class.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkerBudgets {
+    pub ocr: u32,
+    pub appends: u32,
+    pub previews: u32,
+    pub maintenance: u32,
+}
+
+impl Default for WorkerBudgets {
+    fn default() -> Self {
+        WorkerBudgets { ocr: 8, appends: 1, previews: 2, maintenance: 1 }
+    }
+}
+
+impl WorkerBudgets {
+    pub const MAX: u32 = 32;
+
+    fn of(&self, class: WorkClass) -> usize {
+        let budget = match class {
+            WorkClass::Ocr => self.ocr,
+            WorkClass::Append => self.appends,
+            WorkClass::Preview => self.previews,
+            WorkClass::Maintenance => self.maintenance,
+        };
+        budget.max(1) as usize
+    }
+
+    fn total(&self) -> usize {
+        let cpus = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(4);
+        cpus.max(self.of(WorkClass::Ocr))
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if [self.ocr, self.appends, self.previews, self.maintenance].iter().any(|b| !(1..=Self::MAX).contains(b)) {
+            return Err(format!("Worker budgets must be between 1 and {}.", Self::MAX));
+        }
+        Ok(())
+    }
+}
+
+const CLASSES: [WorkClass; 4] = [WorkClass::Ocr, WorkClass::Append, WorkClass::Preview, WorkClass::Maintenance];
+
+#[derive(Default)]
+struct PoolState {
+    running: [usize; 4],
+    waiting: [usize; 4],
+}
+
+impl PoolState {
+    fn can_start(&self, class: WorkClass, budgets: &WorkerBudgets) -> bool {
+        let has_room = |c: WorkClass| self.running[c.index()] < budgets.of(c);
+        if !has_room(class) || self.running.iter().sum::<usize>() >= budgets.total() {
+            return false;
+        }
+        // A more important class that could start right now gets the slot first.
+        !CLASSES[..class.index()].iter().any(|&c| self.waiting[c.index()] > 0 && has_room(c))
+    }
+}
+
+fn state() -> &'static Mutex<PoolState> {
+    static STATE: OnceLock<Mutex<PoolState>> = OnceLock::new();
+    STATE.get_or_init(|| Mutex::new(PoolState::default()))
+}
+
+fn notify() -> &'static Notify {
+    static NOTIFY: OnceLock<Notify> = OnceLock::new();
+    NOTIFY.get_or_init(Notify::new)
+}
+
+/// Jobs of one class holding or waiting for a slot.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClassDepth {
+    pub class: WorkClass,
+    pub running: usize,
+    pub waiting: usize,
+    pub budget: usize,
+}
+
+pub fn depths() -> Vec<ClassDepth> {
+    let budgets = crate::services::settings::worker_budgets();
+    let s = state().lock().unwrap_or_else(|e| e.into_inner());
+    CLASSES
+        .iter()
+        .map(|&class| ClassDepth {
+            class,
+            running: s.running[class.index()],
+            waiting: s.waiting[class.index()],
+            budget: budgets.of(class),
+        })
+        .collect()
+}
+
+/// Slot in the pool; released on drop.
+pub struct Permit {
+    class: WorkClass,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        state().lock().unwrap_or_else(|e| e.into_inner()).running[self.class.index()] -= 1;
+        notify().notify_waiters();
+    }
+}
+
+/// Counts a caller in `waiting` until it starts; undoes the count when the waiting future is dropped.
+struct Waiter {
+    class: WorkClass,
+}
+
+impl Drop for Waiter {
+    fn drop(&mut self) {
+        state().lock().unwrap_or_else(|e| e.into_inner()).waiting[self.class.index()] -= 1;
+        // Lower classes may have been held back only by this waiter.
+        notify().notify_waiters();
+    }
+}
+
+/// Wait for a slot of `class`. Hold the permit for the duration of the work.
+pub async fn acquire(class: WorkClass) -> Permit {
+    let i = class.index();
+    state().lock().unwrap_or_else(|e| e.into_inner()).waiting[i] += 1;
+    let waiter = Waiter { class };
+    loop {
+        let mut notified = std::pin::pin!(notify().notified());
+        notified.as_mut().enable();
+        {
+            let budgets = crate::services::settings::worker_budgets();
+            let mut s = state().lock().unwrap_or_else(|e| e.into_inner());
+            if s.can_start(class, &budgets) {
+                std::mem::forget(waiter);
+                s.waiting[i] -= 1;
+                s.running[i] += 1;
+                // Budgets may have room for other waiters too.
+                notify().notify_waiters();
+                return Permit { class };
+            }
+        }
+        notified.await;
+    }
+}
+
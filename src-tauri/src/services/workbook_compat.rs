@@ -0,0 +1,152 @@
+//! Workbook variants that read wrong without special handling: the 1904 date system of old Mac Excel
+//! (calamine returns the raw serial, so dates come out 4 years early) and Cyrillic text stored in the
+//! wrong encoding by old exporters (UTF-8 bytes read as Windows-1252, or Windows-1251 bytes read as
+//! Latin-1), which shows up as mojibake such as "Ð¤Ð°ÐºÑ‚ÑƒÑ€Ð°" or "Ôàêòóðà".
+
+use calamine::{Data, DataType};
+use quick_xml::events::Event;
+use quick_xml::Reader as XmlReader;
+use std::io::Read;
+use std::path::Path;
+use zip::read::ZipArchive;
+
+/// Days between the 1900 and 1904 epochs.
+const DATE1904_OFFSET_DAYS: f64 = 1462.0;
+
+/// Windows-1252 characters for bytes 0x80–0x9F (None where the code page has no character).
+const CP1252_HIGH: [Option<char>; 32] = [
+    Some('€'), None, Some('‚'), Some('ƒ'), Some('„'), Some('…'), Some('†'), Some('‡'),
+    Some('ˆ'), Some('‰'), Some('Š'), Some('‹'), Some('Œ'), None, Some('Ž'), None,
+    None, Some('‘'), Some('’'), Some('“'), Some('”'), Some('•'), Some('–'), Some('—'),
+    Some('˜'), Some('™'), Some('š'), Some('›'), Some('œ'), None, Some('ž'), Some('Ÿ'),
+];
+
+/// Windows-1251 Cyrillic outside the А–я block (0xC0–0xFF): Macedonian and Serbian letters and Ё.
+const CP1251_EXTRA: [(u8, char); 14] = [
+    (0x81, 'Ѓ'), (0x83, 'ѓ'), (0x8A, 'Љ'), (0x8C, 'Њ'), (0x8D, 'Ќ'), (0x9A, 'љ'), (0x9C, 'њ'),
+    (0x9D, 'ќ'), (0xA3, 'Ј'), (0xA8, 'Ё'), (0xB8, 'ё'), (0xBC, 'ј'), (0xBD, 'Ѕ'), (0xBE, 'ѕ'),
+];
+
+/// True when an .xlsx workbook uses the 1904 date system (`<workbookPr date1904="1"/>`).
+/// Other formats and unreadable files count as 1900.
+pub fn uses_1904_dates(path: &Path) -> bool {
+    let Ok(file) = std::fs::File::open(path) else { return false };
+    let Ok(mut archive) = ZipArchive::new(file) else { return false };
+    let mut xml = String::new();
+    match archive.by_name("xl/workbook.xml") {
+        Ok(mut entry) => {
+            if entry.read_to_string(&mut xml).is_err() {
+                return false;
+            }
+        }
+        Err(_) => return false,
+    }
+    let mut reader = XmlReader::from_str(&xml);
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) | Ok(Event::Empty(e)) if e.local_name().as_ref() == b"workbookPr" => {
+                return e
+                    .attributes()
+                    .flatten()
+                    .find(|a| a.key.local_name().as_ref() == b"date1904")
+                    .is_some_and(|a| matches!(a.value.as_ref(), b"1" | b"true"));
+            }
+            Ok(Event::Eof) | Err(_) => return false,
+            _ => {}
+        }
+    }
+}
+
+/// Excel serial date as dd.mm.yyyy (with HH:MM when it has a time part), the format the app writes dates in.
+pub fn serial_to_date_text(serial: f64, date1904: bool) -> String {
+    if serial < 1.0 {
+        // Time-only cell.
+        let minutes = (serial * 24.0 * 60.0).round() as i64;
+        return format!("{:02}:{:02}", minutes / 60 % 24, minutes % 60);
+    }
+    let mut days = if date1904 { serial + DATE1904_OFFSET_DAYS } else { serial };
+    // 1900 system: serials below 60 are one day off because of Excel's fictitious 29.02.1900.
+    if days < 60.0 {
+        days += 1.0;
+    }
+    let base = chrono::NaiveDate::from_ymd_opt(1899, 12, 30).expect("valid date");
+    let Some(date) = base.checked_add_signed(chrono::Duration::days(days.trunc() as i64)) else {
+        return serial.to_string();
+    };
+    let minutes = (days.fract() * 24.0 * 60.0).round() as i64;
+    if minutes == 0 || minutes >= 24 * 60 {
+        date.format("%d.%m.%Y").to_string()
+    } else {
+        format!("{} {:02}:{:02}", date.format("%d.%m.%Y"), minutes / 60, minutes % 60)
+    }
+}
+
+fn is_cyrillic(c: char) -> bool {
+    ('\u{0400}'..='\u{04FF}').contains(&c)
+}
+
+/// Byte a character had in Windows-1252 (Latin-1 for 0xA0–0xFF and C1 controls).
+fn cp1252_byte(c: char) -> Option<u8> {
+    let code = c as u32;
+    if code <= 0xFF {
+        return Some(code as u8);
+    }
+    CP1252_HIGH
+        .iter()
+        .position(|h| *h == Some(c))
+        .map(|i| 0x80 + i as u8)
+}
+
+fn cp1251_char(byte: u8) -> Option<char> {
+    match byte {
+        0x00..=0x7F => Some(byte as char),
+        0xC0..=0xFF => char::from_u32(0x0410 + (byte - 0xC0) as u32),
+        _ => CP1251_EXTRA.iter().find(|(b, _)| *b == byte).map(|(_, c)| *c),
+    }
+}
+
+/// UTF-8 Cyrillic that was decoded as Windows-1252: "Ð¤Ð°ÐºÑ‚ÑƒÑ€Ð°" → "Фактура".
+fn repair_utf8_as_cp1252(s: &str) -> Option<String> {
+    if !s.contains(['Ð', 'Ñ']) {
+        return None;
+    }
+    let bytes: Vec<u8> = s.chars().map(cp1252_byte).collect::<Option<_>>()?;
+    let fixed = String::from_utf8(bytes).ok()?;
+    fixed.chars().any(is_cyrillic).then_some(fixed)
+}
+
+/// Windows-1251 Cyrillic that was decoded as Latin-1: "Ôàêòóðà" → "Фактура". Only when most letters
+/// are accented Latin, so ordinary Western text (Müller, Société) is left alone.
+fn repair_cp1251_as_latin1(s: &str) -> Option<String> {
+    let letters = s.chars().filter(|c| c.is_alphabetic()).count();
+    let high = s.chars().filter(|c| ('\u{00C0}'..='\u{00FF}').contains(c)).count();
+    if high < 3 || high * 10 < letters * 6 || s.chars().any(is_cyrillic) {
+        return None;
+    }
+    s.chars()
+        .map(|c| cp1252_byte(c).and_then(cp1251_char))
+        .collect::<Option<String>>()
+}
+
+/// Text with mis-decoded Cyrillic repaired; anything else is returned unchanged.
+pub fn repair_mojibake(s: &str) -> String {
+    if s.is_ascii() {
+        return s.to_string();
+    }
+    repair_utf8_as_cp1252(s)
+        .or_else(|| repair_cp1251_as_latin1(s))
+        .unwrap_or_else(|| s.to_string())
+}
+
+/// Display text of a calamine cell: dates as dd.mm.yyyy (honouring the 1904 system), strings with
+/// encoding damage repaired, errors and empty cells as "".
+pub fn cell_text(cell: &Data, date1904: bool) -> String {
+    match cell {
+        Data::DateTime(dt) => serial_to_date_text(dt.as_f64(), date1904),
+        Data::DateTimeIso(s) | Data::DurationIso(s) => s.clone(),
+        Data::String(s) => repair_mojibake(s),
+        Data::Bool(b) => b.to_string(),
+        Data::Error(_) | Data::Empty => String::new(),
+        other => other.as_string().unwrap_or_default(),
+    }
+}
@@ -0,0 +1,100 @@
+//! Ledger workbook formats, detected from the file's content rather than its extension. Appends edit the
+//! OOXML package (edit_xlsx plus zip-level rewrites), which covers .xlsx and macro-enabled .xlsm, whose
+//! VBA project is carried over (see `excel::restore_vba_workbook_pr`). Legacy .xls, binary .xlsb and
+//! .ods are only read, through calamine: schemas can be analyzed and profiles made, but appends fail with
+//! `AppError::ConversionRequired` until the file is saved as .xlsx (or copied with `convert_to_xlsx`).
+
+use crate::error::AppError;
+use crate::excel;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use zip::ZipArchive;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorkbookFormat {
+    Xlsx,
+    Xlsm,
+    Xlsb,
+    Xls,
+    Ods,
+}
+
+/// Compound File header of Excel 97-2003 workbooks.
+const CFB_MAGIC: [u8; 8] = [0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1];
+
+impl WorkbookFormat {
+    /// Whether appends can write to this format.
+    pub fn appendable(self) -> bool {
+        matches!(self, WorkbookFormat::Xlsx | WorkbookFormat::Xlsm)
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            WorkbookFormat::Xlsx => "an Excel workbook (.xlsx)",
+            WorkbookFormat::Xlsm => "a macro-enabled Excel workbook (.xlsm)",
+            WorkbookFormat::Xlsb => "a binary Excel workbook (.xlsb)",
+            WorkbookFormat::Xls => "an Excel 97-2003 workbook (.xls)",
+            WorkbookFormat::Ods => "an OpenDocument spreadsheet (.ods)",
+        }
+    }
+}
+
+/// Format of the workbook at `path`; None when it is none of the above.
+pub fn detect(path: &Path) -> Result<Option<WorkbookFormat>, AppError> {
+    let mut file = std::fs::File::open(path).map_err(AppError::io)?;
+    let mut magic = [0u8; 8];
+    let read = file.read(&mut magic).map_err(AppError::io)?;
+    if read == magic.len() && magic == CFB_MAGIC {
+        return Ok(Some(WorkbookFormat::Xls));
+    }
+    if !magic.starts_with(b"PK\x03\x04") {
+        return Ok(None);
+    }
+    let Ok(archive) = ZipArchive::new(file) else {
+        return Ok(None);
+    };
+    let has = |name: &str| archive.file_names().any(|n| n == name);
+    Ok(if has("xl/vbaProject.bin") {
+        Some(WorkbookFormat::Xlsm)
+    } else if has("xl/workbook.xml") {
+        Some(WorkbookFormat::Xlsx)
+    } else if has("xl/workbook.bin") {
+        Some(WorkbookFormat::Xlsb)
+    } else if has("content.xml") && has("mimetype") {
+        Some(WorkbookFormat::Ods)
+    } else {
+        None
+    })
+}
+
+/// Fail with `ConversionRequired` when appends cannot write to `path`. Missing files and unknown formats
+/// pass, so the caller reports them as before.
+pub fn ensure_appendable(path: &Path) -> Result<(), AppError> {
+    if !path.exists() {
+        return Ok(());
+    }
+    match detect(path)? {
+        Some(format) if !format.appendable() => Err(AppError::ConversionRequired(format!(
+            "'{}' is {}, which can be read but not appended to. Open it in Excel and save it as an Excel \
+             Workbook (.xlsx), or convert a copy here (values only), then point the profile at the .xlsx.",
+            path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default(),
+            format.description()
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Copy the values of a read-only workbook into `<name>.xlsx` next to it (`<name>_2.xlsx` and so on when
+/// taken). Returns the new path.
+pub fn convert_to_xlsx(path: &Path) -> Result<PathBuf, AppError> {
+    match detect(path)? {
+        Some(format) if !format.appendable() => {}
+        Some(_) => return Err(AppError::Validation("The workbook is already .xlsx or .xlsm.".to_string())),
+        None => return Err(AppError::Validation("Not a workbook that can be read.".to_string())),
+    }
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let stem = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_else(|| "Converted".to_string());
+    let target = excel::unique_export_path_in(dir, &stem, "xlsx");
+    excel::copy_values_to_xlsx(path, &target)?;
+    Ok(target)
+}
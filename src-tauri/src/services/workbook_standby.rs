@@ -0,0 +1,146 @@
+//! Warm standby of the most recently used profile workbook for the review → append flow. Parsing a large
+//! ledger takes most of a single-row append, so the last workbook appended to is kept parsed in memory and
+//! the next append starts from it. The copy is only used while the file's mtime and size are unchanged
+//! (an edit in Excel drops it), and because an append changes both the copy and the file, the file is
+//! parsed again right after each append, while the user reviews the next document.
+//!
+//! edit_xlsx workbooks cannot move between threads, so the copy lives on one thread that runs the jobs.
+
+use crate::error::AppError;
+use std::collections::HashMap;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::sync::{Arc, OnceLock};
+use std::time::SystemTime;
+
+/// Formulas of one sheet by (1-based row, 0-based column).
+pub type SheetFormulas = Arc<HashMap<(u32, u32), String>>;
+
+/// A parsed workbook and the formulas of the sheets appended to (edit_xlsx does not expose them).
+pub struct WarmWorkbook {
+    path: PathBuf,
+    pub workbook: edit_xlsx::Workbook,
+    formulas: HashMap<String, SheetFormulas>,
+}
+
+impl WarmWorkbook {
+    fn open(path: &Path, sheets: Vec<String>) -> Result<Self, AppError> {
+        let workbook = catch_unwind(|| crate::excel::open_edit_workbook(path))
+            .map_err(|_| AppError::Excel(crate::excel::LIBRARY_STOPPED.to_string()))??;
+        let mut warm = WarmWorkbook { path: path.to_path_buf(), workbook, formulas: HashMap::new() };
+        for sheet in sheets {
+            warm.formulas(&sheet);
+        }
+        Ok(warm)
+    }
+
+    pub fn formulas(&mut self, sheet_name: &str) -> SheetFormulas {
+        let path = &self.path;
+        self.formulas
+            .entry(sheet_name.to_string())
+            .or_insert_with(|| Arc::new(crate::excel::sheet_formulas(path, sheet_name)))
+            .clone()
+    }
+}
+
+struct Standby {
+    /// mtime and size of the file when it was parsed.
+    stamp: (SystemTime, u64),
+    warm: WarmWorkbook,
+}
+
+type Job = Box<dyn FnOnce(&mut Option<Standby>) + Send>;
+
+fn jobs() -> &'static mpsc::Sender<Job> {
+    static JOBS: OnceLock<mpsc::Sender<Job>> = OnceLock::new();
+    JOBS.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        std::thread::Builder::new()
+            .name("workbook-standby".to_string())
+            .spawn(move || {
+                let mut standby = None;
+                for job in receiver {
+                    job(&mut standby);
+                }
+            })
+            .expect("spawn workbook standby thread");
+        sender
+    })
+}
+
+fn stamp(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// Parse `path` into the standby (with the formulas of `sheets`), or clear it when that fails.
+fn reload(standby: &mut Option<Standby>, path: &Path, sheets: Vec<String>) {
+    *standby = None;
+    let Some(before) = stamp(path) else {
+        return;
+    };
+    if let Ok(warm) = WarmWorkbook::open(path, sheets) {
+        // A file that changed while it was read is parsed again on the next call.
+        *standby = Some(Standby { stamp: before, warm });
+    }
+}
+
+/// The standby's workbook when it is `path` and the file has not changed since it was parsed.
+fn take_current(standby: &mut Option<Standby>, path: &Path) -> Option<WarmWorkbook> {
+    let current = stamp(path)?;
+    match standby.take() {
+        Some(s) if s.warm.path == path && s.stamp == current => Some(s.warm),
+        _ => None,
+    }
+}
+
+/// Run `f` on the parsed workbook at `path`: the standby copy when the file is unchanged, else a fresh
+/// parse. `f` may change and save it; either way the file is parsed again afterwards for the next call.
+/// Open errors are returned as they are; a panic in edit_xlsx gives an `Excel` error.
+pub fn with_workbook<T: Send + 'static>(
+    path: &Path,
+    f: impl FnOnce(&mut WarmWorkbook) -> T + Send + 'static,
+) -> Result<T, AppError> {
+    let path = path.to_path_buf();
+    let (reply, result) = mpsc::channel();
+    let job: Job = Box::new(move |standby| {
+        let sheets: Vec<String> = standby.as_ref().map(|s| s.warm.formulas.keys().cloned().collect()).unwrap_or_default();
+        let warm = match take_current(standby, &path) {
+            Some(warm) => Ok(warm),
+            None => WarmWorkbook::open(&path, Vec::new()),
+        };
+        let outcome = warm.and_then(|mut warm| {
+            let value = catch_unwind(AssertUnwindSafe(|| f(&mut warm)));
+            let sheets = warm.formulas.keys().cloned().collect::<Vec<_>>();
+            value.map(|v| (v, sheets)).map_err(|_| AppError::Excel(crate::excel::LIBRARY_STOPPED.to_string()))
+        });
+        let sheets = outcome.as_ref().map(|(_, s)| s.clone()).unwrap_or(sheets);
+        let _ = reply.send(outcome.map(|(v, _)| v));
+        reload(standby, &path, sheets);
+    });
+    jobs()
+        .send(job)
+        .map_err(|_| AppError::Internal("The workbook standby is not running.".to_string()))?;
+    result
+        .recv()
+        .map_err(|_| AppError::Internal("The workbook standby stopped.".to_string()))?
+}
+
+/// Parse `path` (and the formulas of `sheet_name`) into the standby in the background, unless it is
+/// already there and current. Called when a profile is opened for review.
+pub fn prewarm(path: &Path, sheet_name: &str) {
+    let path = path.to_path_buf();
+    let sheet_name = sheet_name.to_string();
+    let _ = jobs().send(Box::new(move |standby| {
+        match take_current(standby, &path) {
+            Some(mut warm) => {
+                let current = stamp(&path);
+                warm.formulas(&sheet_name);
+                *standby = current.map(|stamp| Standby { stamp, warm });
+            }
+            None => reload(standby, &path, vec![sheet_name]),
+        }
+    }));
+}
+
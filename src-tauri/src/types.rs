@@ -1,9 +1,15 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct OcrLine {
     pub text: String,
     pub confidence: Option<f64>,
+    /// 1-based page of the line, when Azure reported where it is.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page: Option<u32>,
+    /// Outline as x1,y1,…,x4,y4 clockwise from top-left, in the page's unit (inches for PDFs, pixels for images).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub polygon: Option<Vec<f64>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -36,18 +42,48 @@ pub struct HistoryRecord {
     pub error_message: Option<String>,
 }
 
+/// One cell written to a workbook, recorded in the write_log table.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CellWrite {
+    pub sheet_name: String,
+    pub cell_ref: String,
+    pub row_number: u32,
+    /// Value that was overwritten (None for a previously empty cell).
+    pub old_value: Option<String>,
+    pub new_value: String,
+}
+
+impl CellWrite {
+    pub fn new(sheet_name: &str, cell_ref: &str, row_number: u32, old_value: Option<String>, new_value: &str) -> Self {
+        CellWrite {
+            sheet_name: sheet_name.to_string(),
+            cell_ref: cell_ref.to_string(),
+            row_number,
+            old_value,
+            new_value: new_value.to_string(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RowCell {
     pub column: String,
     pub value: String,
 }
 
-/// Single field from Azure prebuilt-invoice (value + optional confidence).
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Single field from Azure prebuilt-invoice (value + optional confidence and where it was read).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct InvoiceFieldValue {
     pub value: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub confidence: Option<f64>,
+    /// 1-based page the value was read from (for highlighting it on the preview).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page: Option<u32>,
+    /// Outline of the value on that page, same format as `OcrLine::polygon`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub polygon: Option<Vec<f64>>,
 }
 
 /// Structured invoice data from Azure prebuilt-invoice, keyed by our internal field keys.
@@ -73,6 +109,39 @@ pub struct OcrInvoiceResult {
     /// When >1, frontend can warn that the PDF likely contains multiple invoices.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub document_count: Option<u32>,
+    /// Suspicious values found by `services::validation` (empty when everything checks out).
+    #[serde(default)]
+    pub warnings: Vec<ValidationWarning>,
+    /// OCR values replaced by learned vendor corrections or vendor master data (`services::vendors`).
+    #[serde(default)]
+    pub applied_corrections: Vec<AppliedCorrection>,
+    /// Full text of text-only (prebuilt-read) results, for `services::llm_extraction`. Not sent to the UI.
+    #[serde(skip)]
+    pub raw_text: Option<String>,
+    /// How the fields were read: "azure", "textract", "mock" or "local_text_layer" (the PDF's own text, no
+    /// OCR call).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extraction_path: Option<String>,
+}
+
+/// A check on extracted fields that did not pass. Warnings never block an append; the UI flags them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValidationWarning {
+    /// "amounts_mismatch", "invalid_date", "unusual_tax_rate", "missing_document_number", or from a field
+    /// schema "missing_required", "invalid_number" and "pattern_mismatch".
+    pub code: String,
+    /// Field key the warning is about (the first of several for amount checks).
+    pub field_key: String,
+    pub message: String,
+}
+
+/// A learned correction or vendor master-data value applied to a scan result, so the UI can show what was changed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppliedCorrection {
+    pub field_key: String,
+    /// Value as extracted by OCR.
+    pub ocr_value: String,
+    pub value: String,
 }
 
 /// Information about a failed scan attempt.
@@ -83,9 +152,22 @@ pub struct FailedScan {
     pub error: String,
 }
 
+/// A file of a batch that was not scanned because its content was scanned before.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SkippedDuplicate {
+    pub file_path: String,
+    pub file_name: String,
+    pub sha256: String,
+    /// History record of the earlier scan; None when the file repeats an earlier file of the batch.
+    pub history_id: Option<i64>,
+    pub scanned_at: Option<String>,
+}
+
 /// Result of batch scanning, containing both successful and failed scans.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BatchScanResult {
     pub successes: Vec<InvoiceData>,
     pub failures: Vec<FailedScan>,
+    #[serde(default)]
+    pub skipped_duplicates: Vec<SkippedDuplicate>,
 }
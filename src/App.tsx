@@ -1,5 +1,6 @@
 import { lazy, Suspense, useEffect } from "react";
 import { getCurrentWindow } from "@tauri-apps/api/window";
+import { listen } from "@tauri-apps/api/event";
 import { ToastProvider } from "@/context/ToastContext";
 import { ToastContainer } from "@/components/Toast";
 import { useToast } from "@/context/ToastContext";
@@ -7,6 +8,7 @@ import { AppProvider, useApp } from "@/context/AppContext";
 import { AuthProvider, useAuth } from "@/context/AuthContext";
 import { Home, History, Settings, LogOut, User, SunMedium, Moon, Monitor, Shield } from "lucide-react";
 import { ErrorBoundary } from "@/components/ErrorBoundary";
+import { getDbRecoveryStatus, recoverDatabase, takeAppWarnings } from "@/services/api";
 import styles from "./App.module.css";
 
 const HomePage = lazy(() => import("@/pages/Home").then((m) => ({ default: m.Home })));
@@ -61,6 +63,78 @@ function AppContent() {
     };
   }, [showToast]);
 
+  useEffect(() => {
+    // On launch: if the database failed to open (corrupted or locked), offer recovery instead of failing silently.
+    let cancelled = false;
+    (async () => {
+      try {
+        const status = await getDbRecoveryStatus();
+        if (cancelled || !status) return;
+
+        const action: "retry" | "restore" | "rebuild" =
+          status.kind !== "corrupted" ? "retry" : status.backups.length > 0 ? "restore" : "rebuild";
+        const label =
+          action === "retry" ? "Retry" : action === "restore" ? "Restore latest backup" : "Rebuild database";
+        const reason =
+          status.kind === "locked"
+            ? "The database is locked by another program. Close other Document Scanner windows and retry."
+            : status.kind === "failed"
+              ? `The database could not be opened (${status.error}). The file was left as it is.`
+              : "The database could not be opened and may be damaged.";
+        showToast(reason, "error", {
+          action: {
+            label,
+            onAction: async () => {
+              try {
+                const moved = await recoverDatabase(action);
+                showToast(
+                  moved ? `Database recovered. The damaged file was kept at ${moved}.` : "Database opened.",
+                  "success"
+                );
+              } catch (e) {
+                showToast(`Recovery failed: ${e instanceof Error ? e.message : String(e)}`, "error");
+              }
+            },
+          },
+        });
+      } catch {
+        // Not in Tauri (e.g. browser)
+      }
+    })();
+    return () => {
+      cancelled = true;
+    };
+  }, [showToast]);
+
+  useEffect(() => {
+    // Side-work failures (audit log, webhooks, background polls) raised before or after the window listens.
+    let cancelled = false;
+    let unlisten: (() => void) | undefined;
+    const drain = async () => {
+      try {
+        const warnings = await takeAppWarnings();
+        if (cancelled) return;
+        for (const w of warnings) showToast(w.message, "error");
+      } catch {
+        // Not in Tauri (e.g. browser)
+      }
+    };
+    (async () => {
+      try {
+        const stop = await listen("app-warning", () => void drain());
+        if (cancelled) stop();
+        else unlisten = stop;
+      } catch {
+        // Not in Tauri (e.g. browser)
+      }
+      await drain();
+    })();
+    return () => {
+      cancelled = true;
+      unlisten?.();
+    };
+  }, [showToast]);
+
   const navItems = [
     { id: "home" as const, icon: Home, label: "Home" },
     { id: "history" as const, icon: History, label: "History" },
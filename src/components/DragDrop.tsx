@@ -1,7 +1,6 @@
 import { useState, useCallback, useEffect } from "react";
 import { getCurrentWindow } from "@tauri-apps/api/window";
-import { open } from "@tauri-apps/plugin-dialog";
-import { runOcrInvoice, addHistoryRecord, buildExtractedDataWithConfidence } from "@/services/api";
+import { runOcrInvoice, addHistoryRecord, buildExtractedDataWithConfidence, pickOpenPaths } from "@/services/api";
 import { useApp } from "@/context/AppContext";
 import { useToast } from "@/context/ToastContext";
 import { toFriendlyScanError } from "@/utils/friendlyErrors";
@@ -15,8 +14,10 @@ const ACCEPT_TYPES = [
   "image/jpg",
   "image/png",
   "image/tiff",
+  "image/heic",
+  "image/heif",
 ];
-const ACCEPT_EXT = [".pdf", ".jpg", ".jpeg", ".png", ".tiff", ".tif"];
+const ACCEPT_EXT = [".pdf", ".jpg", ".jpeg", ".png", ".tiff", ".tif", ".heic", ".heif"];
 
 // Azure has payload size limits; reject obviously huge files from the HTML drag/drop
 // path so the user gets a clear message before we call the backend.
@@ -73,6 +74,7 @@ export function DragDrop() {
             extracted_data: extractedData,
             status: "pending",
             folder_id: defaultFolderId ?? undefined,
+            source_path: filePath,
           });
         } catch {
           // non-fatal
@@ -138,7 +140,7 @@ export function DragDrop() {
             const fileName = filePath.split(/[/\\]/).pop() ?? filePath;
             processFile(filePath, fileName);
           } else {
-            showError("Please drop a PDF or image (JPG, PNG, TIFF).");
+            showError("Please drop a PDF or image (JPG, PNG, TIFF, HEIC).");
           }
         }
       }
@@ -154,7 +156,7 @@ export function DragDrop() {
       setDragging(false);
       const file = e.dataTransfer?.files?.[0];
       if (!file || !isAcceptedFile(file)) {
-        showError("Please drop a PDF or image (JPG, PNG, TIFF).");
+        showError("Please drop a PDF or image (JPG, PNG, TIFF, HEIC).");
         return;
       }
       if (file.size > MAX_FILE_SIZE_BYTES) {
@@ -172,16 +174,15 @@ export function DragDrop() {
   );
 
   const handleBrowse = useCallback(async () => {
-    const selected = await open({
-      multiple: false,
+    const [selected] = await pickOpenPaths({
       filters: [
         {
           name: "Documents",
-          extensions: ["pdf", "jpg", "jpeg", "png", "tiff", "tif"],
+          extensions: ["pdf", "jpg", "jpeg", "png", "tiff", "tif", "heic", "heif"],
         },
       ],
     });
-    if (selected && typeof selected === "string") {
+    if (selected) {
       const fileName = selected.split(/[/\\]/).pop() ?? selected;
       await processFile(selected, fileName);
     }
@@ -242,7 +243,7 @@ export function DragDrop() {
             <line x1="12" y1="3" x2="12" y2="15" />
           </svg>
           <p className={styles.title}>Drop a document here</p>
-          <p className={styles.sub}>PDF, JPG, PNG, or TIFF</p>
+          <p className={styles.sub}>PDF, JPG, PNG, TIFF or HEIC</p>
           <button type="button" className={styles.browse} onClick={handleBrowse}>
             Choose file
           </button>
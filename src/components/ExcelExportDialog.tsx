@@ -1,8 +1,8 @@
 import { useState, useCallback } from "react";
-import { save } from "@tauri-apps/plugin-dialog";
 import {
   exportInvoicesToNewExcel,
   exportToNewExcelWithColumns,
+  pickSavePath,
   writeFileBase64,
 } from "@/services/api";
 import { exportPlataToNewTableBuffer } from "@/services/plataExportExcelJS";
@@ -59,7 +59,7 @@ export function ExcelExportDialog({
       const defaultName = `${defaultNameBase}_${new Date().toISOString().slice(0, 10)}_${Date.now()
         .toString()
         .slice(-6)}.xlsx`;
-      const path = await save({
+      const path = await pickSavePath({
         filters: [{ name: "Excel", extensions: ["xlsx"] }],
         defaultPath: defaultName,
         title: MK.chooseLocation,
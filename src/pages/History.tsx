@@ -2,7 +2,7 @@ import { useCallback, useEffect, useState } from "react";
 import { useApp } from "@/context/AppContext";
 import { useToast } from "@/context/ToastContext";
 import { useDebounce } from "@/hooks/useDebounce";
-import { getHistory, getFolders, createFolder, deleteFolder, assignHistoryToFolder, deleteHistoryRecord } from "@/services/api";
+import { getHistory, getHistoryCount, getFolders, createFolder, deleteFolder, assignHistoryToFolder, deleteHistoryRecord } from "@/services/api";
 import type { ExtractedField } from "@/shared/types";
 import type { OcrResult } from "@/shared/types";
 import {
@@ -172,7 +172,7 @@ export function History() {
   const { setScreen, setReview, historyPageSize } = useApp();
   const { success, error: showError } = useToast();
   const [rows, setRows] = useState<HistoryRow[]>([]);
-  const [folders, setFolders] = useState<[number, string, string][]>([]);
+  const [folders, setFolders] = useState<[number, string, string, number | null][]>([]);
   const [search, setSearch] = useState("");
   const [folderFilter, setFolderFilter] = useState<number | null>(null); // null = Сите (all)
   const [loading, setLoading] = useState(true);
@@ -180,6 +180,7 @@ export function History() {
   const [folderName, setFolderName] = useState("");
   const [saving, setSaving] = useState(false);
   const [currentPage, setCurrentPage] = useState(0);
+  const [totalCount, setTotalCount] = useState(0);
 
   const debouncedSearch = useDebounce(search, 300);
   const debouncedFolder = useDebounce(folderFilter, 200);
@@ -192,14 +193,24 @@ export function History() {
 
   const load = useCallback(() => {
     setLoading(true);
-    getHistory({
+    const filters = {
       search: debouncedSearch || undefined,
       folder_id: debouncedFolder,
-    })
-      .then(setRows)
-      .catch(() => setRows([]))
+    };
+    Promise.all([
+      getHistory({ ...filters, limit: historyPageSize, offset: currentPage * historyPageSize }),
+      getHistoryCount(filters),
+    ])
+      .then(([pageRows, count]) => {
+        setRows(pageRows);
+        setTotalCount(count);
+      })
+      .catch(() => {
+        setRows([]);
+        setTotalCount(0);
+      })
       .finally(() => setLoading(false));
-  }, [debouncedSearch, debouncedFolder]);
+  }, [debouncedSearch, debouncedFolder, currentPage, historyPageSize]);
 
   useEffect(() => {
     loadFolders();
@@ -213,11 +224,7 @@ export function History() {
     setCurrentPage(0);
   }, [debouncedSearch, debouncedFolder]);
 
-  const totalPages = Math.max(1, Math.ceil(rows.length / historyPageSize));
-  const paginatedRows = rows.slice(
-    currentPage * historyPageSize,
-    (currentPage + 1) * historyPageSize
-  );
+  const totalPages = Math.max(1, Math.ceil(totalCount / historyPageSize));
 
   const openCreateFolder = useCallback(() => {
     setFolderName("");
@@ -351,7 +358,7 @@ export function History() {
                 </tr>
               </thead>
               <tbody>
-                {paginatedRows.map((row) => {
+                {rows.map((row) => {
                   const [id, createdAt, docType, filePathOrName] = row;
                   const name = fileNameFromPath(filePathOrName);
                   return (
@@ -421,7 +428,7 @@ export function History() {
                 ←
               </button>
               <span className={styles.paginationInfo}>
-                {currentPage * historyPageSize + 1}–{Math.min((currentPage + 1) * historyPageSize, rows.length)} од {rows.length}
+                {currentPage * historyPageSize + 1}–{Math.min((currentPage + 1) * historyPageSize, totalCount)} од {totalCount}
               </span>
               <button
                 type="button"
@@ -1,10 +1,9 @@
 import { useState, useCallback, useEffect, useRef } from "react";
-import { open } from "@tauri-apps/plugin-dialog";
 import { getCurrentWindow } from "@tauri-apps/api/window";
 import { Receipt, Calculator, Percent, CreditCard, Upload, FileText, X, LucideIcon } from "lucide-react";
 import { useApp } from "@/context/AppContext";
 import { useToast } from "@/context/ToastContext";
-import { runOcrInvoice } from "@/services/api";
+import { pickOpenPaths, runOcrInvoice } from "@/services/api";
 import { logAuditEvent, checkRateLimitBeforeScan } from "@/services/audit";
 import { DOCUMENT_TYPE_CHOICES } from "@/shared/constants";
 import type { DocumentType, InvoiceData } from "@/shared/types";
@@ -18,6 +17,14 @@ const ICON_MAP: Record<string, LucideIcon> = {
   CreditCard,
 };
 
+/** PDFs and scanned images; the backend detects the real format from the file content. */
+const DOCUMENT_EXTENSIONS = ["pdf", "jpg", "jpeg", "png", "tiff", "tif", "heic", "heif"];
+
+function isSupportedDocument(path: string): boolean {
+  const ext = path.split(".").pop()?.toLowerCase() ?? "";
+  return DOCUMENT_EXTENSIONS.includes(ext);
+}
+
 function getFileName(path: string): string {
   return path.split(/[/\\]/).pop() ?? path;
 }
@@ -34,7 +41,7 @@ export function Home() {
   const effectiveDocumentType: DocumentType = chosenDocumentType ?? defaultDocumentType ?? "generic";
 
   const addPdfPaths = useCallback((paths: string[]) => {
-    const pdfOnly = paths.filter((p) => p.toLowerCase().endsWith(".pdf"));
+    const pdfOnly = paths.filter(isSupportedDocument);
     if (pdfOnly.length === 0) return;
     setSelectedFiles((prev) => {
       const combined = [...prev];
@@ -46,12 +53,11 @@ export function Home() {
   }, []);
 
   const handleSelectPdfs = useCallback(async () => {
-    const selected = await open({
+    const paths = await pickOpenPaths({
       multiple: true,
-      filters: [{ name: "PDF", extensions: ["pdf"] }],
+      filters: [{ name: "Documents", extensions: DOCUMENT_EXTENSIONS }],
     });
-    if (!selected) return;
-    const paths = Array.isArray(selected) ? selected : [selected];
+    if (paths.length === 0) return;
     addPdfPaths(paths);
   }, [addPdfPaths]);
 
@@ -302,7 +308,7 @@ export function Home() {
                 const file = items[i].getAsFile();
                 if (!file) continue;
                 const path = (file as File & { path?: string }).path;
-                if (path && file.name.toLowerCase().endsWith(".pdf")) paths.push(path);
+                if (path && isSupportedDocument(file.name)) paths.push(path);
               }
               addPdfPaths(paths);
             }}
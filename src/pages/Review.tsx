@@ -3,13 +3,14 @@ import { useApp } from "@/context/AppContext";
 import { useToast } from "@/context/ToastContext";
 import { DataCard } from "@/components/DataCard";
 import { DocumentPreview } from "@/components/DocumentPreview";
-import { save } from "@tauri-apps/plugin-dialog";
 import {
   updateHistoryStatus,
   updateHistoryRecord,
+  saveInvoiceEdits,
   deleteHistoryRecord,
   buildExtractedDataWithConfidence,
   exportInvoicesToNewExcel,
+  pickSavePath,
   writeFileBase64,
 } from "@/services/api";
 import { exportTaxBalanceToNewTableBuffer } from "@/services/taxBalanceExportExcelJS";
@@ -138,6 +139,14 @@ export function Review() {
         extracted_data: extractedData,
         status: review.status ?? "pending",
       });
+      // Audit trail of hand corrections; the record itself is already saved.
+      const original = new Map(review.fields.map((f) => [f.key, fixDisplayValue(f.value)]));
+      const edits = fields
+        .filter((f) => (original.get(f.key) ?? "").trim() !== f.value.trim())
+        .map((f) => ({ fieldKey: f.key, originalValue: original.get(f.key) ?? null, correctedValue: f.value }));
+      if (edits.length > 0) {
+        await saveInvoiceEdits(review.historyId, edits).catch(() => {});
+      }
       success("Ставката е зачувана.");
       setReview(null);
       setScreen("history");
@@ -156,7 +165,7 @@ export function Review() {
       const docType = docTypeId;
       if (docType === "smetka") {
         // Даночен биланс: new workbook with only the table (no logo/header). Values only to avoid Excel repair issues.
-        const path = await save({
+        const path = await pickSavePath({
           filters: [{ name: "Excel", extensions: ["xlsx"] }],
           defaultPath: `Даночен_биланс_${new Date().toISOString().slice(0, 10)}.xlsx`,
           title: "Зачувај како",
@@ -183,7 +192,7 @@ export function Review() {
         const defaultName = `Фактури_${new Date().toISOString().slice(0, 10)}_${Date.now()
           .toString()
           .slice(-6)}.xlsx`;
-        const path = await save({
+        const path = await pickSavePath({
           filters: [{ name: "Excel", extensions: ["xlsx"] }],
           defaultPath: defaultName,
           title: "Зачувај како",
@@ -61,7 +61,7 @@ export function Settings() {
   const isLight = resolvedTheme === "light";
   const designs = isLight ? LIGHT_DESIGNS : DARK_DESIGNS;
 
-  const [folders, setFolders] = useState<[number, string, string][]>([]);
+  const [folders, setFolders] = useState<[number, string, string, number | null][]>([]);
   useEffect(() => {
     getFolders().then(setFolders).catch(() => setFolders([]));
   }, []);
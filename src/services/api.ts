@@ -1,8 +1,46 @@
-import { invoke } from "@tauri-apps/api/core";
-import type { OcrResult, InvoiceData, OcrInvoiceResult } from "@/shared/types";
+import { invoke as tauriInvoke, type InvokeArgs } from "@tauri-apps/api/core";
+import type { OcrResult, InvoiceData, OcrInvoiceResult, ValidationWarning, AppliedCorrection } from "@/shared/types";
 import type { ExtractedField } from "@/shared/types";
 import { parseAzureExtraction, parseAzureFieldsWithConfidence } from "@/utils/parseAzureExtraction";
 
+export type AppErrorCode =
+  | "OCR"
+  | "EXCEL"
+  | "DB"
+  | "IO"
+  | "VALIDATION"
+  | "PATH_NOT_ALLOWED"
+  | "SCHEMA_CHANGED"
+  | "CONVERSION_REQUIRED"
+  | "INTERNAL";
+
+/** Error rejected by backend commands; branch on `code` instead of the message text. */
+export class AppError extends Error {
+  readonly code: AppErrorCode;
+  /** Data of the error when the code carries some (SCHEMA_CHANGED: a SchemaDiff). */
+  readonly details?: unknown;
+
+  constructor(code: AppErrorCode, message: string, details?: unknown) {
+    super(message);
+    this.name = "AppError";
+    this.code = code;
+    this.details = details;
+  }
+}
+
+/** Tauri invoke that turns the backend's `{ code, message, details? }` errors into AppError. */
+async function invoke<T>(cmd: string, args?: InvokeArgs): Promise<T> {
+  try {
+    return await tauriInvoke<T>(cmd, args);
+  } catch (e) {
+    if (e && typeof e === "object" && "code" in e && "message" in e) {
+      const { code, message, details } = e as { code: AppErrorCode; message: string; details?: unknown };
+      throw new AppError(code, message, details);
+    }
+    throw e;
+  }
+}
+
 /** Build extracted_data payload for history: key-value plus _confidence so the Review page can show confidence %. */
 export function buildExtractedDataWithConfidence(fields: ExtractedField[]): Record<string, unknown> {
   const data: Record<string, unknown> = Object.fromEntries(fields.map((f) => [f.key, f.value]));
@@ -41,22 +79,889 @@ export async function getAppVersion(): Promise<string> {
   return invoke<string>("get_app_version");
 }
 
+/** A failure of side work (audit log, webhook, background poll) that did not fail its command. */
+export interface AppWarning {
+  source: string;
+  message: string;
+  createdAt: string;
+}
+
+/** Warnings not shown yet; they are cleared. Announced with the "app-warning" event. */
+export async function takeAppWarnings(): Promise<AppWarning[]> {
+  return invoke<AppWarning[]>("take_app_warnings");
+}
+
 export async function getAzureStatus(): Promise<string> {
   return invoke<string>("get_azure_status");
 }
 
+export interface AppSettings {
+  azureEndpoint: string | null;
+  /** The key itself is never returned. */
+  azureKeyConfigured: boolean;
+  defaultExportFolder: string | null;
+  pollIntervalSecs: number;
+  /** Tries per Azure request (1 = no retry). */
+  retryMaxAttempts: number;
+  /** First backoff delay; doubles per retry unless Azure sends Retry-After. */
+  retryBaseDelayMs: number;
+  language: "mk" | "en" | null;
+  /** Last-row detection: 0 searches the whole used range; N stops after N empty rows below the header. */
+  lastRowGapRows: number;
+  /** Read PDFs with their own text layer locally instead of sending them to OCR. */
+  preferLocalTextLayer: boolean;
+  /** Recorded on scan sessions; null uses the OS login name. */
+  operatorName: string | null;
+  workerBudgets: WorkerBudgets;
+}
+
+/** Concurrent jobs per class; OCR is served first, maintenance last. */
+export interface WorkerBudgets {
+  ocr: number;
+  appends: number;
+  previews: number;
+  maintenance: number;
+}
+
+/** Omitted fields are left unchanged; an empty string clears a value. */
+export interface SettingsUpdate {
+  azureEndpoint?: string;
+  azureKey?: string;
+  defaultExportFolder?: string;
+  pollIntervalSecs?: number;
+  retryMaxAttempts?: number;
+  retryBaseDelayMs?: number;
+  language?: "mk" | "en" | "";
+  lastRowGapRows?: number;
+  preferLocalTextLayer?: boolean;
+  operatorName?: string;
+  workerBudgets?: WorkerBudgets;
+}
+
+export async function getSettings(): Promise<AppSettings> {
+  return invoke<AppSettings>("get_settings");
+}
+
+/** Save settings (Azure key is stored encrypted); returns the updated settings. */
+export async function setSettings(update: SettingsUpdate): Promise<AppSettings> {
+  return invoke<AppSettings>("set_settings", { update });
+}
+
+/** Save the Azure endpoint and key (key is kept in the OS credential store). */
+export async function setAzureCredentials(endpoint: string, key: string): Promise<AppSettings> {
+  return invoke<AppSettings>("set_azure_credentials", { endpoint, key });
+}
+
+/** Resolves when Azure accepts the credentials; omit both to check the ones in use. */
+export async function testAzureCredentials(endpoint?: string, key?: string): Promise<void> {
+  return invoke("test_azure_credentials", { endpoint: endpoint ?? null, key: key ?? null });
+}
+
+export interface AzureConnectionTest {
+  ok: boolean;
+  endpoint: string | null;
+  latencyMs: number | null;
+  errorKind:
+    | "not_configured"
+    | "invalid_key"
+    | "wrong_region"
+    | "endpoint_not_found"
+    | "network"
+    | "model_missing"
+    | "other"
+    | null;
+  error: string | null;
+  analyzers: { documentType: string | null; analyzerId: string; available: boolean }[];
+}
+
+/** Check the configured Azure connection and analyzers (optionally a specific analyzer id). */
+export async function testAzureConnection(analyzerId?: string): Promise<AzureConnectionTest> {
+  return invoke<AzureConnectionTest>("test_azure_connection", { analyzerId: analyzerId ?? null });
+}
+
+/** Local pre-check of a document before it is sent to Azure. */
+export interface DocumentInspection {
+  mimeType: string;
+  fileSizeBytes: number;
+  pageCount: number;
+  /** Page sizes: points for PDF pages, pixels for images. */
+  pages: { width: number; height: number; unit: "pt" | "px" }[];
+  analyzerId: string;
+  pricePer1000Pages: number;
+  /** Estimated analyze cost in USD. */
+  estimatedCostUsd: number;
+  /** More than 20 pages. */
+  large: boolean;
+}
+
+export async function inspectDocument(filePath: string, documentType?: string): Promise<DocumentInspection> {
+  return invoke<DocumentInspection>("inspect_document", { filePath, documentType: documentType ?? null });
+}
+
+export interface RenderedPreview {
+  pngBase64: string;
+  width: number;
+  height: number;
+  page: number;
+  cached: boolean;
+}
+
+/** PNG of one page (default: first page at 96 dpi) for History and review previews. */
+export async function renderDocumentPreview(path: string, page?: number, dpi?: number): Promise<RenderedPreview> {
+  return invoke<RenderedPreview>("render_document_preview", { path, page: page ?? null, dpi: dpi ?? null });
+}
+
+export interface RegressionReport {
+  dir: string;
+  passed: number;
+  failed: number;
+  updated: number;
+  cases: {
+    name: string;
+    documentType: string | null;
+    passed: boolean;
+    error: string | null;
+    diffs: { fieldKey: string; expected: string | null; actual: string | null }[];
+    ledgerDiffs: string[];
+  }[];
+}
+
+/** Replay stored poll responses in dir and diff the fields (builds with the "regression" feature only). */
+export async function runExtractionRegression(dir: string, update?: boolean): Promise<RegressionReport> {
+  return invoke<RegressionReport>("run_extraction_regression", { dir, update: update ?? null });
+}
+
+/** Registered document type: the analyzer it is scanned with, how results are read, where and how it exports. */
+export interface DocumentType {
+  key: string;
+  displayName: string;
+  /** Azure analyzer; null uses the built-in default. */
+  modelId: string | null;
+  extractor: "fields" | "tax_balance" | "vat_return" | "payroll";
+  defaultFolder: string | null;
+  exportLayout: "standard" | "schema";
+  builtin: boolean;
+  updatedAt: string;
+}
+
+export async function getDocumentTypes(): Promise<DocumentType[]> {
+  return invoke<DocumentType[]>("get_document_types");
+}
+
+/** Add or change a document type, e.g. a new "ispratnica" type. Returns all types. */
+export async function saveDocumentType(documentType: DocumentType): Promise<DocumentType[]> {
+  return invoke<DocumentType[]>("save_document_type", { documentType });
+}
+
+/** Delete a user-added document type; built-in types cannot be deleted. */
+export async function deleteDocumentType(key: string): Promise<boolean> {
+  return invoke<boolean>("delete_document_type", { key });
+}
+
+/** Shareable preset: document type, field schema, analyzer field mappings and optionally a profile's sheet and mapping. */
+export interface PresetBundle {
+  format: string;
+  formatVersion: number;
+  name: string;
+  description: string | null;
+  appVersion: string;
+  createdAt: string;
+  documentType: DocumentType;
+  fieldSchema: SchemaField[] | null;
+  modelMappings: FieldMapping[];
+  profile: { sheetName: string; columnMapping: unknown } | null;
+}
+
+export interface PresetImportResult {
+  name: string;
+  documentType: string;
+  schemaImported: boolean;
+  mappingsImported: number;
+  profileId: number | null;
+}
+
+/** Write a preset bundle (.dspreset) of a document type, optionally with a profile's mapping. Returns the saved path. */
+export async function exportPresetBundle(
+  path: string,
+  name: string,
+  documentType: string,
+  options?: { description?: string; profileId?: number }
+): Promise<string> {
+  return invoke<string>("export_preset_bundle", {
+    path,
+    name,
+    description: options?.description ?? null,
+    documentType,
+    profileId: options?.profileId ?? null,
+  });
+}
+
+export async function readPresetBundle(path: string): Promise<PresetBundle> {
+  return invoke<PresetBundle>("read_preset_bundle", { path });
+}
+
+/** Import a preset bundle; pass excelPath to also create its profile on that workbook. */
+export async function importPresetBundle(path: string, excelPath?: string): Promise<PresetImportResult> {
+  return invoke<PresetImportResult>("import_preset_bundle", { path, excelPath: excelPath ?? null });
+}
+
+/** Azure field name -> app field key for a custom analyzer; an empty fieldKey ignores the Azure field. */
+export interface FieldMapping {
+  azureKey: string;
+  fieldKey: string;
+}
+
+export interface ModelFieldMappings {
+  modelId: string;
+  mappings: FieldMapping[];
+}
+
+/** Custom mappings per analyzer (all analyzers when modelId is omitted). */
+export async function getModelFieldMappings(modelId?: string): Promise<ModelFieldMappings[]> {
+  return invoke<ModelFieldMappings[]>("get_model_field_mappings", { modelId: modelId ?? null });
+}
+
+/** Replace an analyzer's mappings; they apply from the next scan. An empty list removes them. */
+export async function saveModelFieldMappings(modelId: string, mappings: FieldMapping[]): Promise<ModelFieldMappings> {
+  return invoke<ModelFieldMappings>("save_model_field_mappings", { modelId, mappings });
+}
+
+export type SchemaFieldType = "text" | "date" | "amount" | "number";
+
+/** One field of a document type's schema. Fields without exportHeader are not exported. */
+export interface SchemaField {
+  key: string;
+  label: string;
+  fieldType: SchemaFieldType;
+  required: boolean;
+  pattern?: string | null;
+  exportHeader?: string | null;
+}
+
+export interface FieldSchema {
+  documentType: string;
+  fields: SchemaField[];
+  /** False for the built-in schema. */
+  custom: boolean;
+  updatedAt: string | null;
+}
+
+export async function getFieldSchemas(): Promise<FieldSchema[]> {
+  return invoke<FieldSchema[]>("get_field_schemas");
+}
+
+/** Saved schema of a document type, else the built-in one; use it to build the review form. */
+export async function getFieldSchema(documentType: string): Promise<FieldSchema> {
+  return invoke<FieldSchema>("get_field_schema", { documentType });
+}
+
+export async function saveFieldSchema(documentType: string, fields: SchemaField[]): Promise<FieldSchema> {
+  return invoke<FieldSchema>("save_field_schema", { documentType, fields });
+}
+
+/** Drop the saved schema; returns the built-in one. */
+export async function resetFieldSchema(documentType: string): Promise<FieldSchema> {
+  return invoke<FieldSchema>("reset_field_schema", { documentType });
+}
+
+/** Check edited fields against the schema before saving. */
+export async function validateWithFieldSchema(documentType: string, invoice: InvoiceData): Promise<ValidationWarning[]> {
+  return invoke<ValidationWarning[]>("validate_with_field_schema", { documentType, invoice });
+}
+
+/** New workbook with the schema's exported columns. Returns the saved path. */
+export async function exportWithFieldSchema(
+  path: string,
+  worksheetName: string,
+  documentType: string,
+  invoices: InvoiceData[],
+  profileId?: number | null
+): Promise<string> {
+  return invoke<string>("export_with_field_schema", {
+    path,
+    worksheetName,
+    documentType,
+    invoices,
+    profileId: profileId ?? null,
+  });
+}
+
+export type StorageStoreName = "archive" | "archive_cache" | "ocr_cache" | "thumbnails" | "backups" | "snapshots" | "templates" | "temp";
+
+export interface StoreUsage {
+  name: StorageStoreName;
+  path: string;
+  bytes: number;
+  fileCount: number;
+  quotaBytes: number | null;
+  overQuota: boolean;
+  /** Re-derivable artifacts that are trimmed automatically when over quota. */
+  derivable: boolean;
+}
+
+export interface StorageUsage {
+  stores: StoreUsage[];
+  databaseBytes: number;
+  totalBytes: number;
+  warnings: string[];
+}
+
+export interface StoreCleanup {
+  name: StorageStoreName;
+  removedFiles: number;
+  freedBytes: number;
+}
+
+export async function getStorageUsage(): Promise<StorageUsage> {
+  return invoke<StorageUsage>("get_storage_usage");
+}
+
+/** Quota in MB (0 = unlimited, null = default); over-quota derivable stores are trimmed immediately. */
+export async function setStorageQuota(store: StorageStoreName, quotaMb: number | null): Promise<StoreCleanup[]> {
+  return invoke<StoreCleanup[]>("set_storage_quota", { store, quotaMb });
+}
+
+export async function cleanupStorage(): Promise<StoreCleanup[]> {
+  return invoke<StoreCleanup[]>("cleanup_storage");
+}
+
+export type OcrProviderName = "azure" | "textract" | "mock";
+
+export interface OcrProviderSettings {
+  provider: OcrProviderName;
+  textractRegion: string | null;
+  textractAccessKeyId: string | null;
+  textractSecretConfigured: boolean;
+}
+
+/** Omitted fields are unchanged; an empty string clears a value. */
+export interface OcrProviderSettingsUpdate {
+  provider?: OcrProviderName;
+  textractRegion?: string;
+  textractAccessKeyId?: string;
+  textractSecretAccessKey?: string;
+}
+
+export async function getOcrProviderSettings(): Promise<OcrProviderSettings> {
+  return invoke<OcrProviderSettings>("get_ocr_provider_settings");
+}
+
+/** Switch the OCR service used by the next scan (Textract reads invoices only). */
+export async function setOcrProviderSettings(update: OcrProviderSettingsUpdate): Promise<OcrProviderSettings> {
+  return invoke<OcrProviderSettings>("set_ocr_provider_settings", { update });
+}
+
+/** OpenAI-compatible chat completions endpoint for plain-text scans (plata, generic); null endpoint = off. */
+export interface LlmSettings {
+  endpoint: string | null;
+  model: string | null;
+  apiKeyConfigured: boolean;
+}
+
+/** Omitted fields are unchanged; an empty string clears a value. */
+export interface LlmSettingsUpdate {
+  endpoint?: string;
+  model?: string;
+  apiKey?: string;
+}
+
+export async function getLlmSettings(): Promise<LlmSettings> {
+  return invoke<LlmSettings>("get_llm_settings");
+}
+
+export async function setLlmSettings(update: LlmSettingsUpdate): Promise<LlmSettings> {
+  return invoke<LlmSettings>("set_llm_settings", { update });
+}
+
+export type ArchiveBackend = "local" | "share" | "s3";
+
+export interface ArchiveSettings {
+  backend: ArchiveBackend;
+  sharePath: string | null;
+  s3Endpoint: string | null;
+  s3Region: string | null;
+  s3Bucket: string | null;
+  s3AccessKeyId: string | null;
+  s3SecretConfigured: boolean;
+}
+
+/** Omitted fields are unchanged; an empty string clears a value. */
+export interface ArchiveSettingsUpdate {
+  backend?: ArchiveBackend;
+  sharePath?: string;
+  s3Endpoint?: string;
+  s3Region?: string;
+  s3Bucket?: string;
+  s3AccessKeyId?: string;
+  s3SecretAccessKey?: string;
+}
+
+export interface ArchivedDocument {
+  historyId: number;
+  backend: ArchiveBackend;
+  /** Root folder or bucket. */
+  location: string;
+  objectKey: string;
+  fileName: string;
+  sizeBytes: number;
+  createdAt: string;
+  /** SHA-256 of the original; null for copies archived before content hashing. */
+  sha256: string | null;
+  /** Full path of the copy (s3://bucket/key for object storage). */
+  archivePath: string | null;
+}
+
+export async function getArchiveSettings(): Promise<ArchiveSettings> {
+  return invoke<ArchiveSettings>("get_archive_settings");
+}
+
+export async function setArchiveSettings(update: ArchiveSettingsUpdate): Promise<ArchiveSettings> {
+  return invoke<ArchiveSettings>("set_archive_settings", { update });
+}
+
+/** Copy a scanned original to the configured archive backend. */
+export async function archiveDocument(historyId: number, filePath: string): Promise<ArchivedDocument> {
+  return invoke<ArchivedDocument>("archive_document", { historyId, filePath });
+}
+
+export async function getArchivedDocument(historyId: number): Promise<ArchivedDocument | null> {
+  return invoke<ArchivedDocument | null>("get_archived_document", { historyId });
+}
+
+/** Local path of the archived original (downloaded first from object storage). */
+export async function getArchivedDocumentPath(historyId: number): Promise<string> {
+  return invoke<string>("get_archived_document_path", { historyId });
+}
+
+/** Open the archived original in the default viewer, even if the source file was deleted. */
+export async function openArchivedDocument(historyId: number): Promise<void> {
+  return invoke("open_archived_document", { historyId });
+}
+
+export interface DeliverySettings {
+  sharePath: string | null;
+}
+
+/** Omitted fields are unchanged; an empty string clears a value. */
+export interface DeliverySettingsUpdate {
+  sharePath?: string;
+}
+
+export interface ExportDelivery {
+  id: number;
+  profileId: number | null;
+  sourcePath: string;
+  destination: string;
+  sha256: string;
+  sizeBytes: number;
+  status: "delivered" | "failed";
+  attempts: number;
+  error: string | null;
+  createdAt: string;
+}
+
+export async function getDeliverySettings(): Promise<DeliverySettings> {
+  return invoke<DeliverySettings>("get_delivery_settings");
+}
+
+export async function setDeliverySettings(update: DeliverySettingsUpdate): Promise<DeliverySettings> {
+  return invoke<DeliverySettings>("set_delivery_settings", { update });
+}
+
+/** Copy a finished export to `destination`, the profile's destination or the default share, verified by checksum. */
+export async function deliverExport(path: string, profileId?: number, destination?: string): Promise<ExportDelivery> {
+  return invoke<ExportDelivery>("deliver_export", {
+    path,
+    profileId: profileId ?? null,
+    destination: destination ?? null,
+  });
+}
+
+export async function listExportDeliveries(profileId?: number, limit?: number): Promise<ExportDelivery[]> {
+  return invoke<ExportDelivery[]>("list_export_deliveries", { profileId: profileId ?? null, limit: limit ?? null });
+}
+
+export interface ProfileDelivery {
+  profileId: number;
+  kind: "share" | "sftp";
+  sharePath: string | null;
+  sftpHost: string | null;
+  sftpPort: number | null;
+  sftpUsername: string | null;
+  sftpDirectory: string | null;
+  /** Host key pinned on the first successful connection. */
+  sftpHostFingerprint: string | null;
+  privateKeyConfigured: boolean;
+}
+
+/** privateKey / passphrase: omit to keep the stored value, "" to remove it. */
+export interface ProfileDeliveryUpdate {
+  kind: "share" | "sftp";
+  sharePath?: string;
+  sftpHost?: string;
+  sftpPort?: number;
+  sftpUsername?: string;
+  sftpDirectory?: string;
+  privateKey?: string;
+  passphrase?: string;
+}
+
+export async function getProfileDelivery(profileId: number): Promise<ProfileDelivery | null> {
+  return invoke<ProfileDelivery | null>("get_profile_delivery", { profileId });
+}
+
+export async function setProfileDelivery(profileId: number, update: ProfileDeliveryUpdate): Promise<ProfileDelivery> {
+  return invoke<ProfileDelivery>("set_profile_delivery", { profileId, update });
+}
+
+export async function removeProfileDelivery(profileId: number): Promise<void> {
+  return invoke<void>("remove_profile_delivery", { profileId });
+}
+
+/** Check the profile's destination is reachable; pins the SFTP host key on first success. */
+export async function testProfileDelivery(profileId: number): Promise<ProfileDelivery> {
+  return invoke<ProfileDelivery>("test_profile_delivery", { profileId });
+}
+
+export interface GoogleSheetsSettings {
+  clientId: string | null;
+  clientSecretConfigured: boolean;
+  /** Signed in to Google (a refresh token is stored). */
+  connected: boolean;
+}
+
+/** Omit a value to keep it, "" to clear it. A new client id signs out. */
+export interface GoogleSheetsSettingsUpdate {
+  clientId?: string;
+  clientSecret?: string;
+}
+
+export async function getGoogleSheetsSettings(): Promise<GoogleSheetsSettings> {
+  return invoke<GoogleSheetsSettings>("get_google_sheets_settings");
+}
+
+export async function setGoogleSheetsSettings(update: GoogleSheetsSettingsUpdate): Promise<GoogleSheetsSettings> {
+  return invoke<GoogleSheetsSettings>("set_google_sheets_settings", { update });
+}
+
+/** Sign in to Google in the browser; resolves when the browser returns (or the sign-in times out). */
+export async function connectGoogleSheets(): Promise<GoogleSheetsSettings> {
+  return invoke<GoogleSheetsSettings>("connect_google_sheets");
+}
+
+export async function disconnectGoogleSheets(): Promise<GoogleSheetsSettings> {
+  return invoke<GoogleSheetsSettings>("disconnect_google_sheets");
+}
+
+/** Spreadsheet id the profile appends to instead of its workbook, or null. */
+export async function getProfileGoogleSheet(profileId: number): Promise<string | null> {
+  return invoke<string | null>("get_profile_google_sheet", { profileId });
+}
+
+/** Point the profile at a Google spreadsheet (id or link), or back at its workbook (null). */
+export async function setProfileGoogleSheet(profileId: number, spreadsheet: string | null): Promise<string | null> {
+  return invoke<string | null>("set_profile_google_sheet", { profileId, spreadsheet });
+}
+
+/** Headers of the profile's sheet in its Google spreadsheet; fails when the sign-in or access is missing. */
+export async function getGoogleSheetHeaders(profileId: number): Promise<ExcelSchemaHeader[]> {
+  return invoke<ExcelSchemaHeader[]>("get_google_sheet_headers", { profileId });
+}
+
+export interface DatabaseTarget {
+  kind: "postgres" | "mysql";
+  host: string;
+  /** Default 5432 / 3306. */
+  port?: number | null;
+  database: string;
+  username: string;
+  /** "table" or "schema.table". */
+  table: string;
+  requireTls?: boolean;
+  /** Table column -> field key. */
+  columns: Record<string, string>;
+  /** Append to the profile's ledger as well; otherwise the database replaces it. */
+  alsoAppendToLedger?: boolean;
+}
+
+export interface ProfileExportTarget extends DatabaseTarget {
+  profileId: number;
+  passwordConfigured: boolean;
+}
+
+/** password: omit to keep the stored value, "" to remove it. */
+export interface ProfileExportTargetUpdate extends DatabaseTarget {
+  password?: string;
+}
+
+export async function getProfileExportTarget(profileId: number): Promise<ProfileExportTarget | null> {
+  return invoke<ProfileExportTarget | null>("get_profile_export_target", { profileId });
+}
+
+export async function setProfileExportTarget(
+  profileId: number,
+  update: ProfileExportTargetUpdate
+): Promise<ProfileExportTarget> {
+  return invoke<ProfileExportTarget>("set_profile_export_target", { profileId, update });
+}
+
+export async function removeProfileExportTarget(profileId: number): Promise<void> {
+  return invoke<void>("remove_profile_export_target", { profileId });
+}
+
+/** Connect to the profile's database target; returns the table's columns (fails on unknown mapped columns). */
+export async function testProfileExportTarget(profileId: number): Promise<string[]> {
+  return invoke<string[]>("test_profile_export_target", { profileId });
+}
+
+export type WebhookEvent = "scan.succeeded" | "append.succeeded";
+
+export interface WebhookSettings {
+  url: string | null;
+  events: WebhookEvent[];
+  secretConfigured: boolean;
+}
+
+/** Omit a field to keep it, "" to clear the URL or secret. */
+export interface WebhookSettingsUpdate {
+  url?: string;
+  events?: WebhookEvent[];
+  secret?: string;
+}
+
+export interface WebhookDelivery {
+  id: number;
+  event: WebhookEvent;
+  url: string;
+  profileId: number | null;
+  status: "pending" | "delivered" | "failed";
+  attempts: number;
+  responseStatus: number | null;
+  error: string | null;
+  createdAt: string;
+  updatedAt: string;
+}
+
+export async function getWebhookSettings(): Promise<WebhookSettings> {
+  return invoke<WebhookSettings>("get_webhook_settings");
+}
+
+export async function setWebhookSettings(update: WebhookSettingsUpdate): Promise<WebhookSettings> {
+  return invoke<WebhookSettings>("set_webhook_settings", { update });
+}
+
+export async function listWebhookDeliveries(limit?: number): Promise<WebhookDelivery[]> {
+  return invoke<WebhookDelivery[]>("list_webhook_deliveries", { limit: limit ?? null });
+}
+
+export async function retryWebhookDelivery(id: number): Promise<WebhookDelivery> {
+  return invoke<WebhookDelivery>("retry_webhook_delivery", { id });
+}
+
+export interface MailboxConfig {
+  host: string;
+  /** 993 when not set. */
+  port?: number | null;
+  username: string;
+  mailbox?: string;
+  /** Minutes between background polls; 0 polls only when asked. */
+  pollMinutes?: number;
+  markSeen?: boolean;
+}
+
+export interface EmailIntakeSettings extends MailboxConfig {
+  mailbox: string;
+  pollMinutes: number;
+  markSeen: boolean;
+  passwordConfigured: boolean;
+}
+
+/** password: omit to keep the stored value, "" to remove it. */
+export interface EmailIntakeSettingsUpdate extends MailboxConfig {
+  password?: string;
+}
+
+export type EmailIntakeStatus = "queued" | "scanned" | "dismissed";
+
+/** PDF attachment taken from the intake mailbox; also the payload of `email-intake-received`. */
+export interface EmailIntakeItem {
+  id: number;
+  sender: string | null;
+  subject: string | null;
+  receivedAt: string | null;
+  fileName: string;
+  filePath: string;
+  status: EmailIntakeStatus;
+  createdAt: string;
+}
+
+export async function getEmailIntakeSettings(): Promise<EmailIntakeSettings | null> {
+  return invoke<EmailIntakeSettings | null>("get_email_intake_settings");
+}
+
+export async function setEmailIntakeSettings(update: EmailIntakeSettingsUpdate): Promise<EmailIntakeSettings> {
+  return invoke<EmailIntakeSettings>("set_email_intake_settings", { update });
+}
+
+export async function removeEmailIntakeSettings(): Promise<void> {
+  return invoke("remove_email_intake_settings");
+}
+
+/** Returns how many messages the mailbox holds. */
+export async function testEmailIntake(): Promise<number> {
+  return invoke<number>("test_email_intake");
+}
+
+export async function pollEmailIntake(): Promise<EmailIntakeItem[]> {
+  return invoke<EmailIntakeItem[]>("poll_email_intake");
+}
+
+export async function listEmailIntake(status?: EmailIntakeStatus): Promise<EmailIntakeItem[]> {
+  return invoke<EmailIntakeItem[]>("list_email_intake", { status: status ?? null });
+}
+
+export async function setEmailIntakeStatus(ids: number[], status: EmailIntakeStatus): Promise<void> {
+  return invoke("set_email_intake_status", { ids, status });
+}
+
+/** True on viewer workstations (READ_ONLY_MODE=1): mutating commands are rejected by the backend. */
+export async function getReadOnlyMode(): Promise<boolean> {
+  return invoke<boolean>("get_read_only_mode");
+}
+
 export async function clearLearnedMappings(): Promise<number> {
   return invoke<number>("clear_learned_mappings");
 }
 
+/** Automatic pre-migration DB backups: [id, from_version, to_version, backup_path, created_at], newest first. */
+export async function getMigrationBackups(): Promise<[number, number, number, string, string][]> {
+  return invoke("get_migration_backups");
+}
+
+export interface DbRecoveryStatus {
+  dbPath: string;
+  /** "failed": the file passed the integrity check but opening or migrating it failed; do not rebuild. */
+  kind: "corrupted" | "locked" | "failed";
+  error: string;
+  backups: { path: string; modified: string; sizeBytes: number }[];
+}
+
+/** Non-null when the database could not be opened at startup. */
+export async function getDbRecoveryStatus(): Promise<DbRecoveryStatus | null> {
+  return invoke<DbRecoveryStatus | null>("get_db_recovery_status");
+}
+
+/** Retry opening, restore a backup (latest when backupPath is omitted) or rebuild an empty DB. Returns where the damaged file was moved. */
+export async function recoverDatabase(
+  action: "retry" | "restore" | "rebuild",
+  backupPath?: string | null
+): Promise<string | null> {
+  return invoke<string | null>("recover_database", { action, backupPath: backupPath ?? null });
+}
+
+export interface AppStateManifest {
+  formatVersion: number;
+  appVersion: string;
+  createdAt: string;
+  sourceAppDataDir: string;
+  includesDocuments: boolean;
+  files: string[];
+  /** Settings keys / credential names whose secret did not carry over; they have to be entered again. */
+  reenterCredentials: string[];
+}
+
+/**
+ * Export DB, settings, templates (and optionally archived documents) to one encrypted archive, protected
+ * by a password or, with recipients (public keys), readable only by those recipients.
+ */
+export async function exportAppState(
+  path: string,
+  password: string | null,
+  includeDocuments?: boolean,
+  recipients?: string[]
+): Promise<string> {
+  return invoke<string>("export_app_state", {
+    path,
+    password,
+    includeDocuments: includeDocuments ?? null,
+    recipients: recipients ?? null,
+  });
+}
+
+/**
+ * Replace the current app state with an exported archive. The previous DB is kept under backups/.
+ * Without a password the archive must have been encrypted for this installation's bundle key.
+ */
+export async function importAppState(path: string, password: string | null): Promise<AppStateManifest> {
+  return invoke<AppStateManifest>("import_app_state", { path, password });
+}
+
+export interface BundleRecipient {
+  label: string;
+  publicKey: string;
+}
+
+export interface BundleKeys {
+  /** This installation's public key (dspub1…) to give to senders; null until generated. */
+  publicKey: string | null;
+  recipients: BundleRecipient[];
+}
+
+export async function getBundleKeys(): Promise<BundleKeys> {
+  return invoke<BundleKeys>("get_bundle_keys");
+}
+
+/** replace overwrites an existing key; bundles encrypted for the old key can no longer be opened. */
+export async function generateBundleKey(replace?: boolean): Promise<BundleKeys> {
+  return invoke<BundleKeys>("generate_bundle_key", { replace: replace ?? null });
+}
+
+export async function importRecipientKey(label: string, publicKey: string): Promise<BundleKeys> {
+  return invoke<BundleKeys>("import_recipient_key", { label, publicKey });
+}
+
+export async function removeRecipientKey(publicKey: string): Promise<BundleKeys> {
+  return invoke<BundleKeys>("remove_recipient_key", { publicKey });
+}
+
 export async function runOcr(filePath: string): Promise<OcrResult> {
   return invoke<OcrResult>("run_ocr", { filePath });
 }
 
-export async function runOcrInvoice(filePath: string, documentType?: string): Promise<InvoiceData> {
+/** Payload of the `ocr-progress` event (one per Azure polling cycle). */
+export interface OcrProgressEvent {
+  jobId: string;
+  filePath: string;
+  stage: "uploading" | "polling";
+  attempt: number;
+  maxAttempts: number;
+  elapsedMs: number;
+  azureStatus: string;
+  queuePosition: number;
+}
+
+/** Payload of the `ocr-status` event (job started / finished). */
+export interface OcrStatusEvent {
+  jobId: string;
+  filePath: string;
+  status: "started" | "succeeded" | "failed" | "cancelled";
+  error?: string;
+}
+
+/** Cancel a running OCR job started with `runOcrInvoice(..., jobId)`. */
+export async function cancelOcr(jobId: string): Promise<boolean> {
+  return invoke<boolean>("cancel_ocr", { jobId });
+}
+
+export async function runOcrInvoice(
+  filePath: string,
+  documentType?: string,
+  jobId?: string
+): Promise<InvoiceData> {
   const result = await invoke<OcrInvoiceResult>("run_ocr_invoice", {
     filePath,
     documentType: documentType ?? null,
+    jobId: jobId ?? null,
   });
 
   const hasRaw = result?.raw_azure_fields != null && typeof result.raw_azure_fields === "object" && !Array.isArray(result.raw_azure_fields);
@@ -64,7 +969,12 @@ export async function runOcrInvoice(filePath: string, documentType?: string): Pr
 
   // 1. Data mapping: parse raw Azure .valueString / .valueNumber / .valueDate into canonical keys
   // 2. Description sanitized inside parseAzureExtraction (strip ``` blocks)
-  const base: InvoiceData & { _document_count?: number } = {
+  const base: InvoiceData & {
+    _document_count?: number;
+    _warnings?: ValidationWarning[];
+    _applied_corrections?: AppliedCorrection[];
+    _extraction_path?: OcrInvoiceResult["extraction_path"];
+  } = {
     fields: { ...backendFields },
     source_file: result?.invoice_data?.source_file,
     source_file_path: result?.invoice_data?.source_file_path,
@@ -75,6 +985,13 @@ export async function runOcrInvoice(filePath: string, documentType?: string): Pr
   if (typeof result?.document_count === "number" && result.document_count > 1) {
     base._document_count = result.document_count;
   }
+  // Validation warnings (amounts, date, VAT rate, document number) for the review screen to flag.
+  if (result?.warnings?.length) {
+    base._warnings = result.warnings;
+  }
+  if (result?.extraction_path) {
+    base._extraction_path = result.extraction_path;
+  }
 
   if (hasRaw) {
     const raw = result!.raw_azure_fields as Record<string, Record<string, unknown>>;
@@ -126,6 +1043,14 @@ export async function runOcrInvoice(filePath: string, documentType?: string): Pr
     }
   }
 
+  // Learned corrections and vendor master data win over the raw Azure values parsed above.
+  if (result?.applied_corrections?.length) {
+    for (const c of result.applied_corrections) {
+      base.fields[c.field_key] = { value: c.value, confidence: undefined };
+    }
+    base._applied_corrections = result.applied_corrections;
+  }
+
   // Ensure document_type is always set for non-invoice flows so that
   // the Review (Преглед) page and Excel profile filtering can pick
   // the correct document-type schema (Даночен биланс, ДДВ, Плати).
@@ -147,38 +1072,215 @@ export async function runOcrInvoice(filePath: string, documentType?: string): Pr
   return base;
 }
 
-export async function batchScanInvoices(pdfPaths: string[], documentType?: string): Promise<import("@/shared/types").BatchScanResult> {
+/** Payload of the `batch-scan-progress` event (each file started / finished). */
+export interface BatchScanProgressEvent {
+  fileName: string;
+  /** 0-based position of the file in the batch. */
+  index: number;
+  total: number;
+  state: "started" | "finished" | "failed" | "skipped";
+  error?: string;
+}
+
+/** Files already scanned are skipped (see `skipped_duplicates`) unless `rescanDuplicates` is set. */
+export async function batchScanInvoices(
+  pdfPaths: string[],
+  documentType?: string,
+  rescanDuplicates?: boolean
+): Promise<import("@/shared/types").BatchScanResult> {
   return invoke<import("@/shared/types").BatchScanResult>("batch_scan_invoices", {
     pdfPaths,
     documentType: documentType ?? null,
+    rescanDuplicates: rescanDuplicates ?? null,
   });
 }
 
 export async function exportInvoicesToExcel(
   invoices: InvoiceData[],
-  path?: string | null
+  path?: string | null,
+  profileId?: number | null,
+  documentType?: string | null,
+  redact?: boolean
 ): Promise<string> {
-  return invoke<string>("export_invoices_to_excel", { invoices, path: path ?? null });
+  return invoke<string>("export_invoices_to_excel", {
+    invoices,
+    path: path ?? null,
+    profileId: profileId ?? null,
+    documentType: documentType ?? null,
+    redact: redact ?? false,
+  });
 }
 
 export async function exportInvoicesToNewExcel(
   invoices: InvoiceData[],
   path?: string | null,
-  worksheetName?: string | null
+  worksheetName?: string | null,
+  profileId?: number | null,
+  documentType?: string | null,
+  redact?: boolean
 ): Promise<string> {
   return invoke<string>("export_invoices_to_new_excel", {
     invoices,
     path: path ?? null,
     worksheetName: worksheetName ?? null,
+    profileId: profileId ?? null,
+    documentType: documentType ?? null,
+    redact: redact ?? false,
   });
 }
 
+/** Same columns as exportInvoicesToNewExcel, saved as OpenDocument (.ods) for LibreOffice. */
+export async function exportInvoicesToOds(
+  invoices: InvoiceData[],
+  path?: string | null,
+  worksheetName?: string | null,
+  profileId?: number | null,
+  documentType?: string | null,
+  redact?: boolean
+): Promise<string> {
+  return invoke<string>("export_invoices_to_ods", {
+    invoices,
+    path: path ?? null,
+    worksheetName: worksheetName ?? null,
+    profileId: profileId ?? null,
+    documentType: documentType ?? null,
+    redact: redact ?? false,
+  });
+}
+
+/** Per-profile rule applied to exports with redact=true; history keeps the full values. */
+export interface RedactionRule {
+  fieldKey: string;
+  /** omit = empty, mask = "*" except the last `keep` chars, truncate = first `keep` chars only. */
+  action: "omit" | "mask" | "truncate";
+  keep?: number;
+}
+
+export async function getRedactionRules(profileId: number): Promise<RedactionRule[]> {
+  return invoke<RedactionRule[]>("get_redaction_rules", { profileId });
+}
+
+export async function setRedactionRules(profileId: number, rules: RedactionRule[]): Promise<RedactionRule[]> {
+  return invoke<RedactionRule[]>("set_redaction_rules", { profileId, rules });
+}
+
+/** File name template for new exports; variables: {client} {profile} {doctype} {count} {year} {month} {day} {date} {timestamp}. */
+export interface ExportNameTemplate {
+  profileId: number | null;
+  template: string | null;
+  effectiveTemplate: string | null;
+  preview: string | null;
+}
+
+export interface ExportLocale {
+  decimalSeparator: "." | ",";
+  thousandsSeparator: "" | "," | "." | " " | "'";
+  /** null keeps dates as scanned. */
+  dateOrder: "dmy" | "mdy" | "ymd" | null;
+  dateSeparator: "." | "/" | "-";
+  /** Write recognized dates as Excel date cells (sortable) instead of text. */
+  excelDates: boolean;
+}
+
+export async function getExportLocale(profileId: number): Promise<ExportLocale> {
+  return invoke<ExportLocale>("get_export_locale", { profileId });
+}
+
+/** null goes back to the default (27,826.17, dates as scanned). */
+export async function setExportLocale(profileId: number, locale: ExportLocale | null): Promise<ExportLocale> {
+  return invoke<ExportLocale>("set_export_locale", { profileId, locale });
+}
+
+export type ExportSortKey = "date" | "vendor" | "number";
+
+/** Row order of batch exports and appends; ties fall back to document number, then source file. */
+export interface ExportSort {
+  /** Empty keeps the order the documents were given in. */
+  by: ExportSortKey[];
+  descending: boolean;
+}
+
+export async function getExportSort(profileId: number): Promise<ExportSort> {
+  return invoke<ExportSort>("get_export_sort", { profileId });
+}
+
+/** null goes back to the default (by document date). */
+export async function setExportSort(profileId: number, sort: ExportSort | null): Promise<ExportSort> {
+  return invoke<ExportSort>("set_export_sort", { profileId, sort });
+}
+
+/** Document type key (e.g. "faktura", "plata") -> worksheet of the profile's workbook. */
+export type SheetRoutes = Record<string, string>;
+
+export async function getSheetRoutes(profileId: number): Promise<SheetRoutes> {
+  return invoke<SheetRoutes>("get_sheet_routes", { profileId });
+}
+
+/** Types left out go to the profile's sheet; a routed sheet missing from the workbook is created on first append. */
+export async function setSheetRoutes(profileId: number, routes: SheetRoutes): Promise<SheetRoutes> {
+  return invoke<SheetRoutes>("set_sheet_routes", { profileId, routes });
+}
+
+/** One problem that keeps a strict-mode batch from being exported. */
+export interface StrictIssue {
+  /** Position of the document in the batch as given. */
+  index: number;
+  sourceFile: string | null;
+  /** "amounts_mismatch", "invalid_date", "missing_document_number", "missing_required", "duplicate_in_batch" or "duplicate_in_ledger". */
+  code: string;
+  fieldKey: string;
+  message: string;
+}
+
+export interface StrictReport {
+  passed: boolean;
+  documentCount: number;
+  issues: StrictIssue[];
+}
+
+export async function getStrictExport(profileId: number): Promise<boolean> {
+  return invoke<boolean>("get_strict_export", { profileId });
+}
+
+/** In strict mode exports and batch appends fail, writing nothing, when any document fails validation. */
+export async function setStrictExport(profileId: number, strict: boolean): Promise<boolean> {
+  return invoke<boolean>("set_strict_export", { profileId, strict });
+}
+
+/** The strict-mode checks without writing; againstLedger also looks for the documents in the profile's ledger. */
+export async function checkStrictExport(
+  profileId: number,
+  invoices: InvoiceData[],
+  documentType?: string | null,
+  againstLedger?: boolean
+): Promise<StrictReport> {
+  return invoke<StrictReport>("check_strict_export", {
+    profileId,
+    invoices,
+    documentType: documentType ?? null,
+    againstLedger: againstLedger ?? null,
+  });
+}
+
+export async function getExportNameTemplate(profileId?: number | null): Promise<ExportNameTemplate> {
+  return invoke<ExportNameTemplate>("get_export_name_template", { profileId: profileId ?? null });
+}
+
+/** Without profileId sets the global default; null template removes it. */
+export async function setExportNameTemplate(
+  profileId: number | null,
+  template: string | null
+): Promise<ExportNameTemplate> {
+  return invoke<ExportNameTemplate>("set_export_name_template", { profileId, template });
+}
+
 export async function exportToNewExcelWithColumns(
   path: string,
   worksheetName: string,
   headers: string[],
   columnFieldKeys: string[],
-  invoices: InvoiceData[]
+  invoices: InvoiceData[],
+  profileId?: number | null
 ): Promise<string> {
   return invoke<string>("export_to_new_excel_with_columns", {
     path,
@@ -186,6 +1288,7 @@ export async function exportToNewExcelWithColumns(
     headers,
     columnFieldKeys,
     invoices,
+    profileId: profileId ?? null,
   });
 }
 
@@ -215,17 +1318,74 @@ export async function copyTemplateAndFillTaxBalance(
   });
 }
 
+export interface BatchExportSummary {
+  historyId: number;
+  excelPath: string;
+  worksheetName: string;
+  firstRow: number;
+  lastRow: number;
+  invoiceCount: number;
+  netTotal: number;
+  taxTotal: number;
+  total: number;
+  /** Invoices with a missing or unreadable net, tax or total amount; left out of the totals. */
+  amountsMissing: number;
+}
+
+/** Append a batch and record it as one "batch_export" history entry; historyIds are grouped under it.
+ *  With expectedTotal (the cover sheet total) the append is refused unless the batch adds up to it. */
 export async function appendInvoicesToExistingExcel(
   excelPath: string,
   worksheetName: string,
   headerRow: number,
-  invoices: InvoiceData[]
-): Promise<void> {
-  return invoke("append_invoices_to_existing_excel", {
+  invoices: InvoiceData[],
+  historyIds?: number[],
+  lockWait?: ExcelLockWaitOptions,
+  expectedTotal?: number | null,
+  profileId?: number | null
+): Promise<BatchExportSummary> {
+  return invoke<BatchExportSummary>("append_invoices_to_existing_excel", {
     excelPath,
     worksheetName,
     headerRow: headerRow >= 1 ? headerRow : 1,
     invoices,
+    historyIds: historyIds ?? null,
+    waitForUnlockSecs: lockWait?.waitForUnlockSecs ?? null,
+    waitId: lockWait?.waitId ?? null,
+    expectedTotal: expectedTotal ?? null,
+    profileId: profileId ?? null,
+  });
+}
+
+export interface BatchDocumentTotal {
+  index: number;
+  sourceFile: string | null;
+  invoiceNumber: string | null;
+  total: number | null;
+  issues: ("missing_total" | "amounts_mismatch" | "duplicate" | "matches_difference")[];
+  message: string | null;
+}
+
+export interface BatchReconciliation {
+  expectedTotal: number;
+  extractedTotal: number;
+  /** Extracted minus expected. */
+  difference: number;
+  balanced: boolean;
+  coverIndex: number | null;
+  documents: BatchDocumentTotal[];
+}
+
+/** Check a batch against its cover sheet total (entered, or scanned from the document at coverIndex). */
+export async function reconcileBatchTotals(
+  invoices: InvoiceData[],
+  expectedTotal?: number | null,
+  coverIndex?: number | null
+): Promise<BatchReconciliation> {
+  return invoke<BatchReconciliation>("reconcile_batch_totals", {
+    invoices,
+    expectedTotal: expectedTotal ?? null,
+    coverIndex: coverIndex ?? null,
   });
 }
 
@@ -263,13 +1423,96 @@ export async function getSheetNames(path: string): Promise<string[]> {
   return invoke<string[]>("get_sheet_names", { path });
 }
 
+export interface ExportDiffRow {
+  key: string;
+  rowNumber: number;
+  values: Record<string, string>;
+}
+
+export interface ExportDiffChangedRow {
+  key: string;
+  rowNumberA: number;
+  rowNumberB: number;
+  changes: { column: string; valueA: string; valueB: string }[];
+}
+
+export interface ExportDiff {
+  keyColumn: string;
+  comparedColumns: string[];
+  onlyInAColumns: string[];
+  onlyInBColumns: string[];
+  added: ExportDiffRow[];
+  removed: ExportDiffRow[];
+  changed: ExportDiffChangedRow[];
+  unchangedCount: number;
+  duplicateKeysA: string[];
+  duplicateKeysB: string[];
+}
+
+/** Compare two exports (CSV or Excel) by a key column (header text or column letter). */
+export async function diffExports(
+  pathA: string,
+  pathB: string,
+  keyColumn: string
+): Promise<ExportDiff> {
+  return invoke<ExportDiff>("diff_exports", { pathA, pathB, keyColumn });
+}
+
+/** Wait for a workbook that is open in Excel instead of failing immediately. */
+export interface ExcelLockWaitOptions {
+  /** Seconds to keep polling the lock (max 300). */
+  waitForUnlockSecs?: number;
+  /** Id for retryExcelWriteNow / cancelExcelLockWait and `excel-lock-wait` events. */
+  waitId?: string;
+  /** appendToExcelFast only: keep the row in the pending queue when the file stays locked, is missing or offline. */
+  queueIfUnavailable?: boolean;
+}
+
+/** Payload of the `excel-lock-wait` event. */
+export interface ExcelLockWaitEvent {
+  waitId: string;
+  path: string;
+  status: "waiting" | "unlocked" | "timeout" | "cancelled";
+  elapsedSecs: number;
+  timeoutSecs: number;
+  attempts: number;
+}
+
+export async function retryExcelWriteNow(waitId: string): Promise<boolean> {
+  return invoke<boolean>("retry_excel_write_now", { waitId });
+}
+
+/** A process (or remote user via Excel's owner file) holding a workbook open. */
+export interface ExcelLockOwner {
+  processName: string | null;
+  processId: number | null;
+  userName: string | null;
+}
+
+export async function getExcelLockOwners(path: string): Promise<ExcelLockOwner[]> {
+  return invoke<ExcelLockOwner[]>("get_excel_lock_owners", { path });
+}
+
+export async function cancelExcelLockWait(waitId: string): Promise<boolean> {
+  return invoke<boolean>("cancel_excel_lock_wait", { waitId });
+}
+
 export async function appendRowToExcel(
   path: string,
   sheet: string,
-  row: { column: string; value: string }[]
+  row: { column: string; value: string }[],
+  lockWait?: ExcelLockWaitOptions,
+  historyId?: number | null
 ): Promise<void> {
   return invoke("append_row_to_excel", {
-    payload: { path, sheet, row },
+    payload: {
+      path,
+      sheet,
+      row,
+      history_id: historyId ?? null,
+      wait_for_unlock_secs: lockWait?.waitForUnlockSecs ?? null,
+      wait_id: lockWait?.waitId ?? null,
+    },
   });
 }
 
@@ -297,6 +1540,114 @@ export async function saveProfile(payload: {
   return invoke("save_profile", { payload });
 }
 
+/** A field chosen for a column; `source` is its strongest signal. */
+export interface MappingSuggestion {
+  columnLetter: string;
+  fieldKey: string;
+  confidence: number;
+  source: "learned" | "profile" | "keyword";
+}
+
+export interface FieldSuggestion {
+  fieldKey: string;
+  /** 0..1 */
+  score: number;
+  sources: ("keyword" | "samples" | "learned" | "profile")[];
+}
+
+export interface ColumnSuggestions {
+  columnLetter: string;
+  header: string;
+  /** Best first. */
+  suggestions: FieldSuggestion[];
+}
+
+/**
+ * Ranked field suggestions per column from header keywords, column samples (as from analyzeExcelSchema)
+ * and learned mappings. `headers` start at column A.
+ */
+export async function suggestColumnMapping(
+  headers: string[],
+  columnSamples?: string[][]
+): Promise<ColumnSuggestions[]> {
+  return invoke<ColumnSuggestions[]>("suggest_column_mapping", { headers, columnSamples: columnSamples ?? null });
+}
+
+export interface CreatedProfile {
+  profileId: number;
+  excelPath: string;
+  sheetName: string;
+  schema: ExcelSchemaFull;
+  suggestions: MappingSuggestion[];
+}
+
+/** Copy a blank template to targetPath (must not exist), scan it, map columns and save profile + schema. */
+export async function createProfileFromTemplate(
+  templatePath: string,
+  targetPath: string,
+  name: string
+): Promise<CreatedProfile> {
+  return invoke<CreatedProfile>("create_profile_from_template", { templatePath, targetPath, name });
+}
+
+export interface DemoWorkspace {
+  folder: string;
+  ledgerPath: string;
+  sheetName: string;
+  profileId: number;
+  /** Demo invoices as the mock provider scans them. */
+  documents: InvoiceData[];
+}
+
+/** (Re)create the demo folder: sample ledger, mock invoices (default 5, max 20) and the demo profile. */
+export async function createDemoWorkspace(documentCount?: number | null): Promise<DemoWorkspace> {
+  return invoke<DemoWorkspace>("create_demo_workspace", { documentCount: documentCount ?? null });
+}
+
+/** Delete the demo profile and folder. Returns false when there was nothing to remove. */
+export async function removeDemoWorkspace(): Promise<boolean> {
+  return invoke<boolean>("remove_demo_workspace");
+}
+
+/** A header of a SchemaDiff: `from` is its stored column (null when added), `to` its column now (null when removed). */
+export interface ColumnChange {
+  text: string;
+  from: string | null;
+  to: string | null;
+  fieldKey: string | null;
+}
+
+/** Details of a SCHEMA_CHANGED error: the ledger's header row against the profile's stored schema. */
+export interface SchemaDiff {
+  headerRow: number;
+  added: ColumnChange[];
+  removed: ColumnChange[];
+  moved: ColumnChange[];
+}
+
+export interface RemappedProfile {
+  columnMapping: Record<string, string>;
+  unmappedFields: string[];
+  diff: SchemaDiff | null;
+  schema: ExcelSchemaFull;
+}
+
+/** Re-scan the profile's sheet and move its mapping onto the current columns (by header text, or columnMapping). */
+export async function remapProfileColumns(
+  profileId: number,
+  columnMapping?: Record<string, string> | null
+): Promise<RemappedProfile> {
+  return invoke<RemappedProfile>("remap_profile_columns", { profileId, columnMapping: columnMapping ?? null });
+}
+
+/**
+ * Copy the values of a read-only ledger (.xls, .xlsb, .ods) into an .xlsx next to it; returns the new path.
+ * With `profileId`, the profile is pointed at the copy and rescanned.
+ */
+export async function convertWorkbookToXlsx(path: string, profileId?: number | null): Promise<string> {
+  return invoke<string>("convert_workbook_to_xlsx", { path, profileId: profileId ?? null });
+}
+
 export async function deleteProfile(id: number): Promise<void> {
   return invoke("delete_profile", { id });
 }
@@ -304,20 +1655,117 @@ export async function deleteProfile(id: number): Promise<void> {
 export async function getHistory(payload?: {
   search?: string;
   folder_id?: number | null; // null/undefined = all, -1 = uncategorized
+  include_subfolders?: boolean; // also records in subfolders of folder_id
+  top_level_only?: boolean; // hide records grouped under a batch export
+  session_id?: number; // only records of this scan session
+  limit?: number; // page size; omit for every record
+  offset?: number;
 }): Promise<
   [number, string, string, string, string, string, number | null, string | null][]
 > {
   return invoke("get_history", { payload: payload ?? null });
 }
 
-export async function createFolder(name: string): Promise<number> {
-  return invoke("create_folder", { name });
+/** Number of records matching the getHistory filters (limit/offset ignored). */
+export async function getHistoryCount(payload?: {
+  search?: string;
+  folder_id?: number | null;
+  include_subfolders?: boolean;
+  top_level_only?: boolean;
+  session_id?: number;
+}): Promise<number> {
+  return invoke("get_history_count", { payload: payload ?? null });
+}
+
+export interface HistorySearchHit {
+  id: number;
+  createdAt: string;
+  documentType: string;
+  filePathOrName: string;
+  status: string;
+  excelProfileId: number | null;
+  /** Matching text; hits are wrapped in <mark></mark>. */
+  snippet: string;
+  /** BM25 score, lower = better match. */
+  rank: number;
+}
+
+/** Ranked full-text search over file names and extracted values (each word prefix-matched). */
+export async function searchHistoryFts(
+  query: string,
+  limit?: number,
+  offset?: number
+): Promise<HistorySearchHit[]> {
+  return invoke<HistorySearchHit[]>("search_history_fts", {
+    query,
+    limit: limit ?? null,
+    offset: offset ?? null,
+  });
+}
+
+/** Cell write log: [id, created_at, sheet_name, cell_ref, row_number, old_value, new_value, history_id], newest first. */
+export async function getWriteLog(
+  filePath: string,
+  options?: { sheetName?: string; rowNumber?: number; limit?: number }
+): Promise<
+  [number, string, string, string, number, string | null, string, number | null][]
+> {
+  return invoke("get_write_log", {
+    filePath,
+    sheetName: options?.sheetName ?? null,
+    rowNumber: options?.rowNumber ?? null,
+    limit: options?.limit ?? null,
+  });
+}
+
+/** One page of a ledger sheet's values; writtenRows marks rows the app wrote (write log) for highlighting. */
+export interface LedgerPage {
+  sheetName: string;
+  startRow: number;
+  rows: { rowNumber: number; cells: string[] }[];
+  /** Last 1-based row with data (0 = empty sheet). */
+  lastRow: number;
+  columnCount: number;
+  writtenRows: { rowNumber: number; writtenAt: string; historyId: number | null }[];
+}
+
+/** Read rows startRow..startRow+rowCount-1 (1-based, max 500) without opening Excel. */
+export async function readLedgerRange(
+  path: string,
+  sheetName: string,
+  startRow: number,
+  rowCount: number
+): Promise<LedgerPage> {
+  return invoke<LedgerPage>("read_ledger_range", { path, sheetName, startRow, rowCount });
+}
+
+/** Per-invoice records of a "batch_export" history entry. */
+export async function getHistoryChildren(
+  parentId: number
+): Promise<
+  [number, string, string, string, string, string, number | null, string | null][]
+> {
+  return invoke("get_history_children", { parentId });
+}
+
+export async function createFolder(name: string, parentId?: number | null): Promise<number> {
+  return invoke("create_folder", { name, parentId: parentId ?? null });
 }
 
-export async function getFolders(): Promise<[number, string, string][]> {
+/** [id, name, createdAt, parentId] of every folder. */
+export async function getFolders(): Promise<[number, string, string, number | null][]> {
   return invoke("get_folders");
 }
 
+export async function renameFolder(id: number, name: string): Promise<void> {
+  return invoke("rename_folder", { id, name });
+}
+
+/** Moves the folder (with its subfolders) under parentId, or to the top level when null. */
+export async function moveFolder(id: number, parentId: number | null): Promise<void> {
+  return invoke("move_folder", { id, parentId });
+}
+
 export async function deleteFolder(id: number): Promise<void> {
   return invoke("delete_folder", { id });
 }
@@ -332,6 +1780,202 @@ export async function assignHistoryToFolder(
   });
 }
 
+export async function createCategory(name: string, monthlyBudget?: number | null): Promise<number> {
+  return invoke("create_category", { name, monthlyBudget: monthlyBudget ?? null });
+}
+
+export async function updateCategory(
+  id: number,
+  name: string,
+  monthlyBudget?: number | null
+): Promise<void> {
+  return invoke("update_category", { id, name, monthlyBudget: monthlyBudget ?? null });
+}
+
+/** [id, name, monthly_budget, created_at] */
+export async function getCategories(): Promise<[number, string, number | null, string][]> {
+  return invoke("get_categories");
+}
+
+export async function deleteCategory(id: number): Promise<void> {
+  return invoke("delete_category", { id });
+}
+
+export async function assignHistoryToCategory(
+  historyId: number,
+  categoryId: number | null
+): Promise<void> {
+  return invoke("assign_history_to_category", {
+    historyId,
+    categoryId: categoryId ?? null,
+  });
+}
+
+export interface CategorySpending {
+  categoryId: number | null;
+  categoryName: string;
+  total: number;
+  invoiceCount: number;
+  previousTotal: number;
+  delta: number;
+  deltaPercent: number | null;
+  monthlyBudget: number | null;
+  monthlyTotals: { month: string; total: number }[];
+}
+
+export interface SpendingReport {
+  period: string;
+  previousPeriod: string;
+  categories: CategorySpending[];
+  total: number;
+  previousTotal: number;
+  skippedWithoutAmount: number;
+}
+
+/** Spending per category for a month ("YYYY-MM") with month-over-month deltas. */
+export async function getSpendingByCategory(period: string): Promise<SpendingReport> {
+  return invoke<SpendingReport>("get_spending_by_category", { period });
+}
+
+/** Export the spending report to .xlsx; returns the saved path. */
+export async function exportSpendingReport(period: string, path: string): Promise<string> {
+  return invoke<string>("export_spending_report", { period, path });
+}
+
+export interface HistoryReportFilter {
+  /** Inclusive YYYY-MM-DD bounds on the document date (scan date when missing). */
+  dateFrom?: string | null;
+  dateTo?: string | null;
+  /** null/undefined = all, -1 = uncategorized */
+  folderId?: number | null;
+  status?: string | null;
+}
+
+/** History report with totals per seller and per month as .xlsx, or CSV (documents only) for a .csv path. */
+export async function exportHistoryReport(path: string, filter?: HistoryReportFilter): Promise<string> {
+  return invoke<string>("export_history_report", { filter: filter ?? null, path });
+}
+
+export interface VatTotals {
+  documentCount: number;
+  netAmount: number;
+  taxAmount: number;
+  totalAmount: number;
+}
+
+/** One VAT rate line; rate is null for documents not matching 18/10/5/0 %. */
+export interface VatRateLine extends VatTotals {
+  rate: number | null;
+}
+
+export interface VatReport {
+  periodStart: string;
+  periodEnd: string;
+  rates: VatRateLine[];
+  months: (VatTotals & { month: string; rates: VatRateLine[] })[];
+  vendors: (VatTotals & { vendor: string; taxId: string })[];
+  totals: VatTotals;
+  skippedWithoutAmounts: number;
+  savedPath: string | null;
+}
+
+/** ДДВ-04 style input VAT workbook for a period ("YYYY-MM" or "YYYY-MM-DD" bounds). */
+export async function generateVatReport(periodStart: string, periodEnd: string, path?: string): Promise<VatReport> {
+  return invoke<VatReport>("generate_vat_report", { periodStart, periodEnd, path: path ?? null });
+}
+
+/** Automatic ledger snapshots: after every N appends and/or on the first append of each day. */
+export interface SnapshotPolicy {
+  everyAppends: number | null;
+  daily: boolean;
+}
+
+export interface LedgerSnapshot {
+  id: number;
+  profileId: number;
+  filePath: string;
+  sha256: string;
+  sizeBytes: number;
+  reason: "appends" | "daily" | "manual" | "before_restore";
+  createdAt: string;
+}
+
+export async function getSnapshotPolicy(profileId: number): Promise<SnapshotPolicy | null> {
+  return invoke<SnapshotPolicy | null>("get_snapshot_policy", { profileId });
+}
+
+/** null turns automatic snapshots off for the profile. */
+export async function setSnapshotPolicy(profileId: number, policy: SnapshotPolicy | null): Promise<SnapshotPolicy | null> {
+  return invoke<SnapshotPolicy | null>("set_snapshot_policy", { profileId, policy });
+}
+
+export async function takeLedgerSnapshot(profileId: number): Promise<LedgerSnapshot> {
+  return invoke<LedgerSnapshot>("take_ledger_snapshot", { profileId });
+}
+
+/** Newest first. */
+export async function listSnapshots(profileId: number): Promise<LedgerSnapshot[]> {
+  return invoke<LedgerSnapshot[]>("list_snapshots", { profileId });
+}
+
+/** Replace the workbook with the snapshot; the current file is snapshotted first. */
+export async function restoreSnapshot(snapshotId: number): Promise<LedgerSnapshot> {
+  return invoke<LedgerSnapshot>("restore_snapshot", { snapshotId });
+}
+
+export interface ProfileAuditFieldChange {
+  columnLetter: string;
+  fieldKey: string;
+  expected: string;
+  actual: string;
+}
+
+export interface ProfileAuditReport {
+  profileId: number;
+  excelPath: string;
+  sheetName: string;
+  checkedAt: string;
+  documentNumberColumn: string;
+  /** Fields rows were matched by (see DuplicateKey). */
+  keyFields: string[];
+  historyCount: number;
+  ledgerRowCount: number;
+  matchedCount: number;
+  missing: { historyId: number; createdAt: string; documentNumber: string }[];
+  duplicated: { documentNumber: string; rowNumbers: number[]; historyIds: number[] }[];
+  altered: { historyId: number; documentNumber: string; rowNumber: number; changes: ProfileAuditFieldChange[] }[];
+  withoutDocumentNumber: number[];
+}
+
+/** Fields that make a ledger row unique; `year:<field>` uses the year of a date field. */
+export interface DuplicateKey {
+  fields: string[];
+  /** What an append does when the ledger already has the key: append anyway, refuse, or overwrite that row. */
+  onDuplicate: "append" | "reject" | "update";
+}
+
+/** null when the profile uses the document number alone. */
+export async function getDuplicateKey(profileId: number): Promise<DuplicateKey | null> {
+  return invoke<DuplicateKey | null>("get_duplicate_key", { profileId });
+}
+
+export async function setDuplicateKey(profileId: number, key: DuplicateKey | null): Promise<DuplicateKey | null> {
+  return invoke<DuplicateKey | null>("set_duplicate_key", { profileId, key });
+}
+
+/** 1-based ledger rows that already hold a document with the same key. */
+export async function findDuplicateRows(
+  profileId: number,
+  invoiceData: { fields: Record<string, { value: string; confidence?: number }> }
+): Promise<number[]> {
+  return invoke<number[]>("find_duplicate_rows", { profileId, invoiceData });
+}
+
+/** Cross-check exported history against the profile's ledger (run before the monthly VAT filing). */
+export async function auditProfileConsistency(profileId: number): Promise<ProfileAuditReport> {
+  return invoke<ProfileAuditReport>("audit_profile_consistency", { profileId });
+}
+
 export async function getHistoryById(
   id: number
 ): Promise<
@@ -348,10 +1992,207 @@ export async function addHistoryRecord(payload: {
   excel_profile_id?: number | null;
   error_message?: string | null;
   folder_id?: number | null;
+  /** Full path of the scanned file when file_path_or_name is only its name. */
+  source_path?: string | null;
 }): Promise<number> {
   return invoke("add_history_record", { payload });
 }
 
+export interface HistoryRevisionChange {
+  key: string;
+  before: unknown;
+  after: unknown;
+}
+
+/** Document type and extracted data of a history record before a change. */
+export interface HistoryRevision {
+  id: number;
+  documentType: string;
+  extractedData: Record<string, unknown>;
+  diff: HistoryRevisionChange[];
+  reason: "edit" | "rescan" | "restore";
+  createdAt: string;
+}
+
+/** Scan a history record's original again (source file, else the archived copy); the old data becomes a revision. */
+export async function rescanHistoryRecord(id: number, documentType?: string): Promise<OcrInvoiceResult> {
+  return invoke<OcrInvoiceResult>("rescan_history_record", { id, documentType: documentType ?? null });
+}
+
+export async function getHistoryRevisions(historyId: number): Promise<HistoryRevision[]> {
+  return invoke<HistoryRevision[]>("get_history_revisions", { historyId });
+}
+
+/** Put back the data a record had before `revisionId`; the replaced data becomes a revision itself. */
+export async function restoreHistoryRevision(historyId: number, revisionId: number): Promise<void> {
+  return invoke("restore_history_revision", { historyId, revisionId });
+}
+
+/** A hand correction of an OCR value, kept for auditing and better field mapping. */
+export interface InvoiceEdit {
+  id: number;
+  historyId: number;
+  documentType: string;
+  fieldKey: string;
+  originalValue: string | null;
+  correctedValue: string;
+  createdAt: string;
+}
+
+/** Record corrected fields of a history record; unchanged fields are skipped. Returns all its edits. */
+export async function saveInvoiceEdits(
+  historyId: number,
+  edits: { fieldKey: string; originalValue: string | null; correctedValue: string }[]
+): Promise<InvoiceEdit[]> {
+  return invoke<InvoiceEdit[]>("save_invoice_edits", { historyId, edits });
+}
+
+export async function getInvoiceEdits(historyId: number): Promise<InvoiceEdit[]> {
+  return invoke<InvoiceEdit[]>("get_invoice_edits", { historyId });
+}
+
+/** A document scanned together with a history record, e.g. the delivery note stapled to an invoice. */
+export interface DocumentLink {
+  id: number;
+  historyId: number;
+  linkType: "delivery_note";
+  linkedNumber: string;
+  /** History record of the linked document when it was scanned on its own too. */
+  linkedHistoryId: number | null;
+  createdAt: string;
+}
+
+/** Links from or to a history record. */
+export async function getDocumentLinks(historyId: number): Promise<DocumentLink[]> {
+  return invoke<DocumentLink[]>("get_document_links", { historyId });
+}
+
+export type ClosingStep =
+  | "invoices_reviewed"
+  | "duplicates_resolved"
+  | "pending_appends_flushed"
+  | "export_delivered"
+  | "backup_taken";
+
+export interface ClosingStepStatus {
+  step: ClosingStep;
+  done: boolean;
+  detail: string;
+  /** History records that keep the step open. */
+  historyIds: number[];
+}
+
+export interface ClosingStatus {
+  /** "YYYY-MM" */
+  period: string;
+  documents: number;
+  steps: ClosingStepStatus[];
+  complete: boolean;
+}
+
+/** Month-end checklist for a "YYYY-MM" period. */
+export async function getClosingStatus(period: string): Promise<ClosingStatus> {
+  return invoke<ClosingStatus>("get_closing_status", { period });
+}
+
+export async function getClosingSteps(): Promise<ClosingStep[]> {
+  return invoke<ClosingStep[]>("get_closing_steps");
+}
+
+/** null restores all steps. */
+export async function setClosingSteps(steps: ClosingStep[] | null): Promise<ClosingStep[]> {
+  return invoke<ClosingStep[]>("set_closing_steps", { steps });
+}
+
+/** Documents processed in one sitting and who scanned them. */
+export interface ScanSession {
+  id: number;
+  operator: string;
+  note: string | null;
+  startedAt: string;
+  /** null while the session is open. */
+  endedAt: string | null;
+  documents: number;
+  /** Documents written to a ledger. */
+  exported: number;
+  errors: number;
+}
+
+/** Start a session (ends one left open); the operator defaults to settings, then the OS login. */
+export async function startScanSession(operator?: string | null, note?: string | null): Promise<ScanSession> {
+  return invoke<ScanSession>("start_scan_session", { operator: operator ?? null, note: note ?? null });
+}
+
+export async function endScanSession(id: number): Promise<ScanSession> {
+  return invoke<ScanSession>("end_scan_session", { id });
+}
+
+export async function getActiveScanSession(): Promise<ScanSession | null> {
+  return invoke<ScanSession | null>("get_active_scan_session");
+}
+
+export async function listScanSessions(limit?: number): Promise<ScanSession[]> {
+  return invoke<ScanSession[]>("list_scan_sessions", { limit: limit ?? null });
+}
+
+/** A field value learned from edits and applied to the vendor's new scans. */
+export interface VendorCorrection {
+  sellerEdb: string;
+  fieldKey: string;
+  originalValue: string | null;
+  correctedValue: string;
+  correctionCount: number;
+  appliedCount: number;
+  updatedAt: string;
+}
+
+export async function getVendorCorrections(sellerEdb?: string): Promise<VendorCorrection[]> {
+  return invoke<VendorCorrection[]>("get_vendor_corrections", { sellerEdb: sellerEdb ?? null });
+}
+
+/** Forget a learned correction. Returns false when there was none. */
+export async function deleteVendorCorrection(sellerEdb: string, fieldKey: string): Promise<boolean> {
+  return invoke<boolean>("delete_vendor_correction", { sellerEdb, fieldKey });
+}
+
+/** Vendor master data; filled in as invoices are scanned. */
+export interface Vendor {
+  id: number;
+  name: string;
+  taxId: string | null;
+  address: string | null;
+  defaultCurrency: string | null;
+  /** Other spellings of the name seen on invoices. */
+  aliases: string[];
+  createdAt: string;
+  updatedAt: string;
+}
+
+export async function getVendors(search?: string): Promise<Vendor[]> {
+  return invoke<Vendor[]>("get_vendors", { search: search ?? null });
+}
+
+/** Create (no id) or update a vendor. */
+export async function saveVendor(payload: {
+  id?: number | null;
+  name: string;
+  taxId?: string | null;
+  address?: string | null;
+  defaultCurrency?: string | null;
+  aliases?: string[];
+}): Promise<Vendor> {
+  return invoke<Vendor>("save_vendor", { payload });
+}
+
+export async function deleteVendor(id: number): Promise<boolean> {
+  return invoke<boolean>("delete_vendor", { id });
+}
+
+/** Known vendor for a seller name and/or tax number (fuzzy name match). */
+export async function resolveVendor(name?: string | null, taxId?: string | null): Promise<Vendor | null> {
+  return invoke<Vendor | null>("resolve_vendor", { name: name ?? null, taxId: taxId ?? null });
+}
+
 export async function updateHistoryStatus(payload: {
   id: number;
   status: string;
@@ -377,6 +2218,29 @@ export async function deleteHistoryRecord(id: number): Promise<void> {
   return invoke("delete_history_record", { id });
 }
 
+export interface TrashedHistoryRecord {
+  id: number;
+  createdAt: string;
+  documentType: string;
+  filePathOrName: string;
+  status: string;
+  excelProfileId: number | null;
+  deletedAt: string;
+}
+
+export async function getTrash(): Promise<TrashedHistoryRecord[]> {
+  return invoke("get_trash");
+}
+
+export async function restoreHistoryRecord(id: number): Promise<void> {
+  return invoke("restore_history_record", { id });
+}
+
+/** Permanently removes trashed records older than `olderThanDays` (all when omitted); returns the count. */
+export async function purgeTrash(olderThanDays?: number): Promise<number> {
+  return invoke("purge_trash", { olderThanDays: olderThanDays ?? null });
+}
+
 export async function getLearnedMapping(schemaHash: string, fieldType: string): Promise<[string, number] | null> {
   return invoke("get_learned_mapping", { schema_hash: schemaHash, field_type: fieldType });
 }
@@ -391,6 +2255,24 @@ export async function upsertLearnedMapping(payload: {
   return invoke("upsert_learned_mapping", payload);
 }
 
+/**
+ * Learned-mapping confidence updates, newest first: [id, created_at, schema_hash, field_type, column_letter,
+ * action, reward, previous_confidence, effective_before, confidence, usage_count].
+ */
+export async function getLearnedMappingHistory(options?: {
+  schemaHash?: string;
+  fieldType?: string;
+  limit?: number;
+}): Promise<
+  [number, string, string, string, string, string, number, number | null, number | null, number, number][]
+> {
+  return invoke("get_learned_mapping_history", {
+    schemaHash: options?.schemaHash ?? null,
+    fieldType: options?.fieldType ?? null,
+    limit: options?.limit ?? null,
+  });
+}
+
 export async function getColumnSamples(payload: {
   path: string;
   sheet: string;
@@ -429,6 +2311,69 @@ export async function deleteFile(path: string): Promise<void> {
   return invoke("delete_file", { path });
 }
 
+/** Save bytes (e.g. a pasted scan) as a temp file and get its path; same content gives the same path. */
+export async function putTempFile(base64Content: string, extension: string): Promise<string> {
+  return invoke<string>("put_temp_file", { base64Content, extension });
+}
+
+/** Where the file commands above may read and write; other paths fail with PATH_NOT_ALLOWED. */
+export interface PathScope {
+  appDataDir: string;
+  documentRoots: string[];
+  exportFolders: string[];
+  profileWorkbooks: string[];
+  grantedFiles: string[];
+  grantedFolders: string[];
+}
+
+export async function getPathScope(): Promise<PathScope> {
+  return invoke<PathScope>("get_path_scope");
+}
+
+/** Add a document root chosen in a native folder picker; returns the roots (unchanged when cancelled). */
+export async function pickDocumentRoot(): Promise<string[]> {
+  return invoke<string[]>("pick_document_root");
+}
+
+export async function removeDocumentRoot(root: string): Promise<string[]> {
+  return invoke<string[]>("remove_document_root", { root });
+}
+
+export interface OpenDialogOptions {
+  title?: string;
+  filters?: { name: string; extensions: string[] }[];
+  multiple?: boolean;
+}
+
+/** Native open dialog whose files may be used by the file commands. Empty when cancelled. */
+export async function pickOpenPaths(options: OpenDialogOptions = {}): Promise<string[]> {
+  return invoke<string[]>("pick_open_paths", {
+    title: options.title ?? null,
+    filters: options.filters ?? null,
+    multiple: options.multiple ?? null,
+  });
+}
+
+/** Native folder picker for export, archive and delivery folders. Null when cancelled. */
+export async function pickFolder(title?: string): Promise<string | null> {
+  return invoke<string | null>("pick_folder", { title: title ?? null });
+}
+
+export interface SaveDialogOptions {
+  defaultPath?: string;
+  title?: string;
+  filters?: { name: string; extensions: string[] }[];
+}
+
+/** Native save dialog whose result may be written with writeFileBase64 / copyFile. Null when cancelled. */
+export async function pickSavePath(options: SaveDialogOptions = {}): Promise<string | null> {
+  return invoke<string | null>("pick_save_path", {
+    defaultPath: options.defaultPath ?? null,
+    title: options.title ?? null,
+    filters: options.filters ?? null,
+  });
+}
+
 export interface ExcelSchemaResponse {
   cached: boolean;
   schema_json?: string;
@@ -439,12 +2384,31 @@ export async function getExcelSchema(path: string): Promise<ExcelSchemaResponse>
   return invoke("get_excel_schema", { path });
 }
 
+export interface ColumnStats {
+  columnLetter: string;
+  filledCount: number;
+  /** 0..1 share of data rows with a value. */
+  fillRate: number;
+  dataType: "number" | "date" | "text" | "mixed" | "empty";
+}
+
+export interface SheetStats {
+  dataRowCount: number;
+  /** The sheet has more rows than were read for these statistics. */
+  truncated: boolean;
+  columns: ColumnStats[];
+  likelyAmountColumn: string | null;
+  likelyDateColumn: string | null;
+}
+
 export interface AnalyzedExcelSchema {
   worksheetName: string;
   headers: string[];
   columnSamples: string[][];
   lastDataRow: number;
+  lastRowTruncated: boolean;
   schemaHash: string;
+  stats: SheetStats;
 }
 
 export async function analyzeExcelSchema(
@@ -511,6 +2475,8 @@ export interface ExcelSchemaFull {
   firstDataRow: number;
   lastDataRow: number;
   nextFreeRow: number;
+  /** Data exists below a gap longer than the empty-row limit and was not counted. */
+  lastRowTruncated: boolean;
   totalRows: number;
   totalColumns: number;
   headers: ExcelSchemaHeader[];
@@ -530,6 +2496,26 @@ export async function scanExcelSchema(
   });
 }
 
+export interface ProfileRefreshResult {
+  profileId: number;
+  profileName: string;
+  status: "unchanged" | "rescanned" | "missing" | "error";
+  error?: string;
+  nextFreeRow?: number;
+}
+
+/** Payload of the `profile-refresh-progress` event. */
+export interface ProfileRefreshProgress {
+  done: number;
+  total: number;
+  result: ProfileRefreshResult;
+}
+
+/** Re-scan ledgers whose file changed since the stored schema, in parallel. */
+export async function refreshAllProfiles(): Promise<ProfileRefreshResult[]> {
+  return invoke<ProfileRefreshResult[]>("refresh_all_profiles");
+}
+
 export async function saveExcelSchema(
   profileId: number,
   schema: ExcelSchemaFull
@@ -550,12 +2536,133 @@ export async function getExcelSchemaForProfile(
   });
 }
 
+/** `documentType` (else the history record's type) picks the sheet when the profile routes it (setSheetRoutes). */
 export async function appendToExcelFast(
   profileId: number,
-  invoiceData: { fields: Record<string, { value: string; confidence?: number }> }
+  invoiceData: { fields: Record<string, { value: string; confidence?: number }> },
+  lockWait?: ExcelLockWaitOptions,
+  historyId?: number | null,
+  documentType?: string | null
 ): Promise<number> {
   return invoke("append_to_excel_fast", {
     profileId,
     invoiceData,
+    historyId: historyId ?? null,
+    documentType: documentType ?? null,
+    waitForUnlockSecs: lockWait?.waitForUnlockSecs ?? null,
+    waitId: lockWait?.waitId ?? null,
+    queueIfUnavailable: lockWait?.queueIfUnavailable ?? null,
+  });
+}
+
+/** Parse the profile's ledger in the background so the first appendToExcelFast from review is fast. */
+export async function warmProfileWorkbook(profileId: number): Promise<void> {
+  return invoke("warm_profile_workbook", { profileId });
+}
+
+/**
+ * Payload of the `excel-append-fallback` event: the workbook could not be edited normally, so the rows were
+ * spliced into the sheet without some formatting (named in `warning`).
+ */
+export interface AppendFallbackEvent {
+  profileId: number;
+  path: string;
+  warning: string;
+}
+
+/** Row kept for a later append; also the payload of the `excel-append-queued` event. */
+export interface PendingAppend {
+  id: number;
+  profileId: number;
+  invoiceData: InvoiceData;
+  historyId: number | null;
+  reason: "locked" | "missing" | "offline";
+  lastError: string | null;
+  attempts: number;
+  createdAt: string;
+  lastAttemptAt: string | null;
+}
+
+export async function getPendingAppends(profileId?: number | null): Promise<PendingAppend[]> {
+  return invoke<PendingAppend[]>("get_pending_appends", { profileId: profileId ?? null });
+}
+
+/** Rolling figures over the last 200 samples of one timed path, in milliseconds. */
+export interface TimingStats {
+  name: "ocr" | "append" | "schemaScan" | "dbQuery";
+  samples: number;
+  /** Samples since the app started. */
+  totalCount: number;
+  avgMs: number;
+  p95Ms: number;
+  maxMs: number;
+  lastMs: number | null;
+}
+
+export interface PerformanceMetrics {
+  timings: TimingStats[];
+  /** Worker pool slots per class. */
+  queues: { class: "ocr" | "append" | "preview" | "maintenance"; running: number; waiting: number; budget: number }[];
+  pendingAppends: number;
+}
+
+/** Timings and queue depths of the current run, for diagnosing slow installations. */
+export async function getPerformanceMetrics(): Promise<PerformanceMetrics> {
+  return invoke<PerformanceMetrics>("get_performance_metrics");
+}
+
+/** Result of flushPendingAppends; also the payload of the `pending-appends-flushed` event. */
+export interface FlushPendingSummary {
+  /** [pending id, ledger row] of the rows written (row 0 for Plata). */
+  written: [number, number][];
+  remaining: PendingAppend[];
+}
+
+/** Retry queued rows now (also done on startup). */
+export async function flushPendingAppends(profileId?: number | null): Promise<FlushPendingSummary> {
+  return invoke<FlushPendingSummary>("flush_pending_appends", { profileId: profileId ?? null });
+}
+
+export async function discardPendingAppend(id: number): Promise<void> {
+  return invoke("discard_pending_append", { id });
+}
+
+/** Column letter -> field key for one batch only; "" leaves the column blank. */
+export type ColumnOverrides = Record<string, string>;
+
+/**
+ * Append several documents in one open/save of the workbook; returns the row of each (0 for Plata).
+ * `columnOverrides` change the profile's mapping for this batch only and are kept in the export manifest.
+ */
+export async function appendInvoicesToExcelFast(
+  profileId: number,
+  invoices: { fields: Record<string, { value: string; confidence?: number }> }[],
+  lockWait?: ExcelLockWaitOptions,
+  historyIds?: (number | null)[],
+  columnOverrides?: ColumnOverrides
+): Promise<number[]> {
+  return invoke("append_invoices_to_excel_fast", {
+    profileId,
+    invoices,
+    historyIds: historyIds ?? null,
+    columnOverrides: columnOverrides ?? null,
+    waitForUnlockSecs: lockWait?.waitForUnlockSecs ?? null,
+    waitId: lockWait?.waitId ?? null,
   });
 }
+
+/** One batch append: rows written (in the order given), their history records and the batch's overrides. */
+export interface ExportManifest {
+  id: number;
+  profileId: number;
+  filePath: string;
+  sheetName: string;
+  rows: number[];
+  historyIds: (number | null)[];
+  columnOverrides: ColumnOverrides;
+  createdAt: string;
+}
+
+export async function getExportManifests(profileId?: number | null, limit?: number): Promise<ExportManifest[]> {
+  return invoke<ExportManifest[]>("get_export_manifests", { profileId: profileId ?? null, limit: limit ?? null });
+}
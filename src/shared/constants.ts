@@ -35,6 +35,7 @@ export const FIELD_KEYS = [
   "currency",
   "due_date",
   "reference",
+  "delivery_note_number",
   "payment_method",
 ] as const;
 
@@ -50,7 +51,7 @@ export const REQUIRED_FIELD_KEYS: FieldKey[] = [
 
 /** Field groups for logical ordering and sections. */
 export const FIELD_GROUPS = {
-  document: ["document_type", "invoice_number", "document_number", "date", "reference"] as const,
+  document: ["document_type", "invoice_number", "document_number", "date", "reference", "delivery_note_number"] as const,
   seller: ["seller_name", "seller_address", "seller_tax_id", "seller_edb"] as const,
   buyer: ["buyer_name", "buyer_address", "buyer_tax_id"] as const,
   amounts: ["description", "net_amount", "tax_amount", "total_amount", "currency"] as const,
@@ -251,6 +252,7 @@ export const FIELD_LABELS: Record<FieldKey, string> = {
   currency: "Currency",
   due_date: "Due date",
   reference: "Reference",
+  delivery_note_number: "Delivery note number",
   payment_method: "Payment method",
 };
 
@@ -274,6 +276,7 @@ export const FIELD_LABELS_MK: Record<FieldKey, string> = {
   currency: "Валута",
   due_date: "Рок на плаќање",
   reference: "Референца",
+  delivery_note_number: "Број на испратница",
   payment_method: "Начин на плаќање",
 };
 
@@ -355,6 +358,7 @@ export const PAYROLL_FIELD_LABELS_MK: Record<string, string> = {
   companyName: "Име на компанија",
   companyTaxId: "Идентификационен број / ЕДБ",
   declarationPeriod: "Период за кој се поднесува (месец/година)",
+  employeeName: "Име и презиме на вработен",
   brojVraboteni: "Број на вработени за кои се пресметува плата",
   brutoPlata: "Бруто плата (Бруто 2)",
   pridonesPIO: "Придонес за ПИО",
@@ -571,206 +575,3 @@ export const KEYWORDS_MAP: Partial<Record<FieldKey, string[]>> = {
     "уплата",
   ],
 };
-
-/** Keywords to auto-match Excel column headers (Macedonian-first; e.g. "Број на документ" → invoice_number). */
-export const HEADER_KEYWORDS: Partial<Record<FieldKey, string[]>> = {
-  document_type: [
-    "document type",
-    "тип на документ",
-    "вид документ",
-    "rechnung",
-    "faktura",
-    "invoice",
-    "тип документ",
-    "вид на документ",
-    "даночен биланс",
-    "type of document",
-    "typeofdocument",
-  ],
-  invoice_number: [
-    "invoice number",
-    "invoice no",
-    "invoice no.",
-    "invoice #",
-    "број на документ",
-    "број на фактура",
-    "document number",
-    "broj dokument",
-    "broj fakture",
-    "br.",
-    "no.",
-    "#",
-    "broj",
-    "number",
-    "rechnungsnummer",
-    "фактура бр",
-    "документ бр",
-  ],
-  document_number: [
-    "invoice id",
-    "document number",
-    "број на документ",
-    "број на фактура",
-    "invoice no",
-  ],
-  date: [
-    "date",
-    "document date",
-    "дата на документ",
-    "датум на фактура",
-    "datum",
-    "дата",
-    "датум",
-    "rechnungsdatum",
-    "invoice date",
-    "period",
-    "период",
-  ],
-  seller_name: [
-    "seller name",
-    "seller",
-    "продавач",
-    "prodavac",
-    "dobavljac",
-    "испраќач",
-    "издавач",
-    "supplier",
-    "vendor",
-  ],
-  seller_address: [
-    "seller address",
-    "адреса продавач",
-    "адреса на продавач",
-    "seller addr",
-    "supplier address",
-  ],
-  seller_tax_id: [
-    "seller tax",
-    "seller tax id",
-    "даночен број",
-    "мат. број",
-    "pib",
-    "vat number",
-    "tax id",
-    "company id",
-  ],
-  seller_edb: [
-    "едб",
-    "vendor tax id",
-    "даночен број",
-    "pib",
-    "tax id",
-    "company id",
-  ],
-  buyer_name: [
-    "buyer name",
-    "buyer",
-    "купувач",
-    "kupac",
-    "client",
-    "клиент",
-    "примач",
-    "customer",
-  ],
-  buyer_address: [
-    "buyer address",
-    "адреса купувач",
-    "адреса на купувач",
-    "buyer addr",
-    "client address",
-  ],
-  buyer_tax_id: [
-    "buyer tax",
-    "buyer tax id",
-    "даночен број купувач",
-    "куп tax",
-    "client tax",
-  ],
-  description: [
-    "description",
-    "опис",
-    "опис во документ",
-    "opis",
-    "item",
-    "items",
-    "услуги",
-    "services",
-    "предмет",
-    "subject",
-  ],
-  net_amount: [
-    "net amount",
-    "net",
-    "нето износ",
-    "neto",
-    "nettobetrag",
-    "net value",
-    "subtotal",
-  ],
-  total_amount: [
-    "total amount",
-    "total",
-    "gross amount",
-    "бруто износ",
-    "ukupno",
-    "износ",
-    "amount",
-    "bruttobetrag",
-    "sum",
-    "total sum",
-    "вкупно",
-    "износ за плаќање",
-    "јануари",
-    "фебруари",
-    "март",
-    "април",
-    "мај",
-    "јуни",
-    "јули",
-    "август",
-    "септември",
-    "октомври",
-    "ноември",
-    "декември",
-    "бруто плата",
-  ],
-  tax_amount: [
-    "tax amount",
-    "tax",
-    "vat",
-    "ддв",
-    "ddv",
-    "pdv",
-    "mwst",
-    "ддв 18%",
-    "18%",
-    "tax (vat)",
-  ],
-  currency: [
-    "currency",
-    "валута",
-    "valuta",
-    "curr",
-  ],
-  due_date: [
-    "due date",
-    "рок плаќање",
-    "fällig",
-    "payment due",
-    "datum dospelosti",
-  ],
-  reference: [
-    "reference",
-    "референца",
-    "збор",
-    "ref",
-    "poziv na broj",
-  ],
-  payment_method: [
-    "payment method",
-    "начин плаќање",
-    "payment",
-    "zahlungsart",
-    "плаќање",
-  ],
-};
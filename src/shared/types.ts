@@ -3,7 +3,9 @@ export type DocumentType = "faktura" | "plata" | "smetka" | "generic";
 export interface OcrLine {
   text: string;
   confidence?: number;
-  boundingBox?: number[];
+  page?: number;
+  /** Same format as InvoiceFieldValue.polygon. */
+  polygon?: number[];
 }
 
 export interface OcrResult {
@@ -43,6 +45,10 @@ export interface ExtractedField {
 export interface InvoiceFieldValue {
   value: string;
   confidence?: number;
+  /** 1-based page the value was read from. */
+  page?: number;
+  /** Outline on that page: x1,y1,…,x4,y4 clockwise from top-left (inches for PDFs, pixels for images). */
+  polygon?: number[];
 }
 
 export interface InvoiceData {
@@ -60,6 +66,32 @@ export interface OcrInvoiceResult {
   raw_azure_fields?: Record<string, unknown> | null;
   /** Total number of documents Azure detected inside this file (1 = normal). */
   document_count?: number | null;
+  /** Suspicious values (amounts that don't add up, unreadable date, ...); never blocks an append. */
+  warnings?: ValidationWarning[];
+  /** OCR values replaced by learned vendor corrections or vendor master data. */
+  applied_corrections?: AppliedCorrection[];
+  /** How the fields were read; "local_text_layer" = the PDF's own text, no OCR call. */
+  extraction_path?: "azure" | "textract" | "mock" | "local_text_layer";
+}
+
+export interface ValidationWarning {
+  code:
+    | "amounts_mismatch"
+    | "invalid_date"
+    | "unusual_tax_rate"
+    | "missing_document_number"
+    | "missing_required"
+    | "invalid_number"
+    | "pattern_mismatch";
+  field_key: string;
+  message: string;
+}
+
+/** A learned correction or vendor master-data value applied to a scan (see run_ocr_invoice). */
+export interface AppliedCorrection {
+  field_key: string;
+  ocr_value: string;
+  value: string;
 }
 
 /** Information about a failed scan attempt. */
@@ -69,10 +101,21 @@ export interface FailedScan {
   error: string;
 }
 
+/** A batch file not scanned because its content was scanned before. */
+export interface SkippedDuplicate {
+  file_path: string;
+  file_name: string;
+  sha256: string;
+  /** History record of the earlier scan; null when the file repeats an earlier file of the batch. */
+  history_id: number | null;
+  scanned_at: string | null;
+}
+
 /** Result of batch scanning, containing both successful and failed scans. */
 export interface BatchScanResult {
   successes: InvoiceData[];
   failures: FailedScan[];
+  skipped_duplicates: SkippedDuplicate[];
 }
 
 /** Excel schema from schemaService.analyzeSchema (for mapping and write). */